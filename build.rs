@@ -23,6 +23,7 @@ use vergen_gitcl::{
 
 pub fn main() -> Result<()> {
     ui::setup()?;
+    deps::setup()?;
 
     // Init vergen
     Emitter::default()
@@ -36,6 +37,90 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
+mod deps {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use anyhow::{Context, Result};
+    use cargo_toml::Manifest;
+
+    // crates whose resolved versions are reported at runtime, with the env var they are exposed as
+    const REPORTED: [(&str, &str); 3] = [
+        ("arrow", "PARSEABLE_ARROW_VERSION"),
+        ("parquet", "PARSEABLE_PARQUET_VERSION"),
+        ("datafusion", "PARSEABLE_DATAFUSION_VERSION"),
+    ];
+
+    /// Exposes the versions of crates to the build, as resolved in Cargo.lock or, when built
+    /// without a lock file, as pinned in Cargo.toml. Fails the build rather than reporting a
+    /// version that isn't known
+    pub fn setup() -> Result<()> {
+        let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+        let lock_file = manifest_dir.join("Cargo.lock");
+        println!("cargo:rerun-if-changed={}", lock_file.display());
+        let lock = fs::read_to_string(&lock_file).ok();
+        let manifest = Manifest::from_path(manifest_dir.join("Cargo.toml"))?;
+
+        for (name, var) in REPORTED {
+            let pinned = manifest
+                .dependencies
+                .get(name)
+                .map(|dependency| dependency.req().trim_start_matches(['^', '=', '~', ' ']))
+                .with_context(|| format!("{name} is not a dependency in Cargo.toml"))?;
+            let version = match &lock {
+                Some(lock) => locked_version(lock, name, pinned).with_context(|| {
+                    format!("{name} {pinned} is not resolved in {}", lock_file.display())
+                })?,
+                None => pinned,
+            };
+            println!("cargo:rustc-env={var}={version}");
+        }
+
+        Ok(())
+    }
+
+    /// The highest of the versions of `name` in the lock file that is compatible with `pinned`,
+    /// other crates may depend on other versions of it
+    fn locked_version<'a>(lock: &'a str, name: &str, pinned: &str) -> Option<&'a str> {
+        let name = format!("name = \"{name}\"");
+        let mut lines = lock.lines();
+        let mut versions = vec![];
+        while lines.any(|line| line == name) {
+            let version = lines
+                .next()
+                .and_then(|line| line.strip_prefix("version = \""))
+                .and_then(|line| line.strip_suffix('"'));
+            versions.extend(version);
+        }
+
+        versions
+            .into_iter()
+            .filter(|version| is_compatible(version, pinned))
+            .max_by_key(|version| numbers(version))
+    }
+
+    /// Whether `version` is semver compatible with `pinned`, i.e. the same major version, or the
+    /// same minor version for versions before 1.0
+    fn is_compatible(version: &str, pinned: &str) -> bool {
+        let (version, pinned) = (numbers(version), numbers(pinned));
+        let significant = if pinned.first() == Some(&0) { 2 } else { 1 };
+
+        version.len() >= significant
+            && version
+                .iter()
+                .take(significant)
+                .eq(pinned.iter().take(significant))
+    }
+
+    fn numbers(version: &str) -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    }
+}
+
 mod ui {
 
     use std::fs::{self, create_dir_all, OpenOptions};
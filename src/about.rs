@@ -150,6 +150,12 @@ pub async fn print(options: &Options, meta: &StorageMetadata) {
     );
 }
 
+/// Versions of the arrow, parquet and datafusion crates the server was built against,
+/// as resolved in Cargo.lock by build.rs
+pub const ARROW_VERSION: &str = env!("PARSEABLE_ARROW_VERSION");
+pub const PARQUET_VERSION: &str = env!("PARSEABLE_PARQUET_VERSION");
+pub const DATAFUSION_VERSION: &str = env!("PARSEABLE_DATAFUSION_VERSION");
+
 pub fn current() -> ParseableVersion {
     // CARGO_PKG_VERSION is set from Cargol.toml file at build time
     // We need to ensure [package].version in Cargo.toml is always valid semver
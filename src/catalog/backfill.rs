@@ -0,0 +1,444 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use arrow::compute::cast;
+use arrow_array::{new_null_array, RecordBatch};
+use arrow_schema::Schema;
+use bytes::Bytes;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    file::properties::WriterProperties,
+};
+use serde::Serialize;
+use tracing::{info, warn};
+use ulid::Ulid;
+
+use crate::{
+    parseable::PARSEABLE,
+    storage::{object_storage::manifest_path, ObjectStorage},
+};
+
+use super::{
+    compaction::{relative_path, CompactionError},
+    lock_snapshot,
+    manifest::create_from_parquet,
+    partition_path,
+};
+
+/// Progress of backfills, by the name of the stream being backfilled
+static PROGRESS: Lazy<RwLock<HashMap<String, BackfillProgress>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillProgress {
+    /// Number of files of the stream that are looked at
+    pub total: usize,
+    /// Number of files rewritten in the stream's schema
+    pub rewritten: usize,
+    /// Number of files that were already in the stream's schema
+    pub skipped: usize,
+    /// Number of files that couldn't be rewritten
+    pub failed: usize,
+    pub finished: bool,
+}
+
+/// Returns the progress of the latest backfill of the stream, if any
+pub fn progress(stream_name: &str) -> Option<BackfillProgress> {
+    PROGRESS
+        .read()
+        .expect("lock poisoned")
+        .get(stream_name)
+        .cloned()
+}
+
+fn update_progress(stream_name: &str, update: impl FnOnce(&mut BackfillProgress)) {
+    update(
+        PROGRESS
+            .write()
+            .expect("lock poisoned")
+            .entry(stream_name.to_owned())
+            .or_default(),
+    )
+}
+
+/// Marks a backfill of the stream as started, returns `false` if one is already running
+pub fn start(stream_name: &str) -> bool {
+    let mut progress = PROGRESS.write().expect("lock poisoned");
+    if progress
+        .get(stream_name)
+        .is_some_and(|progress| !progress.finished)
+    {
+        return false;
+    }
+    progress.insert(stream_name.to_owned(), BackfillProgress::default());
+
+    true
+}
+
+/// Rewrites the parquet files of the stream in its current schema, adding missing columns as
+/// nulls, dropping columns no longer in the schema and casting columns whose type has changed.
+///
+/// Every file is committed to the manifest as soon as it is rewritten, and files already in the
+/// stream's schema are skipped, so a backfill that was interrupted picks up where it left off
+/// when started again. Like compaction, only manifests written by this node are backfilled.
+///
+/// Manifests are read again under the lock of the snapshot for every file committed, as uploads
+/// keep adding files to them while the stream is backfilled.
+pub async fn backfill_stream(stream_name: &str) -> Result<BackfillProgress, CompactionError> {
+    let result = backfill(stream_name).await;
+    update_progress(stream_name, |progress| progress.finished = true);
+
+    result.map(|_| progress(stream_name).unwrap_or_default())
+}
+
+async fn backfill(stream_name: &str) -> Result<(), CompactionError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    let data_store = PARSEABLE.data_store(stream_name);
+    let stream = PARSEABLE.get_stream(stream_name)?;
+    let schema = stream.get_schema();
+    let meta = storage.get_object_store_format(stream_name).await?;
+    let time_partition = meta.time_partition.as_ref();
+    let custom_partition = meta.custom_partition.as_ref();
+
+    let own_manifest = manifest_path("").to_string();
+    let mut manifests = vec![];
+    for item in meta
+        .snapshot
+        .manifest_list
+        .iter()
+        .filter(|item| item.manifest_path.contains(&own_manifest))
+    {
+        let path = partition_path(stream_name, item.time_lower_bound, item.time_upper_bound);
+        if let Some(manifest) = storage.get_manifest(&path).await? {
+            let file_paths = manifest
+                .files
+                .into_iter()
+                .map(|f| f.file_path)
+                .collect_vec();
+            manifests.push((path, file_paths));
+        }
+    }
+    let total = manifests.iter().map(|(_, files)| files.len()).sum();
+    update_progress(stream_name, |progress| progress.total = total);
+
+    for (path, file_paths) in manifests {
+        for file_path in file_paths {
+            let key = relative_path(&*data_store, &file_path);
+            let source = data_store.get_object(&key).await?;
+            let reprojected = match reproject_parquet(source, &schema, |schema| {
+                stream.parquet_writer_props(schema, time_partition, custom_partition)
+            }) {
+                Ok(Some(reprojected)) => reprojected,
+                Ok(None) => {
+                    update_progress(stream_name, |progress| progress.skipped += 1);
+                    continue;
+                }
+                Err(err) => {
+                    warn!("Failed to backfill file {file_path} of stream- {stream_name}: {err}");
+                    update_progress(stream_name, |progress| progress.failed += 1);
+                    continue;
+                }
+            };
+
+            // rewritten file is placed under the same partition prefix as the file it replaces
+            let new_key = key.with_file_name(format!("{}.backfill.parquet", Ulid::new()));
+            let size = reprojected.len() as u64;
+            let reprojected = Bytes::from(reprojected);
+            data_store.put_object(&new_key, reprojected.clone()).await?;
+            let new_file_path = data_store.absolute_url(&new_key).to_string();
+
+            let new_file = create_from_parquet(new_file_path, reprojected, size)?;
+            let committed = {
+                let _snapshot = lock_snapshot(stream_name).await;
+                match storage.get_manifest(&path).await? {
+                    // files compacted or deleted in the meantime are gone from the manifest
+                    Some(mut manifest)
+                        if manifest
+                            .files
+                            .iter()
+                            .any(|file| file.file_path == file_path) =>
+                    {
+                        manifest.files.retain(|file| file.file_path != file_path);
+                        manifest.apply_change(new_file);
+                        storage.put_manifest(&path, manifest).await?;
+                        true
+                    }
+                    _ => false,
+                }
+            };
+            if !committed {
+                if let Err(err) = data_store.delete_object(&new_key).await {
+                    warn!("Failed to delete backfilled file {new_key} of removed file: {err}");
+                }
+                update_progress(stream_name, |progress| progress.skipped += 1);
+                continue;
+            }
+
+            if let Err(err) = data_store.delete_object(&key).await {
+                warn!("Failed to delete backfilled file {file_path}: {err}");
+            }
+            update_progress(stream_name, |progress| progress.rewritten += 1);
+        }
+    }
+    info!(
+        "Backfilled stream- {stream_name}: {:?}",
+        progress(stream_name)
+    );
+
+    Ok(())
+}
+
+/// Rewrites the parquet file in the given schema, returns `None` if it is already in the schema
+pub fn reproject_parquet(
+    file: Bytes,
+    schema: &Schema,
+    props: impl FnOnce(&Schema) -> WriterProperties,
+) -> Result<Option<Vec<u8>>, CompactionError> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let file_schema = builder.schema().clone();
+    // files already backfilled can differ from the schema in nullability and order of columns
+    let same_columns = file_schema.fields().len() == schema.fields().len()
+        && schema.fields().iter().all(|field| {
+            file_schema
+                .field_with_name(field.name())
+                .is_ok_and(|f| f.data_type() == field.data_type())
+        });
+    if same_columns {
+        return Ok(None);
+    }
+
+    // columns of the file are made nullable, as not every file has every column
+    let schema = Arc::new(Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| field.as_ref().clone().with_nullable(true))
+            .collect_vec(),
+        schema.metadata().clone(),
+    ));
+    let mut buf = vec![];
+    let mut writer = ArrowWriter::try_new(&mut buf, schema.clone(), Some(props(&schema)))?;
+    for batch in builder.build()? {
+        writer.write(&reproject(&schema, &batch?)?)?;
+    }
+    writer.close()?;
+
+    Ok(Some(buf))
+}
+
+fn reproject(schema: &Arc<Schema>, batch: &RecordBatch) -> Result<RecordBatch, CompactionError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+            Some(column) => cast(column, field.data_type()),
+            None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .try_collect()?;
+
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Array, ArrayRef, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use bytes::Bytes;
+    use parquet::{
+        arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+        file::properties::WriterProperties,
+    };
+
+    use super::reproject_parquet;
+
+    fn file(messages: &[&str]) -> Bytes {
+        let batch = RecordBatch::try_from_iter([(
+            "msg",
+            Arc::new(StringArray::from_iter_values(messages)) as ArrayRef,
+        )])
+        .unwrap();
+
+        let mut buf = vec![];
+        let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        buf.into()
+    }
+
+    #[test]
+    fn backfill_adds_nullable_column_to_every_file() {
+        let schema = Schema::new(vec![
+            Field::new("level", DataType::Utf8, true),
+            Field::new("msg", DataType::Utf8, false),
+        ]);
+
+        for messages in [&["a", "b"][..], &["c"][..]] {
+            let reprojected =
+                reproject_parquet(file(messages), &schema, |_| WriterProperties::default())
+                    .unwrap()
+                    .expect("file lacks the new column");
+            let reprojected = Bytes::from(reprojected);
+
+            let batches = ParquetRecordBatchReaderBuilder::try_new(reprojected.clone())
+                .unwrap()
+                .build()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].num_rows(), messages.len());
+            let level = batches[0].column_by_name("level").unwrap();
+            assert_eq!(level.null_count(), messages.len());
+
+            // a resumed backfill leaves files already in the schema as is
+            assert!(
+                reproject_parquet(reprojected, &schema, |_| WriterProperties::default())
+                    .unwrap()
+                    .is_none()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn files_uploaded_during_backfill_stay_in_manifest() {
+        use arrow_array::TimestampMillisecondArray;
+        use arrow_schema::TimeUnit;
+        use chrono::{TimeZone, Utc};
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog::{self, partition_path},
+            event::DEFAULT_TIMESTAMP_KEY,
+            parseable::PARSEABLE,
+            storage::{ObjectStorageError, ObjectStoreFormat},
+        };
+
+        let stream_name = "backfill_upload";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, 30, 0).unwrap();
+        let batch = |columns: &[&str]| {
+            let mut fields: Vec<(&str, ArrayRef)> = vec![(
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])),
+            )];
+            for &column in columns {
+                fields.push((column, Arc::new(StringArray::from(vec!["a"]))));
+            }
+            RecordBatch::try_from_iter(fields).unwrap()
+        };
+        let schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+            Field::new("msg", DataType::Utf8, true),
+            Field::new("level", DataType::Utf8, true),
+        ]);
+        stream.set_schema(&schema);
+        let store = PARSEABLE.storage.get_object_store();
+        store
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat::default(),
+                Arc::new(schema.clone()),
+            )
+            .await
+            .unwrap();
+        let upload = |name: &'static str, batch: RecordBatch| {
+            let store = store.clone();
+            async move {
+                let mut parquet = vec![];
+                let mut writer = ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+                writer.write(&batch).unwrap();
+                writer.close().unwrap();
+                let parquet = Bytes::from(parquet);
+                let path = RelativePathBuf::from(format!(
+                    "{stream_name}/date=2025-01-01/hour=10/minute=30/{name}.parquet"
+                ));
+                store.put_object(&path, parquet.clone()).await.unwrap();
+                let file = catalog::manifest::create_from_parquet(
+                    store.absolute_url(&path).to_string(),
+                    parquet.clone(),
+                    parquet.len() as u64,
+                )
+                .unwrap();
+                catalog::update_snapshot(store.clone(), stream_name, file)
+                    .await
+                    .unwrap();
+                path
+            }
+        };
+        let old = upload("old", batch(&["msg"])).await;
+
+        // a file in the current schema is uploaded while the stream is backfilled
+        assert!(super::start(stream_name));
+        let (progress, new) = tokio::join!(
+            super::backfill_stream(stream_name),
+            upload("new", batch(&["msg", "level"]))
+        );
+        assert_eq!(progress.unwrap().rewritten, 1);
+
+        let item = store
+            .get_object_store_format(stream_name)
+            .await
+            .unwrap()
+            .snapshot
+            .manifest_list
+            .remove(0);
+        let manifest = store
+            .get_manifest(&partition_path(
+                stream_name,
+                item.time_lower_bound,
+                item.time_upper_bound,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert!(manifest
+            .files
+            .iter()
+            .any(|file| file.file_path.ends_with(new.as_str())));
+        let backfilled = manifest
+            .files
+            .iter()
+            .find(|file| file.file_path.ends_with(".backfill.parquet"))
+            .expect("file in the old schema is rewritten");
+        let backfilled = store
+            .get_object(&super::relative_path(&*store, &backfilled.file_path))
+            .await
+            .unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(backfilled).unwrap();
+        assert!(reader.schema().field_with_name("level").is_ok());
+        assert!(matches!(
+            store.get_object(&old).await,
+            Err(ObjectStorageError::NoSuchKey(_))
+        ));
+    }
+}
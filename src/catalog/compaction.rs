@@ -0,0 +1,583 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use arrow::compute::{concat_batches, sort_to_indices, take_record_batch};
+use arrow_schema::{ArrowError, Schema, SortOptions};
+use bytes::Bytes;
+use itertools::Itertools;
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use relative_path::{RelativePath, RelativePathBuf};
+use tracing::{info, warn};
+use ulid::Ulid;
+
+use crate::{
+    event::DEFAULT_TIMESTAMP_KEY,
+    parseable::{StreamNotFound, PARSEABLE},
+    storage::{object_storage::manifest_path, ObjectStorage, ObjectStorageError},
+    utils::arrow::adapt_batch,
+};
+
+use super::{
+    lock_snapshot,
+    manifest::{create_from_parquet, File},
+    partition_path,
+};
+
+/// Suffix of the name of files written by compaction
+pub const COMPACTED_FILE_SUFFIX: &str = "compacted.parquet";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CompactionError {
+    #[error("{0}")]
+    StreamNotFound(#[from] StreamNotFound),
+    #[error("{0}")]
+    ObjectStorage(#[from] ObjectStorageError),
+    #[error("{0}")]
+    Arrow(#[from] ArrowError),
+    #[error("{0}")]
+    Parquet(#[from] ParquetError),
+    #[error("Failed to describe compacted file: {0}")]
+    Manifest(#[from] anyhow::Error),
+}
+
+/// Compacts the small parquet files of every stream, failures are logged and don't stop
+/// compaction of the other streams.
+pub async fn compact_streams() {
+    for stream_name in PARSEABLE.streams.list() {
+        match compact_stream(&stream_name).await {
+            Ok(0) => {}
+            Ok(count) => info!("Compacted {count} parquet files of stream- {stream_name}"),
+            Err(err) => warn!("Failed to compact parquet files of stream- {stream_name}: {err}"),
+        }
+    }
+}
+
+/// Merges small files within each partition of the stream into larger ones, returns the number
+/// of files that were replaced.
+///
+/// Only manifests written by this node are compacted. The compacted file is uploaded before the
+/// manifest is updated to point to it, and the original files are deleted only after, so queries
+/// see either the original files or the compacted one, never both or neither. Files the manifest
+/// no longer holds by then, e.g. as they were backfilled or deleted meanwhile, aren't replaced.
+pub async fn compact_stream(stream_name: &str) -> Result<usize, CompactionError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    let data_store = PARSEABLE.data_store(stream_name);
+    let stream = PARSEABLE.get_stream(stream_name)?;
+    let meta = storage.get_object_store_format(stream_name).await?;
+    let time_partition = meta.time_partition.as_ref();
+    let custom_partition = meta.custom_partition.as_ref();
+    let sort_column = time_partition.map_or(DEFAULT_TIMESTAMP_KEY, |tp| tp.as_str());
+
+    let own_manifest = manifest_path("").to_string();
+    let mut compacted = 0;
+    for item in meta
+        .snapshot
+        .manifest_list
+        .iter()
+        .filter(|item| item.manifest_path.contains(&own_manifest))
+    {
+        let path = partition_path(stream_name, item.time_lower_bound, item.time_upper_bound);
+        let Some(manifest) = storage.get_manifest(&path).await? else {
+            continue;
+        };
+
+        let groups = plan(
+            &manifest.files,
+            stream
+                .get_settings()
+                .parquet_target_size
+                .unwrap_or(PARSEABLE.options.compaction_target_size),
+            PARSEABLE.options.compaction_min_files,
+        );
+        if groups.is_empty() {
+            continue;
+        }
+
+        let mut replaced = vec![];
+        for group in groups {
+            let mut sources = Vec::with_capacity(group.len());
+            for file in &group {
+                sources.push(
+                    data_store
+                        .get_object(&relative_path(&*data_store, &file.file_path))
+                        .await?,
+                );
+            }
+            let merged = merge_parquet(&sources, sort_column, |schema| {
+                stream.parquet_writer_props(schema, time_partition, custom_partition)
+            })?;
+
+            // compacted file is placed under the same partition prefix as the files it replaces
+            let key = relative_path(&*data_store, &group[0].file_path)
+                .with_file_name(format!("{}.{COMPACTED_FILE_SUFFIX}", Ulid::new()));
+            let size = merged.len() as u64;
+            let merged = Bytes::from(merged);
+            data_store.put_object(&key, merged.clone()).await?;
+
+            let file_path = data_store.absolute_url(&key).to_string();
+            replaced.push((
+                key,
+                group
+                    .into_iter()
+                    .map(|file| file.file_path.clone())
+                    .collect_vec(),
+                create_from_parquet(file_path, merged, size)?,
+            ));
+        }
+
+        let mut originals = vec![];
+        let mut discarded = vec![];
+        {
+            let _snapshot = lock_snapshot(stream_name).await;
+            if let Some(mut manifest) = storage.get_manifest(&path).await? {
+                for (key, file_paths, compacted_file) in replaced {
+                    let present = file_paths.iter().all(|file_path| {
+                        manifest
+                            .files
+                            .iter()
+                            .any(|file| &file.file_path == file_path)
+                    });
+                    if !present {
+                        discarded.push(key);
+                        continue;
+                    }
+                    manifest
+                        .files
+                        .retain(|file| !file_paths.contains(&file.file_path));
+                    manifest.apply_change(compacted_file);
+                    originals.extend(file_paths);
+                }
+                if !originals.is_empty() {
+                    storage.put_manifest(&path, manifest).await?;
+                }
+            } else {
+                discarded.extend(replaced.into_iter().map(|(key, ..)| key));
+            }
+        }
+
+        for key in discarded {
+            if let Err(err) = data_store.delete_object(&key).await {
+                warn!("Failed to delete compacted file {key} of removed files: {err}");
+            }
+        }
+
+        for file_path in originals {
+            if let Err(err) = data_store
+                .delete_object(&relative_path(&*data_store, &file_path))
+                .await
+            {
+                warn!("Failed to delete compacted file {file_path}: {err}");
+                continue;
+            }
+            compacted += 1;
+        }
+    }
+
+    Ok(compacted)
+}
+
+/// Maps the path of a file as recorded in the manifest back to its path relative to the storage root
+pub(crate) fn relative_path(storage: &dyn ObjectStorage, file_path: &str) -> RelativePathBuf {
+    let root = storage.absolute_url(RelativePath::new("")).to_string();
+    let path = file_path.strip_prefix(&root).unwrap_or(file_path);
+
+    RelativePathBuf::from(path.trim_start_matches('/'))
+}
+
+/// Groups files smaller than `target_size` that share a partition prefix, i.e. the same minute
+/// and custom partition values, into sets to be merged together. A set adds up to at most
+/// `target_size` bytes and sets with less than `min_files` files are left as is.
+pub fn plan(files: &[File], target_size: u64, min_files: usize) -> Vec<Vec<&File>> {
+    let mut partitions: BTreeMap<&str, Vec<&File>> = BTreeMap::new();
+    for file in files.iter().filter(|file| file.file_size < target_size) {
+        let prefix = file
+            .file_path
+            .rsplit_once('/')
+            .map_or("", |(prefix, _)| prefix);
+        partitions.entry(prefix).or_default().push(file);
+    }
+
+    let mut groups = vec![];
+    for files in partitions.into_values() {
+        let mut group = vec![];
+        let mut size = 0;
+        for file in files {
+            if !group.is_empty() && size + file.file_size > target_size {
+                groups.push(std::mem::take(&mut group));
+                size = 0;
+            }
+            size += file.file_size;
+            group.push(file);
+        }
+        groups.push(group);
+    }
+    // merging a single file into itself is pointless
+    groups.retain(|group| group.len() >= min_files.max(2));
+
+    groups
+}
+
+/// Rewrites the given parquet files as one, rows are ordered by `sort_column`, latest first,
+/// as is expected of every parquet file of a stream. Columns missing from some of the files
+/// are filled with nulls.
+pub fn merge_parquet(
+    files: &[Bytes],
+    sort_column: &str,
+    props: impl FnOnce(&Schema) -> WriterProperties,
+) -> Result<Vec<u8>, CompactionError> {
+    let mut schemas = Vec::with_capacity(files.len());
+    let mut batches = vec![];
+    for file in files {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file.clone())?;
+        schemas.push(builder.schema().as_ref().clone());
+        for batch in builder.build()? {
+            batches.push(batch?);
+        }
+    }
+
+    let schema = Schema::try_merge(schemas)?;
+    let schema = Arc::new(Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| field.as_ref().clone().with_nullable(true))
+            .collect_vec(),
+        schema.metadata().clone(),
+    ));
+    let batches = batches
+        .iter()
+        .map(|batch| adapt_batch(&schema, batch))
+        .collect_vec();
+    let mut merged = concat_batches(&schema, &batches)?;
+
+    if let Some(column) = merged.column_by_name(sort_column) {
+        let options = SortOptions {
+            descending: true,
+            nulls_first: true,
+        };
+        let indices = sort_to_indices(column, Some(options), None)?;
+        merged = take_record_batch(&merged, &indices)?;
+    }
+
+    let mut buf = vec![];
+    let mut writer = ArrowWriter::try_new(&mut buf, schema.clone(), Some(props(&schema)))?;
+    writer.write(&merged)?;
+    writer.close()?;
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use arrow::util::pretty::pretty_format_batches;
+    use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMillisecondArray};
+    use bytes::Bytes;
+    use chrono::{DateTime, Utc};
+    use datafusion::prelude::{ParquetReadOptions, SessionContext};
+    use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+    use temp_dir::TempDir;
+
+    use crate::{
+        catalog::manifest::{create_from_parquet, File},
+        event::DEFAULT_TIMESTAMP_KEY,
+    };
+
+    use super::{compact_stream, merge_parquet, plan, COMPACTED_FILE_SUFFIX};
+
+    // a file holding three events ingested one after the other
+    fn small_file(index: i64) -> Bytes {
+        let start = "2025-01-01T10:00:00Z"
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+            .timestamp_millis();
+        let timestamps = (0..3)
+            .map(|i| start + index * 10 + i)
+            .rev()
+            .collect::<Vec<_>>();
+        let messages = timestamps.iter().map(|t| format!("event-{t}"));
+        let batch = RecordBatch::try_from_iter([
+            (
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(timestamps)) as ArrayRef,
+            ),
+            (
+                "msg",
+                Arc::new(StringArray::from_iter_values(messages)) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+
+        let mut buf = vec![];
+        let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        buf.into()
+    }
+
+    async fn query(dir: &std::path::Path) -> String {
+        let ctx = SessionContext::new();
+        ctx.register_parquet(
+            "stream",
+            dir.to_str().unwrap(),
+            ParquetReadOptions::default(),
+        )
+        .await
+        .unwrap();
+        let batches = ctx
+            .sql("SELECT * FROM stream ORDER BY p_timestamp")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        pretty_format_batches(&batches).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn five_small_files_are_compacted_into_one() {
+        let files = (0..5).map(small_file).collect::<Vec<_>>();
+        let mut manifest_files = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| File {
+                file_path: format!("app/date=2025-01-01/hour=10/minute=00/{i}.data.parquet"),
+                file_size: file.len() as u64,
+                ..File::default()
+            })
+            .collect::<Vec<_>>();
+        // files of other partitions aren't merged together
+        manifest_files.push(File {
+            file_path: "app/date=2025-01-01/hour=10/minute=01/5.data.parquet".to_owned(),
+            file_size: 100,
+            ..File::default()
+        });
+
+        let groups = plan(&manifest_files, 1024 * 1024, 5);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 5);
+        assert!(plan(&manifest_files, 1024 * 1024, 6).is_empty());
+
+        let merged = merge_parquet(&files, DEFAULT_TIMESTAMP_KEY, |_| {
+            WriterProperties::default()
+        })
+        .unwrap();
+        let merged = Bytes::from(merged);
+        let size = merged.len() as u64;
+        let compacted =
+            create_from_parquet("compacted.parquet".to_owned(), merged.clone(), size).unwrap();
+        assert_eq!(compacted.num_rows, 15);
+
+        let dir = TempDir::new().unwrap();
+        let before = dir.path().join("before");
+        let after = dir.path().join("after");
+        fs::create_dir_all(&before).unwrap();
+        fs::create_dir_all(&after).unwrap();
+        for (i, file) in files.iter().enumerate() {
+            fs::write(before.join(format!("{i}.parquet")), file).unwrap();
+        }
+        fs::write(after.join("compacted.parquet"), &merged).unwrap();
+        assert_eq!(fs::read_dir(&after).unwrap().count(), 1);
+
+        assert_eq!(query(&before).await, query(&after).await);
+    }
+
+    #[tokio::test]
+    async fn compacted_stream_is_queried_for_the_same_data() {
+        use actix_web::Either;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        use relative_path::{RelativePath, RelativePathBuf};
+
+        use crate::{
+            catalog::{self, partition_path},
+            parseable::PARSEABLE,
+            query::{execute, Query, QUERY_SESSION},
+            storage::ObjectStoreFormat,
+            utils::time::TimeRange,
+        };
+
+        let stream_name = "compaction_small_files";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        let files = (0..5).map(small_file).collect::<Vec<_>>();
+        let schema = ParquetRecordBatchReaderBuilder::try_new(files[0].clone())
+            .unwrap()
+            .schema()
+            .clone();
+        stream.set_schema(&schema);
+        store
+            .create_stream(stream_name, ObjectStoreFormat::default(), schema)
+            .await
+            .unwrap();
+        for (i, file) in files.iter().enumerate() {
+            let key = RelativePathBuf::from(format!(
+                "{stream_name}/date=2025-01-01/hour=10/minute=00/{i}.data.parquet"
+            ));
+            store.put_object(&key, file.clone()).await.unwrap();
+            let file = create_from_parquet(
+                store.absolute_url(&key).to_string(),
+                file.clone(),
+                file.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+        }
+
+        let query_stream = || async {
+            let raw_logical_plan = QUERY_SESSION
+                .state()
+                .create_logical_plan(&format!("SELECT * FROM {stream_name} ORDER BY p_timestamp"))
+                .await
+                .unwrap();
+            let query = Query {
+                raw_logical_plan,
+                time_range: TimeRange::new(
+                    "2025-01-01T00:00:00Z".parse().unwrap(),
+                    "2025-01-02T00:00:00Z".parse().unwrap(),
+                ),
+                filter_tag: None,
+            };
+            let (Either::Left(records), _) = execute(query, stream_name, false).await.unwrap()
+            else {
+                unreachable!("non-streaming query returns batches")
+            };
+            pretty_format_batches(&records).unwrap().to_string()
+        };
+        let before = query_stream().await;
+
+        // the five files are replaced by one, in the manifest and in the store
+        assert_eq!(compact_stream(stream_name).await.unwrap(), 5);
+        let meta = store.get_object_store_format(stream_name).await.unwrap();
+        let item = &meta.snapshot.manifest_list[0];
+        let manifest = store
+            .get_manifest(&partition_path(
+                stream_name,
+                item.time_lower_bound,
+                item.time_upper_bound,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.files[0].num_rows, 15);
+        assert!(manifest.files[0].file_path.ends_with(COMPACTED_FILE_SUFFIX));
+        let parquet_files = store
+            .list_objects(RelativePath::new(stream_name))
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|object| object.location.as_ref().ends_with(".parquet"))
+            .count();
+        assert_eq!(parquet_files, 1);
+
+        assert_eq!(query_stream().await, before);
+    }
+
+    #[tokio::test]
+    async fn files_removed_during_compaction_are_not_replaced() {
+        use relative_path::{RelativePath, RelativePathBuf};
+
+        use crate::{
+            catalog::{self, lock_snapshot, partition_path},
+            parseable::PARSEABLE,
+            storage::ObjectStoreFormat,
+        };
+
+        let stream_name = "compaction_concurrent_deletion";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        let files = (0..4).map(small_file).collect::<Vec<_>>();
+        let schema = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+            files[0].clone(),
+        )
+        .unwrap()
+        .schema()
+        .clone();
+        stream.set_schema(&schema);
+        store
+            .create_stream(stream_name, ObjectStoreFormat::default(), schema)
+            .await
+            .unwrap();
+        let mut keys = vec![];
+        for (i, file) in files.iter().enumerate() {
+            let key = RelativePathBuf::from(format!(
+                "{stream_name}/date=2025-01-01/hour=10/minute=00/{i}.data.parquet"
+            ));
+            store.put_object(&key, file.clone()).await.unwrap();
+            let file = create_from_parquet(
+                store.absolute_url(&key).to_string(),
+                file.clone(),
+                file.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+            keys.push(key);
+        }
+        let meta = store.get_object_store_format(stream_name).await.unwrap();
+        let item = &meta.snapshot.manifest_list[0];
+        let path = partition_path(stream_name, item.time_lower_bound, item.time_upper_bound);
+        let compacted_files = || async {
+            store
+                .list_objects(RelativePath::new(stream_name))
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|object| object.location.as_ref().ends_with(COMPACTED_FILE_SUFFIX))
+                .count()
+        };
+
+        // a range deletion holds the snapshot while compaction merges the files
+        let snapshot = lock_snapshot(stream_name).await;
+        let compaction = tokio::spawn(compact_stream(stream_name));
+        for _ in 0..500 {
+            if compacted_files().await > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(compacted_files().await, 1);
+        let mut manifest = store.get_manifest(&path).await.unwrap().unwrap();
+        let deleted = store.absolute_url(&keys[0]).to_string();
+        manifest.files.retain(|file| file.file_path != deleted);
+        store.put_manifest(&path, manifest).await.unwrap();
+        drop(snapshot);
+
+        // the deleted file isn't brought back by the compacted one, nor are the others removed
+        assert_eq!(compaction.await.unwrap().unwrap(), 0);
+        let manifest = store.get_manifest(&path).await.unwrap().unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        assert!(manifest.files.iter().all(|file| file.file_path != deleted));
+        assert_eq!(compacted_files().await, 0);
+        for key in &keys[1..] {
+            assert!(store.get_object(key).await.is_ok());
+        }
+    }
+}
@@ -0,0 +1,371 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use relative_path::RelativePathBuf;
+use tracing::warn;
+
+use crate::{
+    handlers::http::cluster::sync_range_deletion_with_ingestors,
+    option::Mode,
+    parseable::PARSEABLE,
+    storage::{
+        evict_prefix, object_storage::to_bytes, stream_data_root, ObjectStorage, ObjectStorageError,
+    },
+    utils::time::{partition_time_range, TimeRange},
+    OBJECT_STORE_DATA_GRANULARITY,
+};
+
+use super::{compaction::relative_path, lock_snapshot, manifest::Manifest};
+
+/// Deletes the data of the stream that lies within `time_range` from object storage, returns the
+/// number of files that were deleted.
+///
+/// Files are first dropped from the manifests and snapshot, so that queries stop reading them,
+/// before the prefixes of the range are deleted. On a querier, the deletion is forwarded to every
+/// ingestor first, as the files they upload are in their snapshots. Data yet to be uploaded from
+/// staging is left as is.
+pub async fn delete_range(
+    stream_name: &str,
+    time_range: TimeRange,
+) -> Result<usize, ObjectStorageError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    let data_store = PARSEABLE.data_store(stream_name);
+    let range = RangeFilter::new(stream_name, time_range.clone());
+
+    let deleted = {
+        let _snapshot = lock_snapshot(stream_name).await;
+        remove_range_from_snapshot(&*storage, stream_name, &time_range, &range).await?
+    };
+    if PARSEABLE.options.mode == Mode::Query {
+        sync_range_deletion_with_ingestors(stream_name, &time_range).await?;
+    }
+
+    for file_path in &deleted {
+        if let Err(err) = data_store
+            .delete_object(&relative_path(&*data_store, file_path))
+            .await
+        {
+            warn!("Failed to delete file {file_path} within deleted range: {err}");
+        }
+    }
+    // clears whatever is left under the range, e.g. files that never made it into a manifest
+    for path in range.prefixes() {
+        if range.is_exact() {
+            data_store.delete_prefix(&path).await?;
+            evict_prefix(path.as_str());
+            continue;
+        }
+        for object in data_store.list_objects(&path).await? {
+            let key = object.location.as_ref();
+            if !range.contains_key(key) {
+                continue;
+            }
+            data_store
+                .delete_object(&RelativePathBuf::from(key))
+                .await?;
+            evict_prefix(key);
+        }
+    }
+
+    Ok(deleted.len())
+}
+
+/// Drops the files within `range` from the manifests and snapshot of the stream, returns the
+/// paths of the dropped files
+async fn remove_range_from_snapshot(
+    storage: &dyn ObjectStorage,
+    stream_name: &str,
+    time_range: &TimeRange,
+    range: &RangeFilter,
+) -> Result<Vec<String>, ObjectStorageError> {
+    let mut meta = storage.get_object_store_format(stream_name).await?;
+
+    let mut deleted = vec![];
+    let mut emptied = vec![];
+    for item in meta.snapshot.manifest_list.iter_mut().filter(|item| {
+        item.time_lower_bound <= time_range.end && item.time_upper_bound >= time_range.start
+    }) {
+        let path = relative_path(storage, &item.manifest_path);
+        let bytes = match storage.get_object(&path).await {
+            Ok(bytes) => bytes,
+            Err(ObjectStorageError::NoSuchKey(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        let mut manifest: Manifest = serde_json::from_slice(&bytes)?;
+
+        let (removed, kept) = manifest
+            .files
+            .into_iter()
+            .partition::<Vec<_>, _>(|file| range.contains(&file.file_path));
+        if removed.is_empty() {
+            continue;
+        }
+
+        for file in &removed {
+            item.events_ingested = item.events_ingested.saturating_sub(file.num_rows);
+            item.ingestion_size = item.ingestion_size.saturating_sub(file.ingestion_size);
+            item.storage_size = item.storage_size.saturating_sub(file.file_size);
+        }
+        deleted.extend(removed.into_iter().map(|file| file.file_path));
+
+        if kept.is_empty() {
+            emptied.push(item.manifest_path.clone());
+            storage.delete_object(&path).await?;
+        } else {
+            manifest.files = kept;
+            storage.put_object(&path, to_bytes(&manifest)).await?;
+        }
+    }
+
+    meta.snapshot
+        .manifest_list
+        .retain(|item| !emptied.contains(&item.manifest_path));
+    storage.put_snapshot(stream_name, meta.snapshot).await?;
+
+    Ok(deleted)
+}
+
+/// Matches the files of a stream within a time range, by the prefixes generated for the range in
+/// the timezone the partitions of the stream are named in. Local times repeat where clocks are
+/// turned back, so the files of zoned partitions are also matched by the time range they are for.
+pub struct RangeFilter {
+    data_store: Arc<dyn ObjectStorage>,
+    data_root: RelativePathBuf,
+    prefixes: Vec<String>,
+    time_range: TimeRange,
+    timezone: Option<Tz>,
+}
+
+impl RangeFilter {
+    pub fn new(stream_name: &str, time_range: TimeRange) -> Self {
+        let settings = PARSEABLE
+            .get_stream(stream_name)
+            .map(|stream| stream.get_settings())
+            .unwrap_or_default();
+        let timezone = settings.partition_timezone();
+        let prefixes = time_range
+            .clone()
+            .in_partition_time(timezone)
+            .generate_prefixes(OBJECT_STORE_DATA_GRANULARITY);
+
+        Self {
+            data_store: PARSEABLE.data_store(stream_name),
+            data_root: stream_data_root(stream_name, settings.storage_prefix.as_deref()),
+            prefixes,
+            time_range,
+            timezone,
+        }
+    }
+
+    /// Whether everything under the prefixes of the range is within it
+    pub fn is_exact(&self) -> bool {
+        self.timezone.is_none()
+    }
+
+    /// First and last date of the partitions, and so the manifests, of the range
+    pub fn partition_dates(&self) -> (NaiveDate, NaiveDate) {
+        let range = self.time_range.clone().in_partition_time(self.timezone);
+        (range.start.date_naive(), range.end.date_naive())
+    }
+
+    /// Prefixes of the range within the data root of the stream, relative to the storage root
+    pub fn prefixes(&self) -> impl Iterator<Item = RelativePathBuf> + '_ {
+        self.prefixes
+            .iter()
+            .map(|prefix| self.data_root.join(prefix))
+    }
+
+    /// Checks if the file, by its path as recorded in manifests, is within the range
+    pub fn contains(&self, file_path: &str) -> bool {
+        self.contains_key(relative_path(&*self.data_store, file_path).as_str())
+    }
+
+    /// Checks if the object, by its key relative to the storage root, is within the range
+    pub fn contains_key(&self, key: &str) -> bool {
+        is_within_prefixes(key, self.data_root.as_str(), &self.prefixes)
+            && (self.is_exact()
+                || partition_time_range(key).is_none_or(|range| range.overlaps(&self.time_range)))
+    }
+}
+
+/// Checks if the path of a file, relative to the storage root, lies under any of the prefixes
+/// generated for a time range, e.g. `date=2025-01-01/hour=10/`, within the data root of the stream
+pub fn is_within_prefixes(path: &str, data_root: &str, prefixes: &[String]) -> bool {
+    path.strip_prefix(data_root)
+        .and_then(|path| path.strip_prefix('/'))
+        .is_some_and(|path| prefixes.iter().any(|prefix| path.starts_with(prefix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::utils::time::TimeRange;
+
+    use super::is_within_prefixes;
+
+    #[test]
+    fn deleting_an_hour_leaves_neighbouring_hours() {
+        let range = TimeRange::new(
+            Utc.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 1, 1, 11, 0, 0).unwrap(),
+        );
+        let prefixes = range.generate_prefixes(1);
+        assert_eq!(prefixes, vec!["date=2025-01-01/hour=10/"]);
+
+        let deleted = |path: &str| is_within_prefixes(path, "app", &prefixes);
+        assert!(deleted(
+            "app/date=2025-01-01/hour=10/minute=00/host.data.parquet"
+        ));
+        assert!(deleted(
+            "app/date=2025-01-01/hour=10/minute=59/region=us/host.data.parquet"
+        ));
+        assert!(!deleted(
+            "app/date=2025-01-01/hour=09/minute=59/host.data.parquet"
+        ));
+        assert!(!deleted(
+            "app/date=2025-01-01/hour=11/minute=00/host.data.parquet"
+        ));
+        // same partition of another stream
+        assert!(!deleted(
+            "application/date=2025-01-01/hour=10/minute=00/host.data.parquet"
+        ));
+    }
+
+    #[tokio::test]
+    async fn deleting_a_range_of_a_zoned_stream_drops_its_local_partitions() {
+        use std::sync::Arc;
+
+        use arrow_array::{RecordBatch, TimestampMillisecondArray};
+        use bytes::Bytes;
+        use chrono_tz::America::New_York;
+        use parquet::arrow::ArrowWriter;
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog,
+            event::DEFAULT_TIMESTAMP_KEY,
+            metadata::StreamSettings,
+            parseable::PARSEABLE,
+            storage::{ObjectStorageError, ObjectStoreFormat},
+        };
+
+        let stream_name = "delete_range_new_york";
+        PARSEABLE
+            .get_or_create_stream(stream_name)
+            .set_settings(StreamSettings {
+                partition_timezone: Some("America/New_York".to_owned()),
+                ..Default::default()
+            });
+        let store = PARSEABLE.storage.get_object_store();
+        let batch_at = |hour: u32| {
+            let at = New_York.with_ymd_and_hms(2025, 1, 1, hour, 30, 0).unwrap();
+            RecordBatch::try_from_iter([(
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as _,
+            )])
+            .unwrap()
+        };
+        store
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat::default(),
+                batch_at(21).schema(),
+            )
+            .await
+            .unwrap();
+        // evenings in New York are on the next day in UTC
+        let path_at = |hour: u32, name: &str| {
+            RelativePathBuf::from(format!(
+                "{stream_name}/date=2025-01-01/hour={hour}/minute=30/zone=-0500/{name}.data.parquet"
+            ))
+        };
+        let mut paths = vec![];
+        for hour in [21, 22] {
+            let batch = batch_at(hour);
+            let mut parquet = vec![];
+            let mut writer = ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+            let parquet = Bytes::from(parquet);
+            let path = path_at(hour, "host");
+            store.put_object(&path, parquet.clone()).await.unwrap();
+            let file = catalog::manifest::create_from_parquet(
+                store.absolute_url(&path).to_string(),
+                parquet.clone(),
+                parquet.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+            paths.push(path);
+
+            // a file that never made it into a manifest
+            let leftover = path_at(hour, "leftover");
+            store.put_object(&leftover, parquet).await.unwrap();
+            paths.push(leftover);
+        }
+
+        let deleted = super::delete_range(
+            stream_name,
+            TimeRange::new(
+                Utc.with_ymd_and_hms(2025, 1, 2, 2, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2025, 1, 2, 3, 0, 0).unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(deleted, 1);
+
+        // the local hour the range is in is gone, leftovers included, the next one is kept
+        for path in &paths[..2] {
+            assert!(matches!(
+                store.get_object(path).await,
+                Err(ObjectStorageError::NoSuchKey(_))
+            ));
+        }
+        for path in &paths[2..] {
+            assert!(store.get_object(path).await.is_ok());
+        }
+        let snapshot = store
+            .get_object_store_format(stream_name)
+            .await
+            .unwrap()
+            .snapshot;
+        assert_eq!(snapshot.manifest_list.len(), 1);
+        let item = &snapshot.manifest_list[0];
+        let manifest = store
+            .get_manifest(&catalog::partition_path(
+                stream_name,
+                item.time_lower_bound,
+                item.time_upper_bound,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert!(manifest.files[0]
+            .file_path
+            .ends_with("hour=22/minute=30/zone=-0500/host.data.parquet"));
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+use std::collections::HashSet;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use object_store::ObjectMeta;
+use relative_path::RelativePath;
+use tracing::{info, warn};
+
+use crate::{
+    parseable::PARSEABLE,
+    storage::{stream_data_root, ObjectStorageError, MANIFEST_FILE},
+};
+
+use super::{compaction::relative_path, manifest::Manifest};
+
+/// Deletes the orphaned parquet files of every stream, failures are logged and don't stop the
+/// collection of the other streams.
+pub async fn collect_garbage() {
+    let cutoff = TimeDelta::from_std(PARSEABLE.options.gc_safety_window)
+        .ok()
+        .and_then(|window| Utc::now().checked_sub_signed(window))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    for stream_name in PARSEABLE.streams.list() {
+        match collect_stream_garbage(&stream_name, cutoff).await {
+            Ok(0) => {}
+            Ok(count) => info!("Deleted {count} orphaned parquet files of stream- {stream_name}"),
+            Err(err) => warn!("Failed to collect garbage of stream- {stream_name}: {err}"),
+        }
+    }
+}
+
+/// Deletes parquet files under the data root of the stream that no manifest refers to, e.g. left behind
+/// by a failed upload or compaction, returns the number of files deleted.
+///
+/// Files are only deleted if last modified before `cutoff`, as a file is uploaded before the
+/// manifest is updated to refer to it, so a recent file may just not be referred to yet.
+pub async fn collect_stream_garbage(
+    stream_name: &str,
+    cutoff: DateTime<Utc>,
+) -> Result<usize, ObjectStorageError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    let settings = PARSEABLE.get_stream(stream_name)?.get_settings();
+    let data_store = PARSEABLE
+        .storage
+        .get_data_store(settings.storage_bucket.as_deref());
+    // manifests are in the directory of the stream, its data files are under its storage prefix
+    // in its bucket, when it has them
+    let listed = storage.list_objects(RelativePath::new(stream_name)).await?;
+    let objects = if settings.storage_bucket.is_none() && settings.storage_prefix.is_none() {
+        listed.clone()
+    } else {
+        data_store
+            .list_objects(&stream_data_root(
+                stream_name,
+                settings.storage_prefix.as_deref(),
+            ))
+            .await?
+    };
+
+    // manifests of every node are considered, not only the ones written by this node
+    let mut referenced = HashSet::new();
+    for object in listed
+        .iter()
+        .filter(|object| object.location.as_ref().ends_with(MANIFEST_FILE))
+    {
+        let bytes = storage
+            .get_object(RelativePath::new(object.location.as_ref()))
+            .await?;
+        let manifest: Manifest = serde_json::from_slice(&bytes)?;
+        referenced.extend(
+            manifest
+                .files
+                .iter()
+                .map(|file| relative_path(&*data_store, &file.file_path).to_string()),
+        );
+    }
+
+    let mut deleted = 0;
+    for object in orphans(&objects, &referenced, cutoff) {
+        let path = RelativePath::new(object.location.as_ref());
+        if let Err(err) = data_store.delete_object(path).await {
+            warn!("Failed to delete orphaned file {path}: {err}");
+            continue;
+        }
+        deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Parquet files among `objects` that aren't `referenced` and were last modified before `cutoff`
+pub fn orphans<'a>(
+    objects: &'a [ObjectMeta],
+    referenced: &HashSet<String>,
+    cutoff: DateTime<Utc>,
+) -> Vec<&'a ObjectMeta> {
+    objects
+        .iter()
+        .filter(|object| {
+            object.location.extension() == Some("parquet")
+                && object.last_modified < cutoff
+                && !referenced.contains(object.location.as_ref())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::path::Path;
+
+    use super::*;
+
+    fn object(location: &str, last_modified: DateTime<Utc>) -> ObjectMeta {
+        ObjectMeta {
+            location: Path::from(location),
+            last_modified,
+            size: 1024,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn only_old_unreferenced_files_are_orphans() {
+        let now = Utc::now();
+        let cutoff = now - TimeDelta::hours(24);
+        let prefix = "app/date=2025-01-01/hour=00/minute=00";
+        let objects = [
+            object(
+                &format!("{prefix}/host.data.abc.parquet"),
+                now - TimeDelta::days(2),
+            ),
+            object(
+                &format!("{prefix}/host.data.def.parquet"),
+                now - TimeDelta::days(2),
+            ),
+            object(&format!("{prefix}/host.data.ghi.parquet"), now),
+            object(
+                "app/date=2025-01-01/manifest.json",
+                now - TimeDelta::days(2),
+            ),
+        ];
+        let referenced = HashSet::from([format!("{prefix}/host.data.abc.parquet")]);
+
+        let orphans = orphans(&objects, &referenced, cutoff);
+
+        // the referenced file, the one within the safety window and the manifest are kept
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(
+            orphans[0].location.as_ref(),
+            format!("{prefix}/host.data.def.parquet")
+        );
+    }
+
+    #[tokio::test]
+    async fn orphaned_files_are_deleted_from_the_store() {
+        use std::sync::Arc;
+
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch, TimestampMillisecondArray};
+        use arrow_schema::Schema;
+        use bytes::Bytes;
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog, event::DEFAULT_TIMESTAMP_KEY, metadata::StreamSettings,
+            storage::ObjectStoreFormat,
+        };
+
+        let at = "2025-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let rb = RecordBatch::try_from_iter([
+            (
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as ArrayRef,
+            ),
+            ("code", Arc::new(Int64Array::from(vec![200])) as ArrayRef),
+        ])
+        .unwrap();
+        let mut parquet = vec![];
+        let mut writer =
+            parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+        writer.write(&rb).unwrap();
+        writer.close().unwrap();
+        let parquet = Bytes::from(parquet);
+
+        // data files are either in the directory of the stream or under its storage prefix
+        for (stream_name, storage_prefix) in
+            [("gc_orphans", None), ("gc_orphans_prefixed", Some("gc"))]
+        {
+            let stream = PARSEABLE.get_or_create_stream(stream_name);
+            stream.set_settings(StreamSettings {
+                storage_prefix: storage_prefix.map(str::to_owned),
+                ..Default::default()
+            });
+            let store = PARSEABLE.storage.get_object_store();
+            store
+                .create_stream(
+                    stream_name,
+                    ObjectStoreFormat::default(),
+                    Arc::new(Schema::empty()),
+                )
+                .await
+                .unwrap();
+            let partition = stream_data_root(stream_name, storage_prefix)
+                .join("date=2025-01-01/hour=00/minute=00");
+            let referenced: RelativePathBuf = partition.join("host.data.abc.parquet");
+            let orphaned: RelativePathBuf = partition.join("host.data.def.parquet");
+            store
+                .put_object(&referenced, parquet.clone())
+                .await
+                .unwrap();
+            store.put_object(&orphaned, parquet.clone()).await.unwrap();
+            let file = catalog::manifest::create_from_parquet(
+                store.absolute_url(&referenced).to_string(),
+                parquet.clone(),
+                parquet.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+
+            // both files were just written, past the cutoff they are old enough
+            let deleted = collect_stream_garbage(stream_name, Utc::now() + TimeDelta::minutes(1))
+                .await
+                .unwrap();
+            assert_eq!(deleted, 1);
+            assert!(store.get_object(&referenced).await.is_ok());
+            assert!(matches!(
+                store.get_object(&orphaned).await,
+                Err(ObjectStorageError::NoSuchKey(_))
+            ));
+        }
+    }
+}
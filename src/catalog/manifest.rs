@@ -19,7 +19,10 @@
 use std::collections::HashMap;
 
 use itertools::Itertools;
-use parquet::{file::reader::FileReader, format::SortingColumn};
+use parquet::{
+    file::reader::{ChunkReader, FileReader},
+    format::SortingColumn,
+};
 
 use super::column::Column;
 
@@ -91,16 +94,26 @@ impl Manifest {
 pub fn create_from_parquet_file(
     object_store_path: String,
     fs_file_path: &std::path::Path,
+) -> anyhow::Result<File> {
+    let file = std::fs::File::open(fs_file_path)?;
+    let file_size = file.metadata()?.len();
+
+    create_from_parquet(object_store_path, file, file_size)
+}
+
+/// Creates a manifest entry for the parquet file of `file_size` bytes read from `reader`
+pub fn create_from_parquet<R: ChunkReader + 'static>(
+    object_store_path: String,
+    reader: R,
+    file_size: u64,
 ) -> anyhow::Result<File> {
     let mut manifest_file = File {
         file_path: object_store_path,
+        file_size,
         ..File::default()
     };
 
-    let file = std::fs::File::open(fs_file_path)?;
-    manifest_file.file_size = file.metadata()?.len();
-
-    let file = parquet::file::serialized_reader::SerializedFileReader::new(file)?;
+    let file = parquet::file::serialized_reader::SerializedFileReader::new(reader)?;
     let file_meta = file.metadata().file_metadata();
     let row_groups = file.metadata().row_groups();
 
@@ -16,14 +16,20 @@
  *
  */
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex as StdMutex},
+};
 
-use chrono::{DateTime, Local, NaiveTime, Utc};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
 use column::Column;
 use manifest::Manifest;
+use once_cell::sync::Lazy;
 use relative_path::RelativePathBuf;
 use snapshot::ManifestItem;
 use std::io::Error as IOError;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 use tracing::{error, info};
 
 use crate::{
@@ -42,11 +48,17 @@ use crate::{
     stats::{event_labels_date, get_current_stats, storage_size_labels_date, update_deleted_stats},
     storage::{
         object_storage::manifest_path, ObjectStorage, ObjectStorageError, ObjectStoreFormat,
+        STREAM_ROOT_DIRECTORY,
     },
+    utils::time::{from_timestamp, timestamp_unit},
 };
 pub use manifest::create_from_parquet_file;
 
+pub mod backfill;
 pub mod column;
+pub mod compaction;
+pub mod deletion;
+pub mod gc;
 pub mod manifest;
 pub mod snapshot;
 pub trait Snapshot {
@@ -100,18 +112,35 @@ fn get_file_bounds(
         .unwrap()
     {
         column::TypedStatistics::Int(stats) => (
-            DateTime::from_timestamp_millis(stats.min).unwrap(),
-            DateTime::from_timestamp_millis(stats.max).unwrap(),
+            from_timestamp(stats.min, timestamp_unit()).unwrap(),
+            from_timestamp(stats.max, timestamp_unit()).unwrap(),
         ),
         _ => unreachable!(),
     }
 }
 
+/// Locks of the snapshots of streams on this node, by stream name
+static SNAPSHOT_LOCKS: Lazy<StdMutex<HashMap<String, Arc<Mutex<()>>>>> =
+    Lazy::new(Default::default);
+
+/// Locks the snapshot of the stream, to be held while it is read, changed and written back, so
+/// that changes made to it concurrently, e.g. by uploads and deletions, aren't lost
+pub async fn lock_snapshot(stream_name: &str) -> OwnedMutexGuard<()> {
+    let lock = SNAPSHOT_LOCKS
+        .lock()
+        .unwrap()
+        .entry(stream_name.to_owned())
+        .or_default()
+        .clone();
+    lock.lock_owned().await
+}
+
 pub async fn update_snapshot(
     storage: Arc<dyn ObjectStorage>,
     stream_name: &str,
     change: manifest::File,
 ) -> Result<(), ObjectStorageError> {
+    let _snapshot = lock_snapshot(stream_name).await;
     let mut meta = storage.get_object_store_format(stream_name).await?;
     let manifests = &mut meta.snapshot.manifest_list;
     let time_partition = &meta.time_partition;
@@ -254,14 +283,12 @@ async fn create_manifest(
     ingestion_size: u64,
     storage_size: u64,
 ) -> Result<(), ObjectStorageError> {
-    let lower_bound = lower_bound.date_naive().and_time(NaiveTime::MIN).and_utc();
-    let upper_bound = lower_bound
-        .date_naive()
-        .and_time(
-            NaiveTime::from_num_seconds_from_midnight_opt(23 * 3600 + 59 * 60 + 59, 999_999_999)
-                .ok_or(IOError::other("Failed to create upper bound for manifest"))?,
-        )
-        .and_utc();
+    // manifests cover a day of the timezone the partitions of the stream are named in
+    let (lower_bound, upper_bound) = match partition_timezone(stream_name) {
+        Some(timezone) => day_bounds(&timezone, lower_bound),
+        None => day_bounds(&Utc, lower_bound),
+    }
+    .ok_or(IOError::other("Failed to create bounds for manifest"))?;
 
     let manifest = Manifest {
         files: vec![change],
@@ -327,6 +354,7 @@ pub async fn remove_manifest_from_snapshot(
     dates: Vec<String>,
 ) -> Result<Option<String>, ObjectStorageError> {
     if !dates.is_empty() {
+        let _snapshot = lock_snapshot(stream_name).await;
         // get current snapshot
         let mut meta = storage.get_object_store_format(stream_name).await?;
         let meta_for_stats = meta.clone();
@@ -356,6 +384,66 @@ pub async fn remove_manifest_from_snapshot(
     }
 }
 
+/// Time of the latest event of the stream in object storage, the upper bound of the files in its
+/// latest manifest. `None` if nothing was uploaded yet
+///
+/// Other than on ingestors, the snapshots of all nodes uploading into the stream are merged, as
+/// each ingestor keeps the manifests of the files it uploaded in a snapshot of its own
+pub async fn get_latest_event(
+    storage: Arc<dyn ObjectStorage>,
+    stream_name: &str,
+) -> Result<Option<DateTime<Utc>>, ObjectStorageError> {
+    let meta = storage.get_object_store_format(stream_name).await?;
+    let manifests = match PARSEABLE.options.mode {
+        Mode::Ingest => meta.snapshot.manifest_list,
+        _ => {
+            let path = RelativePathBuf::from_iter([stream_name, STREAM_ROOT_DIRECTORY]);
+            storage
+                .get_objects(
+                    Some(&path),
+                    Box::new(|file_name| file_name.ends_with("stream.json")),
+                )
+                .await?
+                .iter()
+                .filter_map(|ob| serde_json::from_slice::<ObjectStoreFormat>(ob).ok())
+                .flat_map(|format| format.snapshot.manifest_list)
+                .collect()
+        }
+    };
+    // manifests of ingestors uploading on the same dates have the same bounds
+    let Some(latest) = manifests.iter().map(|item| item.time_upper_bound).max() else {
+        return Ok(None);
+    };
+    let time_partition = meta
+        .time_partition
+        .unwrap_or_else(|| DEFAULT_TIMESTAMP_KEY.to_string());
+
+    let mut latest_event = None;
+    for item in manifests
+        .iter()
+        .filter(|item| item.time_upper_bound == latest)
+    {
+        let Some(file_name) = item.manifest_path.rsplit('/').next() else {
+            continue;
+        };
+        let path = partition_path(stream_name, item.time_lower_bound, item.time_upper_bound)
+            .join(file_name);
+        let manifest: Manifest = match storage.get_object(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(ObjectStorageError::NoSuchKey(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        latest_event = manifest
+            .files
+            .iter()
+            .map(|file| get_file_bounds(file, time_partition.clone()).1)
+            .chain(latest_event)
+            .max();
+    }
+
+    Ok(latest_event)
+}
+
 pub async fn get_first_event(
     storage: Arc<dyn ObjectStorage>,
     stream_name: &str,
@@ -449,6 +537,33 @@ pub async fn get_first_event(
     Ok(Some(first_event_at))
 }
 
+/// Timezone the partitions of the stream are aligned to, if not UTC
+fn partition_timezone(stream_name: &str) -> Option<Tz> {
+    PARSEABLE
+        .get_stream(stream_name)
+        .ok()
+        .and_then(|stream| stream.get_settings().partition_timezone())
+}
+
+/// First and last instant of the day in `timezone` that `at` lies in
+fn day_bounds<Z: TimeZone>(
+    timezone: &Z,
+    at: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let day = at.with_timezone(timezone).date_naive();
+    let midnight = |day: NaiveDate| {
+        timezone
+            .from_local_datetime(&day.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|midnight| midnight.with_timezone(&Utc))
+    };
+
+    Some((
+        midnight(day)?,
+        midnight(day.succ_opt()?)? - TimeDelta::nanoseconds(1),
+    ))
+}
+
 /// Partition the path to which this manifest belongs.
 /// Useful when uploading the manifest file.
 pub fn partition_path(
@@ -456,8 +571,14 @@ pub fn partition_path(
     lower_bound: DateTime<Utc>,
     upper_bound: DateTime<Utc>,
 ) -> RelativePathBuf {
-    let lower = lower_bound.date_naive().format("%Y-%m-%d").to_string();
-    let upper = upper_bound.date_naive().format("%Y-%m-%d").to_string();
+    // manifests are dated in the timezone the partitions of the stream are aligned to
+    let timezone = partition_timezone(stream);
+    let date = |bound: DateTime<Utc>| match timezone {
+        Some(timezone) => bound.with_timezone(&timezone).date_naive(),
+        None => bound.date_naive(),
+    };
+    let lower = date(lower_bound).format("%Y-%m-%d").to_string();
+    let upper = date(upper_bound).format("%Y-%m-%d").to_string();
     if lower == upper {
         RelativePathBuf::from_iter([stream, &format!("date={}", lower)])
     } else {
@@ -16,8 +16,9 @@
  *
  */
 
+use chrono_tz::Tz;
 use clap::Parser;
-use std::{env, fs, path::PathBuf};
+use std::{env, fs, path::PathBuf, time::Duration};
 
 use url::Url;
 
@@ -25,9 +26,11 @@ use url::Url;
 use crate::connectors::kafka::config::KafkaConfig;
 
 use crate::{
+    event::format::{ClockSkewPolicy, CoercionPolicy, DuplicateColumnPolicy, FieldLengthPolicy},
     oidc::{self, OpenidConfig},
-    option::{validation, Compression, Mode},
+    option::{validation, AckMode, Compression, Mode},
     storage::{AzureBlobConfig, FSConfig, S3Config},
+    utils::time::TimestampPrecision,
 };
 
 /// Default username and password for Parseable server, used by default for local mode.
@@ -208,6 +211,14 @@ pub struct Options {
     )]
     pub trusted_ca_certs_path: Option<PathBuf>,
 
+    #[arg(
+        long,
+        env = "P_API_TOKENS_FILE",
+        value_parser = validation::file_path,
+        help = "Local path on this device to a JSON file of bearer tokens scoped to streams and operations"
+    )]
+    pub api_tokens_file: Option<PathBuf>,
+
     /// Allows invalid TLS certificates for intra-cluster communication.
     /// This is needed when nodes connect to each other via IP addresses
     /// which don't match the domain names in their certificates.
@@ -238,6 +249,23 @@ pub struct Options {
     )]
     pub hot_tier_storage_path: Option<PathBuf>,
 
+    #[arg(
+        long,
+        env = "P_OBJECT_CACHE_PATH",
+        value_parser = validation::canonicalize_path,
+        help = "Local path on this device to cache parquet files downloaded from object store for queries"
+    )]
+    pub object_cache_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "P_OBJECT_CACHE_SIZE",
+        default_value = "10 GiB",
+        value_parser = validation::human_size,
+        help = "Maximum size of the object store cache, least recently read files are evicted beyond it"
+    )]
+    pub object_cache_size: u64,
+
     //TODO: remove this when smart cache is implemented
     #[arg(
         long = "index-storage-path",
@@ -273,6 +301,13 @@ pub struct Options {
     )]
     pub flight_port: u16,
 
+    #[arg(
+        long,
+        env = "P_FLIGHT_SQL_PORT",
+        help = "Port for the Arrow Flight SQL endpoint for BI tools, disabled when unset"
+    )]
+    pub flight_sql_port: Option<u16>,
+
     // Performance settings
     #[arg(
         long,
@@ -300,6 +335,22 @@ pub struct Options {
     )]
     pub row_group_size: usize,
 
+    #[arg(
+        long,
+        env = "P_PARQUET_WRITE_BATCH_SIZE",
+        default_value = "1024",
+        help = "Number of rows handed to the parquet writer at a time"
+    )]
+    pub parquet_write_batch_size: usize,
+
+    #[arg(
+        long,
+        env = "P_PARQUET_MAX_BUFFER_SIZE",
+        value_parser = validation::human_size,
+        help = "Size of encoded rows buffered by the parquet writer after which the row group is flushed early, e.g. \"128 MiB\". Row groups are only flushed at the row group size when unset"
+    )]
+    pub parquet_max_buffer_size: Option<u64>,
+
     #[arg(
         long,
         env = "P_EXECUTION_BATCH_SIZE",
@@ -317,6 +368,14 @@ pub struct Options {
     )]
     pub parquet_compression: Compression,
 
+    #[arg(
+        long,
+        env = "P_PARQUET_BLOOM_FILTER_COLUMNS",
+        value_delimiter = ',',
+        help = "Comma separated list of columns to write parquet bloom filters for, useful for high cardinality string columns queried by equality"
+    )]
+    pub parquet_bloom_filter_columns: Vec<String>,
+
     // Integration features
     #[arg(
         long,
@@ -388,6 +447,366 @@ pub struct Options {
         help = "Maximum level of flattening allowed for events"
     )]
     pub event_flatten_level: usize,
+
+    #[arg(
+        long,
+        env = "P_MAX_INFERENCE_DEPTH",
+        help = "Level of nesting beyond which objects in events are stored as JSON strings instead of being inferred into columns"
+    )]
+    pub max_inference_depth: Option<usize>,
+
+    // number events of a stream sequentially as they are ingested,
+    // to tell gaps and duplicates apart at query time
+    #[arg(
+        long,
+        env = "P_SEQUENCE_NUMBERS",
+        default_value = "false",
+        help = "Assign a monotonically increasing sequence number to events that don't carry one"
+    )]
+    pub sequence_numbers: bool,
+
+    // rename incoming fields into snake_case, so that `requestId` and
+    // `request_id` of different shippers end up in the same column
+    #[arg(
+        long,
+        env = "P_SNAKE_CASE_FIELDS",
+        default_value = "false",
+        help = "Rename fields of incoming events into snake_case before their schema is inferred"
+    )]
+    pub snake_case_fields: bool,
+
+    // fold casing of incoming field names onto that of known columns,
+    // so that `Status` and `status` end up in the same column
+    #[arg(
+        long,
+        env = "P_CASE_INSENSITIVE_FIELDS",
+        default_value = "false",
+        help = "Match incoming field names to existing columns ignoring case"
+    )]
+    pub case_insensitive_fields: bool,
+
+    // number of records the schema of a new stream is inferred from,
+    // so that fields missing from the very first record get their type from the others
+    #[arg(
+        long,
+        env = "P_INFERENCE_SAMPLE_SIZE",
+        default_value = "1",
+        help = "Number of records of the first events of a stream to infer its schema from"
+    )]
+    pub inference_sample_size: usize,
+
+    #[arg(
+        long,
+        env = "P_STREAM_SELECTOR_FIELD",
+        default_value = "stream",
+        help = "Field naming the stream each record posted to the batch ingest endpoint is routed to"
+    )]
+    pub stream_selector_field: String,
+
+    #[arg(
+        long,
+        env = "P_TYPE_COERCION",
+        default_value = "strict",
+        value_parser = validation::coercion_policy,
+        help = "Policy for values that don't match the type of an existing column: strict, string or number"
+    )]
+    pub type_coercion: CoercionPolicy,
+
+    #[arg(
+        long,
+        env = "P_CLOCK_SKEW_TOLERANCE",
+        value_parser = humantime::parse_duration,
+        help = "How far from the current time the time partition of events may be, e.g. \"1d\". Events are not checked when unset"
+    )]
+    pub clock_skew_tolerance: Option<Duration>,
+
+    #[arg(
+        long,
+        env = "P_CLOCK_SKEW_POLICY",
+        default_value = "reject",
+        value_parser = validation::clock_skew_policy,
+        help = "Policy for events outside the clock skew tolerance: reject, or clamp to the current time"
+    )]
+    pub clock_skew_policy: ClockSkewPolicy,
+
+    #[arg(
+        long,
+        env = "P_MAX_FIELD_NAME_LENGTH",
+        help = "Maximum length in bytes of field names in events. Field names are not checked when unset"
+    )]
+    pub max_field_name_length: Option<usize>,
+
+    #[arg(
+        long,
+        env = "P_MAX_FIELD_VALUE_LENGTH",
+        help = "Maximum length in bytes of string values in events. Values are not checked when unset"
+    )]
+    pub max_field_value_length: Option<usize>,
+
+    #[arg(
+        long,
+        env = "P_FIELD_LENGTH_POLICY",
+        default_value = "reject",
+        value_parser = validation::field_length_policy,
+        help = "Policy for field names and values longer than the maximum: reject, or truncate to the maximum"
+    )]
+    pub field_length_policy: FieldLengthPolicy,
+
+    #[arg(
+        long,
+        env = "P_DUPLICATE_COLUMN_POLICY",
+        default_value = "suffix",
+        value_parser = validation::duplicate_column_policy,
+        help = "Policy for fields of an event that flatten into the same column name: reject, or suffix the later ones with _<n>"
+    )]
+    pub duplicate_column_policy: DuplicateColumnPolicy,
+
+    #[arg(
+        long,
+        env = "P_NAIVE_TIMESTAMP_TIMEZONE",
+        value_parser = validation::timezone,
+        help = "Timezone timestamps without an offset, in query ranges and time partitions of events, are assumed to be in, e.g. \"Asia/Kolkata\". Such timestamps are rejected when unset"
+    )]
+    pub naive_timestamp_timezone: Option<Tz>,
+
+    #[arg(
+        long,
+        env = "P_TIMESTAMP_PRECISION",
+        default_value = "ms",
+        value_parser = validation::timestamp_precision,
+        help = "Precision of p_timestamp and time columns: s, ms, us or ns, not to be changed once data is ingested"
+    )]
+    pub timestamp_precision: TimestampPrecision,
+
+    #[arg(
+        long,
+        env = "P_ACK_MODE",
+        default_value = "async",
+        value_parser = validation::ack_mode,
+        help = "When to acknowledge ingested events: async (once staged) or sync (once uploaded to object store)"
+    )]
+    pub ack_mode: AckMode,
+
+    #[arg(
+        long,
+        env = "P_ACK_TIMEOUT",
+        default_value = "5m",
+        value_parser = humantime::parse_duration,
+        help = "Maximum duration to wait in sync ack mode for events to be uploaded, before the request fails"
+    )]
+    pub ack_timeout: Duration,
+
+    // maximum number of prefixes a single query is allowed to list in object store
+    #[arg(
+        long,
+        env = "P_MAX_QUERY_PREFIXES",
+        default_value = "10000",
+        help = "Maximum number of object store prefixes a query may scan, 0 disables the limit"
+    )]
+    pub max_query_prefixes: usize,
+
+    #[arg(
+        long,
+        env = "P_QUERY_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        help = "Maximum duration a query may execute for before it is cancelled, e.g. \"5m\""
+    )]
+    pub query_timeout: Option<Duration>,
+
+    #[arg(
+        long,
+        env = "P_QUERY_ORDER_BY_TIME",
+        default_value = "true",
+        help = "Return the results of queries without an ORDER BY latest first, as per their ingestion time"
+    )]
+    pub query_order_by_time: bool,
+
+    #[arg(
+        long,
+        env = "P_DEFAULT_QUERY_WINDOW",
+        default_value = "1h",
+        value_parser = humantime::parse_duration,
+        help = "Duration up to now that queries without a startTime and endTime are run over"
+    )]
+    pub default_query_window: Duration,
+
+    #[arg(
+        long,
+        env = "P_MAX_CONCURRENT_QUERIES",
+        help = "Maximum number of queries executing at the same time, queries beyond this are rejected, 0 means no limit"
+    )]
+    pub max_concurrent_queries: Option<usize>,
+
+    #[arg(
+        long,
+        env = "P_QUERY_FETCH_CONCURRENCY",
+        default_value = "16",
+        help = "Maximum number of object store listings and manifest fetches a query has in flight at a time"
+    )]
+    pub query_fetch_concurrency: usize,
+
+    #[arg(
+        long,
+        env = "P_QUERY_PLAN_CACHE_SIZE",
+        default_value = "128",
+        help = "Number of query plans reused by repeated queries until the schema of their streams changes, 0 disables the cache"
+    )]
+    pub query_plan_cache_size: usize,
+
+    #[arg(
+        long,
+        env = "P_PARTITION_KEY_BUCKETS",
+        default_value = "16",
+        help = "Number of partitions events are fanned out into as per the hash of their X-P-Partition-Key, must not be changed once set"
+    )]
+    pub partition_key_buckets: usize,
+
+    #[arg(
+        long,
+        env = "P_FLUSH_MAX_ROWS",
+        help = "Number of records buffered in a stream after which it is flushed and converted into parquet, without waiting for the next local sync"
+    )]
+    pub flush_max_rows: Option<usize>,
+
+    #[arg(
+        long,
+        env = "P_FLUSH_MAX_SIZE",
+        value_parser = validation::human_size,
+        help = "Size of records buffered in a stream after which it is flushed and converted into parquet, e.g. \"64 MiB\""
+    )]
+    pub flush_max_size: Option<u64>,
+
+    #[arg(
+        long,
+        env = "P_FLUSH_MAX_AGE",
+        value_parser = humantime::parse_duration,
+        help = "Maximum duration records stay buffered in a stream before it is flushed and converted into parquet, e.g. \"10s\""
+    )]
+    pub flush_max_age: Option<Duration>,
+
+    #[arg(
+        long,
+        env = "P_SHUTDOWN_DRAIN_TIMEOUT",
+        default_value = "30s",
+        value_parser = humantime::parse_duration,
+        help = "Maximum duration to wait on shutdown for events being processed, before staging is flushed"
+    )]
+    pub shutdown_drain_timeout: Duration,
+
+    #[arg(
+        long,
+        env = "P_UPLOAD_MAX_RETRIES",
+        default_value = "5",
+        help = "Number of times a staged file that failed to upload is retried, before it is moved into the dead-letter directory of the stream's staging"
+    )]
+    pub upload_max_retries: u32,
+
+    #[arg(
+        long,
+        env = "P_UPLOAD_RETRY_BACKOFF",
+        default_value = "30s",
+        value_parser = humantime::parse_duration,
+        help = "Duration to wait before retrying a failed upload, doubles with every failed attempt"
+    )]
+    pub upload_retry_backoff: Duration,
+
+    #[arg(
+        long,
+        env = "P_CONDITIONAL_PUTS",
+        default_value = "false",
+        help = "Upload parquet files only if no object exists at their key, retrying under a new key when another writer got there first"
+    )]
+    pub conditional_puts: bool,
+
+    #[arg(
+        long,
+        env = "P_COMPACTION_INTERVAL",
+        value_parser = humantime::parse_duration,
+        help = "Interval at which small parquet files in object store are merged into larger ones, e.g. \"1h\". Compaction is disabled when unset"
+    )]
+    pub compaction_interval: Option<Duration>,
+
+    #[arg(
+        long,
+        env = "P_DISABLE_LOCAL_CACHE",
+        default_value = "false",
+        help = "Build parquet files in memory and put them straight into object store instead of writing them to the staging directory first, queries then skip the hot tier. Files failing to upload are written to the staging directory to be retried, files pending upload are lost on a crash"
+    )]
+    pub disable_local_cache: bool,
+
+    #[arg(
+        long,
+        env = "P_MAX_PENDING_UPLOAD_SIZE",
+        default_value = "1 GiB",
+        value_parser = validation::human_size,
+        help = "Maximum size of the parquet files built in memory per stream that are pending upload with the local cache disabled, further files are written to the staging directory"
+    )]
+    pub max_pending_upload_size: Option<u64>,
+
+    #[arg(
+        long,
+        env = "P_MAX_OPEN_PARQUET_WRITERS",
+        help = "Maximum number of parquet files written at once across all streams, conversions past it wait for a file to be closed. Unbounded when unset"
+    )]
+    pub max_open_parquet_writers: Option<usize>,
+
+    #[arg(
+        long,
+        env = "P_MAX_OPEN_ARROW_WRITERS",
+        help = "Maximum number of arrow files each stream writes events into at once, the least recently written is closed to open another. Unbounded when unset"
+    )]
+    pub max_open_arrow_writers: Option<usize>,
+
+    #[arg(
+        long,
+        env = "P_ARROW_WRITER_IDLE_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        help = "Duration after which arrow files that no events were written into are closed, e.g. \"30s\". Kept open until their minute is over when unset"
+    )]
+    pub arrow_writer_idle_timeout: Option<Duration>,
+
+    #[arg(
+        long,
+        env = "P_EVENTS_COUNTER_INTERVAL",
+        default_value = "1m",
+        value_parser = humantime::parse_duration,
+        help = "Interval at which the count of events ingested into all streams is persisted in object store"
+    )]
+    pub events_counter_interval: Duration,
+
+    #[arg(
+        long,
+        env = "P_COMPACTION_TARGET_SIZE",
+        default_value = "128 MiB",
+        value_parser = validation::human_size,
+        help = "Size upto which small parquet files are merged together by compaction"
+    )]
+    pub compaction_target_size: u64,
+
+    #[arg(
+        long,
+        env = "P_COMPACTION_MIN_FILES",
+        default_value = "4",
+        help = "Minimum number of small parquet files in a partition for them to be compacted"
+    )]
+    pub compaction_min_files: usize,
+
+    #[arg(
+        long,
+        env = "P_GC_INTERVAL",
+        value_parser = humantime::parse_duration,
+        help = "Interval at which parquet files in object store that no manifest refers to are deleted, e.g. \"1d\". Garbage collection is disabled when unset"
+    )]
+    pub gc_interval: Option<Duration>,
+
+    #[arg(
+        long,
+        env = "P_GC_SAFETY_WINDOW",
+        default_value = "24h",
+        value_parser = humantime::parse_duration,
+        help = "Age below which files that no manifest refers to are left alone by garbage collection, as they may still be getting added to one"
+    )]
+    pub gc_safety_window: Duration,
 }
 
 #[derive(Parser, Debug)]
@@ -37,10 +37,10 @@ use crate::{
     },
     parseable::PARSEABLE,
     query::QUERY_SESSION,
-    rbac::{map::SessionKey, Users},
+    rbac::map::SessionKey,
     storage::ObjectStorageError,
     users::filters::FilterQuery,
-    utils::{get_hash, user_auth_for_datasets},
+    utils::{get_hash, session_auth_for_datasets},
 };
 
 pub static CORRELATIONS: Lazy<Correlations> = Lazy::new(Correlations::default);
@@ -79,7 +79,6 @@ impl Correlations {
         session_key: &SessionKey,
     ) -> Result<Vec<CorrelationConfig>, CorrelationError> {
         let mut user_correlations = vec![];
-        let permissions = Users.get_permissions(session_key);
 
         for correlation in self.read().await.values() {
             let tables = &correlation
@@ -87,7 +86,7 @@ impl Correlations {
                 .iter()
                 .map(|t| t.table_name.clone())
                 .collect_vec();
-            if user_auth_for_datasets(&permissions, tables).is_ok() {
+            if session_auth_for_datasets(session_key, tables).is_ok() {
                 user_correlations.push(correlation.clone());
             }
         }
@@ -274,14 +273,13 @@ impl CorrelationConfig {
         }
 
         // check if user has access to table
-        let permissions = Users.get_permissions(session_key);
         let tables = &self
             .table_configs
             .iter()
             .map(|t| t.table_name.clone())
             .collect_vec();
 
-        user_auth_for_datasets(&permissions, tables)?;
+        session_auth_for_datasets(session_key, tables)?;
 
         // to validate table config, we need to check whether the mentioned fields
         // are present in the table or not
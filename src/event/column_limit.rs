@@ -0,0 +1,187 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::HashSet;
+
+use arrow_schema::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Column the fields of events past the column limit of a stream are folded into, as JSON
+pub const OVERFLOW_KEY: &str = "p_overflow";
+
+/// What becomes of events that would add columns to a stream beyond its limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExceedAction {
+    /// The event is rejected
+    #[default]
+    Reject,
+    /// The fields that would be new columns are folded into the overflow column
+    Overflow,
+}
+
+/// Limit on the number of distinct columns of a stream, guarding its schema against events
+/// with random keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColumnLimit {
+    pub max_columns: usize,
+    #[serde(default)]
+    pub on_exceed: ExceedAction,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Event adds column {column:?} to a stream already at its limit of {limit} columns")]
+pub struct ColumnLimitExceeded {
+    pub column: String,
+    pub limit: usize,
+}
+
+impl ColumnLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_columns == 0 {
+            return Err("Column limit should be greater than 0".to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Applies the limit to a flattened event, or an array of them, given the `columns` of the
+    /// stream, which are added to as events are admitted. New columns are admitted in the order
+    /// they are met, until the limit is reached. The overflow column is the one column admitted
+    /// past it, so that folded fields are never lost.
+    pub fn apply(
+        &self,
+        json: Value,
+        columns: &mut HashSet<String>,
+    ) -> Result<Value, ColumnLimitExceeded> {
+        let mut event = match json {
+            Value::Array(events) => {
+                return events
+                    .into_iter()
+                    .map(|event| self.apply(event, columns))
+                    .collect::<Result<_, _>>()
+                    .map(Value::Array)
+            }
+            Value::Object(event) => event,
+            json => return Ok(json),
+        };
+
+        let new_columns: Vec<String> = event
+            .keys()
+            .filter(|key| !columns.contains(*key))
+            .cloned()
+            .collect();
+        let mut overflow = Map::new();
+        for column in new_columns {
+            if columns.len() < self.max_columns {
+                columns.insert(column);
+                continue;
+            }
+            match self.on_exceed {
+                ExceedAction::Reject => {
+                    return Err(ColumnLimitExceeded {
+                        column,
+                        limit: self.max_columns,
+                    })
+                }
+                ExceedAction::Overflow => {
+                    let value = event.remove(&column).expect("key of the event");
+                    overflow.insert(column, value);
+                }
+            }
+        }
+        if !overflow.is_empty() {
+            columns.insert(OVERFLOW_KEY.to_owned());
+            event.insert(
+                OVERFLOW_KEY.to_owned(),
+                Value::String(Value::Object(overflow).to_string()),
+            );
+        }
+
+        Ok(Value::Object(event))
+    }
+}
+
+/// Names of the columns of a stream, as counted against its column limit
+pub fn columns(schema: &Schema) -> HashSet<String> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.name().to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::{DataType, Field};
+    use serde_json::json;
+
+    use super::*;
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("a", DataType::Utf8, true),
+            Field::new("b", DataType::Utf8, true),
+        ])
+    }
+
+    #[test]
+    fn column_limit_rejects_further_schema_growth() {
+        let limit = ColumnLimit {
+            max_columns: 3,
+            on_exceed: ExceedAction::Reject,
+        };
+
+        // existing columns and a single new one fit within the limit
+        let events = json!([{"a": "1", "c": "2"}, {"b": "3", "c": "4"}]);
+        let mut stream_columns = columns(&schema());
+        assert_eq!(
+            limit.apply(events.clone(), &mut stream_columns).unwrap(),
+            events
+        );
+
+        // columns added by earlier events count against the limit of later ones
+        let err = limit
+            .apply(json!({"d": "3"}), &mut stream_columns)
+            .unwrap_err();
+        assert_eq!(err.column, "d");
+        assert_eq!(err.limit, 3);
+    }
+
+    #[test]
+    fn fields_past_column_limit_are_folded_into_overflow() {
+        let limit = ColumnLimit {
+            max_columns: 2,
+            on_exceed: ExceedAction::Overflow,
+        };
+
+        let event = limit
+            .apply(
+                json!({"a": "1", "x": 1, "y": {"z": true}}),
+                &mut columns(&schema()),
+            )
+            .unwrap();
+
+        assert_eq!(event["a"], "1");
+        assert!(event.get("x").is_none());
+        let overflow: Value = serde_json::from_str(event[OVERFLOW_KEY].as_str().unwrap()).unwrap();
+        assert_eq!(overflow, json!({"x": 1, "y": {"z": true}}));
+    }
+}
@@ -0,0 +1,202 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use actix_web::{web, Responder};
+use once_cell::sync::Lazy;
+use relative_path::{RelativePath, RelativePathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    handlers::http::modal::ingest_server::{INGESTOR_EXPECT, INGESTOR_META},
+    option::Mode,
+    parseable::PARSEABLE,
+    storage::{ObjectStorage, ObjectStorageError, PARSEABLE_ROOT_DIRECTORY},
+};
+
+const EVENTS_COUNT_FILE_NAME: &str = "events.json";
+
+/// Events ingested by this node into all streams, since the server was first started
+pub static EVENT_COUNTER: Lazy<EventCounter> = Lazy::new(EventCounter::default);
+
+#[derive(Debug, Default)]
+pub struct EventCounter {
+    total: AtomicU64,
+}
+
+/// Count of events as persisted in object storage
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EventsCount {
+    events: u64,
+}
+
+impl EventCounter {
+    pub fn add(&self, events: u64) {
+        self.total.fetch_add(events, Ordering::Relaxed);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Adds the count persisted before a restart, keeping the events counted since
+    fn restore(&self, persisted: u64) {
+        self.add(persisted);
+    }
+
+    /// Restores the count persisted by this node, if any
+    pub async fn load(&self) -> Result<(), ObjectStorageError> {
+        let storage = PARSEABLE.storage.get_object_store();
+        match storage.get_object(&events_count_path()).await {
+            Ok(bytes) => {
+                let count: EventsCount =
+                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)?;
+                self.restore(count.events);
+                Ok(())
+            }
+            Err(ObjectStorageError::NoSuchKey(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Puts the count in object storage, nodes that don't ingest have nothing to persist
+    pub async fn persist(&self) -> Result<(), ObjectStorageError> {
+        if matches!(PARSEABLE.options.mode, Mode::Query | Mode::Prism) {
+            return Ok(());
+        }
+        let count = EventsCount {
+            events: self.total(),
+        };
+        PARSEABLE
+            .storage
+            .get_object_store()
+            .put_object(
+                &events_count_path(),
+                serde_json::to_vec(&count)
+                    .map_err(anyhow::Error::from)?
+                    .into(),
+            )
+            .await
+    }
+}
+
+/// path will be ".parseable/events.json", or ".parseable/ingestor.{id}.events.json" for an ingestor
+fn events_count_path() -> RelativePathBuf {
+    let file_name = match &PARSEABLE.options.mode {
+        Mode::Ingest => {
+            let id = INGESTOR_META
+                .get()
+                .unwrap_or_else(|| panic!("{}", INGESTOR_EXPECT))
+                .get_node_id();
+            format!("ingestor.{id}.{EVENTS_COUNT_FILE_NAME}")
+        }
+        _ => EVENTS_COUNT_FILE_NAME.to_owned(),
+    };
+    RelativePathBuf::from_iter([PARSEABLE_ROOT_DIRECTORY, &file_name])
+}
+
+/// Total of the counts last persisted by the ingestors of the cluster
+async fn cluster_total(storage: &dyn ObjectStorage) -> Result<u64, ObjectStorageError> {
+    let counts = storage
+        .get_objects(
+            Some(RelativePath::new(PARSEABLE_ROOT_DIRECTORY)),
+            Box::new(|file_name| {
+                file_name.starts_with("ingestor.")
+                    && file_name.ends_with(&format!(".{EVENTS_COUNT_FILE_NAME}"))
+            }),
+        )
+        .await?;
+
+    let mut total = 0;
+    for bytes in counts {
+        let count: EventsCount = serde_json::from_slice(&bytes).map_err(anyhow::Error::from)?;
+        total += count.events;
+    }
+    Ok(total)
+}
+
+/// GET "/events/count" ==> Get the total of events ingested into all streams
+/// {
+///     "events": total
+/// }
+pub async fn get_events_count() -> Result<impl Responder, actix_web::Error> {
+    let events = match PARSEABLE.options.mode {
+        Mode::Query | Mode::Prism => cluster_total(&*PARSEABLE.storage.get_object_store())
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        _ => EVENT_COUNTER.total(),
+    };
+    Ok(web::Json(EventsCount { events }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use temp_dir::TempDir;
+
+    use crate::storage::LocalFS;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_ingests_are_all_counted() {
+        let counter = Arc::new(EventCounter::default());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        counter.add(3);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(counter.total(), 8 * 10_000 * 3);
+
+        // events counted before the persisted count was restored aren't lost
+        counter.restore(100);
+        assert_eq!(counter.total(), 8 * 10_000 * 3 + 100);
+    }
+
+    #[tokio::test]
+    async fn cluster_total_sums_persisted_ingestor_counts() {
+        let dir = TempDir::new().unwrap();
+        let storage = LocalFS::new(dir.path().to_path_buf());
+        for (file_name, events) in [
+            ("ingestor.a.events.json", 5),
+            ("ingestor.b.events.json", 7),
+            // the count of a standalone node isn't part of the cluster total
+            ("events.json", 100),
+        ] {
+            storage
+                .put_object(
+                    &RelativePathBuf::from_iter([PARSEABLE_ROOT_DIRECTORY, file_name]),
+                    serde_json::to_vec(&EventsCount { events }).unwrap().into(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(cluster_total(&storage).await.unwrap(), 12);
+    }
+}
@@ -0,0 +1,282 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use arrow::{
+    compute::filter_record_batch,
+    row::{RowConverter, SortField},
+};
+use arrow_array::{BooleanArray, RecordBatch};
+use arrow_schema::ArrowError;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::LOCK_EXPECT;
+
+/// Most hashes a stream keeps, past which the oldest are forgotten, so that duplicates of the
+/// oldest events in the window may be stored when more events than this are ingested within it
+const MAX_HASHES: usize = 1 << 20;
+
+/// Hashes of the content of the events recently ingested into a stream, so that exact duplicates
+/// of them are dropped. Fields added by parseable, such as `p_timestamp`, aren't part of the
+/// content, and neither are fields an event doesn't have a value for.
+#[derive(Debug)]
+pub struct Deduplicator {
+    /// Time each hash was first seen at
+    seen: HashMap<u64, Instant>,
+    /// Hashes in the order they were seen in, to expire them
+    order: VecDeque<(Instant, u64)>,
+    /// Hashes of the events being stored, dropped as duplicates until they are recorded or released
+    pending: HashSet<u64>,
+    capacity: usize,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Self::with_capacity(MAX_HASHES)
+    }
+}
+
+impl Deduplicator {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            pending: HashSet::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the events of `rb` not seen within `window` before `now`, nor being stored, events
+    /// repeated within `rb` are kept once. The hashes of the events kept are returned along, they
+    /// are pending until [`record`](Self::record)ed once the events are stored, or
+    /// [`release`](Self::release)d when they fail to be.
+    pub fn dedup(
+        &mut self,
+        rb: &RecordBatch,
+        window: Duration,
+        now: Instant,
+    ) -> Result<(RecordBatch, Vec<u64>), ArrowError> {
+        self.expire(window, now);
+
+        let mut kept = HashSet::new();
+        let mask: BooleanArray = content_hashes(rb)?
+            .into_iter()
+            .map(|hash| {
+                Some(
+                    !self.seen.contains_key(&hash)
+                        && !self.pending.contains(&hash)
+                        && kept.insert(hash),
+                )
+            })
+            .collect();
+        self.pending.extend(&kept);
+
+        Ok((filter_record_batch(rb, &mask)?, kept.into_iter().collect()))
+    }
+
+    /// Records the hashes of events stored at `now`, forgetting the oldest past the capacity
+    pub fn record(&mut self, hashes: &[u64], now: Instant) {
+        for &hash in hashes {
+            self.pending.remove(&hash);
+            if self.seen.insert(hash, now).is_none() {
+                self.order.push_back((now, hash));
+            }
+        }
+        while self.order.len() > self.capacity {
+            if let Some((_, hash)) = self.order.pop_front() {
+                self.seen.remove(&hash);
+            }
+        }
+    }
+
+    /// Releases the hashes of events that failed to be stored, so that they are kept when retried
+    pub fn release(&mut self, hashes: &[u64]) {
+        for hash in hashes {
+            self.pending.remove(hash);
+        }
+    }
+
+    fn expire(&mut self, window: Duration, now: Instant) {
+        while let Some(&(seen_at, hash)) = self.order.front() {
+            if now.duration_since(seen_at) < window {
+                break;
+            }
+            self.order.pop_front();
+            self.seen.remove(&hash);
+        }
+    }
+}
+
+/// Hashes of events pending in a [`Deduplicator`], the lock of which isn't held while they are
+/// stored. They are released when dropped, unless [`record`](Self::record)ed
+pub struct Pending<'a> {
+    dedup: &'a Mutex<Deduplicator>,
+    hashes: Vec<u64>,
+    now: Instant,
+}
+
+impl<'a> Pending<'a> {
+    /// Returns the events of `rb` that aren't duplicates, see [`Deduplicator::dedup`]
+    pub fn dedup(
+        dedup: &'a Mutex<Deduplicator>,
+        rb: &RecordBatch,
+        window: Duration,
+        now: Instant,
+    ) -> Result<(RecordBatch, Self), ArrowError> {
+        let (rb, hashes) = dedup.lock().expect(LOCK_EXPECT).dedup(rb, window, now)?;
+
+        Ok((rb, Self { dedup, hashes, now }))
+    }
+
+    /// Records the events as stored
+    pub fn record(mut self) {
+        let hashes = std::mem::take(&mut self.hashes);
+        self.dedup
+            .lock()
+            .expect(LOCK_EXPECT)
+            .record(&hashes, self.now);
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if self.hashes.is_empty() {
+            return;
+        }
+        if let Ok(mut dedup) = self.dedup.lock() {
+            dedup.release(&self.hashes);
+        }
+    }
+}
+
+/// Hash of the content of each event in `rb`, i.e. of the names and values of its fields
+fn content_hashes(rb: &RecordBatch) -> Result<Vec<u64>, ArrowError> {
+    let mut hashers = vec![Xxh3::new(); rb.num_rows()];
+    let schema = rb.schema();
+    for (field, column) in schema.fields().iter().zip(rb.columns()) {
+        if field.name().starts_with("p_") {
+            continue;
+        }
+        // values are hashed in the row format, which is the same for equal values of a type
+        let converter = RowConverter::new(vec![SortField::new(field.data_type().clone())])?;
+        let rows = converter.convert_columns(&[column.clone()])?;
+        for (i, hasher) in hashers.iter_mut().enumerate() {
+            if column.is_valid(i) {
+                hasher.update(field.name().as_bytes());
+                hasher.update(&[0]);
+                hasher.update(rows.row(i).as_ref());
+            }
+        }
+    }
+
+    Ok(hashers.iter().map(|hasher| hasher.digest()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::{
+        event::format::{json, EventFormat},
+        metadata::SchemaVersion,
+    };
+
+    use super::*;
+
+    fn event(json: serde_json::Value) -> RecordBatch {
+        let (rb, _) = json::Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+        rb
+    }
+
+    #[test]
+    fn identical_events_within_window_are_stored_once() {
+        let mut dedup = Deduplicator::default();
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+
+        let mut store = |rb: &RecordBatch, now: Instant| {
+            let (rb, hashes) = dedup.dedup(rb, window, now).unwrap();
+            dedup.record(&hashes, now);
+            rb.num_rows()
+        };
+
+        // identical events are ingested at different times, hence with different p_timestamp
+        let first = event(json!({"msg": "disk full", "host": "web-1"}));
+        let second = event(json!({"host": "web-1", "msg": "disk full"}));
+        let stored = store(&first, now) + store(&second, now + Duration::from_secs(10));
+        assert_eq!(stored, 1);
+
+        // events that differ, or that repeat once the window has passed, are stored
+        let other = event(json!({"msg": "disk full", "host": "web-2"}));
+        assert_eq!(store(&other, now), 1);
+        assert_eq!(store(&first, now + Duration::from_secs(61)), 1);
+    }
+
+    #[test]
+    fn events_are_seen_once_recorded_and_oldest_are_forgotten() {
+        let mut dedup = Deduplicator::with_capacity(2);
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+        let events = [
+            event(json!({"msg": "first"})),
+            event(json!({"msg": "second"})),
+            event(json!({"msg": "third"})),
+        ];
+
+        // events being stored are duplicates of those ingested concurrently
+        let (_, hashes) = dedup.dedup(&events[0], window, now).unwrap();
+        let (concurrent, _) = dedup.dedup(&events[0], window, now).unwrap();
+        assert_eq!(concurrent.num_rows(), 0);
+        // events that failed to be stored aren't recorded, hence are kept when retried
+        dedup.release(&hashes);
+        let (retried, hashes) = dedup.dedup(&events[0], window, now).unwrap();
+        assert_eq!(retried.num_rows(), 1);
+
+        dedup.record(&hashes, now);
+        for event in &events[1..] {
+            let (_, hashes) = dedup.dedup(event, window, now).unwrap();
+            dedup.record(&hashes, now);
+        }
+        assert_eq!(dedup.seen.len(), 2);
+        assert!(dedup.pending.is_empty());
+        // the first event was forgotten to make space for the third
+        assert_eq!(
+            dedup.dedup(&events[0], window, now).unwrap().0.num_rows(),
+            1
+        );
+        assert_eq!(
+            dedup.dedup(&events[2], window, now).unwrap().0.num_rows(),
+            0
+        );
+    }
+}
@@ -0,0 +1,262 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::compute::{can_cast_types, cast, concat};
+use arrow_array::{new_null_array, ArrayRef, RecordBatch};
+use arrow_schema::{Field, Schema};
+use datafusion::{
+    common::DFSchema,
+    error::DataFusionError,
+    prelude::SessionContext,
+    sql::sqlparser::{dialect::GenericDialect, parser::Parser},
+};
+use serde::{Deserialize, Serialize};
+
+/// Column computed from the other fields of the events of a stream as they are ingested, and
+/// stored alongside them, e.g. `{"name": "status_class", "expr": "status / 100"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivedColumn {
+    pub name: String,
+    /// SQL expression over the fields of the event, and the derived columns declared before it
+    pub expr: String,
+}
+
+/// Checks that the derived columns have distinct names that aren't reserved and valid expressions
+pub fn validate(columns: &[DerivedColumn]) -> Result<(), String> {
+    for (i, column) in columns.iter().enumerate() {
+        if column.name.is_empty() || column.name.starts_with("p_") {
+            return Err(format!(
+                "derived column name {:?} should be non-empty and not start with \"p_\"",
+                column.name
+            ));
+        }
+        if columns[..i].iter().any(|other| other.name == column.name) {
+            return Err(format!(
+                "derived column {:?} is declared twice",
+                column.name
+            ));
+        }
+        Parser::new(&GenericDialect {})
+            .try_with_sql(&column.expr)
+            .and_then(|mut parser| parser.parse_expr())
+            .map_err(|err| {
+                format!(
+                    "invalid expression of derived column {:?}: {err}",
+                    column.name
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Evaluates the derived columns against `rb` in `ctx`, that of the stream, and adds them to it,
+/// replacing fields of the events by the same name. Values are cast to the type the column
+/// already has in `stream_schema`. Batches a column can't be evaluated against, say as they miss
+/// a field it refers to, are stored without it, rows it fails for, say dividing by zero, without
+/// a value for it.
+pub fn apply(
+    ctx: &SessionContext,
+    columns: &[DerivedColumn],
+    rb: RecordBatch,
+    stream_schema: &HashMap<String, Arc<Field>>,
+) -> Result<RecordBatch, DataFusionError> {
+    let mut rb = rb;
+    for column in columns {
+        let schema = DFSchema::try_from(rb.schema().as_ref().clone())?;
+        let Ok(expr) = ctx
+            .parse_sql_expr(&column.expr, &schema)
+            .and_then(|expr| ctx.create_physical_expr(expr, &schema))
+        else {
+            continue;
+        };
+        let evaluate = |rb: &RecordBatch| {
+            expr.evaluate(rb)
+                .and_then(|values| values.into_array(rb.num_rows()))
+        };
+        let mut values = match evaluate(&rb) {
+            Ok(values) => values,
+            Err(_) => {
+                let data_type = expr.data_type(rb.schema().as_ref())?;
+                let rows: Vec<ArrayRef> = (0..rb.num_rows())
+                    .map(|row| {
+                        evaluate(&rb.slice(row, 1))
+                            .unwrap_or_else(|_| new_null_array(&data_type, 1))
+                    })
+                    .collect();
+                concat(&rows.iter().map(|row| row.as_ref()).collect::<Vec<_>>())?
+            }
+        };
+        if let Some(field) = stream_schema.get(&column.name) {
+            if values.data_type() != field.data_type()
+                && can_cast_types(values.data_type(), field.data_type())
+            {
+                values = cast(&values, field.data_type())?;
+            }
+        }
+
+        let mut fields = vec![];
+        let mut arrays = vec![];
+        for (field, array) in rb.schema().fields().iter().zip(rb.columns()) {
+            if field.name() != &column.name {
+                fields.push(field.clone());
+                arrays.push(array.clone());
+            }
+        }
+        fields.push(Arc::new(Field::new(
+            &column.name,
+            values.data_type().clone(),
+            true,
+        )));
+        arrays.push(values);
+        rb = RecordBatch::try_new(
+            Arc::new(Schema::new_with_metadata(
+                fields,
+                rb.schema().metadata().clone(),
+            )),
+            arrays,
+        )?;
+    }
+
+    Ok(rb)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use arrow_array::{cast::AsArray, types::Int64Type};
+    use chrono::Utc;
+    use serde_json::json;
+    use temp_dir::TempDir;
+
+    use crate::{
+        cli::Options,
+        event::format::{json, EventFormat},
+        metadata::{LogStreamMetadata, SchemaVersion},
+        parseable::Stream,
+        storage::StreamType,
+    };
+
+    use super::*;
+
+    #[test]
+    fn derived_column_is_stored_with_the_event() {
+        let columns = vec![DerivedColumn {
+            name: "status_class".to_owned(),
+            expr: "status / 100".to_owned(),
+        }];
+        validate(&columns).unwrap();
+
+        let (rb, _) = json::Event::new(json!({"status": 404, "msg": "not found"}))
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V0,
+                &HashMap::new(),
+            )
+            .unwrap();
+        let ctx = SessionContext::new();
+        let rb = apply(&ctx, &columns, rb, &HashMap::new()).unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        // the schema of the event is committed along with it, as on ingestion
+        stream.metadata.write().unwrap().schema = rb
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| (field.name().clone(), field.clone()))
+            .collect();
+        stream
+            .push(
+                "abc",
+                &rb,
+                Utc::now().naive_utc(),
+                &HashMap::new(),
+                StreamType::UserDefined,
+            )
+            .unwrap();
+
+        let stored = stream.tail(1);
+        let status_class = stored[0]
+            .column_by_name("status_class")
+            .unwrap()
+            .as_primitive::<Int64Type>();
+        assert_eq!(status_class.value(0), 4);
+
+        // events without the fields it refers to are stored without it
+        let msg = stored[0].schema().index_of("msg").unwrap();
+        let rb = apply(
+            &ctx,
+            &columns,
+            stored[0].project(&[msg]).unwrap(),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(rb.num_columns(), 1);
+    }
+
+    #[test]
+    fn rows_a_derived_column_fails_for_are_stored_without_it() {
+        let columns = vec![DerivedColumn {
+            name: "per_request".to_owned(),
+            expr: "total / requests".to_owned(),
+        }];
+        let (rb, _) = json::Event::new(json!([
+            {"total": 10, "requests": 5},
+            {"total": 10, "requests": 0},
+        ]))
+        .into_recordbatch(
+            &HashMap::new(),
+            false,
+            None,
+            SchemaVersion::V0,
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        // dividing by zero fails the second row alone
+        let rb = apply(&SessionContext::new(), &columns, rb, &HashMap::new()).unwrap();
+        let per_request = rb
+            .column_by_name("per_request")
+            .unwrap()
+            .as_primitive::<Int64Type>();
+        assert_eq!(per_request.len(), 2);
+        assert_eq!(per_request.value(0), 2);
+        assert!(per_request.is_null(1));
+    }
+
+    #[test]
+    fn invalid_derived_columns_are_rejected() {
+        let column = |name: &str, expr: &str| DerivedColumn {
+            name: name.to_owned(),
+            expr: expr.to_owned(),
+        };
+        assert!(validate(&[column("p_class", "status / 100")]).is_err());
+        assert!(validate(&[column("class", "status /")]).is_err());
+        assert!(validate(&[column("class", "1"), column("class", "2")]).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::anyhow;
+use arrow_json::reader::infer_json_schema_from_iterator;
+use arrow_schema::{Field, Schema};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::metadata::SchemaVersion;
+
+use super::{update_field_type_in_schema, NumberInference};
+
+/// Name of the strategy used by streams that don't configure one
+pub const DEFAULT_STRATEGY: &str = "default";
+
+static STRATEGIES: Lazy<RwLock<HashMap<String, Arc<dyn SchemaInference>>>> =
+    Lazy::new(Default::default);
+
+/// A strategy to infer the arrow schema of fields that are new to a stream from the json records
+/// carrying them. The inferred types are validated against the records on ingestion.
+pub trait SchemaInference: Send + Sync {
+    /// Infers the schema of the fields of `records`, `existing_schema` is that of the stream
+    /// when it already has one. Fields with a null type are dropped by the caller.
+    fn infer_schema(
+        &self,
+        records: &[Value],
+        existing_schema: Option<&HashMap<String, Arc<Field>>>,
+        time_partition: Option<&String>,
+        schema_version: SchemaVersion,
+    ) -> Result<Schema, anyhow::Error>;
+}
+
+/// Infers types as arrow-json does, with timestamps and numbers handled as per the schema
+/// version and number inference of the stream
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultInference {
+    pub number_inference: Option<NumberInference>,
+}
+
+impl SchemaInference for DefaultInference {
+    fn infer_schema(
+        &self,
+        records: &[Value],
+        existing_schema: Option<&HashMap<String, Arc<Field>>>,
+        time_partition: Option<&String>,
+        schema_version: SchemaVersion,
+    ) -> Result<Schema, anyhow::Error> {
+        let inferred = infer_json_schema_from_iterator(records.iter().map(Ok))
+            .map_err(|err| anyhow!("Could not infer schema for this event due to err {:?}", err))?;
+        let schema = update_field_type_in_schema(
+            Arc::new(inferred),
+            existing_schema,
+            time_partition,
+            Some(records),
+            schema_version,
+            self.number_inference,
+        );
+
+        Ok(Schema::new(schema.fields().clone()))
+    }
+}
+
+/// Registers a strategy under `name`, so that streams can be configured to use it
+pub fn register_strategy(name: impl Into<String>, strategy: Arc<dyn SchemaInference>) {
+    STRATEGIES
+        .write()
+        .expect("strategies lock poisoned")
+        .insert(name.into(), strategy);
+}
+
+/// Registers the strategies shipped with parseable, done once on startup before streams are loaded
+///
+/// - `float`: every number is inferred as float64
+/// - `integer`: numbers are inferred as int64, unless one with a fractional part appears
+pub fn register_builtin_strategies() {
+    for (name, number_inference) in [
+        ("float", NumberInference::Float),
+        ("integer", NumberInference::Integer),
+    ] {
+        register_strategy(
+            name,
+            Arc::new(DefaultInference {
+                number_inference: Some(number_inference),
+            }),
+        );
+    }
+}
+
+/// Fails with the name of the strategy a stream is configured with, unless it is registered
+pub fn ensure_registered(name: Option<&str>) -> Result<(), anyhow::Error> {
+    match name {
+        Some(name) if !is_registered(name) => Err(anyhow!(
+            "schema inference strategy {name} is not registered"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that a strategy by the name is available to be configured for a stream
+pub fn is_registered(name: &str) -> bool {
+    name == DEFAULT_STRATEGY
+        || STRATEGIES
+            .read()
+            .expect("strategies lock poisoned")
+            .contains_key(name)
+}
+
+/// Returns the strategy registered under `name`, the default one when unset or not registered
+pub fn strategy(
+    name: Option<&str>,
+    number_inference: Option<NumberInference>,
+) -> Arc<dyn SchemaInference> {
+    name.filter(|name| *name != DEFAULT_STRATEGY)
+        .and_then(|name| {
+            STRATEGIES
+                .read()
+                .expect("strategies lock poisoned")
+                .get(name)
+                .cloned()
+        })
+        .unwrap_or_else(|| Arc::new(DefaultInference { number_inference }))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::DataType;
+    use serde_json::json;
+
+    use crate::event::format::{json::Event, EventFormat};
+
+    use super::*;
+
+    /// Infers every numeric field as float64, whatever the values
+    struct AllFloats;
+
+    impl SchemaInference for AllFloats {
+        fn infer_schema(
+            &self,
+            records: &[Value],
+            existing_schema: Option<&HashMap<String, Arc<Field>>>,
+            time_partition: Option<&String>,
+            schema_version: SchemaVersion,
+        ) -> Result<Schema, anyhow::Error> {
+            let schema = DefaultInference::default().infer_schema(
+                records,
+                existing_schema,
+                time_partition,
+                schema_version,
+            )?;
+            Ok(Schema::new(
+                schema
+                    .fields()
+                    .iter()
+                    .map(|field| match field.data_type().is_numeric() {
+                        true => field.as_ref().clone().with_data_type(DataType::Float64),
+                        false => field.as_ref().clone(),
+                    })
+                    .collect::<Vec<_>>(),
+            ))
+        }
+    }
+
+    #[test]
+    fn custom_strategy_is_applied_to_new_fields() {
+        register_strategy("all-floats", Arc::new(AllFloats));
+        assert!(is_registered("all-floats"));
+
+        let mut event = Event::new(json!({"code": 200, "msg": "ok"}));
+        event.schema_inference = strategy(Some("all-floats"), None);
+        let (rb, _) = event
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V0,
+                &HashMap::new(),
+            )
+            .unwrap();
+        let schema = rb.schema();
+        assert_eq!(
+            schema.field_with_name("code").unwrap().data_type(),
+            &DataType::Float64
+        );
+        assert_eq!(
+            schema.field_with_name("msg").unwrap().data_type(),
+            &DataType::Utf8
+        );
+
+        // the default strategy keeps integers as inferred in schema version 0
+        let (rb, _) = Event::new(json!({"code": 200}))
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V0,
+                &HashMap::new(),
+            )
+            .unwrap();
+        assert_eq!(
+            rb.schema().field_with_name("code").unwrap().data_type(),
+            &DataType::Int64
+        );
+    }
+
+    #[test]
+    fn builtin_strategies_are_registered_on_startup() {
+        let _ = &*crate::parseable::PARSEABLE;
+        assert!(ensure_registered(Some("float")).is_ok());
+        assert!(ensure_registered(Some("integer")).is_ok());
+        assert!(ensure_registered(None).is_ok());
+        assert!(ensure_registered(Some("not-a-strategy")).is_err());
+
+        let mut event = Event::new(json!({"code": 200}));
+        event.schema_inference = strategy(Some("float"), None);
+        let (rb, _) = event
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V0,
+                &HashMap::new(),
+            )
+            .unwrap();
+        assert_eq!(
+            rb.schema().field_with_name("code").unwrap().data_type(),
+            &DataType::Float64
+        );
+    }
+}
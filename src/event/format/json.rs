@@ -21,21 +21,31 @@
 
 use anyhow::anyhow;
 use arrow_array::RecordBatch;
-use arrow_json::reader::{infer_json_schema_from_iterator, ReaderBuilder};
+use arrow_json::reader::ReaderBuilder;
 use arrow_schema::{DataType, Field, Fields, Schema};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
 use datafusion::arrow::util::bit_util::round_upto_multiple_of_64;
 use itertools::Itertools;
-use serde_json::Value;
+use serde_json::{Map, Number, Value};
 use std::{collections::HashMap, sync::Arc};
 use tracing::error;
 
-use super::EventFormat;
-use crate::{metadata::SchemaVersion, storage::StreamType, utils::arrow::get_field};
+use super::{
+    inference::{DefaultInference, SchemaInference},
+    CoercionPolicy, EventFormat,
+};
+use crate::{
+    event::{partition_bucket, PARTITION_BUCKET_KEY, PARTITION_KEY, RAW_EVENT_KEY},
+    metadata::SchemaVersion,
+    parseable::PARSEABLE,
+    storage::StreamType,
+    utils::{arrow::get_field, time::parse_timestamp},
+};
 
 pub struct Event {
     pub json: Value,
     pub p_timestamp: DateTime<Utc>,
+    pub schema_inference: Arc<dyn SchemaInference>,
 }
 
 impl Event {
@@ -43,6 +53,7 @@ impl Event {
         Self {
             json,
             p_timestamp: Utc::now(),
+            schema_inference: Arc::new(DefaultInference::default()),
         }
     }
 }
@@ -65,6 +76,7 @@ impl EventFormat for Event {
         static_schema_flag: bool,
     ) -> Result<(Self::Data, Vec<Arc<Field>>, bool), anyhow::Error> {
         let stream_schema = schema;
+        let schema_inference = self.schema_inference;
 
         // incoming event may be a single json or a json array
         // but Data (type defined above) is a vector of json values
@@ -83,18 +95,12 @@ impl EventFormat for Event {
         let schema = match derive_arrow_schema(stream_schema, fields) {
             Ok(schema) => schema,
             Err(_) => {
-                let mut infer_schema = infer_json_schema_from_iterator(value_arr.iter().map(Ok))
-                    .map_err(|err| {
-                        anyhow!("Could not infer schema for this event due to err {:?}", err)
-                    })?;
-                let new_infer_schema = super::update_field_type_in_schema(
-                    Arc::new(infer_schema),
+                let infer_schema = schema_inference.infer_schema(
+                    &value_arr,
                     Some(stream_schema),
                     time_partition,
-                    Some(&value_arr),
                     schema_version,
-                );
-                infer_schema = Schema::new(new_infer_schema.fields().clone());
+                )?;
                 Schema::try_merge(vec![
                     Schema::new(stream_schema.values().cloned().collect::<Fields>()),
                     infer_schema.clone(),
@@ -140,7 +146,7 @@ impl EventFormat for Event {
 
     /// Converts a JSON event into a Parseable Event
     fn into_event(
-        self,
+        mut self,
         stream_name: String,
         origin_size: u64,
         storage_schema: &HashMap<String, Arc<Field>>,
@@ -151,16 +157,40 @@ impl EventFormat for Event {
         stream_type: StreamType,
         p_custom_fields: &HashMap<String, String>,
     ) -> Result<super::Event, anyhow::Error> {
-        let custom_partition_values = match custom_partitions.as_ref() {
+        let mut custom_partition_values = match custom_partitions.as_ref() {
             Some(custom_partition) => {
                 let custom_partitions = custom_partition.split(',').collect_vec();
                 extract_custom_partition_values(&self.json, &custom_partitions)
             }
             None => HashMap::new(),
         };
+        if let Some(partition_key) = p_custom_fields.get(PARTITION_KEY) {
+            let buckets = PARSEABLE
+                .get_stream(&stream_name)
+                .map(|stream| stream.get_settings().partition_key_buckets())
+                .unwrap_or(PARSEABLE.options.partition_key_buckets);
+            custom_partition_values.insert(
+                PARTITION_BUCKET_KEY.to_owned(),
+                partition_bucket(partition_key, buckets),
+            );
+        }
 
         let parsed_timestamp = match time_partition {
-            Some(time_partition) => extract_and_parse_time(&self.json, time_partition)?,
+            Some(time_partition) => {
+                let parsed_timestamp = extract_and_parse_time(&self.json, time_partition)?;
+                // stored with its offset, as a timestamp without one would be read as UTC
+                if let Some(time) = self.json.get_mut(time_partition).filter(|time| {
+                    time.as_str()
+                        .is_some_and(|time| DateTime::parse_from_rfc3339(time).is_err())
+                }) {
+                    *time = Value::String(
+                        parsed_timestamp
+                            .and_utc()
+                            .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                    );
+                }
+                parsed_timestamp
+            }
             _ => self.p_timestamp.naive_utc(),
         };
 
@@ -186,6 +216,38 @@ impl EventFormat for Event {
     }
 }
 
+/// Infers the schema of a new stream from up to `sample_size` of the records first ingested
+/// into it, as opposed to inferring it from whichever record happens to be processed first.
+pub fn infer_sample_schema(
+    values: &[Value],
+    sample_size: usize,
+    time_partition: Option<&String>,
+    schema_version: SchemaVersion,
+    schema_inference: &dyn SchemaInference,
+) -> Result<Schema, anyhow::Error> {
+    let sample = values
+        .iter()
+        .flat_map(|value| match value {
+            Value::Array(arr) => arr.iter().collect_vec(),
+            value => vec![value],
+        })
+        .take(sample_size)
+        .cloned()
+        .collect_vec();
+
+    let schema = schema_inference.infer_schema(&sample, None, time_partition, schema_version)?;
+
+    Ok(Schema::new(
+        schema
+            .fields
+            .iter()
+            .filter(|field| !field.data_type().is_null())
+            .cloned()
+            .sorted_by(|a, b| a.name().cmp(b.name()))
+            .collect::<Fields>(),
+    ))
+}
+
 /// Extracts custom partition values from provided JSON object
 /// e.g. `json: {"status": 400, "msg": "Hello, World!"}, custom_partition_list: ["status"]` returns `{"status" => 400}`
 pub fn extract_custom_partition_values(
@@ -208,8 +270,328 @@ pub fn extract_custom_partition_values(
     custom_partition_values
 }
 
+/// Renames fields of the incoming json object(s) to match the casing of a known column,
+/// columns of the stream take precedence, followed by the first casing seen in the event.
+/// e.g. with a `status` column, `[{"Status": 200}, {"STATUS": 404}]` becomes `[{"status": 200}, {"status": 404}]`
+pub fn fold_field_names(json: Value, schema: &HashMap<String, Arc<Field>>) -> Value {
+    let mut known: HashMap<String, String> = HashMap::new();
+    for name in schema.keys().sorted() {
+        known
+            .entry(name.to_lowercase())
+            .or_insert_with(|| name.clone());
+    }
+
+    match json {
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .map(|value| fold_object_keys(value, &mut known))
+                .collect(),
+        ),
+        value => fold_object_keys(value, &mut known),
+    }
+}
+
+fn fold_object_keys(value: Value, known: &mut HashMap<String, String>) -> Value {
+    let Value::Object(map) = value else {
+        return value;
+    };
+
+    let mut folded = Map::with_capacity(map.len());
+    let mut renamed = vec![];
+    for (key, value) in map {
+        let name = known
+            .entry(key.to_lowercase())
+            .or_insert_with(|| key.clone());
+        if *name == key {
+            folded.insert(key, value);
+        } else {
+            renamed.push((name.clone(), key, value));
+        }
+    }
+    for (name, key, value) in renamed {
+        // don't overwrite a field of the event that already has the folded name
+        let key = if folded.contains_key(&name) {
+            key
+        } else {
+            name
+        };
+        folded.insert(key, value);
+    }
+
+    Value::Object(folded)
+}
+
+/// Converts a field name into snake_case, e.g. `requestId` and `RequestID` both become `request_id`
+pub fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev = i.checked_sub(1).map(|i| chars[i]);
+            let next = chars.get(i + 1);
+            // a word starts at an uppercase letter following a lowercase one or a digit,
+            // or at the last letter of an acronym, as in `HTTPStatus`
+            let starts_word = prev.is_some_and(|prev| {
+                prev.is_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+            });
+            if starts_word && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+
+    snake
+}
+
+/// Renames fields of the incoming json object(s) into snake_case, nested objects included,
+/// e.g. `{"requestId": 1, "httpRequest": {"statusCode": 200}}` becomes
+/// `{"request_id": 1, "http_request": {"status_code": 200}}`. A field that already has the
+/// snake_case name is kept over one renamed into it.
+pub fn snake_case_field_names(json: Value) -> Value {
+    match json {
+        Value::Array(arr) => Value::Array(arr.into_iter().map(snake_case_field_names).collect()),
+        Value::Object(map) => {
+            let mut renamed = Map::with_capacity(map.len());
+            let mut collisions = vec![];
+            for (key, value) in map {
+                let name = to_snake_case(&key);
+                let value = snake_case_field_names(value);
+                if name == key {
+                    renamed.insert(key, value);
+                } else {
+                    collisions.push((name, key, value));
+                }
+            }
+            for (name, key, value) in collisions {
+                let key = if renamed.contains_key(&name) {
+                    key
+                } else {
+                    name
+                };
+                renamed.insert(key, value);
+            }
+
+            Value::Object(renamed)
+        }
+        value => value,
+    }
+}
+
+/// Converts values of the incoming json object(s) into the type of the existing column, as allowed by the policy
+/// e.g. with an `Int64` column `code` and [`CoercionPolicy::Number`], `{"code": "200"}` becomes `{"code": 200}`
+pub fn coerce_to_schema(
+    json: Value,
+    schema: &HashMap<String, Arc<Field>>,
+    policy: CoercionPolicy,
+) -> Value {
+    if policy == CoercionPolicy::Strict {
+        return json;
+    }
+
+    match json {
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .map(|value| coerce_to_schema(value, schema, policy))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = match schema.get(&key) {
+                        Some(field) => coerce_value(value, field.data_type(), policy),
+                        None => value,
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Converts values of the declared columns into the type they were declared with, regardless of the policy
+/// e.g. with `status` declared `int`, `{"status": "404"}` becomes `{"status": 404}`
+pub fn coerce_to_declared(
+    json: Value,
+    schema: &HashMap<String, Arc<Field>>,
+    declared_columns: &[String],
+) -> Value {
+    match json {
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .map(|value| coerce_to_declared(value, schema, declared_columns))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = match schema.get(&key) {
+                        Some(field) if declared_columns.contains(&key) => {
+                            coerce_declared_value(value, field.data_type())
+                        }
+                        _ => value,
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn coerce_declared_value(value: Value, data_type: &DataType) -> Value {
+    match (data_type, value) {
+        (DataType::Boolean, Value::String(s)) => match s.trim() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::String(s),
+        },
+        (data_type, value) => coerce_value(
+            coerce_value(value, data_type, CoercionPolicy::Number),
+            data_type,
+            CoercionPolicy::String,
+        ),
+    }
+}
+
+fn coerce_value(value: Value, data_type: &DataType, policy: CoercionPolicy) -> Value {
+    match (policy, data_type, value) {
+        (CoercionPolicy::String, DataType::Utf8, value @ (Value::Number(_) | Value::Bool(_))) => {
+            Value::String(value.to_string())
+        }
+        (
+            CoercionPolicy::Number,
+            DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64,
+            Value::String(s),
+        ) => match s.trim().parse::<i64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => Value::String(s),
+        },
+        (
+            CoercionPolicy::Number,
+            DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64,
+            Value::String(s),
+        ) => match s.trim().parse::<u64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => Value::String(s),
+        },
+        (
+            CoercionPolicy::Number,
+            DataType::Float16 | DataType::Float32 | DataType::Float64,
+            Value::String(s),
+        ) => match s.trim().parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(n) => Value::Number(n),
+            None => Value::String(s),
+        },
+        (_, _, value) => value,
+    }
+}
+
+/// Normalizes the values of `columns` that represent a boolean, i.e. `true`/`false`, `1`/`0` and
+/// strings `"true"`/`"false"`, `"yes"`/`"no"`, `"1"`/`"0"` in any case, into json booleans so that
+/// the columns are inferred and stored as booleans. Other values are left as is
+/// e.g. with `columns: ["ok"]`, `[{"ok": "True"}, {"ok": 0}]` becomes `[{"ok": true}, {"ok": false}]`
+pub fn normalize_booleans(json: Value, columns: &[String]) -> Value {
+    match json {
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .map(|value| normalize_booleans(value, columns))
+                .collect(),
+        ),
+        Value::Object(mut map) => {
+            for column in columns {
+                if let Some(value) = map.get_mut(column) {
+                    if let Some(boolean) = as_boolean(value) {
+                        *value = Value::Bool(boolean);
+                    }
+                }
+            }
+            Value::Object(map)
+        }
+        value => value,
+    }
+}
+
+fn as_boolean(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(boolean) => Some(*boolean),
+        Value::Number(n) => match n.as_u64() {
+            Some(1) => Some(true),
+            Some(0) => Some(false),
+            _ => None,
+        },
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Removes the fields excluded from ingestion for the stream from the incoming json object(s)
+/// e.g. with `exclude_columns: ["debug_dump"]`, `{"msg": "hi", "debug_dump": "..."}` becomes `{"msg": "hi"}`
+pub fn drop_excluded_fields(json: Value, exclude_columns: &[String]) -> Value {
+    match json {
+        Value::Array(arr) => Value::Array(
+            arr.into_iter()
+                .map(|value| drop_excluded_fields(value, exclude_columns))
+                .collect(),
+        ),
+        Value::Object(mut map) => {
+            map.retain(|key, _| !exclude_columns.contains(key));
+            Value::Object(map)
+        }
+        value => value,
+    }
+}
+
+/// Unwraps the incoming json object(s) from the envelope they are shipped in, keeping only the
+/// subtree at `path`, e.g. with `path: "record"`, `{"record": {"msg": "hi"}, "meta": {..}}`
+/// becomes `{"msg": "hi"}`. Nested fields are addressed with dots, as in `data.record`
+pub fn extract_event_body(json: Value, path: &str) -> Result<Value, anyhow::Error> {
+    if let Value::Array(arr) = json {
+        return arr
+            .into_iter()
+            .map(|value| extract_event_body(value, path))
+            .collect::<Result<_, _>>()
+            .map(Value::Array);
+    }
+
+    let mut body = json;
+    for key in path.split('.') {
+        body = match body {
+            Value::Object(mut map) => map.remove(key),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("Missing field for event body in json: {path}"))?;
+    }
+
+    Ok(body)
+}
+
+/// Wraps each of the incoming json object(s) into a single field holding it as JSON, so that
+/// events of schema-on-read streams are stored as is, e.g. `{"msg": "hi"}` becomes
+/// `{"p_raw": "{\"msg\":\"hi\"}"}`
+pub fn to_raw_events(json: Value) -> Value {
+    match json {
+        Value::Array(arr) => Value::Array(arr.into_iter().map(to_raw_events).collect()),
+        value => {
+            let mut raw = Map::with_capacity(1);
+            raw.insert(RAW_EVENT_KEY.to_owned(), Value::String(value.to_string()));
+            Value::Object(raw)
+        }
+    }
+}
+
 /// Returns the parsed timestamp of deignated time partition from json object
-/// e.g. `json: {"timestamp": "2025-05-15T15:30:00Z"}` returns `2025-05-15T15:30:00`
+/// e.g. `json: {"timestamp": "2025-05-15T15:30:00Z"}` returns `2025-05-15T15:30:00`.
+/// Timestamps without an offset are read in the configured timezone, see [`parse_timestamp`].
 fn extract_and_parse_time(
     json: &Value,
     time_partition: &str,
@@ -217,11 +599,26 @@ fn extract_and_parse_time(
     let current_time = json
         .get(time_partition)
         .ok_or_else(|| anyhow!("Missing field for time partition in json: {time_partition}"))?;
-    let parsed_time: DateTime<Utc> = serde_json::from_value(current_time.clone())?;
+    let parsed_time: DateTime<Utc> = match serde_json::from_value(current_time.clone()) {
+        Ok(parsed_time) => parsed_time,
+        Err(err) => current_time
+            .as_str()
+            .and_then(|time| parse_timestamp(time).ok())
+            .ok_or(err)?,
+    };
 
     Ok(parsed_time.naive_utc())
 }
 
+/// Returns the time of the event in the first of `timestamp_fields` that it holds a timestamp in,
+/// e.g. with fields `["timestamp", "@timestamp"]`, `{"@timestamp": "2025-05-15T15:30:00Z"}`
+/// returns `2025-05-15T15:30:00`
+pub fn timestamp_from_fields(json: &Value, timestamp_fields: &[String]) -> Option<NaiveDateTime> {
+    timestamp_fields
+        .iter()
+        .find_map(|field| extract_and_parse_time(json, field).ok())
+}
+
 // Returns arrow schema with the fields that are present in the request body
 // This schema is an input to convert the request body to arrow record batch
 fn derive_arrow_schema(
@@ -290,6 +687,9 @@ fn valid_type(
         DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => value.is_u64(),
         DataType::Float16 | DataType::Float32 => value.is_f64(),
         DataType::Float64 => validate_float(value, schema_version, static_schema_flag),
+        DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => {
+            value.is_number() || value.as_str().is_some_and(is_decimal)
+        }
         DataType::Utf8 => value.is_string(),
         DataType::List(field) => validate_list(field, value, schema_version, static_schema_flag),
         DataType::Struct(fields) => {
@@ -313,6 +713,17 @@ fn valid_type(
     }
 }
 
+/// Whether `s` is a decimal literal such as `-12345678901234567.89`. Decimals are built from the
+/// digits of such strings, json numbers are read as floats and lose precision past 2^53
+fn is_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    !(integer.is_empty() && fraction.is_empty())
+        && integer.bytes().all(|b| b.is_ascii_digit())
+        && fraction.bytes().all(|b| b.is_ascii_digit())
+}
+
 fn validate_int(value: &Value, static_schema_flag: bool) -> bool {
     // allow casting string to int for static schema
     if static_schema_flag {
@@ -388,10 +799,45 @@ fn validate_struct(
 mod tests {
     use std::str::FromStr;
 
+    use arrow_array::cast::AsArray;
     use serde_json::json;
 
+    use crate::event::{format::NumberInference, DEFAULT_TIMESTAMP_KEY};
+
     use super::*;
 
+    #[test]
+    fn sample_schema_includes_fields_missing_from_first_event() {
+        let values = vec![
+            json!({"msg": "started"}),
+            json!({"msg": "request", "status": 200}),
+        ];
+
+        let schema = infer_sample_schema(
+            &values,
+            2,
+            None,
+            SchemaVersion::V1,
+            &DefaultInference::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            schema.field_with_name("msg").unwrap().data_type(),
+            &DataType::Utf8
+        );
+        assert!(schema.field_with_name("status").is_ok());
+
+        let schema = infer_sample_schema(
+            &values,
+            1,
+            None,
+            SchemaVersion::V1,
+            &DefaultInference::default(),
+        )
+        .unwrap();
+        assert!(schema.field_with_name("status").is_err());
+    }
+
     #[test]
     fn parse_time_parition_from_value() {
         let json = json!({"timestamp": "2025-05-15T15:30:00Z"});
@@ -401,6 +847,31 @@ mod tests {
         assert_eq!(parsed.unwrap(), expected);
     }
 
+    #[test]
+    fn event_is_partitioned_by_first_timestamp_field_present() {
+        let timestamp_fields = ["timestamp", "@timestamp", "ts"].map(str::to_owned);
+        let json = json!({"@timestamp": "2024-05-01T10:20:00Z", "msg": "hello"});
+        let parsed = timestamp_from_fields(&json, &timestamp_fields).unwrap();
+        assert_eq!(
+            parsed,
+            NaiveDateTime::from_str("2024-05-01T10:20:00").unwrap()
+        );
+
+        let options = Arc::new(crate::cli::Options::default());
+        let stream = crate::parseable::Stream::new(
+            options,
+            "test_stream",
+            crate::metadata::LogStreamMetadata::default(),
+            None,
+        );
+        let filename = stream.filename_by_partition("abc", parsed, &HashMap::new());
+        assert!(filename.starts_with("abc.date=2024-05-01.hour=10.minute=20."));
+
+        // with none of the fields, the event falls back to the time it is ingested
+        let json = json!({"time": "2024-05-01T10:20:00Z"});
+        assert!(timestamp_from_fields(&json, &timestamp_fields).is_none());
+    }
+
     #[test]
     fn time_parition_not_in_json() {
         let json = json!({"hello": "world!"});
@@ -416,4 +887,368 @@ mod tests {
 
         assert!(parsed.is_err());
     }
+
+    #[test]
+    fn differently_cased_fields_fold_into_same_column() {
+        let json = json!([{"Status": 200}, {"status": 404}]);
+        let json = fold_field_names(json, &HashMap::new());
+
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+
+        assert_eq!(rb.num_rows(), 2);
+        assert!(rb.column_by_name("status").is_none());
+        assert_eq!(rb.column_by_name("Status").unwrap().null_count(), 0);
+    }
+
+    #[test]
+    fn existing_column_casing_takes_precedence() {
+        let schema = HashMap::from([(
+            "status".to_owned(),
+            Arc::new(Field::new("status", DataType::Int64, true)),
+        )]);
+        let json = json!({"STATUS": 200, "Msg": "ok", "msg": "dup"});
+
+        assert_eq!(
+            fold_field_names(json, &schema),
+            json!({"status": 200, "Msg": "ok", "msg": "dup"})
+        );
+    }
+
+    #[test]
+    fn excluded_field_is_not_ingested() {
+        let json = json!({"msg": "hello", "debug_dump": {"body": "large request body"}});
+        let json = drop_excluded_fields(json, &["debug_dump".to_owned()]);
+
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+
+        assert!(rb.column_by_name("msg").is_some());
+        assert!(rb
+            .schema()
+            .fields()
+            .iter()
+            .all(|field| !field.name().starts_with("debug_dump")));
+    }
+
+    #[test]
+    fn only_event_body_is_ingested() {
+        let json = json!({
+            "record": {"msg": "hi", "level": "info"},
+            "meta": {"shipper": "fluentd", "version": 2}
+        });
+        let json = extract_event_body(json, "record").unwrap();
+
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+
+        assert_eq!(rb.num_rows(), 1);
+        let schema = rb.schema();
+        let mut fields = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .filter(|name| !name.starts_with("p_"))
+            .collect_vec();
+        fields.sort();
+        assert_eq!(fields, ["level", "msg"]);
+
+        let json = json!([{"data": {"record": {"msg": "hi"}}}, {"data": {"meta": {}}}]);
+        assert!(extract_event_body(json, "data.record").is_err());
+    }
+
+    #[test]
+    fn camel_and_snake_case_fields_share_a_column() {
+        for (name, snake) in [
+            ("requestId", "request_id"),
+            ("request_id", "request_id"),
+            ("RequestID", "request_id"),
+            ("HTTPStatus", "http_status"),
+            ("status2xx", "status2xx"),
+            ("level", "level"),
+        ] {
+            assert_eq!(to_snake_case(name), snake);
+        }
+
+        let json = json!([
+            {"requestId": "a", "httpRequest": {"statusCode": 200}},
+            {"request_id": "b", "http_request": {"status_code": 404}}
+        ]);
+        let json = snake_case_field_names(json);
+        assert_eq!(
+            json,
+            json!([
+                {"request_id": "a", "http_request": {"status_code": 200}},
+                {"request_id": "b", "http_request": {"status_code": 404}}
+            ])
+        );
+
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+        assert_eq!(rb.num_rows(), 2);
+        assert_eq!(rb.column_by_name("request_id").unwrap().null_count(), 0);
+        assert!(rb.column_by_name("requestId").is_none());
+    }
+
+    #[tokio::test]
+    async fn fields_of_raw_events_are_extracted_when_queried() {
+        let json = to_raw_events(json!([
+            {"user": {"id": 7}, "msg": "login"},
+            {"user": {"id": "u-8", "role": "admin"}},
+            {"level": "warn", "tags": ["a", "b"]}
+        ]));
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+        // heterogeneous events share the one column
+        assert_eq!(
+            rb.schema()
+                .fields()
+                .iter()
+                .filter(|field| field.name() != DEFAULT_TIMESTAMP_KEY)
+                .map(|field| field.name().as_str())
+                .collect_vec(),
+            [RAW_EVENT_KEY]
+        );
+
+        let ctx = datafusion::prelude::SessionContext::new();
+        crate::query::functions::udfs()
+            .into_iter()
+            .for_each(|udf| ctx.register_udf(udf));
+        let table = datafusion::datasource::MemTable::try_new(rb.schema(), vec![vec![rb]]).unwrap();
+        ctx.register_table("raw", Arc::new(table)).unwrap();
+        let records = ctx
+            .sql("SELECT json_extract(p_raw, '$.user.id') AS id FROM raw")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let ids = records[0].column(0).as_string::<i32>();
+        assert_eq!(ids.iter().collect_vec(), [Some("7"), Some("u-8"), None]);
+    }
+
+    fn coercion_schema() -> HashMap<String, Arc<Field>> {
+        [
+            Field::new("code", DataType::Int64, true),
+            Field::new("msg", DataType::Utf8, true),
+        ]
+        .into_iter()
+        .map(|field| (field.name().to_owned(), Arc::new(field)))
+        .collect()
+    }
+
+    fn infer_number_type(
+        events: Vec<Value>,
+        number_inference: NumberInference,
+        schema_version: SchemaVersion,
+    ) -> DataType {
+        let mut schema = HashMap::new();
+        for json in events {
+            let (rb, _) = Event {
+                json,
+                p_timestamp: Utc::now(),
+                schema_inference: Arc::new(DefaultInference {
+                    number_inference: Some(number_inference),
+                }),
+            }
+            .into_recordbatch(&schema, false, None, schema_version, &HashMap::new())
+            .unwrap();
+            // commit the schema of the event, as would be done on ingestion
+            for field in rb.schema().fields() {
+                schema.insert(field.name().clone(), field.clone());
+            }
+        }
+
+        schema["n"].data_type().clone()
+    }
+
+    #[test]
+    fn float_inference_unifies_numbers_across_events() {
+        let events = vec![json!({"n": 1}), json!({"n": 1.5})];
+        for schema_version in [SchemaVersion::V0, SchemaVersion::V1] {
+            assert_eq!(
+                infer_number_type(events.clone(), NumberInference::Float, schema_version),
+                DataType::Float64
+            );
+        }
+    }
+
+    #[test]
+    fn integer_inference_widens_only_on_fractional_numbers() {
+        let events = vec![json!({"n": 1}), json!({"n": 2.0})];
+        assert_eq!(
+            infer_number_type(events, NumberInference::Integer, SchemaVersion::V1),
+            DataType::Int64
+        );
+
+        let events = vec![json!([{"n": 1}, {"n": 1.5}])];
+        assert_eq!(
+            infer_number_type(events, NumberInference::Integer, SchemaVersion::V1),
+            DataType::Float64
+        );
+
+        // once widened in the stream, integers of later events don't narrow the field again
+        let events = vec![json!({"n": 1.5}), json!({"n": 2, "msg": "new field"})];
+        assert_eq!(
+            infer_number_type(events, NumberInference::Integer, SchemaVersion::V1),
+            DataType::Float64
+        );
+    }
+
+    fn ingest_with_policy(
+        json: Value,
+        policy: CoercionPolicy,
+    ) -> Result<RecordBatch, anyhow::Error> {
+        let schema = coercion_schema();
+        let json = coerce_to_schema(json, &schema, policy);
+        Event::new(json)
+            .into_recordbatch(&schema, false, None, SchemaVersion::V1, &HashMap::new())
+            .map(|(rb, _)| rb)
+    }
+
+    #[test]
+    fn strict_policy_rejects_mismatched_types() {
+        let json = json!([{"code": 200, "msg": "ok"}, {"code": "404", "msg": "not found"}]);
+        assert!(ingest_with_policy(json, CoercionPolicy::Strict).is_err());
+    }
+
+    #[test]
+    fn number_policy_coerces_numeric_strings() {
+        let json = json!([{"code": 200, "msg": "ok"}, {"code": "404", "msg": "not found"}]);
+        let rb = ingest_with_policy(json, CoercionPolicy::Number).unwrap();
+        assert_eq!(rb.num_rows(), 2);
+        assert_eq!(
+            rb.column_by_name("code").unwrap().data_type(),
+            &DataType::Int64
+        );
+
+        // strings that aren't numbers are still rejected
+        let json = json!({"code": "unknown", "msg": "ok"});
+        assert!(ingest_with_policy(json, CoercionPolicy::Number).is_err());
+    }
+
+    #[test]
+    fn string_policy_coerces_numbers() {
+        let json = json!([{"code": 200, "msg": "ok"}, {"code": 500, "msg": 500}]);
+        let rb = ingest_with_policy(json.clone(), CoercionPolicy::String).unwrap();
+        assert_eq!(rb.num_rows(), 2);
+        assert_eq!(
+            rb.column_by_name("msg").unwrap().data_type(),
+            &DataType::Utf8
+        );
+
+        // numeric columns aren't affected
+        let json = json!({"code": "200", "msg": "ok"});
+        assert!(ingest_with_policy(json, CoercionPolicy::String).is_err());
+    }
+
+    #[test]
+    fn partial_schema_declares_some_columns_and_infers_the_rest() {
+        let partial_schema: crate::static_schema::StaticSchema = serde_json::from_value(json!({
+            "fields": [{"name": "status", "data_type": "int"}]
+        }))
+        .unwrap();
+        let schema: HashMap<String, Arc<Field>> =
+            crate::static_schema::parse_partial_schema(partial_schema)
+                .unwrap()
+                .fields()
+                .iter()
+                .map(|field| (field.name().to_owned(), field.clone()))
+                .collect();
+        let declared_columns = vec!["status".to_owned()];
+
+        let json = json!([
+            {"status": 200, "msg": "ok", "latency": 1.5},
+            {"status": "404", "msg": "not found", "latency": 0.5}
+        ]);
+        let json = coerce_to_declared(json, &schema, &declared_columns);
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(&schema, false, None, SchemaVersion::V1, &HashMap::new())
+            .unwrap();
+
+        assert_eq!(rb.num_rows(), 2);
+        let status = rb.column_by_name("status").unwrap();
+        assert_eq!(status.data_type(), &DataType::Int64);
+        assert_eq!(
+            status
+                .as_primitive::<arrow_array::types::Int64Type>()
+                .value(1),
+            404
+        );
+        assert_eq!(
+            rb.column_by_name("msg").unwrap().data_type(),
+            &DataType::Utf8
+        );
+        assert_eq!(
+            rb.column_by_name("latency").unwrap().data_type(),
+            &DataType::Float64
+        );
+    }
+
+    #[test]
+    fn mixed_boolean_representations_share_a_boolean_column() {
+        let json = json!([
+            {"ok": true, "n": 1},
+            {"ok": "true", "n": 2},
+            {"ok": 1, "n": 3},
+            {"ok": "No", "n": 4},
+            {"ok": 0, "n": 5}
+        ]);
+        let json = normalize_booleans(json, &["ok".to_owned()]);
+
+        let (rb, _) = Event::new(json)
+            .into_recordbatch(
+                &HashMap::new(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+
+        let ok = rb.column_by_name("ok").unwrap().as_boolean();
+        assert_eq!(
+            ok.iter().collect_vec(),
+            [Some(true), Some(true), Some(true), Some(false), Some(false)]
+        );
+        // columns not listed are left as is
+        let json = normalize_booleans(json!({"ok": 1, "n": 1}), &["ok".to_owned()]);
+        assert_eq!(json, json!({"ok": true, "n": 1}));
+    }
 }
@@ -25,7 +25,7 @@ use std::{
 
 use anyhow::{anyhow, Error as AnyError};
 use arrow_array::RecordBatch;
-use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use arrow_schema::{DataType, Field, Schema};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -33,13 +33,19 @@ use serde_json::Value;
 use crate::{
     metadata::SchemaVersion,
     storage::StreamType,
-    utils::arrow::{add_parseable_fields, get_field},
+    utils::{
+        arrow::{add_parseable_fields, get_field, sort_schema_fields},
+        time::timestamp_type,
+    },
 };
 
 use super::{Event, DEFAULT_TIMESTAMP_KEY};
 
+pub mod inference;
 pub mod json;
 pub mod known_schema;
+pub mod msgpack;
+pub mod protobuf;
 
 static TIME_FIELD_NAME_PARTS: [&str; 11] = [
     "time",
@@ -56,6 +62,59 @@ static TIME_FIELD_NAME_PARTS: [&str; 11] = [
 ];
 type EventSchema = Vec<Arc<Field>>;
 
+/// How values that don't match the type of an existing column are handled on ingestion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CoercionPolicy {
+    /// Values must match the type of the column, events with mismatching values are rejected
+    #[default]
+    Strict,
+    /// Numbers and booleans are converted into strings for string columns
+    String,
+    /// Numeric strings are converted into numbers for numeric columns
+    Number,
+}
+
+/// How events whose time partition is further from the current time than the clock skew
+/// tolerance are handled on ingestion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSkewPolicy {
+    /// Events are rejected
+    #[default]
+    Reject,
+    /// The time partition of events is set to the current time
+    Clamp,
+}
+
+/// How fields with names or string values longer than the configured maximum are handled on ingestion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FieldLengthPolicy {
+    /// Events are rejected
+    #[default]
+    Reject,
+    /// Names and values are truncated to the maximum length
+    Truncate,
+}
+
+/// How fields of an event that flatten into the same column name are handled on ingestion
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateColumnPolicy {
+    /// Events are rejected
+    Reject,
+    /// Later fields get the first free name with a `_<n>` suffix, e.g. `a_b_1`
+    #[default]
+    Suffix,
+}
+
+/// How the type of new number fields is inferred, when not set the type is as per the schema version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberInference {
+    /// All numbers are inferred as float64
+    Float,
+    /// Numbers are inferred as int64, unless one with a fractional part appears
+    Integer,
+}
+
 /// Source of the logs, used to perform special processing for certain sources
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum LogSource {
@@ -172,13 +231,19 @@ pub trait EventFormat: Sized {
             ));
         };
 
-        // prepare the record batch and new fields to be added
-        let mut new_schema = Arc::new(Schema::new(schema));
+        // prepare the record batch and new fields to be added, in a stable order of columns
+        let mut new_schema = Arc::new(sort_schema_fields(&Schema::new(schema)));
         if !Self::is_schema_matching(new_schema.clone(), storage_schema, static_schema_flag) {
             return Err(anyhow!("Schema mismatch"));
         }
-        new_schema =
-            update_field_type_in_schema(new_schema, None, time_partition, None, schema_version);
+        new_schema = update_field_type_in_schema(
+            new_schema,
+            None,
+            time_partition,
+            None,
+            schema_version,
+            None,
+        );
 
         let rb = Self::decode(data, new_schema.clone())?;
         let rb = add_parseable_fields(rb, p_timestamp, p_custom_fields)?;
@@ -245,26 +310,20 @@ pub fn override_existing_timestamp_fields(
     existing_schema: &HashMap<String, Arc<Field>>,
     inferred_schema: Arc<Schema>,
 ) -> Arc<Schema> {
-    let timestamp_field_names: HashSet<String> = existing_schema
+    // timestamp fields keep the precision they were created with
+    let timestamp_fields: HashMap<&String, &DataType> = existing_schema
         .values()
-        .filter_map(|field| {
-            if let DataType::Timestamp(TimeUnit::Millisecond, None) = field.data_type() {
-                Some(field.name().to_owned())
-            } else {
-                None
-            }
+        .filter_map(|field| match field.data_type() {
+            data_type @ DataType::Timestamp(_, None) => Some((field.name(), data_type)),
+            _ => None,
         })
         .collect();
     let updated_fields: Vec<Arc<Field>> = inferred_schema
         .fields()
         .iter()
         .map(|field| {
-            if timestamp_field_names.contains(field.name()) {
-                Arc::new(Field::new(
-                    field.name(),
-                    DataType::Timestamp(TimeUnit::Millisecond, None),
-                    field.is_nullable(),
-                ))
+            if let Some(&data_type) = timestamp_fields.get(field.name()) {
+                Arc::new(field.as_ref().clone().with_data_type(data_type.clone()))
             } else {
                 field.clone()
             }
@@ -274,12 +333,50 @@ pub fn override_existing_timestamp_fields(
     Arc::new(Schema::new(updated_fields))
 }
 
+/// Number fields that exist in the stream schema as int64 or decimal retain their type
+/// instead of the inferred type, preventing a loss of precision. Decimal fields retain it for
+/// strings too, as exact values are sent as decimal strings, e.g. `"12345678901234567.89"`
+pub fn override_existing_numeric_fields(
+    existing_schema: &HashMap<String, Arc<Field>>,
+    inferred_schema: Arc<Schema>,
+) -> Arc<Schema> {
+    let updated_fields: Vec<Arc<Field>> = inferred_schema
+        .fields()
+        .iter()
+        .map(|field| match existing_schema.get(field.name()) {
+            Some(existing)
+                if (field.data_type().is_numeric()
+                    && matches!(
+                        existing.data_type(),
+                        DataType::Int64 | DataType::Decimal128(_, _) | DataType::Decimal256(_, _)
+                    ))
+                    || (field.data_type() == &DataType::Utf8
+                        && matches!(
+                            existing.data_type(),
+                            DataType::Decimal128(_, _) | DataType::Decimal256(_, _)
+                        )) =>
+            {
+                Arc::new(
+                    field
+                        .as_ref()
+                        .clone()
+                        .with_data_type(existing.data_type().clone()),
+                )
+            }
+            _ => field.clone(),
+        })
+        .collect();
+
+    Arc::new(Schema::new(updated_fields))
+}
+
 pub fn update_field_type_in_schema(
     inferred_schema: Arc<Schema>,
     existing_schema: Option<&HashMap<String, Arc<Field>>>,
     time_partition: Option<&String>,
-    log_records: Option<&Vec<Value>>,
+    log_records: Option<&[Value]>,
     schema_version: SchemaVersion,
+    number_inference: Option<NumberInference>,
 ) -> Arc<Schema> {
     let mut updated_schema = inferred_schema.clone();
     let existing_field_names = get_existing_field_names(inferred_schema.clone(), existing_schema);
@@ -296,6 +393,20 @@ pub fn update_field_type_in_schema(
         }
     }
 
+    if let (Some(number_inference), Some(log_records)) = (number_inference, log_records) {
+        updated_schema = infer_number_types(
+            updated_schema,
+            existing_schema,
+            log_records,
+            number_inference,
+        );
+    }
+
+    if let Some(existing_schema) = existing_schema {
+        // retain the declared int64/decimal types of number fields instead of defaulting to float64
+        updated_schema = override_existing_numeric_fields(existing_schema, updated_schema);
+    }
+
     let Some(time_partition) = time_partition else {
         return updated_schema;
     };
@@ -309,16 +420,67 @@ pub fn update_field_type_in_schema(
                 && !existing_field_names.contains(field.name())
                 && field.data_type() == &DataType::Utf8
             {
-                let new_data_type = DataType::Timestamp(TimeUnit::Millisecond, None);
-                Field::new(field.name(), new_data_type, true)
+                field
+                    .as_ref()
+                    .clone()
+                    .with_data_type(timestamp_type())
+                    .with_nullable(true)
             } else {
-                Field::new(field.name(), field.data_type().clone(), true)
+                field.as_ref().clone().with_nullable(true)
             }
         })
         .collect();
     Arc::new(Schema::new(new_schema))
 }
 
+/// Infers the type of number fields as per `number_inference`, so that the type doesn't
+/// flip between int64 and float64 depending on the values seen in an event. Fields already
+/// in the stream keep their type, a field once widened to float64 stays so.
+fn infer_number_types(
+    schema: Arc<Schema>,
+    existing_schema: Option<&HashMap<String, Arc<Field>>>,
+    log_records: &[Value],
+    number_inference: NumberInference,
+) -> Arc<Schema> {
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            if !field.data_type().is_numeric() {
+                return field.as_ref().clone();
+            }
+            let data_type = match number_inference {
+                NumberInference::Float => DataType::Float64,
+                NumberInference::Integer
+                    if existing_schema
+                        .and_then(|schema| schema.get(field.name()))
+                        .is_some_and(|existing| existing.data_type() == &DataType::Float64) =>
+                {
+                    DataType::Float64
+                }
+                NumberInference::Integer => {
+                    let has_fraction = log_records
+                        .iter()
+                        .filter_map(|log_record| log_record.get(field.name()))
+                        .any(|value| value.as_f64().is_some_and(|n| n.fract() != 0.0));
+                    if has_fraction {
+                        DataType::Float64
+                    } else {
+                        DataType::Int64
+                    }
+                }
+            };
+            field
+                .as_ref()
+                .clone()
+                .with_data_type(data_type)
+                .with_nullable(true)
+        })
+        .collect();
+
+    Arc::new(Schema::new(fields))
+}
+
 // From Schema v1 onwards, convert json fields with name containig "date"/"time" and having
 // a string value parseable into timestamp as timestamp type and all numbers as float64.
 pub fn override_data_type(
@@ -347,19 +509,23 @@ pub fn override_data_type(
                             || DateTime::parse_from_rfc2822(s).is_ok()) =>
                 {
                     // Update the field's data type to Timestamp
-                    Field::new(
-                        field_name,
-                        DataType::Timestamp(TimeUnit::Millisecond, None),
-                        true,
-                    )
+                    field
+                        .as_ref()
+                        .clone()
+                        .with_data_type(timestamp_type())
+                        .with_nullable(true)
                 }
                 // in V1 for new fields in json with inferred type number, cast as float64.
                 (SchemaVersion::V1, Some(Value::Number(_))) if field.data_type().is_numeric() => {
                     // Update the field's data type to Float64
-                    Field::new(field_name, DataType::Float64, true)
+                    field
+                        .as_ref()
+                        .clone()
+                        .with_data_type(DataType::Float64)
+                        .with_nullable(true)
                 }
-                // Return the original field if no update is needed
-                _ => Field::new(field_name, field.data_type().clone(), true),
+                // Return the original field if no update is needed, along with its metadata
+                _ => field.as_ref().clone().with_nullable(true),
             }
         })
         .collect();
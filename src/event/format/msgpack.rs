@@ -0,0 +1,338 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+//! Decodes MessagePack encoded events into JSON, so that they can be ingested through the same
+//! path as JSON events.
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{Map, Number, Value};
+
+/// Extension type reserved by the MessagePack spec for timestamps
+const TIMESTAMP_EXT: i8 = -1;
+/// Limits nesting of arrays and maps, so that a malicious payload can't overflow the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MsgpackError {
+    #[error("Unexpected end of msgpack data")]
+    UnexpectedEof,
+    #[error("Msgpack data has reserved marker byte {0:#04x}")]
+    ReservedMarker(u8),
+    #[error("Msgpack extension type {0} is not supported")]
+    UnsupportedExtension(i8),
+    #[error("Msgpack timestamp is invalid")]
+    InvalidTimestamp,
+    #[error("Msgpack string is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("Msgpack data is nested deeper than {MAX_DEPTH} levels")]
+    TooDeep,
+}
+
+/// Decodes a msgpack body into JSON, the body may hold a single value or a sequence of values
+/// one after another, the latter is treated as a batch like NDJSON and returned as a JSON array.
+pub fn decode(buf: &[u8]) -> Result<Value, MsgpackError> {
+    let mut reader = Reader { buf };
+    let mut values = vec![];
+    while !reader.is_empty() {
+        values.push(reader.value(0)?);
+    }
+
+    match values.len() {
+        0 => Err(MsgpackError::UnexpectedEof),
+        1 => Ok(values.pop().expect("exactly one value is decoded")),
+        _ => Ok(Value::Array(values)),
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], MsgpackError> {
+        if self.buf.len() < n {
+            return Err(MsgpackError::UnexpectedEof);
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+
+        Ok(head)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], MsgpackError> {
+        let bytes = self.take(N)?;
+        Ok(bytes.try_into().expect("exactly N bytes are taken"))
+    }
+
+    fn u8(&mut self) -> Result<u8, MsgpackError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MsgpackError> {
+        Ok(u16::from_be_bytes(self.fixed()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, MsgpackError> {
+        Ok(u32::from_be_bytes(self.fixed()?))
+    }
+
+    fn str(&mut self, len: usize) -> Result<String, MsgpackError> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| MsgpackError::InvalidUtf8)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, MsgpackError> {
+        if depth > MAX_DEPTH {
+            return Err(MsgpackError::TooDeep);
+        }
+
+        let marker = self.u8()?;
+        let value = match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.array((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => Value::String(self.str((marker & 0x1f) as usize)?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            // binary data is kept as base64, same as protobuf bytes
+            0xc4 => {
+                let len = self.u8()? as usize;
+                Value::String(BASE64_STANDARD.encode(self.take(len)?))
+            }
+            0xc5 => {
+                let len = self.u16()? as usize;
+                Value::String(BASE64_STANDARD.encode(self.take(len)?))
+            }
+            0xc6 => {
+                let len = self.u32()? as usize;
+                Value::String(BASE64_STANDARD.encode(self.take(len)?))
+            }
+            0xc7 => {
+                let len = self.u8()? as usize;
+                self.ext(len)?
+            }
+            0xc8 => {
+                let len = self.u16()? as usize;
+                self.ext(len)?
+            }
+            0xc9 => {
+                let len = self.u32()? as usize;
+                self.ext(len)?
+            }
+            0xca => float(f32::from_be_bytes(self.fixed()?) as f64),
+            0xcb => float(f64::from_be_bytes(self.fixed()?)),
+            0xcc => Value::from(self.u8()?),
+            0xcd => Value::from(self.u16()?),
+            0xce => Value::from(self.u32()?),
+            0xcf => Value::from(u64::from_be_bytes(self.fixed()?)),
+            0xd0 => Value::from(i8::from_be_bytes(self.fixed()?)),
+            0xd1 => Value::from(i16::from_be_bytes(self.fixed()?)),
+            0xd2 => Value::from(i32::from_be_bytes(self.fixed()?)),
+            0xd3 => Value::from(i64::from_be_bytes(self.fixed()?)),
+            0xd4 => self.ext(1)?,
+            0xd5 => self.ext(2)?,
+            0xd6 => self.ext(4)?,
+            0xd7 => self.ext(8)?,
+            0xd8 => self.ext(16)?,
+            0xd9 => {
+                let len = self.u8()? as usize;
+                Value::String(self.str(len)?)
+            }
+            0xda => {
+                let len = self.u16()? as usize;
+                Value::String(self.str(len)?)
+            }
+            0xdb => {
+                let len = self.u32()? as usize;
+                Value::String(self.str(len)?)
+            }
+            0xdc => {
+                let len = self.u16()? as usize;
+                self.array(len, depth)?
+            }
+            0xdd => {
+                let len = self.u32()? as usize;
+                self.array(len, depth)?
+            }
+            0xde => {
+                let len = self.u16()? as usize;
+                self.map(len, depth)?
+            }
+            0xdf => {
+                let len = self.u32()? as usize;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            0xc1 => return Err(MsgpackError::ReservedMarker(marker)),
+        };
+
+        Ok(value)
+    }
+
+    fn array(&mut self, len: usize, depth: usize) -> Result<Value, MsgpackError> {
+        // length is untrusted, don't preallocate more than the remaining bytes can hold
+        let mut values = Vec::with_capacity(len.min(self.buf.len()));
+        for _ in 0..len {
+            values.push(self.value(depth + 1)?);
+        }
+
+        Ok(Value::Array(values))
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value, MsgpackError> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            // JSON only allows string keys, others are used in their JSON representation
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            let value = self.value(depth + 1)?;
+            map.insert(key, value);
+        }
+
+        Ok(Value::Object(map))
+    }
+
+    /// Decodes extension types, only the timestamp extension defined by the spec is supported
+    /// and is converted into an RFC 3339 string
+    fn ext(&mut self, len: usize) -> Result<Value, MsgpackError> {
+        let ext_type = i8::from_be_bytes(self.fixed()?);
+        let data = self.take(len)?;
+        if ext_type != TIMESTAMP_EXT {
+            return Err(MsgpackError::UnsupportedExtension(ext_type));
+        }
+
+        let (secs, nanos) = match data.len() {
+            4 => (
+                u32::from_be_bytes(data.try_into().expect("4 bytes")) as i64,
+                0,
+            ),
+            8 => {
+                let value = u64::from_be_bytes(data.try_into().expect("8 bytes"));
+                ((value & 0x3_ffff_ffff) as i64, (value >> 34) as u32)
+            }
+            12 => (
+                i64::from_be_bytes(data[4..].try_into().expect("8 bytes")),
+                u32::from_be_bytes(data[..4].try_into().expect("4 bytes")),
+            ),
+            _ => return Err(MsgpackError::InvalidTimestamp),
+        };
+        let timestamp =
+            DateTime::from_timestamp(secs, nanos).ok_or(MsgpackError::InvalidTimestamp)?;
+
+        Ok(Value::String(
+            timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        ))
+    }
+}
+
+/// NaN and infinities can't be represented in JSON and are ingested as null
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use arrow_array::cast::AsArray;
+    use serde_json::json;
+
+    use crate::{
+        event::format::{json, EventFormat},
+        metadata::SchemaVersion,
+    };
+
+    use super::*;
+
+    // {"level": "info", "code": 200, "ok": true, "latency": 1.5, "tags": ["a"]}
+    const EVENT: &[u8] = &[
+        0x85, 0xa5, b'l', b'e', b'v', b'e', b'l', 0xa4, b'i', b'n', b'f', b'o', 0xa4, b'c', b'o',
+        b'd', b'e', 0xcc, 0xc8, 0xa2, b'o', b'k', 0xc3, 0xa7, b'l', b'a', b't', b'e', b'n', b'c',
+        b'y', 0xcb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xa4, b't', b'a', b'g', b's',
+        0x91, 0xa1, b'a',
+    ];
+
+    #[test]
+    fn msgpack_event_matches_json_equivalent() {
+        let decoded = decode(EVENT).unwrap();
+        let expected =
+            json!({"level": "info", "code": 200, "ok": true, "latency": 1.5, "tags": ["a"]});
+        assert_eq!(decoded, expected);
+
+        let to_batch = |json| {
+            json::Event::new(json)
+                .into_recordbatch(
+                    &HashMap::new(),
+                    false,
+                    None,
+                    SchemaVersion::V1,
+                    &HashMap::new(),
+                )
+                .unwrap()
+                .0
+        };
+        let from_msgpack = to_batch(decoded);
+        let from_json = to_batch(expected);
+        assert_eq!(from_msgpack.schema(), from_json.schema());
+        // columns other than p_timestamp hold the same row
+        for (field, column) in from_json.schema().fields().iter().zip(from_json.columns()) {
+            if field.name() == "p_timestamp" {
+                continue;
+            }
+            assert_eq!(from_msgpack.column_by_name(field.name()).unwrap(), column);
+        }
+        assert_eq!(
+            from_msgpack
+                .column_by_name("level")
+                .unwrap()
+                .as_string::<i32>()
+                .value(0),
+            "info"
+        );
+    }
+
+    #[test]
+    fn sequence_of_values_is_a_batch() {
+        // {"a": 1} {"a": -1}
+        let buf = [0x81, 0xa1, b'a', 0x01, 0x81, 0xa1, b'a', 0xff];
+        assert_eq!(decode(&buf).unwrap(), json!([{"a": 1}, {"a": -1}]));
+    }
+
+    #[test]
+    fn decodes_timestamp_extension() {
+        // fixext4 timestamp of 2024-01-01T00:00:00Z
+        let buf = [0xd6, 0xff, 0x65, 0x92, 0x00, 0x80];
+        assert_eq!(decode(&buf).unwrap(), json!("2024-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        assert!(matches!(
+            decode(&EVENT[..EVENT.len() - 1]),
+            Err(MsgpackError::UnexpectedEof)
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+//! Decodes protobuf encoded events into JSON, as described by a descriptor registered for the
+//! stream, so that they can be ingested through the same path as JSON events.
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, SerializeOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Describes the protobuf messages of a stream with a `FileDescriptorSet`, as put out by
+/// `protoc --include_imports --descriptor_set_out`, and the full name of the message events are
+/// encoded as, e.g.
+/// ```json
+/// { "file_descriptor_set": "<base64 encoded FileDescriptorSet>", "message": "logs.v1.Log" }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "EncodedDescriptor", into = "EncodedDescriptor")]
+pub struct ProtoDescriptor {
+    encoded: EncodedDescriptor,
+    message: MessageDescriptor,
+}
+
+/// The descriptor as registered and persisted along with the settings of the stream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct EncodedDescriptor {
+    file_descriptor_set: String,
+    message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProtobufError {
+    #[error("Invalid protobuf message: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("Protobuf message can't be represented as JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid protobuf descriptor: {0}")]
+    InvalidDescriptor(String),
+    #[error("No protobuf descriptor is registered for stream {0}")]
+    MissingDescriptor(String),
+}
+
+impl TryFrom<EncodedDescriptor> for ProtoDescriptor {
+    type Error = ProtobufError;
+
+    /// Ensures the file descriptor set is valid and describes the message events are encoded as
+    fn try_from(encoded: EncodedDescriptor) -> Result<Self, Self::Error> {
+        let bytes = BASE64_STANDARD
+            .decode(&encoded.file_descriptor_set)
+            .map_err(|err| {
+                ProtobufError::InvalidDescriptor(format!(
+                    "file descriptor set is not base64 encoded: {err}"
+                ))
+            })?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .map_err(|err| ProtobufError::InvalidDescriptor(err.to_string()))?;
+        let message = pool.get_message_by_name(&encoded.message).ok_or_else(|| {
+            ProtobufError::InvalidDescriptor(format!(
+                "message {} is not described by the file descriptor set",
+                encoded.message
+            ))
+        })?;
+
+        Ok(Self { encoded, message })
+    }
+}
+
+impl From<ProtoDescriptor> for EncodedDescriptor {
+    fn from(descriptor: ProtoDescriptor) -> Self {
+        descriptor.encoded
+    }
+}
+
+impl PartialEq for ProtoDescriptor {
+    fn eq(&self, other: &Self) -> bool {
+        self.encoded == other.encoded
+    }
+}
+
+impl Eq for ProtoDescriptor {}
+
+impl ProtoDescriptor {
+    /// Decodes a binary protobuf message into a JSON object, as per the JSON mapping of protobuf,
+    /// except that field names are those of the proto file and 64 bit integers and enums are kept as
+    /// numbers. Unset fields and fields not in the descriptor are left out.
+    pub fn decode(&self, buf: &[u8]) -> Result<Value, ProtobufError> {
+        let message = DynamicMessage::decode(self.message.clone(), buf)?;
+        let options = SerializeOptions::new()
+            .use_proto_field_name(true)
+            .stringify_64_bit_integers(false)
+            .use_enum_numbers(true);
+
+        Ok(message.serialize_with_options(serde_json::value::Serializer, &options)?)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use prost::Message;
+    use prost_reflect::prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(name: &str, number: i32, field_type: Type, label: Label) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(field_type as i32),
+            ..Default::default()
+        }
+    }
+
+    /// Descriptor of `logs.Log`, with fields `level = 1`, `status = 2`, `repeated codes = 3`,
+    /// `source = 4` of message `logs.Source { host = 1, latency = 2 }` and `repeated tags = 5`
+    pub(crate) fn descriptor() -> Value {
+        let file = FileDescriptorProto {
+            name: Some("log.proto".to_owned()),
+            package: Some("logs".to_owned()),
+            syntax: Some("proto3".to_owned()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Source".to_owned()),
+                    field: vec![
+                        field("host", 1, Type::String, Label::Optional),
+                        field("latency", 2, Type::Double, Label::Optional),
+                    ],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Log".to_owned()),
+                    field: vec![
+                        field("level", 1, Type::String, Label::Optional),
+                        field("status", 2, Type::Int32, Label::Optional),
+                        field("codes", 3, Type::Int64, Label::Repeated),
+                        FieldDescriptorProto {
+                            type_name: Some(".logs.Source".to_owned()),
+                            ..field("source", 4, Type::Message, Label::Optional)
+                        },
+                        field("tags", 5, Type::String, Label::Repeated),
+                    ],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let set = FileDescriptorSet { file: vec![file] };
+
+        json!({
+            "file_descriptor_set": BASE64_STANDARD.encode(set.encode_to_vec()),
+            "message": "logs.Log"
+        })
+    }
+
+    #[test]
+    fn decode_message() {
+        let descriptor: ProtoDescriptor = serde_json::from_value(descriptor()).unwrap();
+        // registered as is
+        assert_eq!(serde_json::to_value(&descriptor).unwrap(), descriptor());
+
+        let mut source = vec![0x0a, 0x01, b'a', 0x11];
+        source.extend_from_slice(&1.5f64.to_le_bytes());
+
+        let mut message = vec![
+            0x0a,
+            0x04,
+            b'w',
+            b'a',
+            b'r',
+            b'n', // level = "warn"
+            0x10,
+            0xac,
+            0x02, // status = 300
+            0x1a,
+            0x02,
+            0x01,
+            0x02, // codes = [1, 2], packed
+            0x60,
+            0x07, // unknown field 12 = 7, skipped
+            0x22,
+            source.len() as u8, // source
+        ];
+        message.extend_from_slice(&source);
+        message.extend_from_slice(&[0x2a, 0x01, b'x', 0x2a, 0x01, b'y']); // tags = ["x", "y"]
+
+        assert_eq!(
+            descriptor.decode(&message).unwrap(),
+            json!({
+                "level": "warn",
+                "status": 300,
+                "codes": [1, 2],
+                "source": { "host": "a", "latency": 1.5 },
+                "tags": ["x", "y"]
+            })
+        );
+    }
+
+    #[test]
+    fn decode_truncated_message() {
+        let descriptor: ProtoDescriptor = serde_json::from_value(descriptor()).unwrap();
+        assert!(matches!(
+            descriptor.decode(&[0x0a, 0x04, b'w']),
+            Err(ProtobufError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_descriptor() {
+        let mut unknown_message = descriptor();
+        unknown_message["message"] = json!("logs.Trace");
+        assert!(serde_json::from_value::<ProtoDescriptor>(unknown_message).is_err());
+
+        let mut not_a_descriptor = descriptor();
+        not_a_descriptor["file_descriptor_set"] = json!(BASE64_STANDARD.encode(b"\xff\xff"));
+        assert!(serde_json::from_value::<ProtoDescriptor>(not_a_descriptor).is_err());
+    }
+}
@@ -17,13 +17,20 @@
 *
 */
 
+pub mod column_limit;
+pub mod counter;
+pub mod dedup;
+pub mod derived;
 pub mod format;
+pub mod sampling;
 
 use arrow_array::RecordBatch;
 use arrow_schema::{Field, Fields, Schema};
+use datafusion::prelude::SessionContext;
 use itertools::Itertools;
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
+use self::dedup::Pending;
 use self::error::EventError;
 use crate::{
     metadata::update_stats,
@@ -39,6 +46,14 @@ pub const USER_AGENT_KEY: &str = "p_user_agent";
 pub const SOURCE_IP_KEY: &str = "p_src_ip";
 pub const FORMAT_KEY: &str = "p_format";
 pub const FORMAT_VERIFY_KEY: &str = "p_format_verified";
+/// Column holding the partition key supplied by the client alongside the events
+pub const PARTITION_KEY: &str = "p_partition_key";
+/// Object store prefix that events are fanned out under, as per the hash of their partition key
+pub const PARTITION_BUCKET_KEY: &str = "p_partition_bucket";
+/// Column holding the events of schema-on-read streams, as JSON
+pub const RAW_EVENT_KEY: &str = "p_raw";
+/// Column holding the position of an event in the sequence of events ingested into its stream
+pub const SEQUENCE_KEY: &str = "p_sequence";
 
 #[derive(Clone)]
 pub struct Event {
@@ -55,7 +70,50 @@ pub struct Event {
 
 // Events holds the schema related to a each event for a single log stream
 impl Event {
-    pub fn process(self) -> Result<(), EventError> {
+    /// Stores the events in the stream, returns the sequence number they were pushed with, none
+    /// when every event was dropped by sampling or as a duplicate
+    pub fn process(mut self) -> Result<Option<u64>, EventError> {
+        let _in_flight = PARSEABLE.ingest_gate.enter()?;
+        let stream = PARSEABLE.get_or_create_stream(&self.stream_name);
+        // checked before the schema is committed, not just on write
+        stream.ensure_writable()?;
+        let settings = stream.get_settings();
+        if let Some(sampling) = &settings.sampling {
+            self.rb = sampling.sample(&self.rb).map_err(StagingError::Arrow)?;
+        }
+        // pending until the events are pushed, only then are they recorded as seen, so that
+        // failed pushes aren't taken as stored while duplicates ingested concurrently are dropped
+        let mut pending = None;
+        if let Some(window) = settings.dedup_window() {
+            let (rb, hashes) = Pending::dedup(&stream.dedup, &self.rb, window, Instant::now())
+                .map_err(StagingError::Arrow)?;
+            self.rb = rb;
+            pending = Some(hashes);
+        }
+        // every event was dropped by sampling or as a duplicate
+        if self.rb.num_rows() == 0 {
+            return Ok(None);
+        }
+        let derived_columns = &settings.derived_columns;
+        if !derived_columns.is_empty() {
+            let stream_schema = stream.get_schema_raw();
+            let ctx = stream.derived_context.get_or_init(SessionContext::new);
+            self.rb = derived::apply(ctx, derived_columns, self.rb, &stream_schema)?;
+            // derived columns are new to the schema when declared after events were ingested
+            self.is_first_event |= self
+                .rb
+                .schema()
+                .fields()
+                .iter()
+                .any(|field| !stream_schema.contains_key(field.name()));
+        }
+        // numbers supplied by the client are kept as is
+        if stream.options.sequence_numbers && self.rb.column_by_name(SEQUENCE_KEY).is_none() {
+            self.rb = stream.assign_sequence(&self.rb, || stream.sequence_seed())?;
+            // the column is new to the schema on the first numbered event
+            self.is_first_event |= !stream.get_schema_raw().contains_key(SEQUENCE_KEY);
+        }
+
         let mut key = get_schema_key(&self.rb.schema().fields);
         if self.time_partition.is_some() {
             let parsed_timestamp_to_min = self.parsed_timestamp.format("%Y%m%dT%H%M").to_string();
@@ -72,13 +130,16 @@ impl Event {
             commit_schema(&self.stream_name, self.rb.schema())?;
         }
 
-        PARSEABLE.get_or_create_stream(&self.stream_name).push(
+        let seq = stream.push(
             &key,
             &self.rb,
             self.parsed_timestamp,
             &self.custom_partition_values,
             self.stream_type,
         )?;
+        if let Some(pending) = pending {
+            pending.record();
+        }
 
         update_stats(
             &self.stream_name,
@@ -87,10 +148,11 @@ impl Event {
             self.rb.num_rows(),
             self.parsed_timestamp.date(),
         );
+        counter::EVENT_COUNTER.add(self.rb.num_rows() as u64);
 
         crate::livetail::LIVETAIL.process(&self.stream_name, &self.rb);
 
-        Ok(())
+        Ok(Some(seq))
     }
 
     pub fn process_unchecked(&self) -> Result<(), EventError> {
@@ -118,6 +180,13 @@ pub fn get_schema_key(fields: &[Arc<Field>]) -> String {
     format!("{hash:x}")
 }
 
+/// Deterministically maps a partition key onto one of `buckets` partitions, so that all events
+/// with the same key are written under, and can be queried from, the same prefix
+pub fn partition_bucket(partition_key: &str, buckets: usize) -> String {
+    let hash = xxhash_rust::xxh3::xxh3_64(partition_key.as_bytes());
+    (hash % buckets.max(1) as u64).to_string()
+}
+
 pub fn commit_schema(stream_name: &str, schema: Arc<Schema>) -> Result<(), StagingError> {
     let mut stream_metadata = PARSEABLE.streams.write().expect("lock poisoned");
 
@@ -137,6 +206,8 @@ pub fn commit_schema(stream_name: &str, schema: Arc<Schema>) -> Result<(), Stagi
 
 pub mod error {
 
+    use datafusion::error::DataFusionError;
+
     use crate::{parseable::StagingError, storage::ObjectStorageError};
 
     #[derive(Debug, thiserror::Error)]
@@ -145,5 +216,7 @@ pub mod error {
         Staging(#[from] StagingError),
         #[error("ObjectStorage Error: {0}")]
         ObjectStorage(#[from] ObjectStorageError),
+        #[error("Derived column could not be computed: {0}")]
+        DerivedColumn(#[from] DataFusionError),
     }
 }
@@ -0,0 +1,145 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use arrow::compute::{cast, filter_record_batch};
+use arrow_array::{cast::AsArray, BooleanArray, RecordBatch};
+use arrow_schema::{ArrowError, DataType};
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Rule by which only a fraction of the events ingested into a stream are stored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingRule {
+    /// Fraction of events that are kept, e.g. `0.1` keeps 1 in 10 events
+    pub rate: f64,
+    /// Field, such as an idempotency key, whose value decides whether an event is kept. Events
+    /// with the same value are either all kept or all dropped, others are sampled at random.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+// rate is validated to not be NaN
+impl Eq for SamplingRule {}
+
+impl SamplingRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.rate > 0.0 && self.rate <= 1.0) {
+            return Err(format!(
+                "Sampling rate should be greater than 0 and at most 1, got {}",
+                self.rate
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Decides whether an event is kept, deterministically so when it has a key
+    fn keep(&self, key: Option<&str>) -> bool {
+        match key {
+            Some(key) => (xxh3_64(key.as_bytes()) as f64 / u64::MAX as f64) < self.rate,
+            None => rand::random::<f64>() < self.rate,
+        }
+    }
+
+    /// Returns the events of `rb` that are kept
+    pub fn sample(&self, rb: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+        if self.rate >= 1.0 {
+            return Ok(rb.clone());
+        }
+
+        let keys = self
+            .key
+            .as_ref()
+            .and_then(|key| rb.column_by_name(key))
+            .map(|column| cast(column, &DataType::Utf8))
+            .transpose()?;
+        let keys = keys.as_ref().map(|keys| keys.as_string::<i32>());
+        let mask: BooleanArray = (0..rb.num_rows())
+            .map(|i| {
+                let key = keys.and_then(|keys| keys.is_valid(i).then(|| keys.value(i)));
+                Some(self.keep(key))
+            })
+            .collect();
+
+        filter_record_batch(rb, &mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int64Array, StringArray};
+    use arrow_schema::{Field, Schema};
+
+    use super::*;
+
+    fn events(count: i64) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("request_id", DataType::Utf8, false),
+        ]));
+        let request_ids = (0..count).map(|i| format!("req-{}", i % 100));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(0..count)),
+                Arc::new(StringArray::from_iter_values(request_ids)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn roughly_one_in_ten_events_are_kept() {
+        let rule = SamplingRule {
+            rate: 0.1,
+            key: None,
+        };
+        let kept = rule.sample(&events(10_000)).unwrap().num_rows();
+        assert!((800..=1200).contains(&kept), "kept {kept} events");
+    }
+
+    #[test]
+    fn events_with_same_key_are_sampled_alike() {
+        let rule = SamplingRule {
+            rate: 0.1,
+            key: Some("request_id".to_owned()),
+        };
+        let first = rule.sample(&events(1000)).unwrap();
+        let second = rule.sample(&events(1000)).unwrap();
+        assert_eq!(first, second);
+
+        // every request id occurs 10 times, all of which are either kept or dropped
+        assert_eq!(first.num_rows() % 10, 0);
+    }
+
+    #[test]
+    fn rate_is_validated() {
+        for rate in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(SamplingRule { rate, key: None }.validate().is_err());
+        }
+        assert!(SamplingRule {
+            rate: 1.0,
+            key: None
+        }
+        .validate()
+        .is_ok());
+    }
+}
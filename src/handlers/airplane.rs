@@ -44,8 +44,8 @@ use crate::utils::arrow::flight::{
     append_temporary_events, get_query_from_ticket, into_flight_data, run_do_get_rpc,
     send_to_ingester,
 };
+use crate::utils::session_auth_for_datasets;
 use crate::utils::time::TimeRange;
-use crate::utils::user_auth_for_datasets;
 use arrow_flight::{
     flight_service_server::FlightService, Action, ActionType, Criteria, Empty, FlightData,
     FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse, PutResult, SchemaAsIpc,
@@ -212,9 +212,7 @@ impl FlightService for AirServiceImpl {
             }
         }
 
-        let permissions = Users.get_permissions(&key);
-
-        user_auth_for_datasets(&permissions, &streams).map_err(|_| {
+        session_auth_for_datasets(&key, &streams).map_err(|_| {
             Status::permission_denied("User Does not have permission to access this")
         })?;
         let time = Instant::now();
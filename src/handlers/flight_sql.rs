@@ -0,0 +1,440 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{net::SocketAddr, pin::Pin};
+
+use arrow_array::RecordBatch;
+use arrow_flight::{
+    flight_service_server::{FlightService, FlightServiceServer},
+    sql::{
+        server::FlightSqlService, CommandGetTables, CommandStatementQuery, ProstMessageExt,
+        SqlInfo, TicketStatementQuery,
+    },
+    FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest, HandshakeResponse, Ticket,
+};
+use arrow_schema::{ArrowError, SchemaRef};
+use datafusion::common::tree_node::TreeNode;
+use futures::{stream, Stream};
+use futures_util::{Future, TryFutureExt};
+use prost::Message;
+use serde_json::json;
+use tonic::{
+    codec::CompressionEncoding,
+    metadata::{MetadataMap, MetadataValue},
+    transport::{Identity, Server, ServerTlsConfig},
+    Request, Response, Status, Streaming,
+};
+use ulid::Ulid;
+
+use crate::{
+    handlers::{
+        airplane::AirServiceImpl, http::query::update_schema_when_distributed,
+        livetail::extract_session_key,
+    },
+    parseable::PARSEABLE,
+    query::{TableScanVisitor, QUERY_SESSION},
+    rbac::{self, map::SessionKey, Users},
+    utils::{arrow::flight::into_flight_data, session_auth_for_datasets},
+};
+
+/// Catalog and schema the streams are listed under, the defaults of datafusion
+const CATALOG: &str = "datafusion";
+const DB_SCHEMA: &str = "public";
+
+/// Flight SQL endpoint for BI tools, streams are exposed as tables and statements are run as
+/// queries over the default query window, as queries over Arrow Flight are
+#[derive(Clone, Debug)]
+pub struct FlightSqlServiceImpl {}
+
+/// Authorizes the user of the request to query `streams`
+fn authorize(metadata: &MetadataMap, streams: &[String]) -> Result<(), Status> {
+    let key = extract_session_key(metadata).map_err(|err| *err)?;
+    match Users.authorize(key.clone(), rbac::role::Action::Query, None, None) {
+        rbac::Response::Authorized => (),
+        rbac::Response::UnAuthorized => {
+            return Err(Status::permission_denied(
+                "user is not authorized to access this resource",
+            ))
+        }
+        rbac::Response::ReloadRequired => return Err(Status::unauthenticated("reload required")),
+    }
+
+    session_auth_for_datasets(&key, streams)
+        .map_err(|_| Status::permission_denied("User Does not have permission to access this"))
+}
+
+/// Streams the session may query, by its roles and the ACLs of the streams
+fn queryable_streams(key: &SessionKey) -> Vec<String> {
+    PARSEABLE
+        .streams
+        .list()
+        .into_iter()
+        .filter(|stream| session_auth_for_datasets(key, &[stream.clone()]).is_ok())
+        .collect()
+}
+
+/// Lists the `streams` as tables, filtered as requested by `query`
+fn tables(
+    query: CommandGetTables,
+    streams: impl IntoIterator<Item = (String, SchemaRef)>,
+) -> Result<RecordBatch, ArrowError> {
+    let mut builder = query.into_builder();
+    for (stream, schema) in streams {
+        builder.append(CATALOG, DB_SCHEMA, stream, "TABLE", &schema)?;
+    }
+
+    builder.build()
+}
+
+#[tonic::async_trait]
+impl FlightSqlService for FlightSqlServiceImpl {
+    type FlightService = FlightSqlServiceImpl;
+
+    /// Checks the credentials of the client, basic credentials are exchanged for a session
+    /// whose id is handed back as the bearer token sent along with every later request
+    async fn do_handshake(
+        &self,
+        request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<
+        Response<Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>>,
+        Status,
+    > {
+        let key = extract_session_key(request.metadata()).map_err(|err| *err)?;
+        authorize(request.metadata(), &[])?;
+
+        let token = match key {
+            SessionKey::BasicAuth { username, .. } => {
+                let user = Users
+                    .get_user(&username)
+                    .ok_or_else(|| Status::unauthenticated("user does not exist"))?;
+                let session = Ulid::new();
+                Users.new_session(&user, SessionKey::SessionId(session));
+                session.to_string()
+            }
+            SessionKey::SessionId(session) => session.to_string(),
+            SessionKey::ApiToken(token) => token,
+        };
+        let authorization = MetadataValue::try_from(format!("Bearer {token}"))
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        let output = stream::iter([Ok(HandshakeResponse {
+            protocol_version: 0,
+            payload: token.into(),
+        })]);
+        let mut response: Response<
+            Pin<Box<dyn Stream<Item = Result<HandshakeResponse, Status>> + Send>>,
+        > = Response::new(Box::pin(output));
+        response
+            .metadata_mut()
+            .insert("authorization", authorization);
+
+        Ok(response)
+    }
+
+    async fn get_flight_info_statement(
+        &self,
+        query: CommandStatementQuery,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let plan = QUERY_SESSION
+            .state()
+            .create_logical_plan(&query.query)
+            .await
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let mut visitor = TableScanVisitor::default();
+        let _ = plan.visit(&mut visitor);
+        let tables = visitor.into_inner();
+        authorize(request.metadata(), &tables)?;
+
+        // the schema of the streams may have grown on the ingestors, it is planned again with it
+        update_schema_when_distributed(&tables)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        let plan = QUERY_SESSION
+            .state()
+            .create_logical_plan(&query.query)
+            .await
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // the statement itself is the handle it is fetched by
+        let ticket = TicketStatementQuery {
+            statement_handle: query.query.into(),
+        };
+        let endpoint =
+            FlightEndpoint::new().with_ticket(Ticket::new(ticket.as_any().encode_to_vec()));
+        let info = FlightInfo::new()
+            .try_with_schema(plan.schema().as_arrow())
+            .map_err(|err| Status::internal(err.to_string()))?
+            .with_endpoint(endpoint)
+            .with_descriptor(request.into_inner());
+
+        Ok(Response::new(info))
+    }
+
+    /// Runs the statement as a query over Arrow Flight
+    async fn do_get_statement(
+        &self,
+        ticket: TicketStatementQuery,
+        request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        let query = String::from_utf8(ticket.statement_handle.to_vec())
+            .map_err(|_| Status::invalid_argument("statement handle is not a query"))?;
+        let mut flight_request = Request::new(Ticket::new(json!({ "query": query }).to_string()));
+        *flight_request.metadata_mut() = request.metadata().clone();
+
+        AirServiceImpl {}.do_get(flight_request).await
+    }
+
+    async fn get_flight_info_tables(
+        &self,
+        query: CommandGetTables,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let endpoint =
+            FlightEndpoint::new().with_ticket(Ticket::new(query.as_any().encode_to_vec()));
+        let info = FlightInfo::new()
+            .try_with_schema(&query.into_builder().schema())
+            .map_err(|err| Status::internal(err.to_string()))?
+            .with_endpoint(endpoint)
+            .with_descriptor(request.into_inner());
+
+        Ok(Response::new(info))
+    }
+
+    /// Lists the streams the user may query
+    async fn do_get_tables(
+        &self,
+        query: CommandGetTables,
+        request: Request<Ticket>,
+    ) -> Result<Response<<Self as FlightService>::DoGetStream>, Status> {
+        authorize(request.metadata(), &[])?;
+        let key = extract_session_key(request.metadata()).map_err(|err| *err)?;
+        let streams = queryable_streams(&key).into_iter().filter_map(|stream| {
+            let schema = PARSEABLE.get_stream(&stream).ok()?.get_schema();
+            Some((stream, schema))
+        });
+        let batch = tables(query, streams).map_err(|err| Status::internal(err.to_string()))?;
+
+        into_flight_data(vec![batch]).map_err(|err| *err)
+    }
+
+    async fn register_sql_info(&self, _id: i32, _result: &SqlInfo) {}
+}
+
+pub fn server() -> impl Future<Output = Result<(), Box<dyn std::error::Error + Send>>> + Send {
+    let mut addr: SocketAddr = PARSEABLE
+        .options
+        .address
+        .parse()
+        .expect("valid socket address, as checked when starting the flight server");
+    addr.set_port(PARSEABLE.options.flight_sql_port.unwrap_or_default());
+
+    let svc = FlightServiceServer::new(FlightSqlServiceImpl {})
+        .max_encoding_message_size(usize::MAX)
+        .max_decoding_message_size(usize::MAX)
+        .send_compressed(CompressionEncoding::Zstd)
+        .accept_compressed(CompressionEncoding::Zstd);
+
+    let identity = match (
+        &PARSEABLE.options.tls_cert_path,
+        &PARSEABLE.options.tls_key_path,
+    ) {
+        (Some(cert), Some(key)) => {
+            match (std::fs::read_to_string(cert), std::fs::read_to_string(key)) {
+                (Ok(cert_file), Ok(key_file)) => Some(Identity::from_pem(cert_file, key_file)),
+                _ => None,
+            }
+        }
+        (_, _) => None,
+    };
+
+    let server = match identity {
+        Some(identity) => Server::builder()
+            .tls_config(ServerTlsConfig::new().identity(identity))
+            .unwrap_or_else(|_| Server::builder()),
+        None => Server::builder(),
+    };
+
+    server
+        .add_service(svc)
+        .serve(addr)
+        .map_err(|err| Box::new(err) as Box<dyn std::error::Error + Send>)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow_array::cast::AsArray;
+    use arrow_flight::sql::client::FlightSqlServiceClient;
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+    use tokio::net::TcpListener;
+    use tonic::transport::Endpoint;
+
+    use crate::{
+        event::format::LogSource,
+        handlers::http::modal::utils::ingest_utils::flatten_and_push_logs,
+        storage::ObjectStoreFormat,
+    };
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_queries_with_the_session_of_its_handshake() {
+        rbac::map::init_for_tests();
+        let stream_name = "flight_sql_client";
+        PARSEABLE.get_or_create_stream(stream_name);
+        PARSEABLE
+            .storage
+            .get_object_store()
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat::default(),
+                Arc::new(Schema::empty()),
+            )
+            .await
+            .unwrap();
+        for code in [200, 404, 500] {
+            flatten_and_push_logs(
+                serde_json::json!({"code": code}),
+                stream_name,
+                &LogSource::Json,
+                &HashMap::new(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = stream::unfold(listener, |listener| async move {
+            let conn = listener.accept().await.map(|(socket, _)| socket);
+            Some((conn, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(FlightServiceServer::new(FlightSqlServiceImpl {}))
+                .serve_with_incoming(incoming),
+        );
+        let channel = Endpoint::from_shared(format!("http://{addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+
+        let mut client = FlightSqlServiceClient::new(channel);
+        assert!(client.handshake("admin", "wrong").await.is_err());
+        client.handshake("admin", "admin").await.unwrap();
+
+        // the bearer token of the handshake is a session of the user
+        let session = client.token().unwrap().parse::<Ulid>().unwrap();
+        assert_eq!(
+            Users.get_username_from_session(&SessionKey::SessionId(session)),
+            Some("admin".to_owned())
+        );
+
+        // which is all the client authenticates the statement with from then on
+        let info = client
+            .execute(format!("select code from {stream_name}"), None)
+            .await
+            .unwrap();
+        let ticket = info.endpoint[0].ticket.clone().unwrap();
+        let batches: Vec<RecordBatch> = client
+            .do_get(ticket)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let rows: usize = batches.iter().map(|rb| rb.num_rows()).sum();
+        assert_eq!(rows, 3);
+    }
+
+    #[test]
+    fn streams_are_listed_as_tables() {
+        let schema = Arc::new(Schema::new(vec![Field::new("msg", DataType::Utf8, true)]));
+        let streams =
+            ["app", "app_logs", "billing"].map(|stream| (stream.to_owned(), schema.clone()));
+        let query = CommandGetTables {
+            catalog: None,
+            db_schema_filter_pattern: None,
+            table_name_filter_pattern: Some("app%".to_owned()),
+            table_types: vec![],
+            include_schema: false,
+        };
+
+        let batch = tables(query, streams).unwrap();
+        let names = batch
+            .column_by_name("table_name")
+            .unwrap()
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["app", "app_logs"]);
+    }
+
+    #[tokio::test]
+    async fn streams_denied_by_acl_are_not_queried_over_flight() {
+        use crate::{
+            metadata::StreamSettings,
+            rbac::{map::mut_roles, role::model::DefaultPrivilege, user::User},
+        };
+
+        rbac::map::init_for_tests();
+        let granted = "flight_acl_granted";
+        let denied = "flight_acl_denied";
+        let username = "flight_acl_reader";
+        // the role of the user grants both streams, the ACL of one denies the user
+        mut_roles().insert(
+            username.to_owned(),
+            [granted, denied]
+                .map(|stream| DefaultPrivilege::Reader {
+                    stream: stream.to_owned(),
+                    tag: None,
+                })
+                .to_vec(),
+        );
+        for stream in [granted, denied] {
+            PARSEABLE.get_or_create_stream(stream);
+        }
+        let stream = PARSEABLE.get_stream(denied).unwrap();
+        stream.set_settings(StreamSettings {
+            acl: [(username.to_owned(), vec![])].into_iter().collect(),
+            ..stream.get_settings().as_ref().clone()
+        });
+        let (mut user, _) = User::new_basic(username.to_owned());
+        user.roles.insert(username.to_owned());
+        Users.put_user(user.clone());
+        let session = Ulid::new();
+        Users.new_session(&user, SessionKey::SessionId(session));
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(
+            "authorization",
+            format!("Bearer {session}").parse().unwrap(),
+        );
+        assert!(authorize(&metadata, &[granted.to_owned()]).is_ok());
+        assert!(authorize(&metadata, &[denied.to_owned()]).is_err());
+
+        let streams = queryable_streams(&SessionKey::SessionId(session));
+        assert!(streams.iter().any(|stream| stream == granted));
+        assert!(!streams.iter().any(|stream| stream == denied));
+    }
+}
@@ -107,3 +107,37 @@ pub async fn about() -> Json<Value> {
         },
     }))
 }
+
+/// {
+///     "version": current_version,
+///     "commit": commit,
+///     "arrow": arrow_version,
+///     "parquet": parquet_version,
+///     "datafusion": datafusion_version
+/// }
+pub async fn build_info() -> Json<Value> {
+    let current_release = about::current();
+
+    Json(json!({
+        "version": format!("v{}", current_release.released_version),
+        "commit": current_release.commit_hash,
+        "arrow": about::ARROW_VERSION,
+        "parquet": about::PARQUET_VERSION,
+        "datafusion": about::DATAFUSION_VERSION,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_info;
+
+    #[actix_web::test]
+    async fn build_info_reports_arrow_version() {
+        let info = build_info().await.into_inner();
+
+        let arrow = info["arrow"].as_str().unwrap();
+        assert!(!arrow.is_empty());
+        assert_ne!(arrow, "unknown");
+        assert!(info["datafusion"].as_str().is_some_and(|v| !v.is_empty()));
+    }
+}
@@ -0,0 +1,470 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{collections::HashSet, sync::Arc};
+
+use actix_web::{
+    web::{Json, Query},
+    Responder,
+};
+use arrow_schema::Schema;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event,
+    option::Mode,
+    parseable::{StreamNotFound, PARSEABLE},
+    stats::{self, FullStats},
+    storage::{ObjectStoreFormat, StreamType},
+};
+
+use super::{
+    cluster::sync_stream_settings_with_ingestors,
+    logstream::{error::StreamError, validate_settings},
+};
+
+/// Schema and configuration of every stream, without any of the data
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetadataBundle {
+    pub streams: Vec<StreamBundle>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamBundle {
+    pub name: String,
+    pub schema: Schema,
+    pub format: ObjectStoreFormat,
+}
+
+impl StreamBundle {
+    fn new(name: String, schema: Schema, format: ObjectStoreFormat) -> Self {
+        // stats and snapshots describe data, which isn't part of the bundle
+        let format = ObjectStoreFormat {
+            stats: FullStats::default(),
+            snapshot: Default::default(),
+            first_event_at: None,
+            ..format
+        };
+
+        Self {
+            name,
+            schema,
+            format,
+        }
+    }
+}
+
+/// How to handle streams in the bundle that already exist on the server
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Merges the imported schema into that of the existing stream, keeping its configuration
+    #[default]
+    Merge,
+    /// Merges the imported schema and replaces the configuration of the existing stream
+    Overwrite,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportParams {
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Create,
+    Merge,
+    Overwrite,
+}
+
+/// Decides what to do with each stream in the bundle, given the streams that already exist
+fn plan_import<'a>(
+    bundle: &'a MetadataBundle,
+    existing: &HashSet<String>,
+    on_conflict: OnConflict,
+) -> Vec<(&'a StreamBundle, ImportAction)> {
+    bundle
+        .streams
+        .iter()
+        .filter(|stream| stream.format.stream_type != StreamType::Internal)
+        .map(|stream| {
+            let action = match (existing.contains(&stream.name), on_conflict) {
+                (false, _) => ImportAction::Create,
+                (true, OnConflict::Merge) => ImportAction::Merge,
+                (true, OnConflict::Overwrite) => ImportAction::Overwrite,
+            };
+            (stream, action)
+        })
+        .collect()
+}
+
+/// Whether events were ever ingested into the stream, as recorded in storage or in the metrics of
+/// those yet to be synced to it
+fn has_events(name: &str, format: &ObjectStoreFormat) -> bool {
+    format.first_event_at.is_some()
+        || format.stats.lifetime_stats.events > 0
+        || !format.snapshot.manifest_list.is_empty()
+        || stats::get_current_stats(name, "json")
+            .is_some_and(|stats| stats.lifetime_stats.events > 0)
+}
+
+/// Checks that the configuration of an existing stream can be overwritten by the imported one.
+/// Partitions and type of a stream with events can't change, its data is laid out by them
+async fn validate_overwrite(
+    name: &str,
+    current: &ObjectStoreFormat,
+    imported: &ObjectStoreFormat,
+) -> Result<(), StreamError> {
+    if has_events(name, current) {
+        let changed = [
+            (
+                "time partition",
+                current.time_partition != imported.time_partition,
+            ),
+            (
+                "time partition limit",
+                current.time_partition_limit != imported.time_partition_limit,
+            ),
+            (
+                "custom partition",
+                current.custom_partition != imported.custom_partition,
+            ),
+            ("type", current.stream_type != imported.stream_type),
+        ]
+        .into_iter()
+        .find_map(|(property, changed)| changed.then_some(property));
+        if let Some(property) = changed {
+            return Err(StreamError::Custom {
+                msg: format!(
+                    "{property} of log stream {name} can't be overwritten as it has events"
+                ),
+                status: StatusCode::BAD_REQUEST,
+            });
+        }
+    }
+
+    if !PARSEABLE.check_or_load_stream(name).await {
+        return Err(StreamNotFound(name.to_owned()).into());
+    }
+    let settings = PARSEABLE.get_stream(name)?.get_settings();
+    validate_settings(name, &settings, &imported.settings).await
+}
+
+// GET "/metadata/export" ==> Export the schema and configuration of all streams
+pub async fn export_metadata() -> Result<impl Responder, StreamError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    let mut streams = vec![];
+    for name in storage.list_streams().await? {
+        let schema = storage.get_schema(&name).await?;
+        let format = storage.get_object_store_format(&name).await?;
+        streams.push(StreamBundle::new(name, schema, format));
+    }
+    streams.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok((Json(MetadataBundle { streams }), StatusCode::OK))
+}
+
+// POST "/metadata/import" ==> Recreate streams from an exported bundle, without any data
+// Schemas of existing streams are always merged, as data already ingested relies on them
+pub async fn import_metadata(
+    params: Query<ImportParams>,
+    Json(bundle): Json<MetadataBundle>,
+) -> Result<impl Responder, StreamError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    let existing = storage.list_streams().await?;
+
+    let plan = plan_import(&bundle, &existing, params.on_conflict);
+    // nothing is imported unless every stream to overwrite can be
+    for (stream, _) in plan
+        .iter()
+        .filter(|(_, action)| *action == ImportAction::Overwrite)
+    {
+        let current = storage.get_object_store_format(&stream.name).await?;
+        validate_overwrite(&stream.name, &current, &stream.format).await?;
+    }
+
+    let mut imported = vec![];
+    for (stream, action) in plan {
+        let StreamBundle {
+            name,
+            schema,
+            format,
+        } = stream;
+        match action {
+            ImportAction::Create => {
+                PARSEABLE
+                    .create_stream(
+                        name.clone(),
+                        format.time_partition.as_deref().unwrap_or_default(),
+                        format
+                            .time_partition_limit
+                            .as_ref()
+                            .and_then(|limit| limit.parse().ok()),
+                        format.custom_partition.as_ref(),
+                        format.static_schema_flag,
+                        Arc::new(schema.clone()),
+                        format.stream_type,
+                        format.log_source.clone(),
+                    )
+                    .await?;
+                let created = storage.get_object_store_format(name).await?;
+                storage
+                    .put_stream_manifest(
+                        name,
+                        &ObjectStoreFormat {
+                            created_at: created.created_at,
+                            owner: created.owner,
+                            permissions: created.permissions,
+                            ..format.clone()
+                        },
+                    )
+                    .await?;
+            }
+            ImportAction::Merge | ImportAction::Overwrite => {
+                let current = storage.get_schema(name).await?;
+                let merged = Schema::try_merge(vec![current, schema.clone()])
+                    .map_err(|err| StreamError::Anyhow(err.into()))?;
+                storage.put_schema(name, &merged).await?;
+                if PARSEABLE.streams.contains(name) {
+                    event::commit_schema(name, Arc::new(merged))
+                        .map_err(|err| StreamError::Anyhow(err.into()))?;
+                }
+
+                if action == ImportAction::Overwrite {
+                    let current = storage.get_object_store_format(name).await?;
+                    storage
+                        .put_stream_manifest(
+                            name,
+                            &ObjectStoreFormat {
+                                created_at: current.created_at,
+                                first_event_at: current.first_event_at,
+                                stats: current.stats,
+                                snapshot: current.snapshot,
+                                ..format.clone()
+                            },
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        // load the imported configuration into memory, merged streams keep theirs
+        if let (Ok(stream), ImportAction::Create | ImportAction::Overwrite) =
+            (PARSEABLE.get_stream(name), action)
+        {
+            if let Some(retention) = &format.retention {
+                stream.set_retention(retention.clone());
+            }
+            stream.set_settings(format.settings.clone());
+            if PARSEABLE.options.mode == Mode::Query {
+                sync_stream_settings_with_ingestors(name, &format.settings).await?;
+            }
+        }
+        imported.push((name.clone(), action));
+    }
+
+    Ok((Json(imported), StatusCode::OK))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::{DataType, Field};
+
+    use super::*;
+
+    fn bundle() -> MetadataBundle {
+        let schema = Schema::new(vec![Field::new("level", DataType::Utf8, true)]);
+        let streams = ["app", "audit"]
+            .into_iter()
+            .map(|name| {
+                StreamBundle::new(
+                    name.to_owned(),
+                    schema.clone(),
+                    ObjectStoreFormat {
+                        custom_partition: Some("level".to_owned()),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+
+        MetadataBundle { streams }
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let exported = serde_json::to_vec(&bundle()).unwrap();
+        let bundle: MetadataBundle = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(bundle.streams.len(), 2);
+        assert_eq!(
+            bundle.streams[1].format.custom_partition.as_deref(),
+            Some("level")
+        );
+
+        // fresh server, both streams are created
+        let plan = plan_import(&bundle, &HashSet::new(), OnConflict::Merge);
+        assert!(plan
+            .iter()
+            .all(|(_, action)| *action == ImportAction::Create));
+
+        // conflicting stream is handled as per the flag
+        let existing = HashSet::from(["app".to_owned()]);
+        let plan = plan_import(&bundle, &existing, OnConflict::Overwrite);
+        assert_eq!(plan[0].0.name, "app");
+        assert_eq!(plan[0].1, ImportAction::Overwrite);
+        assert_eq!(plan[1].1, ImportAction::Create);
+        let plan = plan_import(&bundle, &existing, OnConflict::Merge);
+        assert_eq!(plan[0].1, ImportAction::Merge);
+    }
+
+    #[actix_web::test]
+    async fn exported_streams_are_recreated_by_import() {
+        use actix_web::{
+            body::to_bytes,
+            http::header::{HeaderMap, HeaderName, HeaderValue},
+            test::TestRequest,
+            web::Path,
+        };
+
+        use crate::handlers::http::logstream::delete;
+
+        let names = ["backup_app", "backup_audit"];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-p-static-schema-flag"),
+            HeaderValue::from_static("true"),
+        );
+        headers.insert(
+            HeaderName::from_static("x-p-custom-partition"),
+            HeaderValue::from_static("level"),
+        );
+        let body = serde_json::json!({
+            "fields": [
+                {"name": "level", "data_type": "string"},
+                {"name": "latency", "data_type": "float"}
+            ]
+        });
+        for name in names {
+            PARSEABLE
+                .create_update_stream(&headers, &body.to_string().into(), name)
+                .await
+                .unwrap();
+        }
+
+        let req = TestRequest::default().to_http_request();
+        let res = export_metadata().await.unwrap().respond_to(&req);
+        let mut bundle: MetadataBundle =
+            serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap();
+        // streams of other tests are in the store too
+        bundle
+            .streams
+            .retain(|stream| names.contains(&stream.name.as_str()));
+        assert_eq!(bundle.streams.len(), 2);
+        let exported = bundle
+            .streams
+            .iter()
+            .map(|stream| stream.schema.clone())
+            .collect::<Vec<_>>();
+
+        // imported into a server that has none of the streams
+        for name in names {
+            delete(Path::from(name.to_owned())).await.unwrap();
+            assert!(!PARSEABLE.streams.contains(name));
+        }
+        let res = import_metadata(Query(ImportParams::default()), Json(bundle))
+            .await
+            .unwrap()
+            .respond_to(&req);
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let storage = PARSEABLE.storage.get_object_store();
+        for (name, exported) in names.into_iter().zip(exported) {
+            let stream = PARSEABLE.get_stream(name).unwrap();
+            assert_eq!(stream.get_custom_partition().as_deref(), Some("level"));
+            assert!(stream.get_static_schema_flag());
+            let imported = storage.get_schema(name).await.unwrap();
+            for field in exported.fields() {
+                assert_eq!(
+                    imported.field_with_name(field.name()).unwrap().data_type(),
+                    field.data_type()
+                );
+            }
+            assert!(stream.get_schema().field_with_name("latency").is_ok());
+        }
+    }
+
+    #[actix_web::test]
+    async fn overwrite_is_refused_for_partition_changes_and_invalid_settings() {
+        use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+
+        let name = "backup_overwrite";
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-p-custom-partition"),
+            HeaderValue::from_static("level"),
+        );
+        PARSEABLE
+            .create_update_stream(&headers, &Default::default(), name)
+            .await
+            .unwrap();
+        let storage = PARSEABLE.storage.get_object_store();
+        let current = storage.get_object_store_format(name).await.unwrap();
+        let import = |format: ObjectStoreFormat| {
+            import_metadata(
+                Query(ImportParams {
+                    on_conflict: OnConflict::Overwrite,
+                }),
+                Json(MetadataBundle {
+                    streams: vec![StreamBundle::new(name.to_owned(), Schema::empty(), format)],
+                }),
+            )
+        };
+
+        // settings are validated as if they were updated through the settings resource
+        let mut format = current.clone();
+        format.settings.parquet_target_size = Some(0);
+        assert!(import(format).await.is_err());
+
+        // partitions can change until the stream has events, not after
+        let mut format = current.clone();
+        format.custom_partition = Some("region".to_owned());
+        storage
+            .put_stream_manifest(
+                name,
+                &ObjectStoreFormat {
+                    first_event_at: Some("2025-01-01T00:00:00Z".to_owned()),
+                    ..current.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(import(format.clone()).await.is_err());
+        let stored = storage.get_object_store_format(name).await.unwrap();
+        assert_eq!(stored.custom_partition.as_deref(), Some("level"));
+        assert_eq!(stored.settings.parquet_target_size, None);
+
+        storage.put_stream_manifest(name, &current).await.unwrap();
+        assert!(import(format).await.is_ok());
+        let stored = storage.get_object_store_format(name).await.unwrap();
+        assert_eq!(stored.custom_partition.as_deref(), Some("region"));
+    }
+}
@@ -41,6 +41,7 @@ use url::Url;
 use utils::{check_liveness, to_url_string, IngestionStats, QueriedStats, StorageStats};
 
 use crate::handlers::http::ingest::ingest_internal_stream;
+use crate::metadata::StreamSettings;
 use crate::metrics::prom_utils::Metrics;
 use crate::parseable::PARSEABLE;
 use crate::rbac::role::model::DefaultPrivilege;
@@ -50,6 +51,7 @@ use crate::storage::{
     ObjectStorage, ObjectStorageError, ObjectStoreFormat, PARSEABLE_ROOT_DIRECTORY,
     STREAM_ROOT_DIRECTORY,
 };
+use crate::utils::time::TimeRange;
 use crate::INTRA_CLUSTER_CLIENT;
 
 use super::base_path_without_preceding_slash;
@@ -60,6 +62,7 @@ use super::modal::{
 };
 use super::rbac::RBACError;
 use super::role::RoleError;
+use super::settings::{SettingsError, SyncSettings};
 
 pub const INTERNAL_STREAM_NAME: &str = "pmeta";
 
@@ -156,6 +159,145 @@ pub async fn sync_streams_with_ingestors(
     ).await
 }
 
+// forward the settings of a stream to all ingestors, they are applied as events are ingested
+pub async fn sync_stream_settings_with_ingestors(
+    stream_name: &str,
+    settings: &StreamSettings,
+) -> Result<(), StreamError> {
+    let body: Bytes = to_vec(settings)?.into();
+    let stream_name = stream_name.to_string();
+
+    for_each_live_ingestor(move |ingestor| {
+        let url = format!(
+            "{}{}/logstream/{}/settings/sync",
+            ingestor.domain_name,
+            base_path_without_preceding_slash(),
+            stream_name
+        );
+        let body = body.clone();
+        async move {
+            let res = INTRA_CLUSTER_CLIENT
+                .put(url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::AUTHORIZATION, &ingestor.token)
+                .body(body)
+                .send()
+                .await
+                .map_err(|err| {
+                    error!(
+                        "Fatal: failed to forward stream settings to ingestor: {}\n Error: {:?}",
+                        ingestor.domain_name, err
+                    );
+                    StreamError::Network(err)
+                })?;
+
+            if !res.status().is_success() {
+                error!(
+                    "failed to forward stream settings to ingestor: {}\nResponse Returned: {:?}",
+                    ingestor.domain_name,
+                    res.text().await
+                );
+            }
+            Ok(())
+        }
+    })
+    .await
+}
+
+// forward the deletion of the data of a stream within a time range to all ingestors, each drops
+// the files from its own snapshot
+pub async fn sync_range_deletion_with_ingestors(
+    stream_name: &str,
+    time_range: &TimeRange,
+) -> Result<(), ObjectStorageError> {
+    let stream_name = stream_name.to_string();
+    let query = [
+        ("startTime", time_range.start.to_rfc3339()),
+        ("endTime", time_range.end.to_rfc3339()),
+    ];
+
+    for_each_live_ingestor(move |ingestor| {
+        let url = format!(
+            "{}{}/logstream/{}/data",
+            ingestor.domain_name,
+            base_path_without_preceding_slash(),
+            stream_name
+        );
+        let query = query.clone();
+        async move {
+            let res = INTRA_CLUSTER_CLIENT
+                .delete(url)
+                .query(&query)
+                .header(header::AUTHORIZATION, &ingestor.token)
+                .send()
+                .await
+                .map_err(|err| {
+                    error!(
+                        "Fatal: failed to forward data deletion to ingestor: {}\n Error: {:?}",
+                        ingestor.domain_name, err
+                    );
+                    ObjectStorageError::Custom(err.to_string())
+                })?;
+
+            // files left in the snapshot of the ingestor would be read after being deleted
+            if !res.status().is_success() {
+                let status = res.status();
+                error!(
+                    "failed to forward data deletion to ingestor: {}\nResponse Returned: {:?}",
+                    ingestor.domain_name,
+                    res.text().await
+                );
+                return Err(ObjectStorageError::Custom(format!(
+                    "ingestor {} failed to delete data with status {status}",
+                    ingestor.domain_name
+                )));
+            }
+            Ok(())
+        }
+    })
+    .await
+}
+
+// forward the sync settings to all ingestors, it is them that flush and convert the arrows
+pub async fn sync_settings_with_ingestors(settings: &SyncSettings) -> Result<(), SettingsError> {
+    let body: Bytes = to_vec(settings)?.into();
+
+    for_each_live_ingestor(move |ingestor| {
+        let url = format!(
+            "{}{}/settings/sync",
+            ingestor.domain_name,
+            base_path_without_preceding_slash(),
+        );
+        let body = body.clone();
+        async move {
+            let res = INTRA_CLUSTER_CLIENT
+                .put(url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::AUTHORIZATION, &ingestor.token)
+                .body(body)
+                .send()
+                .await
+                .map_err(|err| {
+                    error!(
+                        "Fatal: failed to forward sync settings to ingestor: {}\n Error: {:?}",
+                        ingestor.domain_name, err
+                    );
+                    SettingsError::Network(err)
+                })?;
+
+            if !res.status().is_success() {
+                error!(
+                    "failed to forward sync settings to ingestor: {}\nResponse Returned: {:?}",
+                    ingestor.domain_name,
+                    res.text().await
+                );
+            }
+            Ok(())
+        }
+    })
+    .await
+}
+
 // forward the role update request to all ingestors to keep them in sync
 pub async fn sync_users_with_roles_with_ingestors(
     username: &str,
@@ -21,9 +21,8 @@ use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use anyhow::Error;
 use itertools::Itertools;
 
-use crate::rbac::Users;
 use crate::utils::actix::extract_session_key_from_req;
-use crate::utils::{get_hash, get_user_from_request, user_auth_for_datasets};
+use crate::utils::{get_hash, get_user_from_request, session_auth_for_datasets};
 
 use crate::correlation::{CorrelationConfig, CorrelationError, CORRELATIONS};
 
@@ -46,15 +45,13 @@ pub async fn get(
 
     let correlation = CORRELATIONS.get_correlation(&correlation_id).await?;
 
-    let permissions = Users.get_permissions(&session_key);
-
     let tables = &correlation
         .table_configs
         .iter()
         .map(|t| t.table_name.clone())
         .collect_vec();
 
-    user_auth_for_datasets(&permissions, tables)?;
+    session_auth_for_datasets(&session_key, tables)?;
 
     Ok(web::Json(correlation))
 }
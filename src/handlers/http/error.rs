@@ -0,0 +1,63 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use actix_web::{http::StatusCode, HttpResponse};
+use datafusion::error::DataFusionError;
+use serde::Serialize;
+
+/// Body of the error responses of the query and ingestion APIs. `code` identifies the kind of
+/// error for clients to match on and doesn't change across releases, `message` is meant for humans
+#[derive(Debug, Serialize)]
+pub struct ErrorEnvelope {
+    pub code: &'static str,
+    pub message: String,
+    /// The underlying cause, when the error is caused by another one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ErrorEnvelope {
+    pub fn new(code: &'static str, error: &dyn std::error::Error) -> Self {
+        let message = error.to_string();
+        let detail = std::iter::successors(error.source(), |cause| cause.source())
+            .last()
+            .map(|cause| cause.to_string())
+            .filter(|detail| !message.ends_with(detail.as_str()));
+
+        Self {
+            code,
+            message,
+            detail,
+        }
+    }
+
+    pub fn into_response(self, status: StatusCode) -> HttpResponse {
+        HttpResponse::build(status).json(self)
+    }
+}
+
+/// Code of an error raised by datafusion, as per the stage of the query it failed in
+pub fn datafusion_error_code(err: &DataFusionError) -> &'static str {
+    match err.find_root() {
+        DataFusionError::SQL(..) => "sql_parse_error",
+        DataFusionError::Plan(_) | DataFusionError::SchemaError(..) => "query_plan_error",
+        DataFusionError::NotImplemented(_) => "query_not_supported",
+        DataFusionError::ResourcesExhausted(_) => "query_resources_exhausted",
+        _ => "query_execution_error",
+    }
+}
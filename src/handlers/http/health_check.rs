@@ -28,10 +28,10 @@ use actix_web::{
 };
 use http::StatusCode;
 use once_cell::sync::Lazy;
-use tokio::{sync::Mutex, task::JoinSet};
-use tracing::{error, info, warn};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
 
-use crate::parseable::PARSEABLE;
+use crate::parseable::{drain_and_flush, PARSEABLE};
 
 // Create a global variable to store signal status
 static SIGNAL_RECEIVED: Lazy<Arc<Mutex<bool>>> = Lazy::new(|| Arc::new(Mutex::new(false)));
@@ -60,18 +60,13 @@ pub async fn shutdown() {
     let mut shutdown_flag = SIGNAL_RECEIVED.lock().await;
     *shutdown_flag = true;
 
-    let mut joinset = JoinSet::new();
-
-    // Sync staging
-    PARSEABLE.streams.flush_and_convert(&mut joinset, true);
-
-    while let Some(res) = joinset.join_next().await {
-        match res {
-            Ok(Ok(_)) => info!("Successfully converted arrow files to parquet."),
-            Ok(Err(err)) => warn!("Failed to convert arrow files to parquet. {err:?}"),
-            Err(err) => error!("Failed to join async task: {err}"),
-        }
-    }
+    // Sync staging, once events already being processed are in it
+    drain_and_flush(
+        &PARSEABLE.ingest_gate,
+        &PARSEABLE.streams,
+        PARSEABLE.options.shutdown_drain_timeout,
+    )
+    .await;
 
     if let Err(e) = PARSEABLE
         .storage
@@ -16,43 +16,63 @@
  *
  */
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::future::Future;
 
-use actix_web::web::{Json, Path};
-use actix_web::{http::header::ContentType, HttpRequest, HttpResponse};
+use actix_web::web::{self, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
 use arrow_array::RecordBatch;
 use bytes::Bytes;
 use chrono::Utc;
+use futures::{stream, Stream, StreamExt};
 use http::StatusCode;
-use serde_json::Value;
+use serde_json::{json, Value};
 
+use crate::event::column_limit::ColumnLimitExceeded;
 use crate::event::error::EventError;
 use crate::event::format::known_schema::{self, KNOWN_SCHEMA_LIST};
+use crate::event::format::msgpack::{self, MsgpackError};
+use crate::event::format::protobuf::ProtobufError;
 use crate::event::format::{self, EventFormat, LogSource, LogSourceEntry};
 use crate::event::{self, FORMAT_KEY, USER_AGENT_KEY};
+use crate::handlers::http::error::ErrorEnvelope;
 use crate::handlers::{EXTRACT_LOG_KEY, LOG_SOURCE_KEY, STREAM_NAME_HEADER_KEY};
 use crate::metadata::SchemaVersion;
 use crate::option::Mode;
 use crate::otel::logs::OTEL_LOG_KNOWN_FIELD_LIST;
 use crate::otel::metrics::OTEL_METRICS_KNOWN_FIELD_LIST;
 use crate::otel::traces::OTEL_TRACES_KNOWN_FIELD_LIST;
-use crate::parseable::{StreamNotFound, PARSEABLE};
+use crate::parseable::{SchemaDriftError, StagingError, StreamNotFound, PARSEABLE};
+use crate::rbac::{self, role::Action, Users};
 use crate::storage::{ObjectStorageError, StreamType};
+use crate::utils::actix::extract_session_key_from_req;
 use crate::utils::header_parsing::ParseHeaderError;
 use crate::utils::json::flatten::JsonFlattenError;
 
 use super::logstream::error::{CreateStreamError, StreamError};
-use super::modal::utils::ingest_utils::{flatten_and_push_logs, get_custom_fields_from_header};
+use super::modal::utils::ingest_utils::{
+    flatten_and_push_each_log, flatten_and_push_logs, get_custom_fields_from_header,
+    is_partial_success_requested, Batch,
+};
 use super::users::dashboards::DashboardError;
 use super::users::filters::FiltersError;
+use super::MAX_EVENT_PAYLOAD_SIZE;
 
 // Handler for POST /api/v1/ingest
 // ingests events by extracting stream name from header
 // creates if stream does not exist
-pub async fn ingest(
-    req: HttpRequest,
-    Json(mut json): Json<Value>,
-) -> Result<HttpResponse, PostError> {
+pub async fn ingest(req: HttpRequest, Json(json): Json<Value>) -> Result<HttpResponse, PostError> {
+    ingest_records(req, Batch::from(json)).await
+}
+
+// Handler for POST /api/v1/ingest with a newline delimited JSON body
+// each line is an event, malformed lines are reported by their number
+pub async fn ingest_ndjson(req: HttpRequest, body: Bytes) -> Result<HttpResponse, PostError> {
+    ingest_records(req, Batch::from_ndjson(&body)).await
+}
+
+async fn ingest_records(req: HttpRequest, mut batch: Batch) -> Result<HttpResponse, PostError> {
     let Some(stream_name) = req.headers().get(STREAM_NAME_HEADER_KEY) else {
         return Err(PostError::Header(ParseHeaderError::MissingStreamName));
     };
@@ -81,11 +101,17 @@ pub async fn ingest(
         return Err(PostError::OtelNotSupported);
     }
 
+    // unless asked to ingest the rest, the batch fails on any malformed line
+    let partial_success = is_partial_success_requested(&req) && log_source != LogSource::Kinesis;
+    if !partial_success {
+        batch.check_parsed()?;
+    }
+
     let mut p_custom_fields = get_custom_fields_from_header(&req);
 
     let fields = match &log_source {
         LogSource::Custom(src) => KNOWN_SCHEMA_LIST.extract_from_inline_log(
-            &mut json,
+            &mut batch.json,
             &mut p_custom_fields,
             src,
             extract_log,
@@ -120,11 +146,232 @@ pub async fn ingest(
         .add_update_log_source(&stream_name, log_source_entry)
         .await?;
 
-    flatten_and_push_logs(json, &stream_name, &log_source, &p_custom_fields).await?;
+    if partial_success {
+        let summary =
+            flatten_and_push_each_log(batch, &stream_name, &log_source, &p_custom_fields).await?;
+        return Ok(HttpResponse::Ok().json(summary));
+    }
+
+    flatten_and_push_logs(batch.json, &stream_name, &log_source, &p_custom_fields).await?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+// Handler for POST /api/v1/ingest/batch
+// ingests a batch of events destined for different streams, routing each event to the stream
+// named in its selector field, creates streams that do not exist
+// the route isn't wrapped in an authorization layer, as the streams are only known from the body
+pub async fn ingest_batch(
+    req: HttpRequest,
+    Json(json): Json<Value>,
+) -> Result<HttpResponse, PostError> {
+    let key = extract_session_key_from_req(&req).map_err(|_| PostError::Unauthenticated)?;
+    let batches = route_by_stream(json, &PARSEABLE.options.stream_selector_field)?;
+
+    // every stream of the batch is checked before any is ingested into, events ingested into
+    // a stream are kept even if ingesting into a later stream of the batch fails
+    let internal_stream_names = PARSEABLE.streams.list_internal_streams();
+    for stream_name in batches.keys() {
+        if internal_stream_names.contains(stream_name) {
+            return Err(PostError::InternalStream(stream_name.clone()));
+        }
+        if Users.authorize(key.clone(), Action::Ingest, Some(stream_name), None)
+            != rbac::Response::Authorized
+        {
+            return Err(PostError::Unauthorized(stream_name.clone()));
+        }
+    }
+
+    let log_source = LogSource::default();
+    let log_source_entry = LogSourceEntry::new(log_source.clone(), HashSet::new());
+    let p_custom_fields = get_custom_fields_from_header(&req);
+    for (stream_name, records) in batches {
+        PARSEABLE
+            .create_stream_if_not_exists(
+                &stream_name,
+                StreamType::UserDefined,
+                vec![log_source_entry.clone()],
+            )
+            .await?;
+        PARSEABLE
+            .add_update_log_source(&stream_name, log_source_entry.clone())
+            .await?;
+        flatten_and_push_logs(
+            Value::Array(records),
+            &stream_name,
+            &log_source,
+            &p_custom_fields,
+        )
+        .await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Groups the events of a batch by the stream named in their `field`, which is removed from them
+fn route_by_stream(json: Value, field: &str) -> Result<BTreeMap<String, Vec<Value>>, PostError> {
+    let records = match json {
+        Value::Array(arr) => arr,
+        value => vec![value],
+    };
+
+    let mut batches: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for mut record in records {
+        let stream_name = match record.as_object_mut().and_then(|obj| obj.remove(field)) {
+            Some(Value::String(stream_name)) if !stream_name.is_empty() => stream_name,
+            _ => return Err(PostError::MissingStreamSelector(field.to_owned())),
+        };
+        batches.entry(stream_name).or_default().push(record);
+    }
+
+    Ok(batches)
+}
+
+// Handler for POST /api/v1/ingest/stream
+// ingests newline delimited JSON events from a long-lived, chunked body as they arrive, into the
+// stream in header, creates stream if it does not exist. Progress is acked in the response body,
+// which is streamed back while the request body is still being read
+pub async fn ingest_stream(
+    req: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, PostError> {
+    let Some(stream_name) = req.headers().get(STREAM_NAME_HEADER_KEY) else {
+        return Err(PostError::Header(ParseHeaderError::MissingStreamName));
+    };
+
+    let stream_name = stream_name.to_str().unwrap().to_owned();
+    let internal_stream_names = PARSEABLE.streams.list_internal_streams();
+    if internal_stream_names.contains(&stream_name) {
+        return Err(PostError::InternalStream(stream_name));
+    }
+
+    let log_source_entry = LogSourceEntry::new(LogSource::default(), HashSet::new());
+    PARSEABLE
+        .create_stream_if_not_exists(
+            &stream_name,
+            StreamType::UserDefined,
+            vec![log_source_entry.clone()],
+        )
+        .await?;
+    PARSEABLE
+        .add_update_log_source(&stream_name, log_source_entry)
+        .await?;
+
+    let p_custom_fields = get_custom_fields_from_header(&req);
+    let acks = ingest_chunks(payload, move |events| {
+        let stream_name = stream_name.clone();
+        let p_custom_fields = p_custom_fields.clone();
+        async move {
+            flatten_and_push_logs(
+                Value::Array(events),
+                &stream_name,
+                &LogSource::default(),
+                &p_custom_fields,
+            )
+            .await
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(acks))
+}
+
+/// Splits a body of newline delimited JSON into events, as its chunks arrive
+#[derive(Debug, Default)]
+struct NdjsonLines {
+    partial: Vec<u8>,
+}
+
+impl NdjsonLines {
+    /// Events of the lines completed by `chunk`, the rest is held on to until the next chunk
+    fn push(&mut self, chunk: &[u8]) -> Result<Vec<Value>, PostError> {
+        self.partial.extend_from_slice(chunk);
+        let Some(end) = self.partial.iter().rposition(|&byte| byte == b'\n') else {
+            if self.partial.len() > MAX_EVENT_PAYLOAD_SIZE {
+                return Err(PostError::CustomError(format!(
+                    "event is larger than {MAX_EVENT_PAYLOAD_SIZE} bytes"
+                )));
+            }
+            return Ok(vec![]);
+        };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+
+        parse_lines(&complete)
+    }
+
+    /// Event of the last line, when the body doesn't end with a newline
+    fn finish(self) -> Result<Vec<Value>, PostError> {
+        parse_lines(&self.partial)
+    }
+}
+
+fn parse_lines(bytes: &[u8]) -> Result<Vec<Value>, PostError> {
+    bytes
+        .split(|&byte| byte == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).map_err(PostError::from))
+        .collect()
+}
+
+/// Ingests the events of an NDJSON body with `ingest` as its chunks arrive. Each batch ingested is
+/// acked with a line `{"ingested": <events ingested so far>}`, the stream ends at the first error,
+/// acked with a line `{"error": "..", "ingested": ..}`.
+fn ingest_chunks<S, E, F, Fut>(
+    chunks: S,
+    ingest: F,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Display,
+    F: FnMut(Vec<Value>) -> Fut,
+    Fut: Future<Output = Result<(), PostError>>,
+{
+    let ack =
+        |ack: Value| -> Result<Bytes, actix_web::Error> { Ok(Bytes::from(format!("{ack}\n"))) };
+    let state = (Box::pin(chunks), ingest, NdjsonLines::default(), 0);
+    stream::unfold(Some(state), move |state| async move {
+        let (mut chunks, mut ingest, mut lines, mut ingested) = state?;
+        loop {
+            let (events, done) = match chunks.next().await {
+                Some(Ok(chunk)) => (lines.push(&chunk), false),
+                Some(Err(err)) => (Err(PostError::CustomError(err.to_string())), true),
+                None => (std::mem::take(&mut lines).finish(), true),
+            };
+            let events = match events {
+                Ok(events) if events.is_empty() && !done => continue,
+                Ok(events) => events,
+                Err(err) => {
+                    let error = json!({"error": err.to_string(), "ingested": ingested});
+                    return Some((ack(error), None));
+                }
+            };
+
+            let count = events.len();
+            if count > 0 {
+                if let Err(err) = ingest(events).await {
+                    let error = json!({"error": err.to_string(), "ingested": ingested});
+                    return Some((ack(error), None));
+                }
+            }
+            ingested += count;
+
+            let next = (!done).then_some((chunks, ingest, lines, ingested));
+            return Some((ack(json!({"ingested": ingested})), next));
+        }
+    })
+}
+
+// Handler for POST /api/v1/ingest with a msgpack body
+// decodes the body into json and ingests it like a json event
+pub async fn ingest_msgpack(req: HttpRequest, body: Bytes) -> Result<HttpResponse, PostError> {
+    let json = msgpack::decode(&body)?;
+
+    ingest(req, Json(json)).await
+}
+
 pub async fn ingest_internal_stream(stream_name: String, body: Bytes) -> Result<(), PostError> {
     let size: usize = body.len();
     let json: Value = serde_json::from_slice(&body)?;
@@ -333,10 +580,26 @@ pub async fn handle_otel_traces_ingestion(
 pub async fn post_event(
     req: HttpRequest,
     stream_name: Path<String>,
-    Json(mut json): Json<Value>,
+    Json(json): Json<Value>,
 ) -> Result<HttpResponse, PostError> {
-    let stream_name = stream_name.into_inner();
+    post_records(req, stream_name.into_inner(), Batch::from(json)).await
+}
+
+// Handler for POST /api/v1/logstream/{logstream} with a newline delimited JSON body
+// each line is an event, malformed lines are reported by their number
+pub async fn post_ndjson_event(
+    req: HttpRequest,
+    stream_name: Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, PostError> {
+    post_records(req, stream_name.into_inner(), Batch::from_ndjson(&body)).await
+}
 
+async fn post_records(
+    req: HttpRequest,
+    stream_name: String,
+    mut batch: Batch,
+) -> Result<HttpResponse, PostError> {
     let internal_stream_names = PARSEABLE.streams.list_internal_streams();
     if internal_stream_names.contains(&stream_name) {
         return Err(PostError::InternalStream(stream_name));
@@ -368,6 +631,13 @@ pub async fn post_event(
         .headers()
         .get(EXTRACT_LOG_KEY)
         .and_then(|h| h.to_str().ok());
+
+    // unless asked to ingest the rest, the batch fails on any malformed line
+    let partial_success = is_partial_success_requested(&req) && log_source != LogSource::Kinesis;
+    if !partial_success {
+        batch.check_parsed()?;
+    }
+
     let mut p_custom_fields = get_custom_fields_from_header(&req);
     match &log_source {
         LogSource::OtelLogs | LogSource::OtelMetrics | LogSource::OtelTraces => {
@@ -375,7 +645,7 @@ pub async fn post_event(
         }
         LogSource::Custom(src) => {
             KNOWN_SCHEMA_LIST.extract_from_inline_log(
-                &mut json,
+                &mut batch.json,
                 &mut p_custom_fields,
                 src,
                 extract_log,
@@ -397,7 +667,63 @@ pub async fn post_event(
             .ok_or(PostError::IncorrectLogFormat(stream_name.clone()))?;
     }
 
-    flatten_and_push_logs(json, &stream_name, &log_source, &p_custom_fields).await?;
+    if partial_success {
+        let summary =
+            flatten_and_push_each_log(batch, &stream_name, &log_source, &p_custom_fields).await?;
+        return Ok(HttpResponse::Ok().json(summary));
+    }
+
+    flatten_and_push_logs(batch.json, &stream_name, &log_source, &p_custom_fields).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Handler for POST /api/v1/logstream/{logstream} with a msgpack body
+// decodes the body into json and ingests it like a json event
+pub async fn post_msgpack_event(
+    req: HttpRequest,
+    stream_name: Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, PostError> {
+    let json = msgpack::decode(&body)?;
+
+    post_event(req, stream_name, Json(json)).await
+}
+
+// Handler for POST /api/v1/logstream/{logstream}/protobuf
+// decodes the protobuf message in the request body, using the descriptor registered
+// for the stream and ingests it like a json event
+pub async fn post_protobuf_event(
+    req: HttpRequest,
+    stream_name: Path<String>,
+    body: Bytes,
+) -> Result<HttpResponse, PostError> {
+    let stream_name = stream_name.into_inner();
+
+    let internal_stream_names = PARSEABLE.streams.list_internal_streams();
+    if internal_stream_names.contains(&stream_name) {
+        return Err(PostError::InternalStream(stream_name));
+    }
+    if !PARSEABLE.streams.contains(&stream_name)
+        && (PARSEABLE.options.mode == Mode::All
+            || !PARSEABLE
+                .create_stream_and_schema_from_storage(&stream_name)
+                .await
+                .unwrap_or_default())
+    {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    let descriptor = PARSEABLE
+        .get_stream(&stream_name)?
+        .get_settings()
+        .protobuf_descriptor
+        .clone()
+        .ok_or_else(|| ProtobufError::MissingDescriptor(stream_name.clone()))?;
+    let json = descriptor.decode(&body)?;
+
+    let p_custom_fields = get_custom_fields_from_header(&req);
+    flatten_and_push_logs(json, &stream_name, &LogSource::default(), &p_custom_fields).await?;
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -428,6 +754,8 @@ pub enum PostError {
     StreamNotFound(#[from] StreamNotFound),
     #[error("Could not deserialize into JSON object, {0}")]
     SerdeError(#[from] serde_json::Error),
+    #[error("Could not deserialize line {0} into JSON object, {1}")]
+    MalformedLine(usize, String),
     #[error("Header Error: {0}")]
     Header(#[from] ParseHeaderError),
     #[error("Event Error: {0}")]
@@ -469,13 +797,37 @@ pub enum PostError {
     IncorrectLogFormat(String),
     #[error("Failed to ingest events in dataset {0}. Total number of fields {1} exceeds the permissible limit of {2}. We recommend creating a new dataset beyond {2} for better query performance.")]
     FieldsCountLimitExceeded(String, usize, usize),
+    #[error("{0}")]
+    Protobuf(#[from] ProtobufError),
+    #[error("{0}")]
+    Msgpack(#[from] MsgpackError),
+    #[error("{0}")]
+    SchemaDrift(#[from] SchemaDriftError),
+    #[error("Every event of the batch must name the stream it is destined for in the field {0:?}")]
+    MissingStreamSelector(String),
+    #[error("{0}")]
+    ColumnLimit(#[from] ColumnLimitExceeded),
+    #[error("Missing or invalid credentials")]
+    Unauthenticated,
+    #[error("Not authorized to ingest into stream {0}")]
+    Unauthorized(String),
 }
 
 impl actix_web::ResponseError for PostError {
     fn status_code(&self) -> http::StatusCode {
         match self {
-            PostError::SerdeError(_) => StatusCode::BAD_REQUEST,
+            PostError::SerdeError(_) | PostError::MalformedLine(_, _) => StatusCode::BAD_REQUEST,
             PostError::Header(_) => StatusCode::BAD_REQUEST,
+            PostError::Event(EventError::Staging(StagingError::StreamFrozen(_))) => {
+                StatusCode::FORBIDDEN
+            }
+            PostError::Event(EventError::Staging(StagingError::ShuttingDown)) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            PostError::Event(EventError::Staging(StagingError::AckTimeout(_))) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            PostError::Event(EventError::DerivedColumn(_)) => StatusCode::BAD_REQUEST,
             PostError::Event(_) => StatusCode::INTERNAL_SERVER_ERROR,
             PostError::Invalid(_) => StatusCode::BAD_REQUEST,
             PostError::CreateStream(CreateStreamError::StreamNameValidation(_)) => {
@@ -489,6 +841,18 @@ impl actix_web::ResponseError for PostError {
             PostError::DashboardError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             PostError::FiltersError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             PostError::StreamError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PostError::JsonFlattenError(JsonFlattenError::DuplicateColumn(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            PostError::JsonFlattenError(JsonFlattenError::TimestampSkewed(_, _)) => {
+                StatusCode::BAD_REQUEST
+            }
+            PostError::JsonFlattenError(JsonFlattenError::FieldTooLong(_, _)) => {
+                StatusCode::BAD_REQUEST
+            }
+            PostError::JsonFlattenError(JsonFlattenError::ValueTooLong(_, _)) => {
+                StatusCode::BAD_REQUEST
+            }
             PostError::JsonFlattenError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             PostError::OtelNotSupported => StatusCode::BAD_REQUEST,
             PostError::InternalStream(_) => StatusCode::BAD_REQUEST,
@@ -498,30 +862,101 @@ impl actix_web::ResponseError for PostError {
             PostError::KnownFormat(_) => StatusCode::BAD_REQUEST,
             PostError::IncorrectLogFormat(_) => StatusCode::BAD_REQUEST,
             PostError::FieldsCountLimitExceeded(_, _, _) => StatusCode::BAD_REQUEST,
+            PostError::Protobuf(_) => StatusCode::BAD_REQUEST,
+            PostError::Msgpack(_) => StatusCode::BAD_REQUEST,
+            PostError::SchemaDrift(SchemaDriftError::Incompatible(_)) => StatusCode::CONFLICT,
+            PostError::SchemaDrift(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PostError::MissingStreamSelector(_) => StatusCode::BAD_REQUEST,
+            PostError::ColumnLimit(_) => StatusCode::BAD_REQUEST,
+            PostError::Unauthenticated => StatusCode::UNAUTHORIZED,
+            PostError::Unauthorized(_) => StatusCode::FORBIDDEN,
         }
     }
 
     fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
-        actix_web::HttpResponse::build(self.status_code())
-            .insert_header(ContentType::plaintext())
-            .body(self.to_string())
+        ErrorEnvelope::new(self.code(), self).into_response(self.status_code())
+    }
+}
+
+impl PostError {
+    /// Machine readable code of the error, as returned in the `code` of the error response
+    pub fn code(&self) -> &'static str {
+        match self {
+            PostError::StreamNotFound(_) => "stream_not_found",
+            PostError::SerdeError(_) | PostError::MalformedLine(_, _) => "invalid_json",
+            PostError::Header(_) => "invalid_header",
+            PostError::Event(EventError::Staging(StagingError::StreamFrozen(_))) => "stream_frozen",
+            PostError::Event(EventError::Staging(StagingError::ShuttingDown)) => "shutting_down",
+            PostError::Event(EventError::Staging(StagingError::AckTimeout(_))) => "ack_timeout",
+            PostError::Event(EventError::Staging(StagingError::UploadFailed)) => "upload_failed",
+            PostError::Event(EventError::DerivedColumn(_)) => "derived_column_error",
+            PostError::Event(_) => "event_error",
+            PostError::Invalid(_) => "invalid_event",
+            PostError::CreateStream(CreateStreamError::StreamNameValidation(_)) => {
+                "invalid_stream_name"
+            }
+            PostError::CreateStream(_) => "create_stream_error",
+            PostError::CustomError(_) => "ingestion_error",
+            PostError::NetworkError(_) => "network_error",
+            PostError::ObjectStorageError(_) => "storage_error",
+            PostError::FiltersError(_) => "filters_error",
+            PostError::DashboardError(_) => "dashboard_error",
+            PostError::StreamError(_) => "stream_error",
+            PostError::JsonFlattenError(JsonFlattenError::DuplicateColumn(_)) => "duplicate_column",
+            PostError::JsonFlattenError(JsonFlattenError::TimestampSkewed(_, _)) => {
+                "timestamp_skewed"
+            }
+            PostError::JsonFlattenError(JsonFlattenError::FieldTooLong(_, _)) => "field_too_long",
+            PostError::JsonFlattenError(JsonFlattenError::ValueTooLong(_, _)) => "value_too_long",
+            PostError::JsonFlattenError(_) => "invalid_event",
+            PostError::OtelNotSupported => "otel_not_supported",
+            PostError::InternalStream(_) => "internal_stream",
+            PostError::IncorrectLogSource(_) => "incorrect_log_source",
+            PostError::IngestionNotAllowed => "ingestion_not_allowed",
+            PostError::MissingTimePartition(_) => "missing_time_partition",
+            PostError::KnownFormat(_) => "known_format_error",
+            PostError::IncorrectLogFormat(_) => "incorrect_log_format",
+            PostError::FieldsCountLimitExceeded(_, _, _) => "fields_count_limit_exceeded",
+            PostError::Protobuf(_) => "invalid_protobuf",
+            PostError::Msgpack(_) => "invalid_msgpack",
+            PostError::SchemaDrift(SchemaDriftError::Incompatible(_)) => "schema_incompatible",
+            PostError::SchemaDrift(_) => "schema_drift",
+            PostError::MissingStreamSelector(_) => "missing_stream_selector",
+            PostError::ColumnLimit(_) => "column_limit_exceeded",
+            PostError::Unauthenticated => "unauthenticated",
+            PostError::Unauthorized(_) => "unauthorized",
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use actix_web::{test::TestRequest, web::Json};
     use arrow::datatypes::Int64Type;
     use arrow_array::{ArrayRef, Float64Array, Int64Array, ListArray, StringArray};
     use arrow_schema::{DataType, Field};
-    use serde_json::json;
-    use std::{collections::HashMap, sync::Arc};
+    use bytes::Bytes;
+    use chrono::{DateTime, Utc};
+    use futures::{stream, StreamExt};
+    use serde_json::{json, Value};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex, RwLock},
+    };
 
     use crate::{
         event::format::{json, EventFormat},
         metadata::SchemaVersion,
+        parseable::PARSEABLE,
+        rbac::{
+            map::{mut_sessions, SessionKey, Sessions, SESSIONS},
+            role::{Action, Permission},
+        },
     };
 
+    use super::{ingest_batch, ingest_chunks, route_by_stream, PostError};
+
     trait TestExt {
         fn as_int64_arr(&self) -> Option<&Int64Array>;
         fn as_float64_arr(&self) -> Option<&Float64Array>;
@@ -580,6 +1015,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn key_order_does_not_change_schema() {
+        let into_schema = |json| {
+            let (rb, _) = json::Event::new(json)
+                .into_recordbatch(
+                    &HashMap::default(),
+                    false,
+                    None,
+                    SchemaVersion::V0,
+                    &HashMap::new(),
+                )
+                .unwrap();
+            rb.schema()
+        };
+
+        let schema = into_schema(json!({"c": 4.23, "a": 1, "b": "hello"}));
+        assert_eq!(schema, into_schema(json!({"b": "world", "c": 1.5, "a": 2})));
+        let names: Vec<_> = schema.fields().iter().map(|field| field.name()).collect();
+        assert_eq!(names, ["p_timestamp", "a", "b", "c"]);
+    }
+
     #[test]
     fn basic_object_with_null_into_rb() {
         let json = json!({
@@ -1003,4 +1459,315 @@ mod tests {
             &Float64Array::from(vec![None, None, None, Some(2.0)])
         );
     }
+
+    #[test]
+    fn declared_int64_retains_precision() {
+        // 2^53 + 1 can't be represented exactly as a float64
+        let json = json!({
+            "id": 9007199254740993_i64,
+            "b": "hello",
+        });
+        let schema = fields_to_map([Field::new("id", DataType::Int64, true)].into_iter());
+
+        let (rb, _) = json::Event::new(json)
+            .into_recordbatch(&schema, false, None, SchemaVersion::V1, &HashMap::new())
+            .unwrap();
+
+        assert_eq!(rb.num_rows(), 1);
+        assert_eq!(
+            rb.column_by_name("id").unwrap().as_int64_arr().unwrap(),
+            &Int64Array::from_iter([9007199254740993])
+        );
+    }
+
+    #[test]
+    fn declared_decimal_is_built_from_its_digits() {
+        use arrow::datatypes::Decimal128Type;
+        use arrow_array::cast::AsArray;
+
+        // more significant digits than a float64 holds
+        let json = json!([
+            {"amount": "12345678901234567.89", "b": "hello"},
+            {"amount": "-0.10"},
+            {"amount": 42},
+        ]);
+        let schema =
+            fields_to_map([Field::new("amount", DataType::Decimal128(38, 2), true)].into_iter());
+
+        let (rb, _) = json::Event::new(json)
+            .into_recordbatch(&schema, false, None, SchemaVersion::V1, &HashMap::new())
+            .unwrap();
+
+        let amount = rb.column_by_name("amount").unwrap();
+        assert_eq!(amount.data_type(), &DataType::Decimal128(38, 2));
+        assert_eq!(
+            amount.as_primitive::<Decimal128Type>().values().to_vec(),
+            vec![1234567890123456789, -10, 4200]
+        );
+
+        // strings that aren't decimals are rejected rather than stored
+        let json = json!({"amount": "12,50"});
+        assert!(json::Event::new(json)
+            .into_recordbatch(&schema, false, None, SchemaVersion::V1, &HashMap::new())
+            .is_err());
+    }
+
+    #[actix_web::test]
+    async fn mixed_batch_lands_in_each_stream() {
+        SESSIONS.get_or_init(|| RwLock::new(Sessions::default()));
+        let key = SessionKey::ApiToken("batch-ingest".to_owned());
+        mut_sessions().track_new(
+            "batch-ingest".to_owned(),
+            key,
+            DateTime::<Utc>::MAX_UTC,
+            vec![Permission::Unit(Action::Ingest)],
+        );
+
+        let batch = json!([
+            {"stream": "batch_a", "msg": "first"},
+            {"stream": "batch_b", "msg": "second"},
+            {"stream": "batch_a", "msg": "third"},
+        ]);
+        let req = TestRequest::post()
+            .insert_header(("Authorization", "Bearer batch-ingest"))
+            .to_http_request();
+        ingest_batch(req, Json(batch)).await.unwrap();
+
+        let rows = |stream_name: &str| -> usize {
+            let stream = PARSEABLE.get_stream(stream_name).unwrap();
+            // the selector is routing information, not part of the event
+            assert!(stream.get_schema().field_with_name("stream").is_err());
+            stream
+                .recordbatches_cloned(&stream.get_schema())
+                .iter()
+                .map(|rb| rb.num_rows())
+                .sum()
+        };
+        assert_eq!(rows("batch_a"), 2);
+        assert_eq!(rows("batch_b"), 1);
+        assert!(matches!(
+            route_by_stream(json!([{"msg": "lost"}]), "stream"),
+            Err(PostError::MissingStreamSelector(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn records_streamed_over_one_connection_are_all_ingested() {
+        let body: String = (0..100)
+            .map(|i| format!("{{\"id\": {i}, \"msg\": \"event {i}\"}}\n"))
+            .collect();
+        // chunk boundaries fall in the middle of records, and the last one has no newline
+        let body = body.trim_end().as_bytes().to_vec();
+        let chunks: Vec<Result<Bytes, std::io::Error>> = body
+            .chunks(37)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+
+        let ingested = Arc::new(Mutex::new(vec![]));
+        let acks: Vec<Value> = ingest_chunks(stream::iter(chunks), |events| {
+            let ingested = ingested.clone();
+            async move {
+                ingested.lock().unwrap().extend(events);
+                Ok(())
+            }
+        })
+        .map(|ack| serde_json::from_slice(&ack.unwrap()).unwrap())
+        .collect()
+        .await;
+
+        let ingested = ingested.lock().unwrap();
+        assert_eq!(ingested.len(), 100);
+        assert_eq!(ingested[99], json!({"id": 99, "msg": "event 99"}));
+        // progress is acked as records arrive, not only once the body ends
+        assert!(acks.len() > 1);
+        assert_eq!(acks.last().unwrap(), &json!({"ingested": 100}));
+    }
+
+    #[actix_web::test]
+    async fn records_streamed_to_the_endpoint_are_all_queried() {
+        use actix_web::{dev::Payload, error::PayloadError, web, Either, FromRequest};
+        use chrono::TimeDelta;
+
+        use crate::{
+            handlers::STREAM_NAME_HEADER_KEY,
+            query::{execute, Query, QUERY_SESSION},
+            utils::time::TimeRange,
+        };
+
+        use super::ingest_stream;
+
+        let stream_name = "ingest_stream_records";
+        let body: String = (0..100)
+            .map(|i| format!("{{\"id\": {i}, \"msg\": \"event {i}\"}}\n"))
+            .collect();
+        let chunks: Vec<Result<Bytes, PayloadError>> = body
+            .as_bytes()
+            .chunks(37)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+
+        // the body arrives in chunks, as over a long-lived connection
+        let (req, _) = TestRequest::post()
+            .insert_header((STREAM_NAME_HEADER_KEY, stream_name))
+            .to_http_parts();
+        let mut payload = Payload::Stream {
+            payload: Box::pin(stream::iter(chunks)),
+        };
+        let payload = web::Payload::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        let res = ingest_stream(req, payload).await.unwrap();
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        let acks = body
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert!(acks.len() > 1);
+        assert_eq!(acks.last().unwrap(), &json!({"ingested": 100}));
+
+        let raw_logical_plan = QUERY_SESSION
+            .state()
+            .create_logical_plan(&format!("SELECT id FROM {stream_name}"))
+            .await
+            .unwrap();
+        let query = Query {
+            raw_logical_plan,
+            time_range: TimeRange::new(
+                Utc::now() - TimeDelta::hours(1),
+                Utc::now() + TimeDelta::minutes(1),
+            ),
+            filter_tag: None,
+        };
+        let (Either::Left(records), _) = execute(query, stream_name, false).await.unwrap() else {
+            unreachable!("non-streaming query returns batches")
+        };
+        assert_eq!(records.iter().map(|rb| rb.num_rows()).sum::<usize>(), 100);
+        assert!(PARSEABLE.streams.contains(stream_name));
+    }
+
+    #[actix_web::test]
+    async fn malformed_line_is_reported_and_the_rest_ingested() {
+        use crate::handlers::{PARTIAL_SUCCESS_KEY, STREAM_NAME_HEADER_KEY};
+
+        use super::ingest_ndjson;
+
+        let stream_name = "ndjson_partial_success";
+        // the second record is cut short, and a blank line separates it from the third
+        let body = "{\"msg\": \"first\"}\n{\"msg\": \"second\"\n\n{\"msg\": \"third\"}\n";
+
+        // without partial success, the batch fails naming the line
+        let req = TestRequest::post()
+            .insert_header((STREAM_NAME_HEADER_KEY, stream_name))
+            .to_http_request();
+        assert!(matches!(
+            ingest_ndjson(req, Bytes::from(body)).await,
+            Err(PostError::MalformedLine(2, _))
+        ));
+        assert!(!PARSEABLE.streams.contains(stream_name));
+
+        let req = TestRequest::post()
+            .insert_header((STREAM_NAME_HEADER_KEY, stream_name))
+            .insert_header((PARTIAL_SUCCESS_KEY, "true"))
+            .to_http_request();
+        let res = ingest_ndjson(req, Bytes::from(body)).await.unwrap();
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        let summary: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(summary["accepted"], 2);
+        assert_eq!(summary["failed"].as_array().unwrap().len(), 1);
+        assert_eq!(summary["failed"][0]["index"], 1);
+        assert_eq!(summary["failed"][0]["line"], 2);
+        assert_eq!(summary["records"][0]["line"], 1);
+        assert_eq!(summary["records"][1]["index"], 2);
+        assert_eq!(summary["records"][1]["line"], 4);
+        // the valid records were ingested together, as one event
+        assert_eq!(
+            summary["records"][0]["p_timestamp"],
+            summary["records"][1]["p_timestamp"]
+        );
+
+        let stream = PARSEABLE.get_stream(stream_name).unwrap();
+        let rows: usize = stream
+            .recordbatches_cloned(&stream.get_schema())
+            .iter()
+            .map(|rb| rb.num_rows())
+            .sum();
+        assert_eq!(rows, 2);
+    }
+
+    #[actix_web::test]
+    async fn protobuf_event_is_ingested_with_the_descriptor_of_the_stream() {
+        use actix_web::{http::header::HeaderMap, web::Path, Either};
+        use arrow::util::pretty::pretty_format_batches;
+        use chrono::TimeDelta;
+
+        use crate::{
+            event::format::protobuf::tests::descriptor,
+            handlers::http::logstream::put_stream_settings,
+            query::{execute, Query, QUERY_SESSION},
+            rbac::map::init_for_tests,
+            utils::time::TimeRange,
+        };
+
+        use super::post_protobuf_event;
+
+        init_for_tests();
+        let stream_name = "protobuf_events";
+        PARSEABLE
+            .create_update_stream(&HeaderMap::new(), &Bytes::new(), stream_name)
+            .await
+            .unwrap();
+        let descriptor = descriptor();
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "Basic YWRtaW46YWRtaW4="))
+            .to_http_request();
+        let Value::Object(patch) = json!({ "protobuf_descriptor": descriptor }) else {
+            unreachable!()
+        };
+        put_stream_settings(req, Path::from(stream_name.to_owned()), Json(patch))
+            .await
+            .unwrap();
+
+        // level = "warn", source = { host = "web-1" }
+        let message = [
+            &[0x0a, 0x04][..],
+            b"warn",
+            &[0x22, 0x07, 0x0a, 0x05],
+            b"web-1",
+        ]
+        .concat();
+        let req = TestRequest::default().to_http_request();
+        let res = post_protobuf_event(req, Path::from(stream_name.to_owned()), message.into())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+
+        let raw_logical_plan = QUERY_SESSION
+            .state()
+            .create_logical_plan(&format!("SELECT level, source_host FROM {stream_name}"))
+            .await
+            .unwrap();
+        let query = Query {
+            raw_logical_plan,
+            time_range: TimeRange::new(
+                Utc::now() - TimeDelta::hours(1),
+                Utc::now() + TimeDelta::minutes(1),
+            ),
+            filter_tag: None,
+        };
+        let (Either::Left(records), _) = execute(query, stream_name, false).await.unwrap() else {
+            unreachable!("non-streaming query returns batches")
+        };
+        assert_eq!(
+            pretty_format_batches(&records).unwrap().to_string(),
+            [
+                "+-------+-------------+",
+                "| level | source_host |",
+                "+-------+-------------+",
+                "| warn  | web-1       |",
+                "+-------+-------------+",
+            ]
+            .join("\n")
+        );
+    }
 }
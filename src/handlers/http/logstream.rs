@@ -17,30 +17,57 @@
  */
 
 use self::error::StreamError;
+use super::cluster::sync_stream_settings_with_ingestors;
 use super::cluster::utils::{IngestionStats, QueriedStats, StorageStats};
 use super::query::update_schema_when_distributed;
-use crate::event::format::override_data_type;
+use crate::catalog::{
+    backfill,
+    deletion::{self, RangeFilter},
+};
+use crate::event::derived;
+use crate::event::format::{inference, json, override_data_type, EventFormat};
+use crate::event::DEFAULT_TIMESTAMP_KEY;
 use crate::hottier::{HotTierManager, StreamHotTier, CURRENT_HOT_TIER_VERSION};
-use crate::metadata::SchemaVersion;
+use crate::livetail::{to_sse_event, RowFilter, LIVETAIL};
+use crate::metadata::{SchemaVersion, StreamSettings};
 use crate::metrics::{EVENTS_INGESTED_DATE, EVENTS_INGESTED_SIZE_DATE, EVENTS_STORAGE_SIZE_DATE};
+use crate::option::Mode;
 use crate::parseable::{StreamNotFound, PARSEABLE};
+use crate::query::stream_schema_provider::{is_within_staging_window, PartialTimeFilter};
+use crate::query::{cardinality, execute, Query as LogicalQuery, QUERY_SESSION};
 use crate::rbac::role::Action;
-use crate::rbac::Users;
+use crate::rbac::{Response, Users};
 use crate::stats::{event_labels_date, storage_size_labels_date, Stats};
 use crate::storage::retention::Retention;
-use crate::storage::{StreamInfo, StreamType};
+use crate::storage::{
+    validate_storage_bucket, validate_storage_prefix, StreamInfo, StreamType, STREAM_ROOT_DIRECTORY,
+};
 use crate::utils::actix::extract_session_key_from_req;
+use crate::utils::arrow::record_batches_to_json;
+use crate::utils::arrow::schema_registry::{to_avro_schema, to_json_schema};
+use crate::utils::human_size::human_size_to_bytes;
+use crate::utils::time::TimeRange;
 use crate::{stats, validator, LOCK_EXPECT};
 
+use actix_web::http::header::CACHE_CONTROL;
 use actix_web::http::StatusCode;
 use actix_web::web::{Json, Path};
-use actix_web::{web, HttpRequest, Responder};
+use actix_web::{web, Either, HttpRequest, HttpResponse, Responder};
+use arrow_array::{RecordBatch, UInt64Array};
 use arrow_json::reader::infer_json_schema_from_iterator;
+use arrow_schema::{DataType, Schema};
 use bytes::Bytes;
 use chrono::Utc;
+use chrono_tz::Tz;
+use futures::{future, StreamExt};
 use itertools::Itertools;
+use rand::distributions::{Alphanumeric, DistString};
+use relative_path::RelativePath;
+use serde::Deserialize;
 use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::ops::Bound;
 use std::sync::Arc;
 use tracing::warn;
 
@@ -54,6 +81,7 @@ pub async fn delete(stream_name: Path<String>) -> Result<impl Responder, StreamE
     let objectstore = PARSEABLE.storage.get_object_store();
 
     // Delete from storage
+    PARSEABLE.delete_stream_data(&stream_name).await?;
     objectstore.delete_stream(&stream_name).await?;
     // Delete from staging
     let stream_dir = PARSEABLE.get_or_create_stream(&stream_name);
@@ -79,6 +107,44 @@ pub async fn delete(stream_name: Path<String>) -> Result<impl Responder, StreamE
     Ok((format!("log stream {stream_name} deleted"), StatusCode::OK))
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeRangeParams {
+    pub start_time: String,
+    pub end_time: String,
+}
+
+// Handler for DELETE /api/v1/logstream/{logstream}/data?startTime=..&endTime=..
+// deletes the data of the stream within the time range from storage and hot tier
+pub async fn delete_range(
+    stream_name: Path<String>,
+    web::Query(TimeRangeParams {
+        start_time,
+        end_time,
+    }): web::Query<TimeRangeParams>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name).into());
+    }
+
+    let time_range = TimeRange::parse_human_time(&start_time, &end_time)
+        .map_err(|err| StreamError::InvalidQueryParameter(err.to_string()))?;
+    let range = RangeFilter::new(&stream_name, time_range.clone());
+    let deleted = deletion::delete_range(&stream_name, time_range).await?;
+
+    if let Some(hot_tier_manager) = HotTierManager::global() {
+        if hot_tier_manager.check_stream_hot_tier_exists(&stream_name) {
+            hot_tier_manager.delete_range(&stream_name, &range).await?;
+        }
+    }
+
+    Ok((
+        format!("deleted {deleted} files of log stream {stream_name} within the time range"),
+        StatusCode::OK,
+    ))
+}
+
 pub async fn list(req: HttpRequest) -> Result<impl Responder, StreamError> {
     let key = extract_session_key_from_req(&req)
         .map_err(|err| StreamError::Anyhow(anyhow::Error::msg(err.to_string())))?;
@@ -93,7 +159,7 @@ pub async fn list(req: HttpRequest) -> Result<impl Responder, StreamError> {
         .into_iter()
         .filter(|logstream| {
             Users.authorize(key.clone(), Action::ListStream, Some(logstream), None)
-                == crate::rbac::Response::Authorized
+                == Response::Authorized
         })
         .map(|name| json!({"name": name}))
         .collect_vec();
@@ -120,7 +186,52 @@ pub async fn detect_schema(Json(json): Json<Value>) -> Result<impl Responder, St
     Ok((web::Json(schema), StatusCode::OK))
 }
 
-pub async fn get_schema(stream_name: Path<String>) -> Result<impl Responder, StreamError> {
+/// Infers the schema of the events in the request body, the same way ingestion into a new
+/// stream would, without writing anything to memory or storage.
+pub async fn preview_schema(Json(json): Json<Value>) -> Result<impl Responder, StreamError> {
+    let schema = infer_preview_schema(json)?;
+    Ok((web::Json(schema), StatusCode::OK))
+}
+
+fn infer_preview_schema(json: Value) -> Result<Schema, StreamError> {
+    let is_object_or_array_of_objects = match &json {
+        Value::Array(arr) => !arr.is_empty() && arr.iter().all(Value::is_object),
+        Value::Object(_) => true,
+        _ => false,
+    };
+    if !is_object_or_array_of_objects {
+        return Err(StreamError::Custom {
+            msg: "please send json events as part of the request".to_string(),
+            status: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    let (_, fields, _) =
+        json::Event::new(json).to_data(&HashMap::new(), None, SchemaVersion::V1, false)?;
+
+    Ok(Schema::new(fields))
+}
+
+/// Format a stream's schema is returned in
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchemaFormat {
+    #[default]
+    Arrow,
+    JsonSchema,
+    Avro,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemaParams {
+    #[serde(default)]
+    pub format: SchemaFormat,
+}
+
+pub async fn get_schema(
+    stream_name: Path<String>,
+    params: web::Query<SchemaParams>,
+) -> Result<impl Responder, StreamError> {
     let stream_name = stream_name.into_inner();
 
     // Ensure parseable is aware of stream in distributed mode
@@ -132,6 +243,11 @@ pub async fn get_schema(stream_name: Path<String>) -> Result<impl Responder, Str
     match update_schema_when_distributed(&vec![stream_name.clone()]).await {
         Ok(_) => {
             let schema = stream.get_schema();
+            let schema = match params.format {
+                SchemaFormat::Arrow => serde_json::to_value(schema.as_ref())?,
+                SchemaFormat::JsonSchema => to_json_schema(&stream_name, &schema),
+                SchemaFormat::Avro => to_avro_schema(&stream_name, &schema),
+            };
             Ok((web::Json(schema), StatusCode::OK))
         }
         Err(err) => Err(StreamError::Custom {
@@ -198,6 +314,628 @@ pub async fn put_retention(
     ))
 }
 
+/// Settings of a stream that can be updated through its settings resource, the declared columns
+/// are only ever set when the stream is created
+const UPDATABLE_SETTINGS: &[&str] = &[
+    "protobuf_descriptor",
+    "exclude_columns",
+    "aliases",
+    "number_inference",
+    "acl",
+    "frozen",
+    "sampling",
+    "event_body_path",
+    "schema_on_read",
+    "parquet_target_size",
+    "column_aliases",
+    "boolean_columns",
+    "partition_timezone",
+    "column_limit",
+    "schema_inference",
+    "storage_prefix",
+    "storage_bucket",
+    "derived_columns",
+    "timestamp_fields",
+    "dedup_window",
+];
+
+pub async fn get_stream_settings(
+    req: HttpRequest,
+    stream_name: Path<String>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    // For query mode, if the stream not found in memory map,
+    //check if it exists in the storage
+    //create stream and schema from storage
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    let mut settings = PARSEABLE
+        .get_stream(&stream_name)?
+        .get_settings()
+        .as_ref()
+        .clone();
+    // the access control list is only shown to those who can see roles
+    let key = extract_session_key_from_req(&req)
+        .map_err(|err| StreamError::Anyhow(anyhow::Error::msg(err.to_string())))?;
+    if Users.authorize(key, Action::GetRole, Some(&stream_name), None) != Response::Authorized {
+        settings.acl.clear();
+    }
+
+    Ok((web::Json(settings), StatusCode::OK))
+}
+
+pub async fn put_stream_settings(
+    req: HttpRequest,
+    stream_name: Path<String>,
+    Json(patch): Json<serde_json::Map<String, Value>>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+
+    // For query mode, if the stream not found in memory map,
+    //check if it exists in the storage
+    //create stream and schema from storage
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name).into());
+    }
+
+    // who can access the stream and whether it can be written to take more than CreateStream
+    let key = extract_session_key_from_req(&req)
+        .map_err(|err| StreamError::Anyhow(anyhow::Error::msg(err.to_string())))?;
+    for (setting, action) in [("acl", Action::PutRole), ("frozen", Action::All)] {
+        if patch.contains_key(setting)
+            && Users.authorize(key.clone(), action, Some(&stream_name), None)
+                != Response::Authorized
+        {
+            return Err(StreamError::Custom {
+                msg: format!("not authorized to update {setting} of log stream {stream_name}"),
+                status: StatusCode::FORBIDDEN,
+            });
+        }
+    }
+
+    let stream = PARSEABLE.get_stream(&stream_name)?;
+    let current = stream.get_settings();
+    let settings = merge_settings(&current, patch)?;
+    validate_settings(&stream_name, &current, &settings).await?;
+    PARSEABLE
+        .update_stream_settings(&stream_name, settings.clone())
+        .await?;
+    // ingestors apply the settings as events are ingested
+    if PARSEABLE.options.mode == Mode::Query {
+        sync_stream_settings_with_ingestors(&stream_name, &settings).await?;
+    }
+
+    Ok((
+        format!("settings updated for log stream {stream_name}"),
+        StatusCode::OK,
+    ))
+}
+
+/// Applies the settings in `patch` over `current`, a null value resets a setting to its default
+fn merge_settings(
+    current: &StreamSettings,
+    patch: serde_json::Map<String, Value>,
+) -> Result<StreamSettings, StreamError> {
+    let Value::Object(mut merged) = serde_json::to_value(current)? else {
+        unreachable!("settings are serialized as an object")
+    };
+    for (setting, value) in patch {
+        if !UPDATABLE_SETTINGS.contains(&setting.as_str()) {
+            return Err(StreamError::Custom {
+                msg: format!("{setting} is not a setting of log streams that can be updated"),
+                status: StatusCode::BAD_REQUEST,
+            });
+        }
+        match value {
+            Value::Null => {
+                merged.remove(&setting);
+            }
+            // sizes are given in a human readable form such as "64 MiB"
+            Value::String(size) if setting == "parquet_target_size" => {
+                let bytes = human_size_to_bytes(&size).map_err(|_| StreamError::Custom {
+                    msg: format!("invalid parquet target size {size:?}, expected e.g. \"64 MiB\""),
+                    status: StatusCode::BAD_REQUEST,
+                })?;
+                merged.insert(setting, bytes.into());
+            }
+            value => {
+                merged.insert(setting, value);
+            }
+        }
+    }
+
+    Ok(serde_json::from_value(Value::Object(merged))?)
+}
+
+/// Validates the settings that differ from the current ones of the stream
+pub async fn validate_settings(
+    stream_name: &str,
+    current: &StreamSettings,
+    settings: &StreamSettings,
+) -> Result<(), StreamError> {
+    let invalid = |msg: String| StreamError::Custom {
+        msg,
+        status: StatusCode::BAD_REQUEST,
+    };
+    let stream = PARSEABLE.get_stream(stream_name)?;
+    let time_partition = stream.get_time_partition();
+    let custom_partition = stream.get_custom_partition();
+
+    if settings.exclude_columns != current.exclude_columns {
+        let custom_partition = custom_partition.clone().unwrap_or_default();
+        let partitions: Vec<&str> = custom_partition
+            .split(',')
+            .map(str::trim)
+            .filter(|partition| !partition.is_empty())
+            .chain(time_partition.as_deref())
+            .collect();
+        if let Some(column) = settings
+            .exclude_columns
+            .iter()
+            .find(|column| partitions.contains(&column.as_str()))
+        {
+            return Err(invalid(format!(
+                "Partition column {column} can't be excluded from ingestion"
+            )));
+        }
+    }
+
+    if settings.aliases != current.aliases {
+        for alias in &settings.aliases {
+            validator::stream_name(alias, StreamType::UserDefined)
+                .map_err(|err| StreamError::CreateStream(err.into()))?;
+            // an alias can't shadow another stream or be shared with it
+            if let Some(existing) = PARSEABLE.streams.resolve(alias) {
+                if existing != stream_name || alias == stream_name {
+                    return Err(invalid(format!(
+                        "{alias} is already in use by log stream {existing}"
+                    )));
+                }
+            }
+        }
+    }
+
+    if settings.schema_on_read && !current.schema_on_read {
+        // events of stream that has columns already would be split across those and the raw column,
+        // partitions need fields of the event which aren't extracted under schema-on-read
+        let reason = if !stream.get_schema_raw().is_empty() {
+            Some("it already has a schema")
+        } else if time_partition.is_some() || custom_partition.is_some() {
+            Some("it is partitioned by fields of its events")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            return Err(invalid(format!(
+                "log stream {stream_name} can't be switched to schema-on-read as {reason}"
+            )));
+        }
+    }
+
+    if settings.sampling != current.sampling {
+        if let Some(sampling) = &settings.sampling {
+            sampling.validate().map_err(invalid)?;
+        }
+    }
+
+    if settings.event_body_path != current.event_body_path {
+        if let Some(path) = &settings.event_body_path {
+            if path.split('.').any(str::is_empty) {
+                return Err(invalid(format!(
+                    "invalid event body path {path:?}, expected e.g. \"record\" or \"data.record\""
+                )));
+            }
+        }
+    }
+
+    if settings.parquet_target_size == Some(0) {
+        return Err(invalid(
+            "invalid parquet target size 0, expected e.g. \"64 MiB\"".to_owned(),
+        ));
+    }
+
+    if settings.column_aliases != current.column_aliases {
+        let schema = stream.get_schema();
+        for (alias, column) in &settings.column_aliases {
+            // aliases of aliases aren't resolved
+            if alias.is_empty() || alias == column || settings.column_aliases.contains_key(column) {
+                return Err(invalid(format!(
+                    "invalid alias {alias:?} for column {column:?}"
+                )));
+            }
+            if schema.field_with_name(column).is_err() {
+                return Err(invalid(format!(
+                    "column {column:?} doesn't exist in log stream {stream_name}"
+                )));
+            }
+        }
+    }
+
+    if settings.boolean_columns != current.boolean_columns {
+        // values can only be normalized into booleans for columns that are or will be boolean
+        let schema = stream.get_schema();
+        for column in &settings.boolean_columns {
+            if let Ok(field) = schema.field_with_name(column) {
+                if field.data_type() != &DataType::Boolean {
+                    return Err(invalid(format!(
+                        "column {column:?} of log stream {stream_name} is of type {}, not boolean",
+                        field.data_type()
+                    )));
+                }
+            }
+        }
+    }
+
+    if settings.partition_timezone != current.partition_timezone {
+        if let Some(timezone) = &settings.partition_timezone {
+            if timezone.parse::<Tz>().is_err() {
+                return Err(invalid(format!(
+                    "invalid timezone {timezone:?}, expected e.g. \"America/New_York\""
+                )));
+            }
+        }
+        // partitions and manifests already dated in one timezone wouldn't be found in another
+        if stream.get_first_event().is_some() {
+            return Err(invalid(format!(
+                "partition timezone of log stream {stream_name} can't be changed once it has events"
+            )));
+        }
+    }
+
+    if settings.column_limit != current.column_limit {
+        if let Some(column_limit) = &settings.column_limit {
+            column_limit.validate().map_err(invalid)?;
+        }
+    }
+
+    if settings.schema_inference != current.schema_inference {
+        if let Some(name) = settings.schema_inference.as_deref() {
+            if !inference::is_registered(name) {
+                return Err(invalid(format!(
+                    "schema inference strategy {name} is not registered"
+                )));
+            }
+        }
+    }
+
+    if settings.storage_bucket != current.storage_bucket {
+        if let Some(bucket) = &settings.storage_bucket {
+            validate_storage_bucket(bucket).map_err(invalid)?;
+        }
+        // data already put in one bucket wouldn't be queried, retained or deleted in another
+        if stream.get_first_event().is_some() {
+            return Err(invalid(format!(
+                "storage bucket of log stream {stream_name} can't be changed once it has events"
+            )));
+        }
+    }
+
+    if settings.storage_prefix != current.storage_prefix {
+        if let Some(prefix) = &settings.storage_prefix {
+            validate_storage_prefix(prefix).map_err(invalid)?;
+        }
+        // data already put under one prefix wouldn't be retained or deleted under another
+        if stream.get_first_event().is_some() {
+            return Err(invalid(format!(
+                "storage prefix of log stream {stream_name} can't be changed once it has events"
+            )));
+        }
+    }
+
+    // a prefix in the default bucket would otherwise be deleted along with the stream it lands in,
+    // streams not loaded on this node are found by their metadata in the directory
+    if settings.storage_prefix != current.storage_prefix
+        || settings.storage_bucket != current.storage_bucket
+    {
+        if let (Some(prefix), None) = (&settings.storage_prefix, &settings.storage_bucket) {
+            let top = prefix.split('/').next().unwrap_or_default();
+            let is_stream = PARSEABLE.streams.contains(top)
+                || PARSEABLE
+                    .storage
+                    .get_object_store()
+                    .list_dirs_relative(RelativePath::new(top))
+                    .await
+                    .unwrap_or_default()
+                    .iter()
+                    .any(|dir| dir == STREAM_ROOT_DIRECTORY);
+            if is_stream {
+                return Err(invalid(format!(
+                    "storage prefix {prefix:?} lands in the directory of log stream {top}"
+                )));
+            }
+        }
+    }
+
+    if settings.dedup_window != current.dedup_window {
+        if let Some(window) = &settings.dedup_window {
+            if !humantime::parse_duration(window).is_ok_and(|window| !window.is_zero()) {
+                return Err(invalid(format!(
+                    "invalid dedup window {window:?}, expected e.g. \"10m\""
+                )));
+            }
+        }
+    }
+
+    if settings.timestamp_fields != current.timestamp_fields {
+        // the time partition of a stream is required of every event, there's nothing to fall back on
+        if !settings.timestamp_fields.is_empty() && time_partition.is_some() {
+            return Err(invalid(format!(
+                "log stream {stream_name} is partitioned by its time partition, timestamp fields can't be set"
+            )));
+        }
+        if settings
+            .timestamp_fields
+            .iter()
+            .any(|field| field.is_empty())
+        {
+            return Err(invalid("timestamp fields must not be empty".to_owned()));
+        }
+    }
+
+    if settings.derived_columns != current.derived_columns {
+        derived::validate(&settings.derived_columns).map_err(invalid)?;
+    }
+
+    Ok(())
+}
+
+pub async fn put_stream_backfill(stream_name: Path<String>) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    if !backfill::start(&stream_name) {
+        return Err(StreamError::Custom {
+            msg: format!("Backfill of logstream {stream_name} is already in progress"),
+            status: StatusCode::CONFLICT,
+        });
+    }
+    let name = stream_name.clone();
+    tokio::spawn(async move {
+        if let Err(err) = backfill::backfill_stream(&name).await {
+            warn!("Failed to backfill stream- {name}: {err}");
+        }
+    });
+
+    Ok((
+        format!("Backfill of logstream {stream_name} started"),
+        StatusCode::ACCEPTED,
+    ))
+}
+
+pub async fn get_stream_backfill(stream_name: Path<String>) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    let Some(progress) = backfill::progress(&stream_name) else {
+        return Err(StreamError::Custom {
+            msg: format!("No backfill of logstream {stream_name} was started"),
+            status: StatusCode::NOT_FOUND,
+        });
+    };
+
+    Ok((web::Json(progress), StatusCode::OK))
+}
+
+/// Windows of recent data, looked through in order, when tailing events that are no longer in memory
+const TAIL_WINDOWS: [&str; 5] = ["10m", "1h", "1d", "7d", "30d"];
+
+fn default_tail_size() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailParams {
+    #[serde(default = "default_tail_size")]
+    pub n: usize,
+}
+
+// Handler for GET /api/v1/logstream/{logstream}/tail?n=10
+// returns the latest n events of the stream, latest first
+pub async fn get_tail(
+    stream_name: Path<String>,
+    web::Query(TailParams { n }): web::Query<TailParams>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    // serve from memory when possible, else look through recent data
+    let mut records = PARSEABLE.get_stream(&stream_name)?.tail(n);
+    if records.iter().map(|rb| rb.num_rows()).sum::<usize>() < n {
+        records = tail_recent_data(&stream_name, n).await?;
+    }
+
+    let events = record_batches_to_json(&records)?;
+    Ok((web::Json(events), StatusCode::OK))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BufferedParams {
+    #[serde(default = "default_tail_size")]
+    pub sample: usize,
+}
+
+// Handler for GET /api/v1/logstream/{logstream}/buffered?sample=10
+// returns the schema, row count and latest rows of what the stream holds in memory, for debugging.
+// Nothing is flushed, and data already on disk or in storage isn't looked at
+pub async fn get_buffered(
+    stream_name: Path<String>,
+    web::Query(BufferedParams { sample }): web::Query<BufferedParams>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.streams.contains(&stream_name) {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    let buffered = PARSEABLE.get_stream(&stream_name)?.buffered(sample);
+    let sample = record_batches_to_json(&buffered.sample)?;
+    Ok((
+        web::Json(json!({
+            "schema": buffered.schema,
+            "rows": buffered.rows,
+            "pendingRows": buffered.pending_rows,
+            "pendingBytes": buffered.pending_bytes,
+            "sample": sample,
+        })),
+        StatusCode::OK,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LiveTailParams {
+    pub filter: Option<String>,
+}
+
+// Handler for GET /api/v1/logstream/{logstream}/livetail?filter=level='error'
+// streams events ingested into the stream from here on, as server-sent events.
+// Slow consumers miss out on events and are notified with a "skipped" event instead
+pub async fn get_live_tail(
+    stream_name: Path<String>,
+    web::Query(LiveTailParams { filter }): web::Query<LiveTailParams>,
+) -> Result<HttpResponse, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    let schema = PARSEABLE.get_stream(&stream_name)?.get_schema();
+    let filter = filter
+        .map(|sql| RowFilter::try_new(&sql, schema.as_ref().clone()))
+        .transpose()
+        .map_err(|err| StreamError::Custom {
+            msg: format!("Invalid filter: {err}"),
+            status: StatusCode::BAD_REQUEST,
+        })?;
+
+    let pipe = LIVETAIL.new_pipe(
+        Alphanumeric.sample_string(&mut rand::thread_rng(), 32),
+        stream_name,
+    );
+    let events = pipe.filter_map(move |message| {
+        future::ready(
+            to_sse_event(message, filter.as_ref())
+                .map(|event| Ok::<_, actix_web::Error>(Bytes::from(event))),
+        )
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+/// Queries progressively wider windows of recent data, until `n` events are found
+async fn tail_recent_data(stream_name: &str, n: usize) -> Result<Vec<RecordBatch>, StreamError> {
+    let time_column = PARSEABLE
+        .get_stream(stream_name)?
+        .get_time_partition()
+        .unwrap_or_else(|| DEFAULT_TIMESTAMP_KEY.to_owned());
+    let sql = format!("SELECT * FROM \"{stream_name}\" ORDER BY \"{time_column}\" DESC LIMIT {n}");
+
+    let mut records = vec![];
+    for window in TAIL_WINDOWS {
+        let query = LogicalQuery {
+            raw_logical_plan: QUERY_SESSION
+                .state()
+                .create_logical_plan(&sql)
+                .await
+                .map_err(anyhow::Error::from)?,
+            time_range: TimeRange::parse_human_time(window, "now").map_err(anyhow::Error::from)?,
+            filter_tag: None,
+        };
+        let (Either::Left(batches), _) = execute(query, stream_name, false)
+            .await
+            .map_err(anyhow::Error::from)?
+        else {
+            unreachable!("non-streaming query returns batches")
+        };
+        records = batches;
+        if records.iter().map(|rb| rb.num_rows()).sum::<usize>() >= n {
+            break;
+        }
+    }
+
+    Ok(records)
+}
+
+// Handler for GET /api/v1/logstream/{logstream}/cardinality?startTime=..&endTime=..
+// returns the approximate number of distinct values of each column within the time range
+pub async fn get_cardinality(
+    stream_name: Path<String>,
+    web::Query(TimeRangeParams {
+        start_time,
+        end_time,
+    }): web::Query<TimeRangeParams>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name).into());
+    }
+
+    let time_range = TimeRange::parse_human_time(&start_time, &end_time)
+        .map_err(|err| StreamError::InvalidQueryParameter(err.to_string()))?;
+
+    // statistics of files in storage don't account for events still in staging
+    let in_staging = is_within_staging_window(&[PartialTimeFilter::High(Bound::Included(
+        time_range.end.naive_utc(),
+    ))]);
+    let bounds = if in_staging {
+        HashMap::new()
+    } else {
+        cardinality::column_bounds(&stream_name, &time_range).await?
+    };
+
+    // columns with a single value as per the statistics need not be scanned
+    let mut estimates: BTreeMap<String, u64> = bounds
+        .iter()
+        .filter(|(_, bound)| **bound == 1)
+        .map(|(name, bound)| (name.clone(), *bound))
+        .collect();
+    let schema = PARSEABLE.get_stream(&stream_name)?.get_schema();
+    let fields = schema
+        .fields()
+        .iter()
+        .filter(|field| !estimates.contains_key(field.name()))
+        .map(|field| field.as_ref());
+    let Some(sql) = cardinality::cardinality_sql(&stream_name, fields) else {
+        return Ok((web::Json(estimates), StatusCode::OK));
+    };
+
+    let query = LogicalQuery {
+        raw_logical_plan: QUERY_SESSION
+            .state()
+            .create_logical_plan(&sql)
+            .await
+            .map_err(anyhow::Error::from)?,
+        time_range,
+        filter_tag: None,
+    };
+    let (Either::Left(batches), _) = execute(query, &stream_name, false)
+        .await
+        .map_err(anyhow::Error::from)?
+    else {
+        unreachable!("non-streaming query returns batches")
+    };
+    if let Some(rb) = batches.first() {
+        for (field, column) in rb.schema().fields().iter().zip(rb.columns()) {
+            let Some(estimate) = column.as_any().downcast_ref::<UInt64Array>() else {
+                continue;
+            };
+            // the sketch can overshoot what the statistics allow for
+            let estimate = match bounds.get(field.name()) {
+                Some(bound) => estimate.value(0).min(*bound),
+                None => estimate.value(0),
+            };
+            estimates.insert(field.name().clone(), estimate);
+        }
+    }
+
+    Ok((web::Json(estimates), StatusCode::OK))
+}
+
 pub async fn get_stats_date(stream_name: &str, date: &str) -> Result<Stats, StreamError> {
     let event_labels = event_labels_date(stream_name, "json", date);
     let storage_size_labels = storage_size_labels_date(stream_name, date);
@@ -408,6 +1146,33 @@ pub async fn get_stream_hot_tier(stream_name: Path<String>) -> Result<impl Respo
     Ok((web::Json(meta), StatusCode::OK))
 }
 
+// Handler for POST /api/v1/logstream/{logstream}/hottier/prefetch?startTime=..&endTime=..
+// downloads the data of the stream within the time range into hot tier, ahead of queries for it
+pub async fn prefetch_stream_hot_tier(
+    stream_name: Path<String>,
+    web::Query(TimeRangeParams {
+        start_time,
+        end_time,
+    }): web::Query<TimeRangeParams>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.check_or_load_stream(&stream_name).await {
+        return Err(StreamNotFound(stream_name).into());
+    }
+
+    let Some(hot_tier_manager) = HotTierManager::global() else {
+        return Err(StreamError::HotTierNotEnabled(stream_name));
+    };
+    let time_range = TimeRange::parse_human_time(&start_time, &end_time)
+        .map_err(|err| StreamError::InvalidQueryParameter(err.to_string()))?;
+    let prefetched = hot_tier_manager.prefetch(&stream_name, time_range).await?;
+
+    Ok((
+        web::Json(json!({ "prefetched": prefetched })),
+        StatusCode::OK,
+    ))
+}
+
 pub async fn delete_stream_hot_tier(
     stream_name: Path<String>,
 ) -> Result<impl Responder, StreamError> {
@@ -455,6 +1220,7 @@ pub mod error {
     use http::StatusCode;
 
     use crate::{
+        handlers::http::templates::TemplateError,
         hottier::HotTierError,
         parseable::StreamNotFound,
         storage::ObjectStorageError,
@@ -526,6 +1292,8 @@ pub mod error {
         HotTierError(#[from] HotTierError),
         #[error("Invalid query parameter: {0}")]
         InvalidQueryParameter(String),
+        #[error("{0}")]
+        Template(#[from] TemplateError),
     }
 
     impl actix_web::ResponseError for StreamError {
@@ -562,6 +1330,7 @@ pub mod error {
                 StreamError::HotTierValidation(_) => StatusCode::BAD_REQUEST,
                 StreamError::HotTierError(_) => StatusCode::INTERNAL_SERVER_ERROR,
                 StreamError::InvalidQueryParameter(_) => StatusCode::BAD_REQUEST,
+                StreamError::Template(err) => actix_web::ResponseError::status_code(err),
             }
         }
 
@@ -579,6 +1348,12 @@ mod tests {
         event::format::LogSource, handlers::http::modal::utils::logstream_utils::PutStreamHeaders,
     };
     use actix_web::test::TestRequest;
+    use arrow_schema::DataType;
+    use serde_json::json;
+
+    use super::{
+        delete_range, infer_preview_schema, merge_settings, StreamSettings, TimeRangeParams,
+    };
 
     // TODO: Fix this test with routes
     // #[actix_web::test]
@@ -637,4 +1412,189 @@ mod tests {
             LogSource::Custom(src) if src == "teststream"
         );
     }
+
+    #[test]
+    fn preview_nested_event_schema() {
+        let json = json!({
+            "level": "info",
+            "http": {
+                "status": 200,
+                "path": "/api"
+            }
+        });
+
+        let schema = infer_preview_schema(json).unwrap();
+        assert_eq!(
+            schema.field_with_name("level").unwrap().data_type(),
+            &DataType::Utf8
+        );
+        let DataType::Struct(fields) = schema.field_with_name("http").unwrap().data_type() else {
+            panic!("nested object should be inferred as a struct")
+        };
+        assert_eq!(fields.len(), 2);
+        assert!(infer_preview_schema(json!([1, 2])).is_err());
+    }
+
+    #[actix_web::test]
+    async fn previewed_schema_is_served_without_creating_a_stream() {
+        use actix_web::{
+            http::StatusCode,
+            test::{call_service, init_service, read_body},
+            App,
+        };
+        use arrow_schema::Schema;
+
+        use crate::{
+            handlers::http::modal::server::Server, parseable::PARSEABLE, rbac::map::init_for_tests,
+        };
+
+        init_for_tests();
+        let app = init_service(App::new().service(Server::get_logstream_webscope())).await;
+        let req = TestRequest::post()
+            .uri("/logstream/schema/preview")
+            .insert_header(("Authorization", "Basic YWRtaW46YWRtaW4="))
+            .set_json(json!({
+                "level": "info",
+                "http": {"status": 200, "path": "/api"}
+            }))
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let schema: Schema = serde_json::from_slice(&read_body(res).await).unwrap();
+        let DataType::Struct(fields) = schema.field_with_name("http").unwrap().data_type() else {
+            panic!("nested object should be previewed as a struct")
+        };
+        assert_eq!(fields.len(), 2);
+        // nothing is ingested, the path isn't taken for a stream by the name of `schema`
+        assert!(!PARSEABLE.streams.contains("schema"));
+    }
+
+    #[actix_web::test]
+    async fn deleting_a_range_drops_its_files_from_snapshot_and_storage() {
+        use std::sync::Arc;
+
+        use actix_web::web;
+        use arrow_array::{RecordBatch, TimestampMillisecondArray};
+        use bytes::Bytes;
+        use chrono::{TimeZone, Utc};
+        use parquet::arrow::ArrowWriter;
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog::{self, partition_path},
+            event::DEFAULT_TIMESTAMP_KEY,
+            parseable::PARSEABLE,
+            storage::{ObjectStorageError, ObjectStoreFormat},
+        };
+
+        let stream_name = "delete_range";
+        PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        let batch_at = |hour: u32| {
+            let at = Utc.with_ymd_and_hms(2025, 1, 1, hour, 30, 0).unwrap();
+            RecordBatch::try_from_iter([(
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as _,
+            )])
+            .unwrap()
+        };
+        store
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat::default(),
+                batch_at(10).schema(),
+            )
+            .await
+            .unwrap();
+        let mut paths = vec![];
+        for hour in [10, 11] {
+            let batch = batch_at(hour);
+            let mut parquet = vec![];
+            let mut writer = ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+            let parquet = Bytes::from(parquet);
+            let path = RelativePathBuf::from(format!(
+                "{stream_name}/date=2025-01-01/hour={hour}/minute=30/host.data.parquet"
+            ));
+            store.put_object(&path, parquet.clone()).await.unwrap();
+            let file = catalog::manifest::create_from_parquet(
+                store.absolute_url(&path).to_string(),
+                parquet.clone(),
+                parquet.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+            paths.push(path);
+        }
+
+        let range = |start: &str, end: &str| {
+            web::Query(TimeRangeParams {
+                start_time: start.to_owned(),
+                end_time: end.to_owned(),
+            })
+        };
+        assert!(delete_range(
+            web::Path::from(stream_name.to_owned()),
+            range("2025-01-01T10:00:00Z", "2025-01-01T11:00:00Z"),
+        )
+        .await
+        .is_ok());
+
+        // only the file of the hour deleted is gone, from the manifest as well as storage
+        assert!(matches!(
+            store.get_object(&paths[0]).await,
+            Err(ObjectStorageError::NoSuchKey(_))
+        ));
+        assert!(store.get_object(&paths[1]).await.is_ok());
+        let snapshot = store
+            .get_object_store_format(stream_name)
+            .await
+            .unwrap()
+            .snapshot;
+        assert_eq!(snapshot.manifest_list.len(), 1);
+        let item = &snapshot.manifest_list[0];
+        let manifest = store
+            .get_manifest(&partition_path(
+                stream_name,
+                item.time_lower_bound,
+                item.time_upper_bound,
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert!(manifest.files[0]
+            .file_path
+            .ends_with("hour=11/minute=30/host.data.parquet"));
+    }
+
+    #[test]
+    fn settings_patch_is_merged_over_current_settings() {
+        let current = StreamSettings {
+            exclude_columns: vec!["password".to_owned()],
+            dedup_window: Some("10m".to_owned()),
+            ..Default::default()
+        };
+        let patch = |patch: serde_json::Value| {
+            let serde_json::Value::Object(patch) = patch else {
+                unreachable!()
+            };
+            merge_settings(&current, patch)
+        };
+
+        // settings missing from the patch are kept, null ones are cleared
+        let settings =
+            patch(json!({"dedup_window": null, "parquet_target_size": "1 KiB"})).unwrap();
+        assert_eq!(settings.exclude_columns, vec!["password".to_owned()]);
+        assert_eq!(settings.dedup_window, None);
+        assert_eq!(settings.parquet_target_size, Some(1024));
+
+        assert!(patch(json!({"unknown": true})).is_err());
+        assert!(patch(json!({"declared_columns": ["level"]})).is_err());
+        assert!(patch(json!({"frozen": "yes"})).is_err());
+    }
 }
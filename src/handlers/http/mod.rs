@@ -32,8 +32,10 @@ use self::query::Query;
 pub mod about;
 pub mod alerts;
 mod audit;
+pub mod backup;
 pub mod cluster;
 pub mod correlation;
+pub mod error;
 pub mod health_check;
 pub mod ingest;
 mod kinesis;
@@ -47,8 +49,12 @@ pub mod prism_logstream;
 pub mod query;
 pub mod rbac;
 pub mod role;
+pub mod settings;
+pub mod templates;
 pub mod users;
 pub const MAX_EVENT_PAYLOAD_SIZE: usize = 10485760;
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
 pub const API_BASE_PATH: &str = "api";
 pub const API_VERSION: &str = "v1";
 pub const PRISM_BASE_PATH: &str = "prism";
@@ -19,7 +19,7 @@
 use std::fs;
 
 use actix_web::{
-    web::{Json, Path},
+    web::{self, Json, Path},
     HttpRequest, Responder,
 };
 use bytes::Bytes;
@@ -27,10 +27,12 @@ use http::StatusCode;
 use tracing::warn;
 
 use crate::{
-    catalog::remove_manifest_from_snapshot,
-    handlers::http::logstream::error::StreamError,
+    catalog::{deletion, remove_manifest_from_snapshot},
+    handlers::http::logstream::{error::StreamError, TimeRangeParams},
+    metadata::StreamSettings,
     parseable::{StreamNotFound, PARSEABLE},
     stats,
+    utils::time::TimeRange,
 };
 
 pub async fn retention_cleanup(
@@ -57,6 +59,32 @@ pub async fn retention_cleanup(
     Ok((first_event_at, StatusCode::OK))
 }
 
+// deletes the data of the stream within the time range from the snapshot of this ingestor and
+// from storage, as forwarded by the querier
+pub async fn delete_range(
+    stream_name: Path<String>,
+    web::Query(TimeRangeParams {
+        start_time,
+        end_time,
+    }): web::Query<TimeRangeParams>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    if !PARSEABLE.streams.contains(&stream_name)
+        && !PARSEABLE
+            .create_stream_and_schema_from_storage(&stream_name)
+            .await
+            .unwrap_or(false)
+    {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    let time_range = TimeRange::parse_human_time(&start_time, &end_time)
+        .map_err(|err| StreamError::InvalidQueryParameter(err.to_string()))?;
+    let deleted = deletion::delete_range(&stream_name, time_range).await?;
+
+    Ok((deleted.to_string(), StatusCode::OK))
+}
+
 pub async fn delete(stream_name: Path<String>) -> Result<impl Responder, StreamError> {
     let stream_name = stream_name.into_inner();
 
@@ -90,3 +118,31 @@ pub async fn put_stream(
 
     Ok(("Log stream created", StatusCode::OK))
 }
+
+pub async fn put_stream_settings(
+    stream_name: Path<String>,
+    Json(settings): Json<StreamSettings>,
+) -> Result<impl Responder, StreamError> {
+    let stream_name = stream_name.into_inner();
+    // if the stream not found in memory map,
+    //check if it exists in the storage
+    //create stream and schema from storage
+    if !PARSEABLE.streams.contains(&stream_name)
+        && !PARSEABLE
+            .create_stream_and_schema_from_storage(&stream_name)
+            .await
+            .unwrap_or(false)
+    {
+        return Err(StreamNotFound(stream_name.clone()).into());
+    }
+
+    // settings were validated by the querier
+    PARSEABLE
+        .update_stream_settings(&stream_name, settings)
+        .await?;
+
+    Ok((
+        format!("settings updated for log stream {stream_name}"),
+        StatusCode::OK,
+    ))
+}
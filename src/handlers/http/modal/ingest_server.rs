@@ -19,6 +19,8 @@
 use std::sync::Arc;
 use std::thread;
 
+use actix_web::guard;
+use actix_web::http::header::CONTENT_TYPE;
 use actix_web::web;
 use actix_web::Scope;
 use actix_web_prometheus::PrometheusMetrics;
@@ -29,16 +31,18 @@ use relative_path::RelativePathBuf;
 use serde_json::Value;
 use tokio::sync::oneshot;
 use tokio::sync::OnceCell;
+use tracing::error;
 
 use crate::handlers::http::modal::NodeType;
 use crate::{
     analytics,
+    event::counter::EVENT_COUNTER,
     handlers::{
         airplane,
         http::{
             base_path, ingest, logstream,
             middleware::{DisAllowRootUser, RouteExt},
-            role,
+            role, MAX_EVENT_PAYLOAD_SIZE, MSGPACK_CONTENT_TYPE, NDJSON_CONTENT_TYPE,
         },
     },
     migration,
@@ -67,14 +71,19 @@ impl ParseableServer for IngestServer {
                 // Base path "{url}/api/v1"
                 web::scope(&base_path())
                     .service(Server::get_ingest_factory())
+                    .service(Server::get_ingest_batch_factory())
+                    .service(Server::get_ingest_stream_factory())
                     .service(Self::logstream_api())
                     .service(Server::get_about_factory())
+                    .service(Server::get_events_count_factory())
+                    .service(Server::get_build_info_factory())
                     .service(Self::analytics_factory())
                     .service(Server::get_liveness_factory())
                     .service(Self::get_user_webscope())
                     .service(Self::get_user_role_webscope())
                     .service(Server::get_metrics_webscope())
-                    .service(Server::get_readiness_factory()),
+                    .service(Server::get_readiness_factory())
+                    .service(Server::get_settings_webscope()),
             )
             .service(Server::get_ingest_otel_factory());
     }
@@ -114,6 +123,11 @@ impl ParseableServer for IngestServer {
 
         migration::run_migration(&PARSEABLE).await?;
 
+        // continue counting from the events ingested before a restart
+        if let Err(err) = EVENT_COUNTER.load().await {
+            error!("Failed to load the count of ingested events: {err}");
+        }
+
         // Run sync on a background thread
         let (cancel_tx, cancel_rx) = oneshot::channel();
         thread::spawn(|| sync::handler(cancel_rx));
@@ -208,13 +222,50 @@ impl IngestServer {
             web::scope("/{logstream}")
                 .service(
                     web::resource("")
+                        // POST "/logstream/{logstream}" ==> Post msgpack encoded logs to given log stream
+                        .route(
+                            web::post()
+                                .guard(guard::Header(CONTENT_TYPE.as_str(), MSGPACK_CONTENT_TYPE))
+                                .to(ingest::post_msgpack_event)
+                                .authorize_for_stream(Action::Ingest),
+                        )
+                        // POST "/logstream/{logstream}" ==> Post newline delimited JSON logs to given log stream
+                        .route(
+                            web::post()
+                                .guard(guard::Header(CONTENT_TYPE.as_str(), NDJSON_CONTENT_TYPE))
+                                .to(ingest::post_ndjson_event)
+                                .authorize_for_stream(Action::Ingest),
+                        )
                         // POST "/logstream/{logstream}" ==> Post logs to given log stream
                         .route(
                             web::post()
                                 .to(ingest::post_event)
                                 .authorize_for_stream(Action::Ingest),
+                        )
+                        .app_data(web::PayloadConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE)),
+                )
+                .service(Server::get_protobuf_factory())
+                .service(
+                    web::resource("/settings")
+                        // GET "/logstream/{logstream}/settings" ==> Get the settings of given logstream
+                        .route(
+                            web::get()
+                                .to(logstream::get_stream_settings)
+                                .authorize_for_stream(Action::GetStreamInfo),
+                        ),
+                )
+                .service(
+                    web::resource("/settings/sync")
+                        // PUT "/logstream/{logstream}/settings/sync" ==> Sync the settings of given logstream
+                        .route(
+                            web::put()
+                                .to(ingestor_logstream::put_stream_settings)
+                                .authorize_for_stream(Action::CreateStream),
                         ),
                 )
+                .service(Server::get_backfill_factory())
+                .service(Server::get_live_tail_factory())
+                .service(Server::get_buffered_factory())
                 .service(
                     web::resource("/sync")
                         // DELETE "/logstream/{logstream}/sync" ==> Sync deletion of a log stream
@@ -254,6 +305,14 @@ impl IngestServer {
                                 .authorize_for_stream(Action::PutRetention),
                         ),
                     ),
+                )
+                .service(
+                    // DELETE "/logstream/{logstream}/data" ==> Delete the data of given logstream within a time range
+                    web::resource("/data").route(
+                        web::delete()
+                            .to(ingestor_logstream::delete_range)
+                            .authorize_for_stream(Action::DeleteStream),
+                    ),
                 ),
         )
     }
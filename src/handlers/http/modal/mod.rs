@@ -37,6 +37,7 @@ use crate::{
     alerts::ALERTS,
     cli::Options,
     correlation::CORRELATIONS,
+    event::counter::EVENT_COUNTER,
     oidc::Claims,
     option::Mode,
     parseable::PARSEABLE,
@@ -196,6 +197,14 @@ pub async fn load_on_init() -> anyhow::Result<()> {
         error!("{err}");
     }
 
+    if let Err(err) = EVENT_COUNTER
+        .load()
+        .await
+        .context("Failed to load the count of ingested events")
+    {
+        error!("{err}");
+    }
+
     Ok(())
 }
 
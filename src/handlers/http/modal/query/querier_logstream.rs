@@ -66,6 +66,7 @@ pub async fn delete(stream_name: Path<String>) -> Result<impl Responder, StreamE
 
     let objectstore = PARSEABLE.storage.get_object_store();
     // Delete from storage
+    PARSEABLE.delete_stream_data(&stream_name).await?;
     objectstore.delete_stream(&stream_name).await?;
     let stream_dir = PARSEABLE.get_or_create_stream(&stream_name);
     if let Err(err) = fs::remove_dir_all(&stream_dir.data_path) {
@@ -19,12 +19,12 @@
 use std::sync::Arc;
 use std::thread;
 
-use crate::handlers::airplane;
 use crate::handlers::http::cluster::{self, init_cluster_metrics_schedular};
 use crate::handlers::http::middleware::{DisAllowRootUser, RouteExt};
 use crate::handlers::http::{base_path, prism_base_path};
 use crate::handlers::http::{logstream, MAX_EVENT_PAYLOAD_SIZE};
 use crate::handlers::http::{rbac, role};
+use crate::handlers::{airplane, flight_sql};
 use crate::hottier::HotTierManager;
 use crate::rbac::role::Action;
 use crate::{analytics, migration, storage, sync};
@@ -53,9 +53,12 @@ impl ParseableServer for QueryServer {
                 web::scope(&base_path())
                     .service(Server::get_correlation_webscope())
                     .service(Server::get_query_factory())
+                    .service(Server::get_query_files_factory())
                     .service(Server::get_liveness_factory())
                     .service(Server::get_readiness_factory())
                     .service(Server::get_about_factory())
+                    .service(Server::get_events_count_factory())
+                    .service(Server::get_build_info_factory())
                     .service(Self::get_logstream_webscope())
                     .service(Self::get_user_webscope())
                     .service(Server::get_users_webscope())
@@ -66,8 +69,11 @@ impl ParseableServer for QueryServer {
                     .service(Self::get_user_role_webscope())
                     .service(Server::get_roles_webscope())
                     .service(Server::get_counts_webscope())
+                    .service(Server::get_volume_webscope())
                     .service(Server::get_metrics_webscope())
                     .service(Server::get_alerts_webscope())
+                    .service(Server::get_metadata_webscope())
+                    .service(Server::get_templates_webscope())
                     .service(Self::get_cluster_web_scope()),
             )
             .service(
@@ -136,6 +142,9 @@ impl ParseableServer for QueryServer {
         thread::spawn(|| sync::handler(cancel_rx));
 
         tokio::spawn(airplane::server());
+        if PARSEABLE.options.flight_sql_port.is_some() {
+            tokio::spawn(flight_sql::server());
+        }
 
         let result = self
             .start(shutdown_rx, prometheus.clone(), PARSEABLE.options.openid())
@@ -238,6 +247,14 @@ impl QueryServer {
                         ),
                 ),
             )
+            .service(
+                // POST "/logstream/schema/preview" ==> Preview the schema inferred during ingestion
+                web::resource("/schema/preview").route(
+                    web::post()
+                        .to(logstream::preview_schema)
+                        .authorize(Action::DetectSchema),
+                ),
+            )
             .service(
                 web::scope("/{logstream}")
                     .service(
@@ -319,7 +336,19 @@ impl QueryServer {
                                     .to(logstream::delete_stream_hot_tier)
                                     .authorize_for_stream(Action::DeleteHotTierEnabled),
                             ),
-                    ),
+                    )
+                    .service(
+                        // POST "/logstream/{logstream}/hottier/prefetch" ==> Download data of given logstream within a time range into hot tier
+                        web::resource("/hottier/prefetch").route(
+                            web::post()
+                                .to(logstream::prefetch_stream_hot_tier)
+                                .authorize_for_stream(Action::Query),
+                        ),
+                    )
+                    .service(Server::get_stream_settings_factory())
+                    .service(Server::get_data_factory())
+                    .service(Server::get_tail_factory())
+                    .service(Server::get_cardinality_factory()),
             )
     }
 
@@ -28,12 +28,15 @@ use crate::handlers::http::prism_base_path;
 use crate::handlers::http::query;
 use crate::handlers::http::users::dashboards;
 use crate::handlers::http::users::filters;
+use crate::handlers::http::{backup, settings, templates};
 use crate::hottier::HotTierManager;
 use crate::metrics;
 use crate::migration;
 use crate::storage;
 use crate::sync;
 
+use actix_web::guard;
+use actix_web::http::header::CONTENT_TYPE;
 use actix_web::web;
 use actix_web::web::resource;
 use actix_web::Resource;
@@ -45,10 +48,11 @@ use bytes::Bytes;
 use tokio::sync::oneshot;
 
 use crate::{
+    event::counter,
     handlers::http::{
         self, ingest, llm, logstream,
         middleware::{DisAllowRootUser, RouteExt},
-        oidc, role, MAX_EVENT_PAYLOAD_SIZE,
+        oidc, role, MAX_EVENT_PAYLOAD_SIZE, MSGPACK_CONTENT_TYPE, NDJSON_CONTENT_TYPE,
     },
     parseable::PARSEABLE,
     rbac::role::Action,
@@ -71,10 +75,15 @@ impl ParseableServer for Server {
                 web::scope(&base_path())
                     .service(Self::get_correlation_webscope())
                     .service(Self::get_query_factory())
+                    .service(Self::get_query_files_factory())
                     .service(Self::get_ingest_factory())
+                    .service(Self::get_ingest_batch_factory())
+                    .service(Self::get_ingest_stream_factory())
                     .service(Self::get_liveness_factory())
                     .service(Self::get_readiness_factory())
                     .service(Self::get_about_factory())
+                    .service(Self::get_events_count_factory())
+                    .service(Self::get_build_info_factory())
                     .service(Self::get_logstream_webscope())
                     .service(Self::get_user_webscope())
                     .service(Self::get_users_webscope())
@@ -85,8 +94,12 @@ impl ParseableServer for Server {
                     .service(Self::get_user_role_webscope())
                     .service(Self::get_roles_webscope())
                     .service(Self::get_counts_webscope())
+                    .service(Self::get_volume_webscope())
                     .service(Self::get_alerts_webscope())
-                    .service(Self::get_metrics_webscope()),
+                    .service(Self::get_metrics_webscope())
+                    .service(Self::get_settings_webscope())
+                    .service(Self::get_metadata_webscope())
+                    .service(Self::get_templates_webscope()),
             )
             .service(
                 web::scope(&prism_base_path())
@@ -136,6 +149,9 @@ impl ParseableServer for Server {
 
         tokio::spawn(handlers::livetail::server());
         tokio::spawn(handlers::airplane::server());
+        if PARSEABLE.options.flight_sql_port.is_some() {
+            tokio::spawn(handlers::flight_sql::server());
+        }
 
         let result = self
             .start(shutdown_rx, prometheus.clone(), PARSEABLE.options.openid())
@@ -306,16 +322,91 @@ impl Server {
                     ),
             )
     }
+    // get the settings web scope, for settings that can be updated at runtime
+    pub fn get_settings_webscope() -> Scope {
+        web::scope("/settings").service(
+            web::resource("/sync")
+                // GET "/settings/sync" ==> Get the sync interval
+                .route(web::get().to(settings::get_sync).authorize(Action::All))
+                // PUT "/settings/sync" ==> Update the sync interval
+                .route(web::put().to(settings::put_sync).authorize(Action::All)),
+        )
+    }
+
+    // get the metadata web scope, for backing up and restoring stream metadata
+    pub fn get_metadata_webscope() -> Scope {
+        web::scope("/metadata")
+            .service(
+                // GET "/metadata/export" ==> Export schema and configuration of all streams
+                web::resource("/export").route(
+                    web::get()
+                        .to(backup::export_metadata)
+                        .authorize(Action::All),
+                ),
+            )
+            .service(
+                // POST "/metadata/import" ==> Recreate streams from an exported bundle
+                web::resource("/import").route(
+                    web::post()
+                        .to(backup::import_metadata)
+                        .authorize(Action::All),
+                ),
+            )
+    }
+
+    // get the templates web scope, for templates that streams are created from
+    pub fn get_templates_webscope() -> Scope {
+        web::scope("/templates")
+            .service(
+                // GET "/templates" ==> List all templates
+                web::resource("")
+                    .route(web::get().to(templates::list).authorize(Action::ListStream)),
+            )
+            .service(
+                web::resource("/{name}")
+                    // GET "/templates/{name}" ==> Get the template
+                    .route(web::get().to(templates::get).authorize(Action::ListStream))
+                    // PUT "/templates/{name}" ==> Create or replace the template
+                    .route(
+                        web::put()
+                            .to(templates::put)
+                            .authorize(Action::CreateStream),
+                    )
+                    // DELETE "/templates/{name}" ==> Delete the template
+                    .route(
+                        web::delete()
+                            .to(templates::delete)
+                            .authorize(Action::DeleteStream),
+                    ),
+            )
+    }
+
     pub fn get_counts_webscope() -> Resource {
         web::resource("/counts").route(web::post().to(query::get_counts).authorize(Action::Query))
     }
 
+    // get the volume web scope
+    // POST "/volume" ==> Get bytes ingested and stored per time bin of a stream
+    pub fn get_volume_webscope() -> Resource {
+        web::resource("/volume").route(web::post().to(query::get_volume).authorize(Action::Query))
+    }
+
     // get the query factory
     // POST "/query" ==> Get results of the SQL query passed in request body
     pub fn get_query_factory() -> Resource {
         web::resource("/query").route(web::post().to(query::query).authorize(Action::Query))
     }
 
+    // get the query files factory
+    // POST "/query/files" ==> Get the files that the SQL query passed in request body would scan
+    pub fn get_query_files_factory() -> Resource {
+        web::resource("/query/files").route(
+            web::post()
+                .to(query::get_query_files)
+                .authorize(Action::Query),
+        )
+    }
+
     // get the logstream web scope
     pub fn get_logstream_webscope() -> Scope {
         web::scope("/logstream")
@@ -335,6 +426,14 @@ impl Server {
                         ),
                 ),
             )
+            .service(
+                // POST "/logstream/schema/preview" ==> Preview the schema inferred during ingestion
+                web::resource("/schema/preview").route(
+                    web::post()
+                        .to(logstream::preview_schema)
+                        .authorize(Action::DetectSchema),
+                ),
+            )
             .service(
                 web::scope("/{logstream}")
                     .service(
@@ -345,6 +444,26 @@ impl Server {
                                     .to(logstream::put_stream)
                                     .authorize_for_stream(Action::CreateStream),
                             )
+                            // POST "/logstream/{logstream}" ==> Post msgpack encoded logs to given log stream
+                            .route(
+                                web::post()
+                                    .guard(guard::Header(
+                                        CONTENT_TYPE.as_str(),
+                                        MSGPACK_CONTENT_TYPE,
+                                    ))
+                                    .to(ingest::post_msgpack_event)
+                                    .authorize_for_stream(Action::Ingest),
+                            )
+                            // POST "/logstream/{logstream}" ==> Post newline delimited JSON logs to given log stream
+                            .route(
+                                web::post()
+                                    .guard(guard::Header(
+                                        CONTENT_TYPE.as_str(),
+                                        NDJSON_CONTENT_TYPE,
+                                    ))
+                                    .to(ingest::post_ndjson_event)
+                                    .authorize_for_stream(Action::Ingest),
+                            )
                             // POST "/logstream/{logstream}" ==> Post logs to given log stream
                             .route(
                                 web::post()
@@ -357,7 +476,8 @@ impl Server {
                                     .to(logstream::delete)
                                     .authorize_for_stream(Action::DeleteStream),
                             )
-                            .app_data(web::JsonConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE)),
+                            .app_data(web::JsonConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE))
+                            .app_data(web::PayloadConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE)),
                     )
                     .service(
                         // GET "/logstream/{logstream}/info" ==> Get info for given log stream
@@ -416,19 +536,167 @@ impl Server {
                                     .to(logstream::delete_stream_hot_tier)
                                     .authorize_for_stream(Action::DeleteHotTierEnabled),
                             ),
-                    ),
+                    )
+                    .service(
+                        // POST "/logstream/{logstream}/hottier/prefetch" ==> Download data of given logstream within a time range into hot tier
+                        web::resource("/hottier/prefetch").route(
+                            web::post()
+                                .to(logstream::prefetch_stream_hot_tier)
+                                .authorize_for_stream(Action::Query),
+                        ),
+                    )
+                    .service(Self::get_stream_settings_factory())
+                    .service(Self::get_protobuf_factory())
+                    .service(Self::get_backfill_factory())
+                    .service(Self::get_data_factory())
+                    .service(Self::get_tail_factory())
+                    .service(Self::get_buffered_factory())
+                    .service(Self::get_cardinality_factory())
+                    .service(Self::get_live_tail_factory()),
+            )
+    }
+
+    // get the factory for the latest events of a logstream
+    pub fn get_tail_factory() -> Resource {
+        // GET "/logstream/{logstream}/tail" ==> Get the latest events of given logstream
+        web::resource("/tail").route(
+            web::get()
+                .to(logstream::get_tail)
+                .authorize_for_stream(Action::Query),
+        )
+    }
+
+    // get the factory for inspecting the records of a logstream held in memory
+    pub fn get_buffered_factory() -> Resource {
+        // GET "/logstream/{logstream}/buffered" ==> Get the records of given logstream buffered in memory
+        web::resource("/buffered").route(
+            web::get()
+                .to(logstream::get_buffered)
+                .authorize_for_stream(Action::All),
+        )
+    }
+
+    // get the factory for the cardinality estimates of the columns of a logstream
+    pub fn get_cardinality_factory() -> Resource {
+        // GET "/logstream/{logstream}/cardinality" ==> Get the approximate number of distinct values in each column of given logstream
+        web::resource("/cardinality").route(
+            web::get()
+                .to(logstream::get_cardinality)
+                .authorize_for_stream(Action::Query),
+        )
+    }
+
+    // get the factory for streaming events of a logstream as they are ingested
+    pub fn get_live_tail_factory() -> Resource {
+        // GET "/logstream/{logstream}/livetail" ==> Subscribe to events ingested into given logstream
+        web::resource("/livetail").route(
+            web::get()
+                .to(logstream::get_live_tail)
+                .authorize_for_stream(Action::Query),
+        )
+    }
+
+    // get the factory for rewriting the parquet files of a logstream in its current schema
+    pub fn get_backfill_factory() -> Resource {
+        web::resource("/backfill")
+            // PUT "/logstream/{logstream}/backfill" ==> Start backfilling given logstream
+            .route(
+                web::put()
+                    .to(logstream::put_stream_backfill)
+                    .authorize_for_stream(Action::CreateStream),
+            )
+            // GET "/logstream/{logstream}/backfill" ==> Get progress of backfilling given logstream
+            .route(
+                web::get()
+                    .to(logstream::get_stream_backfill)
+                    .authorize_for_stream(Action::GetStreamInfo),
+            )
+    }
+
+    // get the factory for the data of a logstream within a time range
+    pub fn get_data_factory() -> Resource {
+        // DELETE "/logstream/{logstream}/data" ==> Delete the data of given logstream within a time range
+        web::resource("/data").route(
+            web::delete()
+                .to(logstream::delete_range)
+                .authorize_for_stream(Action::DeleteStream),
+        )
+    }
+
+    // get the factory for the settings of a logstream
+    pub fn get_stream_settings_factory() -> Resource {
+        web::resource("/settings")
+            // PUT "/logstream/{logstream}/settings" ==> Update the settings given in the body for given logstream
+            .route(
+                web::put()
+                    .to(logstream::put_stream_settings)
+                    .authorize_for_stream(Action::CreateStream),
+            )
+            // GET "/logstream/{logstream}/settings" ==> Get the settings of given logstream
+            .route(
+                web::get()
+                    .to(logstream::get_stream_settings)
+                    .authorize_for_stream(Action::GetStreamInfo),
+            )
+    }
+
+    // get the factory for ingesting protobuf encoded events into a logstream
+    pub fn get_protobuf_factory() -> Resource {
+        // POST "/logstream/{logstream}/protobuf" ==> Post protobuf encoded event to given logstream
+        web::resource("/protobuf")
+            .route(
+                web::post()
+                    .to(ingest::post_protobuf_event)
+                    .authorize_for_stream(Action::Ingest),
+            )
+            .app_data(web::PayloadConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE))
+    }
+
+    // get the factory for the ingest route of batches of events destined for different streams
+    pub fn get_ingest_batch_factory() -> Resource {
+        web::resource("/ingest/batch")
+            // POST "/ingest/batch" ==> Post logs to the log streams named in each of them
+            // streams are authorized by the handler, as they are only known from the body
+            .route(web::post().to(ingest::ingest_batch))
+            .app_data(web::JsonConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE))
+            .app_data(web::PayloadConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE))
+    }
+
+    // get the factory for the ingest route of events streamed over a long-lived connection
+    pub fn get_ingest_stream_factory() -> Resource {
+        web::resource("/ingest/stream")
+            // POST "/ingest/stream" ==> Stream NDJSON logs to the log stream in header
+            .route(
+                web::post()
+                    .to(ingest::ingest_stream)
+                    .authorize_for_stream(Action::Ingest),
             )
     }
 
     // get the factory for the ingest route
     pub fn get_ingest_factory() -> Resource {
         web::resource("/ingest")
+            // POST "/ingest" ==> Post msgpack encoded logs to the log stream in header
+            .route(
+                web::post()
+                    .guard(guard::Header(CONTENT_TYPE.as_str(), MSGPACK_CONTENT_TYPE))
+                    .to(ingest::ingest_msgpack)
+                    .authorize_for_stream(Action::Ingest),
+            )
+            // POST "/ingest" ==> Post newline delimited JSON logs to the log stream in header
+            .route(
+                web::post()
+                    .guard(guard::Header(CONTENT_TYPE.as_str(), NDJSON_CONTENT_TYPE))
+                    .to(ingest::ingest_ndjson)
+                    .authorize_for_stream(Action::Ingest),
+            )
             .route(
                 web::post()
                     .to(ingest::ingest)
                     .authorize_for_stream(Action::Ingest),
             )
             .app_data(web::JsonConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE))
+            .app_data(web::PayloadConfig::default().limit(MAX_EVENT_PAYLOAD_SIZE))
     }
 
     // /v1/logs endpoint to be used for OTEL log ingestion only
@@ -614,6 +882,21 @@ impl Server {
         web::resource("/about").route(web::get().to(about::about).authorize(Action::GetAbout))
     }
 
+    // get the factory for the count of events ingested into all streams
+    pub fn get_events_count_factory() -> Resource {
+        web::resource("/events/count").route(
+            web::get()
+                .to(counter::get_events_count)
+                .authorize(Action::GetAnalytics),
+        )
+    }
+
+    // get the factory for the versions of the server and the crates it was built against
+    pub fn get_build_info_factory() -> Resource {
+        web::resource(["/version", "/build-info"])
+            .route(web::get().to(about::build_info).authorize(Action::GetAbout))
+    }
+
     // GET "/" ==> Serve the static frontend directory
     pub fn get_generated() -> ResourceFiles {
         ResourceFiles::new("/", generate()).resolve_not_found_to_root()
@@ -17,34 +17,47 @@
  */
 
 use actix_web::HttpRequest;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use http::header::USER_AGENT;
 use opentelemetry_proto::tonic::{
     logs::v1::LogsData, metrics::v1::MetricsData, trace::v1::TracesData,
 };
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future, sync::Arc};
 use tracing::warn;
 
 use crate::{
     event::{
+        column_limit, commit_schema,
+        error::EventError,
         format::{json, EventFormat, LogSource},
-        FORMAT_KEY, SOURCE_IP_KEY, USER_AGENT_KEY,
+        FORMAT_KEY, PARTITION_KEY, SOURCE_IP_KEY, USER_AGENT_KEY,
     },
     handlers::{
         http::{
             ingest::PostError,
             kinesis::{flatten_kinesis_logs, Message},
         },
-        EXTRACT_LOG_KEY, LOG_SOURCE_KEY, STREAM_NAME_HEADER_KEY,
+        EXTRACT_LOG_KEY, LOG_SOURCE_KEY, PARTIAL_SUCCESS_KEY, PARTITION_KEY_HEADER,
+        STREAM_NAME_HEADER_KEY,
     },
     otel::{logs::flatten_otel_logs, metrics::flatten_otel_metrics, traces::flatten_otel_traces},
     parseable::PARSEABLE,
-    storage::StreamType,
-    utils::json::{convert_array_to_object, flatten::convert_to_array},
+    storage::{stream_data_root, StreamType},
+    utils::json::{
+        convert_array_to_object,
+        flatten::{apply_clock_skew, apply_field_length_limits, convert_to_array},
+    },
 };
 
-const IGNORE_HEADERS: [&str; 3] = [STREAM_NAME_HEADER_KEY, LOG_SOURCE_KEY, EXTRACT_LOG_KEY];
+const IGNORE_HEADERS: [&str; 5] = [
+    STREAM_NAME_HEADER_KEY,
+    LOG_SOURCE_KEY,
+    EXTRACT_LOG_KEY,
+    PARTIAL_SUCCESS_KEY,
+    PARTITION_KEY_HEADER,
+];
 const MAX_CUSTOM_FIELDS: usize = 10;
 const MAX_FIELD_VALUE_LENGTH: usize = 100;
 
@@ -56,48 +69,324 @@ pub async fn flatten_and_push_logs(
 ) -> Result<(), PostError> {
     // Verify the dataset fields count
     verify_dataset_fields_count(stream_name)?;
+    PARSEABLE.reconcile_schema(stream_name).await?;
 
+    // only the batches of this request are waited on to be acknowledged
+    let mut seq = 0;
     match log_source {
         LogSource::Kinesis => {
             //custom flattening required for Amazon Kinesis
             let message: Message = serde_json::from_value(json)?;
             let flattened_kinesis_data = flatten_kinesis_logs(message).await?;
             let record = convert_to_array(flattened_kinesis_data)?;
-            push_logs(stream_name, record, log_source, p_custom_fields).await?;
+            seq = pushed_seq(&push_logs(stream_name, record, log_source, p_custom_fields).await?);
         }
         LogSource::OtelLogs => {
             //custom flattening required for otel logs
             let logs: LogsData = serde_json::from_value(json)?;
             for record in flatten_otel_logs(&logs) {
-                push_logs(stream_name, record, log_source, p_custom_fields).await?;
+                seq = seq.max(pushed_seq(
+                    &push_logs(stream_name, record, log_source, p_custom_fields).await?,
+                ));
             }
         }
         LogSource::OtelTraces => {
             //custom flattening required for otel traces
             let traces: TracesData = serde_json::from_value(json)?;
             for record in flatten_otel_traces(&traces) {
-                push_logs(stream_name, record, log_source, p_custom_fields).await?;
+                seq = seq.max(pushed_seq(
+                    &push_logs(stream_name, record, log_source, p_custom_fields).await?,
+                ));
             }
         }
         LogSource::OtelMetrics => {
             //custom flattening required for otel metrics
             let metrics: MetricsData = serde_json::from_value(json)?;
             for record in flatten_otel_metrics(metrics) {
-                push_logs(stream_name, record, log_source, p_custom_fields).await?;
+                seq = seq.max(pushed_seq(
+                    &push_logs(stream_name, record, log_source, p_custom_fields).await?,
+                ));
             }
         }
-        _ => push_logs(stream_name, json, log_source, p_custom_fields).await?,
+        _ => {
+            seq = pushed_seq(&push_logs(stream_name, json, log_source, p_custom_fields).await?);
+        }
     }
 
+    PARSEABLE
+        .get_stream(stream_name)?
+        .acknowledge(seq)
+        .await
+        .map_err(EventError::Staging)?;
+
     Ok(())
 }
 
+/// Records of a request body, along with the lines of it that couldn't be parsed
+#[derive(Debug)]
+pub struct Batch {
+    /// An event or an array of them
+    pub json: Value,
+    /// Position in the body and line of each of the records, when parsed line by line
+    lines: Option<Vec<(usize, usize)>>,
+    failed: Vec<FailedRecord>,
+}
+
+impl From<Value> for Batch {
+    fn from(json: Value) -> Self {
+        Self {
+            json,
+            lines: None,
+            failed: vec![],
+        }
+    }
+}
+
+impl Batch {
+    /// Parses a body of newline delimited JSON, a line at a time so that a malformed line is
+    /// failed on its own, blank lines are skipped
+    pub fn from_ndjson(body: &[u8]) -> Self {
+        let mut records = vec![];
+        let mut lines = vec![];
+        let mut failed = vec![];
+        let events = body
+            .split(|&byte| byte == b'\n')
+            .map(<[u8]>::trim_ascii)
+            .enumerate()
+            .filter(|(_, event)| !event.is_empty());
+        for (index, (line, event)) in events.enumerate() {
+            // lines are numbered from 1, as by editors
+            let line = line + 1;
+            match serde_json::from_slice(event) {
+                Ok(json) => {
+                    records.push(json);
+                    lines.push((index, line));
+                }
+                Err(err) => failed.push(FailedRecord {
+                    index,
+                    line: Some(line),
+                    error: err.to_string(),
+                }),
+            }
+        }
+
+        Self {
+            json: Value::Array(records),
+            lines: Some(lines),
+            failed,
+        }
+    }
+
+    /// Fails on the first malformed line, for batches that are ingested as a whole
+    pub fn check_parsed(&self) -> Result<(), PostError> {
+        match self.failed.first() {
+            Some(FailedRecord {
+                line: Some(line),
+                error,
+                ..
+            }) => Err(PostError::MalformedLine(*line, error.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records of the batch along with their position in it, and those that couldn't be parsed
+    fn into_records(self) -> (Vec<BatchRecord>, Vec<FailedRecord>) {
+        let records = match self.json {
+            Value::Array(arr) => arr,
+            value => vec![value],
+        };
+        let records = match self.lines {
+            Some(lines) => records
+                .into_iter()
+                .zip(lines)
+                .map(|(json, (index, line))| BatchRecord {
+                    index,
+                    line: Some(line),
+                    json,
+                })
+                .collect(),
+            None => records
+                .into_iter()
+                .enumerate()
+                .map(|(index, json)| BatchRecord {
+                    index,
+                    line: None,
+                    json,
+                })
+                .collect(),
+        };
+
+        (records, self.failed)
+    }
+}
+
+/// A record of a batch, with its position in the batch and the line of the body it is on
+#[derive(Debug)]
+struct BatchRecord {
+    index: usize,
+    line: Option<usize>,
+    json: Value,
+}
+
+/// Outcome of ingesting the records of a batch one at a time
+#[derive(Debug, Default, Serialize)]
+pub struct IngestionSummary {
+    pub accepted: usize,
+    /// Where each of the accepted records was put
+    pub records: Vec<AcceptedRecord>,
+    pub failed: Vec<FailedRecord>,
+    /// Sequence number of the last batch the records were pushed with, that they are acknowledged on
+    #[serde(skip)]
+    pub seq: u64,
+}
+
+/// Time a record was assigned on ingestion and the prefix in object storage of the partition it
+/// was put in, e.g. `app/date=2025-01-01/hour=10/minute=00`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Placement {
+    pub p_timestamp: DateTime<Utc>,
+    pub partition: String,
+    /// Sequence number the event was pushed into the stream with
+    #[serde(skip)]
+    pub seq: u64,
+}
+
+// Sequence number of the last of the events pushed, for them to be acknowledged on
+fn pushed_seq(placements: &[Placement]) -> u64 {
+    placements
+        .iter()
+        .map(|placement| placement.seq)
+        .max()
+        .unwrap_or(0)
+}
+
+/// A record that was ingested, identified by its position in the batch
+#[derive(Debug, Serialize)]
+pub struct AcceptedRecord {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(flatten)]
+    pub placement: Placement,
+}
+
+/// A record that couldn't be ingested, identified by its position in the batch
+#[derive(Debug, Serialize)]
+pub struct FailedRecord {
+    pub index: usize,
+    /// Line of the body the record is on, for newline delimited JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub error: String,
+}
+
+/// Checks if the client asked for records of the batch to be ingested independently
+pub fn is_partial_success_requested(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(PARTIAL_SUCCESS_KEY)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+/// Ingests the records of a batch such that valid records are committed even when some of the
+/// others fail, the failures, along with the lines that couldn't be parsed, are reported back in
+/// the summary
+pub async fn flatten_and_push_each_log(
+    batch: Batch,
+    stream_name: &str,
+    log_source: &LogSource,
+    p_custom_fields: &HashMap<String, String>,
+) -> Result<IngestionSummary, PostError> {
+    // Verify the dataset fields count
+    verify_dataset_fields_count(stream_name)?;
+    PARSEABLE.reconcile_schema(stream_name).await?;
+
+    // records that are partitioned one at a time are events of their own anyway
+    let stream = PARSEABLE.get_stream(stream_name)?;
+    let grouped = stream.get_time_partition().is_none()
+        && stream.get_custom_partition().is_none()
+        && stream.get_settings().timestamp_fields.is_empty();
+
+    let (records, failed) = batch.into_records();
+    let mut summary = push_each(records, grouped, |record| {
+        push_logs(stream_name, record, log_source, p_custom_fields)
+    })
+    .await;
+    summary.failed.extend(failed);
+    summary.failed.sort_by_key(|failed| failed.index);
+
+    PARSEABLE
+        .get_stream(stream_name)?
+        .acknowledge(summary.seq)
+        .await
+        .map_err(EventError::Staging)?;
+
+    Ok(summary)
+}
+
+/// Pushes the records of a batch as a single event when `grouped`, or when that is rejected, one
+/// record at a time to find those at fault
+async fn push_each<F, Fut>(
+    records: Vec<BatchRecord>,
+    grouped: bool,
+    mut push: F,
+) -> IngestionSummary
+where
+    F: FnMut(Value) -> Fut,
+    Fut: Future<Output = Result<Vec<Placement>, PostError>>,
+{
+    let mut summary = IngestionSummary::default();
+    if grouped && records.len() > 1 {
+        let json = Value::Array(records.iter().map(|record| record.json.clone()).collect());
+        if let Ok(placements) = push(json).await {
+            summary.accepted = records.len();
+            summary.seq = pushed_seq(&placements);
+            // all of the records are put in the partition of the event
+            if let Some(placement) = placements.first() {
+                summary.records = records
+                    .iter()
+                    .map(|record| AcceptedRecord {
+                        index: record.index,
+                        line: record.line,
+                        placement: placement.clone(),
+                    })
+                    .collect();
+            }
+            return summary;
+        }
+    }
+
+    for BatchRecord { index, line, json } in records {
+        match push(json).await {
+            Ok(placements) => {
+                summary.accepted += 1;
+                summary.seq = summary.seq.max(pushed_seq(&placements));
+                // a record is a single event, unless it is flattened into several
+                summary
+                    .records
+                    .extend(placements.into_iter().map(|placement| AcceptedRecord {
+                        index,
+                        line,
+                        placement,
+                    }));
+            }
+            Err(err) => summary.failed.push(FailedRecord {
+                index,
+                line,
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    summary
+}
+
 async fn push_logs(
     stream_name: &str,
     json: Value,
     log_source: &LogSource,
     p_custom_fields: &HashMap<String, String>,
-) -> Result<(), PostError> {
+) -> Result<Vec<Placement>, PostError> {
     let stream = PARSEABLE.get_stream(stream_name)?;
     let time_partition = stream.get_time_partition();
     let time_partition_limit = PARSEABLE
@@ -106,46 +395,190 @@ async fn push_logs(
     let static_schema_flag = stream.get_static_schema_flag();
     let custom_partition = stream.get_custom_partition();
     let schema_version = stream.get_schema_version();
+    let settings = stream.get_settings();
+    let exclude_columns = &settings.exclude_columns;
+    let schema_inference = settings.schema_inference();
+    let boolean_columns = &settings.boolean_columns;
+    let declared_columns = &settings.declared_columns;
+    // the time partition, when set, is required of events and takes precedence
+    let timestamp_fields: &[String] = match time_partition {
+        Some(_) => &[],
+        None => &settings.timestamp_fields,
+    };
     let p_timestamp = Utc::now();
 
-    let data = if time_partition.is_some() || custom_partition.is_some() {
-        convert_array_to_object(
-            json,
-            time_partition.as_ref(),
-            time_partition_limit,
-            custom_partition.as_ref(),
-            schema_version,
-            log_source,
-        )?
+    // the envelope is discarded before anything is inferred from the event
+    let json = match &settings.event_body_path {
+        Some(path) => json::extract_event_body(json, path)?,
+        None => json,
+    };
+    let json = if PARSEABLE.options.snake_case_fields {
+        json::snake_case_field_names(json)
     } else {
-        vec![convert_to_array(convert_array_to_object(
-            json,
-            None,
-            None,
-            None,
-            schema_version,
-            log_source,
-        )?)?]
+        json
     };
 
-    for json in data {
-        let origin_size = serde_json::to_vec(&json).unwrap().len() as u64; // string length need not be the same as byte length
-        let schema = PARSEABLE.get_stream(stream_name)?.get_schema_raw();
-        json::Event { json, p_timestamp }
-            .into_event(
-                stream_name.to_owned(),
-                origin_size,
-                &schema,
-                static_schema_flag,
-                custom_partition.as_ref(),
+    // drop excluded fields before flattening, so that nested fields under them go along
+    let json = if exclude_columns.is_empty() {
+        json
+    } else {
+        json::drop_excluded_fields(json, exclude_columns)
+    };
+    // fields are left to be extracted at query time
+    let json = if settings.schema_on_read {
+        json::to_raw_events(json)
+    } else {
+        json
+    };
+
+    // events are partitioned one at a time by their own timestamp field
+    let data =
+        if time_partition.is_some() || custom_partition.is_some() || !timestamp_fields.is_empty() {
+            convert_array_to_object(
+                json,
                 time_partition.as_ref(),
+                time_partition_limit,
+                custom_partition.as_ref(),
                 schema_version,
-                StreamType::UserDefined,
-                p_custom_fields,
+                log_source,
             )?
-            .process()?;
+        } else {
+            vec![convert_to_array(convert_array_to_object(
+                json,
+                None,
+                None,
+                None,
+                schema_version,
+                log_source,
+            )?)?]
+        };
+    // booleans are normalized once flattened, as the names of the columns are only known then
+    let data = if boolean_columns.is_empty() {
+        data
+    } else {
+        data.into_iter()
+            .map(|json| json::normalize_booleans(json, boolean_columns))
+            .collect()
+    };
+    // event times too far from now are rejected or clamped, before they decide the partitions
+    let data = match (
+        time_partition.as_ref(),
+        PARSEABLE.options.clock_skew_tolerance,
+    ) {
+        (Some(time_partition), Some(tolerance)) => {
+            let now = Utc::now();
+            let mut data = data;
+            for json in &mut data {
+                apply_clock_skew(
+                    json,
+                    time_partition,
+                    tolerance,
+                    PARSEABLE.options.clock_skew_policy,
+                    now,
+                )?;
+            }
+            data
+        }
+        _ => data,
+    };
+    // pathologically long field names and values are rejected or truncated
+    let data = match (
+        PARSEABLE.options.max_field_name_length,
+        PARSEABLE.options.max_field_value_length,
+    ) {
+        (None, None) => data,
+        (max_name_length, max_value_length) => {
+            let mut data = data;
+            for json in &mut data {
+                apply_field_length_limits(
+                    json,
+                    max_name_length,
+                    max_value_length,
+                    PARSEABLE.options.field_length_policy,
+                    PARSEABLE.options.duplicate_column_policy,
+                )?;
+            }
+            data
+        }
+    };
+    // columns past the limit of the stream are rejected or folded into the overflow column
+    let data: Vec<Value> = match &settings.column_limit {
+        Some(limit) => {
+            let mut columns = column_limit::columns(&stream.get_schema());
+            data.into_iter()
+                .map(|json| limit.apply(json, &mut columns))
+                .collect::<Result<_, _>>()?
+        }
+        None => data,
+    };
+
+    // records of a new stream are processed one at a time when partitioned, infer its schema from
+    // a sample of them up front, so that the first record alone doesn't decide the column types
+    let sample_size = PARSEABLE.options.inference_sample_size;
+    if sample_size > 1 && !static_schema_flag && stream.get_schema_raw().is_empty() {
+        let schema = json::infer_sample_schema(
+            &data,
+            sample_size,
+            time_partition.as_ref(),
+            schema_version,
+            schema_inference.as_ref(),
+        )?;
+        commit_schema(stream_name, Arc::new(schema)).map_err(EventError::from)?;
     }
-    Ok(())
+
+    let mut placements = vec![];
+    for json in data {
+        let origin_size = serde_json::to_vec(&json).unwrap().len() as u64; // string length need not be the same as byte length
+        let schema = PARSEABLE.get_stream(stream_name)?.get_schema_raw();
+        let json = if PARSEABLE.options.case_insensitive_fields {
+            json::fold_field_names(json, &schema)
+        } else {
+            json
+        };
+        let json = if declared_columns.is_empty() {
+            json
+        } else {
+            json::coerce_to_declared(json, &schema, declared_columns)
+        };
+        let json = json::coerce_to_schema(json, &schema, PARSEABLE.options.type_coercion);
+        let json = if exclude_columns.is_empty() {
+            json
+        } else {
+            json::drop_excluded_fields(json, exclude_columns)
+        };
+        // events are timestamped, hence partitioned, by the first of the timestamp fields they
+        // hold, those holding none of them by the time they are ingested at
+        let p_timestamp = json::timestamp_from_fields(&json, timestamp_fields)
+            .map_or(p_timestamp, |timestamp| timestamp.and_utc());
+        let event = json::Event {
+            json,
+            p_timestamp,
+            schema_inference: schema_inference.clone(),
+        }
+        .into_event(
+            stream_name.to_owned(),
+            origin_size,
+            &schema,
+            static_schema_flag,
+            custom_partition.as_ref(),
+            time_partition.as_ref(),
+            schema_version,
+            StreamType::UserDefined,
+            p_custom_fields,
+        )?;
+        let partition = stream_data_root(stream_name, settings.storage_prefix.as_deref())
+            .join(stream.partition_prefix(event.parsed_timestamp, &event.custom_partition_values))
+            .to_string();
+        // events dropped by sampling or as duplicates weren't put anywhere
+        if let Some(seq) = event.process()? {
+            placements.push(Placement {
+                p_timestamp,
+                partition,
+                seq,
+            });
+        }
+    }
+    Ok(placements)
 }
 
 pub fn get_custom_fields_from_header(req: &HttpRequest) -> HashMap<String, String> {
@@ -203,6 +636,12 @@ pub fn get_custom_fields_from_header(req: &HttpRequest) -> HashMap<String, Strin
                 p_custom_fields.insert(FORMAT_KEY.to_string(), value.to_string());
             }
         }
+
+        if header_name == PARTITION_KEY_HEADER {
+            if let Ok(value) = header_value.to_str() {
+                p_custom_fields.insert(PARTITION_KEY.to_string(), value.to_string());
+            }
+        }
     }
 
     p_custom_fields
@@ -296,4 +735,217 @@ mod tests {
         assert_eq!(custom_fields.get(USER_AGENT_KEY).unwrap(), "");
         assert_eq!(custom_fields.get(SOURCE_IP_KEY).unwrap(), "");
     }
+
+    #[tokio::test]
+    async fn malformed_record_does_not_fail_batch() {
+        let (records, _) = Batch::from(serde_json::json!([
+            {"msg": "first"},
+            "not an object",
+            {"msg": "third"},
+        ]))
+        .into_records();
+
+        // the records are pushed one at a time once rejected as a whole
+        let summary = push_each(records, true, |record| async move {
+            let valid = match &record {
+                Value::Array(records) => records.iter().all(Value::is_object),
+                record => record.is_object(),
+            };
+            if valid {
+                Ok(vec![])
+            } else {
+                Err(PostError::Invalid(anyhow::anyhow!(
+                    "record is not an object"
+                )))
+            }
+        })
+        .await;
+
+        assert_eq!(summary.accepted, 2);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].index, 1);
+        assert!(summary.failed[0].error.contains("not an object"));
+    }
+
+    #[tokio::test]
+    async fn partition_key_is_bucketed_by_the_count_of_the_stream() {
+        use crate::event::{partition_bucket, PARTITION_BUCKET_KEY, PARTITION_KEY};
+
+        let stream_name = "partition_key_buckets_ingestion";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        // the stream was created with fewer buckets than are now configured
+        let buckets = PARSEABLE.options.partition_key_buckets / 4;
+        stream.set_settings(crate::metadata::StreamSettings {
+            partition_key_buckets: Some(buckets),
+            ..Default::default()
+        });
+
+        let placements = push_logs(
+            stream_name,
+            serde_json::json!({"msg": "hello"}),
+            &LogSource::Json,
+            &HashMap::from([(PARTITION_KEY.to_owned(), "tenant-a".to_owned())]),
+        )
+        .await
+        .unwrap();
+        let bucket = format!(
+            "{PARTITION_BUCKET_KEY}={}",
+            partition_bucket("tenant-a", buckets)
+        );
+        assert!(placements[0].partition.contains(&bucket));
+    }
+
+    #[tokio::test]
+    async fn events_are_timestamped_by_their_timestamp_field() {
+        use arrow_array::{cast::AsArray, types::TimestampMillisecondType};
+
+        use crate::event::DEFAULT_TIMESTAMP_KEY;
+
+        let stream_name = "timestamp_fields_ingestion";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.set_settings(crate::metadata::StreamSettings {
+            timestamp_fields: vec!["ts".to_owned()],
+            ..Default::default()
+        });
+
+        let placements = push_logs(
+            stream_name,
+            serde_json::json!({"ts": "2025-05-15T15:30:00Z", "msg": "late"}),
+            &LogSource::Json,
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+        let at = "2025-05-15T15:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(placements[0].p_timestamp, at);
+        assert!(placements[0]
+            .partition
+            .starts_with(&format!("{stream_name}/date=2025-05-15/hour=15/")));
+
+        // and those without it by the time they are ingested at
+        let before = Utc::now();
+        let placements = push_logs(
+            stream_name,
+            serde_json::json!({"msg": "untimed"}),
+            &LogSource::Json,
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+        assert!(placements[0].p_timestamp >= before);
+
+        let mut timestamps: Vec<i64> = stream
+            .recordbatches_cloned(&stream.get_schema())
+            .iter()
+            .flat_map(|rb| {
+                rb.column_by_name(DEFAULT_TIMESTAMP_KEY)
+                    .unwrap()
+                    .as_primitive::<TimestampMillisecondType>()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        timestamps.sort();
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(timestamps[0], at.timestamp_millis());
+        assert!(timestamps[1] >= before.timestamp_millis());
+    }
+
+    #[tokio::test]
+    async fn time_partition_without_offset_is_read_in_configured_timezone() {
+        use chrono::{TimeDelta, Timelike};
+
+        use crate::utils::time::set_naive_timestamp_timezone;
+
+        let stream_name = "naive_time_partition";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.metadata.write().unwrap().time_partition = Some("ts".to_owned());
+        set_naive_timestamp_timezone(chrono_tz::Asia::Kolkata);
+
+        let at = Utc::now().with_nanosecond(0).unwrap() - TimeDelta::hours(1);
+        let local = at.with_timezone(&chrono_tz::Asia::Kolkata).naive_local();
+        let placements = push_logs(
+            stream_name,
+            serde_json::json!({"ts": local.format("%Y-%m-%dT%H:%M:%S").to_string(), "msg": "local"}),
+            &LogSource::Json,
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+        // partitioned by the time in UTC
+        assert!(placements[0].partition.starts_with(&format!(
+            "{stream_name}/date={}/hour={:02}/",
+            at.date_naive(),
+            at.hour()
+        )));
+    }
+
+    #[tokio::test]
+    async fn duplicate_events_are_stored_once() {
+        let stream_name = "dedup_ingestion";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.set_settings(crate::metadata::StreamSettings {
+            dedup_window: Some("10m".to_owned()),
+            ..Default::default()
+        });
+
+        for event in [
+            serde_json::json!({"msg": "disk full", "host": "web-1"}),
+            serde_json::json!({"host": "web-1", "msg": "disk full"}),
+        ] {
+            push_logs(stream_name, event, &LogSource::Json, &HashMap::new())
+                .await
+                .unwrap();
+        }
+
+        let rows: usize = stream
+            .recordbatches_cloned(&stream.get_schema())
+            .iter()
+            .map(|rb| rb.num_rows())
+            .sum();
+        assert_eq!(rows, 1);
+    }
+
+    #[tokio::test]
+    async fn accepted_records_report_their_timestamp_and_partition() {
+        let stream_name = "placements_reported";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.metadata.write().unwrap().custom_partition = Some("host".to_owned());
+        stream.set_settings(crate::metadata::StreamSettings {
+            storage_prefix: Some("archive".to_owned()),
+            timestamp_fields: vec!["ts".to_owned()],
+            dedup_window: Some("10m".to_owned()),
+            ..Default::default()
+        });
+        let (records, _) = Batch::from(serde_json::json!([
+            {"ts": "2025-01-01T10:20:30Z", "host": "a", "msg": "first"},
+            {"ts": "2025-01-01T10:20:30Z", "host": "b", "msg": "second"},
+            {"ts": "2025-01-01T10:20:30Z", "host": "a", "msg": "first"},
+        ]))
+        .into_records();
+
+        let summary = push_each(records, false, |record| {
+            push_logs(stream_name, record, &LogSource::Json, &HashMap::new())
+        })
+        .await;
+
+        // the duplicate is accepted, but wasn't put anywhere
+        assert_eq!(summary.accepted, 3);
+        let response = serde_json::to_value(&summary).unwrap();
+        assert_eq!(
+            response["records"],
+            serde_json::json!([
+                {
+                    "index": 0,
+                    "p_timestamp": "2025-01-01T10:20:30Z",
+                    "partition": "archive/placements_reported/date=2025-01-01/hour=10/minute=20/host=a"
+                },
+                {
+                    "index": 1,
+                    "p_timestamp": "2025-01-01T10:20:30Z",
+                    "partition": "archive/placements_reported/date=2025-01-01/hour=10/minute=20/host=b"
+                }
+            ])
+        );
+    }
 }
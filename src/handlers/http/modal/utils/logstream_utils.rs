@@ -21,12 +21,36 @@ use actix_web::http::header::HeaderMap;
 use crate::{
     event::format::LogSource,
     handlers::{
-        CUSTOM_PARTITION_KEY, LOG_SOURCE_KEY, STATIC_SCHEMA_FLAG, STREAM_TYPE_KEY,
-        TIME_PARTITION_KEY, TIME_PARTITION_LIMIT_KEY, UPDATE_STREAM_KEY,
+        COLUMN_TYPES_KEY, CUSTOM_PARTITION_KEY, LOG_SOURCE_KEY, ON_CONFLICT_KEY,
+        STATIC_SCHEMA_FLAG, STREAM_TYPE_KEY, TEMPLATE_KEY, TIME_PARTITION_KEY,
+        TIME_PARTITION_LIMIT_KEY, UPDATE_STREAM_KEY,
     },
     storage::StreamType,
 };
 
+/// What to do when a stream being created already exists
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Reject the request
+    #[default]
+    Error,
+    /// Do nothing if the existing stream is compatible with the request
+    Ignore,
+    /// Update the schema of the existing stream with the columns of the request, if doing so
+    /// doesn't make existing data unreadable, the columns it already has are kept
+    Overwrite,
+}
+
+impl From<&str> for OnConflict {
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "ignore" => OnConflict::Ignore,
+            "overwrite" => OnConflict::Overwrite,
+            _ => OnConflict::Error,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct PutStreamHeaders {
     pub time_partition: String,
@@ -36,6 +60,10 @@ pub struct PutStreamHeaders {
     pub update_stream_flag: bool,
     pub stream_type: StreamType,
     pub log_source: LogSource,
+    pub column_types: Option<String>,
+    pub on_conflict: OnConflict,
+    /// Name of the template the stream is created from
+    pub template: Option<String>,
 }
 
 impl From<&HeaderMap> for PutStreamHeaders {
@@ -65,6 +93,16 @@ impl From<&HeaderMap> for PutStreamHeaders {
             log_source: headers
                 .get(LOG_SOURCE_KEY)
                 .map_or(LogSource::default(), |v| v.to_str().unwrap().into()),
+            column_types: headers
+                .get(COLUMN_TYPES_KEY)
+                .map(|v| v.to_str().unwrap().to_string()),
+            on_conflict: headers
+                .get(ON_CONFLICT_KEY)
+                .map(|v| v.to_str().unwrap().into())
+                .unwrap_or_default(),
+            template: headers
+                .get(TEMPLATE_KEY)
+                .map(|v| v.to_str().unwrap().to_string()),
         }
     }
 }
@@ -120,6 +120,8 @@ pub async fn login(
             };
             Ok(resp)
         }
+        // API tokens are never authorized to login
+        SessionKey::ApiToken(_) => Err(OIDCError::Unauthorized),
     }
 }
 
@@ -17,58 +17,139 @@
  */
 
 use crate::event::error::EventError;
+use crate::handlers::http::error::{datafusion_error_code, ErrorEnvelope};
 use crate::handlers::http::fetch_schema;
-use actix_web::http::header::ContentType;
+use actix_web::http::header;
 use actix_web::web::{self, Json};
 use actix_web::{Either, FromRequest, HttpRequest, HttpResponse, Responder};
+use arrow::compute::can_cast_types;
 use arrow_array::RecordBatch;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use datafusion::common::tree_node::TreeNode;
+use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion::common::ScalarValue;
 use datafusion::error::DataFusionError;
 use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::{BinaryExpr, Expr, LogicalPlan, Operator};
 use futures::stream::once;
 use futures::{future, Stream, StreamExt};
 use futures_util::Future;
 use http::StatusCode;
+use regex::RegexBuilder;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing::error;
 
+use crate::catalog;
 use crate::event::commit_schema;
 use crate::metrics::QUERY_EXECUTE_TIME;
 use crate::option::Mode;
-use crate::parseable::{StreamNotFound, PARSEABLE};
+use crate::parseable::{SchemaDriftError, StreamNotFound, PARSEABLE};
 use crate::query::error::ExecuteError;
+use crate::query::plan_cache::PLAN_CACHE;
+use crate::query::stream_schema_provider::{FileTier, FILE_TIER, SCHEMA_OVERRIDES, SKIP_CACHE};
 use crate::query::{execute, CountsRequest, CountsResponse, Query as LogicalQuery};
-use crate::query::{TableScanVisitor, QUERY_SESSION};
+use crate::query::{range_schema, TableScanVisitor, QUERY_SESSION};
 use crate::rbac::Users;
-use crate::response::QueryResponse;
+use crate::response::{CsvOptions, ExportEncoding, QueryExport, QueryResponse};
 use crate::storage::ObjectStorageError;
 use crate::utils::actix::extract_session_key_from_req;
-use crate::utils::time::{TimeParseError, TimeRange};
-use crate::utils::user_auth_for_datasets;
+use crate::utils::session_auth_for_datasets;
+use crate::utils::time::{parse_timestamp, TimeParseError, TimeRange};
 
 const TIME_ELAPSED_HEADER: &str = "p-time-elapsed";
+const CSV_CONTENT_TYPE: &str = "text/csv";
 /// Query Request through http endpoint.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Query {
     pub query: String,
+    /// Both times may be left out, for the default window up to now
+    #[serde(default)]
     pub start_time: String,
+    #[serde(default)]
     pub end_time: String,
     #[serde(default)]
     pub send_null: bool,
+    /// Values bound to the `$1`, `$2`, .. placeholders of the query, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Value>,
     #[serde(skip)]
     pub fields: bool,
     #[serde(skip)]
     pub streaming: bool,
     #[serde(skip)]
     pub filter_tags: Option<Vec<String>>,
+    /// Header and delimiter used when results are returned as CSV
+    #[serde(default)]
+    pub csv: CsvOptions,
+    /// Tier of files scanned, e.g. only compacted files for speed at the cost of freshness
+    #[serde(default)]
+    pub file_tier: FileTier,
+    /// Files are read only from object storage, the source of truth, and not from the hot tier
+    #[serde(default)]
+    pub skip_cache: bool,
+    /// What a relative start time is counted back from, e.g. the last 30m before the latest event
+    #[serde(default)]
+    pub anchor: TimeAnchor,
+    /// Results are written to this key, under the requesting user's exports, rather than being returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<QueryExport>,
+    /// Results are returned as CSV, set when the request has `Accept: text/csv`
+    #[serde(skip)]
+    pub accept_csv: bool,
+}
+
+/// Point in time the relative window of a query ends at
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeAnchor {
+    /// The current time
+    #[default]
+    Now,
+    /// The latest event of the queried streams, so that the window isn't empty when no events
+    /// were ingested lately
+    LatestEvent,
+}
+
+/// Range the query runs over, a relative window ending at the latest event of the queried streams
+/// when the query is anchored to it. Streams without events fall back to a window up to now.
+async fn query_time_range(
+    query_request: &Query,
+    tables: &[String],
+) -> Result<TimeRange, QueryError> {
+    if query_request.anchor == TimeAnchor::Now {
+        return Ok(TimeRange::parse_human_time(
+            &query_request.start_time,
+            &query_request.end_time,
+        )?);
+    }
+    if query_request.end_time != "now" {
+        return Err(QueryError::InvalidParams(
+            "Queries anchored to the latest event take a relative start time and an end time of now"
+                .to_owned(),
+        ));
+    }
+
+    let mut latest = None;
+    for stream in tables
+        .iter()
+        .filter_map(|table| PARSEABLE.streams.resolve(table))
+    {
+        let ingested = PARSEABLE.get_stream(&stream)?.get_latest_event_at();
+        let uploaded =
+            catalog::get_latest_event(PARSEABLE.storage.get_object_store(), &stream).await?;
+        latest = latest.max(ingested).max(uploaded);
+    }
+
+    Ok(match latest {
+        Some(latest) => TimeRange::anchored_at(&query_request.start_time, latest)?,
+        None => TimeRange::parse_human_time(&query_request.start_time, &query_request.end_time)?,
+    })
 }
 
 /// A function to execute the query and fetch QueryResponse
@@ -85,25 +166,26 @@ pub async fn get_records_and_fields(
         .create_logical_plan(&query_request.query)
         .await?;
 
-    let time_range =
-        TimeRange::parse_human_time(&query_request.start_time, &query_request.end_time)?;
     // create a visitor to extract the table name
     let mut visitor = TableScanVisitor::default();
     let _ = raw_logical_plan.visit(&mut visitor);
 
     let tables = visitor.into_inner();
+    // authorized before anything of the tables is read from storage
+    let creds = extract_session_key_from_req(req)?;
+    session_auth_for_datasets(&creds, &tables)?;
+
+    let time_range = query_time_range(query_request, &tables).await?;
     update_schema_when_distributed(&tables).await?;
+    for table in &tables {
+        PARSEABLE.reconcile_schema(table).await?;
+    }
     let query: LogicalQuery = into_query(query_request, &session_state, time_range).await?;
 
-    let creds = extract_session_key_from_req(req)?;
-    let permissions = Users.get_permissions(&creds);
-
     let table_name = query
         .first_table_name()
         .ok_or_else(|| QueryError::MalformedQuery("No table name found in query"))?;
 
-    user_auth_for_datasets(&permissions, &tables)?;
-
     let (records, fields) = execute(query, &table_name, false).await?;
 
     let records = match records {
@@ -116,7 +198,12 @@ pub async fn get_records_and_fields(
     Ok((Some(records), Some(fields)))
 }
 
-pub async fn query(req: HttpRequest, query_request: Query) -> Result<HttpResponse, QueryError> {
+/// Plans the query once the user is found to be allowed to query its tables,
+/// returns it along with the name of the first table queried
+async fn plan_query(
+    req: &HttpRequest,
+    query_request: &Query,
+) -> Result<(LogicalQuery, String), QueryError> {
     let session_state = QUERY_SESSION.state();
     let raw_logical_plan = match session_state
         .create_logical_plan(&query_request.query)
@@ -130,33 +217,75 @@ pub async fn query(req: HttpRequest, query_request: Query) -> Result<HttpRespons
                 .await?
         }
     };
-    let time_range =
-        TimeRange::parse_human_time(&query_request.start_time, &query_request.end_time)?;
-
     let mut visitor = TableScanVisitor::default();
     let _ = raw_logical_plan.visit(&mut visitor);
     let tables = visitor.into_inner();
-    update_schema_when_distributed(&tables).await?;
-    let query: LogicalQuery = into_query(&query_request, &session_state, time_range).await?;
+    // authorized before anything of the tables is read from storage, e.g. the latest event
+    // a query is anchored to
+    let creds = extract_session_key_from_req(req)?;
+    session_auth_for_datasets(&creds, &tables)?;
 
-    let creds = extract_session_key_from_req(&req)?;
-    let permissions = Users.get_permissions(&creds);
+    let time_range = query_time_range(query_request, &tables).await?;
+    update_schema_when_distributed(&tables).await?;
+    for table in &tables {
+        PARSEABLE.reconcile_schema(table).await?;
+    }
+    // files in the range written before the schema evolved can have columns it no longer has
+    let mut schemas = HashMap::new();
+    for stream in tables
+        .iter()
+        .filter_map(|table| PARSEABLE.streams.resolve(table))
+    {
+        if let Some(schema) = range_schema(&stream, &time_range).await? {
+            schemas.insert(stream, schema);
+        }
+    }
+    let query: LogicalQuery = SKIP_CACHE
+        .scope(
+            query_request.skip_cache,
+            FILE_TIER.scope(
+                query_request.file_tier,
+                SCHEMA_OVERRIDES.scope(
+                    schemas,
+                    into_query(query_request, &session_state, time_range),
+                ),
+            ),
+        )
+        .await?;
 
     let table_name = query
         .first_table_name()
         .ok_or_else(|| QueryError::MalformedQuery("No table name found in query"))?;
 
-    user_auth_for_datasets(&permissions, &tables)?;
+    Ok((query, table_name))
+}
 
+pub async fn query(req: HttpRequest, query_request: Query) -> Result<HttpResponse, QueryError> {
+    let (query, table_name) = plan_query(&req, &query_request).await?;
     let time = Instant::now();
 
     // if the query is `select count(*) from <dataset>`
     // we use the `get_bin_density` method to get the count of records in the dataset
     // instead of executing the query using datafusion
-    if let Some(column_name) = query.is_logical_plan_count_without_filters() {
+    // CSV and exported results are written directly from the record batches, so counts go through datafusion
+    if let Some(column_name) = query
+        .is_logical_plan_count_without_filters()
+        .filter(|_| !query_request.accept_csv && query_request.export.is_none())
+    {
         return handle_count_query(&query_request, &table_name, column_name, time).await;
     }
 
+    if query_request.streaming && query_request.accept_csv {
+        return Err(QueryError::InvalidParams(
+            "CSV results can't be streamed".to_owned(),
+        ));
+    }
+
+    // exported results are streamed into object storage, rather than collected in memory
+    if let Some(export) = &query_request.export {
+        return handle_export_query(&req, query, &table_name, export, &query_request, time).await;
+    }
+
     // if the query request has streaming = false (default)
     // we use datafusion's `execute` method to get the records
     if !query_request.streaming {
@@ -257,13 +386,83 @@ async fn handle_non_streaming_query(
         fields,
         fill_null: query_request.send_null,
         with_fields: query_request.fields,
+    };
+    if query_request.accept_csv {
+        return Ok(HttpResponse::Ok()
+            .insert_header((TIME_ELAPSED_HEADER, total_time.as_str()))
+            .content_type(CSV_CONTENT_TYPE)
+            .body(response.to_csv(&query_request.csv)?));
     }
-    .to_json()?;
+
+    let response = response.to_json()?;
     Ok(HttpResponse::Ok()
         .insert_header((TIME_ELAPSED_HEADER, total_time.as_str()))
         .json(response))
 }
 
+/// Handles queries exported to object storage, returning where the results were written to.
+///
+/// Executes the logical query using DataFusion's streaming execution and writes the record
+/// batches out as they are produced, under the directory of the requesting user, see
+/// [`QueryExport::write`].
+///
+/// # Arguments
+/// - `req`: The request, to find the requesting user from.
+/// - `query`: The logical query to execute.
+/// - `table_name`: The name of the table/dataset being queried.
+/// - `export`: Where and how to export the results.
+/// - `query_request`: The original query request from the client.
+/// - `time`: The timer for measuring query execution time.
+///
+/// # Returns
+/// - `HttpResponse` with the exported key and number of rows as a JSON object.
+async fn handle_export_query(
+    req: &HttpRequest,
+    query: LogicalQuery,
+    table_name: &str,
+    export: &QueryExport,
+    query_request: &Query,
+    time: Instant,
+) -> Result<HttpResponse, QueryError> {
+    let username = Users
+        .get_username_from_session(&extract_session_key_from_req(req)?)
+        .ok_or(QueryError::Unauthorized)?;
+    let (records_stream, fields) = execute(query, table_name, true).await?;
+    let records_stream = match records_stream {
+        Either::Left(_) => {
+            return Err(QueryError::MalformedQuery(
+                "Expected stream results, got batch",
+            ))
+        }
+        Either::Right(stream) => stream,
+    };
+    let encoding = ExportEncoding {
+        csv: query_request.csv,
+        fields: &fields,
+        fill_null: query_request.send_null,
+        with_fields: query_request.fields,
+    };
+    let summary = export
+        .write(
+            records_stream,
+            &encoding,
+            &username,
+            PARSEABLE.storage.get_object_store().as_ref(),
+            PARSEABLE.options.staging_dir(),
+        )
+        .await?;
+
+    let total_time = format!("{:?}", time.elapsed());
+    let time = time.elapsed().as_secs_f64();
+    QUERY_EXECUTE_TIME
+        .with_label_values(&[table_name])
+        .observe(time);
+
+    Ok(HttpResponse::Ok()
+        .insert_header((TIME_ELAPSED_HEADER, total_time.as_str()))
+        .json(summary))
+}
+
 /// Handles streaming queries, returning results as newline-delimited JSON (NDJSON).
 ///
 /// Executes the logical query using DataFusion's streaming execution. If the `fields`
@@ -358,15 +557,27 @@ fn create_batch_processor(
         Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
     }
 }
+/// Lists the files the query would scan, along with their sizes and the time range of the
+/// partition they are in, without executing it
+pub async fn get_query_files(
+    req: HttpRequest,
+    query_request: Query,
+) -> Result<HttpResponse, QueryError> {
+    let (query, table_name) = plan_query(&req, &query_request).await?;
+    let time_partition = PARSEABLE.get_stream(&table_name)?.get_time_partition();
+    let files = query.scanned_files(time_partition.as_ref()).await?;
+
+    Ok(HttpResponse::Ok().json(files))
+}
+
 pub async fn get_counts(
     req: HttpRequest,
     counts_request: Json<CountsRequest>,
 ) -> Result<impl Responder, QueryError> {
     let creds = extract_session_key_from_req(&req)?;
-    let permissions = Users.get_permissions(&creds);
 
     // does user have access to table?
-    user_auth_for_datasets(&permissions, &[counts_request.stream.clone()])?;
+    session_auth_for_datasets(&creds, &[counts_request.stream.clone()])?;
 
     let records = counts_request.get_bin_density().await?;
 
@@ -376,6 +587,23 @@ pub async fn get_counts(
     }))
 }
 
+pub async fn get_volume(
+    req: HttpRequest,
+    volume_request: Json<CountsRequest>,
+) -> Result<impl Responder, QueryError> {
+    let creds = extract_session_key_from_req(&req)?;
+
+    // does user have access to table?
+    session_auth_for_datasets(&creds, &[volume_request.stream.clone()])?;
+
+    let records = volume_request.get_bin_volume().await?;
+
+    Ok(web::Json(json!({
+        "fields": ["start_time", "end_time", "ingestion_size", "storage_size"],
+        "records": records,
+    })))
+}
+
 pub async fn update_schema_when_distributed(tables: &Vec<String>) -> Result<(), EventError> {
     // if the mode is query or prism, we need to update the schema in memory
     // no need to commit schema to storage
@@ -407,6 +635,17 @@ pub async fn create_streams_for_querier() {
     }
 }
 
+impl Query {
+    /// Runs a query that carries neither a start nor an end time over the `window` up to now
+    pub fn with_default_time_range(mut self, window: Duration) -> Self {
+        if self.start_time.is_empty() && self.end_time.is_empty() {
+            self.start_time = humantime::format_duration(window).to_string();
+            self.end_time = "now".to_owned();
+        }
+        self
+    }
+}
+
 impl FromRequest for Query {
     type Error = actix_web::Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
@@ -417,9 +656,17 @@ impl FromRequest for Query {
             .into_inner()
             .map(|x| x.0)
             .unwrap_or_default();
+        let accept_csv = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains(CSV_CONTENT_TYPE));
 
         let fut = async move {
-            let mut query = query.await?.into_inner();
+            let mut query = query
+                .await?
+                .into_inner()
+                .with_default_time_range(PARSEABLE.options.default_query_window);
             // format output json to include field names
             query.fields = params.get("fields").cloned().unwrap_or(false);
 
@@ -431,6 +678,8 @@ impl FromRequest for Query {
                 query.streaming = params.get("streaming").cloned().unwrap_or(false);
             }
 
+            query.accept_csv = accept_csv;
+
             Ok(query)
         };
 
@@ -455,13 +704,142 @@ pub async fn into_query(
         return Err(QueryError::EmptyEndTime);
     }
 
+    let raw_logical_plan = PLAN_CACHE.logical_plan(session_state, &query.query).await?;
+
+    let raw_logical_plan = bind_params(raw_logical_plan, &query.params)?;
+    validate_regex_patterns(&raw_logical_plan)?;
+
     Ok(crate::query::Query {
-        raw_logical_plan: session_state.create_logical_plan(&query.query).await?,
+        raw_logical_plan,
         time_range,
         filter_tag: query.filter_tags.clone(),
     })
 }
 
+/// Longest regex pattern a query may filter with
+const MAX_REGEX_LENGTH: usize = 1024;
+/// Bound on the size of a compiled regex, so that a pattern like `(a{100}){100}` can't blow up memory
+const MAX_REGEX_SIZE: usize = 1024 * 1024;
+
+/// Validates the patterns of regex filters in the query, i.e. `column ~ 'pattern'` and the
+/// `regexp_*` functions. Regexes are matched in linear time, as there's no backtracking, hence
+/// bounding the size of patterns bounds the cost of matching them against each row.
+pub fn validate_regex_patterns(plan: &LogicalPlan) -> Result<(), QueryError> {
+    let mut patterns = vec![];
+    plan.apply_with_subqueries(|node| {
+        node.apply_expressions(|expr| {
+            expr.apply(|expr| {
+                if let Some(pattern) = regex_pattern(expr) {
+                    patterns.push(pattern.to_owned());
+                }
+                Ok(TreeNodeRecursion::Continue)
+            })
+        })
+    })?;
+
+    for pattern in patterns {
+        if pattern.len() > MAX_REGEX_LENGTH {
+            return Err(QueryError::InvalidRegex(format!(
+                "pattern is longer than {MAX_REGEX_LENGTH} characters"
+            )));
+        }
+        RegexBuilder::new(&pattern)
+            .size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map_err(|err| QueryError::InvalidRegex(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the literal pattern of a regex filter, if the expression is one
+fn regex_pattern(expr: &Expr) -> Option<&str> {
+    let pattern = match expr {
+        Expr::BinaryExpr(BinaryExpr {
+            op:
+                Operator::RegexMatch
+                | Operator::RegexIMatch
+                | Operator::RegexNotMatch
+                | Operator::RegexNotIMatch,
+            right,
+            ..
+        }) => right.as_ref(),
+        Expr::ScalarFunction(func) if func.name().starts_with("regexp_") => func.args.get(1)?,
+        _ => return None,
+    };
+
+    match pattern {
+        Expr::Literal(
+            ScalarValue::Utf8(Some(pattern))
+            | ScalarValue::LargeUtf8(Some(pattern))
+            | ScalarValue::Utf8View(Some(pattern)),
+        ) => Some(pattern),
+        _ => None,
+    }
+}
+
+/// Binds `params` to the placeholders of the plan, parameters are bound as values after the
+/// query is parsed, hence can't change the structure of the query the way string formatting can
+pub fn bind_params(plan: LogicalPlan, params: &[Value]) -> Result<LogicalPlan, QueryError> {
+    let param_types = plan.get_parameter_types()?;
+    if param_types.len() != params.len() {
+        return Err(QueryError::InvalidParams(format!(
+            "query expects {} parameters, got {}",
+            param_types.len(),
+            params.len()
+        )));
+    }
+
+    if params.is_empty() {
+        return Ok(plan);
+    }
+
+    let mut values = Vec::with_capacity(params.len());
+    for (idx, param) in params.iter().enumerate() {
+        let placeholder = format!("${}", idx + 1);
+        let Some(expected) = param_types.get(&placeholder) else {
+            return Err(QueryError::InvalidParams(format!(
+                "placeholder {placeholder} not found in query"
+            )));
+        };
+        let value = json_to_scalar(param).ok_or_else(|| {
+            QueryError::InvalidParams(format!(
+                "parameter {placeholder} must be a string, number, boolean or null"
+            ))
+        })?;
+
+        let value = match expected {
+            Some(data_type) if !value.is_null() && value.data_type() != *data_type => {
+                if !can_cast_types(&value.data_type(), data_type) {
+                    return Err(QueryError::InvalidParams(format!(
+                        "parameter {placeholder} of type {} can't be compared with {data_type}",
+                        value.data_type()
+                    )));
+                }
+                value.cast_to(data_type)?
+            }
+            _ => value,
+        };
+        values.push(value);
+    }
+
+    Ok(plan.with_param_values(values)?)
+}
+
+fn json_to_scalar(value: &Value) -> Option<ScalarValue> {
+    match value {
+        Value::Null => Some(ScalarValue::Null),
+        Value::Bool(b) => Some(ScalarValue::Boolean(Some(*b))),
+        Value::Number(n) => n
+            .as_i64()
+            .map(|n| ScalarValue::Int64(Some(n)))
+            .or_else(|| n.as_u64().map(|n| ScalarValue::UInt64(Some(n))))
+            .or_else(|| n.as_f64().map(|n| ScalarValue::Float64(Some(n)))),
+        Value::String(s) => Some(ScalarValue::Utf8(Some(s.clone()))),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
 /// unused for now, might need it in the future
 #[allow(unused)]
 fn transform_query_for_ingestor(query: &Query) -> Option<Query> {
@@ -480,9 +858,7 @@ fn transform_query_for_ingestor(query: &Query) -> Option<Query> {
     let end_time: DateTime<Utc> = if query.end_time == "now" {
         Utc::now()
     } else {
-        DateTime::parse_from_rfc3339(&query.end_time)
-            .ok()?
-            .with_timezone(&Utc)
+        parse_timestamp(&query.end_time).ok()?
     };
 
     let start_time = end_time - chrono::Duration::minutes(1);
@@ -492,9 +868,16 @@ fn transform_query_for_ingestor(query: &Query) -> Option<Query> {
         fields: false,
         filter_tags: query.filter_tags.clone(),
         send_null: query.send_null,
+        params: query.params.clone(),
         start_time: start_time.to_rfc3339(),
         end_time: end_time.to_rfc3339(),
         streaming: query.streaming,
+        csv: query.csv,
+        file_tier: query.file_tier,
+        skip_cache: query.skip_cache,
+        anchor: TimeAnchor::Now,
+        export: None,
+        accept_csv: false,
     };
 
     Some(q)
@@ -540,20 +923,62 @@ Description: {0}"#
     CustomError(String),
     #[error("No available queriers found")]
     NoAvailableQuerier,
+    #[error("Invalid query parameters: {0}")]
+    InvalidParams(String),
+    #[error("Invalid regex pattern: {0}")]
+    InvalidRegex(String),
+    #[error("{0}")]
+    SchemaDrift(#[from] SchemaDriftError),
+    #[error("Results were already exported to {0}, set overwrite to replace them")]
+    ExportExists(String),
 }
 
 impl actix_web::ResponseError for QueryError {
     fn status_code(&self) -> http::StatusCode {
         match self {
+            QueryError::Execute(ExecuteError::QueryTimeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+            QueryError::Execute(ExecuteError::TooManyQueries) => StatusCode::TOO_MANY_REQUESTS,
             QueryError::Execute(_) | QueryError::JsonParse(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            QueryError::ExportExists(_) => StatusCode::CONFLICT,
             _ => StatusCode::BAD_REQUEST,
         }
     }
 
     fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
-        actix_web::HttpResponse::build(self.status_code())
-            .insert_header(ContentType::plaintext())
-            .body(self.to_string())
+        ErrorEnvelope::new(self.code(), self).into_response(self.status_code())
+    }
+}
+
+impl QueryError {
+    /// Machine readable code of the error, as returned in the `code` of the error response
+    pub fn code(&self) -> &'static str {
+        match self {
+            QueryError::EmptyQuery => "empty_query",
+            QueryError::EmptyStartTime => "empty_start_time",
+            QueryError::EmptyEndTime => "empty_end_time",
+            QueryError::TimeParse(_) => "invalid_time_range",
+            QueryError::Unauthorized => "unauthorized",
+            QueryError::Datafusion(err) => datafusion_error_code(err),
+            QueryError::Execute(ExecuteError::Datafusion(err)) => datafusion_error_code(err),
+            QueryError::Execute(ExecuteError::QueryTimeout(_)) => "query_timeout",
+            QueryError::Execute(ExecuteError::TooManyQueries) => "too_many_queries",
+            QueryError::Execute(ExecuteError::StreamNotFound(_))
+            | QueryError::StreamNotFound(_) => "stream_not_found",
+            QueryError::Execute(ExecuteError::ObjectStorage(_)) | QueryError::ObjectStorage(_) => {
+                "storage_error"
+            }
+            QueryError::EventError(_) => "event_error",
+            QueryError::MalformedQuery(_) => "malformed_query",
+            QueryError::JsonParse(_) => "response_encoding_error",
+            QueryError::ActixError(_) => "invalid_request",
+            QueryError::SerdeJsonError(_) => "invalid_json",
+            QueryError::Anyhow(_) | QueryError::CustomError(_) => "query_error",
+            QueryError::NoAvailableQuerier => "no_available_querier",
+            QueryError::InvalidParams(_) => "invalid_params",
+            QueryError::InvalidRegex(_) => "invalid_regex",
+            QueryError::SchemaDrift(_) => "schema_drift",
+            QueryError::ExportExists(_) => "export_exists",
+        }
     }
 }
 
@@ -562,3 +987,514 @@ impl From<reqwest::Error> for QueryError {
         QueryError::Anyhow(anyhow::Error::msg(value.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow::util::pretty::pretty_format_batches;
+    use arrow_array::StringArray;
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::{datasource::MemTable, prelude::SessionContext};
+
+    use super::*;
+
+    fn context() -> SessionContext {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "level",
+            DataType::Utf8,
+            false,
+        )]));
+        let rb = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(vec!["info", "it's", "error"]))],
+        )
+        .unwrap();
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "s",
+            Arc::new(MemTable::try_new(schema, vec![vec![rb]]).unwrap()),
+        )
+        .unwrap();
+
+        ctx
+    }
+
+    #[test]
+    fn query_without_times_runs_over_default_window() {
+        let query: Query = serde_json::from_value(json!({"query": "select * from s"})).unwrap();
+        let query = query.with_default_time_range(Duration::from_secs(60 * 60));
+
+        let time_range = TimeRange::parse_human_time(&query.start_time, &query.end_time).unwrap();
+        assert_eq!(
+            time_range.end - time_range.start,
+            chrono::Duration::hours(1)
+        );
+        assert!(!time_range.generate_prefixes(1).is_empty());
+    }
+
+    #[test]
+    fn explicit_times_are_kept() {
+        let query: Query = serde_json::from_value(json!({
+            "query": "select * from s",
+            "startTime": "2024-01-01T00:00:00Z",
+            "endTime": "2024-01-01T00:10:00Z"
+        }))
+        .unwrap();
+        let query = query.with_default_time_range(Duration::from_secs(60 * 60));
+
+        assert_eq!(query.start_time, "2024-01-01T00:00:00Z");
+        assert_eq!(query.end_time, "2024-01-01T00:10:00Z");
+    }
+
+    #[tokio::test]
+    async fn string_param_with_quote_is_bound_as_value() {
+        let ctx = context();
+        let plan = ctx
+            .state()
+            .create_logical_plan("SELECT * FROM s WHERE level = $1")
+            .await
+            .unwrap();
+
+        let plan = bind_params(plan, &[json!("it's")]).unwrap();
+        let records = ctx
+            .execute_logical_plan(plan)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let output = pretty_format_batches(&records).unwrap().to_string();
+        assert_eq!(records.iter().map(|rb| rb.num_rows()).sum::<usize>(), 1);
+        assert!(output.contains("it's"));
+    }
+
+    #[tokio::test]
+    async fn rows_are_filtered_by_regex() {
+        let ctx = context();
+        let plan = ctx
+            .state()
+            .create_logical_plan("SELECT * FROM s WHERE level ~ '^(err|inf).*'")
+            .await
+            .unwrap();
+        validate_regex_patterns(&plan).unwrap();
+
+        let records = ctx
+            .execute_logical_plan(plan)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let output = pretty_format_batches(&records).unwrap().to_string();
+        assert_eq!(records.iter().map(|rb| rb.num_rows()).sum::<usize>(), 2);
+        assert!(output.contains("error") && output.contains("info"));
+
+        let long_pattern = format!("SELECT * FROM s WHERE level ~ '{}'", "a".repeat(2000));
+        let plan = ctx
+            .state()
+            .create_logical_plan(&long_pattern)
+            .await
+            .unwrap();
+        assert!(matches!(
+            validate_regex_patterns(&plan),
+            Err(QueryError::InvalidRegex(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn params_are_validated() {
+        let ctx = context();
+        let plan = ctx
+            .state()
+            .create_logical_plan("SELECT * FROM s WHERE level = $1")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            bind_params(plan.clone(), &[]),
+            Err(QueryError::InvalidParams(_))
+        ));
+        assert!(matches!(
+            bind_params(plan.clone(), &[json!("info"), json!("error")]),
+            Err(QueryError::InvalidParams(_))
+        ));
+        assert!(matches!(
+            bind_params(plan, &[json!(["info"])]),
+            Err(QueryError::InvalidParams(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn parse_error_is_returned_with_its_code() {
+        let err = context()
+            .state()
+            .create_logical_plan("SELEC * FROM s")
+            .await
+            .unwrap_err();
+        let err = QueryError::from(err);
+        assert_eq!(err.code(), "sql_parse_error");
+
+        let response = actix_web::ResponseError::error_response(&err);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "sql_parse_error");
+        assert_eq!(body["message"], err.to_string());
+    }
+
+    #[tokio::test]
+    async fn queries_are_authorized_before_their_tables_are_read() {
+        use actix_web::{cookie::Cookie, test::TestRequest};
+        use ulid::Ulid;
+
+        use crate::rbac::{
+            map::{init_for_tests, SessionKey},
+            user::User,
+            Users,
+        };
+
+        init_for_tests();
+        let stream_name = "query_authorized_first";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.set_schema(&Schema::new(vec![Field::new(
+            "code",
+            DataType::Int64,
+            true,
+        )]));
+        // a user without any role
+        let (user, _) = User::new_basic("query_unprivileged".to_owned());
+        Users.put_user(user.clone());
+        let session = Ulid::new();
+        Users.new_session(&user, SessionKey::SessionId(session));
+        let req = TestRequest::default()
+            .cookie(Cookie::new("session", session.to_string()))
+            .to_http_request();
+
+        // the range of an anchored query would be read from the latest event of the stream, and
+        // is invalid too, the query is refused as unauthorized before either
+        let query: Query = serde_json::from_value(json!({
+            "query": format!("select * from {stream_name}"),
+            "startTime": "30m",
+            "endTime": "1h",
+            "anchor": "latest-event"
+        }))
+        .unwrap();
+        assert!(matches!(
+            plan_query(&req, &query).await,
+            Err(QueryError::ActixError(_))
+        ));
+        assert!(matches!(
+            get_records_and_fields(&query, &req).await,
+            Err(QueryError::ActixError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn anchored_query_returns_events_older_than_the_window() {
+        use arrow_array::{Int64Array, TimestampMillisecondArray};
+        use chrono::{TimeDelta, TimeZone};
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog::{manifest::Manifest, snapshot::ManifestItem},
+            event::DEFAULT_TIMESTAMP_KEY,
+            storage::{ObjectStoreFormat, STREAM_ROOT_DIRECTORY},
+        };
+
+        let stream_name = "anchored_latest_event";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        let upload = |at: DateTime<Utc>, file_name: &'static str| {
+            let store = store.clone();
+            async move {
+                let rb = RecordBatch::try_from_iter([
+                    (
+                        DEFAULT_TIMESTAMP_KEY,
+                        Arc::new(TimestampMillisecondArray::from(vec![
+                            at.timestamp_millis();
+                            3
+                        ])) as _,
+                    ),
+                    ("code", Arc::new(Int64Array::from(vec![200, 404, 500])) as _),
+                ])
+                .unwrap();
+                let mut parquet = vec![];
+                let mut writer =
+                    parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+                writer.write(&rb).unwrap();
+                writer.close().unwrap();
+                let parquet = Bytes::from(parquet);
+                let key = RelativePathBuf::from_iter([
+                    stream_name,
+                    &format!("date={}", at.date_naive()),
+                    file_name,
+                ]);
+                store.put_object(&key, parquet.clone()).await.unwrap();
+                let file = catalog::manifest::create_from_parquet(
+                    store.absolute_url(&key).to_string(),
+                    parquet.clone(),
+                    parquet.len() as u64,
+                )
+                .unwrap();
+                (rb.schema(), file)
+            }
+        };
+
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, 30, 0).unwrap();
+        let (schema, file) = upload(at, "host.data.parquet").await;
+        stream.set_schema(&schema);
+        store
+            .create_stream(stream_name, ObjectStoreFormat::default(), schema)
+            .await
+            .unwrap();
+        catalog::update_snapshot(store.clone(), stream_name, file)
+            .await
+            .unwrap();
+
+        // the last 30m before the latest event, long before now
+        let query: Query = serde_json::from_value(json!({
+            "query": format!("select * from {stream_name}"),
+            "startTime": "30m",
+            "endTime": "now",
+            "anchor": "latest-event"
+        }))
+        .unwrap();
+        let time_range = query_time_range(&query, &[stream_name.to_owned()])
+            .await
+            .unwrap();
+        assert!(time_range.start <= at && at < time_range.end);
+        let logical_query = into_query(&query, &QUERY_SESSION.state(), time_range)
+            .await
+            .unwrap();
+        let (Either::Left(records), _) = execute(logical_query, stream_name, false).await.unwrap()
+        else {
+            unreachable!("non-streaming query returns batches")
+        };
+        assert_eq!(records.iter().map(|rb| rb.num_rows()).sum::<usize>(), 3);
+
+        // events uploaded by an ingestor are in the snapshot of its own
+        let later = at + TimeDelta::minutes(10);
+        let (_, file) = upload(later, "ingestor.data.parquet").await;
+        let bounds = catalog::partition_path(stream_name, at, at);
+        let manifest_key = bounds.join("ingestor.test.manifest.json");
+        let manifest = Manifest {
+            files: vec![file],
+            ..Default::default()
+        };
+        store
+            .put_object(&manifest_key, serde_json::to_vec(&manifest).unwrap().into())
+            .await
+            .unwrap();
+        let own = store.get_object_store_format(stream_name).await.unwrap();
+        let mut ingestor = ObjectStoreFormat::default();
+        ingestor.snapshot.manifest_list = own
+            .snapshot
+            .manifest_list
+            .iter()
+            .map(|item| ManifestItem {
+                manifest_path: store.absolute_url(&manifest_key).to_string(),
+                ..item.clone()
+            })
+            .collect();
+        store
+            .put_object(
+                &RelativePathBuf::from_iter([
+                    stream_name,
+                    STREAM_ROOT_DIRECTORY,
+                    ".ingestor.test.stream.json",
+                ]),
+                serde_json::to_vec(&ingestor).unwrap().into(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            catalog::get_latest_event(store, stream_name).await.unwrap(),
+            Some(later)
+        );
+    }
+
+    #[actix_web::test]
+    async fn files_listed_for_a_query_are_those_of_its_range() {
+        use actix_web::test::TestRequest;
+        use arrow_array::{Int64Array, TimestampMillisecondArray};
+        use chrono::TimeZone;
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            event::DEFAULT_TIMESTAMP_KEY, rbac::map::init_for_tests, storage::ObjectStoreFormat,
+        };
+
+        init_for_tests();
+        let stream_name = "query_files_of_range";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        let mut keys = vec![];
+        for minute in [0, 1, 5] {
+            let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, minute, 30).unwrap();
+            let rb = RecordBatch::try_from_iter([
+                (
+                    DEFAULT_TIMESTAMP_KEY,
+                    Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as _,
+                ),
+                ("code", Arc::new(Int64Array::from(vec![200])) as _),
+            ])
+            .unwrap();
+            let mut parquet = vec![];
+            let mut writer =
+                parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+            writer.write(&rb).unwrap();
+            writer.close().unwrap();
+            let parquet = Bytes::from(parquet);
+            let key = RelativePathBuf::from(format!(
+                "{stream_name}/date=2025-01-01/hour=10/minute={minute:02}/host.data.parquet"
+            ));
+            store.put_object(&key, parquet.clone()).await.unwrap();
+            if keys.is_empty() {
+                stream.set_schema(&rb.schema());
+                store
+                    .create_stream(stream_name, ObjectStoreFormat::default(), rb.schema())
+                    .await
+                    .unwrap();
+            }
+            let file = catalog::manifest::create_from_parquet(
+                store.absolute_url(&key).to_string(),
+                parquet.clone(),
+                parquet.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+            keys.push(key);
+        }
+
+        // only the files of the first two minutes are in the range
+        let query: Query = serde_json::from_value(json!({
+            "query": format!("select * from {stream_name}"),
+            "startTime": "2025-01-01T10:00:00Z",
+            "endTime": "2025-01-01T10:02:00Z"
+        }))
+        .unwrap();
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "Basic YWRtaW46YWRtaW4="))
+            .to_http_request();
+        let res = get_query_files(req, query).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        let files: Vec<Value> = serde_json::from_slice(&body).unwrap();
+
+        let mut files = files
+            .iter()
+            .map(|file| {
+                (
+                    file["path"].as_str().unwrap().to_owned(),
+                    file["size"].as_u64().unwrap(),
+                    file["startTime"].as_str().unwrap().to_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), 2);
+        for ((path, size, start_time), (key, minute)) in
+            files.iter().zip(keys.iter().zip(["00", "01"]))
+        {
+            assert!(path.ends_with(key.as_str()), "{path} is not {key}");
+            assert_eq!(*size, store.get_object(key).await.unwrap().len() as u64);
+            assert_eq!(start_time, &format!("2025-01-01T10:{minute}:00+00:00"));
+        }
+    }
+
+    #[actix_web::test]
+    async fn compacted_only_query_leaves_out_recent_small_files() {
+        use actix_web::test::TestRequest;
+        use arrow_array::TimestampMillisecondArray;
+        use chrono::TimeZone;
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog::compaction::compact_stream, event::DEFAULT_TIMESTAMP_KEY,
+            rbac::map::init_for_tests, storage::ObjectStoreFormat,
+        };
+
+        init_for_tests();
+        let stream_name = "file_tier_scans";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        let upload = |minute: u32, index: u32| {
+            let store = store.clone();
+            let stream = stream.clone();
+            async move {
+                let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, minute, index).unwrap();
+                let rb = RecordBatch::try_from_iter([
+                    (
+                        DEFAULT_TIMESTAMP_KEY,
+                        Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as _,
+                    ),
+                    (
+                        "msg",
+                        Arc::new(StringArray::from(vec![format!("event {index}")])) as _,
+                    ),
+                ])
+                .unwrap();
+                let mut parquet = vec![];
+                let mut writer =
+                    parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+                writer.write(&rb).unwrap();
+                writer.close().unwrap();
+                let parquet = Bytes::from(parquet);
+                let key = RelativePathBuf::from(format!(
+                    "{stream_name}/date=2025-01-01/hour=10/minute={minute:02}/{index}.data.parquet"
+                ));
+                store.put_object(&key, parquet.clone()).await.unwrap();
+                if stream.get_schema().fields().is_empty() {
+                    stream.set_schema(&rb.schema());
+                    store
+                        .create_stream(stream_name, ObjectStoreFormat::default(), rb.schema())
+                        .await
+                        .unwrap();
+                }
+                let file = catalog::manifest::create_from_parquet(
+                    store.absolute_url(&key).to_string(),
+                    parquet.clone(),
+                    parquet.len() as u64,
+                )
+                .unwrap();
+                catalog::update_snapshot(store.clone(), stream_name, file)
+                    .await
+                    .unwrap();
+            }
+        };
+
+        // four small files compacted into one, and a file written after
+        for index in 0..4 {
+            upload(0, index).await;
+        }
+        assert_eq!(compact_stream(stream_name).await.unwrap(), 4);
+        upload(1, 4).await;
+
+        let scanned = |file_tier: &'static str| async move {
+            let query: Query = serde_json::from_value(json!({
+                "query": format!("select msg from {stream_name}"),
+                "startTime": "2025-01-01T10:00:00Z",
+                "endTime": "2025-01-01T11:00:00Z",
+                "fileTier": file_tier
+            }))
+            .unwrap();
+            let req = TestRequest::default()
+                .insert_header(("Authorization", "Basic YWRtaW46YWRtaW4="))
+                .to_http_request();
+            let res = super::query(req, query).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+            let records: Vec<Value> = serde_json::from_slice(&body).unwrap();
+
+            records.len()
+        };
+        assert_eq!(scanned("compacted").await, 4);
+        assert_eq!(scanned("recent").await, 1);
+        assert_eq!(scanned("all").await, 5);
+    }
+}
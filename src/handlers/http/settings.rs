@@ -0,0 +1,95 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::time::Duration;
+
+use actix_web::{http::header::ContentType, web::Json, HttpResponse, Responder};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{option::Mode, parseable::PARSEABLE, sync};
+
+use super::cluster::sync_settings_with_ingestors;
+
+/// Settings of the server that can be tuned at runtime, without a restart
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSettings {
+    /// humantime representation of the local sync interval, e.g. "1m" or "30s"
+    local_sync_interval: String,
+}
+
+// GET "/settings/sync" ==> Get the currently configured sync interval
+pub async fn get_sync() -> Result<impl Responder, SettingsError> {
+    let settings = SyncSettings {
+        local_sync_interval: humantime::format_duration(sync::local_sync_interval()).to_string(),
+    };
+
+    Ok((Json(settings), StatusCode::OK))
+}
+
+// PUT "/settings/sync" ==> Update the sync interval, picked up by the next sync cycle
+pub async fn put_sync(Json(settings): Json<SyncSettings>) -> Result<impl Responder, SettingsError> {
+    let interval = humantime::parse_duration(&settings.local_sync_interval)
+        .map_err(|_| SettingsError::InvalidDuration(settings.local_sync_interval.clone()))?;
+    if interval < Duration::from_secs(1) {
+        return Err(SettingsError::InvalidDuration(settings.local_sync_interval));
+    }
+    sync::set_local_sync_interval(interval);
+    // ingestors are the nodes that sync, the querier's own interval only applies to itself
+    if PARSEABLE.options.mode == Mode::Query {
+        sync_settings_with_ingestors(&settings).await?;
+    }
+
+    Ok((
+        format!(
+            "Local sync interval updated to {}",
+            humantime::format_duration(interval)
+        ),
+        StatusCode::OK,
+    ))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("Invalid duration {0:?}, expected a humantime duration of atleast 1s, e.g. \"30s\"")]
+    InvalidDuration(String),
+    #[error("Error: {0}")]
+    Anyhow(#[from] anyhow::Error),
+    #[error("{0}")]
+    SerdeError(#[from] serde_json::Error),
+    #[error("Network Error: {0}")]
+    Network(#[from] reqwest::Error),
+}
+
+impl actix_web::ResponseError for SettingsError {
+    fn status_code(&self) -> http::StatusCode {
+        match self {
+            SettingsError::InvalidDuration(_) => StatusCode::BAD_REQUEST,
+            SettingsError::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SettingsError::SerdeError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SettingsError::Network(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::plaintext())
+            .body(self.to_string())
+    }
+}
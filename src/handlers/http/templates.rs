@@ -0,0 +1,280 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+use std::sync::Arc;
+
+use actix_web::{
+    http::header::ContentType,
+    web::{Json, Path},
+    HttpResponse, Responder,
+};
+use arrow_schema::Schema;
+use bytes::Bytes;
+use http::StatusCode;
+use relative_path::RelativePathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::format::LogSource,
+    parseable::{
+        validate_column_types, validate_custom_partition, validate_static_schema, DeclaredColumns,
+        PARSEABLE,
+    },
+    static_schema::StaticSchema,
+    storage::{object_storage::to_bytes, ObjectStorageError, PARSEABLE_ROOT_DIRECTORY},
+};
+
+use super::logstream::error::CreateStreamError;
+
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// Named bundle of schema and configuration, that any number of streams can be created from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamTemplate {
+    #[serde(default)]
+    pub name: String,
+    /// Schema of static schema streams, same as the body of a stream creation request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<StaticSchema>,
+    #[serde(default)]
+    pub static_schema_flag: bool,
+    /// Declared columns of dynamic schema streams, same as the `X-P-Column-Types` header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column_types: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_partition: Option<String>,
+    #[serde(default)]
+    pub log_source: LogSource,
+}
+
+impl StreamTemplate {
+    /// Schema that a stream created from the template starts out with
+    pub fn schema(&self, stream_name: &str) -> Result<Arc<Schema>, CreateStreamError> {
+        if let Some(custom_partition) = &self.custom_partition {
+            validate_custom_partition(custom_partition)?;
+        }
+
+        if let Some(column_types) = &self.column_types {
+            return validate_column_types(
+                DeclaredColumns::Header(column_types),
+                self.static_schema_flag,
+            );
+        }
+        if let (Some(schema), false) = (&self.schema, self.static_schema_flag) {
+            return validate_column_types(DeclaredColumns::Schema(schema.clone()), false);
+        }
+        let body = match &self.schema {
+            Some(schema) => Bytes::from(serde_json::to_vec(schema)?),
+            None => Bytes::new(),
+        };
+
+        validate_static_schema(
+            &body,
+            stream_name,
+            "",
+            self.custom_partition.as_ref(),
+            self.static_schema_flag,
+        )
+    }
+}
+
+pub fn template_path(name: &str) -> RelativePathBuf {
+    RelativePathBuf::from_iter([
+        PARSEABLE_ROOT_DIRECTORY,
+        TEMPLATES_DIR,
+        &format!("{name}.json"),
+    ])
+}
+
+/// Fetches the template from storage, `None` if there is no template by that name
+pub async fn load(name: &str) -> Result<Option<StreamTemplate>, TemplateError> {
+    let storage = PARSEABLE.storage.get_object_store();
+    match storage.get_object(&template_path(name)).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(ObjectStorageError::NoSuchKey(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), TemplateError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(TemplateError::InvalidName(name.to_owned()));
+    }
+
+    Ok(())
+}
+
+// GET "/templates" ==> List all templates
+pub async fn list() -> Result<impl Responder, TemplateError> {
+    let path = RelativePathBuf::from_iter([PARSEABLE_ROOT_DIRECTORY, TEMPLATES_DIR]);
+    let templates = PARSEABLE
+        .storage
+        .get_object_store()
+        .get_objects(
+            Some(&path),
+            Box::new(|file_name| file_name.ends_with(".json")),
+        )
+        .await?
+        .iter()
+        .map(|bytes| serde_json::from_slice(bytes))
+        .collect::<Result<Vec<StreamTemplate>, _>>()?;
+
+    Ok((Json(templates), StatusCode::OK))
+}
+
+// GET "/templates/{name}" ==> Get the template
+pub async fn get(name: Path<String>) -> Result<impl Responder, TemplateError> {
+    let name = name.into_inner();
+    let template = load(&name).await?.ok_or(TemplateError::NotFound(name))?;
+
+    Ok((Json(template), StatusCode::OK))
+}
+
+// PUT "/templates/{name}" ==> Create or replace the template, streams already created from it are unaffected
+pub async fn put(
+    name: Path<String>,
+    Json(mut template): Json<StreamTemplate>,
+) -> Result<impl Responder, TemplateError> {
+    let name = name.into_inner();
+    validate_name(&name)?;
+    // a template that can't create a stream is rejected upfront
+    template.schema(&name)?;
+    template.name = name;
+
+    PARSEABLE
+        .storage
+        .get_object_store()
+        .put_object(&template_path(&template.name), to_bytes(&template))
+        .await?;
+
+    Ok((format!("Template {} saved", template.name), StatusCode::OK))
+}
+
+// DELETE "/templates/{name}" ==> Delete the template, streams already created from it are unaffected
+pub async fn delete(name: Path<String>) -> Result<impl Responder, TemplateError> {
+    let name = name.into_inner();
+    if load(&name).await?.is_none() {
+        return Err(TemplateError::NotFound(name));
+    }
+    PARSEABLE
+        .storage
+        .get_object_store()
+        .delete_object(&template_path(&name))
+        .await?;
+
+    Ok((format!("Template {name} deleted"), StatusCode::OK))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Template {0} not found")]
+    NotFound(String),
+    #[error("Invalid template name {0:?}, only alphanumeric characters, '-' and '_' are allowed")]
+    InvalidName(String),
+    #[error("Invalid template: {0}")]
+    Invalid(#[from] CreateStreamError),
+    #[error("Storage Error {0}")]
+    Storage(#[from] ObjectStorageError),
+    #[error("Invalid template JSON: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+impl actix_web::ResponseError for TemplateError {
+    fn status_code(&self) -> http::StatusCode {
+        match self {
+            TemplateError::NotFound(_) => StatusCode::NOT_FOUND,
+            TemplateError::InvalidName(_) | TemplateError::Invalid(_) => StatusCode::BAD_REQUEST,
+            TemplateError::Storage(_) | TemplateError::Serde(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::plaintext())
+            .body(self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use crate::handlers::http::logstream::put_stream;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn streams_created_from_template_share_schema() {
+        let template: StreamTemplate = serde_json::from_value(serde_json::json!({
+            "staticSchemaFlag": true,
+            "schema": {
+                "fields": [
+                    {"name": "level", "data_type": "string"},
+                    {"name": "latency", "data_type": "float"}
+                ]
+            },
+            "customPartition": "level"
+        }))
+        .unwrap();
+        put(Path::from("web".to_owned()), Json(template))
+            .await
+            .unwrap();
+
+        let created = ["template_checkout", "template_payments"];
+        for stream_name in created {
+            let req = TestRequest::default()
+                .insert_header(("X-P-Template", "web"))
+                .to_http_request();
+            put_stream(req, Path::from(stream_name.to_owned()), Bytes::new())
+                .await
+                .unwrap();
+        }
+
+        let store = PARSEABLE.storage.get_object_store();
+        let [checkout, payments] =
+            created.map(|stream_name| PARSEABLE.get_stream(stream_name).unwrap());
+        assert_eq!(checkout.get_schema(), payments.get_schema());
+        assert!(checkout.get_schema().field_with_name("latency").is_ok());
+        assert_eq!(
+            store.get_schema(created[0]).await.unwrap(),
+            store.get_schema(created[1]).await.unwrap()
+        );
+        assert!(checkout.get_static_schema_flag());
+        assert_eq!(checkout.get_custom_partition().as_deref(), Some("level"));
+        assert_eq!(
+            checkout.get_custom_partition(),
+            payments.get_custom_partition()
+        );
+
+        // a stream can't be created from a template that doesn't exist
+        let req = TestRequest::default()
+            .insert_header(("X-P-Template", "missing"))
+            .to_http_request();
+        assert!(
+            put_stream(req, Path::from("template_missing".to_owned()), Bytes::new())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -261,6 +261,20 @@ pub fn extract_session_key(headers: &MetadataMap) -> Result<SessionKey, Box<Stat
         return Ok(SessionKey::SessionId(session));
     }
 
+    // bearer tokens are either sessions handed out by the flight sql handshake or API tokens
+    let bearer = headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    if let Some(token) = bearer {
+        return Ok(match ulid::Ulid::from_string(token) {
+            Ok(session) => SessionKey::SessionId(session),
+            Err(_) => SessionKey::ApiToken(token.to_owned()),
+        });
+    }
+
     Err(Box::new(Status::unauthenticated(
         "No authentication method supplied",
     )))
@@ -17,6 +17,7 @@
  */
 
 pub mod airplane;
+pub mod flight_sql;
 pub mod http;
 pub mod livetail;
 
@@ -27,8 +28,13 @@ const TIME_PARTITION_KEY: &str = "x-p-time-partition";
 const TIME_PARTITION_LIMIT_KEY: &str = "x-p-time-partition-limit";
 const CUSTOM_PARTITION_KEY: &str = "x-p-custom-partition";
 const STATIC_SCHEMA_FLAG: &str = "x-p-static-schema-flag";
+const COLUMN_TYPES_KEY: &str = "x-p-column-types";
 const AUTHORIZATION_KEY: &str = "authorization";
 const UPDATE_STREAM_KEY: &str = "x-p-update-stream";
+const ON_CONFLICT_KEY: &str = "x-p-on-conflict";
+const TEMPLATE_KEY: &str = "x-p-template";
+const PARTIAL_SUCCESS_KEY: &str = "x-p-partial-success";
+const PARTITION_KEY_HEADER: &str = "x-p-partition-key";
 pub const STREAM_TYPE_KEY: &str = "x-p-stream-type";
 const OIDC_SCOPE: &str = "openid profile email";
 const COOKIE_AGE_DAYS: usize = 7;
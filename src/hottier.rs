@@ -17,18 +17,22 @@
  */
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet},
     io,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use crate::{
-    catalog::manifest::{File, Manifest},
+    catalog::{
+        compaction::relative_path,
+        deletion::RangeFilter,
+        manifest::{File, Manifest},
+    },
     handlers::http::cluster::INTERNAL_STREAM_NAME,
     parseable::PARSEABLE,
     storage::{ObjectStorage, ObjectStorageError},
-    utils::{extract_datetime, human_size::bytes_to_human_size},
+    utils::{extract_datetime, human_size::bytes_to_human_size, time::TimeRange},
     validator::error::HotTierValidationError,
 };
 use chrono::NaiveDate;
@@ -68,6 +72,8 @@ pub struct StreamHotTier {
 pub struct HotTierManager {
     filesystem: LocalFileSystem,
     hot_tier_path: &'static Path,
+    /// Parquet files that are being downloaded and not yet recorded in the hot tier manifest
+    downloading: Mutex<HashSet<String>>,
 }
 
 impl HotTierManager {
@@ -76,6 +82,7 @@ impl HotTierManager {
         HotTierManager {
             filesystem: LocalFileSystem::new(),
             hot_tier_path,
+            downloading: Mutex::default(),
         }
     }
 
@@ -353,41 +360,89 @@ impl HotTierManager {
         parquet_path: PathBuf,
         date: NaiveDate,
     ) -> Result<bool, HotTierError> {
-        let mut file_processed = false;
-        let mut stream_hot_tier = self.get_hot_tier(stream).await?;
-        if !self.is_disk_available(parquet_file.file_size).await?
-            || stream_hot_tier.available_size <= parquet_file.file_size
+        // a file being downloaded by another task, say the sync during a prefetch, is sized and
+        // recorded by that task alone
+        if !self
+            .downloading
+            .lock()
+            .unwrap()
+            .insert(parquet_file.file_path.clone())
         {
-            if !self
-                .cleanup_hot_tier_old_data(
-                    stream,
-                    &mut stream_hot_tier,
-                    &parquet_path,
-                    parquet_file.file_size,
-                )
-                .await?
+            return Ok(true);
+        }
+        let processed: Result<bool, HotTierError> = async {
+            let mut stream_hot_tier = self.get_hot_tier(stream).await?;
+            if !self.is_disk_available(parquet_file.file_size).await?
+                || stream_hot_tier.available_size <= parquet_file.file_size
             {
-                return Ok(file_processed);
+                if !self
+                    .cleanup_hot_tier_old_data(
+                        stream,
+                        &mut stream_hot_tier,
+                        &parquet_path,
+                        parquet_file.file_size,
+                    )
+                    .await?
+                {
+                    return Ok(false);
+                }
+                *parquet_file_size = stream_hot_tier.used_size;
             }
-            *parquet_file_size = stream_hot_tier.used_size;
+            self.download_parquet_file(stream, parquet_file, parquet_file_size, parquet_path, date)
+                .await
         }
-        let parquet_file_path = RelativePathBuf::from(parquet_file.file_path.clone());
+        .await;
+        self.downloading
+            .lock()
+            .unwrap()
+            .remove(&parquet_file.file_path);
+        processed
+    }
+
+    /// download the parquet file and record it in the hot tier metadata and manifest
+    async fn download_parquet_file(
+        &self,
+        stream: &str,
+        parquet_file: &File,
+        parquet_file_size: &mut u64,
+        parquet_path: PathBuf,
+        date: NaiveDate,
+    ) -> Result<bool, HotTierError> {
+        let data_store = PARSEABLE.data_store(stream);
+        let parquet_file_path = relative_path(&*data_store, &parquet_file.file_path);
         fs::create_dir_all(parquet_path.parent().unwrap()).await?;
         let mut file = fs::File::create(parquet_path.clone()).await?;
-        let parquet_data = PARSEABLE
-            .storage
-            .get_object_store()
-            .get_object(&parquet_file_path)
-            .await?;
+        let parquet_data = data_store.get_object(&parquet_file_path).await?;
         file.write_all(&parquet_data).await?;
-        *parquet_file_size += parquet_file.file_size;
-        stream_hot_tier.used_size = *parquet_file_size;
 
-        stream_hot_tier.available_size -= parquet_file.file_size;
+        // sized after the hot tier as it is once downloaded, as other files may have been
+        // downloaded or evicted meanwhile
+        let mut stream_hot_tier = self.get_hot_tier(stream).await?;
+        stream_hot_tier.used_size += parquet_file.file_size;
+        *parquet_file_size = stream_hot_tier.used_size;
+        stream_hot_tier.available_size = stream_hot_tier
+            .available_size
+            .saturating_sub(parquet_file.file_size);
         self.put_hot_tier(stream, &mut stream_hot_tier).await?;
-        file_processed = true;
+        self.record_in_manifest(stream, date, parquet_file).await?;
+
+        Ok(true)
+    }
+
+    /// record a parquet file downloaded into the hot tier in the hot tier manifest of its date,
+    /// making it available to queries
+    async fn record_in_manifest(
+        &self,
+        stream: &str,
+        date: NaiveDate,
+        parquet_file: &File,
+    ) -> Result<(), HotTierError> {
         let path = self.get_stream_path_for_date(stream, &date);
         let mut hot_tier_manifest = HotTierManager::get_hot_tier_manifest_from_path(path).await?;
+        // a file downloaded again replaces its earlier entry, so that it isn't read twice
+        hot_tier_manifest
+            .files
+            .retain(|file| file.file_path != parquet_file.file_path);
         hot_tier_manifest.files.push(parquet_file.clone());
         hot_tier_manifest
             .files
@@ -399,7 +454,67 @@ impl HotTierManager {
         fs::create_dir_all(manifest_path.parent().unwrap()).await?;
         fs::write(manifest_path, serde_json::to_vec(&hot_tier_manifest)?).await?;
 
-        Ok(file_processed)
+        Ok(())
+    }
+
+    /// download the parquet files of the stream within `time_range` into the hot tier ahead of
+    /// the queries expected to read them, returns the number of files that were downloaded.
+    /// Stops early if the hot tier runs out of space that can be freed up for newer files
+    pub async fn prefetch(
+        &self,
+        stream: &str,
+        time_range: TimeRange,
+    ) -> Result<usize, HotTierError> {
+        let stream_hot_tier = self.get_hot_tier(stream).await?;
+        let mut parquet_file_size = stream_hot_tier.used_size;
+        let range = RangeFilter::new(stream, time_range);
+        let (start_date, end_date) = range.partition_dates();
+
+        let object_store = PARSEABLE.storage.get_object_store();
+        let mut prefetched = 0;
+        for (str_date, manifest_files) in object_store.list_manifest_files(stream).await? {
+            let Ok(date) =
+                NaiveDate::parse_from_str(str_date.trim_start_matches("date="), "%Y-%m-%d")
+            else {
+                warn!("Invalid date format: {}", str_date);
+                continue;
+            };
+            if date < start_date || date > end_date {
+                continue;
+            }
+
+            for manifest_file in manifest_files {
+                let manifest_path = RelativePathBuf::from(manifest_file);
+                let storage_manifest_bytes = object_store.get_object(&manifest_path).await?;
+                let storage_manifest: Manifest = serde_json::from_slice(&storage_manifest_bytes)?;
+
+                for parquet_file in storage_manifest
+                    .files
+                    .iter()
+                    .filter(|file| range.contains(&file.file_path))
+                {
+                    let parquet_path = self.hot_tier_path.join(&parquet_file.file_path);
+                    if parquet_path.exists() {
+                        continue;
+                    }
+                    if !self
+                        .process_parquet_file(
+                            stream,
+                            parquet_file,
+                            &mut parquet_file_size,
+                            parquet_path,
+                            date,
+                        )
+                        .await?
+                    {
+                        return Ok(prefetched);
+                    }
+                    prefetched += 1;
+                }
+            }
+        }
+
+        Ok(prefetched)
     }
 
     ///fetch the list of dates available in the hot tier directory for the stream and sort them
@@ -473,27 +588,8 @@ impl HotTierManager {
         manifest_files: &mut Vec<File>,
     ) -> Result<Vec<File>, HotTierError> {
         // Fetch the list of hot tier parquet files for the given stream.
-        let mut hot_tier_files = self.get_hot_tier_parquet_files(stream).await?;
-
-        // Retain only the files in `hot_tier_files` that also exist in `manifest_files`.
-        hot_tier_files.retain(|file| {
-            manifest_files
-                .iter()
-                .any(|manifest_file| manifest_file.file_path.eq(&file.file_path))
-        });
-
-        // Sort `hot_tier_files` in descending order by file path.
-        hot_tier_files.sort_unstable_by(|a, b| b.file_path.cmp(&a.file_path));
-
-        // Update `manifest_files` to exclude files that are present in the filtered `hot_tier_files`.
-        manifest_files.retain(|manifest_file| {
-            hot_tier_files
-                .iter()
-                .all(|file| !file.file_path.eq(&manifest_file.file_path))
-        });
-
-        // Sort `manifest_files` in descending order by file path.
-        manifest_files.sort_unstable_by(|a, b| b.file_path.cmp(&a.file_path));
+        let hot_tier_files = self.get_hot_tier_parquet_files(stream).await?;
+        let hot_tier_files = split_by_layer(hot_tier_files, manifest_files);
 
         Ok(hot_tier_files)
     }
@@ -617,6 +713,56 @@ impl HotTierManager {
         Ok(delete_successful)
     }
 
+    /// Deletes the files of the stream in hot tier that lie within `range`, as its data was
+    /// deleted, and frees up the space used by them
+    pub async fn delete_range(
+        &self,
+        stream: &str,
+        range: &RangeFilter,
+    ) -> Result<(), HotTierError> {
+        let mut stream_hot_tier = self.get_hot_tier(stream).await?;
+        for date in self.fetch_hot_tier_dates(stream).await? {
+            let path = self.get_stream_path_for_date(stream, &date);
+            if !path.exists() {
+                continue;
+            }
+
+            let date_dirs = ReadDirStream::new(fs::read_dir(&path).await?);
+            let mut manifest_files: Vec<DirEntry> = date_dirs.try_collect().await?;
+            manifest_files.retain(|manifest| {
+                manifest
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".manifest.json")
+            });
+            for manifest_file in manifest_files {
+                let file = fs::read(manifest_file.path()).await?;
+                let mut manifest: Manifest = serde_json::from_slice(&file)?;
+                let (removed, kept): (Vec<_>, Vec<_>) = manifest
+                    .files
+                    .into_iter()
+                    .partition(|file| range.contains(&file.file_path));
+                manifest.files = kept;
+                if removed.is_empty() {
+                    continue;
+                }
+                fs::write(manifest_file.path(), serde_json::to_vec(&manifest)?).await?;
+
+                for file in removed {
+                    let path_to_delete = self.hot_tier_path.join(&file.file_path);
+                    if path_to_delete.exists() {
+                        fs::remove_file(&path_to_delete).await?;
+                        stream_hot_tier.used_size =
+                            stream_hot_tier.used_size.saturating_sub(file.file_size);
+                        stream_hot_tier.available_size += file.file_size;
+                    }
+                }
+            }
+        }
+
+        self.put_hot_tier(stream, &mut stream_hot_tier).await
+    }
+
     ///check if the disk is available to download the parquet file
     /// check if the disk usage is above the threshold
     pub async fn is_disk_available(&self, size_to_download: u64) -> Result<bool, HotTierError> {
@@ -787,3 +933,213 @@ pub enum HotTierError {
     #[error("{0}")]
     Anyhow(#[from] anyhow::Error),
 }
+
+/// Splits the files of a query between the hot tier and object storage, such that every file is
+/// read exactly once. Returns the files to be read from the hot tier, `manifest_files` is left
+/// with the ones to be read from storage. Files are matched by path and size, a cached copy of a
+/// different size is stale and is read from storage instead. Both lists are sorted in descending
+/// order by file path.
+fn split_by_layer(mut hot_tier_files: Vec<File>, manifest_files: &mut Vec<File>) -> Vec<File> {
+    // a file listed in more than one manifest is only read once
+    let mut seen = HashSet::new();
+    manifest_files.retain(|file| seen.insert(file.file_path.clone()));
+
+    let in_storage: HashMap<&str, u64> = manifest_files
+        .iter()
+        .map(|file| (file.file_path.as_str(), file.file_size))
+        .collect();
+    let mut seen = HashSet::new();
+    hot_tier_files.retain(|file| {
+        in_storage.get(file.file_path.as_str()) == Some(&file.file_size)
+            && seen.insert(file.file_path.clone())
+    });
+    manifest_files.retain(|file| !seen.contains(&file.file_path));
+
+    hot_tier_files.sort_unstable_by(|a, b| b.file_path.cmp(&a.file_path));
+    manifest_files.sort_unstable_by(|a, b| b.file_path.cmp(&a.file_path));
+
+    hot_tier_files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(file_path: &str, file_size: u64) -> File {
+        File {
+            file_path: file_path.to_owned(),
+            num_rows: 1,
+            file_size,
+            ingestion_size: file_size,
+            columns: vec![],
+            sort_order_id: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn prefetched_files_are_read_from_hot_tier() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let manager = HotTierManager::new(Box::leak(dir.path().to_path_buf().into_boxed_path()));
+        let date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let prefetched = file("app/date=2025-01-01/hour=10/minute=00/a.parquet", 10);
+        let remote = file("app/date=2025-01-01/hour=11/minute=00/b.parquet", 10);
+
+        let path = dir.path().join(&prefetched.file_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"parquet").unwrap();
+        manager
+            .record_in_manifest("app", date, &prefetched)
+            .await
+            .unwrap();
+
+        // the query is served from hot tier for the prefetched file, and from storage for the rest
+        let mut manifest_files = vec![prefetched.clone(), remote.clone()];
+        let hot_tier_files = manager
+            .get_hot_tier_manifest_files("app", &mut manifest_files)
+            .await
+            .unwrap();
+        assert_eq!(hot_tier_files[0].file_path, prefetched.file_path);
+        assert_eq!(hot_tier_files.len(), 1);
+        assert_eq!(manifest_files.len(), 1);
+        assert_eq!(manifest_files[0].file_path, remote.file_path);
+    }
+
+    #[test]
+    fn file_in_both_hot_tier_and_storage_is_read_once() {
+        let both = file("app/date=2025-01-01/hour=10/minute=00/a.parquet", 10);
+        let stale = file("app/date=2025-01-01/hour=10/minute=01/b.parquet", 10);
+        let remote = file("app/date=2025-01-01/hour=11/minute=00/c.parquet", 10);
+        let mut stale_copy = stale.clone();
+        stale_copy.file_size = 5;
+
+        // the file is listed twice in each layer, as by overlapping manifests
+        let hot_tier_files = vec![both.clone(), stale_copy, both.clone()];
+        let mut manifest_files = vec![both.clone(), stale.clone(), remote.clone(), both.clone()];
+        let hot_tier_files = split_by_layer(hot_tier_files, &mut manifest_files);
+
+        let mut read: Vec<_> = hot_tier_files
+            .iter()
+            .chain(&manifest_files)
+            .map(|file| file.file_path.as_str())
+            .collect();
+        read.sort_unstable();
+        assert_eq!(
+            read,
+            [
+                both.file_path.as_str(),
+                stale.file_path.as_str(),
+                remote.file_path.as_str()
+            ]
+        );
+        assert_eq!(hot_tier_files.len(), 1);
+        assert_eq!(hot_tier_files[0].file_path, both.file_path);
+        let rows: u64 = hot_tier_files
+            .iter()
+            .chain(&manifest_files)
+            .map(|file| file.num_rows)
+            .sum();
+        assert_eq!(rows, 3);
+    }
+
+    #[tokio::test]
+    async fn files_of_the_range_are_prefetched_from_the_store() {
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch, TimestampMillisecondArray};
+        use arrow_schema::Schema;
+        use bytes::Bytes;
+        use chrono::{DateTime, Utc};
+
+        use crate::{catalog, event::DEFAULT_TIMESTAMP_KEY, storage::ObjectStoreFormat};
+
+        let stream_name = "prefetch_hot_tier";
+        let dir = temp_dir::TempDir::new().unwrap();
+        let manager = HotTierManager::new(Box::leak(dir.path().to_path_buf().into_boxed_path()));
+        manager
+            .put_hot_tier(
+                stream_name,
+                &mut StreamHotTier {
+                    version: Some(CURRENT_HOT_TIER_VERSION.to_owned()),
+                    size: 1 << 30,
+                    used_size: 0,
+                    available_size: 1 << 30,
+                    oldest_date_time_entry: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        PARSEABLE.get_or_create_stream(stream_name);
+        let store = PARSEABLE.storage.get_object_store();
+        store
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat::default(),
+                Arc::new(Schema::empty()),
+            )
+            .await
+            .unwrap();
+        let mut uploaded = vec![];
+        for hour in ["10", "12"] {
+            let at = format!("2025-01-01T{hour}:00:00Z")
+                .parse::<DateTime<Utc>>()
+                .unwrap();
+            let rb = RecordBatch::try_from_iter([
+                (
+                    DEFAULT_TIMESTAMP_KEY,
+                    Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()]))
+                        as ArrayRef,
+                ),
+                ("code", Arc::new(Int64Array::from(vec![200])) as ArrayRef),
+            ])
+            .unwrap();
+            let mut parquet = vec![];
+            let mut writer =
+                parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+            writer.write(&rb).unwrap();
+            writer.close().unwrap();
+            let parquet = Bytes::from(parquet);
+
+            let key = RelativePathBuf::from(format!(
+                "{stream_name}/date=2025-01-01/hour={hour}/minute=00/host.data.parquet"
+            ));
+            store.put_object(&key, parquet.clone()).await.unwrap();
+            let file = catalog::manifest::create_from_parquet(
+                store.absolute_url(&key).to_string(),
+                parquet.clone(),
+                parquet.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file.clone())
+                .await
+                .unwrap();
+            uploaded.push(file);
+        }
+
+        // only the file of hour 10 is within the range, and is sized and recorded once downloaded
+        let time_range = TimeRange::new(
+            "2025-01-01T10:00:00Z".parse().unwrap(),
+            "2025-01-01T11:00:00Z".parse().unwrap(),
+        );
+        let prefetched = manager
+            .prefetch(stream_name, time_range.clone())
+            .await
+            .unwrap();
+        assert_eq!(prefetched, 1);
+        let hot_tier = manager.get_hot_tier(stream_name).await.unwrap();
+        assert_eq!(hot_tier.used_size, uploaded[0].file_size);
+        assert_eq!(hot_tier.available_size, (1 << 30) - uploaded[0].file_size);
+
+        let mut manifest_files = uploaded.clone();
+        let hot_tier_files = manager
+            .get_hot_tier_manifest_files(stream_name, &mut manifest_files)
+            .await
+            .unwrap();
+        assert_eq!(hot_tier_files.len(), 1);
+        assert_eq!(hot_tier_files[0].file_path, uploaded[0].file_path);
+        assert_eq!(manifest_files.len(), 1);
+        assert_eq!(manifest_files[0].file_path, uploaded[1].file_path);
+
+        // files already in the hot tier are not downloaded again
+        let prefetched = manager.prefetch(stream_name, time_range).await.unwrap();
+        assert_eq!(prefetched, 0);
+    }
+}
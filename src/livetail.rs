@@ -27,9 +27,16 @@ use tokio::sync::mpsc::{
     self, error::TrySendError, Receiver, Sender, UnboundedReceiver, UnboundedSender,
 };
 
-use arrow_array::RecordBatch;
+use arrow::compute::filter_record_batch;
+use arrow_array::{cast::AsArray, RecordBatch};
+use arrow_schema::Schema;
+use datafusion::{
+    common::DFSchema, error::DataFusionError, logical_expr::Expr, prelude::SessionContext,
+};
 use once_cell::sync::Lazy;
 
+use crate::utils::arrow::record_batches_to_json;
+
 pub static LIVETAIL: Lazy<LiveTail> = Lazy::new(LiveTail::default);
 
 pub type LiveTailRegistry = RwLock<HashMap<String, Vec<SenderPipe>>>;
@@ -75,6 +82,57 @@ pub enum Message {
     Skipped(usize),
 }
 
+/// Filter on the rows pushed to a live tail, a SQL boolean expression such as `level = 'error'`
+pub struct RowFilter {
+    ctx: SessionContext,
+    expr: Expr,
+}
+
+impl RowFilter {
+    /// Parses the filter, columns it refers to are checked against `schema` of the stream
+    pub fn try_new(sql: &str, schema: Schema) -> Result<Self, DataFusionError> {
+        let ctx = SessionContext::new();
+        let expr = ctx.parse_sql_expr(sql, &DFSchema::try_from(schema)?)?;
+
+        Ok(Self { ctx, expr })
+    }
+
+    /// Returns the rows of `rb` matching the filter, if any. Batches the filter can't be
+    /// evaluated against, say when they don't have a column the filter refers to, match no rows.
+    pub fn apply(&self, rb: &RecordBatch) -> Option<RecordBatch> {
+        let schema = DFSchema::try_from(rb.schema().as_ref().clone()).ok()?;
+        let predicate = self
+            .ctx
+            .create_physical_expr(self.expr.clone(), &schema)
+            .ok()?;
+        let mask = predicate
+            .evaluate(rb)
+            .and_then(|mask| mask.into_array(rb.num_rows()))
+            .ok()?;
+        let rb = filter_record_batch(rb, mask.as_boolean_opt()?).ok()?;
+
+        (rb.num_rows() > 0).then_some(rb)
+    }
+}
+
+/// Formats a message as a server-sent event, rows are sent as a json array.
+/// Returns `None` when none of the rows match `filter`.
+pub fn to_sse_event(message: Message, filter: Option<&RowFilter>) -> Option<String> {
+    match message {
+        Message::Record(rb) => {
+            let rb = match filter {
+                Some(filter) => filter.apply(&rb)?,
+                None => rb,
+            };
+            let rows = record_batches_to_json(&[rb]).ok()?;
+            Some(format!("data: {}\n\n", serde_json::to_string(&rows).ok()?))
+        }
+        Message::Skipped(count) => {
+            Some(format!("event: skipped\ndata: {{\"skipped\":{count}}}\n\n"))
+        }
+    }
+}
+
 // Receiver should swap out channel for a new one when full
 pub enum Command {
     Skipping(usize),
@@ -167,3 +225,43 @@ impl Drop for ReceiverPipe {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::StringArray;
+    use arrow_schema::{DataType, Field};
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn subscriber_receives_event_ingested_after_subscribing() {
+        let livetail = LiveTail::default();
+        let schema = Schema::new(vec![Field::new("level", DataType::Utf8, true)]);
+        let rb = RecordBatch::try_new(
+            Arc::new(schema.clone()),
+            vec![Arc::new(StringArray::from(vec!["info", "error"]))],
+        )
+        .unwrap();
+
+        // events before subscribing are not sent
+        livetail.process("test_stream", &rb);
+        let mut pipe = livetail.new_pipe("id".to_owned(), "test_stream".to_owned());
+        livetail.process("test_stream", &rb);
+
+        let filter = RowFilter::try_new("level = 'error'", schema).unwrap();
+        let message = pipe.next().await.unwrap();
+        assert_eq!(
+            to_sse_event(message, Some(&filter)).unwrap(),
+            "data: [{\"level\":\"error\"}]\n\n"
+        );
+    }
+
+    #[test]
+    fn skipped_rows_are_notified() {
+        assert_eq!(
+            to_sse_event(Message::Skipped(5), None).unwrap(),
+            "event: skipped\ndata: {\"skipped\":5}\n\n"
+        );
+    }
+}
@@ -55,7 +55,7 @@ async fn main() -> anyhow::Result<()> {
     let metadata = storage::resolve_parseable_metadata(&parseable_json).await?;
     banner::print(&PARSEABLE, &metadata).await;
     // initialize the rbac map
-    rbac::map::init(&metadata);
+    rbac::map::init(&metadata)?;
     // keep metadata info in mem
     metadata.set_global();
 
@@ -16,21 +16,30 @@
  *
  */
 
-use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use arrow_schema::{DataType, Field, Schema};
 use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::catalog::snapshot::ManifestItem;
-use crate::event::format::LogSourceEntry;
+use crate::event::column_limit::ColumnLimit;
+use crate::event::derived::DerivedColumn;
+use crate::event::format::inference::{self, SchemaInference};
+use crate::event::format::{protobuf::ProtoDescriptor, LogSourceEntry, NumberInference};
+use crate::event::sampling::SamplingRule;
 use crate::metrics::{
     EVENTS_INGESTED, EVENTS_INGESTED_DATE, EVENTS_INGESTED_SIZE, EVENTS_INGESTED_SIZE_DATE,
     EVENTS_STORAGE_SIZE_DATE, LIFETIME_EVENTS_INGESTED, LIFETIME_EVENTS_INGESTED_SIZE,
 };
+use crate::parseable::PARSEABLE;
+use crate::rbac::acl::StreamAcl;
 use crate::storage::retention::Retention;
 use crate::storage::StreamType;
+use crate::utils::time::timestamp_type;
 
 pub fn update_stats(
     stream_name: &str,
@@ -88,6 +97,108 @@ pub struct LogStreamMetadata {
     pub hot_tier_enabled: bool,
     pub stream_type: StreamType,
     pub log_source: Vec<LogSourceEntry>,
+    /// Settings of the stream that can be updated once it is created
+    pub settings: Arc<StreamSettings>,
+}
+
+/// Per-stream settings, persisted in the stream.json alongside the rest of its metadata
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protobuf_descriptor: Option<ProtoDescriptor>,
+    /// Columns dropped from events on ingestion
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude_columns: Vec<String>,
+    /// Other names by which the stream can be queried
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number_inference: Option<NumberInference>,
+    #[serde(skip_serializing_if = "StreamAcl::is_empty")]
+    pub acl: StreamAcl,
+    /// Frozen streams reject new events, but remain queryable
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub frozen: bool,
+    /// Only a fraction of events ingested are stored when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingRule>,
+    /// Path of the field events are wrapped under, its subtree is ingested as the event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_body_path: Option<String>,
+    /// Events are stored as is in a single JSON column, their fields are extracted when queried
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub schema_on_read: bool,
+    /// Size in bytes at which parquet files of the stream are rolled over into a new one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parquet_target_size: Option<u64>,
+    /// Names by which columns can still be queried after being renamed, mapped to the new names
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub column_aliases: HashMap<String, String>,
+    /// Columns whose values such as `"true"`, `1` or `"no"` are normalized into booleans
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub boolean_columns: Vec<String>,
+    /// Timezone, e.g. `America/New_York`, whose local midnight the date partitions start at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition_timezone: Option<String>,
+    /// Limit on the number of distinct columns of the stream, and what happens to events past it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column_limit: Option<ColumnLimit>,
+    /// Name of the registered strategy the types of new fields are inferred with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_inference: Option<String>,
+    /// Prefix in object storage the data files of the stream are put under, instead of the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_prefix: Option<String>,
+    /// Bucket of the object store the data files of the stream are put in, instead of the default
+    /// one, its manifests and metadata stay in the default bucket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_bucket: Option<String>,
+    /// Columns computed from the fields of events as they are ingested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub derived_columns: Vec<DerivedColumn>,
+    /// Fields the time of events is taken from, as their `p_timestamp` and to partition them by,
+    /// the first one present is used
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timestamp_fields: Vec<String>,
+    /// Duration, e.g. `10m`, within which exact duplicates of an event are dropped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedup_window: Option<String>,
+    /// Columns whose type was declared when the stream was created, values of other types are converted into it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub declared_columns: Vec<String>,
+    /// Number of partitions events are fanned out into by their partition key, fixed when the
+    /// stream is created as the partitions of its files are looked up by it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition_key_buckets: Option<usize>,
+}
+
+impl StreamSettings {
+    /// Timezone the partitions of the stream are aligned to, UTC if unset
+    pub fn partition_timezone(&self) -> Option<Tz> {
+        self.partition_timezone
+            .as_deref()
+            .and_then(|timezone| timezone.parse().ok())
+    }
+
+    /// Window within which exact duplicates of an event are dropped, none if events aren't deduplicated
+    pub fn dedup_window(&self) -> Option<Duration> {
+        self.dedup_window
+            .as_deref()
+            .and_then(|window| humantime::parse_duration(window).ok())
+    }
+
+    /// Returns the strategy the types of new fields of the stream are inferred with
+    pub fn schema_inference(&self) -> Arc<dyn SchemaInference> {
+        inference::strategy(self.schema_inference.as_deref(), self.number_inference)
+    }
+
+    /// Number of partitions events are fanned out into by their partition key, as configured for
+    /// streams created before it was recorded
+    pub fn partition_key_buckets(&self) -> usize {
+        self.partition_key_buckets
+            .unwrap_or(PARSEABLE.options.partition_key_buckets)
+    }
 }
 
 impl LogStreamMetadata {
@@ -141,19 +252,18 @@ pub async fn update_data_type_time_partition(
 ) -> anyhow::Result<()> {
     if let Some(time_partition) = time_partition {
         if let Ok(time_partition_field) = schema.field_with_name(time_partition) {
-            if time_partition_field.data_type() != &DataType::Timestamp(TimeUnit::Millisecond, None)
-            {
+            if !matches!(
+                time_partition_field.data_type(),
+                DataType::Timestamp(_, None)
+            ) {
                 let mut fields = schema
                     .fields()
                     .iter()
                     .filter(|field| field.name() != time_partition)
                     .cloned()
                     .collect::<Vec<Arc<Field>>>();
-                let time_partition_field = Arc::new(Field::new(
-                    time_partition,
-                    DataType::Timestamp(TimeUnit::Millisecond, None),
-                    true,
-                ));
+                let time_partition_field =
+                    Arc::new(Field::new(time_partition, timestamp_type(), true));
                 fields.push(time_partition_field);
                 *schema = Schema::new(fields);
             }
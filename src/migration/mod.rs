@@ -21,7 +21,7 @@ pub mod metadata_migration;
 mod schema_migration;
 mod stream_metadata_migration;
 
-use std::{collections::HashMap, fs::OpenOptions};
+use std::{collections::HashMap, fs::OpenOptions, sync::Arc};
 
 use arrow_schema::Schema;
 use bytes::Bytes;
@@ -31,13 +31,17 @@ use serde_json::Value;
 use tracing::warn;
 
 use crate::{
-    metadata::{load_daily_metrics, update_data_type_time_partition, LogStreamMetadata},
+    event::format::inference,
+    metadata::{
+        load_daily_metrics, update_data_type_time_partition, LogStreamMetadata, StreamSettings,
+    },
     metrics::fetch_stats_from_storage,
     option::Mode,
     parseable::{Parseable, PARSEABLE},
     storage::{
         object_storage::{parseable_json_path, schema_path, stream_json_path},
-        ObjectStorage, ObjectStoreFormat, PARSEABLE_METADATA_FILE_NAME,
+        ObjectStorage, ObjectStoreFormat, PARSEABLE_METADATA_FILE_NAME, STREAM_METADATA_FILE_NAME,
+        STREAM_ROOT_DIRECTORY,
     },
 };
 
@@ -355,11 +359,26 @@ async fn setup_logstream_metadata(
         hot_tier_enabled,
         stream_type,
         log_source,
+        settings,
         ..
     } = serde_json::from_value(stream_metadata_value).unwrap_or_default();
 
     let storage = PARSEABLE.storage.get_object_store();
 
+    // settings are updated through the querier, an ingestor that missed an update catches up on start
+    let settings = match PARSEABLE.options.mode {
+        Mode::Ingest => match querier_settings(stream, &*storage).await {
+            Some(querier) if querier != settings => {
+                storage.put_stream_settings(stream, &querier).await?;
+                querier
+            }
+            _ => settings,
+        },
+        _ => settings,
+    };
+    // streams configured with a strategy that isn't registered would silently infer with the default one
+    inference::ensure_registered(settings.schema_inference.as_deref())?;
+
     update_data_type_time_partition(arrow_schema, time_partition.as_ref()).await?;
     storage.put_schema(stream, arrow_schema).await?;
     fetch_stats_from_storage(stream, stats).await;
@@ -388,11 +407,22 @@ async fn setup_logstream_metadata(
         hot_tier_enabled,
         stream_type,
         log_source,
+        settings: Arc::new(settings),
     };
 
     Ok(metadata)
 }
 
+/// Settings of the stream as last updated through the querier
+async fn querier_settings(stream: &str, storage: &dyn ObjectStorage) -> Option<StreamSettings> {
+    let path =
+        RelativePathBuf::from_iter([stream, STREAM_ROOT_DIRECTORY, STREAM_METADATA_FILE_NAME]);
+    let bytes = storage.get_object(&path).await.ok()?;
+    serde_json::from_slice::<ObjectStoreFormat>(&bytes)
+        .ok()
+        .map(|format| format.settings)
+}
+
 #[inline(always)]
 pub fn to_bytes(any: &(impl ?Sized + Serialize)) -> Bytes {
     serde_json::to_vec(any)
@@ -84,6 +84,16 @@ impl From<Compression> for parquet::basic::Compression {
     }
 }
 
+/// Decides when ingestion acknowledges events to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AckMode {
+    /// Acknowledge as soon as events are staged, before they reach object store
+    #[default]
+    Async,
+    /// Acknowledge only after events have been uploaded to object store
+    Sync,
+}
+
 pub mod validation {
     use std::{
         env, io,
@@ -91,10 +101,17 @@ pub mod validation {
         path::{Path, PathBuf},
     };
 
-    use crate::cli::DATASET_FIELD_COUNT_LIMIT;
+    use crate::{
+        cli::DATASET_FIELD_COUNT_LIMIT,
+        event::format::{
+            ClockSkewPolicy, CoercionPolicy, DuplicateColumnPolicy, FieldLengthPolicy,
+        },
+        utils::{human_size::human_size_to_bytes, time::TimestampPrecision},
+    };
+    use chrono_tz::Tz;
     use path_clean::PathClean;
 
-    use super::{Compression, Mode};
+    use super::{AckMode, Compression, Mode};
 
     pub fn file_path(s: &str) -> Result<PathBuf, String> {
         if s.is_empty() {
@@ -149,6 +166,62 @@ pub mod validation {
         }
     }
 
+    pub fn timestamp_precision(s: &str) -> Result<TimestampPrecision, String> {
+        match s {
+            "s" | "second" => Ok(TimestampPrecision::Second),
+            "ms" | "millisecond" => Ok(TimestampPrecision::Millisecond),
+            "us" | "microsecond" => Ok(TimestampPrecision::Microsecond),
+            "ns" | "nanosecond" => Ok(TimestampPrecision::Nanosecond),
+            _ => Err("Invalid TIMESTAMP PRECISION provided".to_string()),
+        }
+    }
+
+    pub fn timezone(s: &str) -> Result<Tz, String> {
+        s.parse()
+            .map_err(|_| format!("Invalid TIMEZONE provided: {s}"))
+    }
+
+    pub fn coercion_policy(s: &str) -> Result<CoercionPolicy, String> {
+        match s {
+            "strict" => Ok(CoercionPolicy::Strict),
+            "string" => Ok(CoercionPolicy::String),
+            "number" => Ok(CoercionPolicy::Number),
+            _ => Err("Invalid TYPE COERCION policy provided".to_string()),
+        }
+    }
+
+    pub fn clock_skew_policy(s: &str) -> Result<ClockSkewPolicy, String> {
+        match s {
+            "reject" => Ok(ClockSkewPolicy::Reject),
+            "clamp" => Ok(ClockSkewPolicy::Clamp),
+            _ => Err("Invalid CLOCK SKEW policy provided".to_string()),
+        }
+    }
+
+    pub fn field_length_policy(s: &str) -> Result<FieldLengthPolicy, String> {
+        match s {
+            "reject" => Ok(FieldLengthPolicy::Reject),
+            "truncate" => Ok(FieldLengthPolicy::Truncate),
+            _ => Err("Invalid FIELD LENGTH policy provided".to_string()),
+        }
+    }
+
+    pub fn duplicate_column_policy(s: &str) -> Result<DuplicateColumnPolicy, String> {
+        match s {
+            "reject" => Ok(DuplicateColumnPolicy::Reject),
+            "suffix" => Ok(DuplicateColumnPolicy::Suffix),
+            _ => Err("Invalid DUPLICATE COLUMN policy provided".to_string()),
+        }
+    }
+
+    pub fn ack_mode(s: &str) -> Result<AckMode, String> {
+        match s {
+            "async" => Ok(AckMode::Async),
+            "sync" => Ok(AckMode::Sync),
+            _ => Err("Invalid ACK MODE provided".to_string()),
+        }
+    }
+
     pub fn compression(s: &str) -> Result<Compression, String> {
         match s {
             "uncompressed" => Ok(Compression::Uncompressed),
@@ -163,6 +236,10 @@ pub mod validation {
         }
     }
 
+    pub fn human_size(s: &str) -> Result<u64, String> {
+        human_size_to_bytes(s).map_err(|err| err.to_string())
+    }
+
     pub fn validate_disk_usage(max_disk_usage: &str) -> Result<f64, String> {
         if let Ok(max_disk_usage) = max_disk_usage.parse::<f64>() {
             if (0.0..=100.0).contains(&max_disk_usage) {
@@ -29,38 +29,60 @@ use actix_web::http::header::HeaderMap;
 use arrow_schema::{Field, Schema};
 use bytes::Bytes;
 use chrono::Utc;
-use clap::{error::ErrorKind, Parser};
+#[cfg(not(test))]
+use clap::error::ErrorKind;
+use clap::Parser;
 use http::{header::CONTENT_TYPE, HeaderName, HeaderValue, StatusCode};
 use once_cell::sync::Lazy;
-pub use staging::StagingError;
+use relative_path::RelativePath;
+pub use shutdown::{drain_and_flush, IngestGate};
+pub use staging::{
+    retry::{dead_letter, RetryDecision},
+    StagingError,
+};
 use streams::StreamRef;
-pub use streams::{Stream, StreamNotFound, Streams};
-use tracing::error;
+pub use streams::{SchemaDriftError, Stream, StreamNotFound, Streams};
+use tracing::{error, warn};
 
+#[cfg(not(test))]
+use crate::cli::{Cli, StorageOptions};
 #[cfg(feature = "kafka")]
 use crate::connectors::kafka::config::KafkaConfig;
 use crate::{
-    cli::{Cli, Options, StorageOptions},
-    event::format::{LogSource, LogSourceEntry},
+    cli::Options,
+    event::{
+        format::{inference, LogSource, LogSourceEntry},
+        DEFAULT_TIMESTAMP_KEY,
+    },
     handlers::{
         http::{
             cluster::{sync_streams_with_ingestors, INTERNAL_STREAM_NAME},
             ingest::PostError,
             logstream::error::{CreateStreamError, StreamError},
-            modal::{ingest_server::INGESTOR_META, utils::logstream_utils::PutStreamHeaders},
+            modal::{
+                ingest_server::INGESTOR_META,
+                utils::logstream_utils::{OnConflict, PutStreamHeaders},
+            },
+            templates,
         },
         STREAM_TYPE_KEY,
     },
-    metadata::{LogStreamMetadata, SchemaVersion},
+    metadata::{LogStreamMetadata, SchemaVersion, StreamSettings},
     option::Mode,
-    static_schema::{convert_static_schema_to_arrow_schema, StaticSchema},
+    static_schema::{
+        convert_static_schema_to_arrow_schema, parse_column_types, parse_partial_schema,
+        StaticSchema,
+    },
     storage::{
-        object_storage::parseable_json_path, ObjectStorageError, ObjectStorageProvider,
-        ObjectStoreFormat, Owner, Permisssion, StreamType,
+        object_storage::parseable_json_path, stream_data_root, ObjectStorage, ObjectStorageError,
+        ObjectStorageProvider, ObjectStoreFormat, Owner, Permisssion, StreamType,
+        STREAM_ROOT_DIRECTORY,
     },
+    utils::time::{set_naive_timestamp_timezone, set_timestamp_precision},
     validator,
 };
 
+mod shutdown;
 mod staging;
 mod streams;
 
@@ -79,6 +101,7 @@ pub const JOIN_COMMUNITY: &str =
 pub const STREAM_EXISTS: &str = "Stream exists";
 
 /// Shared state of the Parseable server.
+#[cfg(not(test))]
 pub static PARSEABLE: Lazy<Parseable> = Lazy::new(|| match Cli::parse().storage {
     StorageOptions::Local(args) => {
         if args.options.staging_dir() == &args.storage.root {
@@ -97,6 +120,14 @@ pub static PARSEABLE: Lazy<Parseable> = Lazy::new(|| match Cli::parse().storage
             .exit();
         }
 
+        if args.options.conditional_puts {
+            clap::Error::raw(
+                ErrorKind::ValueValidation,
+                "Cannot use conditional puts with local-store subcommand, files can't be written only if absent.",
+            )
+            .exit();
+        }
+
         Parseable::new(
             args.options,
             #[cfg(feature = "kafka")]
@@ -118,6 +149,22 @@ pub static PARSEABLE: Lazy<Parseable> = Lazy::new(|| match Cli::parse().storage
     ),
 });
 
+/// Shared state for unit tests, backed by local storage under a per-process temp dir.
+#[cfg(test)]
+pub static PARSEABLE: Lazy<Parseable> = Lazy::new(|| {
+    let dir = std::env::temp_dir().join(format!("parseable-test-{}", std::process::id()));
+    let mut args = crate::cli::LocalStoreArgs::parse_from(["parseable"]);
+    args.options.local_staging_path = dir.join("staging");
+    args.storage.root = dir.join("data");
+
+    Parseable::new(
+        args.options,
+        #[cfg(feature = "kafka")]
+        args.kafka,
+        Arc::new(args.storage),
+    )
+});
+
 /// All state related to parseable, in one place.
 pub struct Parseable {
     /// Configuration variables for parseable
@@ -127,6 +174,8 @@ pub struct Parseable {
     /// Metadata and staging realting to each logstreams
     /// A globally shared mapping of `Streams` that parseable is aware of.
     pub streams: Streams,
+    /// Events being processed, waited on before shutting down
+    pub ingest_gate: IngestGate,
     /// Used to configure the kafka connector
     #[cfg(feature = "kafka")]
     pub kafka_config: KafkaConfig,
@@ -138,10 +187,16 @@ impl Parseable {
         #[cfg(feature = "kafka")] kafka_config: KafkaConfig,
         storage: Arc<dyn ObjectStorageProvider>,
     ) -> Self {
+        set_timestamp_precision(options.timestamp_precision);
+        inference::register_builtin_strategies();
+        if let Some(timezone) = options.naive_timestamp_timezone {
+            set_naive_timestamp_timezone(timezone);
+        }
         Parseable {
             options: Arc::new(options),
             storage,
             streams: Streams::default(),
+            ingest_gate: IngestGate::default(),
             #[cfg(feature = "kafka")]
             kafka_config,
         }
@@ -156,6 +211,44 @@ impl Parseable {
             .cloned()
     }
 
+    /// Refreshes the schema of a stream in memory, in case the one in object store was updated
+    /// out of band. Storage is checked at most once a minute per stream, and on every call while
+    /// the stored schema is incompatible with the one in memory.
+    pub async fn reconcile_schema(&self, stream_name: &str) -> Result<(), SchemaDriftError> {
+        // query nodes fetch the schema from storage on every query
+        if matches!(self.options.mode, Mode::Query | Mode::Prism) {
+            return Ok(());
+        }
+        let Ok(stream) = self.get_stream(stream_name) else {
+            return Ok(());
+        };
+        if !stream.is_schema_sync_due() {
+            return Ok(());
+        }
+
+        let stored = match self
+            .storage
+            .get_object_store()
+            .get_schema(stream_name)
+            .await
+        {
+            Ok(schema) => schema,
+            Err(ObjectStorageError::NoSuchKey(_)) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let drifted = stream.reconcile_schema(&stored).inspect_err(|_| {
+            // compared again on the next call, so that every request reports the conflict
+            stream.reset_schema_sync();
+        })?;
+        if drifted {
+            warn!(
+                "Schema of stream {stream_name} in memory had drifted from storage, refreshed it"
+            );
+        }
+
+        Ok(())
+    }
+
     /// Get the handle to a stream in staging, create one if it doesn't exist
     pub fn get_or_create_stream(&self, stream_name: &str) -> StreamRef {
         if let Ok(staging) = self.get_stream(stream_name) {
@@ -227,6 +320,32 @@ impl Parseable {
         self.storage.clone()
     }
 
+    /// Object storage the data files of the stream are put in, the bucket of its own if it has one
+    pub fn data_store(&self, stream_name: &str) -> Arc<dyn ObjectStorage> {
+        let bucket = self
+            .get_stream(stream_name)
+            .ok()
+            .and_then(|stream| stream.get_settings().storage_bucket.clone());
+        self.storage.get_data_store(bucket.as_deref())
+    }
+
+    /// Deletes the data files the stream put outside of its directory in the default bucket,
+    /// under a storage prefix or in a bucket of its own, the others are deleted along with it
+    pub async fn delete_stream_data(&self, stream_name: &str) -> Result<(), ObjectStorageError> {
+        let settings = self.get_stream(stream_name)?.get_settings();
+        if settings.storage_bucket.is_none() && settings.storage_prefix.is_none() {
+            return Ok(());
+        }
+
+        self.storage
+            .get_data_store(settings.storage_bucket.as_deref())
+            .delete_prefix(&stream_data_root(
+                stream_name,
+                settings.storage_prefix.as_deref(),
+            ))
+            .await
+    }
+
     pub fn hot_tier_dir(&self) -> &Option<PathBuf> {
         &self.options.hot_tier_storage_path
     }
@@ -298,7 +417,7 @@ impl Parseable {
         let stream_type = stream_metadata.stream_type;
         let schema_version = stream_metadata.schema_version;
         let log_source = stream_metadata.log_source;
-        let metadata = LogStreamMetadata::new(
+        let mut metadata = LogStreamMetadata::new(
             created_at,
             time_partition,
             time_partition_limit,
@@ -309,6 +428,8 @@ impl Parseable {
             schema_version,
             log_source,
         );
+        inference::ensure_registered(stream_metadata.settings.schema_inference.as_deref())?;
+        metadata.settings = Arc::new(stream_metadata.settings);
         let ingestor_id = INGESTOR_META
             .get()
             .map(|ingestor_metadata| ingestor_metadata.get_node_id());
@@ -446,11 +567,14 @@ impl Parseable {
         let PutStreamHeaders {
             time_partition,
             time_partition_limit,
-            custom_partition,
-            static_schema_flag,
+            mut custom_partition,
+            mut static_schema_flag,
             update_stream_flag,
             stream_type,
-            log_source,
+            mut log_source,
+            column_types,
+            on_conflict,
+            template,
         } = headers.into();
 
         let stream_in_memory_dont_update =
@@ -458,12 +582,17 @@ impl Parseable {
         // check if stream in storage only if not in memory
         // for Parseable OSS, create_update_stream is called only from query node
         // for Parseable Enterprise, create_update_stream is called from prism node
+        // ingestors the conflict was resolved for by the querier resolve it again with the
+        // stored stream, so as to end up with the same schema
         let stream_in_storage_only_for_query_node = !self.streams.contains(stream_name)
-            && (self.options.mode == Mode::Query || self.options.mode == Mode::Prism)
+            && (self.options.mode == Mode::Query
+                || self.options.mode == Mode::Prism
+                || (self.options.mode == Mode::Ingest && on_conflict != OnConflict::Error))
             && self
                 .create_stream_and_schema_from_storage(stream_name)
                 .await?;
-        if stream_in_memory_dont_update || stream_in_storage_only_for_query_node {
+        let stream_exists = stream_in_memory_dont_update || stream_in_storage_only_for_query_node;
+        if stream_exists && on_conflict == OnConflict::Error {
             return Err(StreamError::Custom {
                  msg: format!(
                      "Logstream {stream_name} already exists, please create a new log stream with unique name"
@@ -473,6 +602,13 @@ impl Parseable {
         }
 
         if update_stream_flag {
+            if column_types.is_some() {
+                return Err(StreamError::Custom {
+                    msg: "Altering the column types of an existing stream is restricted."
+                        .to_string(),
+                    status: StatusCode::BAD_REQUEST,
+                });
+            }
             return self
                 .update_stream(
                     headers,
@@ -492,17 +628,70 @@ impl Parseable {
             });
         }
 
+        // streams created from a template take its schema and configuration
+        let template = match template {
+            Some(name) => match templates::load(&name).await? {
+                Some(template) => Some(template),
+                None => {
+                    return Err(StreamError::Custom {
+                        msg: format!("Template {name} doesn't exist"),
+                        status: StatusCode::BAD_REQUEST,
+                    })
+                }
+            },
+            None => None,
+        };
+        if let Some(template) = &template {
+            custom_partition = template.custom_partition.clone();
+            static_schema_flag = template.static_schema_flag;
+            log_source = template.log_source.clone();
+        }
+
         if let Some(custom_partition) = &custom_partition {
             validate_custom_partition(custom_partition)?;
         }
 
-        let schema = validate_static_schema(
-            body,
-            stream_name,
-            &time_partition,
-            custom_partition.as_ref(),
-            static_schema_flag,
-        )?;
+        let schema = match (&template, column_types) {
+            (Some(template), _) => template.schema(stream_name)?,
+            (None, Some(column_types)) => {
+                validate_column_types(DeclaredColumns::Header(&column_types), static_schema_flag)?
+            }
+            (None, None) if !static_schema_flag && !body.is_empty() => validate_column_types(
+                DeclaredColumns::Schema(serde_json::from_slice(body)?),
+                static_schema_flag,
+            )?,
+            (None, None) => validate_static_schema(
+                body,
+                stream_name,
+                &time_partition,
+                custom_partition.as_ref(),
+                static_schema_flag,
+            )?,
+        };
+
+        if stream_exists {
+            let stream = self.get_stream(stream_name)?;
+            let overwrite = resolve_conflict(
+                on_conflict,
+                stream_name,
+                &stream.get_schema(),
+                stream.get_static_schema_flag(),
+                stream.get_custom_partition().as_ref(),
+                &schema,
+                static_schema_flag,
+                custom_partition.as_ref(),
+            )?;
+            if let Some(schema) = overwrite {
+                self.storage
+                    .get_object_store()
+                    .put_schema(stream_name, &schema)
+                    .await?;
+                stream.set_schema(&schema);
+            }
+
+            return Ok(headers.clone());
+        }
+
         let log_source_entry = LogSourceEntry::new(log_source, HashSet::new());
         self.create_stream(
             stream_name.to_string(),
@@ -519,6 +708,21 @@ impl Parseable {
         Ok(headers.clone())
     }
 
+    /// Persists the settings of a stream and applies them to the stream in memory
+    pub async fn update_stream_settings(
+        &self,
+        stream_name: &str,
+        settings: StreamSettings,
+    ) -> Result<(), StreamError> {
+        self.storage
+            .get_object_store()
+            .put_stream_settings(stream_name, &settings)
+            .await?;
+        self.get_stream(stream_name)?.set_settings(settings);
+
+        Ok(())
+    }
+
     async fn update_stream(
         &self,
         headers: &HeaderMap,
@@ -576,7 +780,39 @@ impl Parseable {
         }
         // Proceed to create log stream if it doesn't exist
         let storage = self.storage.get_object_store();
+        if stream_type != StreamType::Internal {
+            // the stream would shadow the alias in queries
+            if let Some(existing) = self
+                .streams
+                .resolve(&stream_name)
+                .filter(|existing| *existing != stream_name)
+            {
+                return Err(CreateStreamError::Custom {
+                    msg: format!(
+                        "log stream {stream_name} can't be created as it is an alias of log stream {existing}"
+                    ),
+                    status: StatusCode::BAD_REQUEST,
+                });
+            }
+            self.ensure_not_storage_prefix(&*storage, &stream_name)
+                .await?;
+        }
 
+        // values of the columns a dynamic schema stream starts out with are converted into their type
+        let settings = StreamSettings {
+            declared_columns: if static_schema_flag {
+                vec![]
+            } else {
+                schema
+                    .fields()
+                    .iter()
+                    .map(|field| field.name().to_owned())
+                    .filter(|name| name != DEFAULT_TIMESTAMP_KEY)
+                    .collect()
+            },
+            partition_key_buckets: Some(self.options.partition_key_buckets),
+            ..Default::default()
+        };
         let meta = ObjectStoreFormat {
             created_at: Utc::now().to_rfc3339(),
             permissions: vec![Permisssion::new(PARSEABLE.options.username.clone())],
@@ -591,6 +827,7 @@ impl Parseable {
                 group: PARSEABLE.options.username.clone(),
             },
             log_source: log_source.clone(),
+            settings: settings.clone(),
             ..Default::default()
         };
 
@@ -609,7 +846,7 @@ impl Parseable {
                     static_schema.insert(field_name, field);
                 }
 
-                let metadata = LogStreamMetadata::new(
+                let mut metadata = LogStreamMetadata::new(
                     created_at,
                     time_partition.to_owned(),
                     time_partition_limit,
@@ -620,6 +857,7 @@ impl Parseable {
                     SchemaVersion::V1, // New stream
                     log_source,
                 );
+                metadata.settings = Arc::new(settings);
                 let ingestor_id = INGESTOR_META
                     .get()
                     .map(|ingestor_metadata| ingestor_metadata.get_node_id());
@@ -639,6 +877,51 @@ impl Parseable {
         Ok(())
     }
 
+    /// Errors if data files of other streams are put under the directory of the stream in the
+    /// default bucket, by a storage prefix starting with its name, as they would be deleted along
+    /// with the stream
+    async fn ensure_not_storage_prefix(
+        &self,
+        storage: &dyn ObjectStorage,
+        stream_name: &str,
+    ) -> Result<(), CreateStreamError> {
+        let prefixed_by = self.streams.list().into_iter().find(|name| {
+            self.get_stream(name).is_ok_and(|stream| {
+                let settings = stream.get_settings();
+                settings.storage_bucket.is_none()
+                    && settings
+                        .storage_prefix
+                        .as_deref()
+                        .is_some_and(|prefix| prefix.split('/').next() == Some(stream_name))
+            })
+        });
+        if let Some(name) = prefixed_by {
+            return Err(CreateStreamError::Custom {
+                msg: format!(
+                    "log stream {stream_name} can't be created as the storage prefix of log stream {name} starts with its name"
+                ),
+                status: StatusCode::BAD_REQUEST,
+            });
+        }
+
+        // streams not loaded on this node are told apart by the files they put there, the
+        // directory of a stream holds its metadata
+        let dirs = storage
+            .list_dirs_relative(RelativePath::new(stream_name))
+            .await
+            .unwrap_or_default();
+        if !dirs.is_empty() && !dirs.iter().any(|dir| dir == STREAM_ROOT_DIRECTORY) {
+            return Err(CreateStreamError::Custom {
+                msg: format!(
+                    "log stream {stream_name} can't be created as its directory in storage holds data files of other log streams"
+                ),
+                status: StatusCode::BAD_REQUEST,
+            });
+        }
+
+        Ok(())
+    }
+
     async fn validate_and_update_custom_partition(
         &self,
         stream_name: &str,
@@ -817,6 +1100,85 @@ impl Parseable {
     }
 }
 
+/// Decides how a request to create an already existing stream is handled as per `on_conflict`,
+/// returns the schema the stream should be updated with, if any.
+#[allow(clippy::too_many_arguments)]
+fn resolve_conflict(
+    on_conflict: OnConflict,
+    stream_name: &str,
+    existing_schema: &Schema,
+    existing_static_schema_flag: bool,
+    existing_custom_partition: Option<&String>,
+    schema: &Schema,
+    static_schema_flag: bool,
+    custom_partition: Option<&String>,
+) -> Result<Option<Arc<Schema>>, StreamError> {
+    let incompatible = |reason: String| StreamError::Custom {
+        msg: format!("Logstream {stream_name} already exists and {reason}"),
+        status: StatusCode::CONFLICT,
+    };
+
+    if existing_static_schema_flag != static_schema_flag {
+        return Err(incompatible(format!(
+            "has static schema flag set to {existing_static_schema_flag}"
+        )));
+    }
+    // existing data is partitioned as it was, so the partitions can't change either
+    let partitions = |custom_partition: Option<&String>| {
+        custom_partition
+            .map(|partitions| partitions.split(',').map(str::trim).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    if partitions(existing_custom_partition) != partitions(custom_partition) {
+        return Err(incompatible(format!(
+            "is partitioned by {}",
+            existing_custom_partition.map_or("no custom partition", |p| p.as_str())
+        )));
+    }
+    // existing data is stored with the existing types, so a column can't change its type
+    for field in schema.fields() {
+        if let Ok(existing) = existing_schema.field_with_name(field.name()) {
+            if existing.data_type() != field.data_type() {
+                return Err(incompatible(format!(
+                    "column {} is of type {}",
+                    field.name(),
+                    existing.data_type()
+                )));
+            }
+        }
+    }
+
+    match on_conflict {
+        OnConflict::Error => Err(incompatible(
+            "please create a new log stream with unique name".to_owned(),
+        )),
+        OnConflict::Ignore => {
+            if let Some(field) = schema
+                .fields()
+                .iter()
+                .find(|field| existing_schema.field_with_name(field.name()).is_err())
+            {
+                return Err(incompatible(format!("has no column {}", field.name())));
+            }
+            Ok(None)
+        }
+        // columns left out of the request are kept, the data stored with them stays readable
+        OnConflict::Overwrite => {
+            let added = schema
+                .fields()
+                .iter()
+                .filter(|field| existing_schema.field_with_name(field.name()).is_err())
+                .cloned()
+                .collect::<Vec<_>>();
+            if added.is_empty() {
+                return Ok(None);
+            }
+            let fields = existing_schema.fields().iter().cloned().chain(added);
+            Ok(Some(Arc::new(Schema::new(fields.collect::<Vec<_>>()))))
+        }
+    }
+}
+
 pub fn validate_static_schema(
     body: &Bytes,
     stream_name: &str,
@@ -848,6 +1210,37 @@ pub fn validate_static_schema(
     Ok(parsed_schema)
 }
 
+/// Columns declared for a dynamic schema stream on creation, the types of the rest are inferred as
+/// events are ingested
+pub enum DeclaredColumns<'a> {
+    /// The `X-P-Column-Types` header, e.g. `amount=decimal(38,10),id=int64`
+    Header(&'a str),
+    /// A partial schema in the request body, in the format of a static schema
+    Schema(StaticSchema),
+}
+
+/// Returns the schema containing the declared columns of a dynamic schema stream
+pub fn validate_column_types(
+    columns: DeclaredColumns<'_>,
+    static_schema_flag: bool,
+) -> Result<Arc<Schema>, CreateStreamError> {
+    if static_schema_flag {
+        return Err(CreateStreamError::Custom {
+            msg: "Column types can't be declared for a static schema logstream, declare them in the static schema instead".to_string(),
+            status: StatusCode::BAD_REQUEST,
+        });
+    }
+
+    match columns {
+        DeclaredColumns::Header(column_types) => parse_column_types(column_types),
+        DeclaredColumns::Schema(partial_schema) => parse_partial_schema(partial_schema),
+    }
+    .map_err(|err| CreateStreamError::Custom {
+        msg: err.to_string(),
+        status: StatusCode::BAD_REQUEST,
+    })
+}
+
 pub fn validate_time_partition_limit(
     time_partition_limit: &str,
 ) -> Result<NonZeroU32, CreateStreamError> {
@@ -878,3 +1271,301 @@ pub fn validate_custom_partition(custom_partition: &str) -> Result<(), CreateStr
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use arrow_schema::{DataType, Field, Schema};
+
+    use crate::{
+        handlers::http::modal::utils::logstream_utils::OnConflict, metadata::StreamSettings,
+        storage::StreamType,
+    };
+
+    use super::{resolve_conflict, SchemaDriftError, PARSEABLE};
+
+    fn existing() -> Schema {
+        Schema::new(vec![
+            Field::new("msg", DataType::Utf8, true),
+            Field::new("status", DataType::Int64, true),
+        ])
+    }
+
+    #[test]
+    fn error_on_conflict_rejects_existing_stream() {
+        let result = resolve_conflict(
+            OnConflict::Error,
+            "test",
+            &existing(),
+            true,
+            None,
+            &existing(),
+            true,
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn ignore_on_conflict_is_noop_when_compatible() {
+        let requested = Schema::new(vec![Field::new("msg", DataType::Utf8, true)]);
+        let result = resolve_conflict(
+            OnConflict::Ignore,
+            "test",
+            &existing(),
+            true,
+            None,
+            &requested,
+            true,
+            None,
+        );
+        assert!(result.unwrap().is_none());
+
+        let requested = Schema::new(vec![Field::new("level", DataType::Utf8, true)]);
+        let result = resolve_conflict(
+            OnConflict::Ignore,
+            "test",
+            &existing(),
+            true,
+            None,
+            &requested,
+            true,
+            None,
+        );
+        assert!(result.is_err());
+
+        // nor is the stream when partitioned otherwise
+        let result = resolve_conflict(
+            OnConflict::Ignore,
+            "test",
+            &existing(),
+            true,
+            Some(&"msg".to_owned()),
+            &existing(),
+            true,
+            Some(&"status".to_owned()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn overwrite_on_conflict_guards_existing_data() {
+        let requested = Schema::new(vec![
+            Field::new("msg", DataType::Utf8, true),
+            Field::new("level", DataType::Utf8, true),
+        ]);
+        let result = resolve_conflict(
+            OnConflict::Overwrite,
+            "test",
+            &existing(),
+            true,
+            None,
+            &requested,
+            true,
+            None,
+        );
+        // the columns left out of the request are kept
+        let updated = Schema::new(vec![
+            Field::new("msg", DataType::Utf8, true),
+            Field::new("status", DataType::Int64, true),
+            Field::new("level", DataType::Utf8, true),
+        ]);
+        assert_eq!(result.unwrap().unwrap().as_ref(), &updated);
+
+        let result = resolve_conflict(
+            OnConflict::Overwrite,
+            "test",
+            &existing(),
+            true,
+            None,
+            &Schema::empty(),
+            true,
+            None,
+        );
+        assert!(result.unwrap().is_none());
+
+        let requested = Schema::new(vec![Field::new("status", DataType::Utf8, true)]);
+        let result = resolve_conflict(
+            OnConflict::Overwrite,
+            "test",
+            &existing(),
+            true,
+            None,
+            &requested,
+            true,
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn incompatible_stored_schema_is_reported_on_every_call() {
+        let stream_name = "incompatible_stored_schema";
+        PARSEABLE
+            .get_or_create_stream(stream_name)
+            .set_schema(&existing());
+        let stored = Schema::new(vec![Field::new("status", DataType::Utf8, true)]);
+        PARSEABLE
+            .storage
+            .get_object_store()
+            .put_schema(stream_name, &stored)
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            assert!(matches!(
+                PARSEABLE.reconcile_schema(stream_name).await,
+                Err(SchemaDriftError::Incompatible(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn partial_schema_is_declared_with_the_stream() {
+        use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+        use bytes::Bytes;
+
+        let stream_name = "partial_schema_declared";
+        let body = Bytes::from(
+            serde_json::json!({
+                "fields": [
+                    {"name": "status", "data_type": "int64"},
+                    {"name": "amount", "data_type": "decimal(10,2)"}
+                ]
+            })
+            .to_string(),
+        );
+        // the custom partition is inferred along with the columns left out of the partial schema
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-p-custom-partition"),
+            HeaderValue::from_static("host"),
+        );
+        PARSEABLE
+            .create_update_stream(&headers, &body, stream_name)
+            .await
+            .unwrap();
+
+        let stream = PARSEABLE.get_stream(stream_name).unwrap();
+        assert_eq!(
+            stream
+                .get_schema()
+                .field_with_name("amount")
+                .unwrap()
+                .data_type(),
+            &DataType::Decimal128(10, 2)
+        );
+        assert_eq!(stream.get_settings().declared_columns, ["status", "amount"]);
+        let stored = PARSEABLE
+            .storage
+            .get_object_store()
+            .get_object_store_format(stream_name)
+            .await
+            .unwrap();
+        assert_eq!(stored.settings.declared_columns, ["status", "amount"]);
+        assert_eq!(
+            stored.settings.partition_key_buckets,
+            Some(PARSEABLE.options.partition_key_buckets)
+        );
+    }
+
+    #[tokio::test]
+    async fn existing_stream_is_created_again_as_per_on_conflict() {
+        use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+        use bytes::Bytes;
+
+        let stream_name = "on_conflict_modes";
+        let body = |fields: serde_json::Value| {
+            Bytes::from(serde_json::json!({ "fields": fields }).to_string())
+        };
+        let headers = |on_conflict: Option<&'static str>, custom_partition: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                HeaderName::from_static("x-p-custom-partition"),
+                HeaderValue::from_static(custom_partition),
+            );
+            if let Some(on_conflict) = on_conflict {
+                headers.insert(
+                    HeaderName::from_static("x-p-on-conflict"),
+                    HeaderValue::from_static(on_conflict),
+                );
+            }
+            headers
+        };
+        let status = body(serde_json::json!([{"name": "status", "data_type": "int64"}]));
+        PARSEABLE
+            .create_update_stream(&headers(None, "host"), &status, stream_name)
+            .await
+            .unwrap();
+        let stream = PARSEABLE.get_stream(stream_name).unwrap();
+        let created = stream.get_schema();
+
+        // rejected by default
+        assert!(PARSEABLE
+            .create_update_stream(&headers(None, "host"), &status, stream_name)
+            .await
+            .is_err());
+
+        // ignored when alike, including how it is partitioned
+        PARSEABLE
+            .create_update_stream(&headers(Some("ignore"), "host"), &status, stream_name)
+            .await
+            .unwrap();
+        assert_eq!(stream.get_schema(), created);
+        assert!(PARSEABLE
+            .create_update_stream(&headers(Some("ignore"), "region"), &status, stream_name)
+            .await
+            .is_err());
+
+        // overwritten without dropping the columns left out of the request
+        PARSEABLE
+            .create_update_stream(
+                &headers(Some("overwrite"), "host"),
+                &Bytes::new(),
+                stream_name,
+            )
+            .await
+            .unwrap();
+        assert_eq!(stream.get_schema(), created);
+        let level = body(serde_json::json!([{"name": "level", "data_type": "string"}]));
+        PARSEABLE
+            .create_update_stream(&headers(Some("overwrite"), "host"), &level, stream_name)
+            .await
+            .unwrap();
+        let stored = PARSEABLE
+            .storage
+            .get_object_store()
+            .get_schema(stream_name)
+            .await
+            .unwrap();
+        for schema in [stream.get_schema().as_ref(), &stored] {
+            assert!(schema.field_with_name("status").is_ok());
+            assert!(schema.field_with_name("level").is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_is_not_created_under_the_alias_of_another() {
+        use actix_web::http::header::HeaderMap;
+        use bytes::Bytes;
+
+        let stream = PARSEABLE.get_or_create_stream("aliased_stream");
+        stream.set_settings(StreamSettings {
+            aliases: vec!["alias_of_stream".to_owned()],
+            ..Default::default()
+        });
+
+        assert!(PARSEABLE
+            .create_update_stream(&HeaderMap::new(), &Bytes::new(), "alias_of_stream")
+            .await
+            .is_err());
+        assert!(PARSEABLE
+            .create_stream_if_not_exists("alias_of_stream", StreamType::UserDefined, vec![])
+            .await
+            .is_err());
+        assert!(!PARSEABLE.streams.contains("alias_of_stream"));
+        assert_eq!(
+            PARSEABLE.streams.resolve("alias_of_stream").as_deref(),
+            Some("aliased_stream")
+        );
+    }
+}
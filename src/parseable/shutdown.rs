@@ -0,0 +1,167 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use tokio::{sync::Notify, task::JoinSet};
+use tracing::{error, info, warn};
+
+use super::{StagingError, Streams};
+
+/// Tracks the events being processed, so that shutdown can wait on them to be staged
+/// before staging is flushed, instead of losing them midway.
+#[derive(Debug, Default)]
+pub struct IngestGate {
+    closed: AtomicBool,
+    in_flight: AtomicUsize,
+    drained: Notify,
+}
+
+/// Held for as long as an event is being processed
+pub struct InFlight<'a>(&'a IngestGate);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+impl IngestGate {
+    /// Marks an event as in flight, errors once the gate is closed for shutdown
+    pub fn enter(&self) -> Result<InFlight<'_>, StagingError> {
+        // counted before checking, so that `drain` either waits on the event or it is rejected
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self);
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(StagingError::ShuttingDown);
+        }
+
+        Ok(in_flight)
+    }
+
+    /// Stops accepting new events
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the events in flight to be processed, returns `false` if some still
+    /// are when the timeout runs out
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                // registered before checking, so that the last event leaving isn't missed
+                let drained = self.drained.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                drained.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+/// Closes the gate to new events, waits up to `timeout` for those in flight and then flushes every
+/// stream in staging and converts it into parquet, ready to be uploaded before exit.
+pub async fn drain_and_flush(gate: &IngestGate, streams: &Streams, timeout: Duration) {
+    gate.close();
+    if !gate.drain(timeout).await {
+        warn!(
+            "{} events were still being processed after {timeout:?}, flushing staging regardless",
+            gate.in_flight()
+        );
+    }
+
+    let mut joinset = JoinSet::new();
+    streams.flush_and_convert(&mut joinset, true);
+    while let Some(res) = joinset.join_next().await {
+        match res {
+            Ok(Ok(_)) => info!("Successfully converted arrow files to parquet."),
+            Ok(Err(err)) => warn!("Failed to convert arrow files to parquet. {err:?}"),
+            Err(err) => error!("Failed to join async task: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use arrow_array::{Int32Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+    use chrono::Utc;
+    use temp_dir::TempDir;
+
+    use crate::{cli::Options, metadata::LogStreamMetadata, storage::StreamType};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_waits_for_events_in_flight_and_flushes_them() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        let streams = Streams::default();
+        let stream = streams.get_or_create(
+            options,
+            "app".to_owned(),
+            LogStreamMetadata::default(),
+            None,
+        );
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, true)]));
+        let rb =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1, 2, 3]))]).unwrap();
+        let gate = IngestGate::default();
+
+        let event = async {
+            let _in_flight = gate.enter().unwrap();
+            // still being processed when shutdown starts
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            stream
+                .push(
+                    "abc",
+                    &rb,
+                    Utc::now().naive_utc(),
+                    &HashMap::new(),
+                    StreamType::UserDefined,
+                )
+                .unwrap();
+        };
+        tokio::join!(
+            event,
+            drain_and_flush(&gate, &streams, Duration::from_secs(10))
+        );
+
+        // the buffered event made it into parquet, nothing is left behind in arrows
+        assert_eq!(stream.parquet_files().len(), 1);
+        assert!(stream.arrow_files().is_empty());
+        assert!(matches!(gate.enter(), Err(StagingError::ShuttingDown)));
+    }
+}
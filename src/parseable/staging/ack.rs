@@ -0,0 +1,198 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use tokio::sync::watch;
+
+use crate::option::AckMode;
+
+use super::StagingError;
+
+/// Tracks how far record batches pushed into a stream have made it towards the object store,
+/// batches are identified by the sequence number they were pushed with, starting from 1.
+#[derive(Debug)]
+pub struct AckTracker {
+    /// Batches upto this sequence number have been converted into parquet
+    converted: AtomicU64,
+    /// Outcome of uploading the converted batches
+    uploads: watch::Sender<Uploads>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Uploads {
+    /// Batches upto this sequence number have been uploaded to object store
+    persisted: u64,
+    /// Batches upto this sequence number may be in parquet files that failed to upload
+    lost: u64,
+}
+
+impl Default for AckTracker {
+    fn default() -> Self {
+        Self {
+            converted: AtomicU64::new(0),
+            uploads: watch::Sender::new(Uploads::default()),
+        }
+    }
+}
+
+impl AckTracker {
+    pub fn converted(&self) -> u64 {
+        self.converted.load(Ordering::Acquire)
+    }
+
+    pub fn mark_converted(&self, seq: u64) {
+        self.converted.fetch_max(seq, Ordering::AcqRel);
+    }
+
+    pub fn persisted(&self) -> u64 {
+        self.uploads.borrow().persisted
+    }
+
+    /// Marks batches upto `seq` as uploaded, waking up ingestion waiting on them
+    pub fn mark_persisted(&self, seq: u64) {
+        self.uploads.send_if_modified(|uploads| {
+            if seq <= uploads.persisted {
+                return false;
+            }
+            uploads.persisted = seq;
+            true
+        });
+    }
+
+    /// Marks batches upto `seq` as lost, as a parquet file they were converted into was given
+    /// up on uploading, failing ingestion waiting on them
+    pub fn mark_lost(&self, seq: u64) {
+        self.uploads.send_if_modified(|uploads| {
+            if seq <= uploads.lost {
+                return false;
+            }
+            uploads.lost = seq;
+            true
+        });
+    }
+
+    /// Returns once the batch with sequence number `seq` can be acknowledged as per `mode`,
+    /// errors if it isn't uploaded within `timeout`
+    pub async fn wait(
+        &self,
+        mode: AckMode,
+        seq: u64,
+        timeout: Duration,
+    ) -> Result<(), StagingError> {
+        if mode == AckMode::Async {
+            return Ok(());
+        }
+
+        let mut uploads = self.uploads.subscribe();
+        let lost = tokio::time::timeout(
+            timeout,
+            uploads.wait_for(|uploads| uploads.persisted >= seq || uploads.lost >= seq),
+        )
+        .await
+        .map_err(|_| StagingError::AckTimeout(timeout))?
+        // sender is owned by self, hence can't be dropped while waiting
+        .is_ok_and(|uploads| uploads.lost >= seq);
+        if lost {
+            return Err(StagingError::UploadFailed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::time::{sleep, timeout};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn async_ack_returns_before_flush() {
+        let tracker = AckTracker::default();
+
+        timeout(
+            Duration::from_millis(100),
+            tracker.wait(AckMode::Async, 1, Duration::ZERO),
+        )
+        .await
+        .expect("async mode doesn't wait for the upload")
+        .unwrap();
+        assert_eq!(tracker.persisted(), 0);
+    }
+
+    #[tokio::test]
+    async fn sync_ack_returns_after_flush() {
+        let tracker = Arc::new(AckTracker::default());
+        let waiter = tokio::spawn({
+            let tracker = tracker.clone();
+            async move { tracker.wait(AckMode::Sync, 2, Duration::from_secs(5)).await }
+        });
+
+        // conversion alone, or uploading older batches isn't enough
+        tracker.mark_converted(2);
+        tracker.mark_persisted(1);
+        sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        tracker.mark_persisted(tracker.converted());
+        timeout(Duration::from_millis(100), waiter)
+            .await
+            .expect("sync mode returns once uploaded")
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sync_ack_errors_when_upload_stalls() {
+        let tracker = AckTracker::default();
+        tracker.mark_converted(1);
+
+        let result = timeout(
+            Duration::from_secs(1),
+            tracker.wait(AckMode::Sync, 1, Duration::from_millis(50)),
+        )
+        .await
+        .expect("waiting is bounded by the ack timeout");
+        assert!(matches!(result, Err(StagingError::AckTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn sync_ack_errors_when_upload_is_given_up() {
+        let tracker = Arc::new(AckTracker::default());
+        let waiter = tokio::spawn({
+            let tracker = tracker.clone();
+            async move { tracker.wait(AckMode::Sync, 1, Duration::from_secs(5)).await }
+        });
+
+        tracker.mark_converted(1);
+        tracker.mark_lost(1);
+        // files converted later being uploaded doesn't acknowledge the lost batch
+        tracker.mark_persisted(2);
+        let result = timeout(Duration::from_millis(100), waiter)
+            .await
+            .expect("sync mode returns once the upload is given up")
+            .unwrap();
+        assert!(matches!(result, Err(StagingError::UploadFailed)));
+    }
+}
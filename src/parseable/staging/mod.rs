@@ -17,7 +17,9 @@
  *
  */
 
+pub mod ack;
 pub mod reader;
+pub mod retry;
 pub mod writer;
 
 #[derive(Debug, thiserror::Error)]
@@ -30,6 +32,14 @@ pub enum StagingError {
     ObjectStorage(#[from] std::io::Error),
     #[error("Could not generate parquet file")]
     Create,
+    #[error("Stream {0} is frozen and doesn't accept new events")]
+    StreamFrozen(String),
+    #[error("Server is shutting down and doesn't accept new events")]
+    ShuttingDown,
+    #[error("Events were staged but not uploaded to object store within {0:?}")]
+    AckTimeout(std::time::Duration),
+    #[error("Events were staged but could not be uploaded to object store")]
+    UploadFailed,
     // #[error("Metadata Error: {0}")]
     // Metadata(#[from] MetadataError),
 }
@@ -25,9 +25,10 @@ use std::{
     vec::IntoIter,
 };
 
-use arrow_array::{RecordBatch, TimestampMillisecondArray};
+use arrow::compute::cast;
+use arrow_array::{ArrayRef, RecordBatch, TimestampMillisecondArray};
 use arrow_ipc::{reader::StreamReader, root_as_message_unchecked, MessageHeader};
-use arrow_schema::Schema;
+use arrow_schema::{DataType, Schema, TimeUnit};
 use byteorder::{LittleEndian, ReadBytesExt};
 use itertools::kmerge_by;
 use tracing::{error, warn};
@@ -135,11 +136,7 @@ fn get_timestamp_millis(batch: &RecordBatch, time_partition: Option<String>) ->
         Some(time_partition) => {
             let time_partition = time_partition.as_str();
             match batch.column_by_name(time_partition) {
-                Some(column) => column
-                    .as_any()
-                    .downcast_ref::<TimestampMillisecondArray>()
-                    .unwrap()
-                    .value(0),
+                Some(column) => first_timestamp_millis(column).unwrap(),
                 None => get_default_timestamp_millis(batch),
             }
         }
@@ -147,22 +144,27 @@ fn get_timestamp_millis(batch: &RecordBatch, time_partition: Option<String>) ->
     }
 }
 fn get_default_timestamp_millis(batch: &RecordBatch) -> i64 {
-    match batch
-        .column(0)
-        .as_any()
-        .downcast_ref::<TimestampMillisecondArray>()
-    {
+    match first_timestamp_millis(batch.column(0)) {
         // Ideally we expect the first column to be a timestamp (because we add the timestamp column first in the writer)
-        Some(array) => array.value(0),
+        Some(millis) => millis,
         // In case the first column is not a timestamp, we fallback to look for default timestamp column across all columns
-        None => batch
-            .column_by_name(DEFAULT_TIMESTAMP_KEY)
-            .unwrap()
-            .as_any()
-            .downcast_ref::<TimestampMillisecondArray>()
-            .unwrap()
-            .value(0),
+        None => {
+            first_timestamp_millis(batch.column_by_name(DEFAULT_TIMESTAMP_KEY).unwrap()).unwrap()
+        }
+    }
+}
+
+/// First value of a timestamp column of any precision, in milliseconds
+fn first_timestamp_millis(column: &ArrayRef) -> Option<i64> {
+    if !matches!(column.data_type(), DataType::Timestamp(_, _)) {
+        return None;
     }
+    let column = cast(column, &DataType::Timestamp(TimeUnit::Millisecond, None)).ok()?;
+
+    column
+        .as_any()
+        .downcast_ref::<TimestampMillisecondArray>()
+        .map(|array| array.value(0))
 }
 
 /// OffsetReader takes in a reader and list of offset and sizes and
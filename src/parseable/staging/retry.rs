@@ -0,0 +1,183 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Name of the directory within the staging directory of a stream, where files that couldn't
+/// be uploaded even after retries are moved to, for manual recovery.
+pub const DEAD_LETTER_DIR: &str = "dead-letter";
+
+#[derive(Debug, Clone, Copy)]
+struct Failure {
+    attempts: u32,
+    retry_at: Instant,
+}
+
+/// Tracks staged files that failed to upload to object store, so that they are retried with
+/// exponential backoff instead of on every sync, until they run out of retries.
+#[derive(Debug, Default)]
+pub struct UploadRetries {
+    failures: Mutex<HashMap<PathBuf, Failure>>,
+}
+
+/// What is to be done with a file once its upload has failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Stays in staging and is retried once the backoff has passed
+    RetryAt(Instant),
+    /// Has failed more than the allowed number of retries
+    DeadLetter,
+}
+
+impl UploadRetries {
+    /// Returns `true` unless the file failed to upload and its backoff hasn't passed yet
+    pub fn is_due(&self, path: &Path, now: Instant) -> bool {
+        self.failures
+            .lock()
+            .unwrap()
+            .get(path)
+            .is_none_or(|failure| failure.retry_at <= now)
+    }
+
+    /// Records a failed upload, backoff doubles with every failed attempt
+    pub fn failed(
+        &self,
+        path: &Path,
+        now: Instant,
+        max_retries: u32,
+        backoff: Duration,
+    ) -> RetryDecision {
+        let mut failures = self.failures.lock().unwrap();
+        let failure = failures.entry(path.to_path_buf()).or_insert(Failure {
+            attempts: 0,
+            retry_at: now,
+        });
+        failure.attempts += 1;
+        if failure.attempts > max_retries {
+            failures.remove(path);
+            return RetryDecision::DeadLetter;
+        }
+
+        failure.retry_at = now + backoff.saturating_mul(1u32 << (failure.attempts - 1).min(16));
+        RetryDecision::RetryAt(failure.retry_at)
+    }
+
+    pub fn succeeded(&self, path: &Path) {
+        self.failures.lock().unwrap().remove(path);
+    }
+}
+
+/// Moves a staged file into `DEAD_LETTER_DIR` next to it, returns its new path
+pub fn dead_letter(path: &Path) -> io::Result<PathBuf> {
+    let dir = path
+        .parent()
+        .expect("Staged file is in a directory")
+        .join(DEAD_LETTER_DIR);
+    fs::create_dir_all(&dir)?;
+    let dead_letter_path = dir.join(path.file_name().expect("Staged file has a name"));
+    fs::rename(path, &dead_letter_path)?;
+
+    Ok(dead_letter_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use temp_dir::TempDir;
+
+    use super::*;
+
+    /// Uploads by copying the file into `remote`, fails if `fail` is set
+    fn upload(path: &Path, remote: &Path, fail: bool) -> io::Result<()> {
+        if fail {
+            return Err(io::Error::other("object store unavailable"));
+        }
+        fs::copy(path, remote.join(path.file_name().unwrap()))?;
+        fs::remove_file(path)
+    }
+
+    #[test]
+    fn failed_upload_is_retried_without_data_loss() {
+        let staging = TempDir::new().unwrap();
+        let remote = TempDir::new().unwrap();
+        let path = staging.path().join("a.parquet");
+        fs::write(&path, b"records").unwrap();
+
+        let retries = UploadRetries::default();
+        let backoff = Duration::from_secs(10);
+        let now = Instant::now();
+
+        assert!(retries.is_due(&path, now));
+        assert!(upload(&path, remote.path(), true).is_err());
+        assert_eq!(
+            retries.failed(&path, now, 3, backoff),
+            RetryDecision::RetryAt(now + backoff)
+        );
+        // the file is kept in staging, but isn't retried before the backoff passes
+        assert_eq!(fs::read(&path).unwrap(), b"records");
+        assert!(!retries.is_due(&path, now + Duration::from_secs(5)));
+
+        let now = now + backoff;
+        assert!(retries.is_due(&path, now));
+        upload(&path, remote.path(), false).unwrap();
+        retries.succeeded(&path);
+
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read(remote.path().join("a.parquet")).unwrap(),
+            b"records"
+        );
+    }
+
+    #[test]
+    fn upload_is_dead_lettered_after_max_retries() {
+        let staging = TempDir::new().unwrap();
+        let path = staging.path().join("a.parquet");
+        fs::write(&path, b"records").unwrap();
+
+        let retries = UploadRetries::default();
+        let backoff = Duration::from_secs(10);
+        let now = Instant::now();
+        assert_eq!(
+            retries.failed(&path, now, 2, backoff),
+            RetryDecision::RetryAt(now + backoff)
+        );
+        assert_eq!(
+            retries.failed(&path, now, 2, backoff),
+            RetryDecision::RetryAt(now + backoff * 2)
+        );
+        assert_eq!(
+            retries.failed(&path, now, 2, backoff),
+            RetryDecision::DeadLetter
+        );
+
+        let moved = dead_letter(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            moved,
+            staging.path().join(DEAD_LETTER_DIR).join("a.parquet")
+        );
+        assert_eq!(fs::read(moved).unwrap(), b"records");
+        assert!(retries.is_due(&path, now));
+    }
+}
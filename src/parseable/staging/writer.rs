@@ -22,7 +22,8 @@ use std::{
     fs::{File, OpenOptions},
     io::BufWriter,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
 };
 
 use arrow_array::RecordBatch;
@@ -31,11 +32,13 @@ use arrow_schema::Schema;
 use arrow_select::concat::concat_batches;
 use chrono::Utc;
 use itertools::Itertools;
-use tracing::{error, warn};
+use rand::distributions::{Alphanumeric, DistString};
+use tracing::error;
 
 use crate::{
     parseable::{ARROW_FILE_EXTENSION, PART_FILE_EXTENSION},
     utils::{arrow::adapt_batch, time::TimeRange},
+    LOCK_EXPECT,
 };
 
 use super::StagingError;
@@ -44,12 +47,39 @@ use super::StagingError;
 pub struct Writer {
     pub mem: MemWriter<16384>,
     pub disk: HashMap<String, DiskWriter>,
+    /// Sequence number of the last record batch pushed
+    pub seq: u64,
+    /// Number of records pushed since the last flush
+    pub pending_rows: usize,
+    /// In-memory size of records pushed since the last flush
+    pub pending_bytes: u64,
+    /// When the first record since the last flush was pushed
+    pub first_pending: Option<Instant>,
+}
+
+impl Writer {
+    /// Closes the least recently written disk writers until fewer than `limit` are open
+    pub fn make_room(&mut self, limit: usize) {
+        while self.disk.len() >= limit.max(1) {
+            let Some(filename) = self
+                .disk
+                .iter()
+                .min_by_key(|(_, w)| w.last_write)
+                .map(|(filename, _)| filename.clone())
+            else {
+                break;
+            };
+            self.disk.remove(&filename);
+        }
+    }
 }
 
 pub struct DiskWriter {
     inner: StreamWriter<BufWriter<File>>,
     path: PathBuf,
     range: TimeRange,
+    first_seq: u64,
+    last_write: Instant,
 }
 
 impl DiskWriter {
@@ -68,15 +98,37 @@ impl DiskWriter {
             .open(&path)?;
         let inner = StreamWriter::try_new_buffered(file, schema)?;
 
-        Ok(Self { inner, path, range })
+        Ok(Self {
+            inner,
+            path,
+            range,
+            first_seq: 0,
+            last_write: Instant::now(),
+        })
+    }
+
+    /// Records the sequence number of the first record batch written into file
+    pub fn starting_at(mut self, seq: u64) -> Self {
+        self.first_seq = seq;
+        self
+    }
+
+    pub fn first_seq(&self) -> u64 {
+        self.first_seq
     }
 
     pub fn is_current(&self) -> bool {
         self.range.contains(Utc::now())
     }
 
+    /// Returns `true` if nothing was written into file for at least `timeout`
+    pub fn is_idle(&self, timeout: Duration, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_write) >= timeout
+    }
+
     /// Write a single recordbatch into file
     pub fn write(&mut self, rb: &RecordBatch) -> Result<(), StagingError> {
+        self.last_write = Instant::now();
         self.inner.write(rb).map_err(StagingError::Arrow)
     }
 }
@@ -92,8 +144,17 @@ impl Drop for DiskWriter {
         let mut arrow_path = self.path.to_owned();
         arrow_path.set_extension(ARROW_FILE_EXTENSION);
 
+        // files closed before their minute is over, once idle or to make room for others, are
+        // opened again under the same name, the schema key is suffixed so they end up in the same parquet
         if arrow_path.exists() {
-            warn!("File {arrow_path:?} exists and will be overwritten");
+            if let Some((schema_key, rest)) = arrow_path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split_once('.'))
+            {
+                let suffix = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+                arrow_path = arrow_path.with_file_name(format!("{schema_key}-{suffix}.{rest}"));
+            }
         }
 
         if let Err(err) = std::fs::rename(&self.path, &arrow_path) {
@@ -102,10 +163,69 @@ impl Drop for DiskWriter {
     }
 }
 
+/// Parquet files being written by the conversions of all streams, bounded by
+/// [`WriterLimit::acquire`] so that many streams converting at once don't run out of file handles
+pub static OPEN_PARQUET_WRITERS: WriterLimit = WriterLimit::new();
+
+/// Counts writers that are open, writers past the limit wait for one to be closed
+#[derive(Debug)]
+pub struct WriterLimit {
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+impl WriterLimit {
+    pub const fn new() -> Self {
+        Self {
+            open: Mutex::new(0),
+            closed: Condvar::new(),
+        }
+    }
+
+    /// Blocks the thread until fewer than `limit` writers are open, the writer counts as open until the
+    /// returned permit is dropped. Conversions are run on blocking threads of the runtime for this reason
+    pub fn acquire(&self, limit: usize) -> WriterPermit<'_> {
+        let mut open = self
+            .closed
+            .wait_while(self.open.lock().expect(LOCK_EXPECT), |open| {
+                *open >= limit.max(1)
+            })
+            .expect(LOCK_EXPECT);
+        *open += 1;
+
+        WriterPermit { limit: self }
+    }
+
+    /// Number of writers currently open
+    pub fn open(&self) -> usize {
+        *self.open.lock().expect(LOCK_EXPECT)
+    }
+}
+
+impl Default for WriterLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An open writer of a [`WriterLimit`], closed once dropped
+pub struct WriterPermit<'a> {
+    limit: &'a WriterLimit,
+}
+
+impl Drop for WriterPermit<'_> {
+    fn drop(&mut self) {
+        *self.limit.open.lock().expect(LOCK_EXPECT) -= 1;
+        self.limit.closed.notify_one();
+    }
+}
+
 /// Structure to keep recordbatches in memory.
 ///
 /// Any new schema is updated in the schema map.
 /// Recordbatches are pushed to mutable buffer first and then concated together and pushed to read buffer
+///
+/// Records are only ever appended, so they are read back in the order they were ingested in.
 #[derive(Debug)]
 pub struct MemWriter<const N: usize> {
     schema: Schema,
@@ -127,6 +247,11 @@ impl<const N: usize> Default for MemWriter<N> {
 }
 
 impl<const N: usize> MemWriter<N> {
+    /// Merged schema of the records held in memory
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
     pub fn push(&mut self, schema_key: &str, rb: &RecordBatch) {
         if !self.schema_map.contains(schema_key) {
             self.schema_map.insert(schema_key.to_owned());
@@ -146,6 +271,7 @@ impl<const N: usize> MemWriter<N> {
         self.mutable_buffer.inner.clear();
     }
 
+    /// Returns the records held in memory, oldest first, i.e. in the order they were ingested
     pub fn recordbatch_cloned(&self, schema: &Arc<Schema>) -> Vec<RecordBatch> {
         let mut read_buffer = self.read_buffer.clone();
         if !self.mutable_buffer.inner.is_empty() {
@@ -198,3 +324,39 @@ impl<const N: usize> MutableBuffer<N> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::MemWriter;
+
+    #[test]
+    fn records_are_read_in_ingestion_order() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let mut writer = MemWriter::<4>::default();
+        // enough batches to spill over from the mutable buffer into the read buffer
+        for ids in [vec![0, 1], vec![2], vec![3, 4, 5], vec![6]] {
+            let rb = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(ids))])
+                .unwrap();
+            writer.push("abc", &rb);
+        }
+
+        let ids: Vec<i32> = writer
+            .recordbatch_cloned(&schema)
+            .iter()
+            .flat_map(|rb| {
+                rb.column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(ids, (0..7).collect::<Vec<_>>());
+    }
+}
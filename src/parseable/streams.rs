@@ -20,21 +20,27 @@
 use std::{
     collections::{HashMap, HashSet},
     fs::{remove_file, write, File, OpenOptions},
+    io::Write,
     num::NonZeroU32,
     path::{Path, PathBuf},
     process,
     sync::{Arc, Mutex, RwLock},
-    time::{Instant, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use arrow_array::RecordBatch;
-use arrow_schema::{Field, Fields, Schema};
-use chrono::{NaiveDateTime, Timelike, Utc};
+use arrow::compute::take_record_batch;
+use arrow_array::{RecordBatch, UInt32Array, UInt64Array};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema};
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
+use datafusion::prelude::SessionContext;
 use derive_more::{Deref, DerefMut};
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
 use parquet::{
     arrow::ArrowWriter,
     basic::Encoding,
+    errors::ParquetError,
     file::{properties::WriterProperties, FOOTER_SIZE},
     format::SortingColumn,
     schema::types::ColumnPath,
@@ -47,21 +53,24 @@ use tracing::{error, info, trace, warn};
 use crate::{
     cli::Options,
     event::{
+        dedup::Deduplicator,
         format::{LogSource, LogSourceEntry},
-        DEFAULT_TIMESTAMP_KEY,
+        DEFAULT_TIMESTAMP_KEY, SEQUENCE_KEY,
     },
-    metadata::{LogStreamMetadata, SchemaVersion},
+    metadata::{LogStreamMetadata, SchemaVersion, StreamSettings},
     metrics,
     option::Mode,
-    storage::{object_storage::to_bytes, retention::Retention, StreamType},
-    utils::time::{Minute, TimeRange},
+    storage::{object_storage::to_bytes, retention::Retention, ObjectStorageError, StreamType},
+    utils::time::{to_partition_time, Minute, TimeRange, PARTITION_ZONE_KEY},
     LOCK_EXPECT, OBJECT_STORE_DATA_GRANULARITY,
 };
 
 use super::{
     staging::{
+        ack::AckTracker,
         reader::{MergedRecordReader, MergedReverseRecordReader},
-        writer::{DiskWriter, Writer},
+        retry::UploadRetries,
+        writer::{DiskWriter, Writer, OPEN_PARQUET_WRITERS},
         StagingError,
     },
     LogStream, ARROW_FILE_EXTENSION,
@@ -83,8 +92,22 @@ fn arrow_path_to_parquet(path: &Path, random_string: &str) -> Option<PathBuf> {
 #[error("Stream not found: {0}")]
 pub struct StreamNotFound(pub String);
 
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaDriftError {
+    #[error("Couldn't fetch schema from storage: {0}")]
+    Storage(#[from] ObjectStorageError),
+    #[error("Schema in storage is incompatible with the schema in memory: {0}")]
+    Incompatible(#[from] ArrowError),
+}
+
 pub type StreamRef = Arc<Stream>;
 
+/// File in the staging directory of a stream holding the sequence number of its next event
+const SEQUENCE_FILE_NAME: &str = ".sequence";
+
+/// Minimum time between two comparisons of a stream's schema in memory against storage
+const SCHEMA_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Gets the unix timestamp for the minute as described by the `SystemTime`
 fn minute_from_system_time(time: SystemTime) -> u128 {
     time.duration_since(UNIX_EPOCH)
@@ -93,6 +116,138 @@ fn minute_from_system_time(time: SystemTime) -> u128 {
         / 60000
 }
 
+/// Path of the `part`th file that records meant for `parquet_path` are rolled over into, the
+/// first one keeps the path and the ones after are e.g. `host.data.abc.1.parquet`
+fn rolled_over_path(parquet_path: &Path, part: usize) -> PathBuf {
+    if part == 0 {
+        return parquet_path.to_owned();
+    }
+
+    parquet_path.with_extension(format!("{part}.parquet"))
+}
+
+/// Writes `record` to the parquet writer `chunk_rows` rows at a time, flushing the row group in
+/// progress early whenever its encoded rows buffered in memory reach `max_buffer_size`, so that
+/// the memory used by the writer stays bounded however large the records are.
+fn write_buffered<W: Write + Send>(
+    writer: &mut ArrowWriter<W>,
+    record: &RecordBatch,
+    chunk_rows: usize,
+    max_buffer_size: Option<u64>,
+) -> Result<(), ParquetError> {
+    let chunk_rows = chunk_rows.max(1);
+    let mut offset = 0;
+    while offset < record.num_rows() {
+        let len = chunk_rows.min(record.num_rows() - offset);
+        writer.write(&record.slice(offset, len))?;
+        if max_buffer_size.is_some_and(|size| writer.in_progress_size() as u64 >= size) {
+            writer.flush()?;
+        }
+        offset += len;
+    }
+
+    Ok(())
+}
+
+/// Writes `records` as a parquet file into `out` until they run out, or the file reaches
+/// `target_size`, in which case the rest of the records are left for the next file
+fn write_parquet<W: Write + Send>(
+    out: W,
+    schema: Arc<Schema>,
+    props: WriterProperties,
+    records: &mut impl Iterator<Item = RecordBatch>,
+    target_size: Option<u64>,
+    options: &Options,
+) -> Result<(), StagingError> {
+    let mut writer = ArrowWriter::try_new(out, schema, Some(props))?;
+    for ref record in records {
+        write_buffered(
+            &mut writer,
+            record,
+            options.parquet_write_batch_size,
+            options.parquet_max_buffer_size,
+        )?;
+        if target_size
+            .is_some_and(|size| (writer.bytes_written() + writer.in_progress_size()) as u64 >= size)
+        {
+            break;
+        }
+    }
+    writer.close()?;
+
+    Ok(())
+}
+
+/// Writes the parquet file at `parquet_path` by way of a part file, which is renamed into place
+/// only once `write` has completely written it, so that a failure midway, e.g. when the disk fills
+/// up while flushing the footer, never leaves a truncated parquet file behind. The part file is
+/// removed if writing fails, returns `false` if the parquet file couldn't be put in place.
+fn write_parquet_atomically(
+    parquet_path: &Path,
+    write: impl FnOnce(&mut File) -> Result<(), StagingError>,
+) -> Result<bool, StagingError> {
+    let part_path = parquet_path.with_extension("part");
+    // a part file left behind by a crash is overwritten, never appended to
+    let mut part_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&part_path)
+        .map_err(|_| StagingError::Create)?;
+
+    if let Err(err) = write(&mut part_file).and_then(|_| Ok(part_file.sync_all()?)) {
+        error!("Failed to write parquet file {part_path:?}, removing it: {err}");
+        drop(part_file);
+        if let Err(e) = remove_file(&part_path) {
+            warn!("Failed to remove part file {part_path:?}: {e}");
+        }
+        return Err(err);
+    }
+
+    if part_file.metadata()?.len() < FOOTER_SIZE as u64 {
+        error!("Invalid parquet file {part_path:?} detected, removing it");
+        remove_file(part_path)?;
+        return Ok(false);
+    }
+
+    if let Err(e) = std::fs::rename(&part_path, parquet_path) {
+        error!("Couldn't rename part file: {part_path:?} -> {parquet_path:?}, error = {e}");
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// Picks upto `n` rows from the end of `records`, which are in the order of ingestion,
+/// and returns them latest first
+fn latest_rows(records: &[RecordBatch], n: usize) -> Vec<RecordBatch> {
+    let mut remaining = n;
+    let mut latest = vec![];
+    for rb in records.iter().rev() {
+        if remaining == 0 {
+            break;
+        }
+        let rows = rb.num_rows();
+        let take = remaining.min(rows);
+        let indices = UInt32Array::from_iter_values((rows - take..rows).rev().map(|i| i as u32));
+        latest.push(take_record_batch(rb, &indices).expect("indices are within the batch"));
+        remaining -= take;
+    }
+
+    latest
+}
+
+/// Records of a stream held in memory, awaiting flush to disk
+#[derive(Debug)]
+pub struct BufferedRecords {
+    pub schema: Schema,
+    pub rows: usize,
+    pub pending_rows: usize,
+    pub pending_bytes: u64,
+    /// Upto the requested number of the latest rows, latest first
+    pub sample: Vec<RecordBatch>,
+}
+
 /// All state associated with a single logstream in Parseable.
 pub struct Stream {
     pub stream_name: String,
@@ -100,6 +255,22 @@ pub struct Stream {
     pub data_path: PathBuf,
     pub options: Arc<Options>,
     pub writer: Mutex<Writer>,
+    pub acks: AckTracker,
+    pub upload_retries: UploadRetries,
+    pub schema_synced_at: Mutex<Option<Instant>>,
+    /// Sequence number the next event of the stream is assigned, set on first use
+    pub next_sequence: Mutex<Option<u64>>,
+    /// Content hashes of the events ingested within the dedup window
+    pub dedup: Mutex<Deduplicator>,
+    /// Time of the latest event ingested into the stream since the server started
+    pub latest_event_at: Mutex<Option<NaiveDateTime>>,
+    /// Parquet files built in memory by their filename, when the local cache is disabled
+    pub pending_uploads: Mutex<Vec<(String, Bytes)>>,
+    /// Held while the arrows of the stream are flushed and converted, so that conversions started
+    /// by the periodic sync and by size or age triggers don't run over the same files
+    pub conversion: Mutex<()>,
+    /// Context the derived columns of the stream are evaluated in, built on first use
+    pub derived_context: OnceCell<SessionContext>,
     pub ingestor_id: Option<String>,
 }
 
@@ -119,11 +290,69 @@ impl Stream {
             data_path,
             options,
             writer: Mutex::new(Writer::default()),
+            acks: AckTracker::default(),
+            upload_retries: UploadRetries::default(),
+            schema_synced_at: Mutex::new(None),
+            next_sequence: Mutex::new(None),
+            dedup: Mutex::default(),
+            latest_event_at: Mutex::new(None),
+            pending_uploads: Mutex::default(),
+            conversion: Mutex::default(),
+            derived_context: OnceCell::new(),
             ingestor_id,
         })
     }
 
-    // Concatenates record batches and puts them in memory store for each event.
+    /// Appends a sequence number column to the records, continuing from the last number
+    /// assigned in this stream. Numbering starts from `seed` when none has been assigned yet.
+    ///
+    /// The number to continue from is kept in the staging directory of the stream, see [`Self::sequence_seed`]
+    pub fn assign_sequence(
+        &self,
+        rb: &RecordBatch,
+        seed: impl FnOnce() -> u64,
+    ) -> Result<RecordBatch, StagingError> {
+        let rows = rb.num_rows() as u64;
+        let start = {
+            let mut next = self.next_sequence.lock().expect(LOCK_EXPECT);
+            let start = *next.get_or_insert_with(seed);
+            std::fs::create_dir_all(&self.data_path)?;
+            write(
+                self.data_path.join(SEQUENCE_FILE_NAME),
+                (start + rows).to_string(),
+            )?;
+            *next = Some(start + rows);
+            start
+        };
+
+        let schema = rb.schema();
+        let mut fields = schema.fields().to_vec();
+        fields.push(Arc::new(Field::new(SEQUENCE_KEY, DataType::UInt64, true)));
+        let mut columns = rb.columns().to_vec();
+        columns.push(Arc::new(UInt64Array::from_iter_values(start..start + rows)));
+
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+            columns,
+        )?)
+    }
+
+    /// Sequence number the events of the stream are numbered from after a restart, where the
+    /// numbering stopped on this node. Each ingestor numbers from a range of its own, the top
+    /// 16 bits being taken from its id, so that events from different nodes are never numbered alike
+    pub fn sequence_seed(&self) -> u64 {
+        std::fs::read_to_string(self.data_path.join(SEQUENCE_FILE_NAME))
+            .ok()
+            .and_then(|next| next.trim().parse().ok())
+            .unwrap_or_else(|| {
+                self.ingestor_id.as_ref().map_or(0, |id| {
+                    xxhash_rust::xxh3::xxh3_64(id.as_bytes()) >> 48 << 48
+                })
+            })
+    }
+
+    // Concatenates record batches and puts them in memory store for each event, returns the
+    // sequence number the batch was pushed with, that it is acknowledged on
     pub fn push(
         &self,
         schema_key: &str,
@@ -131,8 +360,11 @@ impl Stream {
         parsed_timestamp: NaiveDateTime,
         custom_partition_values: &HashMap<String, String>,
         stream_type: StreamType,
-    ) -> Result<(), StagingError> {
+    ) -> Result<u64, StagingError> {
+        self.ensure_writable()?;
         let mut guard = self.writer.lock().unwrap();
+        guard.seq += 1;
+        let seq = guard.seq;
         if self.options.mode != Mode::Query || stream_type == StreamType::Internal {
             let filename =
                 self.filename_by_partition(schema_key, parsed_timestamp, custom_partition_values);
@@ -148,9 +380,13 @@ impl Stream {
                         parsed_timestamp.and_local_timezone(Utc).unwrap(),
                         OBJECT_STORE_DATA_GRANULARITY,
                     );
+                    if let Some(limit) = self.options.max_open_arrow_writers {
+                        guard.make_room(limit);
+                    }
                     let file_path = self.data_path.join(&filename);
                     let mut writer = DiskWriter::try_new(file_path, &record.schema(), range)
-                        .expect("File and RecordBatch both are checked");
+                        .expect("File and RecordBatch both are checked")
+                        .starting_at(seq);
 
                     writer.write(record)?;
                     guard.disk.insert(filename, writer);
@@ -159,8 +395,14 @@ impl Stream {
         }
 
         guard.mem.push(schema_key, record);
+        guard.pending_rows += record.num_rows();
+        guard.pending_bytes += record.get_array_memory_size() as u64;
+        guard.first_pending.get_or_insert_with(Instant::now);
 
-        Ok(())
+        let mut latest_event_at = self.latest_event_at.lock().expect(LOCK_EXPECT);
+        *latest_event_at = (*latest_event_at).max(Some(parsed_timestamp));
+
+        Ok(seq)
     }
 
     pub fn filename_by_partition(
@@ -174,16 +416,53 @@ impl Stream {
             hostname.push_str(id);
         }
         format!(
-            "{stream_hash}.date={}.hour={:02}.minute={}.{}{hostname}.data.{ARROW_FILE_EXTENSION}",
-            parsed_timestamp.date(),
-            parsed_timestamp.hour(),
-            Minute::from(parsed_timestamp).to_slot(OBJECT_STORE_DATA_GRANULARITY),
+            "{stream_hash}.{}.{hostname}.data.{ARROW_FILE_EXTENSION}",
+            self.partitions(parsed_timestamp, custom_partition_values)
+                .join(".")
+        )
+    }
+
+    /// Prefix under the data root of the stream that events of the time and custom partition
+    /// values are put at in object storage, e.g. `date=2025-01-01/hour=10/minute=00/host=a`
+    pub fn partition_prefix(
+        &self,
+        parsed_timestamp: NaiveDateTime,
+        custom_partition_values: &HashMap<String, String>,
+    ) -> String {
+        self.partitions(parsed_timestamp, custom_partition_values)
+            .join("/")
+    }
+
+    fn partitions(
+        &self,
+        parsed_timestamp: NaiveDateTime,
+        custom_partition_values: &HashMap<String, String>,
+    ) -> Vec<String> {
+        // partitions aligned to a timezone are named after the local time, followed by its offset
+        let (partition_time, zone) = match self.get_settings().partition_timezone() {
+            Some(timezone) => {
+                let (local, offset) = to_partition_time(parsed_timestamp, timezone);
+                (local, Some(format!("{PARTITION_ZONE_KEY}={offset}")))
+            }
+            None => (parsed_timestamp, None),
+        };
+        let mut partitions = vec![
+            format!("date={}", partition_time.date()),
+            format!("hour={:02}", partition_time.hour()),
+            format!(
+                "minute={}",
+                Minute::from(partition_time).to_slot(OBJECT_STORE_DATA_GRANULARITY)
+            ),
+        ];
+        partitions.extend(zone);
+        partitions.extend(
             custom_partition_values
                 .iter()
                 .sorted_by_key(|v| v.0)
-                .map(|(key, value)| format!("{key}={value}."))
-                .join("")
-        )
+                .map(|(key, value)| format!("{key}={value}")),
+        );
+
+        partitions
     }
 
     pub fn arrow_files(&self) -> Vec<PathBuf> {
@@ -362,19 +641,94 @@ impl Stream {
         self.writer.lock().unwrap().mem.recordbatch_cloned(schema)
     }
 
+    /// Returns upto `n` of the latest rows held in memory, latest first
+    pub fn tail(&self, n: usize) -> Vec<RecordBatch> {
+        let records = self.recordbatches_cloned(&self.get_schema());
+        latest_rows(&records, n)
+    }
+
+    /// Returns what is held in memory for the stream, without flushing it
+    pub fn buffered(&self, sample_size: usize) -> BufferedRecords {
+        let writer = self.writer.lock().unwrap();
+        let schema = writer.mem.schema().clone();
+        let records = writer.mem.recordbatch_cloned(&Arc::new(schema.clone()));
+        let (pending_rows, pending_bytes) = (writer.pending_rows, writer.pending_bytes);
+        drop(writer);
+
+        BufferedRecords {
+            schema,
+            rows: records.iter().map(|rb| rb.num_rows()).sum(),
+            pending_rows,
+            pending_bytes,
+            sample: latest_rows(&records, sample_size),
+        }
+    }
+
     pub fn clear(&self) {
         self.writer.lock().unwrap().mem.clear();
     }
 
-    pub fn flush(&self, forced: bool) {
+    /// Flushes record batches onto disk, returns the sequence number upto which batches are flushed
+    pub fn flush(&self, forced: bool) -> u64 {
         let mut writer = self.writer.lock().unwrap();
         // Flush memory
         writer.mem.clear();
+        writer.pending_rows = 0;
+        writer.pending_bytes = 0;
+        writer.first_pending = None;
         // Drop schema -> disk writer mapping, triggers flush to disk
-        writer.disk.retain(|_, w| !forced && w.is_current());
+        let now = Instant::now();
+        let idle_timeout = self.options.arrow_writer_idle_timeout;
+        writer.disk.retain(|_, w| {
+            !forced
+                && w.is_current()
+                && !idle_timeout.is_some_and(|timeout| w.is_idle(timeout, now))
+        });
+
+        // Batches pushed before the first one into files that are still being written, are flushed
+        writer
+            .disk
+            .values()
+            .map(|w| w.first_seq() - 1)
+            .min()
+            .unwrap_or(writer.seq)
+    }
+
+    /// Returns `true` once records buffered since the last flush cross `P_FLUSH_MAX_ROWS` or `P_FLUSH_MAX_SIZE`,
+    /// or have been buffered for longer than `P_FLUSH_MAX_AGE`, whichever happens first
+    pub fn is_flush_due(&self, now: Instant) -> bool {
+        let writer = self.writer.lock().unwrap();
+        let Some(first_pending) = writer.first_pending else {
+            return false;
+        };
+
+        self.options
+            .flush_max_rows
+            .is_some_and(|max| writer.pending_rows >= max)
+            || self
+                .options
+                .flush_max_size
+                .is_some_and(|max| writer.pending_bytes >= max)
+            || self
+                .options
+                .flush_max_age
+                .is_some_and(|max| now.saturating_duration_since(first_pending) >= max)
+    }
+
+    /// Returns once the batches pushed into the stream upto sequence number `seq` can be
+    /// acknowledged, as per `P_ACK_MODE`. Batches pushed later, by concurrent requests, aren't waited on
+    pub async fn acknowledge(&self, seq: u64) -> Result<(), StagingError> {
+        // data is not staged by query nodes
+        if self.options.mode == Mode::Query {
+            return Ok(());
+        }
+
+        self.acks
+            .wait(self.options.ack_mode, seq, self.options.ack_timeout)
+            .await
     }
 
-    fn parquet_writer_props(
+    pub fn parquet_writer_props(
         &self,
         merged_schema: &Schema,
         time_partition: Option<&String>,
@@ -388,6 +742,7 @@ impl Stream {
 
         let mut props = WriterProperties::builder()
             .set_max_row_group_size(self.options.row_group_size)
+            .set_write_batch_size(self.options.parquet_write_batch_size.max(1))
             .set_compression(self.options.parquet_compression.into())
             .set_column_encoding(
                 ColumnPath::new(vec![time_partition_field.to_string()]),
@@ -417,6 +772,14 @@ impl Stream {
             }
         }
 
+        // Enable bloom filters on configured columns, so that point lookups can skip row groups
+        for column in &self.options.parquet_bloom_filter_columns {
+            if merged_schema.index_of(column).is_ok() {
+                let column_path = ColumnPath::new(vec![column.to_string()]);
+                props = props.set_column_bloom_filter_enabled(column_path, true);
+            }
+        }
+
         // Set sorting columns
         props.set_sorting_columns(Some(sorting_column_vec)).build()
     }
@@ -476,35 +839,55 @@ impl Stream {
             let props = self.parquet_writer_props(&merged_schema, time_partition, custom_partition);
             schemas.push(merged_schema.clone());
             let schema = Arc::new(merged_schema);
-            let mut part_path = parquet_path.to_owned();
-            part_path.set_extension("part");
-            let mut part_file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&part_path)
-                .map_err(|_| StagingError::Create)?;
-            let mut writer = ArrowWriter::try_new(&mut part_file, schema.clone(), Some(props))?;
-            for ref record in record_reader.merged_iter(schema, time_partition.cloned()) {
-                writer.write(record)?;
+            let target_size = self.get_settings().parquet_target_size;
+            let mut records = record_reader
+                .merged_iter(schema.clone(), time_partition.cloned())
+                .peekable();
+            let mut written = true;
+            let mut part = 0;
+            // records past the target size of the stream are rolled over into the next file
+            while records.peek().is_some() {
+                let path = rolled_over_path(&parquet_path, part);
+                if self.options.disable_local_cache {
+                    // kept in memory until uploaded, unless too much is already pending
+                    let mut buffer = Vec::new();
+                    write_parquet(
+                        &mut buffer,
+                        schema.clone(),
+                        props.clone(),
+                        &mut records,
+                        target_size,
+                        &self.options,
+                    )?;
+                    let filename = path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .expect("parquet filename is valid string")
+                        .to_owned();
+                    written &= self.queue_upload(filename, Bytes::from(buffer))?;
+                } else {
+                    // waits for the conversions of other streams when as many files are open
+                    let _permit = self
+                        .options
+                        .max_open_parquet_writers
+                        .map(|limit| OPEN_PARQUET_WRITERS.acquire(limit));
+                    written &= write_parquet_atomically(&path, |part_file| {
+                        write_parquet(
+                            part_file,
+                            schema.clone(),
+                            props.clone(),
+                            &mut records,
+                            target_size,
+                            &self.options,
+                        )
+                    })?;
+                }
+                part += 1;
             }
-            writer.close()?;
 
-            if part_file.metadata().expect("File was just created").len()
-                < parquet::file::FOOTER_SIZE as u64
-            {
-                error!(
-                    "Invalid parquet file {part_path:?} detected for stream {}, removing it",
-                    &self.stream_name
-                );
-                remove_file(part_path).unwrap();
-            } else {
+            // arrow files are only removed once their records are safely in a parquet file
+            if written {
                 trace!("Parquet file successfully constructed");
-                if let Err(e) = std::fs::rename(&part_path, &parquet_path) {
-                    error!(
-                        "Couldn't rename part file: {part_path:?} -> {parquet_path:?}, error = {e}"
-                    );
-                }
-
                 for file in arrow_files {
                     let file_size = match file.metadata() {
                         Ok(meta) => meta.len(),
@@ -606,10 +989,137 @@ impl Stream {
         Arc::new(Schema::new(fields))
     }
 
+    /// Replaces the schema of the stream
+    pub fn set_schema(&self, schema: &Schema) {
+        self.metadata.write().expect(LOCK_EXPECT).schema = schema
+            .fields()
+            .iter()
+            .map(|field| (field.name().to_owned(), field.clone()))
+            .collect();
+    }
+
     pub fn get_schema_raw(&self) -> HashMap<String, Arc<Field>> {
         self.metadata.read().expect(LOCK_EXPECT).schema.clone()
     }
 
+    /// Checks if the schema is due to be compared against storage, marks it as compared if so
+    pub fn is_schema_sync_due(&self) -> bool {
+        let mut synced_at = self.schema_synced_at.lock().expect(LOCK_EXPECT);
+        if synced_at.is_some_and(|at| at.elapsed() < SCHEMA_SYNC_INTERVAL) {
+            return false;
+        }
+        *synced_at = Some(Instant::now());
+
+        true
+    }
+
+    /// Makes the next call to [`Self::is_schema_sync_due`] return true
+    pub fn reset_schema_sync(&self) {
+        *self.schema_synced_at.lock().expect(LOCK_EXPECT) = None;
+    }
+
+    /// Merges the schema stored in object store into the one in memory, returns `true` if
+    /// the schema in memory had drifted from the stored one
+    pub fn reconcile_schema(&self, stored: &Schema) -> Result<bool, ArrowError> {
+        let mut metadata = self.metadata.write().expect(LOCK_EXPECT);
+        let drifted = stored
+            .fields()
+            .iter()
+            .any(|field| metadata.schema.get(field.name()) != Some(field));
+        if !drifted {
+            return Ok(false);
+        }
+
+        let current = Schema::new(metadata.schema.values().cloned().collect::<Fields>());
+        let merged = Schema::try_merge(vec![current, stored.clone()])?;
+        metadata.schema = merged
+            .fields
+            .iter()
+            .map(|field| (field.name().clone(), field.clone()))
+            .collect();
+
+        Ok(true)
+    }
+
+    /// Returns the settings of the stream, as of when it is called
+    pub fn get_settings(&self) -> Arc<StreamSettings> {
+        self.metadata.read().expect(LOCK_EXPECT).settings.clone()
+    }
+
+    pub fn set_settings(&self, settings: StreamSettings) {
+        self.metadata.write().expect(LOCK_EXPECT).settings = Arc::new(settings);
+    }
+
+    pub fn get_latest_event_at(&self) -> Option<DateTime<Utc>> {
+        self.latest_event_at
+            .lock()
+            .expect(LOCK_EXPECT)
+            .map(|latest| latest.and_utc())
+    }
+
+    /// Takes the parquet files built in memory, to be put into object storage
+    pub fn take_pending_uploads(&self) -> Vec<(String, Bytes)> {
+        std::mem::take(&mut *self.pending_uploads.lock().expect(LOCK_EXPECT))
+    }
+
+    /// Returns parquet files that weren't attempted to be put into object storage
+    pub fn restore_pending_uploads(&self, uploads: Vec<(String, Bytes)>) {
+        self.pending_uploads
+            .lock()
+            .expect(LOCK_EXPECT)
+            .extend(uploads);
+    }
+
+    /// Queues a parquet file built in memory to be uploaded, it is spilled into staging instead
+    /// once the files queued add up to more than `P_MAX_PENDING_UPLOAD_SIZE`. Returns `false` if
+    /// the file couldn't be put in place.
+    fn queue_upload(&self, filename: String, parquet: Bytes) -> Result<bool, StagingError> {
+        let mut pending = self.pending_uploads.lock().expect(LOCK_EXPECT);
+        let queued: usize = pending.iter().map(|(_, parquet)| parquet.len()).sum();
+        if self
+            .options
+            .max_pending_upload_size
+            .is_none_or(|max| (queued + parquet.len()) as u64 <= max)
+        {
+            pending.push((filename, parquet));
+            return Ok(true);
+        }
+        drop(pending);
+
+        self.spill_upload(&filename, &parquet)
+    }
+
+    /// Writes a parquet file built in memory into staging, from where it is uploaded like any
+    /// parquet file converted on disk
+    fn spill_upload(&self, filename: &str, parquet: &[u8]) -> Result<bool, StagingError> {
+        write_parquet_atomically(&self.data_path.join(filename), |part_file| {
+            Ok(part_file.write_all(parquet)?)
+        })
+    }
+
+    /// Hands a parquet file built in memory that failed to upload over to staging, so that it is
+    /// retried with backoff and dead-lettered like files converted on disk. It is kept in memory
+    /// if it can't be written into staging.
+    pub fn retry_upload(&self, filename: String, parquet: Bytes) {
+        match self.spill_upload(&filename, &parquet) {
+            Ok(true) => {}
+            Ok(false) => self.restore_pending_uploads(vec![(filename, parquet)]),
+            Err(e) => {
+                error!("Failed to write {filename:?} into staging, keeping it in memory: {e}");
+                self.restore_pending_uploads(vec![(filename, parquet)]);
+            }
+        }
+    }
+
+    /// Errors if the stream is frozen and can't be written to
+    pub fn ensure_writable(&self) -> Result<(), StagingError> {
+        if self.get_settings().frozen {
+            return Err(StagingError::StreamFrozen(self.stream_name.clone()));
+        }
+
+        Ok(())
+    }
+
     pub fn set_retention(&self, retention: Retention) {
         self.metadata.write().expect(LOCK_EXPECT).retention = Some(retention);
     }
@@ -726,8 +1236,24 @@ impl Stream {
 
     /// First flushes arrows onto disk and then converts the arrow into parquet
     pub fn flush_and_convert(&self, shutdown_signal: bool) -> Result<(), StagingError> {
+        let _conversion = self.conversion.lock().expect(LOCK_EXPECT);
+        self.flush_and_convert_locked(shutdown_signal)
+    }
+
+    /// Flushes and converts like [`Self::flush_and_convert`] unless a conversion of the stream is
+    /// already running, returns whether it did
+    pub fn try_flush_and_convert(&self, shutdown_signal: bool) -> Result<bool, StagingError> {
+        let Ok(_conversion) = self.conversion.try_lock() else {
+            return Ok(false);
+        };
+        self.flush_and_convert_locked(shutdown_signal)?;
+
+        Ok(true)
+    }
+
+    fn flush_and_convert_locked(&self, shutdown_signal: bool) -> Result<(), StagingError> {
         let start_flush = Instant::now();
-        self.flush(shutdown_signal);
+        let flushed = self.flush(shutdown_signal);
         trace!(
             "Flushing stream ({}) took: {}s",
             self.stream_name,
@@ -736,6 +1262,7 @@ impl Stream {
 
         let start_convert = Instant::now();
         self.prepare_parquet(shutdown_signal)?;
+        self.acks.mark_converted(flushed);
         trace!(
             "Converting arrows to parquet on stream ({}) took: {}s",
             self.stream_name,
@@ -785,6 +1312,28 @@ impl Streams {
         self.read().expect(LOCK_EXPECT).contains_key(stream_name)
     }
 
+    /// Resolves the name used in a query into that of the stream, which is either the name itself
+    /// or that of the stream it is an alias of.
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        let map = self.read().expect(LOCK_EXPECT);
+        if map.contains_key(name) {
+            return Some(name.to_owned());
+        }
+
+        map.iter()
+            .find(|(_, stream)| {
+                stream
+                    .metadata
+                    .read()
+                    .expect(LOCK_EXPECT)
+                    .settings
+                    .aliases
+                    .iter()
+                    .any(|alias| alias == name)
+            })
+            .map(|(stream_name, _)| stream_name.clone())
+    }
+
     /// Returns the number of logstreams that parseable is aware of
     pub fn len(&self) -> usize {
         self.read().expect(LOCK_EXPECT).len()
@@ -829,7 +1378,25 @@ impl Streams {
             .map(Arc::clone)
             .collect();
         for stream in streams {
-            joinset.spawn(async move { stream.flush_and_convert(shutdown_signal) });
+            // conversions block on file IO and on the limit of open parquet writers
+            joinset.spawn_blocking(move || stream.flush_and_convert(shutdown_signal));
+        }
+    }
+
+    /// Spawns a flush+conversion task for streams whose size or age trigger has fired, such streams
+    /// are converted in entirety, including the arrows being written for the current minute.
+    pub fn flush_due(&self, joinset: &mut JoinSet<Result<(), StagingError>>) {
+        let now = Instant::now();
+        let streams: Vec<Arc<Stream>> = self
+            .read()
+            .expect(LOCK_EXPECT)
+            .values()
+            .filter(|stream| stream.is_flush_due(now))
+            .map(Arc::clone)
+            .collect();
+        // streams still being converted are left for the next check, the trigger is still due then
+        for stream in streams {
+            joinset.spawn_blocking(move || stream.try_flush_and_convert(true).map(|_| ()));
         }
     }
 }
@@ -839,11 +1406,14 @@ mod tests {
     use std::{io::Write, sync::Barrier, thread::spawn, time::Duration};
 
     use arrow_array::{Int32Array, StringArray, TimestampMillisecondArray};
-    use arrow_schema::{DataType, Field, TimeUnit};
+    use arrow_schema::TimeUnit;
     use chrono::{NaiveDate, TimeDelta, Utc};
+    use rand::distributions::Alphanumeric;
     use temp_dir::TempDir;
     use tokio::time::sleep;
 
+    use crate::option::AckMode;
+
     use super::*;
 
     #[test]
@@ -970,6 +1540,55 @@ mod tests {
         assert_eq!(generated, expected);
     }
 
+    #[test]
+    fn partitions_are_aligned_to_local_midnight_of_stream_timezone() {
+        let staging = Stream::new(
+            Arc::new(Options::default()),
+            "test_stream",
+            LogStreamMetadata {
+                settings: Arc::new(StreamSettings {
+                    partition_timezone: Some("America/New_York".to_owned()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            None,
+        );
+        let utc = |day, hour, minute| {
+            NaiveDate::from_ymd_opt(2025, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+        let slot = |minute| {
+            Minute::try_from(minute)
+                .unwrap()
+                .to_slot(OBJECT_STORE_DATA_GRANULARITY)
+        };
+
+        // midnight in New York is at 05:00 UTC in winter
+        let before_midnight = staging.filename_by_partition("abc", utc(2, 4, 59), &HashMap::new());
+        assert!(before_midnight.starts_with(&format!(
+            "abc.date=2025-01-01.hour=23.minute={}.zone=-0500.",
+            slot(59)
+        )));
+        let at_midnight = staging.filename_by_partition("abc", utc(2, 5, 0), &HashMap::new());
+        assert!(at_midnight.starts_with(&format!(
+            "abc.date=2025-01-02.hour=00.minute={}.zone=-0500.",
+            slot(0)
+        )));
+        // and at 04:00 UTC in summer, which the offset in the key tells apart
+        let summer = NaiveDate::from_ymd_opt(2025, 7, 1)
+            .unwrap()
+            .and_hms_opt(4, 0, 0)
+            .unwrap();
+        let in_summer = staging.filename_by_partition("abc", summer, &HashMap::new());
+        assert!(in_summer.starts_with(&format!(
+            "abc.date=2025-07-01.hour=00.minute={}.zone=-0400.",
+            slot(0)
+        )));
+    }
+
     #[test]
     fn generate_correct_path_with_current_time_and_custom_partitioning() {
         let stream_name = "test_stream";
@@ -1060,6 +1679,83 @@ mod tests {
         staging.flush(true);
     }
 
+    #[test]
+    fn flush_reports_batches_written_before_current_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        let staging = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]);
+        let batch = RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(TimestampMillisecondArray::from(vec![1])),
+                Arc::new(Int32Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["a"])),
+            ],
+        )
+        .unwrap();
+        let now = Utc::now().naive_utc();
+        for time in [now - TimeDelta::minutes(5), now] {
+            staging
+                .push(
+                    "abc",
+                    &batch,
+                    time,
+                    &HashMap::new(),
+                    StreamType::UserDefined,
+                )
+                .unwrap();
+        }
+
+        // file for the current minute is still being written into
+        assert_eq!(staging.flush(false), 1);
+        assert_eq!(staging.flush(true), 2);
+    }
+
+    #[tokio::test]
+    async fn acknowledge_waits_only_on_own_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ack_mode: AckMode::Sync,
+            ack_timeout: Duration::from_millis(100),
+            ..Default::default()
+        });
+        let staging = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let schema = Schema::new(vec![Field::new("id", DataType::Int32, false)]);
+        let batch =
+            RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int32Array::from(vec![1]))])
+                .unwrap();
+        let now = Utc::now().naive_utc();
+        let push = || {
+            staging
+                .push("abc", &batch, now, &HashMap::new(), StreamType::UserDefined)
+                .unwrap()
+        };
+        let own = push();
+        // staged by a concurrent request, after this one
+        let later = push();
+        assert!(later > own);
+
+        staging.acks.mark_persisted(own);
+        staging.acknowledge(own).await.unwrap();
+        assert!(matches!(
+            staging.acknowledge(later).await,
+            Err(StagingError::AckTimeout(_))
+        ));
+    }
+
     #[test]
     fn different_minutes_multiple_arrow_files_to_parquet() {
         let temp_dir = TempDir::new().unwrap();
@@ -1110,22 +1806,16 @@ mod tests {
     }
 
     #[test]
-    fn same_minute_multiple_arrow_files_to_parquet() {
+    fn conversions_past_writer_limit_wait_their_turn() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
         let temp_dir = TempDir::new().unwrap();
-        let stream_name = "test_stream";
         let options = Arc::new(Options {
             local_staging_path: temp_dir.path().to_path_buf(),
             row_group_size: 1048576,
+            max_open_parquet_writers: Some(2),
             ..Default::default()
         });
-        let staging = Stream::new(
-            options.clone(),
-            stream_name,
-            LogStreamMetadata::default(),
-            None,
-        );
-
-        // Create test arrow files
         let schema = Schema::new(vec![
             Field::new(
                 DEFAULT_TIMESTAMP_KEY,
@@ -1135,47 +1825,334 @@ mod tests {
             Field::new("id", DataType::Int32, false),
             Field::new("value", DataType::Utf8, false),
         ]);
+        let streams: Vec<_> = ["first", "second", "third"]
+            .into_iter()
+            .map(|stream_name| {
+                let staging = Stream::new(
+                    options.clone(),
+                    stream_name,
+                    LogStreamMetadata::default(),
+                    None,
+                );
+                for i in 0..3 {
+                    write_log(&staging, &schema, i);
+                }
+                drop(staging);
+                Stream::new(
+                    options.clone(),
+                    stream_name,
+                    LogStreamMetadata::default(),
+                    None,
+                )
+            })
+            .collect();
 
-        for _ in 0..3 {
-            write_log(&staging, &schema, 0);
+        let handles: Vec<_> = streams
+            .iter()
+            .cloned()
+            .map(|staging| spawn(move || staging.convert_disk_files_to_parquet(None, None, true)))
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().unwrap().is_some());
         }
-        // verify the arrow files exist in staging
-        assert_eq!(staging.arrow_files().len(), 1);
-        drop(staging);
-
-        // Start with a fresh staging
-        let staging = Stream::new(options, stream_name, LogStreamMetadata::default(), None);
-        let result = staging
-            .convert_disk_files_to_parquet(None, None, true)
-            .unwrap();
-
-        assert!(result.is_some());
-        let result_schema = result.unwrap();
-        assert_eq!(result_schema.fields().len(), 3);
-
-        // Verify parquet files were created and the arrow files deleted
-        assert_eq!(staging.parquet_files().len(), 1);
-        assert_eq!(staging.arrow_files().len(), 0);
+        for staging in &streams {
+            assert_eq!(staging.parquet_files().len(), 3);
+            assert_eq!(staging.arrow_files().len(), 0);
+        }
+        assert_eq!(OPEN_PARQUET_WRITERS.open(), 0);
+
+        // no more than the limit are ever open at once
+        let peak = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let peak = peak.clone();
+                spawn(move || {
+                    for _ in 0..20 {
+                        let _permit = OPEN_PARQUET_WRITERS.acquire(2);
+                        peak.fetch_max(OPEN_PARQUET_WRITERS.open(), Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(OPEN_PARQUET_WRITERS.open(), 0);
     }
 
-    #[tokio::test]
-    async fn miss_current_arrow_file_when_converting_to_parquet() {
+    #[test]
+    fn arrow_writers_past_limit_are_closed_without_losing_events() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
         let temp_dir = TempDir::new().unwrap();
-        let stream_name = "test_stream";
         let options = Arc::new(Options {
             local_staging_path: temp_dir.path().to_path_buf(),
             row_group_size: 1048576,
+            max_open_arrow_writers: Some(2),
+            arrow_writer_idle_timeout: Some(Duration::ZERO),
             ..Default::default()
         });
         let staging = Stream::new(
-            options.clone(),
-            stream_name,
+            options,
+            "arrow_writer_limit",
             LogStreamMetadata::default(),
             None,
         );
-
-        // Create test arrow files
-        let schema = Schema::new(vec![
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(TimestampMillisecondArray::from(vec![1, 2, 3])),
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+            ],
+        )
+        .unwrap();
+        let time = Utc::now().naive_utc();
+
+        // three partitions written in turns, each write past the limit closes the one written longest ago
+        for _ in 0..3 {
+            for env in ["a", "b", "c"] {
+                let partition = HashMap::from([("env".to_owned(), env.to_owned())]);
+                staging
+                    .push("abc", &batch, time, &partition, StreamType::UserDefined)
+                    .unwrap();
+                assert!(staging.writer.lock().unwrap().disk.len() <= 2);
+            }
+        }
+
+        // files reopened for a partition don't overwrite the ones closed before
+        staging.flush(true);
+        assert_eq!(staging.arrow_files().len(), 9);
+
+        staging
+            .convert_disk_files_to_parquet(None, None, true)
+            .unwrap();
+        let parquet_files = staging.parquet_files();
+        assert_eq!(parquet_files.len(), 3);
+        let rows: i64 = parquet_files
+            .iter()
+            .map(|path| {
+                let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+                reader.metadata().file_metadata().num_rows()
+            })
+            .sum();
+        assert_eq!(rows, 27);
+
+        // idle files are closed on flush, even while their minute is current
+        staging
+            .push(
+                "abc",
+                &batch,
+                time,
+                &HashMap::new(),
+                StreamType::UserDefined,
+            )
+            .unwrap();
+        staging.flush(false);
+        assert!(staging.writer.lock().unwrap().disk.is_empty());
+    }
+
+    #[tokio::test]
+    async fn disabled_local_cache_puts_parquet_straight_into_storage() {
+        use arrow_array::cast::AsArray;
+        use datafusion::prelude::{ParquetReadOptions, SessionContext};
+
+        use crate::{parseable::PARSEABLE, storage::ObjectStoreFormat};
+
+        let stream_name = "disabled_local_cache";
+        let options = Arc::new(Options {
+            local_staging_path: PARSEABLE.options.local_staging_path.clone(),
+            row_group_size: 1048576,
+            disable_local_cache: true,
+            ..Default::default()
+        });
+        let staging = PARSEABLE.streams.get_or_create(
+            options,
+            stream_name.to_owned(),
+            LogStreamMetadata::default(),
+            None,
+        );
+        let schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]);
+        let store = PARSEABLE.storage.get_object_store();
+        store
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat::default(),
+                Arc::new(schema.clone()),
+            )
+            .await
+            .unwrap();
+        for i in 0..3 {
+            write_log(&staging, &schema, i);
+        }
+        staging
+            .convert_disk_files_to_parquet(None, None, true)
+            .unwrap();
+
+        // neither arrows nor parquet files are left on disk
+        let mut dirs = vec![staging.data_path.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                assert!(path.is_dir(), "{path:?} was left on disk");
+                dirs.push(path);
+            }
+        }
+        assert_eq!(staging.pending_uploads.lock().unwrap().len(), 3);
+
+        store.upload_files_from_staging().await.unwrap();
+        assert!(staging.take_pending_uploads().is_empty());
+        assert_eq!(
+            store
+                .get_object_store_format(stream_name)
+                .await
+                .unwrap()
+                .snapshot
+                .manifest_list
+                .len(),
+            1
+        );
+
+        let ctx = SessionContext::new();
+        ctx.register_parquet(
+            "t",
+            &format!("{}/{stream_name}/", PARSEABLE.storage.get_endpoint()),
+            ParquetReadOptions::default(),
+        )
+        .await
+        .unwrap();
+        let records = ctx
+            .sql("SELECT count(*) FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            records[0]
+                .column(0)
+                .as_primitive::<arrow_array::types::Int64Type>()
+                .value(0),
+            9
+        );
+    }
+
+    #[test]
+    fn pending_uploads_past_limit_are_spilled_into_staging() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            row_group_size: 1048576,
+            disable_local_cache: true,
+            max_pending_upload_size: Some(1),
+            ..Default::default()
+        });
+        let staging = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]);
+        for i in 0..3 {
+            write_log(&staging, &schema, i);
+        }
+        staging
+            .convert_disk_files_to_parquet(None, None, true)
+            .unwrap();
+
+        // uploaded, retried and dead-lettered like any parquet file in staging
+        assert!(staging.take_pending_uploads().is_empty());
+        assert_eq!(staging.parquet_files().len(), 3);
+    }
+
+    #[test]
+    fn same_minute_multiple_arrow_files_to_parquet() {
+        let temp_dir = TempDir::new().unwrap();
+        let stream_name = "test_stream";
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            row_group_size: 1048576,
+            ..Default::default()
+        });
+        let staging = Stream::new(
+            options.clone(),
+            stream_name,
+            LogStreamMetadata::default(),
+            None,
+        );
+
+        // Create test arrow files
+        let schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]);
+
+        for _ in 0..3 {
+            write_log(&staging, &schema, 0);
+        }
+        // verify the arrow files exist in staging
+        assert_eq!(staging.arrow_files().len(), 1);
+        drop(staging);
+
+        // Start with a fresh staging
+        let staging = Stream::new(options, stream_name, LogStreamMetadata::default(), None);
+        let result = staging
+            .convert_disk_files_to_parquet(None, None, true)
+            .unwrap();
+
+        assert!(result.is_some());
+        let result_schema = result.unwrap();
+        assert_eq!(result_schema.fields().len(), 3);
+
+        // Verify parquet files were created and the arrow files deleted
+        assert_eq!(staging.parquet_files().len(), 1);
+        assert_eq!(staging.arrow_files().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn miss_current_arrow_file_when_converting_to_parquet() {
+        let temp_dir = TempDir::new().unwrap();
+        let stream_name = "test_stream";
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            row_group_size: 1048576,
+            ..Default::default()
+        });
+        let staging = Stream::new(
+            options.clone(),
+            stream_name,
+            LogStreamMetadata::default(),
+            None,
+        );
+
+        // Create test arrow files
+        let schema = Schema::new(vec![
             Field::new(
                 DEFAULT_TIMESTAMP_KEY,
                 DataType::Timestamp(TimeUnit::Millisecond, None),
@@ -1212,6 +2189,78 @@ mod tests {
         assert_eq!(staging.arrow_files().len(), 1);
     }
 
+    #[test]
+    fn parquet_file_is_rolled_over_at_target_size() {
+        const TARGET_SIZE: u64 = 256 * 1024;
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            row_group_size: 1048576,
+            ..Default::default()
+        });
+        let metadata = LogStreamMetadata {
+            settings: Arc::new(StreamSettings {
+                parquet_target_size: Some(TARGET_SIZE),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let staging = Stream::new(options, "test_stream", metadata, None);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("value", DataType::Utf8, false),
+        ]));
+
+        // just under twice the target size of random values, in batches of ~32 KiB
+        let time = Utc::now().naive_utc();
+        for _ in 0..15 {
+            let values = (0..256)
+                .map(|_| Alphanumeric.sample_string(&mut rand::thread_rng(), 128))
+                .collect::<Vec<_>>();
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(TimestampMillisecondArray::from(vec![1; 256])),
+                    Arc::new(StringArray::from(values)),
+                ],
+            )
+            .unwrap();
+            staging
+                .push(
+                    "abc",
+                    &batch,
+                    time,
+                    &HashMap::new(),
+                    StreamType::UserDefined,
+                )
+                .unwrap();
+        }
+        staging.flush(true);
+
+        staging
+            .convert_disk_files_to_parquet(None, None, true)
+            .unwrap();
+
+        let mut sizes = staging
+            .parquet_files()
+            .iter()
+            .map(|file| file.metadata().unwrap().len())
+            .collect::<Vec<_>>();
+        sizes.sort_unstable();
+        assert_eq!(sizes.len(), 2, "{sizes:?}");
+        for size in sizes {
+            assert!(
+                size >= TARGET_SIZE / 2 && size <= TARGET_SIZE * 5 / 4,
+                "{size}"
+            );
+        }
+        assert_eq!(staging.arrow_files().len(), 0);
+    }
+
     fn create_test_file(dir: &TempDir, filename: &str) -> PathBuf {
         let file_path = dir.path().join(filename);
         let mut file = File::create(&file_path).expect("Failed to create test file");
@@ -1371,4 +2420,626 @@ mod tests {
         let guard = streams.read().expect("Failed to acquire read lock");
         assert_eq!(guard.len(), 1);
     }
+
+    #[test]
+    fn next_event_picks_up_schema_changed_in_storage() {
+        use crate::event::format::{json, EventFormat};
+
+        let metadata = LogStreamMetadata {
+            schema: HashMap::from([(
+                "a".to_owned(),
+                Arc::new(Field::new("a", DataType::Utf8, true)),
+            )]),
+            ..Default::default()
+        };
+        let stream = Stream::new(Arc::new(Options::default()), "test_stream", metadata, None);
+
+        // schema of the stream is updated in storage, out of band
+        let stored = Schema::new(vec![
+            Field::new("a", DataType::Utf8, true),
+            Field::new("b", DataType::Int64, true),
+        ]);
+        assert!(stream.reconcile_schema(&stored).unwrap());
+        assert!(!stream.reconcile_schema(&stored).unwrap());
+
+        let (rb, is_first) = json::Event::new(serde_json::json!({"a": "x", "b": 5}))
+            .into_recordbatch(
+                &stream.get_schema_raw(),
+                false,
+                None,
+                SchemaVersion::V1,
+                &HashMap::new(),
+            )
+            .unwrap();
+        assert!(!is_first);
+        assert_eq!(
+            rb.schema().field_with_name("b").unwrap().data_type(),
+            &DataType::Int64
+        );
+
+        // incompatible changes are not applied
+        let stored = Schema::new(vec![Field::new("a", DataType::Int64, true)]);
+        assert!(stream.reconcile_schema(&stored).is_err());
+        assert_eq!(stream.get_schema_raw()["a"].data_type(), &DataType::Utf8);
+    }
+
+    #[test]
+    fn tail_returns_latest_events_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        stream.metadata.write().unwrap().schema = schema
+            .fields()
+            .iter()
+            .map(|field| (field.name().clone(), field.clone()))
+            .collect();
+
+        for id in 0..10 {
+            let rb =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![id]))])
+                    .unwrap();
+            stream
+                .push(
+                    "abc",
+                    &rb,
+                    Utc::now().naive_utc(),
+                    &HashMap::new(),
+                    StreamType::UserDefined,
+                )
+                .unwrap();
+        }
+
+        let ids: Vec<i32> = stream
+            .tail(3)
+            .iter()
+            .flat_map(|rb| {
+                rb.column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect();
+        assert_eq!(ids, vec![9, 8, 7]);
+    }
+
+    #[test]
+    fn buffered_records_are_reported_without_flushing() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        for id in [1, 2] {
+            let rb =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![id]))])
+                    .unwrap();
+            stream
+                .push(
+                    "abc",
+                    &rb,
+                    Utc::now().naive_utc(),
+                    &HashMap::new(),
+                    StreamType::UserDefined,
+                )
+                .unwrap();
+        }
+
+        let buffered = stream.buffered(1);
+        assert_eq!(buffered.rows, 2);
+        assert_eq!(buffered.schema, *schema);
+        assert_eq!(buffered.sample.len(), 1);
+        assert_eq!(buffered.sample[0].num_rows(), 1);
+        // nothing is flushed by looking
+        assert_eq!(stream.buffered(10).rows, 2);
+    }
+
+    #[test]
+    fn frozen_stream_rejects_events_but_stays_queryable() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        stream.metadata.write().unwrap().schema = schema
+            .fields()
+            .iter()
+            .map(|field| (field.name().clone(), field.clone()))
+            .collect();
+        let rb = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1]))])
+            .unwrap();
+        let push = || {
+            stream.push(
+                "abc",
+                &rb,
+                Utc::now().naive_utc(),
+                &HashMap::new(),
+                StreamType::UserDefined,
+            )
+        };
+        push().unwrap();
+
+        stream.set_settings(StreamSettings {
+            frozen: true,
+            ..Default::default()
+        });
+        assert!(matches!(push(), Err(StagingError::StreamFrozen(_))));
+
+        let rows: usize = stream.tail(10).iter().map(|rb| rb.num_rows()).sum();
+        assert_eq!(rows, 1);
+
+        stream.set_settings(StreamSettings::default());
+        push().unwrap();
+    }
+
+    fn push_rows(stream: &Stream, rows: i32) {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let rb = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int32Array::from_iter_values(0..rows))],
+        )
+        .unwrap();
+        stream
+            .push(
+                "abc",
+                &rb,
+                Utc::now().naive_utc(),
+                &HashMap::new(),
+                StreamType::UserDefined,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn flush_is_due_once_size_threshold_is_crossed() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            flush_max_rows: Some(5),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        assert!(!stream.is_flush_due(Instant::now()));
+
+        push_rows(&stream, 3);
+        assert!(!stream.is_flush_due(Instant::now()));
+
+        push_rows(&stream, 2);
+        assert!(stream.is_flush_due(Instant::now()));
+
+        // flushing resets the trigger, without age bound the buffer can stay around
+        stream.flush(true);
+        assert!(!stream.is_flush_due(Instant::now()));
+        push_rows(&stream, 1);
+        assert!(!stream.is_flush_due(Instant::now() + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn flush_is_due_once_age_threshold_is_crossed() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            flush_max_age: Some(Duration::from_secs(10)),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        // an idle stream is never due
+        assert!(!stream.is_flush_due(Instant::now() + Duration::from_secs(60)));
+
+        push_rows(&stream, 1000);
+        let now = Instant::now();
+        assert!(!stream.is_flush_due(now));
+        assert!(stream.is_flush_due(now + Duration::from_secs(10)));
+
+        stream.flush(true);
+        assert!(!stream.is_flush_due(now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn triggered_conversion_skips_stream_being_converted() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp_dir.path().to_path_buf(),
+            row_group_size: 1048576,
+            ..Default::default()
+        });
+        let schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int32, false),
+            Field::new("value", DataType::Utf8, false),
+        ]);
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        write_log(&stream, &schema, 0);
+
+        let running = stream.conversion.lock().unwrap();
+        assert!(!stream.try_flush_and_convert(true).unwrap());
+        assert!(stream.parquet_files().is_empty());
+        assert_eq!(stream.arrow_files().len(), 1);
+
+        drop(running);
+        assert!(stream.try_flush_and_convert(true).unwrap());
+        assert_eq!(stream.parquet_files().len(), 1);
+        assert!(stream.arrow_files().is_empty());
+    }
+
+    #[test]
+    fn alias_resolves_to_underlying_stream() {
+        let streams = Streams::default();
+        let options = Arc::new(Options::default());
+        let metadata = LogStreamMetadata {
+            settings: Arc::new(StreamSettings {
+                aliases: vec!["new_name".to_owned()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let stream = streams.get_or_create(options, "old_name".to_owned(), metadata, None);
+
+        assert_eq!(streams.resolve("old_name").as_deref(), Some("old_name"));
+        assert_eq!(streams.resolve("new_name").as_deref(), Some("old_name"));
+        assert!(streams.resolve("unknown").is_none());
+
+        // the alias reads the same stream, along with its data
+        let resolved = streams.resolve("new_name").unwrap();
+        let guard = streams.read().unwrap();
+        assert!(Arc::ptr_eq(&stream, guard.get(&resolved).unwrap()));
+    }
+
+    /// Number of row groups that have to be read to find `value` in `column`,
+    /// row groups whose bloom filter rules the value out are skipped
+    fn row_groups_read_for(parquet: Vec<u8>, column: usize, value: &str) -> usize {
+        use parquet::file::{
+            properties::ReaderProperties,
+            reader::{FileReader, RowGroupReader},
+            serialized_reader::{ReadOptionsBuilder, SerializedFileReader},
+        };
+
+        let options = ReadOptionsBuilder::new()
+            .with_reader_properties(
+                ReaderProperties::builder()
+                    .set_read_bloom_filter(true)
+                    .build(),
+            )
+            .build();
+        let reader =
+            SerializedFileReader::new_with_options(bytes::Bytes::from(parquet), options).unwrap();
+
+        (0..reader.num_row_groups())
+            .filter(|&i| {
+                let row_group = reader.get_row_group(i).unwrap();
+                row_group
+                    .get_column_bloom_filter(column)
+                    .is_none_or(|filter| filter.check(value))
+            })
+            .count()
+    }
+
+    #[test]
+    fn bloom_filter_reduces_row_groups_read_on_point_lookup() {
+        let schema = Arc::new(Schema::new(vec![Field::new("host", DataType::Utf8, false)]));
+        let hosts: Vec<String> = (0..40).map(|i| format!("host-{i}")).collect();
+        let rb =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(hosts))]).unwrap();
+
+        let write = |bloom_filter_columns: Vec<String>| {
+            let options = Arc::new(Options {
+                row_group_size: 10,
+                parquet_bloom_filter_columns: bloom_filter_columns,
+                ..Default::default()
+            });
+            let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+            let props = stream.parquet_writer_props(&schema, None, None);
+
+            let mut buf = vec![];
+            let mut writer = ArrowWriter::try_new(&mut buf, schema.clone(), Some(props)).unwrap();
+            writer.write(&rb).unwrap();
+            writer.close().unwrap();
+            buf
+        };
+
+        let without = write(vec![]);
+        let with = write(vec!["host".to_owned()]);
+
+        assert_eq!(row_groups_read_for(without, 0, "host-5"), 4);
+        assert_eq!(row_groups_read_for(with, 0, "host-5"), 1);
+    }
+
+    #[tokio::test]
+    async fn query_prunes_row_groups_by_bloom_filter_on_equality() {
+        use datafusion::{
+            physical_plan::{collect, ExecutionPlan},
+            prelude::ParquetReadOptions,
+        };
+
+        use crate::query::QUERY_SESSION;
+
+        fn metric(plan: &Arc<dyn ExecutionPlan>, name: &str) -> usize {
+            plan.metrics()
+                .and_then(|metrics| metrics.sum_by_name(name))
+                .map_or(0, |value| value.as_usize())
+                + plan
+                    .children()
+                    .into_iter()
+                    .map(|child| metric(child, name))
+                    .sum::<usize>()
+        }
+
+        let schema = Arc::new(Schema::new(vec![Field::new("host", DataType::Utf8, false)]));
+        // every row group spans the same min and max, so statistics can't prune any of them
+        let hosts: Vec<String> = (0..40)
+            .map(|i| match i % 10 {
+                0 => "a".to_owned(),
+                9 => "z".to_owned(),
+                _ => format!("host-{i}"),
+            })
+            .collect();
+        let rb =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(hosts))]).unwrap();
+        let options = Arc::new(Options {
+            row_group_size: 10,
+            parquet_bloom_filter_columns: vec!["host".to_owned()],
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "test_stream", LogStreamMetadata::default(), None);
+        let props = stream.parquet_writer_props(&schema, None, None);
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("host.data.parquet");
+        let mut writer =
+            ArrowWriter::try_new(File::create(&path).unwrap(), schema.clone(), Some(props))
+                .unwrap();
+        writer.write(&rb).unwrap();
+        writer.close().unwrap();
+
+        // queried with the configuration of the query session
+        let ctx = SessionContext::new_with_config(QUERY_SESSION.state().config().clone());
+        ctx.register_parquet(
+            "logs",
+            path.to_str().unwrap(),
+            ParquetReadOptions::default(),
+        )
+        .await
+        .unwrap();
+        let plan = ctx
+            .sql("select * from logs where host = 'host-5'")
+            .await
+            .unwrap()
+            .create_physical_plan()
+            .await
+            .unwrap();
+        let batches = collect(plan.clone(), ctx.task_ctx()).await.unwrap();
+
+        assert_eq!(batches.iter().map(|rb| rb.num_rows()).sum::<usize>(), 1);
+        assert_eq!(metric(&plan, "row_groups_pruned_statistics"), 0);
+        assert_eq!(metric(&plan, "row_groups_pruned_bloom_filter"), 3);
+    }
+
+    #[test]
+    fn large_batch_is_written_in_chunks_with_small_buffer() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("msg", DataType::Utf8, false),
+        ]));
+        let ids: Vec<i32> = (0..100_000).collect();
+        let msgs: Vec<String> = ids
+            .iter()
+            .map(|id| format!("message number {id}"))
+            .collect();
+        let rb = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(ids)),
+                Arc::new(StringArray::from(msgs)),
+            ],
+        )
+        .unwrap();
+
+        let options = Arc::new(Options {
+            row_group_size: 1_000_000,
+            parquet_write_batch_size: 1000,
+            parquet_max_buffer_size: Some(64 * 1024),
+            ..Default::default()
+        });
+        let stream = Stream::new(
+            options.clone(),
+            "test_stream",
+            LogStreamMetadata::default(),
+            None,
+        );
+        let props = stream.parquet_writer_props(&schema, None, None);
+
+        let mut buf = vec![];
+        let mut writer = ArrowWriter::try_new(&mut buf, schema.clone(), Some(props)).unwrap();
+        write_buffered(
+            &mut writer,
+            &rb,
+            options.parquet_write_batch_size,
+            options.parquet_max_buffer_size,
+        )
+        .unwrap();
+        writer.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(buf)).unwrap();
+        // the row group is flushed well before reaching the row group size
+        assert!(reader.metadata().num_row_groups() > 1);
+        let batches: Vec<RecordBatch> = reader.build().unwrap().collect::<Result<_, _>>().unwrap();
+        let read = arrow::compute::concat_batches(&schema, &batches).unwrap();
+        assert_eq!(read, rb);
+    }
+
+    #[test]
+    fn failed_parquet_write_leaves_no_partial_file() {
+        let dir = TempDir::new().unwrap();
+        let parquet_path = dir
+            .path()
+            .join("abc.date=2025-01-01.hour=10.minute=00.host.data.parquet");
+
+        let result = write_parquet_atomically(&parquet_path, |file| {
+            file.write_all(b"PAR1 row groups without a footer")?;
+            // closing the writer fails to flush the footer, as when the disk is full
+            Err(std::io::Error::other("No space left on device").into())
+        });
+
+        assert!(result.is_err());
+        assert!(!parquet_path.exists());
+        assert_eq!(dir.path().read_dir().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn stale_part_file_is_overwritten() {
+        let dir = TempDir::new().unwrap();
+        let parquet_path = dir
+            .path()
+            .join("abc.date=2025-01-01.hour=10.minute=00.host.data.parquet");
+        std::fs::write(
+            parquet_path.with_extension("part"),
+            b"left behind by a crash",
+        )
+        .unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let rb = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1, 2]))])
+            .unwrap();
+        let written = write_parquet_atomically(&parquet_path, |file| {
+            let mut writer = ArrowWriter::try_new(file, schema, None)?;
+            writer.write(&rb)?;
+            writer.close()?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(written);
+        assert!(!parquet_path.with_extension("part").exists());
+        let reader = parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(
+            File::open(&parquet_path).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+    }
+
+    #[tokio::test]
+    async fn missing_sequence_number_is_detected() {
+        let temp = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp.path().to_path_buf(),
+            ..Default::default()
+        });
+        let stream = Stream::new(options, "seq_stream", LogStreamMetadata::default(), None);
+
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, true)]));
+        let batches = [vec![1, 2, 3], vec![4, 5], vec![6, 7, 8]]
+            .into_iter()
+            .map(|ids| {
+                let rb =
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(ids))])
+                        .unwrap();
+                stream.assign_sequence(&rb, || 100).unwrap()
+            })
+            .collect_vec();
+        // the event numbered 104 never made it
+        let lost = UInt32Array::from(vec![0]);
+        let batches = vec![
+            batches[0].clone(),
+            take_record_batch(&batches[1], &lost).unwrap(),
+            batches[2].clone(),
+        ];
+
+        let ctx = datafusion::prelude::SessionContext::new();
+        let table =
+            datafusion::datasource::MemTable::try_new(batches[0].schema(), vec![batches]).unwrap();
+        ctx.register_table("seq_stream", Arc::new(table)).unwrap();
+        let gaps = ctx
+            .sql(
+                "SELECT prev + 1 AS missing FROM (
+                    SELECT p_sequence, LAG(p_sequence) OVER (ORDER BY p_sequence) AS prev
+                    FROM seq_stream
+                ) WHERE p_sequence - prev > 1",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let missing = gaps[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert_eq!(missing.values(), &[104]);
+    }
+
+    #[test]
+    fn sequence_continues_after_restart_in_a_range_of_each_node() {
+        let temp = TempDir::new().unwrap();
+        let options = Arc::new(Options {
+            local_staging_path: temp.path().to_path_buf(),
+            ..Default::default()
+        });
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, true)]));
+        let rb =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1, 2, 3]))]).unwrap();
+        let sequence = |rb: &RecordBatch| {
+            rb.column_by_name(SEQUENCE_KEY)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap()
+                .values()
+                .to_vec()
+        };
+
+        let stream = Stream::new(
+            options.clone(),
+            "seq_restart",
+            LogStreamMetadata::default(),
+            None,
+        );
+        let first = stream
+            .assign_sequence(&rb, || stream.sequence_seed())
+            .unwrap();
+        assert_eq!(sequence(&first), [0, 1, 2]);
+
+        // the number to continue from is read back from staging
+        let stream = Stream::new(
+            options.clone(),
+            "seq_restart",
+            LogStreamMetadata::default(),
+            None,
+        );
+        let next = stream
+            .assign_sequence(&rb, || stream.sequence_seed())
+            .unwrap();
+        assert_eq!(sequence(&next), [3, 4, 5]);
+
+        // ingestors number from ranges that don't overlap
+        let seeds: HashSet<u64> = ["ingestor-a", "ingestor-b"]
+            .into_iter()
+            .map(|id| {
+                Stream::new(
+                    options.clone(),
+                    &format!("seq_{id}"),
+                    LogStreamMetadata::default(),
+                    Some(id.to_owned()),
+                )
+                .sequence_seed()
+            })
+            .collect();
+        assert_eq!(seeds.len(), 2);
+    }
 }
@@ -0,0 +1,278 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{collections::HashMap, mem::discriminant};
+
+use arrow_schema::{DataType, Field};
+use chrono::NaiveDate;
+use relative_path::RelativePathBuf;
+use tracing::warn;
+
+use crate::{
+    catalog::{
+        column::{Column, TypedStatistics},
+        deletion::RangeFilter,
+        manifest::Manifest,
+    },
+    parseable::PARSEABLE,
+    storage::ObjectStorageError,
+    utils::time::TimeRange,
+};
+
+/// Largest number of distinct values a column can have, as per its min/max statistics
+pub fn bound_from_stats(stats: &TypedStatistics) -> Option<u64> {
+    match stats {
+        TypedStatistics::Bool(stats) => Some(if stats.min == stats.max { 1 } else { 2 }),
+        TypedStatistics::Int(stats) => {
+            u64::try_from(stats.max as i128 - stats.min as i128 + 1).ok()
+        }
+        TypedStatistics::Float(stats) => (stats.min == stats.max).then_some(1),
+        TypedStatistics::String(stats) => (stats.min == stats.max).then_some(1),
+    }
+}
+
+/// Merges the statistics of the columns of a file into `merged`, columns whose statistics
+/// are missing or of mismatched types in some file are set to `None`
+pub fn merge_column_stats(
+    merged: &mut HashMap<String, Option<TypedStatistics>>,
+    columns: &[Column],
+) {
+    for column in columns {
+        let stats = column.stats.clone();
+        merged
+            .entry(column.name.clone())
+            .and_modify(|merged| {
+                *merged = match (merged.take(), stats.clone()) {
+                    (Some(this), Some(other)) if discriminant(&this) == discriminant(&other) => {
+                        Some(this.update(other))
+                    }
+                    _ => None,
+                }
+            })
+            .or_insert(stats);
+    }
+}
+
+/// Bounds on the cardinality of columns, from the statistics of the files of the stream that
+/// are within the time range. Columns without usable statistics are left out
+pub async fn column_bounds(
+    stream_name: &str,
+    time_range: &TimeRange,
+) -> Result<HashMap<String, u64>, ObjectStorageError> {
+    let range = RangeFilter::new(stream_name, time_range.clone());
+    let (start_date, end_date) = range.partition_dates();
+
+    let object_store = PARSEABLE.storage.get_object_store();
+    let mut merged = HashMap::new();
+    for (str_date, manifest_files) in object_store.list_manifest_files(stream_name).await? {
+        let Ok(date) = NaiveDate::parse_from_str(str_date.trim_start_matches("date="), "%Y-%m-%d")
+        else {
+            warn!("Invalid date format: {}", str_date);
+            continue;
+        };
+        if date < start_date || date > end_date {
+            continue;
+        }
+
+        for manifest_file in manifest_files {
+            let manifest_path = RelativePathBuf::from(manifest_file);
+            let manifest: Manifest =
+                serde_json::from_slice(&object_store.get_object(&manifest_path).await?)?;
+            for file in manifest
+                .files
+                .iter()
+                .filter(|file| range.contains(&file.file_path))
+            {
+                merge_column_stats(&mut merged, &file.columns);
+            }
+        }
+    }
+
+    Ok(merged
+        .into_iter()
+        .filter_map(|(name, stats)| Some((name, bound_from_stats(&stats?)?)))
+        .collect())
+}
+
+/// Expression estimating the number of distinct values of the column, with a HyperLogLog sketch
+/// where the type allows for one. `None` for nested types that can't be counted
+fn distinct_expr(field: &Field) -> Option<String> {
+    let column = format!("\"{}\"", field.name().replace('"', "\"\""));
+    let expr = match field.data_type() {
+        // only ever two values, an exact count is as cheap
+        DataType::Boolean => format!("CAST(COUNT(DISTINCT {column}) AS BIGINT UNSIGNED)"),
+        DataType::Float16 | DataType::Float32 | DataType::Float64 | DataType::Decimal128(..) => {
+            format!("approx_distinct(CAST({column} AS VARCHAR))")
+        }
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Utf8
+        | DataType::LargeUtf8
+        | DataType::Utf8View
+        | DataType::Binary
+        | DataType::LargeBinary
+        | DataType::Date32
+        | DataType::Date64
+        | DataType::Time32(_)
+        | DataType::Time64(_)
+        | DataType::Timestamp(..) => format!("approx_distinct({column})"),
+        _ => return None,
+    };
+
+    Some(format!("{expr} AS {column}"))
+}
+
+/// Query estimating the cardinality of each of the `fields` in the stream, every column of the
+/// result is the estimate for the field of the same name, as a `UInt64`
+pub fn cardinality_sql<'a>(
+    stream_name: &str,
+    fields: impl IntoIterator<Item = &'a Field>,
+) -> Option<String> {
+    let exprs = fields
+        .into_iter()
+        .filter_map(distinct_expr)
+        .collect::<Vec<_>>();
+    if exprs.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "SELECT {} FROM \"{stream_name}\"",
+        exprs.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{BooleanArray, Int64Array, RecordBatch, StringArray, UInt64Array};
+    use arrow_schema::Schema;
+    use datafusion::{datasource::MemTable, prelude::SessionContext};
+
+    use crate::catalog::column::{BoolType, Int64Type, Utf8Type};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn low_cardinality_column_is_estimated_closely() {
+        let levels = ["trace", "debug", "info", "warn", "error"];
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("level", DataType::Utf8, true),
+            Field::new("id", DataType::Int64, true),
+            Field::new("ok", DataType::Boolean, true),
+        ]));
+        let rb = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    (0..10_000).map(|i| levels[i % levels.len()]),
+                )),
+                Arc::new(Int64Array::from_iter_values(0..10_000)),
+                Arc::new(BooleanArray::from_iter(
+                    (0..10_000).map(|i| Some(i % 2 == 0)),
+                )),
+            ],
+        )
+        .unwrap();
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "app",
+            Arc::new(MemTable::try_new(schema.clone(), vec![vec![rb]]).unwrap()),
+        )
+        .unwrap();
+
+        let sql = cardinality_sql("app", schema.fields().iter().map(|f| f.as_ref())).unwrap();
+        let batches = ctx.sql(&sql).await.unwrap().collect().await.unwrap();
+        let estimate = |name: &str| {
+            batches[0]
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap()
+                .value(0)
+        };
+
+        assert!(estimate("level").abs_diff(5) <= 1);
+        assert_eq!(estimate("ok"), 2);
+        // a sketch of high cardinality columns is within a few percent
+        assert!(estimate("id").abs_diff(10_000) < 500);
+    }
+
+    #[test]
+    fn stats_bound_cardinality() {
+        let mut merged = HashMap::new();
+        let column = |name: &str, stats| Column {
+            name: name.to_owned(),
+            stats: Some(stats),
+            uncompressed_size: 0,
+            compressed_size: 0,
+        };
+        merge_column_stats(
+            &mut merged,
+            &[
+                column(
+                    "status",
+                    TypedStatistics::Int(Int64Type { min: 200, max: 204 }),
+                ),
+                column(
+                    "ok",
+                    TypedStatistics::Bool(BoolType {
+                        min: true,
+                        max: true,
+                    }),
+                ),
+            ],
+        );
+        merge_column_stats(
+            &mut merged,
+            &[
+                column(
+                    "status",
+                    TypedStatistics::Int(Int64Type { min: 201, max: 209 }),
+                ),
+                column(
+                    "ok",
+                    TypedStatistics::Bool(BoolType {
+                        min: true,
+                        max: true,
+                    }),
+                ),
+                column(
+                    "host",
+                    TypedStatistics::String(Utf8Type {
+                        min: "a".to_owned(),
+                        max: "b".to_owned(),
+                    }),
+                ),
+            ],
+        );
+
+        let bound = |name: &str| bound_from_stats(merged[name].as_ref().unwrap());
+        assert_eq!(bound("status"), Some(10));
+        assert_eq!(bound("ok"), Some(1));
+        assert_eq!(bound("host"), None);
+    }
+}
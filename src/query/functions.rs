@@ -0,0 +1,442 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    any::Any,
+    sync::{Arc, RwLock},
+};
+
+use arrow::compute::cast;
+use arrow_array::{cast::AsArray, Array, ArrayRef, StringArray};
+use arrow_schema::{DataType, Field};
+use datafusion::{
+    error::{DataFusionError, Result},
+    functions_aggregate::approx_percentile_cont::{
+        approx_percentile_cont_udaf, ApproxPercentileAccumulator,
+    },
+    logical_expr::{
+        function::{AccumulatorArgs, StateFieldsArgs},
+        Accumulator, AggregateUDF, AggregateUDFImpl, ColumnarValue, ScalarUDF, ScalarUDFImpl,
+        Signature, Volatility,
+    },
+};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+/// Functions registered with [`register_udf`], in addition to those Parseable provides
+static REGISTERED_UDFS: Lazy<RwLock<Vec<ScalarUDF>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Registers the functions Parseable provides on top of those of DataFusion
+pub fn udfs() -> Vec<ScalarUDF> {
+    let mut udfs = vec![
+        ScalarUDF::from(JsonExtract::new()),
+        ScalarUDF::from(ParseUserAgent::new()),
+    ];
+    udfs.extend(
+        REGISTERED_UDFS
+            .read()
+            .expect("registered functions lock shouldn't be poisoned")
+            .iter()
+            .cloned(),
+    );
+    udfs
+}
+
+/// Makes a custom scalar function callable in queries, it must be registered at startup,
+/// before the session queries are run in is created. Replaces a function by the same name.
+pub fn register_udf(udf: ScalarUDF) {
+    let mut registered = REGISTERED_UDFS
+        .write()
+        .expect("registered functions lock shouldn't be poisoned");
+    registered.retain(|registered| registered.name() != udf.name());
+    registered.push(udf);
+}
+
+/// Registers the aggregates Parseable provides on top of those of DataFusion
+pub fn udafs() -> Vec<AggregateUDF> {
+    [50, 95, 99]
+        .into_iter()
+        .map(|percentile| AggregateUDF::from(ApproxPercentile::new(percentile)))
+        .collect()
+}
+
+/// `json_extract(column, path)` extracts the value at `path` from JSON stored in a string column,
+/// e.g. `json_extract(payload, '$.user.id')`. Array elements are addressed by their index, as in
+/// `$.items.0.name`. Strings are returned as is, other values as JSON and missing values as null.
+#[derive(Debug)]
+pub struct JsonExtract {
+    signature: Signature,
+}
+
+impl JsonExtract {
+    pub fn new() -> Self {
+        Self {
+            signature: Signature::string(2, Volatility::Immutable),
+        }
+    }
+}
+
+impl Default for JsonExtract {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScalarUDFImpl for JsonExtract {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "json_extract"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke_batch(&self, args: &[ColumnarValue], _number_rows: usize) -> Result<ColumnarValue> {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let json = cast(&args[0], &DataType::Utf8)?;
+        let paths = cast(&args[1], &DataType::Utf8)?;
+        let (json, paths) = (json.as_string::<i32>(), paths.as_string::<i32>());
+
+        let extracted: StringArray = (0..json.len())
+            .map(|row| {
+                if json.is_null(row) || paths.is_null(row) {
+                    return None;
+                }
+                extract(json.value(row), paths.value(row))
+            })
+            .collect();
+
+        Ok(ColumnarValue::Array(Arc::new(extracted) as ArrayRef))
+    }
+}
+
+/// `parse_user_agent(column)` parses a `User-Agent` header into JSON with the `browser`, its
+/// `version` and the `os` it runs on, e.g. `{"browser":"Firefox","version":"121.0","os":"Linux"}`,
+/// so that parts of it can be picked with `json_extract`. Unrecognized parts are null.
+#[derive(Debug)]
+pub struct ParseUserAgent {
+    signature: Signature,
+}
+
+impl ParseUserAgent {
+    pub fn new() -> Self {
+        Self {
+            signature: Signature::string(1, Volatility::Immutable),
+        }
+    }
+}
+
+impl Default for ParseUserAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScalarUDFImpl for ParseUserAgent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "parse_user_agent"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke_batch(&self, args: &[ColumnarValue], _number_rows: usize) -> Result<ColumnarValue> {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let user_agents = cast(&args[0], &DataType::Utf8)?;
+        let parsed: StringArray = user_agents
+            .as_string::<i32>()
+            .iter()
+            .map(|user_agent| user_agent.map(parse_user_agent))
+            .collect();
+
+        Ok(ColumnarValue::Array(Arc::new(parsed) as ArrayRef))
+    }
+}
+
+/// Browsers in the order they are looked for, as the user agents of most also mention the ones
+/// they are derived from, e.g. Edge mentions Chrome and Safari
+const BROWSERS: [(&str, &str); 6] = [
+    ("Edg/", "Edge"),
+    ("OPR/", "Opera"),
+    ("Firefox/", "Firefox"),
+    ("Chrome/", "Chrome"),
+    ("Version/", "Safari"),
+    ("curl/", "curl"),
+];
+
+const OPERATING_SYSTEMS: [(&str, &str); 6] = [
+    ("Windows", "Windows"),
+    ("Android", "Android"),
+    ("iPhone", "iOS"),
+    ("iPad", "iOS"),
+    ("Mac OS X", "macOS"),
+    ("Linux", "Linux"),
+];
+
+fn parse_user_agent(user_agent: &str) -> String {
+    let (browser, version) = BROWSERS
+        .iter()
+        .find_map(|(marker, browser)| {
+            let (_, rest) = user_agent.split_once(marker)?;
+            let version = rest.split([' ', ';', ')']).next().unwrap_or_default();
+            Some((Some(*browser), Some(version)))
+        })
+        .unwrap_or_default();
+    let os = OPERATING_SYSTEMS
+        .iter()
+        .find(|(marker, _)| user_agent.contains(marker))
+        .map(|(_, os)| *os);
+
+    json!({"browser": browser, "version": version, "os": os}).to_string()
+}
+
+/// `p50(column)`, `p95(column)` and `p99(column)` are shorthands for
+/// `approx_percentile_cont(column, 0.95)` and friends, estimating the percentile of a numeric
+/// column from a t-digest rather than sorting all of its values.
+#[derive(Debug)]
+pub struct ApproxPercentile {
+    name: String,
+    percentile: f64,
+    signature: Signature,
+}
+
+impl ApproxPercentile {
+    pub fn new(percentile: u8) -> Self {
+        Self {
+            name: format!("p{percentile}"),
+            percentile: percentile as f64 / 100.0,
+            signature: Signature::numeric(1, Volatility::Immutable),
+        }
+    }
+}
+
+impl AggregateUDFImpl for ApproxPercentile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        if !arg_types[0].is_numeric() {
+            return Err(DataFusionError::Plan(format!(
+                "{} requires a numeric column",
+                self.name
+            )));
+        }
+        Ok(arg_types[0].clone())
+    }
+
+    // the digest is shared with approx_percentile_cont, so partial states merge the same way
+    fn state_fields(&self, args: StateFieldsArgs) -> Result<Vec<Field>> {
+        approx_percentile_cont_udaf().state_fields(args)
+    }
+
+    fn accumulator(&self, args: AccumulatorArgs) -> Result<Box<dyn Accumulator>> {
+        Ok(Box::new(ApproxPercentileAccumulator::new(
+            self.percentile,
+            args.return_type.clone(),
+        )))
+    }
+}
+
+/// Extracts the value at `path` from `json`, `None` if either isn't valid or the path is missing
+fn extract(json: &str, path: &str) -> Option<String> {
+    let mut value = &serde_json::from_str::<Value>(json).ok()?;
+    let path = path.strip_prefix('$').unwrap_or(path);
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        value = match value {
+            Value::Object(map) => map.get(key)?,
+            Value::Array(arr) => arr.get(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.to_owned()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{cast::AsArray, types::Float64Type, Float64Array, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::{
+        datasource::MemTable,
+        error::Result,
+        logical_expr::{create_udf, ColumnarValue, Volatility},
+        prelude::SessionContext,
+    };
+
+    use super::{register_udf, udafs, udfs};
+
+    #[tokio::test]
+    async fn nested_value_is_extracted_from_json_column() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "payload",
+            DataType::Utf8,
+            true,
+        )]));
+        let payloads = StringArray::from(vec![
+            Some(r#"{"user": {"id": 7, "tags": ["a", "b"]}}"#),
+            Some(r#"{"user": {"name": "x"}}"#),
+            Some("not json"),
+            None,
+        ]);
+        let rb = RecordBatch::try_new(schema.clone(), vec![Arc::new(payloads)]).unwrap();
+        let ctx = SessionContext::new();
+        udfs().into_iter().for_each(|udf| ctx.register_udf(udf));
+        ctx.register_table(
+            "t",
+            Arc::new(MemTable::try_new(schema, vec![vec![rb]]).unwrap()),
+        )
+        .unwrap();
+
+        let records = ctx
+            .sql("SELECT json_extract(payload, '$.user.id') AS id, json_extract(payload, 'user.tags.1') AS tag FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = records[0].column(0).as_string::<i32>().iter().collect();
+        assert_eq!(ids, vec![Some("7"), None, None, None]);
+        let tags: Vec<_> = records[0].column(1).as_string::<i32>().iter().collect();
+        assert_eq!(tags, vec![Some("b"), None, None, None]);
+    }
+
+    #[tokio::test]
+    async fn registered_udf_is_callable_in_queries() {
+        let shout = create_udf(
+            "shout",
+            vec![DataType::Utf8],
+            DataType::Utf8,
+            Volatility::Immutable,
+            Arc::new(|args: &[ColumnarValue]| -> Result<ColumnarValue> {
+                let args = ColumnarValue::values_to_arrays(args)?;
+                let shouted: StringArray = args[0]
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|value| value.map(str::to_uppercase))
+                    .collect();
+                Ok(ColumnarValue::Array(Arc::new(shouted)))
+            }),
+        );
+        register_udf(shout);
+
+        let schema = Arc::new(Schema::new(vec![Field::new("ua", DataType::Utf8, true)]));
+        let user_agents = StringArray::from(vec![
+            Some("Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0"),
+            Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.2210.91"),
+            None,
+        ]);
+        let rb = RecordBatch::try_new(schema.clone(), vec![Arc::new(user_agents)]).unwrap();
+        let ctx = SessionContext::new();
+        udfs().into_iter().for_each(|udf| ctx.register_udf(udf));
+        ctx.register_table(
+            "t",
+            Arc::new(MemTable::try_new(schema, vec![vec![rb]]).unwrap()),
+        )
+        .unwrap();
+
+        let records = ctx
+            .sql("SELECT shout(json_extract(parse_user_agent(ua), '$.browser')) AS browser, json_extract(parse_user_agent(ua), '$.os') AS os FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let browsers: Vec<_> = records[0].column(0).as_string::<i32>().iter().collect();
+        assert_eq!(browsers, vec![Some("FIREFOX"), Some("EDGE"), None]);
+        let os: Vec<_> = records[0].column(1).as_string::<i32>().iter().collect();
+        assert_eq!(os, vec![Some("Linux"), Some("Windows"), None]);
+    }
+
+    #[tokio::test]
+    async fn percentiles_are_approximated_within_tolerance() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "latency",
+            DataType::Float64,
+            false,
+        )]));
+        // uniformly spread over 1..=10000, split across batches so partial digests get merged
+        let batches = (0..10)
+            .map(|batch| {
+                let values =
+                    Float64Array::from_iter_values((1..=1000).map(|v| (batch * 1000 + v) as f64));
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(values)]).unwrap()
+            })
+            .map(|rb| vec![rb])
+            .collect();
+        let ctx = SessionContext::new();
+        udafs().into_iter().for_each(|udaf| ctx.register_udaf(udaf));
+        ctx.register_table("t", Arc::new(MemTable::try_new(schema, batches).unwrap()))
+            .unwrap();
+
+        let records = ctx
+            .sql("SELECT p50(latency), p95(latency), p99(latency), approx_percentile_cont(latency, 0.95) FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let value = |column: usize| {
+            records[0]
+                .column(column)
+                .as_primitive::<Float64Type>()
+                .value(0)
+        };
+        for (column, expected) in [(0, 5000.0), (1, 9500.0), (2, 9900.0), (3, 9500.0)] {
+            let estimate = value(column);
+            assert!(
+                (estimate - expected).abs() <= 100.0,
+                "estimated {estimate}, expected {expected}"
+            );
+        }
+    }
+}
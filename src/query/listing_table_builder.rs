@@ -27,16 +27,19 @@ use datafusion::{
     error::DataFusionError,
     logical_expr::col,
 };
-use futures_util::{stream::FuturesUnordered, Future, TryStreamExt};
+use futures_util::{Future, TryStreamExt};
 use itertools::Itertools;
 use object_store::{path::Path, ObjectMeta, ObjectStore};
 
 use crate::{
-    event::DEFAULT_TIMESTAMP_KEY, storage::ObjectStorage, utils::time::TimeRange,
+    event::DEFAULT_TIMESTAMP_KEY,
+    parseable::PARSEABLE,
+    storage::{stream_data_root, ObjectStorage},
+    utils::time::TimeRange,
     OBJECT_STORE_DATA_GRANULARITY,
 };
 
-use super::PartialTimeFilter;
+use super::{stream_schema_provider::fetch_concurrently, PartialTimeFilter};
 
 // Listing Table Builder for querying old data
 #[derive(Debug, Default)]
@@ -86,15 +89,26 @@ impl ListingTableBuilder {
             ));
         };
 
-        // Generate prefixes for the given time range
+        // Generate prefixes for the given time range, in the timezone the partitions of the stream
+        // are named in, bounded to avoid flooding the object store with list calls
+        let settings = PARSEABLE
+            .get_stream(&self.stream)
+            .map(|stream| stream.get_settings())
+            .unwrap_or_default();
         let prefixes = TimeRange::new(start_time.and_utc(), end_time.and_utc())
-            .generate_prefixes(OBJECT_STORE_DATA_GRANULARITY);
+            .in_partition_time(settings.partition_timezone())
+            .generate_prefixes_bounded(
+                OBJECT_STORE_DATA_GRANULARITY,
+                PARSEABLE.options.max_query_prefixes,
+            )
+            .map_err(|err| DataFusionError::Plan(err.to_string()))?;
 
         // Categorizes prefixes into "minute" and general resolve lists.
         let mut minute_resolve = HashMap::<String, Vec<String>>::new();
         let mut all_resolve = Vec::new();
+        let data_root = stream_data_root(&self.stream, settings.storage_prefix.as_deref());
         for prefix in prefixes {
-            let path = relative_path::RelativePathBuf::from(format!("{}/{}", &self.stream, prefix));
+            let path = data_root.join(prefix);
             let prefix = storage.absolute_url(path.as_relative_path()).to_string();
             if let Some(pos) = prefix.rfind("minute") {
                 let hour_prefix = &prefix[..pos];
@@ -110,7 +124,7 @@ impl ListingTableBuilder {
         /// Resolve all prefixes asynchronously and collect the object metadata.
         type ResolveFuture =
             Pin<Box<dyn Future<Output = Result<Vec<ObjectMeta>, object_store::Error>> + Send>>;
-        let tasks: FuturesUnordered<ResolveFuture> = FuturesUnordered::new();
+        let mut tasks: Vec<ResolveFuture> = Vec::new();
         for (listing_prefix, prefixes) in minute_resolve {
             let client = Arc::clone(&client);
             tasks.push(Box::pin(async move {
@@ -138,7 +152,7 @@ impl ListingTableBuilder {
             }));
         }
 
-        let listing = tasks
+        let listing = fetch_concurrently(tasks, PARSEABLE.options.query_fetch_concurrency)
             .try_collect::<Vec<Vec<ObjectMeta>>>()
             .await
             .map_err(|err| DataFusionError::External(Box::new(err)))?
@@ -16,39 +16,54 @@
  *
  */
 
+pub mod cardinality;
 mod filter_optimizer;
+pub mod functions;
 mod listing_table_builder;
+pub mod plan_cache;
 pub mod stream_schema_provider;
 
 use actix_web::Either;
+use arrow_schema::Schema;
 use chrono::NaiveDateTime;
 use chrono::{DateTime, Duration, Utc};
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::common::tree_node::{Transformed, TreeNode, TreeNodeRecursion, TreeNodeVisitor};
+use datafusion::datasource::file_format::parquet::fetch_parquet_metadata;
+use datafusion::datasource::physical_plan::ParquetExec;
 use datafusion::error::DataFusionError;
 use datafusion::execution::disk_manager::DiskManagerConfig;
 use datafusion::execution::{SendableRecordBatchStream, SessionStateBuilder};
 use datafusion::logical_expr::expr::Alias;
 use datafusion::logical_expr::{
-    Aggregate, Explain, Filter, LogicalPlan, PlanType, Projection, ToStringifiedPlan,
+    Aggregate, Explain, Filter, LogicalPlan, PlanType, Projection, Sort, ToStringifiedPlan,
 };
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::ExecutionPlan;
 use datafusion::prelude::*;
+use futures::StreamExt;
 use itertools::Itertools;
+use object_store::ObjectMeta;
 use once_cell::sync::Lazy;
+use parquet::arrow::parquet_to_arrow_schema;
 use relative_path::RelativePathBuf;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::future::Future;
 use std::ops::Bound;
 use std::sync::Arc;
-use stream_schema_provider::collect_manifest_files;
+use stream_schema_provider::{collect_manifest_files, superset_schema};
 use sysinfo::System;
 use tokio::runtime::Runtime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use self::error::ExecuteError;
 use self::stream_schema_provider::GlobalSchemaProvider;
 pub use self::stream_schema_provider::PartialTimeFilter;
 use crate::catalog::column::{Int64Type, TypedStatistics};
-use crate::catalog::manifest::Manifest;
+use crate::catalog::compaction::relative_path;
+use crate::catalog::manifest::{File, Manifest};
 use crate::catalog::snapshot::Snapshot;
 use crate::catalog::Snapshot as CatalogSnapshot;
 use crate::event;
@@ -56,7 +71,7 @@ use crate::handlers::http::query::QueryError;
 use crate::option::Mode;
 use crate::parseable::PARSEABLE;
 use crate::storage::{ObjectStorageProvider, ObjectStoreFormat, STREAM_ROOT_DIRECTORY};
-use crate::utils::time::TimeRange;
+use crate::utils::time::{from_timestamp, partition_time_range, timestamp_unit, TimeRange};
 
 pub static QUERY_SESSION: Lazy<SessionContext> =
     Lazy::new(|| Query::create_session_context(PARSEABLE.storage()));
@@ -65,6 +80,10 @@ pub static QUERY_SESSION: Lazy<SessionContext> =
 pub static QUERY_RUNTIME: Lazy<Runtime> =
     Lazy::new(|| Runtime::new().expect("Runtime should be constructible"));
 
+/// Limits the number of queries executing at a time, as configured with `P_MAX_CONCURRENT_QUERIES`
+static QUERY_LIMITER: Lazy<Option<Arc<Semaphore>>> =
+    Lazy::new(|| query_limiter(PARSEABLE.options.max_concurrent_queries));
+
 /// This function executes a query on the dedicated runtime, ensuring that the query is not isolated to a single thread/CPU
 /// at a time and has access to the entire thread pool, enabling better concurrent processing, and thus quicker results.
 pub async fn execute(
@@ -79,10 +98,100 @@ pub async fn execute(
     ExecuteError,
 > {
     let time_partition = PARSEABLE.get_stream(stream_name)?.get_time_partition();
-    QUERY_RUNTIME
-        .spawn(async move { query.execute(time_partition.as_ref(), is_streaming).await })
+    let timeout = PARSEABLE.options.query_timeout;
+    let started = tokio::time::Instant::now();
+    let permit = acquire_query_permit(QUERY_LIMITER.as_ref())?;
+    let (records, fields) = QUERY_RUNTIME
+        .spawn(async move {
+            with_query_timeout(
+                timeout,
+                query.execute(time_partition.as_ref(), is_streaming),
+            )
+            .await
+        })
+        .await
+        .expect("The Join should have been successful")?;
+
+    let records = match records {
+        Either::Right(stream) => Either::Right(bound_stream(
+            stream,
+            timeout.map(|timeout| (started + timeout, timeout)),
+            permit,
+        )),
+        records => records,
+    };
+
+    Ok((records, fields))
+}
+
+/// A streamed query keeps executing while the response is being sent, the stream is hence
+/// bounded by what remains of the query timeout, ending with an error on the `deadline`, and
+/// holds on to the `permit` of the query until it ends.
+fn bound_stream(
+    stream: SendableRecordBatchStream,
+    deadline: Option<(tokio::time::Instant, std::time::Duration)>,
+    permit: Option<OwnedSemaphorePermit>,
+) -> SendableRecordBatchStream {
+    let schema = stream.schema();
+    let stream = futures::stream::unfold(Some(stream), move |stream| {
+        let _permit = &permit;
+        async move {
+            let mut stream = stream?;
+            let next = match deadline {
+                Some((deadline, timeout)) => {
+                    match tokio::time::timeout_at(deadline, stream.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            let err = ExecuteError::QueryTimeout(timeout).to_string();
+                            return Some((Err(DataFusionError::Execution(err)), None));
+                        }
+                    }
+                }
+                None => stream.next().await,
+            };
+            next.map(|batch| (batch, Some(stream)))
+        }
+    });
+
+    Box::pin(RecordBatchStreamAdapter::new(schema, stream))
+}
+
+/// Semaphore limiting queries to `limit` at a time, no limit is applied when it is unset or 0
+fn query_limiter(limit: Option<usize>) -> Option<Arc<Semaphore>> {
+    limit
+        .filter(|&limit| limit > 0)
+        .map(|limit| Arc::new(Semaphore::new(limit)))
+}
+
+/// Reserves a slot for executing a query with `limiter`, if any. The query is rejected when all
+/// slots are taken by queries that are already executing.
+fn acquire_query_permit(
+    limiter: Option<&Arc<Semaphore>>,
+) -> Result<Option<OwnedSemaphorePermit>, ExecuteError> {
+    let Some(limiter) = limiter else {
+        return Ok(None);
+    };
+
+    limiter
+        .clone()
+        .try_acquire_owned()
+        .map(Some)
+        .map_err(|_| ExecuteError::TooManyQueries)
+}
+
+/// Bounds the execution of a query with `timeout`, if any. On timing out, the future is dropped
+/// along with the execution plan, cancelling all pending work of the query.
+async fn with_query_timeout<T>(
+    timeout: Option<std::time::Duration>,
+    execution: impl Future<Output = Result<T, ExecuteError>>,
+) -> Result<T, ExecuteError> {
+    let Some(timeout) = timeout else {
+        return execution.await;
+    };
+
+    tokio::time::timeout(timeout, execution)
         .await
-        .expect("The Join should have been successful")
+        .map_err(|_| ExecuteError::QueryTimeout(timeout))?
 }
 
 // A query request by client
@@ -129,6 +238,8 @@ impl Query {
         // Reorder filters allows DF to decide the order of filters minimizing the cost of filter evaluation
         config.options_mut().execution.parquet.reorder_filters = true;
         config.options_mut().execution.parquet.binary_as_string = true;
+        // Use bloom filters written for configured columns to prune row groups on equality filters
+        config.options_mut().execution.parquet.bloom_filter_on_read = true;
         config
             .options_mut()
             .execution
@@ -161,7 +272,15 @@ impl Query {
             )
             .unwrap();
 
-        SessionContext::new_with_state(state)
+        let ctx = SessionContext::new_with_state(state);
+        functions::udfs()
+            .into_iter()
+            .for_each(|udf| ctx.register_udf(udf));
+        functions::udafs()
+            .into_iter()
+            .for_each(|udaf| ctx.register_udaf(udaf));
+
+        ctx
     }
 
     /// this function returns the result of the query
@@ -223,34 +342,59 @@ impl Query {
 
         match self.raw_logical_plan.clone() {
             LogicalPlan::Explain(plan) => {
-                let transformed = transform(
-                    plan.plan.as_ref().clone(),
-                    self.time_range.start.naive_utc(),
-                    self.time_range.end.naive_utc(),
-                    time_partition,
+                let transformed = self.with_default_order(
+                    transform(
+                        plan.plan.as_ref().clone(),
+                        self.time_range.start.naive_utc(),
+                        self.time_range.end.naive_utc(),
+                        time_partition,
+                    )
+                    .data,
                 );
                 LogicalPlan::Explain(Explain {
                     verbose: plan.verbose,
-                    stringified_plans: vec![transformed
-                        .data
-                        .to_stringified(PlanType::InitialLogicalPlan)],
-                    plan: Arc::new(transformed.data),
+                    stringified_plans: vec![
+                        transformed.to_stringified(PlanType::InitialLogicalPlan)
+                    ],
+                    plan: Arc::new(transformed),
                     schema: plan.schema,
                     logical_optimization_succeeded: plan.logical_optimization_succeeded,
                 })
             }
-            x => {
+            x => self.with_default_order(
                 transform(
                     x,
                     self.time_range.start.naive_utc(),
                     self.time_range.end.naive_utc(),
                     time_partition,
                 )
-                .data
-            }
+                .data,
+            ),
         }
     }
 
+    fn with_default_order(&self, plan: LogicalPlan) -> LogicalPlan {
+        if PARSEABLE.options.query_order_by_time {
+            order_by_recency(plan, event::DEFAULT_TIMESTAMP_KEY)
+        } else {
+            plan
+        }
+    }
+
+    /// Returns the files scanned by the physical plan of the query
+    pub async fn scanned_files(
+        &self,
+        time_partition: Option<&String>,
+    ) -> Result<Vec<ScannedFile>, ExecuteError> {
+        let plan = self
+            .get_dataframe(time_partition)
+            .await?
+            .create_physical_plan()
+            .await?;
+
+        Ok(plan_files(&plan)?)
+    }
+
     pub fn first_table_name(&self) -> Option<String> {
         let mut visitor = TableScanVisitor::default();
         let _ = self.raw_logical_plan.visit(&mut visitor);
@@ -296,6 +440,41 @@ impl Query {
     }
 }
 
+/// A parquet file scanned by a query
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannedFile {
+    /// Key of the file within its object store, or path on disk for staged files
+    pub path: String,
+    pub size: u64,
+    /// Start of the time range of the partition the file is in
+    pub start_time: Option<String>,
+    /// End of the time range of the partition the file is in
+    pub end_time: Option<String>,
+}
+
+/// Collects the files scanned by the parquet scans of the plan
+fn plan_files(plan: &Arc<dyn ExecutionPlan>) -> Result<Vec<ScannedFile>, DataFusionError> {
+    let mut files = vec![];
+    plan.apply(|node| {
+        if let Some(exec) = node.as_any().downcast_ref::<ParquetExec>() {
+            for file in exec.base_config().file_groups.iter().flatten() {
+                let path = file.object_meta.location.to_string();
+                let time_range = partition_time_range(&path);
+                files.push(ScannedFile {
+                    size: file.object_meta.size as u64,
+                    start_time: time_range.as_ref().map(|range| range.start.to_rfc3339()),
+                    end_time: time_range.map(|range| range.end.to_rfc3339()),
+                    path,
+                });
+            }
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })?;
+
+    Ok(files)
+}
+
 /// Record of counts for a given time bin.
 #[derive(Debug, Serialize, Clone)]
 pub struct CountsRecord {
@@ -307,11 +486,46 @@ pub struct CountsRecord {
     pub count: u64,
 }
 
+/// Record of the volume of data ingested in a given time bin.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct VolumeRecord {
+    /// Start time of the bin
+    pub start_time: String,
+    /// End time of the bin
+    pub end_time: String,
+    /// Uncompressed size in bytes of the data ingested in the bin
+    pub ingestion_size: u64,
+    /// Size in bytes of the parquet files the data ingested in the bin is stored in
+    pub storage_size: u64,
+}
+
 struct TimeBounds {
     start: DateTime<Utc>,
     end: DateTime<Utc>,
 }
 
+impl TimeBounds {
+    /// Files whose earliest event, as per the time partition column, falls within the bin
+    fn files<'a>(
+        &'a self,
+        manifests: &'a [Manifest],
+        time_partition: &'a str,
+    ) -> impl Iterator<Item = &'a File> + 'a {
+        manifests.iter().flat_map(|m| &m.files).filter(move |f| {
+            f.columns.iter().any(|c| {
+                c.name == time_partition
+                    && c.stats.as_ref().is_some_and(|stats| match stats {
+                        TypedStatistics::Int(Int64Type { min, .. }) => {
+                            let min = from_timestamp(*min, timestamp_unit()).unwrap();
+                            self.start <= min && self.end >= min // Determines if a column matches the bin's time range.
+                        }
+                        _ => false,
+                    })
+            })
+        })
+    }
+}
+
 /// Request for counts, received from API/SQL query.
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -331,15 +545,7 @@ impl CountsRequest {
     /// get the sum of `num_rows` between the `startTime` and `endTime`,
     /// divide that by number of bins and return in a manner acceptable for the console
     pub async fn get_bin_density(&self) -> Result<Vec<CountsRecord>, QueryError> {
-        let time_partition = PARSEABLE
-            .get_stream(&self.stream)
-            .map_err(|err| anyhow::Error::msg(err.to_string()))?
-            .get_time_partition()
-            .unwrap_or_else(|| event::DEFAULT_TIMESTAMP_KEY.to_owned());
-
-        // get time range
-        let time_range = TimeRange::parse_human_time(&self.start_time, &self.end_time)?;
-        let all_manifest_files = get_manifest_list(&self.stream, &time_range).await?;
+        let (time_partition, time_range, all_manifest_files) = self.manifests().await?;
         // get bounds
         let counts = self.get_bounds(&time_range);
 
@@ -353,25 +559,9 @@ impl CountsRequest {
         for bin in counts {
             // extract start and end time to compare
             // Sum up the number of rows that fall within the bin
-            let count: u64 = all_manifest_files
-                .iter()
-                .flat_map(|m| &m.files)
-                .filter_map(|f| {
-                    if f.columns.iter().any(|c| {
-                        c.name == time_partition
-                            && c.stats.as_ref().is_some_and(|stats| match stats {
-                                TypedStatistics::Int(Int64Type { min, .. }) => {
-                                    let min = DateTime::from_timestamp_millis(*min).unwrap();
-                                    bin.start <= min && bin.end >= min // Determines if a column matches the bin's time range.
-                                }
-                                _ => false,
-                            })
-                    }) {
-                        Some(f.num_rows)
-                    } else {
-                        None
-                    }
-                })
+            let count: u64 = bin
+                .files(&all_manifest_files, &time_partition)
+                .map(|f| f.num_rows)
                 .sum();
 
             counts_records.push(CountsRecord {
@@ -383,6 +573,54 @@ impl CountsRequest {
         Ok(counts_records)
     }
 
+    /// Like `get_bin_density`, but sums up the ingested and stored bytes of each bin instead
+    pub async fn get_bin_volume(&self) -> Result<Vec<VolumeRecord>, QueryError> {
+        let (time_partition, time_range, all_manifest_files) = self.manifests().await?;
+
+        Ok(self.volume(&time_range, &all_manifest_files, &time_partition))
+    }
+
+    fn volume(
+        &self,
+        time_range: &TimeRange,
+        manifests: &[Manifest],
+        time_partition: &str,
+    ) -> Vec<VolumeRecord> {
+        self.get_bounds(time_range)
+            .into_iter()
+            .map(|bin| {
+                let (ingestion_size, storage_size) = bin
+                    .files(manifests, time_partition)
+                    .fold((0, 0), |(ingested, stored), f| {
+                        (ingested + f.ingestion_size, stored + f.file_size)
+                    });
+
+                VolumeRecord {
+                    start_time: bin.start.to_rfc3339(),
+                    end_time: bin.end.to_rfc3339(),
+                    ingestion_size,
+                    storage_size,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the time partition column of the stream, the requested time range
+    /// and the manifests of files in it
+    async fn manifests(&self) -> Result<(String, TimeRange, Vec<Manifest>), QueryError> {
+        let time_partition = PARSEABLE
+            .get_stream(&self.stream)
+            .map_err(|err| anyhow::Error::msg(err.to_string()))?
+            .get_time_partition()
+            .unwrap_or_else(|| event::DEFAULT_TIMESTAMP_KEY.to_owned());
+
+        // get time range
+        let time_range = TimeRange::parse_human_time(&self.start_time, &self.end_time)?;
+        let all_manifest_files = get_manifest_list(&self.stream, &time_range).await?;
+
+        Ok((time_partition, time_range, all_manifest_files))
+    }
+
     /// Calculate the end time for each bin based on the number of bins
     fn get_bounds(&self, time_range: &TimeRange) -> Vec<TimeBounds> {
         let total_minutes = time_range
@@ -457,7 +695,14 @@ impl TreeNodeVisitor<'_> for TableScanVisitor {
     fn f_down(&mut self, node: &Self::Node) -> Result<TreeNodeRecursion, DataFusionError> {
         match node {
             LogicalPlan::TableScan(table) => {
-                self.tables.push(table.table_name.table().to_string());
+                // aliases are reported as the stream they point to, for permissions and metadata lookup
+                let name = table.table_name.table();
+                self.tables.push(
+                    PARSEABLE
+                        .streams
+                        .resolve(name)
+                        .unwrap_or_else(|| name.to_owned()),
+                );
                 Ok(TreeNodeRecursion::Jump)
             }
             _ => Ok(TreeNodeRecursion::Continue),
@@ -531,6 +776,68 @@ pub async fn get_manifest_list(
     Ok(all_manifest_files)
 }
 
+/// Returns the superset of the stream's schema and the schemas of files within `time_range`,
+/// if any of those files have columns that are no longer part of the stream's schema.
+/// Only the footers of files with such columns are read, and only until every such column is known.
+pub async fn range_schema(
+    stream_name: &str,
+    time_range: &TimeRange,
+) -> Result<Option<Schema>, QueryError> {
+    let stream = PARSEABLE.get_stream(stream_name)?;
+    let schema = stream.get_schema();
+    let manifests = get_manifest_list(stream_name, time_range).await?;
+    let mut unknown: HashSet<&str> = manifests
+        .iter()
+        .flat_map(|manifest| &manifest.files)
+        .flat_map(|file| &file.columns)
+        .map(|column| column.name.as_str())
+        .filter(|name| schema.field_with_name(name).is_err())
+        .collect();
+    if unknown.is_empty() {
+        return Ok(None);
+    }
+
+    let glob_storage = PARSEABLE.storage.get_object_store();
+    let registry = &QUERY_SESSION.state().runtime_env().object_store_registry;
+    let url = match &stream.get_settings().storage_bucket {
+        Some(bucket) => PARSEABLE.storage.register_bucket(registry.as_ref(), bucket),
+        None => glob_storage.store_url(),
+    };
+    let object_store = registry.get_store(&url)?;
+    let mut file_schemas = vec![];
+    for file in manifests.iter().flat_map(|manifest| &manifest.files) {
+        if !file
+            .columns
+            .iter()
+            .any(|column| unknown.contains(column.name.as_str()))
+        {
+            continue;
+        }
+        let meta = ObjectMeta {
+            location: glob_storage.absolute_url(&relative_path(&*glob_storage, &file.file_path)),
+            last_modified: Utc::now(),
+            size: file.file_size as usize,
+            e_tag: None,
+            version: None,
+        };
+        let metadata = fetch_parquet_metadata(object_store.as_ref(), &meta, None).await?;
+        let file_schema = parquet_to_arrow_schema(
+            metadata.file_metadata().schema_descr(),
+            metadata.file_metadata().key_value_metadata(),
+        )
+        .map_err(anyhow::Error::from)?;
+        for column in &file.columns {
+            unknown.remove(column.name.as_str());
+        }
+        file_schemas.push(file_schema);
+        if unknown.is_empty() {
+            break;
+        }
+    }
+
+    Ok(Some(superset_schema(&schema, &file_schemas)))
+}
+
 fn transform(
     plan: LogicalPlan,
     start_time: NaiveDateTime,
@@ -591,6 +898,35 @@ fn transform(
     .expect("transform only transforms the tablescan")
 }
 
+/// Orders the results of a query that doesn't order them itself by `time_column`, latest first.
+/// Only plain selections from a stream are ordered, i.e. projections and limits over a possibly
+/// filtered scan, as the results of aggregates or joins needn't have a time column to order by.
+pub fn order_by_recency(plan: LogicalPlan, time_column: &str) -> LogicalPlan {
+    fn is_scan(plan: &LogicalPlan) -> bool {
+        match plan {
+            LogicalPlan::TableScan(_) => true,
+            LogicalPlan::Filter(filter) => is_scan(&filter.input),
+            _ => false,
+        }
+    }
+
+    match plan {
+        LogicalPlan::Limit(_) | LogicalPlan::Projection(_) => {
+            plan.map_children(|input| Ok(Transformed::yes(order_by_recency(input, time_column))))
+                .expect("ordering the input doesn't fail")
+                .data
+        }
+        plan if is_scan(&plan) && plan.schema().has_column_with_unqualified_name(time_column) => {
+            LogicalPlan::Sort(Sort {
+                expr: vec![Expr::Column(Column::from_name(time_column)).sort(false, false)],
+                input: Arc::new(plan),
+                fetch: None,
+            })
+        }
+        plan => plan,
+    }
+}
+
 fn table_contains_any_time_filters(
     table: &datafusion::logical_expr::TableScan,
     time_partition: Option<&String>,
@@ -670,14 +1006,306 @@ pub mod error {
         Datafusion(#[from] DataFusionError),
         #[error("{0}")]
         StreamNotFound(#[from] StreamNotFound),
+        #[error(
+            "Query execution timed out after {0:?}, please narrow down the query or time range"
+        )]
+        QueryTimeout(std::time::Duration),
+        #[error("Too many queries are executing at the moment, please retry later")]
+        TooManyQueries,
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use arrow_schema::Schema;
+    use chrono::{TimeZone, Utc};
+    use datafusion::{
+        execution::SendableRecordBatchStream, physical_plan::stream::RecordBatchStreamAdapter,
+    };
+    use futures::StreamExt;
     use serde_json::json;
+    use tokio::sync::Semaphore;
+
+    use crate::catalog::column::{Column, Int64Type, TypedStatistics};
+    use crate::catalog::manifest::{File, Manifest};
+    use crate::query::{
+        acquire_query_permit, bound_stream, error::ExecuteError, flatten_objects_for_count,
+        order_by_recency, partition_time_range, plan_files, query_limiter, with_query_timeout,
+        CountsRequest,
+    };
+    use crate::utils::time::TimeRange;
+
+    fn file(min: i64, ingestion_size: u64, file_size: u64) -> File {
+        File {
+            file_path: format!("{min}.parquet"),
+            num_rows: 10,
+            file_size,
+            ingestion_size,
+            columns: vec![Column {
+                name: "p_timestamp".to_owned(),
+                stats: Some(TypedStatistics::Int(Int64Type { min, max: min })),
+                uncompressed_size: ingestion_size,
+                compressed_size: file_size,
+            }],
+            sort_order_id: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn unordered_query_returns_latest_rows_first() {
+        use arrow::util::pretty::pretty_format_batches;
+        use arrow_array::{Int64Array, RecordBatch, TimestampMillisecondArray};
+        use arrow_schema::{DataType, Field, Schema, TimeUnit};
+        use datafusion::{datasource::MemTable, prelude::SessionContext};
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                "p_timestamp",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("id", DataType::Int64, false),
+        ]));
+        // ingested oldest first
+        let rb = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(TimestampMillisecondArray::from(vec![
+                    1000, 2000, 3000, 4000,
+                ])),
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4])),
+            ],
+        )
+        .unwrap();
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "app",
+            Arc::new(MemTable::try_new(schema, vec![vec![rb]]).unwrap()),
+        )
+        .unwrap();
+        let run = |sql: &'static str| {
+            let ctx = ctx.clone();
+            async move {
+                let plan = ctx.state().create_logical_plan(sql).await.unwrap();
+                let plan = order_by_recency(plan, "p_timestamp");
+                let records = ctx
+                    .execute_logical_plan(plan)
+                    .await
+                    .unwrap()
+                    .collect()
+                    .await
+                    .unwrap();
+                pretty_format_batches(&records).unwrap().to_string()
+            }
+        };
+
+        let latest = run("SELECT id FROM app WHERE id > 1 LIMIT 2").await;
+        assert_eq!(
+            latest,
+            ["+----+", "| id |", "+----+", "| 4  |", "| 3  |", "+----+"].join("\n")
+        );
+
+        // an explicit order is kept
+        let ordered = run("SELECT id FROM app ORDER BY id LIMIT 2").await;
+        assert_eq!(
+            ordered,
+            ["+----+", "| id |", "+----+", "| 1  |", "| 2  |", "+----+"].join("\n")
+        );
+
+        // as are aggregates, having no time column to order by
+        let plan = ctx
+            .state()
+            .create_logical_plan("SELECT count(*) FROM app")
+            .await
+            .unwrap();
+        assert_eq!(order_by_recency(plan.clone(), "p_timestamp"), plan);
+    }
+
+    #[tokio::test]
+    async fn scanned_files_match_partitions_of_range() {
+        use std::sync::Arc;
+
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch};
+        use datafusion::{
+            common::Constraints,
+            datasource::{
+                file_format::{parquet::ParquetFormat, FileFormat},
+                listing::PartitionedFile,
+                physical_plan::FileScanConfig,
+            },
+            execution::object_store::ObjectStoreUrl,
+            physical_plan::{coalesce_partitions::CoalescePartitionsExec, Statistics},
+            prelude::SessionContext,
+        };
+        use parquet::arrow::ArrowWriter;
+        use temp_dir::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let batch =
+            RecordBatch::try_from_iter([("id", Arc::new(Int64Array::from(vec![1])) as ArrayRef)])
+                .unwrap();
+        let files = ["00", "01"].map(|minute| {
+            let partition = dir
+                .path()
+                .join(format!("app/date=2025-01-01/hour=10/minute={minute}"));
+            std::fs::create_dir_all(&partition).unwrap();
+            let path = partition.join("host.data.parquet");
+            let mut writer =
+                ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), batch.schema(), None)
+                    .unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+            let size = path.metadata().unwrap().len();
+            PartitionedFile::new(path.display().to_string(), size)
+        });
+
+        let ctx = SessionContext::new();
+        let schema = batch.schema();
+        let scan = ParquetFormat::default()
+            .create_physical_plan(
+                &ctx.state(),
+                FileScanConfig {
+                    object_store_url: ObjectStoreUrl::parse("file:///").unwrap(),
+                    file_schema: schema.clone(),
+                    file_groups: vec![files.to_vec()],
+                    statistics: Statistics::new_unknown(&schema),
+                    projection: None,
+                    limit: None,
+                    output_ordering: vec![],
+                    table_partition_cols: vec![],
+                    constraints: Constraints::empty(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let plan: Arc<dyn datafusion::physical_plan::ExecutionPlan> =
+            Arc::new(CoalescePartitionsExec::new(scan));
+
+        let scanned = plan_files(&plan).unwrap();
+        assert_eq!(scanned.len(), 2);
+        for (scanned, (file, minute)) in scanned.iter().zip(files.iter().zip(["00", "01"])) {
+            assert!(scanned.path.ends_with(&format!(
+                "app/date=2025-01-01/hour=10/minute={minute}/host.data.parquet"
+            )));
+            assert_eq!(scanned.size, file.object_meta.size as u64);
+            assert_eq!(
+                scanned.start_time.as_deref(),
+                Some(format!("2025-01-01T10:{minute}:00+00:00").as_str())
+            );
+        }
+        assert!(partition_time_range("app/1.parquet").is_none());
+    }
 
-    use crate::query::flatten_objects_for_count;
+    #[test]
+    fn zoned_partition_time_range_is_in_utc() {
+        let range = partition_time_range(
+            "app/date=2025-01-02/hour=00/minute=00/zone=-0500/host.data.parquet",
+        )
+        .unwrap();
+        assert_eq!(
+            range.start,
+            Utc.with_ymd_and_hms(2025, 1, 2, 5, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn volume_is_summed_per_bin() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let minutes = |m: i64| (start + chrono::Duration::minutes(m)).timestamp_millis();
+        let manifests = vec![Manifest {
+            files: vec![
+                file(minutes(10), 1000, 100),
+                file(minutes(20), 2000, 200),
+                file(minutes(70), 4000, 400),
+            ],
+            ..Default::default()
+        }];
+        let request = CountsRequest {
+            stream: "test".to_owned(),
+            start_time: start.to_rfc3339(),
+            end_time: (start + chrono::Duration::hours(2)).to_rfc3339(),
+            num_bins: 2,
+        };
+        let time_range = TimeRange {
+            start,
+            end: start + chrono::Duration::hours(2),
+        };
+
+        let volume = request.volume(&time_range, &manifests, "p_timestamp");
+
+        assert_eq!(volume.len(), 2);
+        assert_eq!(
+            (volume[0].ingestion_size, volume[0].storage_size),
+            (3000, 300)
+        );
+        assert_eq!(
+            (volume[1].ingestion_size, volume[1].storage_size),
+            (4000, 400)
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_query_times_out() {
+        // mocks a scan that takes longer than the configured timeout
+        let slow_scan = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<_, ExecuteError>(vec![1])
+        };
+        let result = with_query_timeout(Some(Duration::from_millis(10)), slow_scan).await;
+        assert!(matches!(result, Err(ExecuteError::QueryTimeout(_))));
+
+        let fast_scan = async { Ok::<_, ExecuteError>(vec![1]) };
+        let result = with_query_timeout(Some(Duration::from_secs(5)), fast_scan).await;
+        assert_eq!(result.unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn slow_streamed_query_times_out() {
+        // mocks a streamed response whose next batch takes longer than the configured timeout
+        let schema = Arc::new(Schema::empty());
+        let slow_stream: SendableRecordBatchStream = Box::pin(RecordBatchStreamAdapter::new(
+            schema,
+            futures::stream::pending(),
+        ));
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(10);
+        let batches: Vec<_> = bound_stream(
+            slow_stream,
+            Some((deadline, Duration::from_millis(10))),
+            None,
+        )
+        .collect()
+        .await;
+        assert_eq!(batches.len(), 1);
+        assert!(batches[0]
+            .as_ref()
+            .is_err_and(|err| err.to_string().contains("timed out")));
+    }
+
+    #[test]
+    fn query_beyond_concurrency_limit_is_rejected() {
+        let limiter = Arc::new(Semaphore::new(2));
+        let running: Vec<_> = (0..2)
+            .map(|_| acquire_query_permit(Some(&limiter)).unwrap())
+            .collect();
+        assert!(matches!(
+            acquire_query_permit(Some(&limiter)),
+            Err(ExecuteError::TooManyQueries)
+        ));
+
+        // a finished query frees up its slot
+        drop(running);
+        assert!(acquire_query_permit(Some(&limiter)).unwrap().is_some());
+        assert!(acquire_query_permit(None).unwrap().is_none());
+
+        // a limit of 0 doesn't reject every query
+        let unbounded = query_limiter(Some(0));
+        assert!(unbounded.is_none());
+        assert!(acquire_query_permit(unbounded.as_ref()).is_ok());
+    }
 
     #[test]
     fn test_flat_simple() {
@@ -756,4 +1384,75 @@ mod tests {
         let out = flatten_objects_for_count(val.clone());
         assert_eq!(val, out);
     }
+
+    #[tokio::test]
+    async fn range_schema_has_columns_of_files_no_longer_in_the_schema() {
+        use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMillisecondArray};
+        use arrow_schema::{DataType, Field, TimeUnit};
+        use bytes::Bytes;
+        use relative_path::RelativePathBuf;
+
+        use crate::{
+            catalog, event::DEFAULT_TIMESTAMP_KEY, parseable::PARSEABLE, query::range_schema,
+            storage::ObjectStoreFormat,
+        };
+
+        let stream_name = "range_schema_dropped_column";
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, 30, 0).unwrap();
+        let rb = RecordBatch::try_from_iter([
+            (
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as ArrayRef,
+            ),
+            (
+                "legacy",
+                Arc::new(StringArray::from(vec!["old"])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let mut parquet = vec![];
+        let mut writer =
+            parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+        writer.write(&rb).unwrap();
+        writer.close().unwrap();
+        let parquet = Bytes::from(parquet);
+
+        // the column was dropped from the schema of the stream since the file was written
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            DEFAULT_TIMESTAMP_KEY,
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            true,
+        )]));
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.set_schema(&schema);
+        let store = PARSEABLE.storage.get_object_store();
+        store
+            .create_stream(stream_name, ObjectStoreFormat::default(), schema)
+            .await
+            .unwrap();
+        let key = RelativePathBuf::from_iter([stream_name, "date=2025-01-01", "host.data.parquet"]);
+        store.put_object(&key, parquet.clone()).await.unwrap();
+        let file = catalog::manifest::create_from_parquet(
+            store.absolute_url(&key).to_string(),
+            parquet.clone(),
+            parquet.len() as u64,
+        )
+        .unwrap();
+        catalog::update_snapshot(store.clone(), stream_name, file)
+            .await
+            .unwrap();
+
+        let time_range = TimeRange::new(
+            at - chrono::Duration::hours(1),
+            at + chrono::Duration::hours(1),
+        );
+        let schema = range_schema(stream_name, &time_range)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            schema.field_with_name("legacy").unwrap().data_type(),
+            &DataType::Utf8
+        );
+    }
 }
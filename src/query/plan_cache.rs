@@ -0,0 +1,242 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use datafusion::{
+    common::tree_node::{Transformed, TreeNode, TreeNodeRecursion},
+    datasource::provider_as_source,
+    error::DataFusionError,
+    execution::context::SessionState,
+    logical_expr::{LogicalPlan, TableScan},
+    sql::TableReference,
+};
+use once_cell::sync::Lazy;
+
+use crate::parseable::PARSEABLE;
+
+/// Plans of the queries run recently, shared by all queries
+pub static PLAN_CACHE: Lazy<PlanCache> =
+    Lazy::new(|| PlanCache::new(PARSEABLE.options.query_plan_cache_size));
+
+/// Logical plans of queries by their SQL, so that a query repeated e.g. by a dashboard isn't
+/// planned again each time. A plan is reused only while the tables it scans have the schema
+/// they had when it was planned, it is planned again once any of them evolves.
+pub struct PlanCache {
+    capacity: usize,
+    plans: Mutex<Plans>,
+    hits: AtomicU64,
+}
+
+#[derive(Default)]
+struct Plans {
+    by_sql: HashMap<String, LogicalPlan>,
+    // oldest first, to evict once the cache is full
+    order: VecDeque<String>,
+}
+
+impl PlanCache {
+    /// Cache of up to `capacity` plans, a `capacity` of 0 disables caching
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            plans: Mutex::default(),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of queries whose plan was reused
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Logical plan of `sql`, the cached one if the tables it scans are unchanged
+    pub async fn logical_plan(
+        &self,
+        session_state: &SessionState,
+        sql: &str,
+    ) -> Result<LogicalPlan, DataFusionError> {
+        if self.capacity == 0 {
+            return session_state.create_logical_plan(sql).await;
+        }
+
+        // keyed by the parsed statement, so that queries differing only in formatting or
+        // comments share a plan
+        let dialect = session_state.config().options().sql_parser.dialect.clone();
+        let statement = session_state.sql_to_statement(sql, &dialect)?;
+        let key = statement.to_string();
+        let cached = self
+            .plans
+            .lock()
+            .expect("plan cache lock shouldn't be poisoned")
+            .by_sql
+            .get(&key)
+            .cloned();
+        if let Some(plan) = cached {
+            if let Some(plan) = rebind_tables(plan, session_state).await? {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(plan);
+            }
+        }
+
+        let plan = session_state.statement_to_plan(statement).await?;
+        let mut plans = self
+            .plans
+            .lock()
+            .expect("plan cache lock shouldn't be poisoned");
+        if plans.by_sql.insert(key.clone(), plan.clone()).is_none() {
+            plans.order.push_back(key);
+        }
+        while plans.by_sql.len() > self.capacity {
+            let Some(oldest) = plans.order.pop_front() else {
+                break;
+            };
+            plans.by_sql.remove(&oldest);
+        }
+
+        Ok(plan)
+    }
+}
+
+/// Points the scans of a cached plan at the current providers of their tables, as the providers
+/// carry the settings of the query they are read for. Returns `None` when the schema of a table
+/// has changed since the plan was made, or the table no longer exists.
+async fn rebind_tables(
+    plan: LogicalPlan,
+    session_state: &SessionState,
+) -> Result<Option<LogicalPlan>, DataFusionError> {
+    let mut tables: Vec<TableReference> = vec![];
+    plan.apply_with_subqueries(|node| {
+        if let LogicalPlan::TableScan(scan) = node {
+            if !tables.contains(&scan.table_name) {
+                tables.push(scan.table_name.clone());
+            }
+        }
+        Ok(TreeNodeRecursion::Continue)
+    })?;
+
+    let mut providers = HashMap::with_capacity(tables.len());
+    for table in tables {
+        let Some(provider) = session_state
+            .schema_for_ref(table.clone())?
+            .table(table.table())
+            .await?
+        else {
+            return Ok(None);
+        };
+        providers.insert(table, provider);
+    }
+
+    let mut schema_changed = false;
+    let plan = plan
+        .transform_up_with_subqueries(|node| match node {
+            LogicalPlan::TableScan(scan) => {
+                let provider = &providers[&scan.table_name];
+                if provider.schema() != scan.source.schema() {
+                    schema_changed = true;
+                    return Ok(Transformed::no(LogicalPlan::TableScan(scan)));
+                }
+                Ok(Transformed::yes(LogicalPlan::TableScan(TableScan {
+                    source: provider_as_source(provider.clone()),
+                    ..scan
+                })))
+            }
+            node => Ok(Transformed::no(node)),
+        })?
+        .data;
+
+    Ok((!schema_changed).then_some(plan))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int64Array, RecordBatch, StringArray};
+    use datafusion::{datasource::MemTable, prelude::SessionContext};
+
+    use super::*;
+
+    fn register(ctx: &SessionContext, batch: RecordBatch) {
+        ctx.deregister_table("app").unwrap();
+        let table = MemTable::try_new(batch.schema(), vec![vec![batch]]).unwrap();
+        ctx.register_table("app", Arc::new(table)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn repeated_query_reuses_cached_plan() {
+        let ctx = SessionContext::new();
+        let cache = PlanCache::new(8);
+        register(
+            &ctx,
+            RecordBatch::try_from_iter([("msg", Arc::new(StringArray::from(vec!["hi"])) as _)])
+                .unwrap(),
+        );
+
+        let first = cache
+            .logical_plan(&ctx.state(), "select * from app")
+            .await
+            .unwrap();
+        let second = cache
+            .logical_plan(&ctx.state(), "select *\n  from app;")
+            .await
+            .unwrap();
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(first, second);
+
+        // the newline ends the comment, the filter is part of the query
+        let filtered = cache
+            .logical_plan(
+                &ctx.state(),
+                "select * -- all columns\nfrom app where msg = 'bye'",
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache.hits(), 1);
+        assert_ne!(first, filtered);
+
+        // the schema evolved, hence the query is planned again
+        register(
+            &ctx,
+            RecordBatch::try_from_iter([
+                ("msg", Arc::new(StringArray::from(vec!["hi"])) as _),
+                ("status", Arc::new(Int64Array::from(vec![200])) as _),
+            ])
+            .unwrap(),
+        );
+        let third = cache
+            .logical_plan(&ctx.state(), "select * from app")
+            .await
+            .unwrap();
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(third.schema().fields().len(), 2);
+
+        let commented = cache
+            .logical_plan(&ctx.state(), "-- dashboard panel\nselect * from app")
+            .await
+            .unwrap();
+        assert_eq!(cache.hits(), 2);
+        assert_eq!(third, commented);
+    }
+}
@@ -19,7 +19,7 @@
 use std::{any::Any, collections::HashMap, ops::Bound, sync::Arc};
 
 use arrow_array::RecordBatch;
-use arrow_schema::{Schema, SchemaRef, SortOptions};
+use arrow_schema::{Schema, SchemaRef, SortOptions, TimeUnit};
 use bytes::Bytes;
 use chrono::{DateTime, NaiveDateTime, TimeDelta, Timelike, Utc};
 use datafusion::{
@@ -33,41 +33,86 @@ use datafusion::{
         file_format::{parquet::ParquetFormat, FileFormat},
         listing::PartitionedFile,
         physical_plan::FileScanConfig,
+        provider_as_source,
+        view::ViewTable,
         MemTable, TableProvider,
     },
     error::{DataFusionError, Result as DataFusionResult},
-    execution::{context::SessionState, object_store::ObjectStoreUrl},
+    execution::{
+        context::SessionState,
+        object_store::{ObjectStoreRegistry, ObjectStoreUrl},
+    },
+    functions::expr_fn::coalesce,
     logical_expr::{
-        utils::conjunction, BinaryExpr, Operator, TableProviderFilterPushDown, TableType,
+        ident, try_cast, utils::conjunction, BinaryExpr, LogicalPlanBuilder, Operator,
+        TableProviderFilterPushDown, TableType,
     },
     physical_expr::{create_physical_expr, expressions::col, LexOrdering, PhysicalSortExpr},
     physical_plan::{empty::EmptyExec, union::UnionExec, ExecutionPlan, Statistics},
     prelude::Expr,
     scalar::ScalarValue,
 };
-use futures_util::{stream::FuturesOrdered, StreamExt, TryFutureExt, TryStreamExt};
+use futures_util::{stream::Stream, Future, StreamExt, TryFutureExt, TryStreamExt};
 use itertools::Itertools;
 use object_store::{path::Path, ObjectStore};
 use relative_path::RelativePathBuf;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::{
     catalog::{
         column::{Column, TypedStatistics},
+        compaction::COMPACTED_FILE_SUFFIX,
         manifest::{File, Manifest},
         snapshot::{ManifestItem, Snapshot},
         ManifestFile, Snapshot as CatalogSnapshot,
     },
-    event::DEFAULT_TIMESTAMP_KEY,
+    event::{partition_bucket, DEFAULT_TIMESTAMP_KEY, PARTITION_BUCKET_KEY, PARTITION_KEY},
     hottier::HotTierManager,
     metrics::QUERY_CACHE_HIT,
     option::Mode,
     parseable::{PARSEABLE, STREAM_EXISTS},
-    storage::{ObjectStorage, ObjectStoreFormat, STREAM_ROOT_DIRECTORY},
+    storage::{uncached_url, ObjectStorage, ObjectStoreFormat, STREAM_ROOT_DIRECTORY},
+    sync,
+    utils::time::{from_timestamp, timestamp_unit, to_timestamp},
+    STORAGE_UPLOAD_INTERVAL,
 };
 
 use super::listing_table_builder::ListingTableBuilder;
 
+tokio::task_local! {
+    /// Schemas streams are read with while a query is planned, in place of their current schema.
+    /// Set for queries over a time range with files written before the schema evolved.
+    pub static SCHEMA_OVERRIDES: HashMap<String, Schema>;
+    /// Tier of files scanned by tables of a query while it is planned
+    pub static FILE_TIER: FileTier;
+    /// Set for queries that read only from object storage, skipping the hot tier
+    pub static SKIP_CACHE: bool;
+}
+
+/// Files of a stream that a query scans, compacted files are larger and hence quicker to scan,
+/// but data is compacted only after a while.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileTier {
+    #[default]
+    All,
+    /// Only files written by compaction
+    Compacted,
+    /// Only files that haven't been compacted yet, along with data still in staging
+    Recent,
+}
+
+impl FileTier {
+    pub fn includes(&self, file: &File) -> bool {
+        match self {
+            FileTier::All => true,
+            FileTier::Compacted => file.file_path.ends_with(COMPACTED_FILE_SUFFIX),
+            FileTier::Recent => !file.file_path.ends_with(COMPACTED_FILE_SUFFIX),
+        }
+    }
+}
+
 // schema provider for stream based on global data
 #[derive(Debug)]
 pub struct GlobalSchemaProvider {
@@ -85,35 +130,177 @@ impl SchemaProvider for GlobalSchemaProvider {
     }
 
     async fn table(&self, name: &str) -> DataFusionResult<Option<Arc<dyn TableProvider>>> {
-        if self.table_exist(name) {
-            Ok(Some(Arc::new(StandardTableProvider {
-                schema: PARSEABLE
-                    .get_stream(name)
-                    .expect(STREAM_EXISTS)
-                    .get_schema(),
-                stream: name.to_owned(),
+        // aliases are read from the prefix of the stream they point to
+        if let Some(stream) = PARSEABLE.streams.resolve(name) {
+            let schema = SCHEMA_OVERRIDES
+                .try_with(|overrides| overrides.get(&stream).map(read_schema))
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| {
+                    read_schema(
+                        &PARSEABLE
+                            .get_stream(&stream)
+                            .expect(STREAM_EXISTS)
+                            .get_schema(),
+                    )
+                });
+            let settings = PARSEABLE
+                .get_stream(&stream)
+                .expect(STREAM_EXISTS)
+                .get_settings();
+            let table = Arc::new(StandardTableProvider {
+                schema,
+                tier: FILE_TIER.try_with(|tier| *tier).unwrap_or_default(),
+                // without the local cache there is nothing but object storage to read from
+                skip_cache: PARSEABLE.options.disable_local_cache
+                    || SKIP_CACHE.try_with(|skip| *skip).unwrap_or_default(),
+                stream: stream.clone(),
                 url: self.storage.store_url(),
-            })))
+                bucket: settings.storage_bucket.clone(),
+            });
+            if settings.column_aliases.is_empty() {
+                return Ok(Some(table));
+            }
+
+            Ok(Some(with_column_aliases(
+                &stream,
+                table,
+                &settings.column_aliases,
+            )?))
         } else {
             Ok(None)
         }
     }
 
     fn table_exist(&self, name: &str) -> bool {
-        PARSEABLE.streams.contains(name)
+        PARSEABLE.streams.resolve(name).is_some()
+    }
+}
+
+/// Wraps `table` in a view where every alias is an additional column, with the values of the
+/// column it points to. If a column by the alias' name still exists, i.e. the column was
+/// renamed, its values are used where the renamed column has none. These are cast to the type of
+/// the renamed column, values that can't be cast are null.
+pub fn with_column_aliases(
+    table_name: &str,
+    table: Arc<dyn TableProvider>,
+    column_aliases: &HashMap<String, String>,
+) -> DataFusionResult<Arc<dyn TableProvider>> {
+    let schema = table.schema();
+    let mut exprs = schema
+        .fields()
+        .iter()
+        .filter(|field| !column_aliases.contains_key(field.name()))
+        .map(|field| ident(field.name()))
+        .collect_vec();
+    for (alias, column) in column_aliases.iter().sorted() {
+        // aliases of columns no longer in the schema are left out
+        let Ok(field) = schema.field_with_name(column) else {
+            continue;
+        };
+        let renamed = ident(column);
+        let expr = match schema.field_with_name(alias) {
+            Ok(old) if old.data_type() == field.data_type() => {
+                coalesce(vec![renamed, ident(alias)])
+            }
+            Ok(_) => coalesce(vec![
+                renamed,
+                try_cast(ident(alias), field.data_type().clone()),
+            ]),
+            Err(_) => renamed,
+        };
+        exprs.push(expr.alias(alias));
+    }
+
+    let plan = LogicalPlanBuilder::scan(table_name, provider_as_source(table), None)?
+        .project(exprs)?
+        .build()?;
+
+    Ok(Arc::new(ViewTable::try_new(plan, None)?))
+}
+
+/// Schema with which the files of a stream are read. Files written before a column was added
+/// to the stream don't contain it, such columns are projected as nulls at read time, hence
+/// every field is read as nullable.
+fn read_schema(schema: &Schema) -> SchemaRef {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| field.as_ref().clone().with_nullable(true))
+        .collect_vec();
+
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Extends `schema` with fields of `file_schemas` that it doesn't contain, such as columns
+/// only found in files written before the schema evolved. Fields already in `schema` keep
+/// their type, values of files with another type are cast at read time.
+pub fn superset_schema<'a>(
+    schema: &Schema,
+    file_schemas: impl IntoIterator<Item = &'a Schema>,
+) -> Schema {
+    let mut fields = schema.fields().iter().cloned().collect_vec();
+    for file_schema in file_schemas {
+        for field in file_schema.fields() {
+            if !fields.iter().any(|f| f.name() == field.name()) {
+                fields.push(Arc::new(field.as_ref().clone().with_nullable(true)));
+            }
+        }
     }
+
+    Schema::new_with_metadata(fields, schema.metadata().clone())
 }
 
 #[derive(Debug)]
 struct StandardTableProvider {
     schema: SchemaRef,
+    tier: FileTier,
+    // files are read from object storage even if they are in the hot tier
+    skip_cache: bool,
     // prefix under which to find snapshot
     stream: String,
     // url to find right instance of object store
     url: Url,
+    // bucket of its own the data files of the stream are in, its manifests are in the default one
+    bucket: Option<String>,
 }
 
 impl StandardTableProvider {
+    /// Hot tier to read the files of the stream from, none when the stream has no hot tier or
+    /// the query skips it for the consistency of reading from object storage alone
+    fn hot_tier<'a>(
+        &self,
+        hot_tier_manager: Option<&'a HotTierManager>,
+    ) -> Option<&'a HotTierManager> {
+        hot_tier_manager.filter(|hot_tier_manager| {
+            !self.skip_cache && hot_tier_manager.check_stream_hot_tier_exists(&self.stream)
+        })
+    }
+
+    /// Url of the object store to read the files of the stream from, the one bypassing the object
+    /// store cache when the query skips caches and the store has a cache
+    fn store_url(&self, registry: &dyn ObjectStoreRegistry) -> Url {
+        let url = match &self.bucket {
+            Some(bucket) => PARSEABLE.storage.register_bucket(registry, bucket),
+            None => self.url.clone(),
+        };
+        self.bypassing_cache(registry, url)
+    }
+
+    /// Url of the object store to read the manifests of the stream from, the default bucket
+    fn manifest_store_url(&self, registry: &dyn ObjectStoreRegistry) -> Url {
+        self.bypassing_cache(registry, self.url.clone())
+    }
+
+    fn bypassing_cache(&self, registry: &dyn ObjectStoreRegistry, url: Url) -> Url {
+        let uncached = uncached_url(&url);
+        if self.skip_cache && registry.get_store(&uncached).is_ok() {
+            return uncached;
+        }
+
+        url
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn create_parquet_physical_plan(
         &self,
@@ -410,7 +597,10 @@ async fn collect_from_snapshot(
     time_filters: &[PartialTimeFilter],
     object_store: Arc<dyn ObjectStore>,
     filters: &[Expr],
+    custom_partition: Option<&String>,
+    partition_key_buckets: usize,
     limit: Option<usize>,
+    tier: FileTier,
 ) -> Result<Vec<File>, DataFusionError> {
     let items = snapshot.manifests(time_filters);
     let manifest_files = collect_manifest_files(
@@ -427,9 +617,17 @@ async fn collect_from_snapshot(
         .into_iter()
         .flat_map(|file| file.files)
         .rev()
+        .filter(|file| tier.includes(file))
         .collect();
+    let custom_partitions = custom_partition
+        .map(|partitions| partitions.split(',').map(str::trim).collect_vec())
+        .unwrap_or_default();
     for filter in filters {
-        manifest_files.retain(|file| !file.can_be_pruned(filter))
+        manifest_files.retain(|file| {
+            !file.can_be_pruned(filter)
+                && !is_pruned_by_partition(&file.file_path, &custom_partitions, filter)
+                && !is_pruned_by_partition_key(&file.file_path, partition_key_buckets, filter)
+        })
     }
     if let Some(limit) = limit {
         let limit = limit as u64;
@@ -474,10 +672,10 @@ impl TableProvider for StandardTableProvider {
         limit: Option<usize>,
     ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
         let mut execution_plans = vec![];
-        let object_store = state
-            .runtime_env()
-            .object_store_registry
-            .get_store(&self.url)
+        let registry = &state.runtime_env().object_store_registry;
+        let url = self.store_url(registry.as_ref());
+        let manifest_store = registry
+            .get_store(&self.manifest_store_url(registry.as_ref()))
             .unwrap();
         let glob_storage = PARSEABLE.storage.get_object_store();
 
@@ -491,7 +689,8 @@ impl TableProvider for StandardTableProvider {
             return Err(DataFusionError::Plan("potentially unbounded query on time range. Table scanning requires atleast one time bound".to_string()));
         }
 
-        if is_within_staging_window(&time_filters) {
+        // staged data is yet to be compacted
+        if self.tier != FileTier::Compacted && is_within_staging_window(&time_filters) {
             self.get_staging_execution_plan(
                 &mut execution_plans,
                 projection,
@@ -533,11 +732,15 @@ impl TableProvider for StandardTableProvider {
             let listing_time_fiters =
                 return_listing_time_filters(&merged_snapshot.manifest_list, &mut time_filters);
 
-            if let Some(listing_time_filter) = listing_time_fiters {
+            // files predating manifests were never compacted
+            if let Some(listing_time_filter) =
+                listing_time_fiters.filter(|_| self.tier != FileTier::Compacted)
+            {
+                // files predating manifests are in the default bucket
                 self.legacy_listing_table(
                     &mut execution_plans,
                     glob_storage.clone(),
-                    object_store.clone(),
+                    manifest_store.clone(),
                     &listing_time_filter,
                     state,
                     projection,
@@ -552,9 +755,12 @@ impl TableProvider for StandardTableProvider {
         let mut manifest_files = collect_from_snapshot(
             &merged_snapshot,
             &time_filters,
-            object_store,
+            manifest_store,
             filters,
+            object_store_format.custom_partition.as_ref(),
+            object_store_format.settings.partition_key_buckets(),
             limit,
+            self.tier,
         )
         .await?;
 
@@ -563,20 +769,18 @@ impl TableProvider for StandardTableProvider {
         }
 
         // Hot tier data fetch
-        if let Some(hot_tier_manager) = HotTierManager::global() {
-            if hot_tier_manager.check_stream_hot_tier_exists(&self.stream) {
-                self.get_hottier_exectuion_plan(
-                    &mut execution_plans,
-                    hot_tier_manager,
-                    &mut manifest_files,
-                    projection,
-                    filters,
-                    limit,
-                    state,
-                    time_partition.clone(),
-                )
-                .await?;
-            }
+        if let Some(hot_tier_manager) = self.hot_tier(HotTierManager::global()) {
+            self.get_hottier_exectuion_plan(
+                &mut execution_plans,
+                hot_tier_manager,
+                &mut manifest_files,
+                projection,
+                filters,
+                limit,
+                state,
+                time_partition.clone(),
+            )
+            .await?;
         }
         if manifest_files.is_empty() {
             QUERY_CACHE_HIT.with_label_values(&[&self.stream]).inc();
@@ -586,7 +790,7 @@ impl TableProvider for StandardTableProvider {
         let (partitioned_files, statistics) = self.partitioned_files(manifest_files);
         self.create_parquet_physical_plan(
             &mut execution_plans,
-            ObjectStoreUrl::parse(glob_storage.store_url()).unwrap(),
+            ObjectStoreUrl::parse(&url).unwrap(),
             partitioned_files,
             statistics,
             projection,
@@ -663,33 +867,52 @@ impl PartialTimeFilter {
 
     pub fn binary_expr(&self, left: Expr) -> Expr {
         let (op, right) = match self {
-            PartialTimeFilter::Low(Bound::Excluded(time)) => {
-                (Operator::Gt, time.and_utc().timestamp_millis())
-            }
-            PartialTimeFilter::Low(Bound::Included(time)) => {
-                (Operator::GtEq, time.and_utc().timestamp_millis())
-            }
-            PartialTimeFilter::High(Bound::Excluded(time)) => {
-                (Operator::Lt, time.and_utc().timestamp_millis())
-            }
-            PartialTimeFilter::High(Bound::Included(time)) => {
-                (Operator::LtEq, time.and_utc().timestamp_millis())
-            }
-            PartialTimeFilter::Eq(time) => (Operator::Eq, time.and_utc().timestamp_millis()),
+            PartialTimeFilter::Low(Bound::Excluded(time)) => (Operator::Gt, time),
+            PartialTimeFilter::Low(Bound::Included(time)) => (Operator::GtEq, time),
+            PartialTimeFilter::High(Bound::Excluded(time)) => (Operator::Lt, time),
+            PartialTimeFilter::High(Bound::Included(time)) => (Operator::LtEq, time),
+            PartialTimeFilter::Eq(time) => (Operator::Eq, time),
             _ => unimplemented!(),
         };
 
+        // compared in the precision timestamps are stored in, so that nothing is lost to a cast
         Expr::BinaryExpr(BinaryExpr::new(
             Box::new(left),
             op,
-            Box::new(Expr::Literal(ScalarValue::TimestampMillisecond(
-                Some(right),
-                None,
-            ))),
+            Box::new(Expr::Literal(timestamp_scalar(right.and_utc()))),
         ))
     }
 }
 
+/// Literal holding `time` in the precision timestamps are stored in
+fn timestamp_scalar(time: DateTime<Utc>) -> ScalarValue {
+    let unit = timestamp_unit();
+    let value = Some(to_timestamp(time, unit));
+    match unit {
+        TimeUnit::Second => ScalarValue::TimestampSecond(value, None),
+        TimeUnit::Millisecond => ScalarValue::TimestampMillisecond(value, None),
+        TimeUnit::Microsecond => ScalarValue::TimestampMicrosecond(value, None),
+        TimeUnit::Nanosecond => ScalarValue::TimestampNanosecond(value, None),
+    }
+}
+
+/// Time held by a timestamp literal of any precision
+fn scalar_timestamp(value: &ScalarValue) -> Option<DateTime<Utc>> {
+    match value {
+        ScalarValue::TimestampSecond(Some(value), _) => from_timestamp(*value, TimeUnit::Second),
+        ScalarValue::TimestampMillisecond(Some(value), _) => {
+            from_timestamp(*value, TimeUnit::Millisecond)
+        }
+        ScalarValue::TimestampMicrosecond(Some(value), _) => {
+            from_timestamp(*value, TimeUnit::Microsecond)
+        }
+        ScalarValue::TimestampNanosecond(Some(value), _) => {
+            from_timestamp(*value, TimeUnit::Nanosecond)
+        }
+        _ => None,
+    }
+}
+
 fn is_overlapping_query(
     manifest_list: &[ManifestItem],
     time_filters: &[PartialTimeFilter],
@@ -781,8 +1004,13 @@ fn return_listing_time_filters(
 
 /// We should consider data in staging for queries concerning a time period,
 /// ending within 5 minutes from now. e.g. If current time is 5
+///
+/// The window is widened when the local sync interval is configured such that
+/// data stays in staging for longer than 5 minutes.
 pub fn is_within_staging_window(time_filters: &[PartialTimeFilter]) -> bool {
-    let five_minutes_back = (Utc::now() - TimeDelta::minutes(5))
+    let staged_for = TimeDelta::from_std(sync::local_sync_interval() + STORAGE_UPLOAD_INTERVAL)
+        .unwrap_or(TimeDelta::minutes(5));
+    let five_minutes_back = (Utc::now() - TimeDelta::minutes(5).max(staged_for))
         .with_second(0)
         .and_then(|x| x.with_nanosecond(0))
         .expect("zeroed value is valid")
@@ -836,15 +1064,11 @@ fn extract_timestamp_bound(
         _ => false,
     };
 
+    if let Some(time) = scalar_timestamp(value) {
+        return Some((binexpr.op, time.naive_utc()));
+    }
+
     match value {
-        ScalarValue::TimestampMillisecond(Some(value), _) => Some((
-            binexpr.op,
-            DateTime::from_timestamp_millis(*value).unwrap().naive_utc(),
-        )),
-        ScalarValue::TimestampNanosecond(Some(value), _) => Some((
-            binexpr.op,
-            DateTime::from_timestamp_nanos(*value).naive_utc(),
-        )),
         ScalarValue::Utf8(Some(str_value)) if is_time_partition => {
             Some((binexpr.op, str_value.parse::<NaiveDateTime>().unwrap()))
         }
@@ -859,11 +1083,10 @@ pub async fn collect_manifest_files(
     let tasks = manifest_urls.into_iter().map(|path| {
         let path = Path::parse(path).unwrap();
         let storage = Arc::clone(&storage);
-        async move { storage.get(&path).await }
+        async move { storage.get(&path).and_then(|res| res.bytes()).await }
     });
 
-    let resp = FuturesOrdered::from_iter(tasks)
-        .and_then(|res| res.bytes())
+    let resp = fetch_concurrently(tasks, PARSEABLE.options.query_fetch_concurrency)
         .collect::<Vec<object_store::Result<Bytes>>>()
         .await;
 
@@ -874,6 +1097,15 @@ pub async fn collect_manifest_files(
         .collect())
 }
 
+/// Runs the object store requests of a query, with at most `concurrency` of them in flight at a
+/// time, the responses are in the same order as the requests
+pub fn fetch_concurrently<F: Future>(
+    requests: impl IntoIterator<Item = F>,
+    concurrency: usize,
+) -> impl Stream<Item = F::Output> {
+    futures_util::stream::iter(requests).buffered(concurrency.max(1))
+}
+
 // Extract start time and end time from filter predicate
 pub fn extract_primary_filter(
     filters: &[Expr],
@@ -896,6 +1128,82 @@ pub fn extract_primary_filter(
         .collect()
 }
 
+/// Checks if the file lies under a custom partition prefix that can't match an equality filter,
+/// e.g. with `region` as a custom partition, `region = 'us'` prunes files under `region=eu/`
+fn is_pruned_by_partition(file_path: &str, custom_partitions: &[&str], filter: &Expr) -> bool {
+    let Expr::BinaryExpr(BinaryExpr {
+        left,
+        op: Operator::Eq,
+        right,
+    }) = filter
+    else {
+        return false;
+    };
+    let (Expr::Column(column), Expr::Literal(value)) = (left.as_ref(), right.as_ref()) else {
+        return false;
+    };
+    if !custom_partitions.contains(&column.name.as_str()) {
+        return false;
+    }
+
+    // partition values are written into the path as they appear in the event
+    let value = match value {
+        ScalarValue::Utf8(Some(value))
+        | ScalarValue::LargeUtf8(Some(value))
+        | ScalarValue::Utf8View(Some(value)) => value.to_owned(),
+        ScalarValue::Boolean(Some(_))
+        | ScalarValue::Int8(Some(_))
+        | ScalarValue::Int16(Some(_))
+        | ScalarValue::Int32(Some(_))
+        | ScalarValue::Int64(Some(_))
+        | ScalarValue::UInt8(Some(_))
+        | ScalarValue::UInt16(Some(_))
+        | ScalarValue::UInt32(Some(_))
+        | ScalarValue::UInt64(Some(_)) => value.to_string(),
+        _ => return false,
+    };
+
+    let prefix = format!("{}=", column.name);
+    file_path
+        .split('/')
+        .find_map(|segment| segment.strip_prefix(&prefix))
+        .is_some_and(|partition_value| partition_value != value)
+}
+
+/// Checks if the file lies under a partition bucket other than the one an equality filter on the
+/// client supplied partition key hashes to, e.g. `p_partition_key = 'tenant-a'`
+fn is_pruned_by_partition_key(file_path: &str, buckets: usize, filter: &Expr) -> bool {
+    let Expr::BinaryExpr(BinaryExpr {
+        left,
+        op: Operator::Eq,
+        right,
+    }) = filter
+    else {
+        return false;
+    };
+    let (
+        Expr::Column(column),
+        Expr::Literal(
+            ScalarValue::Utf8(Some(partition_key))
+            | ScalarValue::LargeUtf8(Some(partition_key))
+            | ScalarValue::Utf8View(Some(partition_key)),
+        ),
+    ) = (left.as_ref(), right.as_ref())
+    else {
+        return false;
+    };
+    if column.name != PARTITION_KEY {
+        return false;
+    }
+
+    let bucket = partition_bucket(partition_key, buckets);
+    let prefix = format!("{PARTITION_BUCKET_KEY}=");
+    file_path
+        .split('/')
+        .find_map(|segment| segment.strip_prefix(&prefix))
+        .is_some_and(|file_bucket| file_bucket != bucket)
+}
+
 pub trait ManifestExt: ManifestFile {
     fn find_matching_column(&self, partial_filter: &Expr) -> Option<&Column> {
         let name = match partial_filter {
@@ -970,7 +1278,13 @@ fn cast_or_none(scalar: &ScalarValue) -> Option<CastRes<'_>> {
         ScalarValue::UInt64(val) => val.map(|val| CastRes::Int(val as i64)),
         ScalarValue::Utf8(val) => val.as_ref().map(|val| CastRes::String(val)),
         ScalarValue::Date32(val) => val.map(|val| CastRes::Int(val as i64)),
-        ScalarValue::TimestampMillisecond(val, _) => val.map(CastRes::Int),
+        // statistics of timestamp columns are in the precision timestamps are stored in
+        ScalarValue::TimestampSecond(..)
+        | ScalarValue::TimestampMillisecond(..)
+        | ScalarValue::TimestampMicrosecond(..)
+        | ScalarValue::TimestampNanosecond(..) => {
+            scalar_timestamp(scalar).map(|time| CastRes::Int(to_timestamp(time, timestamp_unit())))
+        }
         _ => None,
     }
 }
@@ -1005,18 +1319,85 @@ fn satisfy_constraints(value: CastRes, op: Operator, stats: &TypedStatistics) ->
 
 #[cfg(test)]
 mod tests {
-    use std::ops::Add;
+    use std::{collections::HashMap, fs::File, ops::Add, path::Path, sync::Arc};
 
+    use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
     use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
     use datafusion::{
+        common::Constraints,
+        datasource::{
+            file_format::{parquet::ParquetFormat, FileFormat},
+            listing::PartitionedFile,
+            physical_plan::FileScanConfig,
+            MemTable,
+        },
+        execution::object_store::ObjectStoreUrl,
         logical_expr::{BinaryExpr, Operator},
-        prelude::Expr,
+        physical_plan::{collect, Statistics},
+        prelude::{Expr, SessionContext},
         scalar::ScalarValue,
     };
+    use itertools::Itertools;
+    use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+    use temp_dir::TempDir;
+
+    use crate::{
+        catalog::{
+            column::{Int64Type, TypedStatistics},
+            snapshot::ManifestItem,
+        },
+        event::{DEFAULT_TIMESTAMP_KEY, PARTITION_BUCKET_KEY, PARTITION_KEY},
+    };
 
-    use crate::catalog::snapshot::ManifestItem;
+    use super::{
+        cast_or_none, extract_timestamp_bound, fetch_concurrently, is_overlapping_query,
+        is_pruned_by_partition, is_pruned_by_partition_key, partition_bucket, read_schema,
+        satisfy_constraints, superset_schema, with_column_aliases, FileTier, PartialTimeFilter,
+        StandardTableProvider,
+    };
 
-    use super::{extract_timestamp_bound, is_overlapping_query, PartialTimeFilter};
+    #[test]
+    fn time_range_is_compared_in_millisecond_precision() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_milli_opt(10, 0, 0, 123)
+            .unwrap();
+        let millis = start.and_utc().timestamp_millis();
+
+        let Expr::BinaryExpr(filter) = PartialTimeFilter::Low(std::ops::Bound::Included(start))
+            .binary_expr(Expr::Column(DEFAULT_TIMESTAMP_KEY.into()))
+        else {
+            panic!("time filter is a binary expression")
+        };
+        assert_eq!(
+            *filter.right,
+            Expr::Literal(ScalarValue::TimestampMillisecond(Some(millis), None))
+        );
+        assert_eq!(
+            extract_timestamp_bound(&filter, &None),
+            Some((Operator::GtEq, start))
+        );
+
+        // a range given in another precision is compared against the millisecond statistics
+        let nanos = ScalarValue::TimestampNanosecond(Some(millis * 1_000_000), None);
+        let in_file = TypedStatistics::Int(Int64Type {
+            min: millis - 1000,
+            max: millis,
+        });
+        let before_file = TypedStatistics::Int(Int64Type {
+            min: millis - 2000,
+            max: millis - 1,
+        });
+        assert_eq!(
+            satisfy_constraints(cast_or_none(&nanos).unwrap(), Operator::GtEq, &in_file),
+            Some(true)
+        );
+        assert_eq!(
+            satisfy_constraints(cast_or_none(&nanos).unwrap(), Operator::GtEq, &before_file),
+            Some(false)
+        );
+    }
 
     fn datetime_min(year: i32, month: u32, day: u32) -> DateTime<Utc> {
         NaiveDate::from_ymd_opt(year, month, day)
@@ -1239,4 +1620,551 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn custom_partition_filter_prunes_other_prefixes() {
+        let files = [
+            "app/date=2025-01-01/hour=10/minute=00/region=us/host.data.parquet",
+            "app/date=2025-01-01/hour=10/minute=00/region=eu/host.data.parquet",
+            "app/date=2025-01-01/hour=10/minute=01/region=us/host.data.parquet",
+        ];
+        let filter = Expr::BinaryExpr(BinaryExpr::new(
+            Box::new(Expr::Column(datafusion::common::Column::from_name(
+                "region",
+            ))),
+            Operator::Eq,
+            Box::new(Expr::Literal(ScalarValue::Utf8(Some("us".to_owned())))),
+        ));
+
+        let scanned: Vec<_> = files
+            .into_iter()
+            .filter(|file| !is_pruned_by_partition(file, &["region"], &filter))
+            .collect();
+        assert_eq!(scanned.len(), 2);
+        assert!(scanned.iter().all(|file| file.contains("/region=us/")));
+
+        // filters on columns that aren't partitions are left to other mechanisms
+        assert!(!is_pruned_by_partition(files[1], &[], &filter));
+    }
+
+    #[tokio::test]
+    async fn custom_partition_query_scans_only_its_prefixes() {
+        use bytes::Bytes;
+        use chrono::{TimeDelta, TimeZone};
+        use parquet::{
+            file::properties::{EnabledStatistics, WriterProperties},
+            format::SortingColumn,
+        };
+
+        use crate::{
+            catalog,
+            parseable::PARSEABLE,
+            query::{Query, QUERY_SESSION},
+            storage::{object_storage::stream_object_key, ObjectStoreFormat},
+            utils::time::TimeRange,
+        };
+
+        let stream_name = "region_partitioned";
+        let custom_partition = "region".to_owned();
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, 30, 0).unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+            Field::new("region", DataType::Utf8, true),
+        ]));
+        stream.set_schema(&schema);
+        let store = PARSEABLE.storage.get_object_store();
+        store
+            .create_stream(
+                stream_name,
+                ObjectStoreFormat {
+                    custom_partition: Some(custom_partition.clone()),
+                    ..Default::default()
+                },
+                schema.clone(),
+            )
+            .await
+            .unwrap();
+
+        let data_store = PARSEABLE.data_store(stream_name);
+        for region in ["us", "eu"] {
+            let rb = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])),
+                    Arc::new(StringArray::from(vec![region])),
+                ],
+            )
+            .unwrap();
+            // without column statistics only the partition prefix can prune the file
+            let props = WriterProperties::builder()
+                .set_statistics_enabled(EnabledStatistics::None)
+                .set_sorting_columns(Some(vec![SortingColumn::new(0, true, false)]))
+                .build();
+            let mut parquet = vec![];
+            let mut writer =
+                ArrowWriter::try_new(&mut parquet, schema.clone(), Some(props)).unwrap();
+            writer.write(&rb).unwrap();
+            writer.close().unwrap();
+            let parquet = Bytes::from(parquet);
+
+            let key = stream_object_key(
+                stream_name,
+                None,
+                &format!("date=2025-01-01.hour=10.minute=30.region={region}.host.data.parquet"),
+                Some(&custom_partition),
+            );
+            data_store.put_object(&key, parquet.clone()).await.unwrap();
+            let file = catalog::manifest::create_from_parquet(
+                data_store.absolute_url(&key).to_string(),
+                parquet.clone(),
+                parquet.len() as u64,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+        }
+
+        let query = Query {
+            raw_logical_plan: QUERY_SESSION
+                .state()
+                .create_logical_plan(&format!("select * from {stream_name} where region = 'us'"))
+                .await
+                .unwrap(),
+            time_range: TimeRange::new(at - TimeDelta::hours(1), at + TimeDelta::hours(1)),
+            filter_tag: None,
+        };
+        let scanned = query.scanned_files(None).await.unwrap();
+        assert_eq!(scanned.len(), 1);
+        assert!(scanned[0].path.contains("/region=us/"));
+    }
+
+    #[tokio::test]
+    async fn object_store_requests_are_bounded_by_concurrency() {
+        use futures_util::StreamExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let requests = (0..20).map(|i| {
+            let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+            async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                i
+            }
+        });
+
+        let responses: Vec<_> = fetch_concurrently(requests, 4).collect().await;
+        assert_eq!(responses, (0..20).collect::<Vec<_>>());
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn partition_key_filter_scans_only_its_bucket() {
+        let buckets = 16;
+        let tenant_a = partition_bucket("tenant-a", buckets);
+        // another tenant whose events land in a different partition
+        let tenant_b = (0..)
+            .map(|i| partition_bucket(&format!("tenant-{i}"), buckets))
+            .find(|bucket| bucket != &tenant_a)
+            .unwrap();
+
+        let files = [
+            format!("app/date=2025-01-01/hour=10/minute=00/{PARTITION_BUCKET_KEY}={tenant_a}/host.data.parquet"),
+            format!("app/date=2025-01-01/hour=10/minute=00/{PARTITION_BUCKET_KEY}={tenant_b}/host.data.parquet"),
+        ];
+        let filter = Expr::BinaryExpr(BinaryExpr::new(
+            Box::new(Expr::Column(datafusion::common::Column::from_name(
+                PARTITION_KEY,
+            ))),
+            Operator::Eq,
+            Box::new(Expr::Literal(ScalarValue::Utf8(Some(
+                "tenant-a".to_owned(),
+            )))),
+        ));
+
+        let scanned: Vec<_> = files
+            .iter()
+            .filter(|file| !is_pruned_by_partition_key(file, buckets, &filter))
+            .collect();
+        assert_eq!(scanned, vec![&files[0]]);
+
+        // files ingested without a partition key can't be pruned
+        assert!(!is_pruned_by_partition_key(
+            "app/date=2025-01-01/hour=10/minute=00/host.data.parquet",
+            buckets,
+            &filter
+        ));
+    }
+
+    #[test]
+    fn compacted_tier_excludes_recent_small_files() {
+        let file = |file_path: &str| crate::catalog::manifest::File {
+            file_path: file_path.to_owned(),
+            num_rows: 10,
+            file_size: 100,
+            ingestion_size: 100,
+            columns: vec![],
+            sort_order_id: vec![],
+        };
+        let files = [
+            file("s3://bucket/app/date=2025-01-01/hour=10/minute=00/01J.compacted.parquet"),
+            file("s3://bucket/app/date=2025-01-01/hour=10/minute=01/host.data.parquet"),
+            file("s3://bucket/app/date=2025-01-01/hour=10/minute=02/host.data.parquet"),
+        ];
+        let scanned = |tier: FileTier| files.iter().filter(|file| tier.includes(file)).count();
+
+        assert_eq!(scanned(FileTier::All), 3);
+        assert_eq!(scanned(FileTier::Compacted), 1);
+        assert_eq!(scanned(FileTier::Recent), 2);
+    }
+
+    #[test]
+    fn skip_cache_does_not_read_from_hot_tier() {
+        let dir = TempDir::new().unwrap();
+        let hot_tier_path: &'static Path = Box::leak(dir.path().to_path_buf().into_boxed_path());
+        let hot_tier_manager = crate::hottier::HotTierManager::new(hot_tier_path);
+        std::fs::create_dir_all(hot_tier_path.join("app")).unwrap();
+        std::fs::write(
+            hot_tier_path
+                .join("app")
+                .join(crate::hottier::STREAM_HOT_TIER_FILENAME),
+            "{}",
+        )
+        .unwrap();
+
+        let table = |skip_cache: bool| StandardTableProvider {
+            schema: Arc::new(Schema::empty()),
+            tier: FileTier::All,
+            skip_cache,
+            stream: "app".to_owned(),
+            url: url::Url::parse("file:///").unwrap(),
+            bucket: None,
+        };
+
+        assert!(table(false).hot_tier(Some(&hot_tier_manager)).is_some());
+        assert!(table(true).hot_tier(Some(&hot_tier_manager)).is_none());
+    }
+
+    #[tokio::test]
+    async fn skip_cache_does_not_read_through_object_store_cache() {
+        use datafusion::execution::object_store::{
+            DefaultObjectStoreRegistry, ObjectStoreRegistry,
+        };
+        use object_store::{memory::InMemory, path::Path, ObjectStore, PutPayload};
+
+        use crate::storage::uncached_url;
+
+        let url = url::Url::parse("s3://bucket").unwrap();
+        let cached: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let uncached: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let location = Path::from("app/date=2025-01-01/data.parquet");
+        cached
+            .put(&location, PutPayload::from_static(b"cached"))
+            .await
+            .unwrap();
+        uncached
+            .put(&location, PutPayload::from_static(b"object store"))
+            .await
+            .unwrap();
+        let registry = DefaultObjectStoreRegistry::new();
+        registry.register_store(&url, cached);
+        registry.register_store(&uncached_url(&url), uncached);
+
+        let table = |skip_cache: bool| StandardTableProvider {
+            schema: Arc::new(Schema::empty()),
+            tier: FileTier::All,
+            skip_cache,
+            stream: "app".to_owned(),
+            url: url.clone(),
+            bucket: None,
+        };
+        let read = |skip_cache: bool| {
+            let store = registry
+                .get_store(&table(skip_cache).store_url(&registry))
+                .unwrap();
+            let location = location.clone();
+            async move { store.get(&location).await.unwrap().bytes().await.unwrap() }
+        };
+        assert_eq!(read(false).await.as_ref(), b"cached");
+        assert_eq!(read(true).await.as_ref(), b"object store");
+
+        // stores without a cache are read from as is
+        let local = DefaultObjectStoreRegistry::new();
+        let file_url = url::Url::parse("file:///").unwrap();
+        let table = StandardTableProvider {
+            url: file_url.clone(),
+            ..table(true)
+        };
+        assert_eq!(table.store_url(&local), file_url);
+    }
+
+    fn write_parquet(path: &Path, columns: Vec<(&str, ArrayRef)>) -> PartitionedFile {
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+        let mut writer =
+            ArrowWriter::try_new(File::create(path).unwrap(), batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let size = path.metadata().unwrap().len();
+        PartitionedFile::new(path.display().to_string(), size)
+    }
+
+    #[tokio::test]
+    async fn files_missing_newer_columns_are_read_as_nulls() {
+        let dir = TempDir::new().unwrap();
+        // written before the stream started recording the ingestion timestamp
+        let old = write_parquet(
+            &dir.path().join("old.parquet"),
+            vec![("msg", Arc::new(StringArray::from(vec!["old"])) as ArrayRef)],
+        );
+        let new = write_parquet(
+            &dir.path().join("new.parquet"),
+            vec![
+                (
+                    DEFAULT_TIMESTAMP_KEY,
+                    Arc::new(TimestampMillisecondArray::from(vec![1_000])) as ArrayRef,
+                ),
+                ("msg", Arc::new(StringArray::from(vec!["new"])) as ArrayRef),
+            ],
+        );
+
+        let stream_schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("msg", DataType::Utf8, true),
+        ]);
+        let file_schema = read_schema(&stream_schema);
+
+        let ctx = SessionContext::new();
+        let plan = ParquetFormat::default()
+            .create_physical_plan(
+                &ctx.state(),
+                FileScanConfig {
+                    object_store_url: ObjectStoreUrl::parse("file:///").unwrap(),
+                    file_schema: file_schema.clone(),
+                    file_groups: vec![vec![old, new]],
+                    statistics: Statistics::new_unknown(&file_schema),
+                    projection: None,
+                    limit: None,
+                    output_ordering: vec![],
+                    table_partition_cols: vec![],
+                    constraints: Constraints::empty(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let batches = collect(plan, ctx.task_ctx()).await.unwrap();
+
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 2);
+        let missing: usize = batches
+            .iter()
+            .map(|batch| batch.column(0).null_count())
+            .sum();
+        assert_eq!(missing, 1);
+    }
+
+    #[tokio::test]
+    async fn files_across_schema_versions_are_read_with_superset() {
+        let dir = TempDir::new().unwrap();
+        let timestamps = || Arc::new(TimestampMillisecondArray::from(vec![1_000])) as ArrayRef;
+        // written before `level` was dropped from the schema of the stream
+        let pre_path = dir.path().join("pre.parquet");
+        let pre = write_parquet(
+            &pre_path,
+            vec![
+                (DEFAULT_TIMESTAMP_KEY, timestamps()),
+                ("msg", Arc::new(StringArray::from(vec!["pre"])) as ArrayRef),
+                (
+                    "level",
+                    Arc::new(StringArray::from(vec!["info"])) as ArrayRef,
+                ),
+            ],
+        );
+        let post = write_parquet(
+            &dir.path().join("post.parquet"),
+            vec![
+                (DEFAULT_TIMESTAMP_KEY, timestamps()),
+                ("msg", Arc::new(StringArray::from(vec!["post"])) as ArrayRef),
+            ],
+        );
+
+        let stream_schema = Schema::new(vec![
+            Field::new(
+                DEFAULT_TIMESTAMP_KEY,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("msg", DataType::Utf8, true),
+        ]);
+        let pre_schema = ParquetRecordBatchReaderBuilder::try_new(File::open(&pre_path).unwrap())
+            .unwrap()
+            .schema()
+            .as_ref()
+            .clone();
+        let superset = superset_schema(&stream_schema, [&pre_schema]);
+        let names = superset
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect_vec();
+        assert_eq!(names, [DEFAULT_TIMESTAMP_KEY, "msg", "level"]);
+
+        let file_schema = read_schema(&superset);
+        let ctx = SessionContext::new();
+        let plan = ParquetFormat::default()
+            .create_physical_plan(
+                &ctx.state(),
+                FileScanConfig {
+                    object_store_url: ObjectStoreUrl::parse("file:///").unwrap(),
+                    file_schema: file_schema.clone(),
+                    file_groups: vec![vec![pre, post]],
+                    statistics: Statistics::new_unknown(&file_schema),
+                    projection: None,
+                    limit: None,
+                    output_ordering: vec![],
+                    table_partition_cols: vec![],
+                    constraints: Constraints::empty(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let batches = collect(plan, ctx.task_ctx()).await.unwrap();
+
+        let mut rows = vec![];
+        for batch in &batches {
+            let msg = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            let level = batch
+                .column(2)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            for i in 0..batch.num_rows() {
+                rows.push((
+                    msg.value(i).to_owned(),
+                    level.is_valid(i).then(|| level.value(i).to_owned()),
+                ));
+            }
+        }
+        rows.sort();
+        assert_eq!(
+            rows,
+            [
+                ("post".to_owned(), None),
+                ("pre".to_owned(), Some("info".to_owned()))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn renamed_column_is_queried_by_old_name() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, true),
+            Field::new("hostname", DataType::Utf8, true),
+            Field::new("region", DataType::Utf8, true),
+        ]));
+        // events before and after `host` was renamed to `hostname` and `region` to `zone`
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("web-1"), None])),
+                Arc::new(StringArray::from(vec![None, Some("web-2")])),
+                Arc::new(StringArray::from(vec![Some("eu"), Some("us")])),
+            ],
+        )
+        .unwrap();
+        let table = Arc::new(MemTable::try_new(schema, vec![vec![batch]]).unwrap());
+        let column_aliases = HashMap::from([
+            ("host".to_owned(), "hostname".to_owned()),
+            ("zone".to_owned(), "region".to_owned()),
+        ]);
+
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "app",
+            with_column_aliases("app", table, &column_aliases).unwrap(),
+        )
+        .unwrap();
+        let batches = ctx
+            .sql("SELECT host, zone FROM app ORDER BY zone")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let column = |i: usize| {
+            batches[0]
+                .column(i)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .iter()
+                .map(|value| value.unwrap().to_owned())
+                .collect_vec()
+        };
+        assert_eq!(column(0), ["web-1", "web-2"]);
+        assert_eq!(column(1), ["eu", "us"]);
+    }
+
+    #[tokio::test]
+    async fn old_name_of_a_column_renamed_with_another_type_has_its_data_cast() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("status", DataType::Int64, true),
+            Field::new("status_code", DataType::Utf8, true),
+        ]));
+        // `status` was renamed to `status_code`, which is of another type
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![Some(200), None])),
+                Arc::new(StringArray::from(vec![None, Some("404")])),
+            ],
+        )
+        .unwrap();
+        let table = Arc::new(MemTable::try_new(schema, vec![vec![batch]]).unwrap());
+        let column_aliases = HashMap::from([("status".to_owned(), "status_code".to_owned())]);
+
+        let ctx = SessionContext::new();
+        ctx.register_table(
+            "app",
+            with_column_aliases("app", table, &column_aliases).unwrap(),
+        )
+        .unwrap();
+        let batches = ctx
+            .sql("SELECT status FROM app ORDER BY status")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let status = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .iter()
+            .map(|value| value.unwrap().to_owned())
+            .collect_vec();
+        assert_eq!(status, ["200", "404"]);
+    }
 }
@@ -0,0 +1,87 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::role::Action;
+
+/// Access control list of a stream, maps principals, i.e. usernames or names of API tokens
+/// prefixed with `token:`, to the permissions they are granted on the stream
+pub type StreamAcl = BTreeMap<String, Vec<StreamPermission>>;
+
+/// Permissions that can be granted to a principal on a single stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamPermission {
+    Ingest,
+    Query,
+    Admin,
+}
+
+impl StreamPermission {
+    /// Whether the permission allows `action` on the stream it is granted on
+    pub fn allows(&self, action: Action) -> bool {
+        match self {
+            Self::Admin => true,
+            Self::Ingest => action == Action::Ingest,
+            Self::Query => matches!(
+                action,
+                Action::Query
+                    | Action::GetSchema
+                    | Action::GetStats
+                    | Action::GetStreamInfo
+                    | Action::GetRetention
+            ),
+        }
+    }
+}
+
+/// Checks whether `principal` may perform `action` on a stream with the given ACL.
+///
+/// Returns `None` if the principal isn't listed in the ACL, such principals are authorized by
+/// their roles alone. For principals that are listed, the ACL takes precedence over their roles.
+pub fn check(acl: &StreamAcl, principal: &str, action: Action) -> Option<bool> {
+    acl.get(principal).map(|permissions| {
+        permissions
+            .iter()
+            .any(|permission| permission.allows(action))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rbac::role::Action;
+
+    use super::{check, StreamAcl, StreamPermission};
+
+    #[test]
+    fn query_only_principal_is_denied_ingestion() {
+        let acl: StreamAcl =
+            serde_json::from_str(r#"{"alice": ["query"], "bob": ["admin"]}"#).unwrap();
+
+        assert_eq!(check(&acl, "alice", Action::Query), Some(true));
+        assert_eq!(check(&acl, "alice", Action::Ingest), Some(false));
+        assert_eq!(check(&acl, "bob", Action::Ingest), Some(true));
+        assert_eq!(check(&acl, "bob", Action::DeleteStream), Some(true));
+        // left to roles
+        assert_eq!(check(&acl, "carol", Action::Ingest), None);
+        assert_eq!(acl["alice"], [StreamPermission::Query]);
+    }
+}
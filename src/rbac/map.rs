@@ -88,7 +88,7 @@ pub fn mut_sessions() -> RwLockWriteGuard<'static, Sessions> {
 // the user_map is initialized from the config file and has a list of all users
 // the auth_map is initialized with admin user only and then gets lazily populated
 // as users authenticate
-pub fn init(metadata: &StorageMetadata) {
+pub fn init(metadata: &StorageMetadata) -> anyhow::Result<()> {
     let users = metadata.users.clone();
     let mut roles = metadata.roles.clone();
 
@@ -122,6 +122,20 @@ pub fn init(metadata: &StorageMetadata) {
     SESSIONS
         .set(RwLock::new(sessions))
         .expect("map is only set once");
+
+    super::token::init()
+}
+
+/// Initializes the maps once for all tests of the process, API tokens are those of
+/// [`super::token::test_tokens`]
+#[cfg(test)]
+pub fn init_for_tests() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        // set before `init`, which would set the default validator otherwise
+        let _ = super::token::set_validator(Box::new(super::token::test_tokens()));
+        init(&StorageMetadata::default()).expect("API tokens of tests are set");
+    });
 }
 
 // A session is loosly active mapping to permissions
@@ -131,6 +145,7 @@ pub fn init(metadata: &StorageMetadata) {
 pub enum SessionKey {
     BasicAuth { username: String, password: String },
     SessionId(ulid::Ulid),
+    ApiToken(String),
 }
 
 #[derive(Debug, Default)]
@@ -188,6 +203,18 @@ impl Sessions {
         sessions.retain(|(_, expiry)| expiry < &now);
     }
 
+    /// Whether the session has outlived its expiry, sessions whose expiry is no longer tracked
+    /// are taken as expired
+    pub fn is_expired(&self, key: &SessionKey) -> bool {
+        let Some((user, _)) = self.active_sessions.get(key) else {
+            return false;
+        };
+        self.user_sessions
+            .get(user)
+            .and_then(|sessions| sessions.iter().find(|(session, _)| session == key))
+            .is_none_or(|(_, expiry)| *expiry <= Utc::now())
+    }
+
     // get permission related to this session
     pub fn get(&self, key: &SessionKey) -> Option<&Vec<Permission>> {
         self.active_sessions.get(key).map(|(_, perms)| perms)
@@ -16,8 +16,10 @@
  *
  */
 
+pub mod acl;
 pub mod map;
 pub mod role;
+pub mod token;
 pub mod user;
 pub mod utils;
 
@@ -29,6 +31,7 @@ use role::model::DefaultPrivilege;
 use serde::Serialize;
 use url::Url;
 
+use crate::parseable::PARSEABLE;
 use crate::rbac::map::{mut_sessions, mut_users, sessions, users};
 use crate::rbac::role::Action;
 use crate::rbac::user::User;
@@ -129,11 +132,59 @@ impl Users {
         context_stream: Option<&str>,
         context_user: Option<&str>,
     ) -> Response {
+        let response = self.authorize_by_role(key.clone(), action, context_stream, context_user);
+        if response == Response::ReloadRequired {
+            return response;
+        }
+
+        // the ACL of the stream takes precedence over roles for the principals it lists
+        let allowed = context_stream
+            .zip(self.get_username_from_session(&key))
+            .and_then(|(stream, username)| {
+                let settings = PARSEABLE.get_stream(stream).ok()?.get_settings();
+                acl::check(&settings.acl, &username, action)
+            });
+        match allowed {
+            Some(true) => Response::Authorized,
+            Some(false) => Response::UnAuthorized,
+            None => response,
+        }
+    }
+
+    fn authorize_by_role(
+        &self,
+        key: SessionKey,
+        action: Action,
+        context_stream: Option<&str>,
+        context_user: Option<&str>,
+    ) -> Response {
+        // sessions of API tokens expire, so that revoked tokens are validated again
+        if matches!(key, SessionKey::ApiToken(_)) && sessions().is_expired(&key) {
+            mut_sessions().remove_session(&key);
+        }
+
         // try fetch from auth map for faster auth flow
         if let Some(res) = sessions().check_auth(&key, action, context_stream, context_user) {
             return res;
         }
 
+        // API tokens are validated once, the scope is then held as a session
+        if let SessionKey::ApiToken(token) = &key {
+            let Some(scope) = token::validate(token) else {
+                return Response::ReloadRequired;
+            };
+            let mut sessions = mut_sessions();
+            sessions.track_new(
+                scope.principal(),
+                key.clone(),
+                token::session_expiry(),
+                scope.permissions(),
+            );
+            return sessions
+                .check_auth(&key, action, context_stream, context_user)
+                .expect("entry for this key just added");
+        }
+
         // attempt reloading permissions into new session for basic auth user
         // id user will be reloaded only through login endpoint
         let SessionKey::BasicAuth { username, password } = &key else {
@@ -200,3 +251,53 @@ fn roles_to_permission(roles: Vec<String>) -> Vec<Permission> {
     }
     perms.into_iter().collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use chrono::{DateTime, Utc};
+
+    use crate::{metadata::StreamSettings, parseable::PARSEABLE};
+
+    use super::{
+        map::{mut_sessions, SessionKey, Sessions, SESSIONS},
+        role::{Action, Permission},
+        Response, Users,
+    };
+
+    #[test]
+    fn acl_rejects_ingestion_by_query_only_user() {
+        SESSIONS.get_or_init(|| RwLock::new(Sessions::default()));
+        let stream_name = "acl_rejects_ingestion";
+        PARSEABLE
+            .get_or_create_stream(stream_name)
+            .set_settings(StreamSettings {
+                acl: serde_json::from_str(r#"{"alice": ["query"]}"#).unwrap(),
+                ..Default::default()
+            });
+
+        // alice is an admin by role, the ACL of the stream still restricts her to queries
+        let key = SessionKey::BasicAuth {
+            username: "alice".to_owned(),
+            password: "secret".to_owned(),
+        };
+        mut_sessions().track_new(
+            "alice".to_owned(),
+            key.clone(),
+            DateTime::<Utc>::MAX_UTC,
+            vec![Permission::Unit(Action::All)],
+        );
+
+        assert!(
+            Users.authorize(key.clone(), Action::Ingest, Some(stream_name), None)
+                == Response::UnAuthorized
+        );
+        assert!(
+            Users.authorize(key.clone(), Action::Query, Some(stream_name), None)
+                == Response::Authorized
+        );
+        // other streams are left to roles
+        assert!(Users.authorize(key, Action::Ingest, Some("other"), None) == Response::Authorized);
+    }
+}
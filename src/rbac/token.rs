@@ -0,0 +1,325 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::parseable::PARSEABLE;
+
+use super::role::{Action, Permission};
+
+static VALIDATOR: OnceCell<Box<dyn TokenValidator>> = OnceCell::new();
+
+/// Prefix of the principals API tokens are attributed to, usernames can't contain `:` so a token
+/// is never mistaken for a user of the same name
+pub const PRINCIPAL_PREFIX: &str = "token:";
+
+/// Seconds the scope of a validated token is held as a session, revoked tokens stop working once
+/// it expires and the token is validated again
+const SESSION_TTL_SECS: i64 = 300;
+
+/// Operations an API token can be scoped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenAction {
+    Ingest,
+    Query,
+}
+
+/// Streams and operations an API token can be used for
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenScope {
+    /// Name of the token, requests made with the token are attributed to it
+    pub name: String,
+    /// Streams the token has access to, `*` grants access to all streams
+    pub streams: Vec<String>,
+    pub actions: Vec<TokenAction>,
+}
+
+impl TokenScope {
+    /// Principal requests made with the token are attributed to, e.g. in the ACLs of streams
+    pub fn principal(&self) -> String {
+        format!("{PRINCIPAL_PREFIX}{}", self.name)
+    }
+
+    pub fn permissions(&self) -> Vec<Permission> {
+        self.streams
+            .iter()
+            .cartesian_product(&self.actions)
+            .map(|(stream, action)| match action {
+                TokenAction::Ingest => Permission::Stream(Action::Ingest, stream.clone()),
+                TokenAction::Query => {
+                    Permission::StreamWithTag(Action::Query, stream.clone(), None)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Validates API tokens sent as bearer tokens, implementations can authenticate
+/// tokens against an external identity provider.
+pub trait TokenValidator: Send + Sync {
+    /// Returns the scope of `token`, `None` if the token is not valid
+    fn validate(&self, token: &str) -> Option<TokenScope>;
+}
+
+/// Tokens listed in the file at `P_API_TOKENS_FILE`, as a JSON array of
+/// `{"token": "...", "name": "...", "streams": [...], "actions": [...]}`
+#[derive(Debug, Default)]
+pub struct StaticTokens(HashMap<String, TokenScope>);
+
+#[derive(Debug, Deserialize)]
+struct TokenEntry {
+    token: String,
+    #[serde(flatten)]
+    scope: TokenScope,
+}
+
+impl StaticTokens {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let entries: Vec<TokenEntry> = serde_json::from_slice(&fs::read(path)?)?;
+
+        Ok(Self(
+            entries
+                .into_iter()
+                .map(|TokenEntry { token, scope }| (token, scope))
+                .collect(),
+        ))
+    }
+}
+
+/// Tokens that tests authenticate with, `s3cr3t` may only ingest into `app` and `token_app`
+#[cfg(test)]
+pub fn test_tokens() -> StaticTokens {
+    StaticTokens(
+        [(
+            "s3cr3t".to_owned(),
+            TokenScope {
+                name: "fluent-bit".to_owned(),
+                streams: vec!["app".to_owned(), "token_app".to_owned()],
+                actions: vec![TokenAction::Ingest],
+            },
+        )]
+        .into(),
+    )
+}
+
+impl TokenValidator for StaticTokens {
+    fn validate(&self, token: &str) -> Option<TokenScope> {
+        self.0.get(token).cloned()
+    }
+}
+
+/// Replaces the default validator of API tokens, fails if the validator is already in use
+pub fn set_validator(validator: Box<dyn TokenValidator>) -> Result<(), Box<dyn TokenValidator>> {
+    VALIDATOR.set(validator)
+}
+
+/// Loads the tokens configured with `P_API_TOKENS_FILE` unless a validator was already set
+pub fn init() -> anyhow::Result<()> {
+    VALIDATOR.get_or_try_init(|| {
+        let tokens = match &PARSEABLE.options.api_tokens_file {
+            Some(path) => StaticTokens::from_file(path)
+                .with_context(|| format!("Failed to load API tokens from {path:?}"))?,
+            None => StaticTokens::default(),
+        };
+        anyhow::Ok(Box::new(tokens) as Box<dyn TokenValidator>)
+    })?;
+
+    Ok(())
+}
+
+pub fn validate(token: &str) -> Option<TokenScope> {
+    VALIDATOR.get()?.validate(token)
+}
+
+/// Expiry of the session of a token validated now
+pub fn session_expiry() -> DateTime<Utc> {
+    Utc::now() + TimeDelta::seconds(SESSION_TTL_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    use crate::{
+        rbac::{
+            map::{SessionKey, Sessions},
+            role::Action,
+            Response,
+        },
+        utils::actix::extract_session_key_from_req,
+    };
+
+    use super::{session_expiry, test_tokens, TokenValidator};
+
+    fn authorize(token: &str, action: Action, stream: &str) -> Option<Response> {
+        let scope = test_tokens().validate(token)?;
+        let key = SessionKey::ApiToken(token.to_owned());
+        let mut sessions = Sessions::default();
+        sessions.track_new(
+            scope.principal(),
+            key.clone(),
+            session_expiry(),
+            scope.permissions(),
+        );
+
+        sessions.check_auth(&key, action, Some(stream), None)
+    }
+
+    #[test]
+    fn valid_token_is_authorized_for_its_scope() {
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "Bearer s3cr3t"))
+            .to_http_request();
+        assert_eq!(
+            extract_session_key_from_req(&req).unwrap(),
+            SessionKey::ApiToken("s3cr3t".to_owned())
+        );
+
+        assert!(authorize("s3cr3t", Action::Ingest, "app") == Some(Response::Authorized));
+        assert!(authorize("invalid", Action::Ingest, "app").is_none());
+    }
+
+    #[test]
+    fn token_is_forbidden_outside_its_scope() {
+        assert!(authorize("s3cr3t", Action::Ingest, "billing") == Some(Response::UnAuthorized));
+        assert!(authorize("s3cr3t", Action::Query, "app") == Some(Response::UnAuthorized));
+    }
+
+    #[test]
+    fn token_sessions_expire_and_are_attributed_to_the_token() {
+        use chrono::{TimeDelta, Utc};
+
+        use crate::rbac::{
+            map::{init_for_tests, mut_sessions},
+            role::Permission,
+            Users,
+        };
+
+        init_for_tests();
+        // a token that was revoked since its session was tracked
+        let revoked = SessionKey::ApiToken("revoked".to_owned());
+        mut_sessions().track_new(
+            "token:revoked".to_owned(),
+            revoked.clone(),
+            Utc::now() - TimeDelta::seconds(1),
+            vec![Permission::Stream(Action::Ingest, "app".to_owned())],
+        );
+        assert!(
+            Users.authorize(revoked.clone(), Action::Ingest, Some("app"), None)
+                == Response::ReloadRequired
+        );
+        assert!(!Users.session_exists(&revoked));
+
+        // valid tokens are held as sessions of their own principal, apart from users
+        let key = SessionKey::ApiToken("s3cr3t".to_owned());
+        assert!(
+            Users.authorize(key.clone(), Action::Ingest, Some("app"), None) == Response::Authorized
+        );
+        assert_eq!(
+            Users.get_username_from_session(&key).as_deref(),
+            Some("token:fluent-bit")
+        );
+        assert!(!Users.contains("token:fluent-bit"));
+    }
+
+    #[test]
+    fn missing_token_is_unauthenticated() {
+        let req = TestRequest::default().to_http_request();
+        let err = extract_session_key_from_req(&req).unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[actix_web::test]
+    async fn ingestion_is_authorized_by_the_scope_of_the_token() {
+        use actix_web::{
+            dev::ServiceResponse,
+            http::header::HeaderMap,
+            test::{init_service, try_call_service},
+            App,
+        };
+        use bytes::Bytes;
+
+        use crate::{
+            handlers::http::modal::server::Server, parseable::PARSEABLE, rbac::map::init_for_tests,
+        };
+
+        init_for_tests();
+        for stream_name in ["token_app", "token_billing"] {
+            PARSEABLE
+                .create_update_stream(&HeaderMap::new(), &Bytes::new(), stream_name)
+                .await
+                .unwrap();
+        }
+        let app = init_service(
+            App::new()
+                .service(Server::get_logstream_webscope())
+                .service(Server::get_query_factory()),
+        )
+        .await;
+        let status = |res: Result<ServiceResponse, actix_web::Error>| match res {
+            Ok(res) => res.status(),
+            Err(err) => err.as_response_error().status_code(),
+        };
+        let ingest = |stream_name: &str, token: Option<&str>| {
+            let mut req = TestRequest::post()
+                .uri(&format!("/logstream/{stream_name}"))
+                .set_json(serde_json::json!([{"msg": "hello"}]));
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {token}")));
+            }
+            req.to_request()
+        };
+
+        // events are ingested into the stream the token is scoped to
+        let res = try_call_service(&app, ingest("token_app", Some("s3cr3t"))).await;
+        assert_eq!(status(res), StatusCode::OK);
+
+        // other streams and operations are forbidden
+        let res = try_call_service(&app, ingest("token_billing", Some("s3cr3t"))).await;
+        assert_eq!(status(res), StatusCode::FORBIDDEN);
+        let query = TestRequest::post()
+            .uri("/query")
+            .insert_header(("Authorization", "Bearer s3cr3t"))
+            .set_json(serde_json::json!({
+                "query": "select * from token_app",
+                "startTime": "10m",
+                "endTime": "now"
+            }))
+            .to_request();
+        assert_eq!(
+            status(try_call_service(&app, query).await),
+            StatusCode::FORBIDDEN
+        );
+
+        // unknown or missing tokens aren't authenticated
+        let res = try_call_service(&app, ingest("token_app", Some("invalid"))).await;
+        assert_eq!(status(res), StatusCode::UNAUTHORIZED);
+        let res = try_call_service(&app, ingest("token_app", None)).await;
+        assert_eq!(status(res), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -16,11 +16,277 @@
  *
  */
 
-use crate::{handlers::http::query::QueryError, utils::arrow::record_batches_to_json};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    handlers::http::query::QueryError,
+    storage::{ObjectStorage, ObjectStorageError, PARSEABLE_ROOT_DIRECTORY},
+    utils::arrow::record_batches_to_json,
+};
+use arrow::csv::{Writer, WriterBuilder};
 use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use datafusion::execution::SendableRecordBatchStream;
+use futures::StreamExt;
 use itertools::Itertools;
+use parquet::arrow::ArrowWriter;
+use relative_path::{Component, RelativePath, RelativePathBuf};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tracing::info;
+use tracing::{info, warn};
+use ulid::Ulid;
+
+/// Directory under the parseable root that query results are exported to
+pub const EXPORTS_DIR: &str = "exports";
+
+/// Options for query results returned as CSV, i.e. with `Accept: text/csv`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CsvOptions {
+    /// Include a header row with the field names
+    pub header: bool,
+    pub delimiter: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            header: true,
+            delimiter: ',',
+        }
+    }
+}
+
+/// Format query results are exported to object storage in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Parquet,
+    Csv,
+    Json,
+}
+
+/// Key in object storage, relative to the exporting user's directory under the exports directory,
+/// query results are written to rather than being returned, e.g. for scheduled reports
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryExport {
+    pub key: String,
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Replace results already exported to the key, rather than refusing the export
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+impl QueryExport {
+    /// Path results of `username` are exported to, every user exports into a directory of their
+    /// own and keys can't reach outside of it
+    pub fn path(&self, username: &str) -> Result<RelativePathBuf, QueryError> {
+        let key = RelativePath::new(&self.key);
+        if !is_confined(key) {
+            return Err(QueryError::InvalidParams(format!(
+                "invalid export key {key:?}, expected a relative path such as \"reports/daily.parquet\""
+            )));
+        }
+        let user_dir = RelativePath::new(username);
+        if !is_confined(user_dir) || user_dir.components().count() != 1 {
+            return Err(QueryError::InvalidParams(format!(
+                "results of user {username:?} can't be exported"
+            )));
+        }
+
+        Ok(RelativePathBuf::from_iter([PARSEABLE_ROOT_DIRECTORY, EXPORTS_DIR, username]).join(key))
+    }
+
+    /// Streams `records` to the export path of `username` in `storage`. The records are spooled
+    /// to a file in `spool_dir` which is then uploaded in parts, so that results are never held
+    /// in memory in full
+    pub async fn write(
+        &self,
+        records: SendableRecordBatchStream,
+        encoding: &ExportEncoding<'_>,
+        username: &str,
+        storage: &dyn ObjectStorage,
+        spool_dir: &Path,
+    ) -> Result<ExportSummary, QueryError> {
+        let key = self.path(username)?;
+        if !self.overwrite {
+            match storage.head(&key).await {
+                Ok(_) => return Err(QueryError::ExportExists(key.to_string())),
+                Err(ObjectStorageError::NoSuchKey(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        std::fs::create_dir_all(spool_dir).map_err(DataFusionError::from)?;
+        let spool = spool_dir.join(format!(".export-{}", Ulid::new()));
+        let uploaded = match spool_records(records, self.format, encoding, &spool).await {
+            Ok(rows) => storage
+                .upload_multipart(&key, &spool)
+                .await
+                .map(|_| rows)
+                .map_err(QueryError::from),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = std::fs::remove_file(&spool) {
+            warn!("Failed to remove spooled export {spool:?}: {err}");
+        }
+
+        Ok(ExportSummary {
+            key: key.to_string(),
+            rows: uploaded?,
+        })
+    }
+}
+
+/// Whether `key` is a non-empty relative path that doesn't go through any parent directory
+fn is_confined(key: &RelativePath) -> bool {
+    !key.as_str().is_empty()
+        && !key.as_str().starts_with('/')
+        && key
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// How the exported results are encoded, as for results returned by the query
+pub struct ExportEncoding<'a> {
+    pub csv: CsvOptions,
+    pub fields: &'a [String],
+    pub fill_null: bool,
+    pub with_fields: bool,
+}
+
+/// Where the results of a query were exported to and how many rows they have
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportSummary {
+    pub key: String,
+    pub rows: usize,
+}
+
+/// Writes `records` to the file at `path` in `format`, returning the number of rows written
+async fn spool_records(
+    mut records: SendableRecordBatchStream,
+    format: ExportFormat,
+    encoding: &ExportEncoding<'_>,
+    path: &Path,
+) -> Result<usize, QueryError> {
+    let file = File::create(path).map_err(DataFusionError::from)?;
+    let mut writer = match format {
+        ExportFormat::Parquet => ExportWriter::Parquet(
+            ArrowWriter::try_new(file, records.schema(), None).map_err(DataFusionError::from)?,
+        ),
+        ExportFormat::Csv => ExportWriter::Csv(csv_writer(&encoding.csv, file)?),
+        ExportFormat::Json => {
+            let mut file = BufWriter::new(file);
+            if encoding.with_fields {
+                write!(
+                    file,
+                    "{{\"fields\":{},\"records\":[",
+                    json!(encoding.fields)
+                )
+            } else {
+                write!(file, "[")
+            }
+            .map_err(DataFusionError::from)?;
+            ExportWriter::Json(file)
+        }
+    };
+
+    let mut rows = 0;
+    while let Some(record) = records.next().await {
+        let record = record?;
+        match &mut writer {
+            ExportWriter::Parquet(writer) => {
+                writer.write(&record).map_err(DataFusionError::from)?
+            }
+            ExportWriter::Csv(writer) => writer.write(&record).map_err(DataFusionError::from)?,
+            ExportWriter::Json(file) => {
+                let values = records_to_json(
+                    std::slice::from_ref(&record),
+                    encoding.fields,
+                    encoding.fill_null,
+                )?;
+                for (i, value) in values.iter().enumerate() {
+                    if rows + i > 0 {
+                        file.write_all(b",").map_err(DataFusionError::from)?;
+                    }
+                    serde_json::to_writer(&mut *file, value)?;
+                }
+            }
+        }
+        rows += record.num_rows();
+    }
+
+    match writer {
+        ExportWriter::Parquet(writer) => {
+            writer.close().map_err(DataFusionError::from)?;
+        }
+        ExportWriter::Csv(mut writer) => {
+            // the header is written along with the first record, so is written for no records too
+            if rows == 0 {
+                writer
+                    .write(&RecordBatch::new_empty(records.schema()))
+                    .map_err(DataFusionError::from)?;
+            }
+            writer.into_inner().flush().map_err(DataFusionError::from)?;
+        }
+        ExportWriter::Json(mut file) => {
+            let end: &[u8] = if encoding.with_fields { b"]}" } else { b"]" };
+            file.write_all(end).map_err(DataFusionError::from)?;
+            file.flush().map_err(DataFusionError::from)?;
+        }
+    }
+
+    Ok(rows)
+}
+
+enum ExportWriter {
+    Parquet(ArrowWriter<File>),
+    Csv(Writer<File>),
+    Json(BufWriter<File>),
+}
+
+/// Builds a CSV writer with the delimiter and header of `options`
+fn csv_writer<W: Write>(options: &CsvOptions, inner: W) -> Result<Writer<W>, QueryError> {
+    if !options.delimiter.is_ascii() {
+        return Err(QueryError::InvalidParams(format!(
+            "CSV delimiter should be an ASCII character, got {:?}",
+            options.delimiter
+        )));
+    }
+
+    Ok(WriterBuilder::new()
+        .with_header(options.header)
+        .with_delimiter(options.delimiter as u8)
+        .build(inner))
+}
+
+/// Converts records to JSON objects, with nulls for the missing `fields` if `fill_null`
+fn records_to_json(
+    records: &[RecordBatch],
+    fields: &[String],
+    fill_null: bool,
+) -> Result<Vec<Value>, QueryError> {
+    let mut json_records = record_batches_to_json(records)?;
+
+    if fill_null {
+        for map in &mut json_records {
+            for field in fields {
+                if !map.contains_key(field) {
+                    map.insert(field.clone(), Value::Null);
+                }
+            }
+        }
+    }
+
+    Ok(json_records.into_iter().map(Value::Object).collect_vec())
+}
 
 pub struct QueryResponse {
     pub records: Vec<RecordBatch>,
@@ -32,18 +298,7 @@ pub struct QueryResponse {
 impl QueryResponse {
     pub fn to_json(&self) -> Result<Value, QueryError> {
         info!("{}", "Returning query results");
-        let mut json_records = record_batches_to_json(&self.records)?;
-
-        if self.fill_null {
-            for map in &mut json_records {
-                for field in &self.fields {
-                    if !map.contains_key(field) {
-                        map.insert(field.clone(), Value::Null);
-                    }
-                }
-            }
-        }
-        let values = json_records.into_iter().map(Value::Object).collect_vec();
+        let values = records_to_json(&self.records, &self.fields, self.fill_null)?;
 
         let response = if self.with_fields {
             json!({
@@ -56,4 +311,249 @@ impl QueryResponse {
 
         Ok(response)
     }
+
+    /// Writes records as CSV, values containing the delimiter, quotes or newlines are quoted
+    pub fn to_csv(&self, options: &CsvOptions) -> Result<Vec<u8>, QueryError> {
+        info!("{}", "Returning query results as CSV");
+        let mut writer = csv_writer(options, Vec::new())?;
+
+        // without any records, there is no schema to derive the header from
+        if self.records.is_empty() {
+            let mut csv = Vec::new();
+            if options.header && !self.fields.is_empty() {
+                csv.extend(
+                    self.fields
+                        .join(&options.delimiter.to_string())
+                        .into_bytes(),
+                );
+                csv.push(b'\n');
+            }
+            return Ok(csv);
+        }
+
+        for record in &self.records {
+            writer.write(record).map_err(DataFusionError::from)?;
+        }
+
+        Ok(writer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Int64Array, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::storage::{FSConfig, ObjectStorageProvider};
+
+    use super::*;
+
+    fn response() -> QueryResponse {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("message", DataType::Utf8, true),
+        ]));
+        let record = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec![
+                    Some("hello"),
+                    Some("hello, world"),
+                    None,
+                ])),
+            ],
+        )
+        .unwrap();
+
+        QueryResponse {
+            records: vec![record],
+            fields: vec!["id".to_owned(), "message".to_owned()],
+            fill_null: false,
+            with_fields: false,
+        }
+    }
+
+    #[test]
+    fn csv_quotes_values_with_delimiter() {
+        let csv = response().to_csv(&CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,message\n1,hello\n2,\"hello, world\"\n3,\n"
+        );
+
+        let options = CsvOptions {
+            header: false,
+            delimiter: ';',
+        };
+        let csv = response().to_csv(&options).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "1;hello\n2;hello, world\n3;\n"
+        );
+    }
+
+    fn records() -> SendableRecordBatchStream {
+        let record = response().records.remove(0);
+        Box::pin(RecordBatchStreamAdapter::new(
+            record.schema(),
+            futures::stream::iter([Ok(record)]),
+        ))
+    }
+
+    fn encoding(fields: &[String]) -> ExportEncoding<'_> {
+        ExportEncoding {
+            csv: CsvOptions::default(),
+            fields,
+            fill_null: true,
+            with_fields: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn exported_results_are_written_to_store() {
+        let temp_dir = temp_dir::TempDir::new().unwrap();
+        let storage = FSConfig {
+            root: temp_dir.path().join("store"),
+        }
+        .construct_client();
+        let spool_dir = temp_dir.path().join("staging");
+        let fields = response().fields;
+        let mut export = QueryExport {
+            key: "reports/daily.parquet".to_owned(),
+            format: ExportFormat::Parquet,
+            overwrite: false,
+        };
+
+        let summary = export
+            .write(
+                records(),
+                &encoding(&fields),
+                "alice",
+                storage.as_ref(),
+                &spool_dir,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            summary.key,
+            ".parseable/exports/alice/reports/daily.parquet"
+        );
+        assert_eq!(summary.rows, 3);
+        // the spooled file is removed once uploaded
+        assert_eq!(std::fs::read_dir(&spool_dir).unwrap().count(), 0);
+
+        let exported = storage
+            .get_object(RelativePath::new(&summary.key))
+            .await
+            .unwrap();
+        let rows: usize = ParquetRecordBatchReaderBuilder::try_new(exported)
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|record| record.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows, 3);
+
+        // exporting to the same key again is refused unless asked to overwrite
+        let exists = export
+            .write(
+                records(),
+                &encoding(&fields),
+                "alice",
+                storage.as_ref(),
+                &spool_dir,
+            )
+            .await;
+        assert!(matches!(exists, Err(QueryError::ExportExists(_))));
+        export.overwrite = true;
+        assert!(export
+            .write(
+                records(),
+                &encoding(&fields),
+                "alice",
+                storage.as_ref(),
+                &spool_dir
+            )
+            .await
+            .is_ok());
+
+        // users export to directories of their own
+        export.overwrite = false;
+        let summary = export
+            .write(
+                records(),
+                &encoding(&fields),
+                "bob",
+                storage.as_ref(),
+                &spool_dir,
+            )
+            .await
+            .unwrap();
+        assert_eq!(summary.key, ".parseable/exports/bob/reports/daily.parquet");
+
+        let escaping = QueryExport {
+            key: "../alice/reports/daily.parquet".to_owned(),
+            format: ExportFormat::Parquet,
+            overwrite: true,
+        };
+        assert!(escaping
+            .write(
+                records(),
+                &encoding(&fields),
+                "bob",
+                storage.as_ref(),
+                &spool_dir
+            )
+            .await
+            .is_err());
+        assert!(export.path("../alice").is_err());
+    }
+
+    #[tokio::test]
+    async fn exported_json_is_written_as_returned() {
+        let temp_dir = temp_dir::TempDir::new().unwrap();
+        let storage = FSConfig {
+            root: temp_dir.path().join("store"),
+        }
+        .construct_client();
+        let fields = response().fields;
+        let export = QueryExport {
+            key: "reports/daily.json".to_owned(),
+            format: ExportFormat::Json,
+            overwrite: false,
+        };
+
+        let summary = export
+            .write(
+                records(),
+                &encoding(&fields),
+                "alice",
+                storage.as_ref(),
+                &temp_dir.path().join("staging"),
+            )
+            .await
+            .unwrap();
+
+        let exported = storage
+            .get_object(RelativePath::new(&summary.key))
+            .await
+            .unwrap();
+        let exported: Value = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(
+            exported,
+            json!({
+                "fields": ["id", "message"],
+                "records": [
+                    {"id": 1, "message": "hello"},
+                    {"id": 2, "message": "hello, world"},
+                    {"id": 3, "message": null},
+                ],
+            })
+        );
+    }
 }
@@ -18,10 +18,11 @@
 
 use crate::event::DEFAULT_TIMESTAMP_KEY;
 use crate::utils::arrow::get_field;
+use crate::utils::time::timestamp_type;
 use serde::{Deserialize, Serialize};
 use std::str;
 
-use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use arrow_schema::{DataType, Field, Schema, DECIMAL128_MAX_PRECISION};
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
@@ -36,6 +37,9 @@ pub struct StaticSchema {
 pub struct SchemaFields {
     name: String,
     data_type: String,
+    /// Metadata such as units or descriptions, kept with the field in storage and query results
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -104,33 +108,11 @@ pub fn convert_static_schema_to_arrow_schema(
         let parsed_field = Fields {
             name: field.name.clone(),
 
-            data_type: {
-                match field.data_type.as_str() {
-                    "int" => DataType::Int64,
-                    "double" | "float" => DataType::Float64,
-                    "boolean" => DataType::Boolean,
-                    "string" => DataType::Utf8,
-                    "datetime" => DataType::Timestamp(TimeUnit::Millisecond, None),
-                    "date" => DataType::Date32,
-                    "string_list" => {
-                        DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)))
-                    }
-                    "int_list" => {
-                        DataType::List(Arc::new(Field::new("item", DataType::Int64, true)))
-                    }
-                    "double_list" | "float_list" => {
-                        DataType::List(Arc::new(Field::new("item", DataType::Float64, true)))
-                    }
-                    "boolean_list" => {
-                        DataType::List(Arc::new(Field::new("item", DataType::Boolean, true)))
-                    }
-                    _ => DataType::Null,
-                }
-            },
+            data_type: convert_static_schema_type(&field.data_type).unwrap_or(DataType::Null),
             nullable: default_nullable(),
             dict_id: default_dict_id(),
             dict_is_ordered: default_dict_is_ordered(),
-            metadata: HashMap::new(),
+            metadata: field.metadata,
         };
 
         parsed_schema.fields.push(parsed_field);
@@ -143,12 +125,34 @@ pub fn convert_static_schema_to_arrow_schema(
     add_parseable_fields_to_static_schema(parsed_schema)
 }
 
+/// Arrow type of a column declared with the type `data_type` in a schema, `None` if it isn't supported
+pub fn convert_static_schema_type(data_type: &str) -> Option<DataType> {
+    let data_type = match data_type {
+        "int" => DataType::Int64,
+        "double" | "float" => DataType::Float64,
+        "boolean" => DataType::Boolean,
+        "string" => DataType::Utf8,
+        "datetime" => timestamp_type(),
+        "date" => DataType::Date32,
+        "string_list" => DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+        "int_list" => DataType::List(Arc::new(Field::new("item", DataType::Int64, true))),
+        "double_list" | "float_list" => {
+            DataType::List(Arc::new(Field::new("item", DataType::Float64, true)))
+        }
+        "boolean_list" => DataType::List(Arc::new(Field::new("item", DataType::Boolean, true))),
+        _ => return None,
+    };
+
+    Some(data_type)
+}
+
 fn add_parseable_fields_to_static_schema(
     parsed_schema: ParsedSchema,
 ) -> Result<Arc<Schema>, StaticSchemaError> {
     let mut schema: Vec<Arc<Field>> = Vec::new();
     for field in parsed_schema.fields.iter() {
-        let field = Field::new(field.name.clone(), field.data_type.clone(), field.nullable);
+        let field = Field::new(field.name.clone(), field.data_type.clone(), field.nullable)
+            .with_metadata(field.metadata.clone());
         schema.push(Arc::new(field));
     }
 
@@ -159,11 +163,7 @@ fn add_parseable_fields_to_static_schema(
     // add the p_timestamp field to the event schema to the 0th index
     schema.insert(
         0,
-        Arc::new(Field::new(
-            DEFAULT_TIMESTAMP_KEY,
-            DataType::Timestamp(TimeUnit::Millisecond, None),
-            true,
-        )),
+        Arc::new(Field::new(DEFAULT_TIMESTAMP_KEY, timestamp_type(), true)),
     );
 
     // prepare the record batch and new fields to be added
@@ -171,6 +171,106 @@ fn add_parseable_fields_to_static_schema(
     Ok(schema)
 }
 
+/// Parses column types declared for a dynamic schema stream, e.g. `amount=decimal(38,10),id=int64`,
+/// into a schema containing only the declared fields. Ingestion then uses these types instead of
+/// inferring the number columns as float64.
+pub fn parse_column_types(column_types: &str) -> Result<Arc<Schema>, StaticSchemaError> {
+    let mut existing_field_names: HashSet<String> = HashSet::new();
+    let mut fields = vec![];
+
+    for declaration in split_column_types(column_types) {
+        let Some((name, data_type)) = declaration.split_once('=') else {
+            return Err(StaticSchemaError::InvalidColumnType(declaration.to_owned()));
+        };
+        let name = name.trim();
+        validate_field_names(name, &mut existing_field_names)?;
+        if name == DEFAULT_TIMESTAMP_KEY {
+            return Err(StaticSchemaError::ReservedKey(DEFAULT_TIMESTAMP_KEY));
+        }
+
+        let data_type = match data_type.trim().to_lowercase().as_str() {
+            "int" | "int64" => DataType::Int64,
+            decimal => parse_decimal_type(decimal)
+                .ok_or_else(|| StaticSchemaError::InvalidColumnType(declaration.to_owned()))?,
+        };
+        fields.push(Field::new(name, data_type, true));
+    }
+
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Parses a partial schema declared for a dynamic schema stream, in the format of a static schema,
+/// into a schema containing only the declared fields. Besides the types of static schemas, columns
+/// may be declared as `int64` and `decimal(precision,scale)` as with [`parse_column_types`].
+pub fn parse_partial_schema(
+    partial_schema: StaticSchema,
+) -> Result<Arc<Schema>, StaticSchemaError> {
+    let mut existing_field_names: HashSet<String> = HashSet::new();
+    let mut fields = vec![];
+
+    for field in partial_schema.fields {
+        validate_field_names(&field.name, &mut existing_field_names)?;
+        if field.name == DEFAULT_TIMESTAMP_KEY {
+            return Err(StaticSchemaError::ReservedKey(DEFAULT_TIMESTAMP_KEY));
+        }
+
+        let data_type = match field.data_type.trim().to_lowercase().as_str() {
+            "int64" => Some(DataType::Int64),
+            data_type => {
+                convert_static_schema_type(data_type).or_else(|| parse_decimal_type(data_type))
+            }
+        }
+        .ok_or_else(|| {
+            StaticSchemaError::UnsupportedColumnType(field.name.clone(), field.data_type)
+        })?;
+        fields.push(Field::new(field.name, data_type, true).with_metadata(field.metadata));
+    }
+
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// Splits the declarations on commas, ignoring the ones within parentheses, e.g. `decimal(38,10)`
+fn split_column_types(column_types: &str) -> Vec<&str> {
+    let mut declarations = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in column_types.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                declarations.push(&column_types[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    declarations.push(&column_types[start..]);
+
+    declarations
+        .into_iter()
+        .filter(|declaration| !declaration.trim().is_empty())
+        .collect()
+}
+
+/// Parses `decimal(precision,scale)`, `decimal(precision)` defaults to a scale of 0
+fn parse_decimal_type(data_type: &str) -> Option<DataType> {
+    let args = data_type
+        .strip_prefix("decimal")?
+        .trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    let (precision, scale) = match args.split_once(',') {
+        Some((precision, scale)) => (precision.trim().parse().ok()?, scale.trim().parse().ok()?),
+        None => (args.trim().parse().ok()?, 0),
+    };
+    if precision == 0 || precision > DECIMAL128_MAX_PRECISION || scale > precision as i8 {
+        return None;
+    }
+
+    Some(DataType::Decimal128(precision, scale))
+}
+
 fn default_nullable() -> bool {
     true
 }
@@ -216,6 +316,12 @@ pub enum StaticSchemaError {
 
     #[error("duplicate field name: {0}")]
     DuplicateField(String),
+
+    #[error("invalid column type declaration {0:?}, expected name=int64 or name=decimal(precision,scale)")]
+    InvalidColumnType(String),
+
+    #[error("unsupported type {1} of column {0}")]
+    UnsupportedColumnType(String, String),
 }
 
 #[cfg(test)]
@@ -233,4 +339,184 @@ mod tests {
         let _ = validate_field_names("test_field", &mut existing_field_names);
         assert!(validate_field_names("test_field", &mut existing_field_names).is_err());
     }
+
+    #[test]
+    fn column_type_declarations() {
+        let schema = parse_column_types("amount=decimal(38,10), id=int64,count=int").unwrap();
+        assert_eq!(
+            schema.field_with_name("amount").unwrap().data_type(),
+            &DataType::Decimal128(38, 10)
+        );
+        assert_eq!(
+            schema.field_with_name("id").unwrap().data_type(),
+            &DataType::Int64
+        );
+        assert_eq!(
+            schema.field_with_name("count").unwrap().data_type(),
+            &DataType::Int64
+        );
+
+        assert!(parse_column_types("amount=decimal(39,2)").is_err());
+        assert!(parse_column_types("amount=float").is_err());
+        assert!(parse_column_types("id=int64,id=int").is_err());
+    }
+
+    #[test]
+    fn partial_schema_takes_static_and_declared_types() {
+        let partial_schema: StaticSchema = serde_json::from_value(serde_json::json!({
+            "fields": [
+                {"name": "amount", "data_type": "decimal(38,10)"},
+                {"name": "status", "data_type": "int"},
+                {"name": "host", "data_type": "string"}
+            ]
+        }))
+        .unwrap();
+        let schema = parse_partial_schema(partial_schema).unwrap();
+
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(
+            schema.field_with_name("amount").unwrap().data_type(),
+            &DataType::Decimal128(38, 10)
+        );
+        assert_eq!(
+            schema.field_with_name("status").unwrap().data_type(),
+            &DataType::Int64
+        );
+
+        let unsupported: StaticSchema = serde_json::from_value(serde_json::json!({
+            "fields": [{"name": "status", "data_type": "uuid"}]
+        }))
+        .unwrap();
+        assert!(parse_partial_schema(unsupported).is_err());
+    }
+
+    #[test]
+    fn field_metadata_survives_storage_round_trip() {
+        use arrow_array::{new_null_array, Float64Array, RecordBatch};
+        use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+
+        let static_schema: StaticSchema = serde_json::from_value(serde_json::json!({
+            "fields": [
+                {"name": "latency", "data_type": "double", "metadata": {"unit": "ms"}}
+            ]
+        }))
+        .unwrap();
+        let schema = convert_static_schema_to_arrow_schema(static_schema, "", None).unwrap();
+        let time_partition = DEFAULT_TIMESTAMP_KEY.to_owned();
+        let schema = crate::event::format::update_field_type_in_schema(
+            schema,
+            None,
+            Some(&time_partition),
+            Some(vec![serde_json::json!({"latency": 1.5})].as_slice()),
+            crate::metadata::SchemaVersion::V1,
+            None,
+        );
+
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            schema
+                .fields()
+                .iter()
+                .map(|field| match field.data_type() {
+                    DataType::Float64 => {
+                        Arc::new(Float64Array::from(vec![1.5])) as arrow_array::ArrayRef
+                    }
+                    data_type => new_null_array(data_type, 1),
+                })
+                .collect(),
+        )
+        .unwrap();
+
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(buf)).unwrap();
+        let field = reader.schema().field_with_name("latency").unwrap().clone();
+        assert_eq!(field.metadata().get("unit").map(String::as_str), Some("ms"));
+    }
+
+    #[tokio::test]
+    async fn field_metadata_survives_ingestion_and_query() {
+        use actix_web::{
+            http::header::{HeaderMap, HeaderName, HeaderValue},
+            Either,
+        };
+        use chrono::{TimeDelta, Utc};
+
+        use crate::{
+            catalog,
+            event::format::LogSource,
+            handlers::http::modal::utils::ingest_utils::flatten_and_push_logs,
+            parseable::PARSEABLE,
+            query::{execute, Query, QUERY_SESSION},
+            storage::object_storage::stream_object_key,
+            utils::time::TimeRange,
+        };
+
+        let stream_name = "field_metadata_round_trip";
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-p-static-schema-flag"),
+            HeaderValue::from_static("true"),
+        );
+        let body = serde_json::json!({
+            "fields": [
+                {"name": "latency", "data_type": "float", "metadata": {"unit": "ms"}}
+            ]
+        });
+        PARSEABLE
+            .create_update_stream(&headers, &body.to_string().into(), stream_name)
+            .await
+            .unwrap();
+        flatten_and_push_logs(
+            serde_json::json!({"latency": 1.5}),
+            stream_name,
+            &LogSource::Json,
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+
+        // staged events are written to parquet and uploaded, as by the sync
+        let stream = PARSEABLE.get_stream(stream_name).unwrap();
+        stream.flush_and_convert(true).unwrap();
+        let store = PARSEABLE.storage.get_object_store();
+        for path in stream.parquet_files() {
+            let filename = path.file_name().unwrap().to_str().unwrap();
+            let key = stream_object_key(stream_name, None, filename, None);
+            store.upload_multipart(&key, &path).await.unwrap();
+            let file = catalog::manifest::create_from_parquet_file(
+                store.absolute_url(&key).to_string(),
+                &path,
+            )
+            .unwrap();
+            catalog::update_snapshot(store.clone(), stream_name, file)
+                .await
+                .unwrap();
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let raw_logical_plan = QUERY_SESSION
+            .state()
+            .create_logical_plan(&format!("SELECT latency FROM {stream_name}"))
+            .await
+            .unwrap();
+        let query = Query {
+            raw_logical_plan,
+            time_range: TimeRange::new(
+                Utc::now() - TimeDelta::hours(1),
+                Utc::now() + TimeDelta::minutes(1),
+            ),
+            filter_tag: None,
+        };
+        let (Either::Left(records), _) = execute(query, stream_name, false).await.unwrap() else {
+            unreachable!("non-streaming query returns batches")
+        };
+        assert_eq!(records.iter().map(|rb| rb.num_rows()).sum::<usize>(), 1);
+        let schema = records[0].schema();
+        let field = schema.field_with_name("latency").unwrap();
+        assert_eq!(field.metadata().get("unit").map(String::as_str), Some("ms"));
+    }
 }
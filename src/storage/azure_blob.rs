@@ -52,10 +52,12 @@ use crate::{
 };
 
 use super::{
-    metrics_layer::MetricLayer, object_storage::parseable_json_path, to_object_store_path,
-    ObjectStorage, ObjectStorageError, ObjectStorageProvider, CONNECT_TIMEOUT_SECS,
-    MIN_MULTIPART_UPLOAD_SIZE, PARSEABLE_ROOT_DIRECTORY, REQUEST_TIMEOUT_SECS, SCHEMA_FILE_NAME,
-    STREAM_METADATA_FILE_NAME, STREAM_ROOT_DIRECTORY,
+    cache_layer::register_store,
+    metrics_layer::MetricLayer,
+    object_storage::{parseable_json_path, upload_file_if_absent},
+    to_object_store_path, ObjectStorage, ObjectStorageError, ObjectStorageProvider,
+    CONNECT_TIMEOUT_SECS, MIN_MULTIPART_UPLOAD_SIZE, PARSEABLE_ROOT_DIRECTORY,
+    REQUEST_TIMEOUT_SECS, SCHEMA_FILE_NAME, STREAM_METADATA_FILE_NAME, STREAM_ROOT_DIRECTORY,
 };
 
 #[derive(Debug, Clone, clap::Args)]
@@ -172,7 +174,7 @@ impl ObjectStorageProvider for AzureBlobConfig {
         let object_store_registry = DefaultObjectStoreRegistry::new();
         let url = ObjectStoreUrl::parse(format!("https://{}.blob.core.windows.net", self.account))
             .unwrap();
-        object_store_registry.register_store(url.as_ref(), Arc::new(azure));
+        register_store(&object_store_registry, url.as_ref(), azure);
 
         RuntimeEnvBuilder::new().with_object_store_registry(Arc::new(object_store_registry))
     }
@@ -189,6 +191,37 @@ impl ObjectStorageProvider for AzureBlobConfig {
         })
     }
 
+    fn construct_bucket_client(&self, bucket: &str) -> Arc<dyn super::ObjectStorage> {
+        let azure = self
+            .get_default_builder()
+            .with_container_name(bucket)
+            .build()
+            .unwrap();
+        let azure = LimitStore::new(azure, super::MAX_OBJECT_STORE_REQUESTS);
+        Arc::new(BlobStore {
+            client: azure,
+            account: self.account.clone(),
+            container: bucket.to_owned(),
+            root: StorePath::from(""),
+        })
+    }
+
+    fn register_bucket(&self, registry: &dyn ObjectStoreRegistry, bucket: &str) -> Url {
+        // the default container is registered under the url of the account, others by their name
+        let url = Url::parse(&format!("az://{bucket}")).unwrap();
+        if registry.get_store(&url).is_err() {
+            let azure = self
+                .get_default_builder()
+                .with_container_name(bucket)
+                .build()
+                .unwrap();
+            let azure = LimitStore::new(azure, super::MAX_OBJECT_STORE_REQUESTS);
+            register_store(registry, &url, MetricLayer::new(azure));
+        }
+
+        url
+    }
+
     fn get_endpoint(&self) -> String {
         self.endpoint_url.clone()
     }
@@ -399,37 +432,31 @@ impl BlobStore {
             // async_writer.complete().await?;
             return Ok(());
         } else {
-            let mut data = Vec::new();
-            file.read_to_end(&mut data).await?;
-
-            // let mut upload_parts = Vec::new();
-
-            let has_final_partial_part = total_size % MIN_MULTIPART_UPLOAD_SIZE > 0;
-            let num_full_parts = total_size / MIN_MULTIPART_UPLOAD_SIZE;
-            let total_parts = num_full_parts + if has_final_partial_part { 1 } else { 0 };
-
-            // Upload each part
-            for part_number in 0..(total_parts) {
-                let start_pos = part_number * MIN_MULTIPART_UPLOAD_SIZE;
-                let end_pos = if part_number == num_full_parts && has_final_partial_part {
-                    // Last part might be smaller than 5MB (which is allowed)
-                    total_size
-                } else {
-                    // All other parts must be at least 5MB
-                    start_pos + MIN_MULTIPART_UPLOAD_SIZE
-                };
-
-                // Extract this part's data
-                let part_data = data[start_pos..end_pos].to_vec();
-
-                // Upload the part
-                async_writer.put_part(part_data.into()).await?;
+            // read the file a part at a time, rather than all of it into memory
+            let mut part = vec![0; MIN_MULTIPART_UPLOAD_SIZE];
+            loop {
+                let mut len = 0;
+                while len < part.len() {
+                    match file.read(&mut part[len..]).await? {
+                        0 => break,
+                        read => len += read,
+                    }
+                }
+                if len == 0 {
+                    break;
+                }
 
-                // upload_parts.push(part_number as u64 + 1);
+                // All parts but the last are 5MB, the last might be smaller (which is allowed)
+                if let Err(err) = async_writer.put_part(part[..len].to_vec().into()).await {
+                    error!("Failed to upload part of multipart upload. {:?}", err);
+                    async_writer.abort().await?;
+                    return Err(err.into());
+                }
             }
             if let Err(err) = async_writer.complete().await {
                 error!("Failed to complete multipart upload. {:?}", err);
                 async_writer.abort().await?;
+                return Err(err.into());
             };
         }
         Ok(())
@@ -488,6 +515,13 @@ impl ObjectStorage for BlobStore {
     ) -> Result<(), ObjectStorageError> {
         self._upload_multipart(key, path).await
     }
+    async fn upload_if_absent(
+        &self,
+        key: &RelativePath,
+        path: &Path,
+    ) -> Result<RelativePathBuf, ObjectStorageError> {
+        upload_file_if_absent(&self.client, self, key, path).await
+    }
     async fn get_buffered_reader(
         &self,
         _path: &RelativePath,
@@ -499,13 +533,8 @@ impl ObjectStorage for BlobStore {
             ),
         )))
     }
-    async fn head(&self, _path: &RelativePath) -> Result<ObjectMeta, ObjectStorageError> {
-        Err(ObjectStorageError::UnhandledError(Box::new(
-            std::io::Error::new(
-                std::io::ErrorKind::Unsupported,
-                "Head operation not implemented for Blob Storage yet",
-            ),
-        )))
+    async fn head(&self, path: &RelativePath) -> Result<ObjectMeta, ObjectStorageError> {
+        Ok(self.client.head(&to_object_store_path(path)).await?)
     }
 
     async fn get_object(&self, path: &RelativePath) -> Result<Bytes, ObjectStorageError> {
@@ -707,6 +736,14 @@ impl ObjectStorage for BlobStore {
         Ok(files)
     }
 
+    async fn list_objects(
+        &self,
+        prefix: &RelativePath,
+    ) -> Result<Vec<ObjectMeta>, ObjectStorageError> {
+        let prefix = to_object_store_path(prefix);
+        Ok(self.client.list(Some(&prefix)).try_collect().await?)
+    }
+
     async fn upload_file(&self, key: &str, path: &Path) -> Result<(), ObjectStorageError> {
         self._upload_file(key, path).await?;
 
@@ -0,0 +1,745 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use datafusion::execution::object_store::ObjectStoreRegistry;
+use futures_util::stream::BoxStream;
+use object_store::{
+    path::Path, Attributes, GetOptions, GetRange, GetResult, GetResultPayload, ListResult,
+    MultipartUpload, ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+    Result as ObjectStoreResult,
+};
+use once_cell::sync::Lazy;
+use tracing::warn;
+use url::{Position, Url};
+
+use crate::parseable::PARSEABLE;
+
+/// Registers `store` under `url`, wrapped in a `CacheLayer` if a directory for the object store
+/// cache is configured. It is also registered as is under the [`uncached_url`] of `url`, for the
+/// queries that skip caches.
+pub fn register_store(registry: &dyn ObjectStoreRegistry, url: &Url, store: impl ObjectStore) {
+    register_with_cache(
+        registry,
+        url,
+        store,
+        PARSEABLE.options.object_cache_path.clone(),
+        PARSEABLE.options.object_cache_size,
+    )
+}
+
+fn register_with_cache(
+    registry: &dyn ObjectStoreRegistry,
+    url: &Url,
+    store: impl ObjectStore,
+    cache_dir: Option<PathBuf>,
+    cache_size: u64,
+) {
+    let store: Arc<dyn ObjectStore> = Arc::new(store);
+    registry.register_store(&uncached_url(url), store.clone());
+    let cached: Arc<dyn ObjectStore> = match cache_dir {
+        Some(dir) => Arc::new(CacheLayer::new(store, dir, cache_size)),
+        None => store,
+    };
+    registry.register_store(url, cached);
+}
+
+/// Url the object store at `url` is registered under without the cache, e.g. `s3+uncached://bucket`
+/// for `s3://bucket`
+pub fn uncached_url(url: &Url) -> Url {
+    Url::parse(&format!(
+        "{}+uncached://{}",
+        url.scheme(),
+        &url[Position::BeforeHost..]
+    ))
+    .expect("url with a suffixed scheme is valid")
+}
+
+/// Caches of the object stores registered for queries
+static CACHES: Lazy<Mutex<Vec<Weak<Cache>>>> = Lazy::new(Default::default);
+
+/// Drops the cached copies of the objects under `prefix` from every object store cache, for
+/// objects deleted from object store
+pub fn evict_prefix(prefix: &str) {
+    let caches: Vec<_> = {
+        let mut caches = CACHES.lock().unwrap();
+        caches.retain(|cache| cache.strong_count() > 0);
+        caches.iter().filter_map(Weak::upgrade).collect()
+    };
+    for cache in caches {
+        for path in cache.forget_prefix(prefix) {
+            if let Err(err) = std::fs::remove_file(&path) {
+                warn!("Couldn't evict {path:?} from object store cache: {err}");
+            }
+        }
+    }
+}
+
+/// Directory within the configured one that the cache downloads files into, so that clearing it
+/// never touches files the cache didn't create
+const CACHE_DIR: &str = "parseable-object-cache";
+
+/// Duration a cached copy is served for before it is checked against object store again
+const REVALIDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct CacheEntry {
+    meta: ObjectMeta,
+    e_tag: String,
+    last_access: u64,
+    /// When the cached copy was last found to be current in object store
+    validated_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<Path, CacheEntry>,
+    /// Objects being downloaded into the cache, along with the space reserved for them once their
+    /// size is known
+    downloading: HashMap<Path, u64>,
+    size: u64,
+    tick: u64,
+}
+
+/// Read-through cache on local disk, for parquet files downloaded from object store. A read
+/// missing the cache is served from object store, while the whole file is downloaded into the
+/// cache in the background, once however many reads miss it at a time. Cached files are checked
+/// against their etag in object store at most once every `REVALIDATE_INTERVAL`, and downloaded
+/// again once it changes. When the cache grows beyond `max_size`, least recently read files are
+/// evicted. Space for a download is reserved before it is written and files being downloaded are
+/// never evicted, a download that doesn't fit beside them is not cached.
+#[derive(Debug)]
+pub struct CacheLayer<T: ObjectStore> {
+    inner: Arc<T>,
+    cache: Arc<Cache>,
+}
+
+#[derive(Debug)]
+struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    state: Mutex<CacheState>,
+    /// Reads served from the local cache
+    hits: AtomicU64,
+    /// Reads that had to be served from object store
+    misses: AtomicU64,
+}
+
+impl<T: ObjectStore> CacheLayer<T> {
+    /// Cached files from an earlier run are not tracked, hence they are cleared on creation
+    pub fn new(inner: T, dir: PathBuf, max_size: u64) -> Self {
+        let dir = dir.join(CACHE_DIR);
+        if dir.exists() {
+            if let Err(err) = std::fs::remove_dir_all(&dir) {
+                warn!("Couldn't clear object store cache at {dir:?}: {err}");
+            }
+        }
+
+        let cache = Arc::new(Cache {
+            dir,
+            max_size,
+            state: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        });
+        CACHES.lock().unwrap().push(Arc::downgrade(&cache));
+
+        Self {
+            inner: Arc::new(inner),
+            cache,
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.cache.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.cache.misses.load(Ordering::Relaxed)
+    }
+
+    /// Checks with object store whether the cached copy of the object is still current
+    async fn is_current(&self, location: &Path, e_tag: String) -> ObjectStoreResult<bool> {
+        let options = GetOptions {
+            if_none_match: Some(e_tag),
+            head: true,
+            ..Default::default()
+        };
+        match self.inner.get_opts(location, options).await {
+            Err(object_store::Error::NotModified { .. }) => Ok(true),
+            Ok(_) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Waits for the downloads into the cache in progress to finish
+    #[cfg(test)]
+    async fn settle(&self) {
+        while !self.cache.state.lock().unwrap().downloading.is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+}
+
+impl Cache {
+    fn local_path(&self, location: &Path) -> PathBuf {
+        self.dir.join(location.as_ref())
+    }
+
+    /// Returns the etag and metadata of a cached object, marking it as read, along with whether
+    /// it is due to be checked against object store
+    fn lookup(&self, location: &Path) -> Option<(String, ObjectMeta, bool)> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = state.entries.get_mut(location)?;
+        entry.last_access = tick;
+
+        Some((
+            entry.e_tag.clone(),
+            entry.meta.clone(),
+            entry.validated_at.elapsed() >= REVALIDATE_INTERVAL,
+        ))
+    }
+
+    fn validated(&self, location: &Path) {
+        if let Some(entry) = self.state.lock().unwrap().entries.get_mut(location) {
+            entry.validated_at = Instant::now();
+        }
+    }
+
+    /// Tracks a downloaded object, returns the local paths of the files evicted to make space for it
+    fn insert(&self, location: &Path, meta: ObjectMeta, e_tag: String) -> Vec<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let entry = CacheEntry {
+            meta,
+            e_tag,
+            last_access: state.tick,
+            validated_at: Instant::now(),
+        };
+        state.size += entry.meta.size as u64;
+        if let Some(previous) = state.entries.insert(location.clone(), entry) {
+            state.size -= previous.meta.size as u64;
+        }
+
+        // the download is written, its file accounts for the space reserved for it
+        if let Some(reserved) = state.downloading.get_mut(location) {
+            *reserved = 0;
+        }
+
+        self.evict(&mut state, location)
+    }
+
+    /// Reserves space for an object being downloaded, returns the local paths of the files evicted
+    /// to make space for it, or `None` if it doesn't fit beside the other downloads
+    fn reserve(&self, location: &Path, size: u64) -> Option<Vec<PathBuf>> {
+        let mut state = self.state.lock().unwrap();
+        let reserved: u64 = state.downloading.values().sum();
+        if reserved + size > self.max_size {
+            return None;
+        }
+        state.downloading.insert(location.clone(), size);
+
+        Some(self.evict(&mut state, location))
+    }
+
+    /// Evicts least recently read files other than `keep` until the cached files and the space
+    /// reserved for downloads fit in `max_size`, returns their local paths
+    fn evict(&self, state: &mut CacheState, keep: &Path) -> Vec<PathBuf> {
+        let reserved: u64 = state.downloading.values().sum();
+        let mut evicted = vec![];
+        while state.size + reserved > self.max_size {
+            let Some(lru) = state
+                .entries
+                .iter()
+                .filter(|(key, _)| *key != keep)
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            let entry = state.entries.remove(&lru).expect("entry exists");
+            state.size -= entry.meta.size as u64;
+            evicted.push(self.local_path(&lru));
+        }
+
+        evicted
+    }
+
+    fn forget(&self, location: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.entries.remove(location) {
+            state.size -= entry.meta.size as u64;
+        }
+    }
+
+    /// Stops tracking the objects under `prefix`, returns the local paths of their files
+    fn forget_prefix(&self, prefix: &str) -> Vec<PathBuf> {
+        let prefix = Path::from(prefix);
+        let mut state = self.state.lock().unwrap();
+        let forgotten: Vec<_> = state
+            .entries
+            .keys()
+            .filter(|location| location.prefix_matches(&prefix))
+            .cloned()
+            .collect();
+        for location in &forgotten {
+            let entry = state.entries.remove(location).expect("entry exists");
+            state.size -= entry.meta.size as u64;
+        }
+
+        forgotten
+            .iter()
+            .map(|location| self.local_path(location))
+            .collect()
+    }
+
+    /// Returns `true` if the object isn't being downloaded already, marking it as being downloaded
+    fn start_download(&self, location: &Path) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .downloading
+            .insert(location.clone(), 0)
+            .is_none()
+    }
+
+    /// Downloads the whole object into the cache
+    async fn download(&self, inner: &dyn ObjectStore, location: &Path) -> ObjectStoreResult<()> {
+        let result = inner.get(location).await?;
+        let meta = result.meta.clone();
+        let Some(e_tag) = meta.e_tag.clone() else {
+            return Ok(());
+        };
+        let Some(evicted) = self.reserve(location, meta.size as u64) else {
+            return Ok(());
+        };
+        self.remove_evicted(evicted).await;
+        let bytes = result.bytes().await?;
+
+        let path = self.local_path(location);
+        let mut part = path.clone();
+        part.set_extension("part");
+        let written = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&part, &bytes).await?;
+            tokio::fs::rename(&part, &path).await
+        };
+        if let Err(err) = written.await {
+            warn!("Couldn't cache {location} on local disk: {err}");
+            return Ok(());
+        }
+
+        self.remove_evicted(self.insert(location, meta, e_tag))
+            .await;
+
+        Ok(())
+    }
+
+    async fn remove_evicted(&self, evicted: Vec<PathBuf>) {
+        for path in evicted {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                warn!("Couldn't evict {path:?} from object store cache: {err}");
+            }
+        }
+    }
+
+    /// Downloads the object into the cache in the background, unless it is being downloaded
+    fn populate(self: &Arc<Self>, inner: Arc<dyn ObjectStore>, location: &Path) {
+        if !self.start_download(location) {
+            return;
+        }
+
+        let cache = self.clone();
+        let location = location.clone();
+        tokio::spawn(async move {
+            if let Err(err) = cache.download(inner.as_ref(), &location).await {
+                warn!("Couldn't download {location} into object store cache: {err}");
+            }
+            cache.state.lock().unwrap().downloading.remove(&location);
+        });
+    }
+
+    fn read_local(
+        &self,
+        location: &Path,
+        meta: ObjectMeta,
+        range: Range<usize>,
+    ) -> Option<GetResult> {
+        let path = self.local_path(location);
+        let file = File::open(&path).ok()?;
+
+        Some(GetResult {
+            payload: GetResultPayload::File(file, path),
+            meta,
+            range,
+            attributes: Attributes::default(),
+        })
+    }
+}
+
+/// Only plain reads of parquet files are served from the cache
+fn is_cacheable(location: &Path, options: &GetOptions) -> bool {
+    location.extension() == Some("parquet")
+        && !options.head
+        && options.if_match.is_none()
+        && options.if_none_match.is_none()
+        && options.if_modified_since.is_none()
+        && options.if_unmodified_since.is_none()
+        && options.version.is_none()
+}
+
+fn resolve_range(range: Option<&GetRange>, size: usize) -> Range<usize> {
+    match range {
+        None => 0..size,
+        Some(GetRange::Bounded(range)) => range.start.min(size)..range.end.min(size),
+        Some(GetRange::Offset(offset)) => (*offset).min(size)..size,
+        Some(GetRange::Suffix(suffix)) => size.saturating_sub(*suffix)..size,
+    }
+}
+
+impl<T: ObjectStore> std::fmt::Display for CacheLayer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cache({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl<T: ObjectStore> ObjectStore for CacheLayer<T> {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> ObjectStoreResult<PutResult> {
+        self.cache.forget(location);
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> ObjectStoreResult<GetResult> {
+        if !is_cacheable(location, &options) {
+            return self.inner.get_opts(location, options).await;
+        }
+
+        if let Some((e_tag, meta, revalidate)) = self.cache.lookup(location) {
+            if !revalidate || self.is_current(location, e_tag).await? {
+                if revalidate {
+                    self.cache.validated(location);
+                }
+                let range = resolve_range(options.range.as_ref(), meta.size);
+                if let Some(result) = self.cache.read_local(location, meta, range) {
+                    self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(result);
+                }
+            }
+            self.cache.forget(location);
+        }
+
+        // only the range read is fetched for this read, the cache is filled in the background
+        self.cache.misses.fetch_add(1, Ordering::Relaxed);
+        self.cache.populate(self.inner.clone(), location);
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn delete(&self, location: &Path) -> ObjectStoreResult<()> {
+        self.cache.forget(location);
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> ObjectStoreResult<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::execution::object_store::DefaultObjectStoreRegistry;
+    use object_store::memory::InMemory;
+    use temp_dir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn second_read_is_served_from_local_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = CacheLayer::new(InMemory::new(), temp_dir.path().join("cache"), 1024);
+        let location = Path::from("stream/date=2025-01-01/data.parquet");
+        store
+            .put(&location, PutPayload::from_static(b"parquet bytes"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_range(&location, 0..7).await.unwrap().as_ref(),
+            b"parquet"
+        );
+        assert_eq!((store.hits(), store.misses()), (0, 1));
+        store.settle().await;
+
+        assert_eq!(
+            store.get_range(&location, 8..13).await.unwrap().as_ref(),
+            b"bytes"
+        );
+        assert_eq!((store.hits(), store.misses()), (1, 1));
+
+        // file changed in object store, hence the etag, is downloaded again
+        store
+            .put(&location, PutPayload::from_static(b"updated bytes"))
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get(&location)
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap()
+                .as_ref(),
+            b"updated bytes"
+        );
+        assert_eq!((store.hits(), store.misses()), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn evicted_prefixes_are_read_from_object_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = CacheLayer::new(InMemory::new(), temp_dir.path().join("cache"), 1024);
+        let deleted = Path::from("evicted/date=2025-01-01/hour=10/data.parquet");
+        let kept = Path::from("evicted/date=2025-01-01/hour=11/data.parquet");
+        for location in [&deleted, &kept] {
+            store
+                .put(location, PutPayload::from_static(b"parquet bytes"))
+                .await
+                .unwrap();
+            store.get(location).await.unwrap();
+        }
+        store.settle().await;
+
+        evict_prefix("evicted/date=2025-01-01/hour=10/");
+        assert!(!store.cache.local_path(&deleted).exists());
+        assert!(store.cache.local_path(&kept).exists());
+        store.get(&kept).await.unwrap();
+        assert_eq!((store.hits(), store.misses()), (1, 2));
+        store.get(&deleted).await.unwrap();
+        assert_eq!((store.hits(), store.misses()), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn reads_skipping_caches_bypass_the_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let registry = DefaultObjectStoreRegistry::new();
+        let url = Url::parse("s3://bucket").unwrap();
+        let inner: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        register_with_cache(
+            &registry,
+            &url,
+            inner.clone(),
+            Some(cache_dir.clone()),
+            1024,
+        );
+        let location = Path::from("stream/date=2025-01-01/data.parquet");
+        inner
+            .put(&location, PutPayload::from_static(b"parquet bytes"))
+            .await
+            .unwrap();
+
+        let uncached = registry.get_store(&uncached_url(&url)).unwrap();
+        assert_eq!(uncached_url(&url).as_str(), "s3+uncached://bucket");
+        uncached.get(&location).await.unwrap();
+        assert!(!cache_dir.join(CACHE_DIR).exists());
+
+        registry
+            .get_store(&url)
+            .unwrap()
+            .get(&location)
+            .await
+            .unwrap();
+        // downloaded in the background
+        let cached = cache_dir.join(CACHE_DIR).join(location.as_ref());
+        for _ in 0..100 {
+            if cached.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(cached.exists());
+    }
+
+    #[tokio::test]
+    async fn repeated_query_is_served_from_local_cache() {
+        use arrow_array::{Int64Array, RecordBatch};
+        use datafusion::prelude::{ParquetReadOptions, SessionContext};
+        use parquet::arrow::ArrowWriter;
+
+        let temp_dir = TempDir::new().unwrap();
+        // files the cache didn't create are left alone when clearing it
+        let unrelated = temp_dir.path().join("notes.parquet");
+        std::fs::write(&unrelated, b"keep").unwrap();
+        let store = Arc::new(CacheLayer::new(
+            InMemory::new(),
+            temp_dir.path().to_path_buf(),
+            1 << 20,
+        ));
+        assert!(unrelated.exists());
+
+        let batch =
+            RecordBatch::try_from_iter([("n", Arc::new(Int64Array::from(vec![1, 2, 3])) as _)])
+                .unwrap();
+        let mut parquet = vec![];
+        let mut writer = ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        store
+            .put(&Path::from("app/data.parquet"), PutPayload::from(parquet))
+            .await
+            .unwrap();
+
+        let ctx = SessionContext::new();
+        ctx.register_object_store(&Url::parse("memory://").unwrap(), store.clone());
+        ctx.register_parquet("app", "memory:///app/", ParquetReadOptions::default())
+            .await
+            .unwrap();
+        let query = || async {
+            ctx.sql("select sum(n) from app")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap()
+        };
+
+        let first = query().await;
+        store.settle().await;
+        let misses = store.misses();
+        assert_eq!(query().await, first);
+        assert!(store.hits() > 0);
+        assert_eq!(store.misses(), misses);
+    }
+
+    #[tokio::test]
+    async fn least_recently_read_files_are_evicted() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = CacheLayer::new(InMemory::new(), temp_dir.path().join("cache"), 10);
+        let locations: Vec<Path> = ["a.parquet", "b.parquet", "c.parquet"]
+            .into_iter()
+            .map(Path::from)
+            .collect();
+        for location in &locations {
+            store
+                .put(location, PutPayload::from_static(b"12345"))
+                .await
+                .unwrap();
+        }
+
+        for i in [0, 1, 0, 2] {
+            store.get(&locations[i]).await.unwrap();
+            store.settle().await;
+        }
+
+        assert!(store.cache.local_path(&locations[0]).exists());
+        assert!(!store.cache.local_path(&locations[1]).exists());
+        assert!(store.cache.local_path(&locations[2]).exists());
+    }
+
+    #[tokio::test]
+    async fn files_being_downloaded_are_never_evicted() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = CacheLayer::new(InMemory::new(), temp_dir.path().join("cache"), 10);
+        let [a, b, c, d, e] =
+            ["a", "b", "c", "d", "e"].map(|name| Path::from(format!("{name}.parquet")));
+        for location in [&a, &b] {
+            store
+                .put(location, PutPayload::from_static(b"12345"))
+                .await
+                .unwrap();
+            store.get(location).await.unwrap();
+            store.settle().await;
+        }
+        store.get(&a).await.unwrap();
+
+        // space for the files being downloaded is made by evicting the least recently read ones
+        assert!(store.cache.start_download(&c));
+        assert_eq!(
+            store.cache.reserve(&c, 5),
+            Some(vec![store.cache.local_path(&b)])
+        );
+        assert!(store.cache.start_download(&d));
+        assert_eq!(
+            store.cache.reserve(&d, 5),
+            Some(vec![store.cache.local_path(&a)])
+        );
+
+        // the cache is full of files not yet written, which are kept over the new download
+        assert!(store.cache.start_download(&e));
+        assert_eq!(store.cache.reserve(&e, 5), None);
+        let evicted = store.cache.insert(
+            &c,
+            ObjectMeta {
+                location: c.clone(),
+                last_modified: chrono::Utc::now(),
+                size: 5,
+                e_tag: Some("c".to_owned()),
+                version: None,
+            },
+            "c".to_owned(),
+        );
+        assert!(evicted.is_empty());
+        let state = store.cache.state.lock().unwrap();
+        assert_eq!(state.size, 5);
+        assert_eq!(state.downloading[&d], 5);
+        assert!(state.entries.contains_key(&c));
+    }
+}
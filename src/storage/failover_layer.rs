@@ -0,0 +1,555 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::fmt::Display;
+
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use object_store::{
+    path::Path, Error as ObjectStoreError, GetOptions, GetResult, ListResult, MultipartUpload,
+    ObjectMeta, ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+    Result as ObjectStoreResult, UploadPart,
+};
+use tracing::warn;
+
+/// Failures to reach the store and errors of the store itself, i.e. 5xx responses. Errors about
+/// the object or the request, including failed authentication, would be the same against the
+/// secondary.
+fn is_unavailable(err: &ObjectStoreError) -> bool {
+    match err {
+        ObjectStoreError::Generic { source, .. } => is_transport_or_server_error(source.as_ref()),
+        ObjectStoreError::JoinError { .. } => true,
+        _ => false,
+    }
+}
+
+/// Requests object_store gives up on have the error of its HTTP client as a source, with no
+/// status when the store couldn't be reached. 4xx responses it doesn't map to an error of their
+/// own have no such source, they are reported with the status alone.
+fn is_transport_or_server_error(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<object_store_reqwest::Error>() {
+            return err.status().is_none_or(|status| status.is_server_error());
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Serves reads from a `secondary` store, e.g. a bucket replicated into another region, when the
+/// `primary` is unavailable. Writes go to the primary, and are mirrored onto the secondary when
+/// `dual_write` is set. Mirroring is best effort, a write that fails against the secondary is
+/// logged and doesn't fail the write.
+#[derive(Debug, Clone)]
+pub struct FailoverLayer<T: ObjectStore> {
+    primary: T,
+    secondary: Option<T>,
+    dual_write: bool,
+}
+
+impl<T: ObjectStore> FailoverLayer<T> {
+    pub fn new(primary: T, secondary: Option<T>, dual_write: bool) -> Self {
+        Self {
+            primary,
+            secondary,
+            dual_write,
+        }
+    }
+
+    fn mirror(&self) -> Option<&T> {
+        self.secondary.as_ref().filter(|_| self.dual_write)
+    }
+
+    /// Runs `read` against the primary, and against the secondary when the primary is unavailable
+    async fn read<'a, R, F>(
+        &'a self,
+        location: &Path,
+        read: impl Fn(&'a T) -> F,
+    ) -> ObjectStoreResult<R>
+    where
+        F: std::future::Future<Output = ObjectStoreResult<R>>,
+    {
+        match (read(&self.primary).await, &self.secondary) {
+            (Err(err), Some(secondary)) if is_unavailable(&err) => {
+                warn!("Reading {location} from secondary object store, primary failed with: {err}");
+                read(secondary).await
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Mirrors a write that succeeded against the primary onto the secondary
+    async fn write<'a, R, F>(
+        &'a self,
+        location: &Path,
+        write: impl Fn(&'a T) -> F,
+    ) -> ObjectStoreResult<R>
+    where
+        F: std::future::Future<Output = ObjectStoreResult<R>>,
+    {
+        let res = write(&self.primary).await?;
+        if let Some(secondary) = self.mirror() {
+            if let Err(err) = write(secondary).await {
+                warn!("Couldn't mirror write of {location} onto secondary object store: {err}");
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Lists from the secondary when the primary fails to start the listing
+    fn list_from<'a>(
+        &'a self,
+        list: impl Fn(&'a T) -> BoxStream<'a, ObjectStoreResult<ObjectMeta>> + Send + 'a,
+    ) -> BoxStream<'a, ObjectStoreResult<ObjectMeta>> {
+        let Some(secondary) = &self.secondary else {
+            return list(&self.primary);
+        };
+
+        stream::once(async move {
+            let mut primary = list(&self.primary);
+            match primary.next().await {
+                Some(Err(err)) if is_unavailable(&err) => {
+                    warn!("Listing from secondary object store, primary failed with: {err}");
+                    list(secondary)
+                }
+                first => stream::iter(first).chain(primary).boxed(),
+            }
+        })
+        .flatten()
+        .boxed()
+    }
+}
+
+impl<T: ObjectStore> Display for FailoverLayer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.secondary {
+            Some(secondary) => write!(f, "Failover({}, {secondary})", self.primary),
+            None => write!(f, "Failover({})", self.primary),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: ObjectStore> ObjectStore for FailoverLayer<T> {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> ObjectStoreResult<PutResult> {
+        self.write(location, |store| {
+            store.put_opts(location, payload.clone(), opts.clone())
+        })
+        .await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+        let primary = self
+            .primary
+            .put_multipart_opts(location, opts.clone())
+            .await?;
+        let secondary = match self.mirror() {
+            Some(secondary) => secondary
+                .put_multipart_opts(location, opts)
+                .await
+                .inspect_err(|err| {
+                    warn!("Couldn't mirror write of {location} onto secondary object store: {err}")
+                })
+                .ok(),
+            None => None,
+        };
+
+        Ok(Box::new(MirroredUpload {
+            location: location.clone(),
+            primary,
+            secondary,
+        }))
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> ObjectStoreResult<GetResult> {
+        self.read(location, |store| store.get_opts(location, options.clone()))
+            .await
+    }
+
+    async fn head(&self, location: &Path) -> ObjectStoreResult<ObjectMeta> {
+        self.read(location, |store| store.head(location)).await
+    }
+
+    async fn delete(&self, location: &Path) -> ObjectStoreResult<()> {
+        self.write(location, |store| store.delete(location)).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+        let prefix = prefix.cloned();
+        self.list_from(move |store| store.list(prefix.as_ref()))
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+        let (prefix, offset) = (prefix.cloned(), offset.clone());
+        self.list_from(move |store| store.list_with_offset(prefix.as_ref(), &offset))
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> ObjectStoreResult<ListResult> {
+        let location = prefix.cloned().unwrap_or_default();
+        self.read(&location, |store| store.list_with_delimiter(prefix))
+            .await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+        self.write(to, |store| store.copy(from, to)).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+        self.write(to, |store| store.copy_if_not_exists(from, to))
+            .await
+    }
+}
+
+/// Multipart upload onto the primary, mirrored onto the secondary while it keeps up
+#[derive(Debug)]
+struct MirroredUpload {
+    location: Path,
+    primary: Box<dyn MultipartUpload>,
+    secondary: Option<Box<dyn MultipartUpload>>,
+}
+
+#[async_trait]
+impl MultipartUpload for MirroredUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        let primary = self.primary.put_part(data.clone());
+        let secondary = self.secondary.as_mut().map(|upload| upload.put_part(data));
+        let location = self.location.clone();
+
+        Box::pin(async move {
+            primary.await?;
+            if let Some(secondary) = secondary {
+                if let Err(err) = secondary.await {
+                    warn!("Couldn't mirror write of {location} onto secondary object store: {err}");
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn complete(&mut self) -> ObjectStoreResult<PutResult> {
+        let res = self.primary.complete().await?;
+        if let Some(secondary) = &mut self.secondary {
+            if let Err(err) = secondary.complete().await {
+                warn!(
+                    "Couldn't mirror write of {} onto secondary object store: {err}",
+                    self.location
+                );
+            }
+        }
+
+        Ok(res)
+    }
+
+    async fn abort(&mut self) -> ObjectStoreResult<()> {
+        if let Some(secondary) = &mut self.secondary {
+            let _ = secondary.abort().await;
+        }
+        self.primary.abort().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::{aws::AmazonS3Builder, memory::InMemory, RetryConfig};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// In memory store that errors out on every request while `down`, or while the credentials
+    /// are `denied`
+    #[derive(Debug, Default)]
+    struct Flaky {
+        inner: InMemory,
+        down: bool,
+        denied: bool,
+    }
+
+    /// Error of the HTTP client of object_store, connecting to a port nothing listens on
+    async fn connection_refused() -> object_store_reqwest::Error {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        object_store_reqwest::get(format!("http://{addr}"))
+            .await
+            .unwrap_err()
+    }
+
+    impl Flaky {
+        async fn check(&self) -> ObjectStoreResult<()> {
+            if self.down {
+                return Err(ObjectStoreError::Generic {
+                    store: "flaky",
+                    source: Box::new(connection_refused().await),
+                });
+            }
+            // as object_store reports 4xx responses it has no error of its own for
+            if self.denied {
+                return Err(ObjectStoreError::Generic {
+                    store: "flaky",
+                    source: "Client error with status 403 Forbidden: AccessDenied".into(),
+                });
+            }
+            Ok(())
+        }
+    }
+
+    impl Display for Flaky {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Flaky")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for Flaky {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            self.check().await?;
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOpts,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.check().await?;
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.check().await?;
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> ObjectStoreResult<()> {
+            self.check().await?;
+            self.inner.delete(location).await
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+            let prefix = prefix.cloned();
+            stream::once(async move {
+                match self.check().await {
+                    Ok(()) => self.inner.list(prefix.as_ref()),
+                    Err(err) => stream::once(async move { Err(err) }).boxed(),
+                }
+            })
+            .flatten()
+            .boxed()
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.check().await?;
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+            self.check().await?;
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+            self.check().await?;
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    #[tokio::test]
+    async fn secondary_serves_reads_when_primary_is_down() {
+        let location = Path::from("stream/.stream.json");
+        let secondary = Flaky::default();
+        secondary
+            .put(&location, PutPayload::from_static(b"{}"))
+            .await
+            .unwrap();
+        let primary = Flaky {
+            down: true,
+            ..Default::default()
+        };
+        let store = FailoverLayer::new(primary, Some(secondary), false);
+
+        let data = store.get(&location).await.unwrap().bytes().await.unwrap();
+        assert_eq!(data.as_ref(), b"{}");
+        let listed = store
+            .list(Some(&Path::from("stream")))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].as_ref().unwrap().location, location);
+    }
+
+    #[tokio::test]
+    async fn missing_object_is_not_read_from_secondary() {
+        let location = Path::from("stream/.stream.json");
+        let secondary = Flaky::default();
+        secondary
+            .put(&location, PutPayload::from_static(b"{}"))
+            .await
+            .unwrap();
+        let store = FailoverLayer::new(Flaky::default(), Some(secondary), false);
+
+        assert!(matches!(
+            store.head(&location).await,
+            Err(ObjectStoreError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn denied_read_is_not_served_from_secondary() {
+        let location = Path::from("stream/.stream.json");
+        let secondary = Flaky::default();
+        secondary
+            .put(&location, PutPayload::from_static(b"{}"))
+            .await
+            .unwrap();
+        let primary = Flaky {
+            denied: true,
+            ..Default::default()
+        };
+        let store = FailoverLayer::new(primary, Some(secondary), false);
+
+        assert!(store.get(&location).await.is_err());
+        let listed = store
+            .list(Some(&Path::from("stream")))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].is_err());
+    }
+
+    /// Error of reading an object from S3, through a server that answers every request with `status`
+    async fn s3_error(status: &str) -> ObjectStoreError {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let response =
+            format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let mut request = vec![];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let store = AmazonS3Builder::new()
+            .with_endpoint(endpoint)
+            .with_allow_http(true)
+            .with_bucket_name("bucket")
+            .with_region("us-east-1")
+            .with_access_key_id("access")
+            .with_secret_access_key("secret")
+            .with_retry(RetryConfig {
+                max_retries: 0,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let err = store
+            .get(&Path::from("stream/.stream.json"))
+            .await
+            .unwrap_err();
+        server.abort();
+
+        err
+    }
+
+    #[tokio::test]
+    async fn only_unreachable_store_and_server_errors_are_failed_over() {
+        assert!(is_unavailable(&ObjectStoreError::Generic {
+            store: "S3",
+            source: Box::new(connection_refused().await),
+        }));
+        assert!(is_unavailable(&s3_error("503 Service Unavailable").await));
+        assert!(is_unavailable(&s3_error("500 Internal Server Error").await));
+        assert!(!is_unavailable(&s3_error("403 Forbidden").await));
+        assert!(!is_unavailable(&s3_error("401 Unauthorized").await));
+        assert!(!is_unavailable(&s3_error("400 Bad Request").await));
+        assert!(!is_unavailable(&s3_error("404 Not Found").await));
+        assert!(!is_unavailable(
+            &ObjectStoreError::UnknownConfigurationKey {
+                store: "S3",
+                key: "token".to_owned(),
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn writes_are_mirrored_onto_secondary() {
+        let location = Path::from("stream/data.parquet");
+        let store = FailoverLayer::new(Flaky::default(), Some(Flaky::default()), true);
+
+        store
+            .put(&location, PutPayload::from_static(b"parquet"))
+            .await
+            .unwrap();
+        let mut upload = store
+            .put_multipart(&Path::from("stream/large.parquet"))
+            .await
+            .unwrap();
+        upload
+            .put_part(PutPayload::from_static(b"part"))
+            .await
+            .unwrap();
+        upload.complete().await.unwrap();
+
+        let secondary = store.secondary.as_ref().unwrap();
+        assert!(secondary.head(&location).await.is_ok());
+        assert!(secondary
+            .head(&Path::from("stream/large.parquet"))
+            .await
+            .is_ok());
+    }
+}
@@ -25,7 +25,10 @@ use std::{
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use datafusion::{datasource::listing::ListingTableUrl, execution::runtime_env::RuntimeEnvBuilder};
+use datafusion::{
+    datasource::listing::ListingTableUrl,
+    execution::{object_store::ObjectStoreRegistry, runtime_env::RuntimeEnvBuilder},
+};
 use fs_extra::file::CopyOptions;
 use futures::{stream::FuturesUnordered, TryStreamExt};
 use object_store::{buffered::BufReader, ObjectMeta};
@@ -80,6 +83,16 @@ impl ObjectStorageProvider for FSConfig {
         Arc::new(LocalFS::new(self.root.clone()))
     }
 
+    fn construct_bucket_client(&self, bucket: &str) -> Arc<dyn ObjectStorage> {
+        // buckets of a drive are the directories beside its data directory
+        Arc::new(LocalFS::new(self.root.with_file_name(bucket)))
+    }
+
+    fn register_bucket(&self, _registry: &dyn ObjectStoreRegistry, _bucket: &str) -> url::Url {
+        // files are recorded by their absolute path, read through the local file system store
+        url::Url::parse("file:///").unwrap()
+    }
+
     fn get_endpoint(&self) -> String {
         self.root.to_str().unwrap().to_string()
     }
@@ -128,13 +141,22 @@ impl ObjectStorage for LocalFS {
             ),
         )))
     }
-    async fn head(&self, _path: &RelativePath) -> Result<ObjectMeta, ObjectStorageError> {
-        Err(ObjectStorageError::UnhandledError(Box::new(
-            std::io::Error::new(
-                std::io::ErrorKind::Unsupported,
-                "Head operation not implemented for LocalFS yet",
-            ),
-        )))
+    async fn head(&self, path: &RelativePath) -> Result<ObjectMeta, ObjectStorageError> {
+        let metadata = match fs::metadata(self.path_in_root(path)).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ObjectStorageError::NoSuchKey(path.to_string()))
+            }
+            Err(e) => return Err(ObjectStorageError::UnhandledError(Box::new(e))),
+        };
+
+        Ok(ObjectMeta {
+            location: object_store::path::Path::from(path.as_str()),
+            last_modified: metadata.modified()?.into(),
+            size: metadata.len() as usize,
+            e_tag: None,
+            version: None,
+        })
     }
     async fn get_object(&self, path: &RelativePath) -> Result<Bytes, ObjectStorageError> {
         let time = Instant::now();
@@ -421,10 +443,60 @@ impl ObjectStorage for LocalFS {
 
     async fn list_manifest_files(
         &self,
-        _stream_name: &str,
+        stream_name: &str,
     ) -> Result<BTreeMap<String, Vec<String>>, ObjectStorageError> {
-        //unimplemented
-        Ok(BTreeMap::new())
+        let mut result_file_list: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for object in self.list_objects(RelativePath::new(stream_name)).await? {
+            // only the manifests of a date, `{stream}/date=../manifest.json`
+            let parts = object.location.parts().collect::<Vec<_>>();
+            let [_, date, file] = parts.as_slice() else {
+                continue;
+            };
+            if !date.as_ref().starts_with("date=") || !file.as_ref().ends_with("manifest.json") {
+                continue;
+            }
+            result_file_list
+                .entry(date.as_ref().to_owned())
+                .or_default()
+                .push(object.location.to_string());
+        }
+
+        Ok(result_file_list)
+    }
+
+    async fn list_objects(
+        &self,
+        prefix: &RelativePath,
+    ) -> Result<Vec<ObjectMeta>, ObjectStorageError> {
+        let mut objects = vec![];
+        let mut dirs = vec![self.root.join(prefix.as_str())];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let meta = entry.metadata().await?;
+                if meta.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let Ok(relative) = path.strip_prefix(&self.root) else {
+                    continue;
+                };
+                objects.push(ObjectMeta {
+                    location: object_store::path::Path::from(relative.to_string_lossy().as_ref()),
+                    last_modified: meta.modified()?.into(),
+                    size: meta.len() as usize,
+                    e_tag: None,
+                    version: None,
+                });
+            }
+        }
+
+        Ok(objects)
     }
 
     async fn upload_file(&self, key: &str, path: &Path) -> Result<(), ObjectStorageError> {
@@ -17,14 +17,15 @@
  */
 
 use object_store::path::Path;
-use relative_path::RelativePath;
+use relative_path::{RelativePath, RelativePathBuf};
 use serde::{Deserialize, Serialize};
 use tokio::task::JoinError;
 
 use crate::{
     catalog::snapshot::Snapshot,
     event::format::LogSourceEntry,
-    metadata::SchemaVersion,
+    handlers::http::users::USERS_ROOT_DIR,
+    metadata::{SchemaVersion, StreamSettings},
     option::StandaloneWithDistributed,
     parseable::StreamNotFound,
     stats::FullStats,
@@ -36,16 +37,20 @@ use chrono::Utc;
 use std::fmt::Debug;
 
 mod azure_blob;
+mod cache_layer;
+mod failover_layer;
 mod localfs;
 mod metrics_layer;
 pub mod object_storage;
 pub mod retention;
 mod s3;
 pub mod store_metadata;
+mod tagging_layer;
 
 use self::retention::Retention;
 pub use azure_blob::AzureBlobConfig;
-pub use localfs::FSConfig;
+pub use cache_layer::{evict_prefix, uncached_url};
+pub use localfs::{FSConfig, LocalFS};
 pub use object_storage::{ObjectStorage, ObjectStorageProvider};
 pub use s3::S3Config;
 pub use store_metadata::{
@@ -119,6 +124,8 @@ pub struct ObjectStoreFormat {
     pub stream_type: StreamType,
     #[serde(default)]
     pub log_source: Vec<LogSourceEntry>,
+    #[serde(flatten)]
+    pub settings: StreamSettings,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -221,6 +228,7 @@ impl Default for ObjectStoreFormat {
             static_schema_flag: false,
             hot_tier_enabled: false,
             log_source: vec![LogSourceEntry::default()],
+            settings: StreamSettings::default(),
         }
     }
 }
@@ -264,6 +272,56 @@ pub enum ObjectStorageError {
     JoinError(#[from] JoinError),
 }
 
+/// Root under which the data files of a stream are put, `{storage_prefix}/{stream_name}` when the
+/// stream declares a storage prefix of its own, the directory of the stream otherwise
+pub fn stream_data_root(stream_name: &str, storage_prefix: Option<&str>) -> RelativePathBuf {
+    match storage_prefix {
+        Some(prefix) => RelativePathBuf::from(prefix).join(stream_name),
+        None => RelativePathBuf::from(stream_name),
+    }
+}
+
+/// Checks that a storage prefix is a relative path of plain directory names, that doesn't land in
+/// the directories of parseable itself
+pub fn validate_storage_prefix(prefix: &str) -> Result<(), String> {
+    if prefix.is_empty() || prefix.starts_with('/') || prefix.ends_with('/') {
+        return Err(format!(
+            "storage prefix {prefix:?} must be a relative path without leading or trailing '/'"
+        ));
+    }
+    for part in prefix.split('/') {
+        if part.is_empty() || part.starts_with('.') || part.contains('\\') {
+            return Err(format!(
+                "storage prefix {prefix:?} must be made of directory names not starting with '.'"
+            ));
+        }
+    }
+    if [USERS_ROOT_DIR, "lost+found"].contains(&prefix.split('/').next().unwrap_or_default()) {
+        return Err(format!("storage prefix {prefix:?} is reserved"));
+    }
+
+    Ok(())
+}
+
+/// Checks that a bucket name is one both S3 buckets and Azure containers can be named, lowercase
+/// letters, digits, '-' and '.' starting and ending with a letter or digit
+pub fn validate_storage_bucket(bucket: &str) -> Result<(), String> {
+    let is_valid = (3..=63).contains(&bucket.len())
+        && bucket
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+        && bucket.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && bucket.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && !bucket.contains("..");
+    if !is_valid {
+        return Err(format!(
+            "invalid storage bucket {bucket:?}, expected 3 to 63 lowercase letters, digits, '-' or '.'"
+        ));
+    }
+
+    Ok(())
+}
+
 pub fn to_object_store_path(path: &RelativePath) -> Path {
     Path::from(path.as_str())
 }
@@ -23,7 +23,7 @@ use std::fmt::Debug;
 use std::fs::{remove_file, File};
 use std::num::NonZeroU32;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::time::Instant;
 
@@ -32,10 +32,14 @@ use arrow_schema::Schema;
 use async_trait::async_trait;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use datafusion::{datasource::listing::ListingTableUrl, execution::runtime_env::RuntimeEnvBuilder};
+use datafusion::{
+    datasource::listing::ListingTableUrl,
+    execution::{object_store::ObjectStoreRegistry, runtime_env::RuntimeEnvBuilder},
+};
 use object_store::buffered::BufReader;
-use object_store::ObjectMeta;
-use once_cell::sync::OnceCell;
+use object_store::{ObjectMeta, ObjectStore, PutMode};
+use once_cell::sync::{Lazy, OnceCell};
+use rand::distributions::{Alphanumeric, DistString};
 use relative_path::RelativePath;
 use relative_path::RelativePathBuf;
 use tracing::info;
@@ -51,16 +55,21 @@ use crate::handlers::http::modal::ingest_server::INGESTOR_EXPECT;
 use crate::handlers::http::modal::ingest_server::INGESTOR_META;
 use crate::handlers::http::users::CORRELATION_DIR;
 use crate::handlers::http::users::{DASHBOARDS_DIR, FILTER_DIR, USERS_ROOT_DIR};
+use crate::metadata::StreamSettings;
 use crate::metrics::storage::StorageMetrics;
 use crate::metrics::{EVENTS_STORAGE_SIZE_DATE, LIFETIME_EVENTS_STORAGE_SIZE, STORAGE_SIZE};
 use crate::option::Mode;
 use crate::parseable::LogStream;
 use crate::parseable::PARSEABLE;
+use crate::parseable::{dead_letter, RetryDecision};
 use crate::stats::FullStats;
+use crate::utils::arrow::sort_schema_fields;
+use crate::utils::time::PARTITION_ZONE_KEY;
 
 use super::{
-    retention::Retention, ObjectStorageError, ObjectStoreFormat, StorageMetadata,
-    ALERTS_ROOT_DIRECTORY, MANIFEST_FILE, PARSEABLE_METADATA_FILE_NAME, PARSEABLE_ROOT_DIRECTORY,
+    retention::Retention, stream_data_root, to_object_store_path, ObjectStorageError,
+    ObjectStoreFormat, StorageMetadata, ALERTS_ROOT_DIRECTORY, MANIFEST_FILE,
+    MIN_MULTIPART_UPLOAD_SIZE, PARSEABLE_METADATA_FILE_NAME, PARSEABLE_ROOT_DIRECTORY,
     SCHEMA_FILE_NAME, STREAM_METADATA_FILE_NAME, STREAM_ROOT_DIRECTORY,
 };
 
@@ -72,6 +81,28 @@ pub trait ObjectStorageProvider: StorageMetrics + std::fmt::Debug + Send + Sync
 
         STORE.get_or_init(|| self.construct_client()).clone()
     }
+    /// Client of another bucket, or container, of the same object store, for the streams that
+    /// put their data files in a bucket of their own
+    fn construct_bucket_client(&self, bucket: &str) -> Arc<dyn ObjectStorage>;
+    /// Client of the bucket the data files of a stream are put in, the default bucket if the
+    /// stream has none of its own
+    fn get_data_store(&self, bucket: Option<&str>) -> Arc<dyn ObjectStorage> {
+        static STORES: Lazy<Mutex<HashMap<String, Arc<dyn ObjectStorage>>>> =
+            Lazy::new(Default::default);
+
+        let Some(bucket) = bucket else {
+            return self.get_object_store();
+        };
+        STORES
+            .lock()
+            .unwrap()
+            .entry(bucket.to_owned())
+            .or_insert_with(|| self.construct_bucket_client(bucket))
+            .clone()
+    }
+    /// Registers the bucket for queries to read data files from, unless it already is, returns
+    /// the url it is registered under
+    fn register_bucket(&self, registry: &dyn ObjectStoreRegistry, bucket: &str) -> url::Url;
     fn get_endpoint(&self) -> String;
     fn register_store_metrics(&self, handler: &PrometheusMetrics);
     fn name(&self) -> &'static str;
@@ -96,6 +127,16 @@ pub trait ObjectStorage: Debug + Send + Sync + 'static {
         key: &RelativePath,
         path: &Path,
     ) -> Result<(), ObjectStorageError>;
+    /// Uploads the file without overwriting an object already at `key`, returning the key it
+    /// ended up under. Stores without conditional puts upload at `key` as is
+    async fn upload_if_absent(
+        &self,
+        key: &RelativePath,
+        path: &Path,
+    ) -> Result<RelativePathBuf, ObjectStorageError> {
+        self.upload_multipart(key, path).await?;
+        Ok(key.to_owned())
+    }
     async fn put_object(
         &self,
         path: &RelativePath,
@@ -193,6 +234,11 @@ pub trait ObjectStorage: Debug + Send + Sync + 'static {
         &self,
         stream_name: &str,
     ) -> Result<BTreeMap<String, Vec<String>>, ObjectStorageError>;
+    /// Lists every object under `prefix`, including those in nested directories
+    async fn list_objects(
+        &self,
+        prefix: &RelativePath,
+    ) -> Result<Vec<ObjectMeta>, ObjectStorageError>;
     async fn upload_file(&self, key: &str, path: &Path) -> Result<(), ObjectStorageError>;
     async fn delete_object(&self, path: &RelativePath) -> Result<(), ObjectStorageError>;
     async fn get_ingestor_meta_file_paths(
@@ -361,6 +407,17 @@ pub trait ObjectStorage: Debug + Send + Sync + 'static {
         self.put_object(&path, to_bytes(&stream_metadata)).await
     }
 
+    async fn put_stream_settings(
+        &self,
+        stream_name: &str,
+        settings: &StreamSettings,
+    ) -> Result<(), ObjectStorageError> {
+        let mut format = self.get_object_store_format(stream_name).await?;
+        format.settings = settings.clone();
+        self.put_object(&stream_json_path(stream_name), to_bytes(&format))
+            .await
+    }
+
     async fn put_metadata(
         &self,
         parseable_metadata: &StorageMetadata,
@@ -809,51 +866,74 @@ pub trait ObjectStorage: Debug + Send + Sync + 'static {
 
             let stream = PARSEABLE.get_or_create_stream(&stream_name);
             let custom_partition = stream.get_custom_partition();
+            let storage_prefix = stream.get_settings().storage_prefix.clone();
+            // data files go to the bucket of the stream, its manifests stay with its metadata
+            let data_store = PARSEABLE.data_store(&stream_name);
+            // batches converted by now are in the parquet files about to be uploaded
+            let converted = stream.acks.converted();
+            let mut uploaded_all = true;
             for path in stream.parquet_files() {
+                // files that failed to upload earlier are retried only once their backoff passes
+                if !stream.upload_retries.is_due(&path, Instant::now()) {
+                    uploaded_all = false;
+                    continue;
+                }
                 let filename = path
                     .file_name()
                     .expect("only parquet files are returned by iterator")
                     .to_str()
                     .expect("filename is valid string");
 
-                let mut file_date_part = filename.split('.').collect::<Vec<&str>>()[0];
-                file_date_part = file_date_part.split('=').collect::<Vec<&str>>()[1];
                 let compressed_size = path.metadata().map_or(0, |meta| meta.len());
-                STORAGE_SIZE
-                    .with_label_values(&["data", &stream_name, "parquet"])
-                    .add(compressed_size as i64);
-                EVENTS_STORAGE_SIZE_DATE
-                    .with_label_values(&["data", &stream_name, "parquet", file_date_part])
-                    .add(compressed_size as i64);
-                LIFETIME_EVENTS_STORAGE_SIZE
-                    .with_label_values(&["data", &stream_name, "parquet"])
-                    .add(compressed_size as i64);
-                let mut file_suffix = str::replacen(filename, ".", "/", 3);
-
-                let custom_partition_clone = custom_partition.clone();
-                if custom_partition_clone.is_some() {
-                    let custom_partition_fields = custom_partition_clone.unwrap();
-                    let custom_partition_list =
-                        custom_partition_fields.split(',').collect::<Vec<&str>>();
-                    file_suffix =
-                        str::replacen(filename, ".", "/", 3 + custom_partition_list.len());
-                }
-
-                let stream_relative_path = format!("{stream_name}/{file_suffix}");
+                add_uploaded_size(&stream_name, filename, compressed_size);
+                let stream_relative_path = stream_object_key(
+                    &stream_name,
+                    storage_prefix.as_deref(),
+                    filename,
+                    custom_partition.as_ref(),
+                );
 
                 // Try uploading the file, handle potential errors without breaking the loop
-                // if let Err(e) = self.upload_multipart(key, path)
-                if let Err(e) = self
-                    .upload_multipart(&RelativePathBuf::from(&stream_relative_path), &path)
-                    .await
-                {
-                    error!("Failed to upload file {filename:?}: {e}");
-                    continue; // Skip to the next file
-                }
+                let uploaded = if PARSEABLE.options.conditional_puts {
+                    data_store
+                        .upload_if_absent(&stream_relative_path, &path)
+                        .await
+                } else {
+                    data_store
+                        .upload_multipart(&stream_relative_path, &path)
+                        .await
+                        .map(|_| stream_relative_path)
+                };
+                let uploaded_path = match uploaded {
+                    Ok(uploaded_path) => uploaded_path,
+                    Err(e) => {
+                        error!("Failed to upload file {filename:?}: {e}");
+                        uploaded_all = false;
+                        match stream.upload_retries.failed(
+                            &path,
+                            Instant::now(),
+                            PARSEABLE.options.upload_max_retries,
+                            PARSEABLE.options.upload_retry_backoff,
+                        ) {
+                            RetryDecision::RetryAt(_) => {}
+                            RetryDecision::DeadLetter => {
+                                // batches converted so far may be in the file, they aren't acked
+                                stream.acks.mark_lost(stream.acks.converted());
+                                match dead_letter(&path) {
+                                    Ok(moved) => error!(
+                                        "Upload of {filename:?} failed after {} retries, moved it to {moved:?} for manual recovery",
+                                        PARSEABLE.options.upload_max_retries
+                                    ),
+                                    Err(e) => error!("Failed to move {filename:?} into dead-letter directory: {e}"),
+                                }
+                            }
+                        }
+                        continue; // Skip to the next file
+                    }
+                };
+                stream.upload_retries.succeeded(&path);
 
-                let absolute_path = self
-                    .absolute_url(RelativePath::from_path(&stream_relative_path).unwrap())
-                    .to_string();
+                let absolute_path = data_store.absolute_url(&uploaded_path).to_string();
                 let store = PARSEABLE.storage().get_object_store();
                 let manifest =
                     catalog::create_from_parquet_file(absolute_path.clone(), &path).unwrap();
@@ -864,6 +944,49 @@ pub trait ObjectStorage: Debug + Send + Sync + 'static {
                 }
             }
 
+            // parquet files built in memory when the local cache is disabled, those failing to
+            // upload are handed over to staging, to be retried like the files above
+            let mut pending = stream.take_pending_uploads().into_iter();
+            while let Some((filename, parquet)) = pending.next() {
+                let stream_relative_path = stream_object_key(
+                    &stream_name,
+                    storage_prefix.as_deref(),
+                    &filename,
+                    custom_partition.as_ref(),
+                );
+                if let Err(e) = data_store
+                    .put_object(&stream_relative_path, parquet.clone())
+                    .await
+                {
+                    error!("Failed to upload file {filename:?}: {e}");
+                    uploaded_all = false;
+                    stream.retry_upload(filename, parquet);
+                    continue;
+                }
+                add_uploaded_size(&stream_name, &filename, parquet.len() as u64);
+
+                let absolute_path = data_store.absolute_url(&stream_relative_path).to_string();
+                let store = PARSEABLE.storage().get_object_store();
+                let updated = match catalog::manifest::create_from_parquet(
+                    absolute_path,
+                    parquet.clone(),
+                    parquet.len() as u64,
+                ) {
+                    Ok(manifest) => catalog::update_snapshot(store, &stream_name, manifest).await,
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = updated {
+                    // put again along with the files not attempted yet on the next sync
+                    stream.retry_upload(filename, parquet);
+                    stream.restore_pending_uploads(pending.collect());
+                    return Err(e);
+                }
+            }
+
+            if uploaded_all {
+                stream.acks.mark_persisted(converted);
+            }
+
             for path in stream.schema_files() {
                 let file = File::open(&path)?;
                 let schema: Schema = serde_json::from_reader(file)?;
@@ -878,13 +1001,51 @@ pub trait ObjectStorage: Debug + Send + Sync + 'static {
     }
 }
 
+/// Accounts for the parquet file `filename` of `size` bytes put into object storage for the stream
+fn add_uploaded_size(stream_name: &str, filename: &str, size: u64) {
+    let mut file_date_part = filename.split('.').collect::<Vec<&str>>()[0];
+    file_date_part = file_date_part.split('=').collect::<Vec<&str>>()[1];
+    STORAGE_SIZE
+        .with_label_values(&["data", stream_name, "parquet"])
+        .add(size as i64);
+    EVENTS_STORAGE_SIZE_DATE
+        .with_label_values(&["data", stream_name, "parquet", file_date_part])
+        .add(size as i64);
+    LIFETIME_EVENTS_STORAGE_SIZE
+        .with_label_values(&["data", stream_name, "parquet"])
+        .add(size as i64);
+}
+
+/// Key in object storage of the staged parquet file `filename` of the stream, the dot separated
+/// partitions of the filename become directories under the data root of the stream, e.g.
+/// `date=2025-01-01.hour=10.minute=00.host.data.parquet` is put at
+/// `app/date=2025-01-01/hour=10/minute=00/host.data.parquet`
+pub fn stream_object_key(
+    stream_name: &str,
+    storage_prefix: Option<&str>,
+    filename: &str,
+    custom_partition: Option<&String>,
+) -> RelativePathBuf {
+    // partitions aligned to a timezone have a prefix of their own for its offset
+    let zone_prefixes = usize::from(
+        filename
+            .split('.')
+            .nth(3)
+            .is_some_and(|part| part.starts_with(&format!("{PARTITION_ZONE_KEY}="))),
+    );
+    let custom_partitions = custom_partition.map_or(0, |fields| fields.split(',').count());
+    let file_suffix = str::replacen(filename, ".", "/", 3 + zone_prefixes + custom_partitions);
+
+    stream_data_root(stream_name, storage_prefix).join(file_suffix)
+}
+
 pub async fn commit_schema_to_storage(
     stream_name: &str,
     schema: Schema,
 ) -> Result<(), ObjectStorageError> {
     let storage = PARSEABLE.storage().get_object_store();
     let stream_schema = storage.get_schema(stream_name).await?;
-    let new_schema = Schema::try_merge(vec![schema, stream_schema]).unwrap();
+    let new_schema = sort_schema_fields(&Schema::try_merge(vec![schema, stream_schema]).unwrap());
     storage.put_schema(stream_name, &new_schema).await
 }
 
@@ -971,3 +1132,317 @@ pub fn manifest_path(prefix: &str) -> RelativePathBuf {
         _ => RelativePathBuf::from_iter([prefix, MANIFEST_FILE]),
     }
 }
+
+/// Number of keys tried by [`put_if_absent`] before giving up
+const CONDITIONAL_PUT_ATTEMPTS: usize = 3;
+
+/// Puts `payload` at `key` only if no object exists there yet, so a concurrent writer to the same
+/// prefix is never overwritten. On a conflict the payload is put under a new key, which is returned.
+pub async fn put_if_absent(
+    store: &dyn ObjectStore,
+    key: &RelativePath,
+    payload: Bytes,
+) -> Result<RelativePathBuf, ObjectStorageError> {
+    let mut candidate = key.to_owned();
+    for _ in 0..CONDITIONAL_PUT_ATTEMPTS {
+        match store
+            .put_opts(
+                &to_object_store_path(&candidate),
+                payload.clone().into(),
+                PutMode::Create.into(),
+            )
+            .await
+        {
+            Ok(_) => return Ok(candidate),
+            Err(object_store::Error::AlreadyExists { path, .. }) => {
+                warn!("Object {path} was written by a concurrent writer, retrying under a new key");
+                candidate = with_conflict_suffix(key);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(ObjectStorageError::Custom(format!(
+        "Couldn't find a free key for {key} after {CONDITIONAL_PUT_ATTEMPTS} attempts"
+    )))
+}
+
+/// Uploads the file at `path` to `store` without overwriting an object already at `key`, returning
+/// the key it ended up under. Files small enough for a single request are put conditionally as is,
+/// larger ones are streamed by the multipart upload of `storage` into a key claimed beforehand with
+/// a conditional put of an empty object, so that they aren't read into memory
+pub async fn upload_file_if_absent(
+    store: &dyn ObjectStore,
+    storage: &(impl ObjectStorage + ?Sized),
+    key: &RelativePath,
+    path: &Path,
+) -> Result<RelativePathBuf, ObjectStorageError> {
+    if tokio::fs::metadata(path).await?.len() < MIN_MULTIPART_UPLOAD_SIZE as u64 {
+        let data = tokio::fs::read(path).await?;
+        return put_if_absent(store, key, data.into()).await;
+    }
+
+    let claimed = put_if_absent(store, key, Bytes::new()).await?;
+    if let Err(err) = storage.upload_multipart(&claimed, path).await {
+        // the claim is released, the file is uploaded under a key of its own on the next attempt
+        if let Err(err) = store.delete(&to_object_store_path(&claimed)).await {
+            warn!("Failed to delete claimed key {claimed} of failed upload: {err}");
+        }
+        return Err(err);
+    }
+
+    Ok(claimed)
+}
+
+/// Key with a random suffix right before the extension, e.g. `host.data.x7Kq2mPz.parquet`
+fn with_conflict_suffix(key: &RelativePath) -> RelativePathBuf {
+    let suffix = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+    match key.extension() {
+        Some(extension) => key.with_extension(format!("{suffix}.{extension}")),
+        None => RelativePathBuf::from(format!("{key}.{suffix}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    async fn read(store: &InMemory, key: &RelativePath) -> Bytes {
+        let object = store.get(&to_object_store_path(key)).await.unwrap();
+        object.bytes().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn conflicting_put_is_retried_under_new_key() {
+        let store = InMemory::new();
+        let key = RelativePath::new("stream/date=2025-01-01/hour=00/minute=00/host.data.parquet");
+        // a concurrent writer got there first
+        store
+            .put(&to_object_store_path(key), Bytes::from("theirs").into())
+            .await
+            .unwrap();
+
+        let uploaded = put_if_absent(&store, key, Bytes::from("ours"))
+            .await
+            .unwrap();
+
+        assert_ne!(uploaded.as_relative_path(), key);
+        assert_eq!(uploaded.parent(), key.parent());
+        assert_eq!(uploaded.extension(), Some("parquet"));
+        assert_eq!(read(&store, key).await, "theirs");
+        assert_eq!(read(&store, &uploaded).await, "ours");
+    }
+
+    #[tokio::test]
+    async fn free_key_is_used_as_is() {
+        let store = InMemory::new();
+        let key = RelativePath::new("stream/host.data.parquet");
+
+        let uploaded = put_if_absent(&store, key, Bytes::from("ours"))
+            .await
+            .unwrap();
+
+        assert_eq!(uploaded.as_relative_path(), key);
+    }
+
+    #[tokio::test]
+    async fn large_file_is_streamed_into_a_claimed_key() {
+        use object_store::local::LocalFileSystem;
+
+        use crate::storage::{FSConfig, ObjectStorageProvider};
+
+        let staging = temp_dir::TempDir::new().unwrap();
+        let root = temp_dir::TempDir::new().unwrap();
+        let store = LocalFileSystem::new_with_prefix(root.path()).unwrap();
+        let storage = FSConfig {
+            root: root.path().to_path_buf(),
+        }
+        .construct_client();
+
+        // too large to be put in a single request
+        let staged = staging.path().join("host.data.parquet");
+        File::create(&staged)
+            .unwrap()
+            .set_len(MIN_MULTIPART_UPLOAD_SIZE as u64)
+            .unwrap();
+        let key = RelativePath::new("stream/host.data.parquet");
+        // a concurrent writer got there first
+        store
+            .put(&to_object_store_path(key), Bytes::from("theirs").into())
+            .await
+            .unwrap();
+
+        let uploaded = upload_file_if_absent(&store, storage.as_ref(), key, &staged)
+            .await
+            .unwrap();
+
+        assert_ne!(uploaded.as_relative_path(), key);
+        let read = |key: &RelativePath| store.head(&to_object_store_path(key));
+        assert_eq!(read(key).await.unwrap().size, "theirs".len());
+        assert_eq!(
+            read(&uploaded).await.unwrap().size,
+            MIN_MULTIPART_UPLOAD_SIZE
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_with_storage_prefix_is_written_and_queried_under_it() {
+        use arrow_array::{Int64Array, RecordBatch};
+        use datafusion::prelude::{ParquetReadOptions, SessionContext};
+        use parquet::arrow::ArrowWriter;
+
+        use crate::storage::{FSConfig, ObjectStorageProvider};
+
+        let staging = temp_dir::TempDir::new().unwrap();
+        let root = temp_dir::TempDir::new().unwrap();
+        let storage = FSConfig {
+            root: root.path().to_path_buf(),
+        }
+        .construct_client();
+
+        let filename = "date=2025-01-01.hour=10.minute=00.host.data.parquet";
+        let staged = staging.path().join(filename);
+        let rb = RecordBatch::try_from_iter([(
+            "code",
+            Arc::new(Int64Array::from(vec![200, 404, 500])) as _,
+        )])
+        .unwrap();
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(&staged).unwrap(), rb.schema(), None)
+                .unwrap();
+        writer.write(&rb).unwrap();
+        writer.close().unwrap();
+
+        let key = stream_object_key("app", Some("team-a/logs"), filename, None);
+        assert_eq!(
+            key.as_str(),
+            "team-a/logs/app/date=2025-01-01/hour=10/minute=00/host.data.parquet"
+        );
+        storage.upload_multipart(&key, &staged).await.unwrap();
+        // nothing is put in the directory of the stream at the root
+        assert!(!root.path().join("app").exists());
+
+        // queries read the files recorded in the manifest, under the prefix
+        let file =
+            catalog::create_from_parquet_file(storage.absolute_url(&key).to_string(), &staged)
+                .unwrap();
+        let ctx = SessionContext::new();
+        let rows: usize = ctx
+            .read_parquet(
+                format!("/{}", file.file_path),
+                ParquetReadOptions::default(),
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum();
+        assert_eq!(rows, 3);
+
+        storage
+            .delete_prefix(&stream_data_root("app", Some("team-a/logs")))
+            .await
+            .unwrap();
+        assert!(!root.path().join("team-a/logs/app").exists());
+    }
+
+    #[tokio::test]
+    async fn stream_with_storage_bucket_is_written_to_and_queried_from_it() {
+        use actix_web::Either;
+        use arrow_array::{
+            cast::AsArray, types::Int64Type, Int64Array, RecordBatch, TimestampMillisecondArray,
+        };
+        use chrono::{TimeDelta, TimeZone};
+
+        use crate::{
+            event::DEFAULT_TIMESTAMP_KEY,
+            query::{execute, Query, QUERY_SESSION},
+            utils::time::TimeRange,
+        };
+
+        let stream_name = "bucket_app";
+        let stream = PARSEABLE.get_or_create_stream(stream_name);
+        stream.set_settings(StreamSettings {
+            storage_bucket: Some("team-a".to_owned()),
+            ..Default::default()
+        });
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 10, 30, 0).unwrap();
+        let rb = RecordBatch::try_from_iter([
+            (
+                DEFAULT_TIMESTAMP_KEY,
+                Arc::new(TimestampMillisecondArray::from(vec![
+                    at.timestamp_millis();
+                    3
+                ])) as _,
+            ),
+            ("code", Arc::new(Int64Array::from(vec![200, 404, 500])) as _),
+        ])
+        .unwrap();
+        stream.set_schema(&rb.schema());
+        let store = PARSEABLE.storage.get_object_store();
+        store
+            .create_stream(stream_name, ObjectStoreFormat::default(), rb.schema())
+            .await
+            .unwrap();
+
+        // uploaded as the files staged for the stream are
+        let mut parquet = vec![];
+        let mut writer =
+            parquet::arrow::ArrowWriter::try_new(&mut parquet, rb.schema(), None).unwrap();
+        writer.write(&rb).unwrap();
+        writer.close().unwrap();
+        let parquet = Bytes::from(parquet);
+        let key = stream_object_key(
+            stream_name,
+            None,
+            "date=2025-01-01.hour=10.minute=30.host.data.parquet",
+            None,
+        );
+        let data_store = PARSEABLE.data_store(stream_name);
+        data_store.put_object(&key, parquet.clone()).await.unwrap();
+        let file = catalog::manifest::create_from_parquet(
+            data_store.absolute_url(&key).to_string(),
+            parquet.clone(),
+            parquet.len() as u64,
+        )
+        .unwrap();
+        catalog::update_snapshot(store.clone(), stream_name, file)
+            .await
+            .unwrap();
+
+        // the data file is in the bucket of the stream, not the default one
+        assert!(data_store.get_object(&key).await.is_ok());
+        assert!(matches!(
+            store.get_object(&key).await,
+            Err(ObjectStorageError::NoSuchKey(_))
+        ));
+
+        let query = Query {
+            raw_logical_plan: QUERY_SESSION
+                .state()
+                .create_logical_plan(&format!("select sum(code) from {stream_name}"))
+                .await
+                .unwrap(),
+            time_range: TimeRange::new(at - TimeDelta::hours(1), at + TimeDelta::hours(1)),
+            filter_tag: None,
+        };
+        let (Either::Left(batches), _) = execute(query, stream_name, false).await.unwrap() else {
+            unreachable!("non-streaming query returns batches")
+        };
+        assert_eq!(
+            batches[0].column(0).as_primitive::<Int64Type>().value(0),
+            1104
+        );
+
+        PARSEABLE.delete_stream_data(stream_name).await.unwrap();
+        assert!(matches!(
+            data_store.get_object(&key).await,
+            Err(ObjectStorageError::NoSuchKey(_))
+        ));
+    }
+}
@@ -171,19 +171,37 @@ impl From<Retention> for Vec<TaskView> {
 mod action {
     use crate::catalog::remove_manifest_from_snapshot;
     use crate::parseable::PARSEABLE;
+    use crate::storage::stream_data_root;
     use chrono::{Days, NaiveDate, Utc};
     use futures::{stream::FuturesUnordered, StreamExt};
     use itertools::Itertools;
-    use relative_path::RelativePathBuf;
     use tracing::{error, info};
 
     pub(super) async fn delete(stream_name: String, days: u32) {
         info!("running retention task - delete for stream={stream_name}");
         let store = PARSEABLE.storage.get_object_store();
 
-        let retain_until = get_retain_until(Utc::now().date_naive(), days as u64);
-
-        let Ok(mut dates) = store.list_dates(&stream_name).await else {
+        let stream = PARSEABLE.get_stream(&stream_name).ok();
+        // dates are those of the timezone the partitions of the stream are aligned to
+        let today = match stream
+            .as_ref()
+            .and_then(|stream| stream.get_settings().partition_timezone())
+        {
+            Some(timezone) => Utc::now().with_timezone(&timezone).date_naive(),
+            None => Utc::now().date_naive(),
+        };
+        let retain_until = get_retain_until(today, days as u64);
+
+        // dated directories are under the storage prefix of the stream in its bucket, when it has them
+        let data_root = stream_data_root(
+            &stream_name,
+            stream
+                .as_ref()
+                .and_then(|stream| stream.get_settings().storage_prefix.clone())
+                .as_deref(),
+        );
+        let data_store = PARSEABLE.data_store(&stream_name);
+        let Ok(mut dates) = data_store.list_dates(data_root.as_str()).await else {
             return;
         };
         dates.retain(|date| date.starts_with("date"));
@@ -198,14 +216,9 @@ mod action {
                 remove_manifest_from_snapshot(store.clone(), &stream_name, dates.clone()).await;
 
             for date in dates_to_delete {
-                let path = RelativePathBuf::from_iter([&stream_name, &date]);
-                delete_tasks.push(async move {
-                    PARSEABLE
-                        .storage
-                        .get_object_store()
-                        .delete_prefix(&path)
-                        .await
-                });
+                let path = data_root.join(&date);
+                let data_store = data_store.clone();
+                delete_tasks.push(async move { data_store.delete_prefix(&path).await });
             }
 
             let res: Vec<_> = delete_tasks.collect().await;
@@ -259,5 +272,96 @@ mod action {
             let date = get_retain_until(current_date, 1);
             assert_eq!(date.day(), 1)
         }
+
+        #[tokio::test]
+        async fn retention_deletes_local_dates_of_stream_timezone() {
+            use std::sync::Arc;
+
+            use arrow_array::{RecordBatch, TimestampMillisecondArray};
+            use bytes::Bytes;
+            use chrono::{DateTime, Days, TimeZone, Utc};
+            use chrono_tz::America::New_York;
+            use parquet::arrow::ArrowWriter;
+            use relative_path::RelativePathBuf;
+
+            use crate::{
+                catalog, event::DEFAULT_TIMESTAMP_KEY, metadata::StreamSettings,
+                parseable::PARSEABLE, storage::ObjectStoreFormat,
+            };
+
+            let stream_name = "retention_new_york";
+            PARSEABLE
+                .get_or_create_stream(stream_name)
+                .set_settings(StreamSettings {
+                    partition_timezone: Some("America/New_York".to_owned()),
+                    ..Default::default()
+                });
+            let store = PARSEABLE.storage.get_object_store();
+            let batch_at = |at: DateTime<Utc>| {
+                RecordBatch::try_from_iter([(
+                    DEFAULT_TIMESTAMP_KEY,
+                    Arc::new(TimestampMillisecondArray::from(vec![at.timestamp_millis()])) as _,
+                )])
+                .unwrap()
+            };
+            // evenings in New York are on the next day in UTC
+            let evening = |day: NaiveDate| {
+                New_York
+                    .from_local_datetime(&day.and_hms_opt(21, 0, 0).unwrap())
+                    .single()
+                    .unwrap()
+                    .to_utc()
+            };
+            let today = Utc::now().with_timezone(&New_York).date_naive();
+            let old = today - Days::new(10);
+            store
+                .create_stream(
+                    stream_name,
+                    ObjectStoreFormat::default(),
+                    batch_at(evening(today)).schema(),
+                )
+                .await
+                .unwrap();
+            for day in [old, today] {
+                let at = evening(day);
+                let batch = batch_at(at);
+                let mut parquet = vec![];
+                let mut writer = ArrowWriter::try_new(&mut parquet, batch.schema(), None).unwrap();
+                writer.write(&batch).unwrap();
+                writer.close().unwrap();
+                let parquet = Bytes::from(parquet);
+                let path = RelativePathBuf::from(format!(
+                    "{stream_name}/date={day}/hour=21/minute=00/zone={}/host.data.parquet",
+                    at.with_timezone(&New_York).format("%z")
+                ));
+                store.put_object(&path, parquet.clone()).await.unwrap();
+                let file = catalog::manifest::create_from_parquet(
+                    store.absolute_url(&path).to_string(),
+                    parquet.clone(),
+                    parquet.len() as u64,
+                )
+                .unwrap();
+                catalog::update_snapshot(store.clone(), stream_name, file)
+                    .await
+                    .unwrap();
+            }
+
+            super::delete(stream_name.to_owned(), 5).await;
+
+            // the manifest left is that of the local date, rather than spanning two
+            let manifests = store
+                .get_object_store_format(stream_name)
+                .await
+                .unwrap()
+                .snapshot
+                .manifest_list;
+            assert_eq!(manifests.len(), 1);
+            assert!(manifests[0]
+                .manifest_path
+                .contains(&format!("date={today}/")));
+            let dates = store.list_dates(stream_name).await.unwrap();
+            assert!(!dates.contains(&format!("date={old}")));
+            assert!(dates.contains(&format!("date={today}")));
+        }
     }
 }
@@ -36,7 +36,10 @@ use datafusion::{
 };
 use futures::{stream::FuturesUnordered, StreamExt, TryStreamExt};
 use object_store::{
-    aws::{AmazonS3, AmazonS3Builder, AmazonS3ConfigKey, Checksum},
+    aws::{
+        AmazonS3, AmazonS3Builder, AmazonS3ConfigKey, Checksum, S3ConditionalPut,
+        S3EncryptionConfigKey,
+    },
     buffered::BufReader,
     limit::LimitStore,
     path::Path as StorePath,
@@ -53,10 +56,14 @@ use crate::{
 };
 
 use super::{
-    metrics_layer::MetricLayer, object_storage::parseable_json_path, to_object_store_path,
-    ObjectStorage, ObjectStorageError, ObjectStorageProvider, CONNECT_TIMEOUT_SECS,
-    MIN_MULTIPART_UPLOAD_SIZE, PARSEABLE_ROOT_DIRECTORY, REQUEST_TIMEOUT_SECS, SCHEMA_FILE_NAME,
-    STREAM_METADATA_FILE_NAME, STREAM_ROOT_DIRECTORY,
+    cache_layer::register_store,
+    failover_layer::FailoverLayer,
+    metrics_layer::MetricLayer,
+    object_storage::{parseable_json_path, upload_file_if_absent},
+    tagging_layer::TaggingLayer,
+    to_object_store_path, ObjectStorage, ObjectStorageError, ObjectStorageProvider,
+    CONNECT_TIMEOUT_SECS, MIN_MULTIPART_UPLOAD_SIZE, PARSEABLE_ROOT_DIRECTORY,
+    REQUEST_TIMEOUT_SECS, SCHEMA_FILE_NAME, STREAM_METADATA_FILE_NAME, STREAM_ROOT_DIRECTORY,
 };
 
 // in bytes
@@ -94,8 +101,8 @@ pub struct S3Config {
     pub bucket_name: String,
 
     /// Server side encryption to use for operations with objects.
-    /// Currently, this only supports SSE-C. Value should be
-    /// like SSE-C:AES256:<base64_encoded_encryption_key>.
+    /// Value should be like SSE-C:AES256:<base64_encoded_encryption_key>,
+    /// SSE-S3 or SSE-KMS:<kms_key_id>.
     #[arg(
         long,
         env = "P_S3_SSEC_ENCRYPTION_KEY",
@@ -147,6 +154,37 @@ pub struct S3Config {
         required = false
     )]
     pub metadata_endpoint: Option<String>,
+
+    /// Bucket that reads fail over to when the primary bucket is unavailable,
+    /// e.g. one replicated into another region
+    #[arg(long, env = "P_S3_SECONDARY_BUCKET", value_name = "bucket-name")]
+    pub secondary_bucket_name: Option<String>,
+
+    /// The region of the secondary bucket, defaults to that of the primary
+    #[arg(long, env = "P_S3_SECONDARY_REGION", value_name = "region")]
+    pub secondary_region: Option<String>,
+
+    /// The endpoint of the secondary bucket, defaults to that of the primary
+    #[arg(long, env = "P_S3_SECONDARY_URL", value_name = "url")]
+    pub secondary_endpoint_url: Option<String>,
+
+    /// Set client to mirror every write onto the secondary bucket as well
+    #[arg(
+        long,
+        env = "P_S3_DUAL_WRITE",
+        value_name = "bool",
+        default_value = "false"
+    )]
+    pub dual_write: bool,
+
+    /// Set client to tag objects with their stream, date and tier, for lifecycle rules to match
+    #[arg(
+        long,
+        env = "P_S3_OBJECT_TAGGING",
+        value_name = "bool",
+        default_value = "false"
+    )]
+    pub object_tagging: bool,
 }
 
 /// This represents the server side encryption to be
@@ -161,13 +199,17 @@ pub enum SSECEncryptionKey {
         _algorithm: ObjectEncryptionAlgorithm,
         base64_encryption_key: String,
     },
+    /// https://docs.aws.amazon.com/AmazonS3/latest/userguide/UsingServerSideEncryption.html
+    SseS3,
+    /// https://docs.aws.amazon.com/AmazonS3/latest/userguide/UsingKMSEncryption.html
+    SseKms { kms_key_id: String },
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum SSEError {
-    #[error("Expected SSE-C:AES256:<base64_encryption_key>")]
+    #[error("Expected SSE-C:AES256:<base64_encryption_key>, SSE-S3 or SSE-KMS:<kms_key_id>")]
     UnexpectedKey,
-    #[error("Only SSE-C is supported for object encryption for now")]
+    #[error("Only SSE-C, SSE-S3 and SSE-KMS are supported for object encryption for now")]
     UnexpectedProtocol,
     #[error("Invalid SSE algorithm. Following are supported: AES256")]
     InvalidAlgorithm,
@@ -177,15 +219,24 @@ impl FromStr for SSECEncryptionKey {
     type Err = SSEError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sse_type, rest) = s.split_once(':').unwrap_or((s, ""));
+        match sse_type {
+            "SSE-C" => {}
+            "SSE-S3" if rest.is_empty() => return Ok(SSECEncryptionKey::SseS3),
+            // key ids can be ARNs, which have colons of their own
+            "SSE-KMS" if !rest.is_empty() => {
+                return Ok(SSECEncryptionKey::SseKms {
+                    kms_key_id: rest.to_owned(),
+                })
+            }
+            "SSE-S3" | "SSE-KMS" => return Err(SSEError::UnexpectedKey),
+            _ => return Err(SSEError::UnexpectedProtocol),
+        }
+
         let parts = s.split(':').collect::<Vec<_>>();
         if parts.len() != 3 {
             return Err(SSEError::UnexpectedKey);
         }
-        let sse_type = parts[0];
-        if sse_type != "SSE-C" {
-            return Err(SSEError::UnexpectedProtocol);
-        }
-
         let algorithm = parts[1];
         let encryption_key = parts[2];
 
@@ -246,7 +297,9 @@ impl S3Config {
             .with_bucket_name(&self.bucket_name)
             .with_virtual_hosted_style_request(!self.use_path_style)
             .with_allow_http(true)
-            .with_retry(retry_config);
+            .with_retry(retry_config)
+            // only takes effect for conditional puts, made when P_CONDITIONAL_PUTS is set
+            .with_conditional_put(S3ConditionalPut::ETagMatch);
 
         if self.set_checksum {
             builder = builder.with_checksum_algorithm(Checksum::SHA256)
@@ -268,6 +321,15 @@ impl S3Config {
                 } => {
                     builder = builder.with_ssec_encryption(base64_encryption_key);
                 }
+                SSECEncryptionKey::SseS3 => {
+                    builder = builder.with_config(
+                        AmazonS3ConfigKey::Encryption(S3EncryptionConfigKey::ServerSideEncryption),
+                        "AES256",
+                    );
+                }
+                SSECEncryptionKey::SseKms { kms_key_id } => {
+                    builder = builder.with_sse_kms_encryption(kms_key_id);
+                }
             }
         }
 
@@ -288,6 +350,23 @@ impl S3Config {
 
         builder.with_client_options(client_options)
     }
+
+    /// Client that fails over to the secondary bucket, if one is configured
+    fn get_failover_client(&self) -> FailoverLayer<AmazonS3> {
+        let primary = self.get_default_builder().build().unwrap();
+        let secondary = self.secondary_bucket_name.as_ref().map(|bucket_name| {
+            let mut builder = self.get_default_builder().with_bucket_name(bucket_name);
+            if let Some(region) = &self.secondary_region {
+                builder = builder.with_region(region);
+            }
+            if let Some(endpoint_url) = &self.secondary_endpoint_url {
+                builder = builder.with_endpoint(endpoint_url);
+            }
+            builder.build().unwrap()
+        });
+
+        FailoverLayer::new(primary, secondary, self.dual_write)
+    }
 }
 
 impl ObjectStorageProvider for S3Config {
@@ -296,7 +375,7 @@ impl ObjectStorageProvider for S3Config {
     }
 
     fn get_datafusion_runtime(&self) -> RuntimeEnvBuilder {
-        let s3 = self.get_default_builder().build().unwrap();
+        let s3 = self.get_failover_client();
 
         // limit objectstore to a concurrent request limit
         let s3 = LimitStore::new(s3, super::MAX_OBJECT_STORE_REQUESTS);
@@ -304,13 +383,13 @@ impl ObjectStorageProvider for S3Config {
 
         let object_store_registry = DefaultObjectStoreRegistry::new();
         let url = ObjectStoreUrl::parse(format!("s3://{}", &self.bucket_name)).unwrap();
-        object_store_registry.register_store(url.as_ref(), Arc::new(s3));
+        register_store(&object_store_registry, url.as_ref(), s3);
 
         RuntimeEnvBuilder::new().with_object_store_registry(Arc::new(object_store_registry))
     }
 
     fn construct_client(&self) -> Arc<dyn ObjectStorage> {
-        let s3 = self.get_default_builder().build().unwrap();
+        let s3 = TaggingLayer::new(self.get_failover_client(), self.object_tagging);
 
         Arc::new(S3 {
             client: s3,
@@ -319,6 +398,40 @@ impl ObjectStorageProvider for S3Config {
         })
     }
 
+    fn construct_bucket_client(&self, bucket: &str) -> Arc<dyn ObjectStorage> {
+        // the secondary bucket only mirrors the default one
+        let primary = self
+            .get_default_builder()
+            .with_bucket_name(bucket)
+            .build()
+            .unwrap();
+        let s3 = TaggingLayer::new(
+            FailoverLayer::new(primary, None, false),
+            self.object_tagging,
+        );
+
+        Arc::new(S3 {
+            client: s3,
+            bucket: bucket.to_owned(),
+            root: StorePath::from(""),
+        })
+    }
+
+    fn register_bucket(&self, registry: &dyn ObjectStoreRegistry, bucket: &str) -> url::Url {
+        let url = url::Url::parse(&format!("s3://{bucket}")).unwrap();
+        if registry.get_store(&url).is_err() {
+            let s3 = self
+                .get_default_builder()
+                .with_bucket_name(bucket)
+                .build()
+                .unwrap();
+            let s3 = LimitStore::new(s3, super::MAX_OBJECT_STORE_REQUESTS);
+            register_store(registry, &url, MetricLayer::new(s3));
+        }
+
+        url
+    }
+
     fn get_endpoint(&self) -> String {
         format!("{}/{}", self.endpoint_url, self.bucket_name)
     }
@@ -330,7 +443,7 @@ impl ObjectStorageProvider for S3Config {
 
 #[derive(Debug)]
 pub struct S3 {
-    client: AmazonS3,
+    client: TaggingLayer<FailoverLayer<AmazonS3>>,
     bucket: String,
     root: StorePath,
 }
@@ -530,37 +643,31 @@ impl S3 {
             // async_writer.complete().await?;
             return Ok(());
         } else {
-            let mut data = Vec::new();
-            file.read_to_end(&mut data).await?;
-
-            // let mut upload_parts = Vec::new();
-
-            let has_final_partial_part = total_size % MIN_MULTIPART_UPLOAD_SIZE > 0;
-            let num_full_parts = total_size / MIN_MULTIPART_UPLOAD_SIZE;
-            let total_parts = num_full_parts + if has_final_partial_part { 1 } else { 0 };
-
-            // Upload each part
-            for part_number in 0..(total_parts) {
-                let start_pos = part_number * MIN_MULTIPART_UPLOAD_SIZE;
-                let end_pos = if part_number == num_full_parts && has_final_partial_part {
-                    // Last part might be smaller than 5MB (which is allowed)
-                    total_size
-                } else {
-                    // All other parts must be at least 5MB
-                    start_pos + MIN_MULTIPART_UPLOAD_SIZE
-                };
-
-                // Extract this part's data
-                let part_data = data[start_pos..end_pos].to_vec();
-
-                // Upload the part
-                async_writer.put_part(part_data.into()).await?;
+            // read the file a part at a time, rather than all of it into memory
+            let mut part = vec![0; MIN_MULTIPART_UPLOAD_SIZE];
+            loop {
+                let mut len = 0;
+                while len < part.len() {
+                    match file.read(&mut part[len..]).await? {
+                        0 => break,
+                        read => len += read,
+                    }
+                }
+                if len == 0 {
+                    break;
+                }
 
-                // upload_parts.push(part_number as u64 + 1);
+                // All parts but the last are 5MB, the last might be smaller (which is allowed)
+                if let Err(err) = async_writer.put_part(part[..len].to_vec().into()).await {
+                    error!("Failed to upload part of multipart upload. {:?}", err);
+                    async_writer.abort().await?;
+                    return Err(err.into());
+                }
             }
             if let Err(err) = async_writer.complete().await {
                 error!("Failed to complete multipart upload. {:?}", err);
                 async_writer.abort().await?;
+                return Err(err.into());
             };
         }
         Ok(())
@@ -587,6 +694,13 @@ impl ObjectStorage for S3 {
     ) -> Result<(), ObjectStorageError> {
         self._upload_multipart(key, path).await
     }
+    async fn upload_if_absent(
+        &self,
+        key: &RelativePath,
+        path: &Path,
+    ) -> Result<RelativePathBuf, ObjectStorageError> {
+        upload_file_if_absent(&self.client, self, key, path).await
+    }
     async fn head(&self, path: &RelativePath) -> Result<ObjectMeta, ObjectStorageError> {
         Ok(self.client.head(&to_object_store_path(path)).await?)
     }
@@ -790,6 +904,14 @@ impl ObjectStorage for S3 {
         Ok(files)
     }
 
+    async fn list_objects(
+        &self,
+        prefix: &RelativePath,
+    ) -> Result<Vec<ObjectMeta>, ObjectStorageError> {
+        let prefix = to_object_store_path(prefix);
+        Ok(self.client.list(Some(&prefix)).try_collect().await?)
+    }
+
     async fn upload_file(&self, key: &str, path: &Path) -> Result<(), ObjectStorageError> {
         self._upload_file(key, path).await?;
 
@@ -863,3 +985,85 @@ impl From<serde_json::Error> for ObjectStorageError {
         ObjectStorageError::UnhandledError(Box::new(error))
     }
 }
+
+#[cfg(all(test, feature = "object-store-tests"))]
+mod tests {
+    use object_store::{path::Path as StorePath, ObjectStore, PutPayload};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{S3Config, SSECEncryptionKey};
+
+    /// Puts an object through a client built from `encryption`, against a server that answers
+    /// as S3 would, returns the headers of the request it received
+    async fn put_request_headers(encryption: SSECEncryptionKey) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let mut request = vec![];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nETag: \"0\"\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+
+            String::from_utf8_lossy(&request).to_lowercase()
+        });
+
+        let config = S3Config {
+            endpoint_url,
+            access_key_id: Some("access".to_owned()),
+            secret_key: Some("secret".to_owned()),
+            region: "us-east-1".to_owned(),
+            bucket_name: "bucket".to_owned(),
+            ssec_encryption_key: Some(encryption),
+            set_checksum: false,
+            use_path_style: true,
+            skip_tls: false,
+            imdsv1_fallback: false,
+            metadata_endpoint: None,
+            secondary_bucket_name: None,
+            secondary_region: None,
+            secondary_endpoint_url: None,
+            dual_write: false,
+            object_tagging: false,
+        };
+        let store = config.get_default_builder().build().unwrap();
+        store
+            .put(&StorePath::from("test"), PutPayload::from_static(b"data"))
+            .await
+            .unwrap();
+
+        server.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn put_carries_kms_encryption_headers() {
+        let encryption = "SSE-KMS:arn:aws:kms:us-east-1:123456789012:key/abcd"
+            .parse()
+            .unwrap();
+        let headers = put_request_headers(encryption).await;
+
+        assert!(headers.contains("x-amz-server-side-encryption: aws:kms"));
+        assert!(headers.contains(
+            "x-amz-server-side-encryption-aws-kms-key-id: arn:aws:kms:us-east-1:123456789012:key/abcd"
+        ));
+    }
+
+    #[tokio::test]
+    async fn put_carries_s3_encryption_header() {
+        let headers = put_request_headers(SSECEncryptionKey::SseS3).await;
+
+        assert!(headers.contains("x-amz-server-side-encryption: aes256"));
+    }
+}
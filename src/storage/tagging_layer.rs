@@ -0,0 +1,296 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::fmt::Display;
+
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use object_store::{
+    path::Path, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+    PutMultipartOpts, PutOptions, PutPayload, PutResult, Result as ObjectStoreResult, TagSet,
+};
+
+use crate::catalog::compaction::COMPACTED_FILE_SUFFIX;
+
+/// Tags the objects put into the store with what they hold, so that lifecycle rules of the bucket
+/// can transition or expire them, e.g. the data files of a stream after a while:
+/// - `stream`: stream the object belongs to
+/// - `date`: date of the data in the object, for data files only
+/// - `tier`: `data` for parquet files, `compacted` for those written by compaction and
+///   `metadata` for all other objects
+#[derive(Debug)]
+pub struct TaggingLayer<T: ObjectStore> {
+    inner: T,
+    enabled: bool,
+}
+
+impl<T: ObjectStore> TaggingLayer<T> {
+    pub fn new(inner: T, enabled: bool) -> Self {
+        Self { inner, enabled }
+    }
+
+    fn tagged(&self, location: &Path, mut tags: TagSet) -> TagSet {
+        if self.enabled {
+            for (key, value) in object_tags(location) {
+                tags.push(key, &value);
+            }
+        }
+        tags
+    }
+}
+
+/// Tags of the object at `location`, data files are put under `{stream}/date={date}/..`, possibly
+/// behind the storage prefix of the stream, whereas the metadata of a stream is under `{stream}/`
+pub fn object_tags(location: &Path) -> Vec<(&'static str, String)> {
+    let parts: Vec<_> = location.parts().collect();
+    let date = parts
+        .iter()
+        .position(|part| part.as_ref().starts_with("date="));
+    let stream = match date {
+        Some(date) => date.checked_sub(1).map(|stream| &parts[stream]),
+        None => parts.first().filter(|_| parts.len() > 1),
+    };
+
+    let mut tags = vec![];
+    if let Some(stream) = stream.filter(|stream| !stream.as_ref().starts_with('.')) {
+        tags.push(("stream", stream.as_ref().to_owned()));
+    }
+    if let Some(date) = date {
+        let date = parts[date].as_ref().trim_start_matches("date=");
+        tags.push(("date", date.to_owned()));
+    }
+    let file_name = location.filename().unwrap_or_default();
+    let tier = if file_name.ends_with(COMPACTED_FILE_SUFFIX) {
+        "compacted"
+    } else if file_name.ends_with(".parquet") {
+        "data"
+    } else {
+        "metadata"
+    };
+    tags.push(("tier", tier.to_owned()));
+
+    tags
+}
+
+impl<T: ObjectStore> Display for TaggingLayer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tagging({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl<T: ObjectStore> ObjectStore for TaggingLayer<T> {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> ObjectStoreResult<PutResult> {
+        let opts = PutOptions {
+            tags: self.tagged(location, opts.tags),
+            ..opts
+        };
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+        let opts = PutMultipartOpts {
+            tags: self.tagged(location, opts.tags),
+            ..opts
+        };
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> ObjectStoreResult<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn head(&self, location: &Path) -> ObjectStoreResult<ObjectMeta> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> ObjectStoreResult<()> {
+        self.inner.delete(location).await
+    }
+
+    fn delete_stream<'a>(
+        &'a self,
+        locations: BoxStream<'a, ObjectStoreResult<Path>>,
+    ) -> BoxStream<'a, ObjectStoreResult<Path>> {
+        self.inner.delete_stream(locations)
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    fn list_with_offset(
+        &self,
+        prefix: Option<&Path>,
+        offset: &Path,
+    ) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+        self.inner.list_with_offset(prefix, offset)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> ObjectStoreResult<ListResult> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    /// In memory store that records the tags of the objects put into it
+    #[derive(Debug, Default)]
+    struct Recording {
+        inner: InMemory,
+        tags: Mutex<Vec<(Path, String)>>,
+    }
+
+    impl Display for Recording {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Recording")
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for Recording {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> ObjectStoreResult<PutResult> {
+            self.tags
+                .lock()
+                .unwrap()
+                .push((location.clone(), opts.tags.encoded().to_owned()));
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOpts,
+        ) -> ObjectStoreResult<Box<dyn MultipartUpload>> {
+            self.tags
+                .lock()
+                .unwrap()
+                .push((location.clone(), opts.tags.encoded().to_owned()));
+            self.inner.put_multipart_opts(location, opts).await
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> ObjectStoreResult<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> ObjectStoreResult<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, ObjectStoreResult<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> ObjectStoreResult<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> ObjectStoreResult<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    #[tokio::test]
+    async fn puts_are_tagged_with_stream_date_and_tier() {
+        let store = TaggingLayer::new(Recording::default(), true);
+        let data = Path::from("app/date=2025-01-01/hour=10/minute=00/host.data.parquet");
+        let compacted = Path::from("team/app/date=2025-01-02/01J.compacted.parquet");
+        let metadata = Path::from("app/.stream/.stream.json");
+
+        store
+            .put(&data, PutPayload::from_static(b"parquet"))
+            .await
+            .unwrap();
+        let mut upload = store.put_multipart(&compacted).await.unwrap();
+        upload
+            .put_part(PutPayload::from_static(b"parquet"))
+            .await
+            .unwrap();
+        upload.complete().await.unwrap();
+        store
+            .put(&metadata, PutPayload::from_static(b"{}"))
+            .await
+            .unwrap();
+
+        let tags = store.inner.tags.lock().unwrap().clone();
+        assert_eq!(
+            tags,
+            [
+                (data, "stream=app&date=2025-01-01&tier=data".to_owned()),
+                (
+                    compacted,
+                    "stream=app&date=2025-01-02&tier=compacted".to_owned()
+                ),
+                (metadata, "stream=app&tier=metadata".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn puts_are_not_tagged_when_disabled() {
+        let store = TaggingLayer::new(Recording::default(), false);
+        store
+            .put(
+                &Path::from("app/.stream/.stream.json"),
+                PutPayload::from_static(b"{}"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(store.inner.tags.lock().unwrap()[0].1, "");
+    }
+}
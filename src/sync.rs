@@ -20,16 +20,47 @@ use chrono::{TimeDelta, Timelike};
 use std::collections::HashMap;
 use std::future::Future;
 use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinSet;
-use tokio::time::{interval_at, sleep, Duration, Instant};
+use tokio::time::{interval_at, sleep, Duration, Instant, Interval};
 use tokio::{select, task};
 use tracing::{error, info, trace, warn};
 
 use crate::alerts::{alerts_utils, AlertTask};
+use crate::catalog::{compaction, gc};
+use crate::event::counter::EVENT_COUNTER;
 use crate::parseable::PARSEABLE;
 use crate::{LOCAL_SYNC_INTERVAL, STORAGE_UPLOAD_INTERVAL};
 
+/// Interval (in seconds) at which in-memory arrows are flushed and converted into parquet,
+/// starts out as `LOCAL_SYNC_INTERVAL` and can be tuned at runtime.
+static LOCAL_SYNC_INTERVAL_SECS: AtomicU64 = AtomicU64::new(LOCAL_SYNC_INTERVAL.as_secs());
+
+/// Returns the interval currently used by the local sync task
+pub fn local_sync_interval() -> Duration {
+    Duration::from_secs(LOCAL_SYNC_INTERVAL_SECS.load(Ordering::Relaxed))
+}
+
+/// Updates the interval used by the local sync task, takes effect from the next tick onwards.
+/// Intervals shorter than a second are rounded up to a second.
+pub fn set_local_sync_interval(interval: Duration) {
+    LOCAL_SYNC_INTERVAL_SECS.store(interval.as_secs().max(1), Ordering::Relaxed);
+}
+
+/// Resets `sync_interval` if `period`, the configured local sync interval, has changed since it
+/// was created, returns `true` if the interval was reset.
+fn refresh_sync_interval(sync_interval: &mut Interval, period: Duration) -> bool {
+    if sync_interval.period() == period {
+        return false;
+    }
+
+    info!("Local sync interval updated to {period:?}");
+    *sync_interval = interval_at(Instant::now() + period, period);
+
+    true
+}
+
 // Calculates the instant that is the start of the next minute
 fn next_minute() -> Instant {
     let now = chrono::Utc::now();
@@ -73,7 +104,7 @@ where
     }
 }
 
-/// Flushes arrows onto disk every `LOCAL_SYNC_INTERVAL` seconds, packs arrows into parquet every
+/// Flushes arrows onto disk every `local_sync_interval()` seconds or once a stream's size/age trigger fires, packs arrows into parquet every
 /// `STORAGE_CONVERSION_INTERVAL` secondsand uploads them every `STORAGE_UPLOAD_INTERVAL` seconds.
 #[tokio::main(flavor = "multi_thread", worker_threads = 2)]
 pub async fn handler(mut cancel_rx: oneshot::Receiver<()>) -> anyhow::Result<()> {
@@ -92,6 +123,9 @@ pub async fn handler(mut cancel_rx: oneshot::Receiver<()>) -> anyhow::Result<()>
                 if let Err(e) = remote_sync_handler.await {
                     error!("Error joining remote_sync_handler: {e:?}");
                 }
+                if let Err(e) = EVENT_COUNTER.persist().await {
+                    warn!("failed to persist the count of ingested events. {e:?}");
+                }
                 return Ok(());
             },
             _ = &mut localsync_outbox => {
@@ -121,6 +155,25 @@ pub fn object_store_sync() -> (
     let handle = task::spawn(async move {
         let result = std::panic::catch_unwind(AssertUnwindSafe(|| async move {
             let mut sync_interval = interval_at(next_minute(), STORAGE_UPLOAD_INTERVAL);
+            // compaction runs on the same task as uploads, so that it never races with them over manifests
+            let compaction_period = PARSEABLE.options.compaction_interval;
+            let mut compaction_interval = interval_at(
+                next_minute() + compaction_period.unwrap_or(STORAGE_UPLOAD_INTERVAL),
+                compaction_period.unwrap_or(STORAGE_UPLOAD_INTERVAL),
+            );
+            // as is garbage collection, so that it never deletes a file being uploaded or compacted
+            let gc_period = PARSEABLE.options.gc_interval;
+            let mut gc_interval = interval_at(
+                next_minute() + gc_period.unwrap_or(STORAGE_UPLOAD_INTERVAL),
+                gc_period.unwrap_or(STORAGE_UPLOAD_INTERVAL),
+            );
+
+            let events_counter_period = PARSEABLE
+                .options
+                .events_counter_interval
+                .max(Duration::from_secs(1));
+            let mut events_counter_interval =
+                interval_at(next_minute() + events_counter_period, events_counter_period);
 
             let mut inbox_rx = AssertUnwindSafe(inbox_rx);
 
@@ -143,6 +196,30 @@ pub fn object_store_sync() -> (
                             warn!("failed to upload local data with object store. {e:?}");
                         }
                     },
+                    _ = compaction_interval.tick(), if compaction_period.is_some() => {
+                        trace!("Compacting small parquet files in Object Store... ");
+                        monitor_task_duration(
+                            "object_store_compaction",
+                            Duration::from_secs(60),
+                            compaction::compact_streams,
+                        )
+                        .await;
+                    },
+                    _ = gc_interval.tick(), if gc_period.is_some() => {
+                        trace!("Deleting orphaned parquet files in Object Store... ");
+                        monitor_task_duration(
+                            "object_store_gc",
+                            Duration::from_secs(60),
+                            gc::collect_garbage,
+                        )
+                        .await;
+                    },
+                    _ = events_counter_interval.tick() => {
+                        trace!("Persisting the count of ingested events... ");
+                        if let Err(e) = EVENT_COUNTER.persist().await {
+                            warn!("failed to persist the count of ingested events. {e:?}");
+                        }
+                    },
                     res = &mut inbox_rx => {match res{
                         Ok(_) => break,
                         Err(_) => {
@@ -184,14 +261,26 @@ pub fn local_sync() -> (
         let mut inbox_rx = inbox_rx;
 
         let result = std::panic::catch_unwind(AssertUnwindSafe(|| async move {
-            let mut sync_interval = interval_at(next_minute(), LOCAL_SYNC_INTERVAL);
+            let mut sync_interval = interval_at(next_minute(), local_sync_interval());
+            // size and age triggers are checked every second, in between local syncs
+            let options = &PARSEABLE.options;
+            let flush_triggers = options.flush_max_rows.is_some()
+                || options.flush_max_size.is_some()
+                || options.flush_max_age.is_some();
+            let mut flush_check_interval = interval_at(Instant::now(), Duration::from_secs(1));
             let mut joinset = JoinSet::new();
 
             loop {
                 select! {
-                    // Spawns a flush+conversion task every `LOCAL_SYNC_INTERVAL` seconds
+                    // Spawns a flush+conversion task every `local_sync_interval()` seconds
                     _ = sync_interval.tick() => {
-                        PARSEABLE.streams.flush_and_convert(&mut joinset, false)
+                        PARSEABLE.streams.flush_and_convert(&mut joinset, false);
+                        // pick up changes made to the interval at runtime
+                        refresh_sync_interval(&mut sync_interval, local_sync_interval());
+                    },
+                    // Spawns a flush+conversion task for streams that have buffered too much or for too long
+                    _ = flush_check_interval.tick(), if flush_triggers => {
+                        PARSEABLE.streams.flush_due(&mut joinset);
                     },
                     // Joins and logs errors in spawned tasks
                     Some(res) = joinset.join_next(), if !joinset.is_empty() => {
@@ -285,3 +374,25 @@ pub async fn alert_runtime(mut rx: mpsc::Receiver<AlertTask>) -> Result<(), anyh
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sync_interval_updated_at_runtime() {
+        // the configured interval is passed in, as the global one is read by other tests
+        let mut sync_interval = interval_at(Instant::now(), LOCAL_SYNC_INTERVAL);
+        assert!(!refresh_sync_interval(
+            &mut sync_interval,
+            LOCAL_SYNC_INTERVAL
+        ));
+        assert_eq!(sync_interval.period(), LOCAL_SYNC_INTERVAL);
+
+        assert!(refresh_sync_interval(
+            &mut sync_interval,
+            Duration::from_secs(5)
+        ));
+        assert_eq!(sync_interval.period(), Duration::from_secs(5));
+    }
+}
@@ -25,9 +25,9 @@ use super::TimeFilter;
 use crate::{
     migration::to_bytes,
     parseable::PARSEABLE,
-    rbac::{map::SessionKey, Users},
+    rbac::map::SessionKey,
     storage::object_storage::filter_path,
-    utils::{get_hash, user_auth_for_datasets, user_auth_for_query},
+    utils::{get_hash, session_auth_for_datasets, user_auth_for_query},
 };
 
 pub static FILTERS: Lazy<Filters> = Lazy::new(Filters::default);
@@ -198,8 +198,7 @@ impl Filters {
                 }
             } else if *filter_type == FilterType::Search {
                 let dataset_name = &f.stream_name;
-                let permissions = Users.get_permissions(key);
-                if user_auth_for_datasets(&permissions, &[dataset_name.to_string()]).is_ok() {
+                if session_auth_for_datasets(key, &[dataset_name.to_string()]).is_ok() {
                     filters.push(f.clone())
                 }
             }
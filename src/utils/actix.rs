@@ -22,7 +22,7 @@ use actix_web::{
     error::{ErrorUnauthorized, ErrorUnprocessableEntity},
     Error, FromRequest, HttpRequest,
 };
-use actix_web_httpauth::extractors::basic::BasicAuth;
+use actix_web_httpauth::extractors::{basic::BasicAuth, bearer::BearerAuth};
 
 use crate::rbac::map::SessionKey;
 
@@ -39,6 +39,8 @@ pub fn extract_session_key(req: &mut ServiceRequest) -> Result<SessionKey, Error
 
     if let Ok(basic) = basic {
         Ok(basic)
+    } else if let Ok(bearer) = req.extract::<BearerAuth>().into_inner() {
+        Ok(SessionKey::ApiToken(bearer.token().to_owned()))
     } else if let Some(cookie) = req.cookie("session") {
         let ulid = ulid::Ulid::from_string(cookie.value())
             .map_err(|_| ErrorUnprocessableEntity("Cookie is tampered with or invalid"))?;
@@ -61,6 +63,8 @@ pub fn extract_session_key_from_req(req: &HttpRequest) -> Result<SessionKey, Err
 
     if let Ok(basic) = basic {
         Ok(basic)
+    } else if let Ok(bearer) = BearerAuth::extract(req).into_inner() {
+        Ok(SessionKey::ApiToken(bearer.token().to_owned()))
     } else if let Some(cookie) = req.cookie("session") {
         let ulid = ulid::Ulid::from_string(cookie.value())
             .map_err(|_| ErrorUnprocessableEntity("Cookie is tampered with or invalid"))?;
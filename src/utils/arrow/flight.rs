@@ -43,6 +43,7 @@ pub type DoGetStream = stream::BoxStream<'static, Result<FlightData, Status>>;
 
 pub fn get_query_from_ticket(req: &Request<Ticket>) -> Result<QueryJson, Box<Status>> {
     serde_json::from_slice::<QueryJson>(&req.get_ref().ticket)
+        .map(|query| query.with_default_time_range(PARSEABLE.options.default_query_window))
         .map_err(|err| Box::new(Status::internal(err.to_string())))
 }
 
@@ -22,7 +22,10 @@ use std::{
     sync::Arc,
 };
 
-use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMillisecondArray, UInt64Array};
+use arrow_array::{
+    ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+    TimestampNanosecondArray, TimestampSecondArray, UInt64Array,
+};
 use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
 use arrow_select::take::take;
 use chrono::{DateTime, Utc};
@@ -30,12 +33,16 @@ use itertools::Itertools;
 
 pub mod batch_adapter;
 pub mod flight;
+pub mod schema_registry;
 
 use anyhow::Result;
 pub use batch_adapter::adapt_batch;
 use serde_json::{Map, Value};
 
-use crate::event::DEFAULT_TIMESTAMP_KEY;
+use crate::{
+    event::DEFAULT_TIMESTAMP_KEY,
+    utils::time::{timestamp_type, timestamp_unit, to_timestamp},
+};
 
 /// Converts a slice of record batches to JSON.
 ///
@@ -96,6 +103,18 @@ pub fn get_timestamp_array(p_timestamp: DateTime<Utc>, size: usize) -> Timestamp
     TimestampMillisecondArray::from_value(p_timestamp.timestamp_millis(), size)
 }
 
+/// Like `get_timestamp_array`, but in the precision timestamps are configured to be stored in
+pub fn get_timestamp_column(p_timestamp: DateTime<Utc>, size: usize) -> ArrayRef {
+    let unit = timestamp_unit();
+    let value = to_timestamp(p_timestamp, unit);
+    match unit {
+        TimeUnit::Second => Arc::new(TimestampSecondArray::from_value(value, size)),
+        TimeUnit::Millisecond => Arc::new(TimestampMillisecondArray::from_value(value, size)),
+        TimeUnit::Microsecond => Arc::new(TimestampMicrosecondArray::from_value(value, size)),
+        TimeUnit::Nanosecond => Arc::new(TimestampNanosecondArray::from_value(value, size)),
+    }
+}
+
 pub fn add_parseable_fields(
     rb: RecordBatch,
     p_timestamp: DateTime<Utc>,
@@ -117,19 +136,9 @@ pub fn add_parseable_fields(
         .collect_vec();
     let mut field_names: HashSet<String> = fields.iter().map(|f| f.name().to_string()).collect();
 
-    fields.insert(
-        0,
-        Field::new(
-            DEFAULT_TIMESTAMP_KEY,
-            DataType::Timestamp(TimeUnit::Millisecond, None),
-            true,
-        ),
-    );
+    fields.insert(0, Field::new(DEFAULT_TIMESTAMP_KEY, timestamp_type(), true));
     let mut columns = rb.columns().iter().map(Arc::clone).collect_vec();
-    columns.insert(
-        0,
-        Arc::new(get_timestamp_array(p_timestamp, row_count)) as ArrayRef,
-    );
+    columns.insert(0, get_timestamp_column(p_timestamp, row_count));
 
     //ignore the duplicate fields, no need to add them again
     for key in sorted_keys {
@@ -149,6 +158,18 @@ pub fn add_parseable_fields(
     RecordBatch::try_new(new_schema, columns)
 }
 
+/// Orders the fields of the schema by name, so that the same set of fields results in the same
+/// schema irrespective of the order of keys in the events they were inferred from
+pub fn sort_schema_fields(schema: &Schema) -> Schema {
+    let fields: Vec<_> = schema
+        .fields()
+        .iter()
+        .sorted_by_key(|field| field.name())
+        .cloned()
+        .collect();
+    Schema::new_with_metadata(fields, schema.metadata().clone())
+}
+
 pub fn reverse(rb: &RecordBatch) -> RecordBatch {
     let indices = UInt64Array::from_iter_values((0..rb.num_rows()).rev().map(|x| x as u64));
     let arrays = rb
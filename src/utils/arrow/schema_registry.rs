@@ -0,0 +1,215 @@
+/*
+ * Parseable Server (C) 2022 - 2024 Parseable, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+//! Conversions of the arrow schema of a stream into the schema formats understood by
+//! schema registries, for pipelines that consume a stream's data elsewhere.
+
+use arrow_schema::{DataType, Field, Fields, Schema, TimeUnit};
+use serde_json::{json, Map, Value};
+
+const JSON_SCHEMA_DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Converts the schema into a JSON Schema describing the events of the stream
+pub fn to_json_schema(stream_name: &str, schema: &Schema) -> Value {
+    let mut json_schema = json_schema_object(schema.fields());
+    json_schema.insert("$schema".to_owned(), JSON_SCHEMA_DRAFT.into());
+    json_schema.insert("title".to_owned(), stream_name.into());
+
+    Value::Object(json_schema)
+}
+
+fn json_schema_object(fields: &Fields) -> Map<String, Value> {
+    let properties: Map<String, Value> = fields
+        .iter()
+        .map(|field| (field.name().to_owned(), json_schema_field(field)))
+        .collect();
+    let required: Vec<&String> = fields
+        .iter()
+        .filter(|field| !field.is_nullable())
+        .map(|field| field.name())
+        .collect();
+
+    let mut object = Map::new();
+    object.insert("type".to_owned(), "object".into());
+    object.insert("properties".to_owned(), Value::Object(properties));
+    if !required.is_empty() {
+        object.insert("required".to_owned(), json!(required));
+    }
+
+    object
+}
+
+fn json_schema_field(field: &Field) -> Value {
+    let mut schema = match field.data_type() {
+        DataType::Null => return json!({"type": "null"}),
+        DataType::Boolean => json!({"type": "boolean"}),
+        data_type if data_type.is_integer() => json!({"type": "integer"}),
+        data_type if data_type.is_numeric() => json!({"type": "number"}),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => json!({"type": "string"}),
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => {
+            json!({"type": "string", "contentEncoding": "base64"})
+        }
+        DataType::Timestamp(_, _) => json!({"type": "string", "format": "date-time"}),
+        DataType::Date32 | DataType::Date64 => json!({"type": "string", "format": "date"}),
+        DataType::List(item) | DataType::LargeList(item) => {
+            json!({"type": "array", "items": json_schema_field(item)})
+        }
+        DataType::Struct(fields) => Value::Object(json_schema_object(fields)),
+        // anything goes for types without a JSON counterpart
+        _ => return json!({}),
+    };
+
+    if field.is_nullable() {
+        let data_type = schema["type"].take();
+        schema["type"] = json!([data_type, "null"]);
+    }
+
+    schema
+}
+
+/// Converts the schema into an Avro record schema describing the events of the stream
+pub fn to_avro_schema(stream_name: &str, schema: &Schema) -> Value {
+    avro_record(&avro_name(stream_name), schema.fields())
+}
+
+fn avro_record(name: &str, fields: &Fields) -> Value {
+    let fields: Vec<Value> = fields
+        .iter()
+        .map(|field| {
+            let mut avro_field = json!({
+                "name": avro_name(field.name()),
+                "type": avro_type(&format!("{name}_{}", avro_name(field.name())), field),
+            });
+            if field.is_nullable() {
+                avro_field["default"] = Value::Null;
+            }
+            avro_field
+        })
+        .collect();
+
+    json!({"type": "record", "name": name, "fields": fields})
+}
+
+/// `name` is used to name the records of nested structs, which Avro requires to be unique
+fn avro_type(name: &str, field: &Field) -> Value {
+    let avro_type = match field.data_type() {
+        DataType::Null => return json!("null"),
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => {
+            json!("int")
+        }
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => json!("long"),
+        DataType::Float16 | DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            json!({"type": "bytes", "logicalType": "decimal", "precision": precision, "scale": scale})
+        }
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => json!("bytes"),
+        DataType::Timestamp(unit, _) => match unit {
+            TimeUnit::Second => json!("long"),
+            TimeUnit::Millisecond => json!({"type": "long", "logicalType": "timestamp-millis"}),
+            TimeUnit::Microsecond => json!({"type": "long", "logicalType": "timestamp-micros"}),
+            TimeUnit::Nanosecond => json!({"type": "long", "logicalType": "timestamp-nanos"}),
+        },
+        DataType::Date32 => json!({"type": "int", "logicalType": "date"}),
+        DataType::List(item) | DataType::LargeList(item) => {
+            json!({"type": "array", "items": avro_type(name, item)})
+        }
+        DataType::Struct(fields) => avro_record(name, fields),
+        _ => json!("string"),
+    };
+
+    if field.is_nullable() {
+        json!(["null", avro_type])
+    } else {
+        avro_type
+    }
+}
+
+/// Avro names can only hold letters, digits and underscores, and can't start with a digit
+fn avro_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use serde_json::json;
+
+    use super::{to_avro_schema, to_json_schema};
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new(
+                "p_timestamp",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new("status", DataType::Int64, true),
+            Field::new(
+                "http",
+                DataType::Struct(vec![Arc::new(Field::new("path", DataType::Utf8, false))].into()),
+                true,
+            ),
+        ])
+    }
+
+    #[test]
+    fn three_field_schema_converts_to_json_schema() {
+        assert_eq!(
+            to_json_schema("app", &schema()),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "title": "app",
+                "type": "object",
+                "properties": {
+                    "p_timestamp": {"type": "string", "format": "date-time"},
+                    "status": {"type": ["integer", "null"]},
+                    "http": {
+                        "type": ["object", "null"],
+                        "properties": {"path": {"type": "string"}},
+                        "required": ["path"]
+                    }
+                },
+                "required": ["p_timestamp"]
+            })
+        );
+    }
+
+    #[test]
+    fn nested_struct_converts_to_avro_record() {
+        let avro = to_avro_schema("app-logs", &schema());
+
+        assert_eq!(avro["name"], "app_logs");
+        assert_eq!(
+            avro["fields"][0]["type"],
+            json!({"type": "long", "logicalType": "timestamp-millis"})
+        );
+        assert_eq!(avro["fields"][1]["type"], json!(["null", "long"]));
+        assert_eq!(avro["fields"][2]["type"][1]["name"], "app_logs_http");
+    }
+}
@@ -4,7 +4,7 @@ use human_size::{Any, SpecificSize};
 use serde::{de, Deserialize, Deserializer, Serializer};
 
 #[derive(Debug, thiserror::Error)]
-enum ParsingError {
+pub enum ParsingError {
     #[error("Expected 'X' | 'X Bytes', but error: {0}")]
     Int(#[from] std::num::ParseIntError),
     #[error("Could not parse given string as human size, erro: {0}")]
@@ -13,7 +13,7 @@ enum ParsingError {
 
 // Function to convert human-readable size to bytes (already provided)
 // NOTE: consider number values as byte count, e.g. "1234" is 1234 bytes.
-fn human_size_to_bytes(s: &str) -> Result<u64, ParsingError> {
+pub fn human_size_to_bytes(s: &str) -> Result<u64, ParsingError> {
     let s = s.trim();
     if let Some(s) = s.strip_suffix("Bytes") {
         let size: u64 = s.trim().parse()?;
@@ -19,13 +19,15 @@
 use std::collections::BTreeMap;
 use std::num::NonZeroU32;
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use serde_json::map::Map;
 use serde_json::value::Value;
 
 use thiserror::Error;
 
+use crate::event::format::{ClockSkewPolicy, DuplicateColumnPolicy, FieldLengthPolicy};
 use crate::parseable::PARSEABLE;
+use crate::utils::time::parse_timestamp;
 
 #[derive(Error, Debug)]
 pub enum JsonFlattenError {
@@ -47,10 +49,18 @@ pub enum JsonFlattenError {
     InvalidDatetimeFormat(String),
     #[error("Field {0} value is more than {1} days old")]
     TimestampTooOld(String, i64),
+    #[error("Field {0} value is further than {1} from the current time")]
+    TimestampSkewed(String, String),
+    #[error("Ingestion failed as field {0} is longer than {1} bytes")]
+    FieldTooLong(String, usize),
+    #[error("Ingestion failed as the value of field {0} is longer than {1} bytes")]
+    ValueTooLong(String, usize),
     #[error("Expected object in array of objects")]
     ExpectedObjectInArray,
     #[error("Found non-object element while flattening array of objects")]
     NonObjectInArray,
+    #[error("Ingestion failed as more than one field flattens into the column {0}, rename one of the fields so that it doesn't collide with the other, or set P_DUPLICATE_COLUMN_POLICY=suffix")]
+    DuplicateColumn(String),
 }
 
 // Recursively flattens JSON objects and arrays, e.g. with the separator `.`, starting from the TOP
@@ -62,6 +72,7 @@ pub fn flatten(
     time_partition_limit: Option<NonZeroU32>,
     custom_partition: Option<&String>,
     validation_required: bool,
+    duplicate_columns: DuplicateColumnPolicy,
 ) -> Result<(), JsonFlattenError> {
     match nested_value {
         Value::Object(nested_dict) => {
@@ -70,7 +81,7 @@ pub fn flatten(
                 validate_custom_partition(nested_dict, custom_partition)?;
             }
             let mut map = Map::new();
-            flatten_object(&mut map, None, nested_dict, separator)?;
+            flatten_object(&mut map, None, nested_dict, separator, duplicate_columns)?;
             *nested_dict = map;
         }
         Value::Array(arr) => {
@@ -83,6 +94,7 @@ pub fn flatten(
                     time_partition_limit,
                     custom_partition,
                     validation_required,
+                    duplicate_columns,
                 )?;
             }
         }
@@ -164,7 +176,7 @@ pub fn validate_time_partition(
     let Value::String(timestamp_str) = timestamp_value else {
         return Err(JsonFlattenError::FieldNotString(partition_key.to_owned()));
     };
-    let Ok(parsed_timestamp) = timestamp_str.parse::<DateTime<Utc>>() else {
+    let Ok(parsed_timestamp) = parse_timestamp(timestamp_str) else {
         return Err(JsonFlattenError::InvalidDatetimeFormat(
             partition_key.to_owned(),
         ));
@@ -180,12 +192,172 @@ pub fn validate_time_partition(
     }
 }
 
+// Keeps the time partition of flattened events within `tolerance` of `now`, events outside it are
+// rejected or clamped to `now` as per `policy`, so that misconfigured clocks don't create partitions
+pub fn apply_clock_skew(
+    value: &mut Value,
+    time_partition: &str,
+    tolerance: std::time::Duration,
+    policy: ClockSkewPolicy,
+    now: DateTime<Utc>,
+) -> Result<(), JsonFlattenError> {
+    let event = match value {
+        Value::Array(arr) => {
+            for value in arr {
+                apply_clock_skew(value, time_partition, tolerance, policy, now)?;
+            }
+            return Ok(());
+        }
+        Value::Object(event) => event,
+        _ => return Ok(()),
+    };
+
+    // values that aren't timestamps are left to the validation of the time partition
+    let Some(timestamp) = event
+        .get(time_partition)
+        .and_then(Value::as_str)
+        .and_then(|timestamp| parse_timestamp(timestamp).ok())
+    else {
+        return Ok(());
+    };
+    let skew = if timestamp > now {
+        timestamp - now
+    } else {
+        now - timestamp
+    };
+    if skew.to_std().is_ok_and(|skew| skew <= tolerance) {
+        return Ok(());
+    }
+
+    match policy {
+        ClockSkewPolicy::Reject => Err(JsonFlattenError::TimestampSkewed(
+            time_partition.to_owned(),
+            humantime::format_duration(tolerance).to_string(),
+        )),
+        ClockSkewPolicy::Clamp => {
+            event.insert(
+                time_partition.to_owned(),
+                Value::String(now.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            );
+            Ok(())
+        }
+    }
+}
+
+// Keeps field names and string values of flattened events within the configured maximum lengths,
+// longer ones are rejected or truncated as per `policy`, so that pathological events don't blow up
+// the schema and the parquet files. Names that collide once truncated are handled as per
+// `duplicate_columns`, suffixed ones are truncated further to stay within the maximum
+pub fn apply_field_length_limits(
+    value: &mut Value,
+    max_name_length: Option<usize>,
+    max_value_length: Option<usize>,
+    policy: FieldLengthPolicy,
+    duplicate_columns: DuplicateColumnPolicy,
+) -> Result<(), JsonFlattenError> {
+    let event = match value {
+        Value::Array(arr) => {
+            for value in arr {
+                apply_field_length_limits(
+                    value,
+                    max_name_length,
+                    max_value_length,
+                    policy,
+                    duplicate_columns,
+                )?;
+            }
+            return Ok(());
+        }
+        Value::Object(event) => event,
+        _ => return Ok(()),
+    };
+
+    let mut limited = Map::with_capacity(event.len());
+    for (mut name, mut value) in std::mem::take(event) {
+        if let Some(max) = max_name_length.filter(|max| name.len() > *max) {
+            match policy {
+                FieldLengthPolicy::Reject => {
+                    return Err(JsonFlattenError::FieldTooLong(
+                        truncated(&name, FIELD_DISPLAY_LENGTH).to_owned(),
+                        max,
+                    ))
+                }
+                FieldLengthPolicy::Truncate => {
+                    name = truncated(&name, max).to_owned();
+                    if limited.contains_key(&name) {
+                        if duplicate_columns == DuplicateColumnPolicy::Reject {
+                            return Err(JsonFlattenError::DuplicateColumn(name));
+                        }
+                        let suffixed = (1..)
+                            .map(|n| {
+                                let suffix = format!("_{n}");
+                                let prefix = truncated(&name, max.saturating_sub(suffix.len()));
+                                format!("{prefix}{suffix}")
+                            })
+                            .find(|suffixed| !limited.contains_key(suffixed))
+                            .expect("the map has finitely many keys");
+                        name = suffixed;
+                    }
+                }
+            }
+        }
+        if let Some(max) = max_value_length {
+            limit_value_length(&name, &mut value, max, policy)?;
+        }
+        limited.insert(name, value);
+    }
+    *event = limited;
+
+    Ok(())
+}
+
+// Number of bytes of an over-long field name shown in errors
+const FIELD_DISPLAY_LENGTH: usize = 64;
+
+// Longest prefix of `s` that is at most `max` bytes and ends on a char boundary
+fn truncated(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn limit_value_length(
+    name: &str,
+    value: &mut Value,
+    max: usize,
+    policy: FieldLengthPolicy,
+) -> Result<(), JsonFlattenError> {
+    match value {
+        Value::String(s) if s.len() > max => match policy {
+            FieldLengthPolicy::Reject => Err(JsonFlattenError::ValueTooLong(
+                truncated(name, FIELD_DISPLAY_LENGTH).to_owned(),
+                max,
+            )),
+            FieldLengthPolicy::Truncate => {
+                let end = truncated(s, max).len();
+                s.truncate(end);
+                Ok(())
+            }
+        },
+        Value::Array(arr) => arr
+            .iter_mut()
+            .try_for_each(|value| limit_value_length(name, value, max, policy)),
+        _ => Ok(()),
+    }
+}
+
 // Flattens a nested JSON Object/Map into another target Map
 fn flatten_object(
     output_map: &mut Map<String, Value>,
     parent_key: Option<&str>,
     nested_map: &mut Map<String, Value>,
     separator: &str,
+    duplicate_columns: DuplicateColumnPolicy,
 ) -> Result<(), JsonFlattenError> {
     for (key, mut value) in nested_map {
         let new_key = match parent_key {
@@ -195,12 +367,19 @@ fn flatten_object(
 
         match &mut value {
             Value::Object(obj) => {
-                flatten_object(output_map, Some(&new_key), obj, separator)?;
+                flatten_object(
+                    output_map,
+                    Some(&new_key),
+                    obj,
+                    separator,
+                    duplicate_columns,
+                )?;
             }
             Value::Array(arr) if arr.iter().any(Value::is_object) => {
-                flatten_array_objects(output_map, &new_key, arr, separator)?;
+                flatten_array_objects(output_map, &new_key, arr, separator, duplicate_columns)?;
             }
             _ => {
+                let new_key = free_column(output_map, new_key, duplicate_columns)?;
                 output_map.insert(new_key, std::mem::take(value));
             }
         }
@@ -214,6 +393,7 @@ pub fn flatten_array_objects(
     parent_key: &str,
     arr: &mut [Value],
     separator: &str,
+    duplicate_columns: DuplicateColumnPolicy,
 ) -> Result<(), JsonFlattenError> {
     let mut columns: BTreeMap<String, Vec<Value>> = BTreeMap::new();
 
@@ -221,7 +401,13 @@ pub fn flatten_array_objects(
         match value {
             Value::Object(nested_object) => {
                 let mut output_map = Map::new();
-                flatten_object(&mut output_map, Some(parent_key), nested_object, separator)?;
+                flatten_object(
+                    &mut output_map,
+                    Some(parent_key),
+                    nested_object,
+                    separator,
+                    duplicate_columns,
+                )?;
                 for (key, value) in output_map {
                     let column = columns
                         .entry(key)
@@ -248,12 +434,32 @@ pub fn flatten_array_objects(
 
     // Update the main map with new keys and their corresponding arrays
     for (key, values) in columns {
+        let key = free_column(output_map, key, duplicate_columns)?;
         output_map.insert(key, Value::Array(values));
     }
 
     Ok(())
 }
 
+// Name `key` is inserted into the flattened map with, when another field already flattened into
+// it the event is rejected or the first free `key_<n>` is used as per `policy`
+fn free_column(
+    map: &Map<String, Value>,
+    key: String,
+    policy: DuplicateColumnPolicy,
+) -> Result<String, JsonFlattenError> {
+    if !map.contains_key(&key) {
+        return Ok(key);
+    }
+    match policy {
+        DuplicateColumnPolicy::Reject => Err(JsonFlattenError::DuplicateColumn(key)),
+        DuplicateColumnPolicy::Suffix => Ok((1..)
+            .map(|n| format!("{key}_{n}"))
+            .find(|name| !map.contains_key(name))
+            .expect("the map has finitely many keys")),
+    }
+}
+
 /// Recursively flattens a JSON value.
 /// - If the value is an array, it flattens all elements of the array.
 /// - If the value is an object, it flattens all nested objects and arrays.
@@ -347,6 +553,34 @@ pub fn has_more_than_max_allowed_levels(value: &Value, current_level: usize) ->
     }
 }
 
+/// Stores objects nested deeper than `max_depth` levels as JSON strings, so that they end up in a
+/// single string column instead of inferring a column for every field in the subtree.
+/// e.g. with a `max_depth` of 2, `{"a":{"b":{"c":1}}}` becomes `{"a":{"b":"{\"c\":1}"}}`
+pub fn stringify_beyond_depth(value: &mut Value, max_depth: usize) {
+    fn stringify(value: &mut Value, level: usize, max_depth: usize) {
+        match value {
+            Value::Object(_) if level >= max_depth => *value = Value::String(value.to_string()),
+            Value::Object(map) => map
+                .values_mut()
+                .for_each(|val| stringify(val, level + 1, max_depth)),
+            Value::Array(arr) => arr
+                .iter_mut()
+                .for_each(|item| stringify(item, level, max_depth)),
+            _ => {}
+        }
+    }
+
+    match value {
+        Value::Array(events) => events
+            .iter_mut()
+            .for_each(|event| stringify_beyond_depth(event, max_depth)),
+        Value::Object(event) => event
+            .values_mut()
+            .for_each(|val| stringify(val, 1, max_depth)),
+        _ => {}
+    }
+}
+
 // Converts a Vector of values into a `Value::Array`, as long as all of them are objects
 pub fn convert_to_array(flattened: Vec<Value>) -> Result<Value, JsonFlattenError> {
     if flattened.iter().any(|item| !item.is_object()) {
@@ -358,16 +592,25 @@ pub fn convert_to_array(flattened: Vec<Value>) -> Result<Value, JsonFlattenError
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::json::flatten::{flatten_array_objects, generic_flattening};
+    use crate::utils::json::flatten::{
+        flatten_array_objects, generic_flattening, stringify_beyond_depth,
+    };
 
-    use super::{flatten, JsonFlattenError};
+    use super::{apply_clock_skew, apply_field_length_limits, flatten, JsonFlattenError};
+    use crate::event::format::{
+        ClockSkewPolicy,
+        DuplicateColumnPolicy::{Reject, Suffix},
+        FieldLengthPolicy,
+    };
+    use chrono::{TimeZone, Utc};
     use serde_json::{json, Map, Value};
+    use std::time::Duration;
 
     #[test]
     fn flatten_single_key_string() {
         let mut obj = json!({"key": "value"});
         let expected = obj.clone();
-        flatten(&mut obj, "_", None, None, None, false).unwrap();
+        flatten(&mut obj, "_", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -375,7 +618,7 @@ mod tests {
     fn flatten_single_key_int() {
         let mut obj = json!({"key": 1});
         let expected = obj.clone();
-        flatten(&mut obj, "_", None, None, None, false).unwrap();
+        flatten(&mut obj, "_", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -383,7 +626,7 @@ mod tests {
     fn flatten_multiple_key_value() {
         let mut obj = json!({"key1": 1, "key2": "value2"});
         let expected = obj.clone();
-        flatten(&mut obj, "_", None, None, None, false).unwrap();
+        flatten(&mut obj, "_", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -391,7 +634,7 @@ mod tests {
     fn flatten_nested_single_key_value() {
         let mut obj = json!({"key": "value", "nested_key": {"key":"value"}});
         let expected = json!({"key": "value", "nested_key.key": "value"});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -400,15 +643,49 @@ mod tests {
         let mut obj = json!({"key": "value", "nested_key": {"key1":"value1", "key2": "value2"}});
         let expected =
             json!({"key": "value", "nested_key.key1": "value1", "nested_key.key2": "value2"});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
+    #[test]
+    fn nested_key_colliding_with_flat_key() {
+        let mut obj = json!({"nested_key.key": "flat", "nested_key": {"key": "nested"}});
+        assert!(matches!(
+            flatten(&mut obj, ".", None, None, None, false, Reject),
+            Err(JsonFlattenError::DuplicateColumn(name)) if name == "nested_key.key"
+        ));
+
+        let mut obj = json!({"key.a": "flat", "key": [{"a": "value0"}, {"a": "value1"}]});
+        assert!(matches!(
+            flatten(&mut obj, ".", None, None, None, false, Reject),
+            Err(JsonFlattenError::DuplicateColumn(name)) if name == "key.a"
+        ));
+    }
+
+    #[test]
+    fn nested_key_colliding_with_flat_key_is_suffixed() {
+        // the first field in key order keeps the name
+        let mut obj =
+            json!({"nested_key": {"key": "nested", "key_1": 1}, "nested_key.key": "flat"});
+        flatten(&mut obj, ".", None, None, None, false, Suffix).unwrap();
+        assert_eq!(
+            obj,
+            json!({"nested_key.key": "nested", "nested_key.key_1": 1, "nested_key.key_2": "flat"})
+        );
+
+        let mut obj = json!({"key": [{"a": "value0"}, {"a": "value1"}], "key.a": "flat"});
+        flatten(&mut obj, ".", None, None, None, false, Suffix).unwrap();
+        assert_eq!(
+            obj,
+            json!({"key.a": ["value0", "value1"], "key.a_1": "flat"})
+        );
+    }
+
     #[test]
     fn nested_key_value_with_array() {
         let mut obj = json!({"key": "value", "nested_key": {"key1":[1,2,3]}});
         let expected = json!({"key": "value", "nested_key.key1": [1,2,3]});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -416,7 +693,7 @@ mod tests {
     fn nested_obj_array() {
         let mut obj = json!({"key": [{"a": "value0"}, {"a": "value1"}]});
         let expected = json!({"key.a": ["value0", "value1"]});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -424,7 +701,7 @@ mod tests {
     fn nested_obj_array_nulls() {
         let mut obj = json!({"key": [{"a": "value0"}, {"a": "value1", "b": "value1"}]});
         let expected = json!({"key.a": ["value0", "value1"], "key.b": [null, "value1"]});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -432,7 +709,7 @@ mod tests {
     fn nested_obj_array_nulls_reversed() {
         let mut obj = json!({"key": [{"a": "value0", "b": "value0"}, {"a": "value1"}]});
         let expected = json!({"key.a": ["value0", "value1"], "key.b": ["value0", null]});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -440,7 +717,7 @@ mod tests {
     fn nested_obj_array_nested_obj() {
         let mut obj = json!({"key": [{"a": {"p": 0}, "b": "value0"}, {"b": "value1"}]});
         let expected = json!({"key.a.p": [0, null], "key.b": ["value0", "value1"]});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
@@ -448,14 +725,14 @@ mod tests {
     fn nested_obj_array_nested_obj_array() {
         let mut obj = json!({"key": [{"a": [{"p": "value0", "q": "value0"}, {"p": "value1", "q": null}], "b": "value0"}, {"b": "value1"}]});
         let expected = json!({"key.a.p": [["value0", "value1"], null], "key.a.q": [["value0", null], null], "key.b": ["value0", "value1"]});
-        flatten(&mut obj, ".", None, None, None, false).unwrap();
+        flatten(&mut obj, ".", None, None, None, false, Reject).unwrap();
         assert_eq!(obj, expected);
     }
 
     #[test]
     fn flatten_mixed_object() {
         let mut obj = json!({"a": 42, "arr": ["1", {"key": "2"}, {"key": {"nested": "3"}}]});
-        assert!(flatten(&mut obj, ".", None, None, None, false).is_err());
+        assert!(flatten(&mut obj, ".", None, None, None, false, Reject).is_err());
     }
 
     #[test]
@@ -469,7 +746,7 @@ mod tests {
         };
 
         let mut map = Map::new();
-        flatten_array_objects(&mut map, "key", &mut arr, ".").unwrap();
+        flatten_array_objects(&mut map, "key", &mut arr, ".", Reject).unwrap();
 
         assert_eq!(map.len(), 2);
         assert_eq!(map.get("key.p").unwrap(), &json!([null, 2, null]));
@@ -483,7 +760,7 @@ mod tests {
         };
 
         let mut map = Map::new();
-        flatten_array_objects(&mut map, "key", &mut arr, ".").unwrap();
+        flatten_array_objects(&mut map, "key", &mut arr, ".", Reject).unwrap();
 
         assert_eq!(map.len(), 2);
         assert_eq!(map.get("key.a").unwrap(), &json!([1, 2, null]));
@@ -497,7 +774,7 @@ mod tests {
         };
 
         let mut map = Map::new();
-        flatten_array_objects(&mut map, "key", &mut arr, ".").unwrap();
+        flatten_array_objects(&mut map, "key", &mut arr, ".", Reject).unwrap();
 
         assert_eq!(map.len(), 3);
         assert_eq!(map.get("key.a").unwrap(), &json!([1, null, 3]));
@@ -516,7 +793,7 @@ mod tests {
         };
 
         let mut map = Map::new();
-        flatten_array_objects(&mut map, "key", &mut arr, ".").unwrap();
+        flatten_array_objects(&mut map, "key", &mut arr, ".", Reject).unwrap();
 
         assert_eq!(map.len(), 3);
         assert_eq!(map.get("key.p").unwrap(), &json!([1, null, 3]));
@@ -535,7 +812,7 @@ mod tests {
         };
 
         let mut map = Map::new();
-        flatten_array_objects(&mut map, "key", &mut arr, ".").unwrap();
+        flatten_array_objects(&mut map, "key", &mut arr, ".", Reject).unwrap();
 
         assert_eq!(map.len(), 3);
         assert_eq!(map.get("key.p").unwrap(), &json!([1, null, 3]));
@@ -548,22 +825,58 @@ mod tests {
         let mut value = json!({
             "a": 1,
         });
-        assert!(flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).is_ok());
+        assert!(flatten(
+            &mut value,
+            "_",
+            None,
+            None,
+            Some(&"a".to_string()),
+            true,
+            Reject
+        )
+        .is_ok());
 
         let mut value = json!({
             "a": true,
         });
-        assert!(flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).is_ok());
+        assert!(flatten(
+            &mut value,
+            "_",
+            None,
+            None,
+            Some(&"a".to_string()),
+            true,
+            Reject
+        )
+        .is_ok());
 
         let mut value = json!({
             "a": "yes",
         });
-        assert!(flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).is_ok());
+        assert!(flatten(
+            &mut value,
+            "_",
+            None,
+            None,
+            Some(&"a".to_string()),
+            true,
+            Reject
+        )
+        .is_ok());
 
         let mut value = json!({
             "a": -1,
         });
-        assert!(flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).is_ok());
+        assert!(flatten(
+            &mut value,
+            "_",
+            None,
+            None,
+            Some(&"a".to_string()),
+            true,
+            Reject
+        )
+        .is_ok());
     }
 
     #[test]
@@ -572,7 +885,16 @@ mod tests {
             "a": null,
         });
         matches!(
-            flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).unwrap_err(),
+            flatten(
+                &mut value,
+                "_",
+                None,
+                None,
+                Some(&"a".to_string()),
+                true,
+                Reject
+            )
+            .unwrap_err(),
             JsonFlattenError::FieldEmptyOrNull(_)
         );
 
@@ -580,7 +902,16 @@ mod tests {
             "a": "",
         });
         matches!(
-            flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).unwrap_err(),
+            flatten(
+                &mut value,
+                "_",
+                None,
+                None,
+                Some(&"a".to_string()),
+                true,
+                Reject
+            )
+            .unwrap_err(),
             JsonFlattenError::FieldEmptyOrNull(_)
         );
 
@@ -588,7 +919,16 @@ mod tests {
             "a": {"b": 1},
         });
         matches!(
-            flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).unwrap_err(),
+            flatten(
+                &mut value,
+                "_",
+                None,
+                None,
+                Some(&"a".to_string()),
+                true,
+                Reject
+            )
+            .unwrap_err(),
             JsonFlattenError::FieldIsObject(_)
         );
 
@@ -596,7 +936,16 @@ mod tests {
             "a": ["b", "c"],
         });
         matches!(
-            flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).unwrap_err(),
+            flatten(
+                &mut value,
+                "_",
+                None,
+                None,
+                Some(&"a".to_string()),
+                true,
+                Reject
+            )
+            .unwrap_err(),
             JsonFlattenError::FieldIsArray(_)
         );
 
@@ -604,7 +953,16 @@ mod tests {
             "a": "b.c",
         });
         matches!(
-            flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).unwrap_err(),
+            flatten(
+                &mut value,
+                "_",
+                None,
+                None,
+                Some(&"a".to_string()),
+                true,
+                Reject
+            )
+            .unwrap_err(),
             JsonFlattenError::FieldContainsPeriod(_)
         );
 
@@ -612,7 +970,16 @@ mod tests {
             "a": 1.0,
         });
         matches!(
-            flatten(&mut value, "_", None, None, Some(&"a".to_string()), true).unwrap_err(),
+            flatten(
+                &mut value,
+                "_",
+                None,
+                None,
+                Some(&"a".to_string()),
+                true,
+                Reject
+            )
+            .unwrap_err(),
             JsonFlattenError::FieldContainsPeriod(_)
         );
     }
@@ -623,4 +990,83 @@ mod tests {
         let expected = vec![json!({"a":{"b":{"e":"a"}}}), json!({"a":{"b":{"e":"b"}}})];
         assert_eq!(generic_flattening(&value).unwrap(), expected);
     }
+
+    #[test]
+    fn nesting_beyond_max_depth_is_stored_as_string() {
+        let mut value = json!({"a": {"b": {"c": {"d": {"e": 1}}}}, "x": 1});
+        stringify_beyond_depth(&mut value, 2);
+        flatten(&mut value, "_", None, None, None, false, Reject).unwrap();
+
+        assert_eq!(value, json!({"a_b": r#"{"c":{"d":{"e":1}}}"#, "x": 1}));
+    }
+
+    #[test]
+    fn event_a_year_ahead_is_handled_per_skew_policy() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let tolerance = Duration::from_secs(24 * 60 * 60);
+        let events = json!([
+            {"ts": "2025-01-01T06:00:00.000Z", "msg": "on time"},
+            {"ts": "2026-01-01T00:00:00.000Z", "msg": "a year ahead"},
+        ]);
+
+        let mut rejected = events.clone();
+        assert!(matches!(
+            apply_clock_skew(&mut rejected, "ts", tolerance, ClockSkewPolicy::Reject, now),
+            Err(JsonFlattenError::TimestampSkewed(field, _)) if field == "ts"
+        ));
+
+        let mut clamped = events;
+        apply_clock_skew(&mut clamped, "ts", tolerance, ClockSkewPolicy::Clamp, now).unwrap();
+        assert_eq!(clamped[0]["ts"], "2025-01-01T06:00:00.000Z");
+        assert_eq!(clamped[1]["ts"], "2025-01-01T00:00:00.000Z");
+        assert_eq!(clamped[1]["msg"], "a year ahead");
+    }
+
+    #[test]
+    fn over_long_field_name_is_rejected() {
+        let long_name = "k".repeat(1024);
+        let mut event = json!({"msg": "hi", long_name.clone(): "value"});
+        assert!(matches!(
+            apply_field_length_limits(
+                &mut event,
+                Some(256),
+                None,
+                FieldLengthPolicy::Reject,
+                Reject
+            ),
+            Err(JsonFlattenError::FieldTooLong(field, 256)) if long_name.starts_with(&field)
+        ));
+
+        let mut event = json!({"msg": "hi", long_name: "é".repeat(8)});
+        apply_field_length_limits(
+            &mut event,
+            Some(256),
+            Some(5),
+            FieldLengthPolicy::Truncate,
+            Reject,
+        )
+        .unwrap();
+        assert_eq!(event["k".repeat(256)], "éé");
+        assert_eq!(event["msg"], "hi");
+    }
+
+    #[test]
+    fn field_names_colliding_once_truncated() {
+        let mut event = json!({"kkkk": 1, "kkkk_long": 2});
+        assert!(matches!(
+            apply_field_length_limits(&mut event, Some(4), None, FieldLengthPolicy::Truncate, Reject),
+            Err(JsonFlattenError::DuplicateColumn(name)) if name == "kkkk"
+        ));
+
+        let mut event = json!({"kkkk": 1, "kkkk_long": 2});
+        apply_field_length_limits(
+            &mut event,
+            Some(4),
+            None,
+            FieldLengthPolicy::Truncate,
+            Suffix,
+        )
+        .unwrap();
+        assert_eq!(event, json!({"kkkk": 1, "kk_1": 2}));
+    }
 }
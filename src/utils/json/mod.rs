@@ -19,13 +19,16 @@
 use std::fmt;
 use std::num::NonZeroU32;
 
-use flatten::{convert_to_array, generic_flattening, has_more_than_max_allowed_levels};
+use flatten::{
+    convert_to_array, generic_flattening, has_more_than_max_allowed_levels, stringify_beyond_depth,
+};
 use serde::de::Visitor;
 use serde_json;
 use serde_json::Value;
 
 use crate::event::format::LogSource;
 use crate::metadata::SchemaVersion;
+use crate::parseable::PARSEABLE;
 
 pub mod flatten;
 
@@ -33,7 +36,7 @@ pub mod flatten;
 /// in case when Vec<Value> is returned, converts the Vec<Value> to Value of Array
 /// this is to ensure recursive flattening does not happen for heavily nested jsons
 pub fn flatten_json_body(
-    body: Value,
+    mut body: Value,
     time_partition: Option<&String>,
     time_partition_limit: Option<NonZeroU32>,
     custom_partition: Option<&String>,
@@ -41,6 +44,13 @@ pub fn flatten_json_body(
     validation_required: bool,
     log_source: &LogSource,
 ) -> Result<Value, anyhow::Error> {
+    // Limit the nesting columns are inferred for, only with the new schema
+    if schema_version == SchemaVersion::V1 {
+        if let Some(max_depth) = PARSEABLE.options.max_inference_depth {
+            stringify_beyond_depth(&mut body, max_depth);
+        }
+    }
+
     // Flatten the json body only if new schema and has less than 4 levels of nesting
     let mut nested_value = if schema_version == SchemaVersion::V1
         && !has_more_than_max_allowed_levels(&body, 1)
@@ -58,6 +68,7 @@ pub fn flatten_json_body(
         time_partition_limit,
         custom_partition,
         validation_required,
+        PARSEABLE.options.duplicate_column_policy,
     )?;
     Ok(nested_value)
 }
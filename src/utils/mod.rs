@@ -26,7 +26,9 @@ pub mod uid;
 pub mod update;
 
 use crate::handlers::http::rbac::RBACError;
+use crate::parseable::PARSEABLE;
 use crate::query::{TableScanVisitor, QUERY_SESSION};
+use crate::rbac::acl;
 use crate::rbac::map::SessionKey;
 use crate::rbac::role::{Action, Permission};
 use crate::rbac::Users;
@@ -92,10 +94,35 @@ pub async fn user_auth_for_query(
     session_key: &SessionKey,
     query: &str,
 ) -> Result<(), actix_web::error::Error> {
-    let tables = get_tables_from_query(query).await?;
-    let permissions = Users.get_permissions(session_key);
-    let tables = tables.into_inner();
-    user_auth_for_datasets(&permissions, &tables)
+    let tables = get_tables_from_query(query).await?.into_inner();
+    session_auth_for_datasets(session_key, &tables)
+}
+
+/// Checks that the session may query each of the datasets, the ACL of a dataset takes
+/// precedence over roles for the principals it lists
+pub fn session_auth_for_datasets(
+    session_key: &SessionKey,
+    tables: &[String],
+) -> Result<(), actix_web::error::Error> {
+    let username = Users.get_username_from_session(session_key);
+    let mut by_role = vec![];
+    for table_name in tables {
+        let allowed = username.as_ref().and_then(|username| {
+            let settings = PARSEABLE.get_stream(table_name).ok()?.get_settings();
+            acl::check(&settings.acl, username, Action::Query)
+        });
+        match allowed {
+            Some(true) => {}
+            Some(false) => {
+                return Err(actix_web::error::ErrorUnauthorized(format!(
+                    "User does not have access to stream- {table_name}"
+                )))
+            }
+            None => by_role.push(table_name.clone()),
+        }
+    }
+
+    user_auth_for_datasets(&Users.get_permissions(session_key), &by_role)
 }
 
 pub fn user_auth_for_datasets(
@@ -150,3 +177,57 @@ pub async fn extract_tables(sql: &str) -> Option<Vec<String>> {
 
     Some(tables)
 }
+
+#[cfg(test)]
+mod tests {
+    use ulid::Ulid;
+
+    use crate::{
+        metadata::StreamSettings,
+        rbac::{map::mut_roles, role::model::DefaultPrivilege, user::User},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn queries_of_saved_objects_are_denied_by_the_acl() {
+        crate::rbac::map::init_for_tests();
+        let granted = "saved_query_granted";
+        let denied = "saved_query_denied";
+        let username = "saved_query_reader";
+        mut_roles().insert(
+            username.to_owned(),
+            [granted, denied]
+                .map(|stream| DefaultPrivilege::Reader {
+                    stream: stream.to_owned(),
+                    tag: None,
+                })
+                .to_vec(),
+        );
+        for stream in [granted, denied] {
+            PARSEABLE.get_or_create_stream(stream);
+        }
+        let stream = PARSEABLE.get_stream(denied).unwrap();
+        stream.set_settings(StreamSettings {
+            acl: [(username.to_owned(), vec![])].into_iter().collect(),
+            ..stream.get_settings().as_ref().clone()
+        });
+        let (mut user, _) = User::new_basic(username.to_owned());
+        user.roles.insert(username.to_owned());
+        Users.put_user(user.clone());
+        let session = SessionKey::SessionId(Ulid::new());
+        Users.new_session(&user, session.clone());
+
+        // as checked for alerts, filters and dashboards
+        assert!(
+            user_auth_for_query(&session, &format!("select * from {granted}"))
+                .await
+                .is_ok()
+        );
+        assert!(
+            user_auth_for_query(&session, &format!("select * from {denied}"))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -16,7 +16,111 @@
  *
  */
 
-use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike, Utc};
+use arrow_schema::{DataType, TimeUnit};
+use chrono::{
+    DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use once_cell::sync::OnceCell;
+
+/// Precision in which `p_timestamp` and the time columns of streams are stored,
+/// set once at startup and defaults to milliseconds.
+static TIMESTAMP_PRECISION: OnceCell<TimestampPrecision> = OnceCell::new();
+
+/// Timezone timestamps without an offset are read in, set once at startup. Such timestamps are
+/// rejected when it is unset.
+static NAIVE_TIMESTAMP_TIMEZONE: OnceCell<Tz> = OnceCell::new();
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    Second,
+    #[default]
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl TimestampPrecision {
+    pub fn unit(self) -> TimeUnit {
+        match self {
+            TimestampPrecision::Second => TimeUnit::Second,
+            TimestampPrecision::Millisecond => TimeUnit::Millisecond,
+            TimestampPrecision::Microsecond => TimeUnit::Microsecond,
+            TimestampPrecision::Nanosecond => TimeUnit::Nanosecond,
+        }
+    }
+}
+
+/// Sets the precision timestamps are stored in, only the first call takes effect
+pub fn set_timestamp_precision(precision: TimestampPrecision) {
+    let _ = TIMESTAMP_PRECISION.set(precision);
+}
+
+/// Unit timestamps are stored in
+pub fn timestamp_unit() -> TimeUnit {
+    TIMESTAMP_PRECISION
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .unit()
+}
+
+/// Sets the timezone timestamps without an offset are read in, only the first call takes effect
+pub fn set_naive_timestamp_timezone(timezone: Tz) {
+    let _ = NAIVE_TIMESTAMP_TIMEZONE.set(timezone);
+}
+
+/// Parses an RFC 3339 timestamp, or one without an offset, e.g. `2022-10-15T10:00:00`, as the
+/// local time of the timezone set with [`set_naive_timestamp_timezone`]
+pub fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    parse_timestamp_in(timestamp, NAIVE_TIMESTAMP_TIMEZONE.get().copied())
+}
+
+/// Same as [`parse_timestamp`], with timestamps without an offset read in `timezone`. Local
+/// times skipped by a DST transition are rejected, those repeated by one read as the earliest.
+fn parse_timestamp_in(
+    timestamp: &str,
+    timezone: Option<Tz>,
+) -> Result<DateTime<Utc>, chrono::ParseError> {
+    let err = match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(time) => return Ok(time.with_timezone(&Utc)),
+        Err(err) => err,
+    };
+    let Some(timezone) = timezone else {
+        return Err(err);
+    };
+
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .and_then(|local| timezone.from_local_datetime(&local).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or(err)
+}
+
+/// Arrow type of `p_timestamp` and the time columns of streams
+pub fn timestamp_type() -> DataType {
+    DataType::Timestamp(timestamp_unit(), None)
+}
+
+/// Number of `unit`s since the epoch, precision finer than `unit` is truncated
+pub fn to_timestamp(time: DateTime<Utc>, unit: TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Second => time.timestamp(),
+        TimeUnit::Millisecond => time.timestamp_millis(),
+        TimeUnit::Microsecond => time.timestamp_micros(),
+        TimeUnit::Nanosecond => time.timestamp_nanos_opt().unwrap_or(i64::MAX),
+    }
+}
+
+/// Time that is `value` `unit`s since the epoch
+pub fn from_timestamp(value: i64, unit: TimeUnit) -> Option<DateTime<Utc>> {
+    match unit {
+        TimeUnit::Second => DateTime::from_timestamp(value, 0),
+        TimeUnit::Millisecond => DateTime::from_timestamp_millis(value),
+        TimeUnit::Microsecond => DateTime::from_timestamp_micros(value),
+        TimeUnit::Nanosecond => Some(DateTime::from_timestamp_nanos(value)),
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum TimeParseError {
@@ -28,6 +132,8 @@ pub enum TimeParseError {
     Chrono(#[from] chrono::ParseError),
     #[error("Start time cannot be greater than the end time")]
     StartTimeAfterEndTime,
+    #[error("Time range is too large, it spans more than {max} prefixes. Please narrow down the time range or use a coarser data granularity")]
+    RangeTooLarge { max: usize },
 }
 
 type Prefix = String;
@@ -68,6 +174,8 @@ impl TimeRange {
     /// - `end_time`: A string representing the end of the time range. This can either be
     ///   the keyword `"now"` (to represent the current time) or an RFC 3339 formatted timestamp.
     ///
+    /// Timestamps without an offset are accepted too, see [`parse_timestamp`].
+    ///
     /// # Errors
     /// - `TimeParseError::StartTimeAfterEndTime`: Returned when the parsed start time is later than the end time.
     /// - Any error that might occur during parsing of durations or RFC 3339 timestamps.
@@ -85,8 +193,8 @@ impl TimeRange {
             end = Utc::now();
             start = end - chrono::Duration::from_std(humantime::parse_duration(start_time)?)?;
         } else {
-            start = DateTime::parse_from_rfc3339(start_time)?.into();
-            end = DateTime::parse_from_rfc3339(end_time)?.into();
+            start = parse_timestamp(start_time)?;
+            end = parse_timestamp(end_time)?;
         };
 
         // Truncate seconds, milliseconds, and nanoseconds to zero
@@ -101,6 +209,17 @@ impl TimeRange {
         Ok(Self { start, end })
     }
 
+    /// Parses a human-readable `start_time` duration into a range ending with the minute of
+    /// `latest`, rather than now, so that the latest event is always in the range however old it is
+    pub fn anchored_at(start_time: &str, latest: DateTime<Utc>) -> Result<Self, TimeParseError> {
+        let end = truncate_to_minute(latest) + TimeDelta::minutes(1);
+        let start = truncate_to_minute(
+            end - chrono::Duration::from_std(humantime::parse_duration(start_time)?)?,
+        );
+
+        Ok(Self { start, end })
+    }
+
     /// Generates prefixes for the time period, e.g:
     /// 1. ("2022-06-11T23:00:01+00:00", "2022-06-12T01:59:59+00:00") => ["date=2022-06-11/hour=23/", "date=2022-06-12/hour=00/", "date=2022-06-12/hour=01/""]
     /// 2. ("2022-06-11T15:59:00+00:00", "2022-06-11T17:01:00+00:00") => ["date=2022-06-11/hour=15/minute=59/", "date=2022-06-11/hour=16/", "date=2022-06-11/hour=17/minute=00/"]
@@ -117,6 +236,32 @@ impl TimeRange {
         prefixes
     }
 
+    /// Same as [`TimeRange::generate_prefixes`], but errors out with `TimeParseError::RangeTooLarge`
+    /// instead of generating more than `max_prefixes` prefixes. A `max_prefixes` of 0 means no limit.
+    pub fn generate_prefixes_bounded(
+        self,
+        data_granularity: u32,
+        max_prefixes: usize,
+    ) -> Result<Vec<Prefix>, TimeParseError> {
+        if max_prefixes == 0 {
+            return Ok(self.generate_prefixes(data_granularity));
+        }
+
+        let mut prefixes = vec![];
+        let time_bounds = self.calculate_time_bounds();
+        let mut current_date = time_bounds.start_date;
+
+        while current_date <= time_bounds.end_date {
+            self.process_date(data_granularity, current_date, time_bounds, &mut prefixes);
+            if prefixes.len() > max_prefixes {
+                return Err(TimeParseError::RangeTooLarge { max: max_prefixes });
+            }
+            current_date += TimeDelta::days(1);
+        }
+
+        Ok(prefixes)
+    }
+
     fn calculate_time_bounds(&self) -> TimeBounds {
         TimeBounds {
             start_date: self.start.date_naive(),
@@ -290,6 +435,28 @@ impl TimeRange {
     pub fn contains(&self, time: DateTime<Utc>) -> bool {
         self.start <= time && self.end > time
     }
+
+    /// Returns true if the ranges share any instant
+    pub fn overlaps(&self, other: &TimeRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// The range in the wall-clock time of `timezone`, which partitions aligned to it are named
+    /// after, to generate their prefixes from. Where clocks are turned back, local times repeat
+    /// and the range is widened to cover both of them.
+    pub fn in_partition_time(self, timezone: Option<Tz>) -> Self {
+        let Some(timezone) = timezone else {
+            return self;
+        };
+        let local = |time: DateTime<Utc>| time.with_timezone(&timezone).naive_local().and_utc();
+        let (start, end) = (local(self.start), local(self.end));
+        let span = self.end - self.start;
+
+        Self {
+            start: start.min(end - span),
+            end: end.max(start + span),
+        }
+    }
 }
 
 pub fn truncate_to_minute(dt: DateTime<Utc>) -> DateTime<Utc> {
@@ -305,6 +472,57 @@ pub fn truncate_to_minute(dt: DateTime<Utc>) -> DateTime<Utc> {
         .unwrap() // This should never fail with valid components
 }
 
+/// Key of the prefix that records the UTC offset of partitions aligned to a timezone, e.g.
+/// `date=2025-01-01/hour=00/minute=00/zone=-0500`, so that keys are never ambiguous about it
+pub const PARTITION_ZONE_KEY: &str = "zone";
+
+/// Local time in `timezone` of a UTC `timestamp`, along with its UTC offset, e.g. `-0500`
+pub fn to_partition_time(timestamp: NaiveDateTime, timezone: Tz) -> (NaiveDateTime, String) {
+    let local = timezone.from_utc_datetime(&timestamp);
+    (local.naive_local(), local.format("%z").to_string())
+}
+
+/// Parses a UTC offset such as `-0500` or `+0530`, as recorded in partition prefixes
+pub fn parse_partition_offset(offset: &str) -> Option<FixedOffset> {
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours: i32 = offset.get(1..3)?.parse().ok()?;
+    let minutes: i32 = offset.get(3..5)?.parse().ok()?;
+    if offset.len() != 5 {
+        return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses the time range of the partition a file is in from its `date=../hour=../minute=..` prefix
+pub fn partition_time_range(path: &str) -> Option<TimeRange> {
+    let value = |key: &str| {
+        path.split('/')
+            .find_map(|part| part.strip_prefix(key)?.strip_prefix('='))
+    };
+    let date = NaiveDate::parse_from_str(value("date")?, "%Y-%m-%d").ok()?;
+    let hour = value("hour")?.parse().ok()?;
+    let minute = value("minute")?.parse().ok()?;
+    let local = date.and_hms_opt(hour, minute, 0)?;
+    // partitions aligned to a timezone record its offset alongside the local time
+    let start = match value(PARTITION_ZONE_KEY) {
+        Some(offset) => parse_partition_offset(offset)?
+            .from_local_datetime(&local)
+            .single()?
+            .to_utc(),
+        None => local.and_utc(),
+    };
+
+    Some(TimeRange::granularity_range(
+        start,
+        crate::OBJECT_STORE_DATA_GRANULARITY,
+    ))
+}
+
 /// Represents a minute value (0-59) and provides methods for converting it to a slot range.
 ///
 /// # Examples
@@ -385,6 +603,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn naive_timestamp_is_read_in_assumed_timezone() {
+        let parsed = parse_timestamp_in("2022-10-15T10:00:00", Some(chrono_tz::Asia::Kolkata));
+        assert_eq!(
+            parsed.unwrap().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2022-10-15T04:30:00Z"
+        );
+
+        // timestamps with an offset are read as is
+        let parsed = parse_timestamp_in("2022-10-15T10:00:00Z", Some(chrono_tz::Asia::Kolkata));
+        assert_eq!(
+            parsed.unwrap().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2022-10-15T10:00:00Z"
+        );
+
+        assert!(parse_timestamp_in("2022-10-15T10:00:00", None).is_err());
+    }
+
     #[test]
     fn end_time_now_with_valid_duration() {
         let start_time = "1h";
@@ -406,6 +642,20 @@ mod tests {
         assert_eq!(parsed.end - parsed.start, Duration::minutes(30));
     }
 
+    #[test]
+    fn anchored_range_covers_latest_event_however_old() {
+        let latest = Utc::now() - Duration::hours(1);
+
+        // the window up to now misses the latest event
+        let wall_clock = TimeRange::parse_human_time("30m", "now").unwrap();
+        assert!(latest < wall_clock.start);
+
+        let anchored = TimeRange::anchored_at("30m", latest).unwrap();
+        assert!(anchored.start <= latest && latest < anchored.end);
+        assert!(anchored.end < wall_clock.start);
+        assert_eq!(anchored.end - anchored.start, Duration::minutes(30));
+    }
+
     #[test]
     fn start_time_after_end_time() {
         let start_time = "2023-01-01T14:00:00Z";
@@ -516,6 +766,21 @@ mod tests {
         assert_eq!(left.as_slice(), right);
     }
 
+    #[test]
+    fn wide_range_exceeds_prefix_limit() {
+        let time_period =
+            time_period_from_str("2022-01-01T00:00:30+00:00", "2024-12-31T23:59:30+00:00");
+        assert!(matches!(
+            time_period.generate_prefixes_bounded(1, 1000),
+            Err(TimeParseError::RangeTooLarge { max: 1000 })
+        ));
+
+        let time_period =
+            time_period_from_str("2022-06-11T15:59:00+00:00", "2022-06-11T17:01:00+00:00");
+        let prefixes = time_period.generate_prefixes_bounded(1, 1000).unwrap();
+        assert_eq!(prefixes.len(), 3);
+    }
+
     #[test]
     fn valid_minute_to_minute_slot() {
         let res = Minute::try_from(10);